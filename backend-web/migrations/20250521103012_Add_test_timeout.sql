@@ -0,0 +1,2 @@
+ALTER TABLE Tests ADD COLUMN
+    timeout_ms INTEGER DEFAULT NULL; -- overrides the global test timeout if set
@@ -0,0 +1,2 @@
+ALTER TABLE Tasks
+    ADD COLUMN environment TEXT DEFAULT NULL;
@@ -0,0 +1,23 @@
+-- Tests can run once per command profile, so the profile becomes part of the key.
+-- Results without a profile use the empty string.
+CREATE TABLE TestResultsWithProfile
+(
+    task_id                  VARCHAR(36) NOT NULL REFERENCES Tasks (task_id) ON DELETE CASCADE ON UPDATE CASCADE,
+    test_id                  VARCHAR(36) NOT NULL REFERENCES Tests (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    profile                  VARCHAR     NOT NULL DEFAULT '',
+    provisional_for_category VARCHAR(10),
+    compiler_exec_id         VARCHAR(36) NOT NULL REFERENCES ExecutionResults (execution_id) ON DELETE CASCADE ON UPDATE CASCADE,
+    binary_exec_id           VARCHAR(36) REFERENCES ExecutionResults (execution_id) ON DELETE CASCADE ON UPDATE CASCADE,
+    status                   VARCHAR(40) NOT NULL,
+
+    PRIMARY KEY (task_id, test_id, profile)
+);
+
+INSERT INTO TestResultsWithProfile
+    (task_id, test_id, provisional_for_category, compiler_exec_id, binary_exec_id, status)
+SELECT task_id, test_id, provisional_for_category, compiler_exec_id, binary_exec_id, status
+FROM TestResults;
+
+DROP TABLE TestResults;
+
+ALTER TABLE TestResultsWithProfile RENAME TO TestResults;
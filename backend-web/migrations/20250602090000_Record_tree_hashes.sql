@@ -0,0 +1,8 @@
+ALTER TABLE Queue
+    ADD COLUMN tree_hash VARCHAR(40) DEFAULT NULL;
+ALTER TABLE Queue
+    ADD COLUMN identical_to VARCHAR(36) DEFAULT NULL;
+ALTER TABLE Tasks
+    ADD COLUMN tree_hash VARCHAR(40) DEFAULT NULL;
+
+CREATE INDEX tasks_team_tree_idx ON Tasks (team_id, tree_hash);
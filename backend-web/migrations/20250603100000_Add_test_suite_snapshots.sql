@@ -0,0 +1,9 @@
+-- The tests of a category frozen at its lab deadline. `tests` is a JSON array of the full test
+-- definitions, so later edits or deletions do not affect grading.
+CREATE TABLE TestSuiteSnapshots
+(
+    category  VARCHAR PRIMARY KEY,
+    frozen_at INTEGER NOT NULL,
+    frozen_by VARCHAR(36) DEFAULT NULL,
+    tests     TEXT    NOT NULL
+);
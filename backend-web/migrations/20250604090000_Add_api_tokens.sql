@@ -0,0 +1,15 @@
+-- Scoped tokens teams can use for their own tooling. Only a hash of the token is stored, the
+-- token itself is shown once on creation.
+CREATE TABLE ApiTokens
+(
+    id           VARCHAR(36)  PRIMARY KEY,
+    team_id      VARCHAR(36)  NOT NULL REFERENCES Teams (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    name         VARCHAR(255) NOT NULL CHECK (LENGTH(name) < 255),
+    token_hash   VARCHAR(64)  NOT NULL UNIQUE,
+    scopes       TEXT         NOT NULL,
+    created_by   VARCHAR(36)  NOT NULL REFERENCES Users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    created_at   INTEGER      NOT NULL,
+    last_used_at INTEGER               DEFAULT NULL
+);
+
+CREATE INDEX idx_api_tokens_team ON ApiTokens (team_id);
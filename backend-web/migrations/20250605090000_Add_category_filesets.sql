@@ -0,0 +1,9 @@
+-- The last seen version of every category fileset. Results of tests run before the fileset of
+-- their category changed are outdated.
+CREATE TABLE CategoryFilesets
+(
+    category   VARCHAR(255) PRIMARY KEY,
+    hash       VARCHAR(64)  NOT NULL,
+    version    INTEGER      NOT NULL,
+    updated_at INTEGER      NOT NULL
+);
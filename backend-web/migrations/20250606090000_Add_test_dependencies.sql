@@ -0,0 +1,2 @@
+ALTER TABLE Tests ADD COLUMN
+    depends_on TEXT NOT NULL DEFAULT '[]'; -- json list of tests that must pass before this one runs
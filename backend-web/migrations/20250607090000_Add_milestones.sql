@@ -0,0 +1,51 @@
+-- The first time a team passed a test, i.e. all of its profiles succeeded in a single task
+CREATE TABLE TestFirstPasses
+(
+    team_id   VARCHAR(36) NOT NULL REFERENCES Teams (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    test_id   VARCHAR(36) NOT NULL REFERENCES Tests (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    task_id   VARCHAR(36) REFERENCES Tasks (task_id) ON DELETE SET NULL ON UPDATE CASCADE,
+    passed_at INTEGER     NOT NULL,
+
+    PRIMARY KEY (team_id, test_id)
+);
+
+-- The first time a single task of a team passed at least `threshold` percent of a category
+CREATE TABLE CategoryMilestones
+(
+    team_id    VARCHAR(36)  NOT NULL REFERENCES Teams (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    category   VARCHAR(255) NOT NULL,
+    threshold  INTEGER      NOT NULL,
+    task_id    VARCHAR(36) REFERENCES Tasks (task_id) ON DELETE SET NULL ON UPDATE CASCADE,
+    reached_at INTEGER      NOT NULL,
+
+    PRIMARY KEY (team_id, category, threshold)
+);
+
+-- Backfill both from the tasks we already know about. SQLite picks the other columns from the row
+-- with the minimal queue time.
+WITH PassedTests AS (SELECT task_id, test_id
+                     FROM TestResults
+                     GROUP BY task_id, test_id
+                     HAVING MIN(status = 'Success') = 1)
+INSERT
+INTO TestFirstPasses (team_id, test_id, task_id, passed_at)
+SELECT Tasks.team_id, PassedTests.test_id, Tasks.task_id, MIN(Tasks.queue_time)
+FROM PassedTests
+         JOIN Tasks ON Tasks.task_id = PassedTests.task_id
+GROUP BY Tasks.team_id, PassedTests.test_id;
+
+WITH Thresholds(threshold) AS (VALUES (25), (50), (75), (100)),
+     TaskTests AS (SELECT TestResults.task_id, Tests.category, MIN(TestResults.status = 'Success') AS passed
+                   FROM TestResults
+                            JOIN Tests ON Tests.id = TestResults.test_id
+                   GROUP BY TestResults.task_id, TestResults.test_id),
+     CategoryRates AS (SELECT task_id, category, 100.0 * SUM(passed) / COUNT(*) AS rate
+                       FROM TaskTests
+                       GROUP BY task_id, category)
+INSERT
+INTO CategoryMilestones (team_id, category, threshold, task_id, reached_at)
+SELECT Tasks.team_id, CategoryRates.category, Thresholds.threshold, Tasks.task_id, MIN(Tasks.queue_time)
+FROM CategoryRates
+         JOIN Tasks ON Tasks.task_id = CategoryRates.task_id
+         JOIN Thresholds ON CategoryRates.rate >= Thresholds.threshold
+GROUP BY Tasks.team_id, CategoryRates.category, Thresholds.threshold;
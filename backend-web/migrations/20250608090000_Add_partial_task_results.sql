@@ -0,0 +1,20 @@
+-- Results a runner already reported for a task it is still working on. They are removed together
+-- with the queue entry, and allow finalizing the task if the runner vanishes before it is done.
+CREATE TABLE PartialTaskBuilds
+(
+    task_id      VARCHAR(36) PRIMARY KEY REFERENCES Queue (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    start_time   INTEGER NOT NULL,
+    -- A JSON encoded FinishedExecution
+    build_output TEXT    NOT NULL
+);
+
+CREATE TABLE PartialTestResults
+(
+    task_id VARCHAR(36)  NOT NULL REFERENCES PartialTaskBuilds (task_id) ON DELETE CASCADE ON UPDATE CASCADE,
+    test_id VARCHAR(36)  NOT NULL,
+    profile VARCHAR(255) NOT NULL,
+    -- A JSON encoded FinishedTest
+    result  TEXT         NOT NULL,
+
+    PRIMARY KEY (task_id, test_id, profile)
+);
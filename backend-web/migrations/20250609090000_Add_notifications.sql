@@ -0,0 +1,11 @@
+-- Messages for a team, e.g. why an admin rejected one of its provisional tests
+CREATE TABLE Notifications
+(
+    id         VARCHAR(36) PRIMARY KEY,
+    team_id    VARCHAR(36) NOT NULL REFERENCES Teams (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    message    TEXT        NOT NULL,
+    created_at INTEGER     NOT NULL,
+    read_at    INTEGER              DEFAULT NULL
+);
+
+CREATE INDEX idx_notifications_team ON Notifications (team_id);
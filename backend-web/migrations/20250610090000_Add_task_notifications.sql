@@ -0,0 +1,25 @@
+-- How a user wants to be told about finished tasks of their team
+CREATE TABLE TaskNotificationSettings
+(
+    user_id     VARCHAR(36) PRIMARY KEY REFERENCES Users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    on_success  BOOLEAN     NOT NULL,
+    on_failure  BOOLEAN     NOT NULL,
+    email       TEXT                 DEFAULT NULL,
+    webhook_url TEXT                 DEFAULT NULL
+);
+
+-- Notifications about finished tasks that still need to be sent out
+CREATE TABLE TaskNotificationDeliveries
+(
+    id           VARCHAR(36) PRIMARY KEY,
+    user_id      VARCHAR(36) NOT NULL REFERENCES Users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    task_id      VARCHAR(36) NOT NULL REFERENCES Tasks (task_id) ON DELETE CASCADE ON UPDATE CASCADE,
+    channel      VARCHAR(10) NOT NULL CHECK (channel IN ('Email', 'Webhook')),
+    target       TEXT        NOT NULL,
+    payload      TEXT        NOT NULL,
+    attempts     INTEGER     NOT NULL,
+    next_attempt INTEGER     NOT NULL,
+    last_error   TEXT                 DEFAULT NULL
+);
+
+CREATE INDEX idx_task_notification_deliveries_next_attempt ON TaskNotificationDeliveries (next_attempt);
@@ -0,0 +1,2 @@
+-- What runc and the shim logged while running the execution, kept apart from its stderr
+ALTER TABLE ExecutionResults ADD COLUMN infrastructure_log TEXT DEFAULT NULL;
@@ -0,0 +1,9 @@
+-- Expected outputs the reference compiler computed for tests uploaded without one
+CREATE TABLE ReferenceOutputs
+(
+    test_id         VARCHAR(120) PRIMARY KEY REFERENCES Tests (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    output          TEXT         NOT NULL,
+    image           TEXT         NOT NULL,
+    compile_command TEXT         NOT NULL, -- json serialized command
+    computed_at     INTEGER      NOT NULL
+);
@@ -0,0 +1,4 @@
+-- The size of the binary the compiler produced and how long compiling took, used to grade
+-- the performance of the compiler
+ALTER TABLE TestResults ADD COLUMN binary_size INTEGER DEFAULT NULL;
+ALTER TABLE TestResults ADD COLUMN compile_duration_ms INTEGER DEFAULT NULL;
@@ -0,0 +1,2 @@
+-- The compiler and binary commands a test ran, as a json serialized TestInvocation
+ALTER TABLE TestResults ADD COLUMN invocation TEXT DEFAULT NULL;
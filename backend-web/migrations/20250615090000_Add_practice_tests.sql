@@ -0,0 +1,3 @@
+-- Practice tests are distributed to teams, but never run on the server
+ALTER TABLE Tests ADD COLUMN
+    practice BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,15 @@
+-- Team memberships managed by the backend. Users need not have logged in yet to be listed here.
+CREATE TABLE TeamMembers
+(
+    user_id VARCHAR(36) PRIMARY KEY,
+    team_id VARCHAR(36) NOT NULL REFERENCES Teams (id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+ALTER TABLE Teams ADD COLUMN
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+
+INSERT INTO TeamMembers (user_id, team_id)
+SELECT id, team FROM Users WHERE team IS NOT NULL;
+
+UPDATE Teams SET is_admin = TRUE
+WHERE id IN (SELECT team FROM Users WHERE role = 'Admin' AND team IS NOT NULL);
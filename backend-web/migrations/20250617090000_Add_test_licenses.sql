@@ -0,0 +1,3 @@
+-- The license and attribution of a test, so the shared suite can be published
+ALTER TABLE Tests ADD COLUMN
+    license TEXT DEFAULT NULL;
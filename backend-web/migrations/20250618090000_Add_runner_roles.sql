@@ -0,0 +1,6 @@
+-- Roles admins assigned to runners at runtime, overriding the role a runner registers with
+CREATE TABLE RunnerRoles
+(
+    runner_id   VARCHAR(255) PRIMARY KEY,
+    test_taster BOOLEAN NOT NULL
+);
@@ -0,0 +1,3 @@
+-- The output of an execution as it appeared over time, as json. Only recorded if configured.
+ALTER TABLE ExecutionResults
+    ADD COLUMN recording TEXT;
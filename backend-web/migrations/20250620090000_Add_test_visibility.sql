@@ -0,0 +1,6 @@
+-- Who may see a test besides its owner. Hidden tests are still run, as the column only controls
+-- what teams are shown. `visible_from` is the end of the hiding for `HiddenUntil`.
+ALTER TABLE Tests ADD COLUMN
+    visibility TEXT NOT NULL DEFAULT 'Public';
+ALTER TABLE Tests ADD COLUMN
+    visible_from INTEGER DEFAULT NULL;
@@ -0,0 +1,3 @@
+-- The time and memory the program used as measured inside its container, as json
+ALTER TABLE ExecutionResults
+    ADD COLUMN resource_usage TEXT;
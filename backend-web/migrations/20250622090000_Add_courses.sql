@@ -0,0 +1,21 @@
+-- Several courses can share a deployment. Teams belong to exactly one of them, tests to the course
+-- they were created in. Tasks and queue entries belong to the course of their team.
+CREATE TABLE Courses
+(
+    id           VARCHAR(36) PRIMARY KEY,
+    display_name VARCHAR(255) NOT NULL CHECK (LENGTH(display_name) < 255),
+    archived     BOOLEAN      NOT NULL DEFAULT FALSE,
+    created_at   INTEGER      NOT NULL
+);
+
+INSERT INTO Courses (id, display_name, created_at)
+VALUES ('default', 'Default course', CAST(unixepoch('subsec') * 1000 as INTEGER));
+
+ALTER TABLE Teams
+    ADD COLUMN course_id VARCHAR(36) NOT NULL DEFAULT 'default';
+
+ALTER TABLE Tests
+    ADD COLUMN course_id VARCHAR(36) NOT NULL DEFAULT 'default';
+
+CREATE INDEX teams_course_idx ON Teams (course_id);
+CREATE INDEX tests_course_idx ON Tests (course_id);
@@ -0,0 +1,4 @@
+ALTER TABLE Queue
+    ADD COLUMN rerun_of VARCHAR(36) DEFAULT NULL;
+ALTER TABLE Tasks
+    ADD COLUMN rerun_of VARCHAR(36) DEFAULT NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE Queue
+    ADD COLUMN commit_metadata TEXT DEFAULT NULL;
+ALTER TABLE Tasks
+    ADD COLUMN commit_metadata TEXT DEFAULT NULL;
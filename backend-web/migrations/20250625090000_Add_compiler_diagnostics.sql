@@ -0,0 +1,2 @@
+-- The machine-readable diagnostics the compiler reported for the test, as a JSON array
+ALTER TABLE TestResults ADD COLUMN diagnostics TEXT DEFAULT NULL;
@@ -0,0 +1,31 @@
+-- Searches for the first revision of a team failing a test, given a passing and a failing one.
+-- Every step is a queued task running only that test.
+CREATE TABLE Bisects
+(
+    id                 VARCHAR(36) PRIMARY KEY,
+    team_id            VARCHAR(36)  NOT NULL REFERENCES Teams (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    test_id            VARCHAR(120) NOT NULL REFERENCES Tests (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    good_revision      VARCHAR(255) NOT NULL,
+    bad_revision       VARCHAR(255) NOT NULL,
+    -- json array of the revisions after the good one up to and including the bad one, oldest first
+    candidates         TEXT         NOT NULL,
+    -- the newest candidate known to pass, -1 for the good revision
+    good_index         INTEGER      NOT NULL,
+    -- the oldest candidate known to fail
+    bad_index          INTEGER      NOT NULL,
+    first_bad_revision VARCHAR(255) DEFAULT NULL,
+    created_at         INTEGER      NOT NULL,
+    finished_at        INTEGER      DEFAULT NULL
+);
+
+CREATE TABLE BisectSteps
+(
+    task_id   VARCHAR(36) PRIMARY KEY,
+    bisect_id VARCHAR(36)  NOT NULL REFERENCES Bisects (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    revision  VARCHAR(255) NOT NULL,
+    -- NULL until the task finished
+    passed    BOOLEAN      DEFAULT NULL,
+    queued_at INTEGER      NOT NULL
+);
+
+CREATE INDEX bisect_steps_bisect_idx ON BisectSteps (bisect_id);
@@ -0,0 +1,21 @@
+-- Codes admins hand out so users can join a team without a config change
+CREATE TABLE TeamInvites
+(
+    code       VARCHAR(64) PRIMARY KEY,
+    team_id    VARCHAR(36) NOT NULL REFERENCES Teams (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    created_by VARCHAR(36) NOT NULL,
+    created_at INTEGER     NOT NULL,
+    expires_at INTEGER,
+    max_uses   INTEGER     NOT NULL
+);
+
+-- Users who joined a team with an invite. Config team syncs apply them again, as the config does
+-- not list these users.
+CREATE TABLE TeamInviteRedemptions
+(
+    user_id     VARCHAR(36) PRIMARY KEY,
+    code        VARCHAR(64) NOT NULL REFERENCES TeamInvites (code) ON DELETE CASCADE,
+    redeemed_at INTEGER     NOT NULL
+);
+
+CREATE INDEX team_invite_redemptions_code_idx ON TeamInviteRedemptions (code);
@@ -0,0 +1,5 @@
+-- Outputs over the configured limit are stored shortened, these files hold the whole stream
+ALTER TABLE ExecutionResults
+    ADD COLUMN full_stdout_path TEXT;
+ALTER TABLE ExecutionResults
+    ADD COLUMN full_stderr_path TEXT;
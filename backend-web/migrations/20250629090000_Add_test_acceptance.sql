@@ -0,0 +1,7 @@
+-- Whether a test counts, decided by the tasting policy on upload. Only `Accepted` tests are run,
+-- `Pending` ones wait for an admin and `Rejected` ones for a fixed upload. `acceptance_message`
+-- tells the owner why a test is not accepted.
+ALTER TABLE Tests
+    ADD COLUMN acceptance TEXT NOT NULL DEFAULT 'Accepted';
+ALTER TABLE Tests
+    ADD COLUMN acceptance_message TEXT DEFAULT NULL;
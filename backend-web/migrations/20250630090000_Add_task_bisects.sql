@@ -0,0 +1,7 @@
+-- Bisect steps only run a single test on an old revision. They are kept apart from the team's
+-- regular tasks, so they do not show up in listings, statistics or grading.
+ALTER TABLE Tasks
+    ADD COLUMN bisect_id VARCHAR(36) DEFAULT NULL;
+
+UPDATE Tasks
+SET bisect_id = (SELECT bisect_id FROM BisectSteps WHERE BisectSteps.task_id = Tasks.task_id);
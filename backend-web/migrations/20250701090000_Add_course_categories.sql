@@ -0,0 +1,85 @@
+-- Every course configures its own categories, so two courses may both have a `Task 1`. Everything
+-- keyed by a category is keyed by its course as well. Existing rows belong to the default course,
+-- final submissions to the course their team is in.
+CREATE TABLE TestSuiteSnapshotsWithCourse
+(
+    course_id VARCHAR(36) NOT NULL DEFAULT 'default',
+    category  VARCHAR     NOT NULL,
+    frozen_at INTEGER     NOT NULL,
+    frozen_by VARCHAR(36) DEFAULT NULL,
+    tests     TEXT        NOT NULL,
+
+    PRIMARY KEY (course_id, category)
+);
+
+INSERT INTO TestSuiteSnapshotsWithCourse
+    (category, frozen_at, frozen_by, tests)
+SELECT category, frozen_at, frozen_by, tests
+FROM TestSuiteSnapshots;
+
+DROP TABLE TestSuiteSnapshots;
+
+ALTER TABLE TestSuiteSnapshotsWithCourse RENAME TO TestSuiteSnapshots;
+
+CREATE TABLE CategoryFilesetsWithCourse
+(
+    course_id  VARCHAR(36)  NOT NULL DEFAULT 'default',
+    category   VARCHAR(255) NOT NULL,
+    hash       VARCHAR(64)  NOT NULL,
+    version    INTEGER      NOT NULL,
+    updated_at INTEGER      NOT NULL,
+
+    PRIMARY KEY (course_id, category)
+);
+
+INSERT INTO CategoryFilesetsWithCourse
+    (category, hash, version, updated_at)
+SELECT category, hash, version, updated_at
+FROM CategoryFilesets;
+
+DROP TABLE CategoryFilesets;
+
+ALTER TABLE CategoryFilesetsWithCourse RENAME TO CategoryFilesets;
+
+CREATE TABLE FinalizedSubmittedTasksWithCourse
+(
+    team_id   VARCHAR(36) NOT NULL REFERENCES Teams (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    course_id VARCHAR(36) NOT NULL,
+    task_id   VARCHAR(36) NOT NULL,
+    category  VARCHAR(10) NOT NULL,
+
+    PRIMARY KEY (team_id, course_id, category)
+);
+
+INSERT INTO FinalizedSubmittedTasksWithCourse
+    (team_id, course_id, task_id, category)
+SELECT FinalizedSubmittedTasks.team_id, Teams.course_id, task_id, category
+FROM FinalizedSubmittedTasks
+JOIN Teams ON Teams.id = FinalizedSubmittedTasks.team_id;
+
+DROP TABLE FinalizedSubmittedTasks;
+
+ALTER TABLE FinalizedSubmittedTasksWithCourse RENAME TO FinalizedSubmittedTasks;
+
+CREATE TABLE ManuallySubmittedTasksWithCourse
+(
+    team_id     VARCHAR(36) NOT NULL REFERENCES Teams (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    course_id   VARCHAR(36) NOT NULL,
+    category    VARCHAR(10) NOT NULL,
+    task_id     VARCHAR(36) REFERENCES Tasks (task_id) ON DELETE CASCADE ON UPDATE CASCADE,
+    -- user kept for disputes...
+    user_id     VARCHAR(36) NOT NULL REFERENCES Users (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    update_time INTEGER     NOT NULL,
+
+    PRIMARY KEY (team_id, course_id, category)
+);
+
+INSERT INTO ManuallySubmittedTasksWithCourse
+    (team_id, course_id, category, task_id, user_id, update_time)
+SELECT ManuallySubmittedTasks.team_id, Teams.course_id, category, task_id, user_id, update_time
+FROM ManuallySubmittedTasks
+JOIN Teams ON Teams.id = ManuallySubmittedTasks.team_id;
+
+DROP TABLE ManuallySubmittedTasks;
+
+ALTER TABLE ManuallySubmittedTasksWithCourse RENAME TO ManuallySubmittedTasks;
@@ -3,14 +3,18 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::error::{Result, WebError};
 use crate::types::{JwtIssuer, UserId, UserRole};
-pub use extractors::Claims;
+pub use extractors::{Claims, ScopedClaims, scopes};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use snafu::location;
 use tracing::{debug, info, instrument, warn};
 
 pub mod extractors;
+pub mod ldap;
 pub mod oidc;
+pub mod password;
+pub mod provider;
 
 #[derive(Clone)]
 pub struct Keys {
@@ -69,3 +73,50 @@ fn validate_jwt(jwt: &str, keys: &Keys) -> Result<CrowJwt> {
             WebError::invalid_credentials(location!())
         })
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TestArchiveClaims {
+    iss: JwtIssuer,
+    /// The hex encoded sha256 of the signed archive contents
+    sha256: String,
+}
+
+const TEST_ARCHIVE_ISSUER: &str = "compilers-test-archive";
+
+/// Signs the digest of a test suite archive. Unlike login tokens, the signature does not expire.
+pub fn sign_test_archive(sha256: String, keys: &Keys) -> jsonwebtoken::errors::Result<String> {
+    let claims = TestArchiveClaims {
+        iss: JwtIssuer(TEST_ARCHIVE_ISSUER.to_string()),
+        sha256,
+    };
+    encode(&Header::default(), &claims, &keys.encoding)
+}
+
+/// Returns the digest of the test suite archive the signature was created for
+pub fn verify_test_archive(signature: &str, keys: &Keys) -> jsonwebtoken::errors::Result<String> {
+    let mut validation = Validation::default();
+    validation.set_issuer(&[TEST_ARCHIVE_ISSUER]);
+    validation.set_required_spec_claims(&["iss"]);
+    validation.validate_exp = false;
+
+    decode::<TestArchiveClaims>(signature, &keys.decoding, &validation).map(|it| it.claims.sha256)
+}
+
+/// Prefix of API tokens, used to tell them apart from JWTs
+pub const API_TOKEN_PREFIX: &str = "crow_";
+
+/// Creates a new random API token, returning the token and the hash to store
+pub fn create_api_token() -> (String, String) {
+    let token = format!(
+        "{API_TOKEN_PREFIX}{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    let hash = hash_api_token(&token);
+
+    (token, hash)
+}
+
+pub fn hash_api_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
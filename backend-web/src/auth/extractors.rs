@@ -1,16 +1,20 @@
-use crate::auth::{CrowJwt, validate_jwt};
+use crate::auth::{API_TOKEN_PREFIX, CrowJwt, hash_api_token, validate_jwt};
 use crate::db::UserForAuth;
 use crate::error::WebError;
-use crate::types::{AppState, JwtIssuer, TeamId, UserId, UserRole};
+use crate::types::{
+    ApiTokenScope, AppState, CourseId, JwtIssuer, TeamId, Test, TestAcceptance, UserId, UserRole,
+};
 use axum::extract::FromRequestParts;
 use axum::http::request::Parts;
 use axum::{RequestPartsExt, async_trait};
 use axum_extra::TypedHeader;
 use axum_extra::headers::Authorization;
 use axum_extra::headers::authorization::Bearer;
+use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 use snafu::location;
 use std::fmt::Debug;
+use std::marker::PhantomData;
 use tracing::info;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +24,9 @@ pub struct Claims<T: Debug + Clone = TeamId> {
     pub exp: u64,
     pub iss: JwtIssuer,
     pub role: UserRole,
+    /// The course of the team, looked up on every request like the role
+    #[serde(default)]
+    pub course: CourseId,
 }
 
 macro_rules! implement_request_parts {
@@ -46,12 +53,89 @@ macro_rules! implement_request_parts {
 implement_request_parts!(Option<TeamId>);
 implement_request_parts!(TeamId);
 
+/// The [ApiTokenScope] an endpoint requires when it is called with an API token
+pub trait RequiredScope {
+    const SCOPE: ApiTokenScope;
+}
+
+pub mod scopes {
+    use super::RequiredScope;
+    use crate::types::ApiTokenScope;
+
+    macro_rules! scope {
+        ($name:ident) => {
+            #[derive(Debug, Clone)]
+            pub struct $name;
+
+            impl RequiredScope for $name {
+                const SCOPE: ApiTokenScope = ApiTokenScope::$name;
+            }
+        };
+    }
+
+    scope!(QueueRead);
+    scope!(QueueWrite);
+    scope!(TasksRead);
+    scope!(TestsRead);
+}
+
+/// The claims of a logged-in team member or of a team API token granted the scope `S`.
+/// API tokens act as a regular member of their team on behalf of the user who created them.
+#[derive(Debug, Clone)]
+pub struct ScopedClaims<S> {
+    pub claims: Claims,
+    _scope: PhantomData<S>,
+}
+
+#[async_trait]
+impl<S: RequiredScope + Send> FromRequestParts<AppState> for ScopedClaims<S> {
+    type Rejection = WebError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let TypedHeader(Authorization(bearer)) = parts
+            .extract::<TypedHeader<Authorization<Bearer>>>()
+            .await
+            .map_err(|_| WebError::invalid_credentials(location!()))?;
+
+        let claims = if bearer.token().starts_with(API_TOKEN_PREFIX) {
+            Claims::from_api_token(state, bearer.token(), S::SCOPE).await?
+        } else {
+            Claims::<TeamId>::from_token(state, bearer.token()).await?
+        };
+
+        Ok(Self {
+            claims,
+            _scope: PhantomData,
+        })
+    }
+}
+
 impl<T: Debug + Clone> Claims<T> {
     pub fn is_admin(&self) -> bool {
         self.role == UserRole::Admin
     }
 }
 
+impl Claims {
+    /// Whether the test is in our course and its [TestVisibility](crate::types::TestVisibility)
+    /// allows us to see it. Tests of other teams are only seen once they are accepted. Admins see
+    /// the tests of all courses.
+    pub fn can_see_test(&self, test: &Test) -> bool {
+        if self.is_admin() {
+            return true;
+        }
+        if test.owner == self.team {
+            return test.course == self.course;
+        }
+        test.course == self.course
+            && test.acceptance == TestAcceptance::Accepted
+            && test.visibility.is_public_at(Timestamp::now())
+    }
+}
+
 impl Claims<Option<TeamId>> {
     pub async fn from_token(state: &AppState, token: &str) -> Result<Self, WebError> {
         let (claims, user) = jwt_user_from_token(state, token).await?;
@@ -62,6 +146,7 @@ impl Claims<Option<TeamId>> {
             exp: claims.exp,
             iss: claims.iss,
             role: claims.role,
+            course: user.course,
         })
     }
 }
@@ -80,10 +165,59 @@ impl Claims<TeamId> {
             exp: claims.exp,
             iss: claims.iss,
             role: claims.role,
+            course: user.course,
+        })
+    }
+
+    async fn from_api_token(
+        state: &AppState,
+        token: &str,
+        scope: ApiTokenScope,
+    ) -> Result<Self, WebError> {
+        let Some(api_token) = state
+            .db
+            .fetch_api_token_by_hash(&hash_api_token(token))
+            .await?
+        else {
+            return Err(WebError::invalid_credentials(location!()));
+        };
+
+        if !api_token.scopes.contains(&scope) {
+            info!(token = %api_token.id, scope = %scope, "API token used without required scope");
+            return Err(WebError::named_unauthorized(
+                format!("access this resource without the `{scope}` scope"),
+                location!(),
+            ));
+        }
+
+        // Tokens act for their creator, so they stop working once the creator left the team
+        let creator = state.db.get_user_for_login(&api_token.created_by).await?;
+        if creator.and_then(|it| it.user.team).as_ref() != Some(&api_token.team) {
+            info!(
+                token = %api_token.id,
+                user = %api_token.created_by,
+                "API token used after its creator left the team"
+            );
+            return Err(WebError::invalid_credentials(location!()));
+        }
+
+        state.db.mark_api_token_used(&api_token.id).await?;
+        let team = state.db.get_team(&api_token.team).await?;
+
+        Ok(Self {
+            sub: api_token.created_by,
+            team: api_token.team,
+            // API tokens do not expire, they are revoked by deleting them
+            exp: u64::MAX,
+            iss: JwtIssuer(API_TOKEN_ISSUER.to_string()),
+            role: UserRole::Regular,
+            course: team.course,
         })
     }
 }
 
+const API_TOKEN_ISSUER: &str = "api-token";
+
 async fn jwt_user_from_token(
     state: &AppState,
     token: &str,
@@ -0,0 +1,130 @@
+use crate::auth::provider::LoginUser;
+use crate::config::LdapConfig;
+use ldap3::{LdapConnAsync, LdapConnSettings};
+use snafu::{IntoError, Location, NoneError, OptionExt, ResultExt, Snafu};
+use std::time::Duration;
+use tracing::warn;
+use url::Url;
+
+/// How long connecting and binding to the server may take
+const BIND_TIMEOUT: Duration = Duration::from_secs(10);
+/// The result code of a bind with a wrong password or an unknown DN
+const INVALID_CREDENTIALS: u32 = 49;
+
+#[derive(Debug, Snafu)]
+pub enum LdapError {
+    #[snafu(display("Could not parse LDAP URL `{url}` at {location}"))]
+    InvalidUrl {
+        url: String,
+        source: url::ParseError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display(
+        "LDAP URL `{url}` needs an `ldap://` or `ldaps://` scheme and a host at {location}"
+    ))]
+    UnsupportedUrl {
+        url: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not talk to the LDAP server `{server}` at {location}"))]
+    Connection {
+        server: String,
+        #[snafu(source(from(ldap3::LdapError, Box::new)))]
+        source: Box<ldap3::LdapError>,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Binding to `{server}` timed out at {location}"))]
+    Timeout {
+        server: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("LDAP bind failed with result code {code}: `{message}` at {location}"))]
+    BindFailed {
+        code: u32,
+        message: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// Checks passwords by binding to an LDAP server as the user
+#[derive(Debug, Clone)]
+pub struct Ldap {
+    url: Url,
+    bind_dn: String,
+}
+
+impl Ldap {
+    pub fn new(config: &LdapConfig) -> Result<Self, LdapError> {
+        let url = Url::parse(&config.url).context(InvalidUrlSnafu { url: &config.url })?;
+        if !matches!(url.scheme(), "ldap" | "ldaps") {
+            return UnsupportedUrlSnafu { url: &config.url }.fail();
+        }
+        url.host_str()
+            .context(UnsupportedUrlSnafu { url: &config.url })?;
+
+        Ok(Self {
+            url,
+            bind_dn: config.bind_dn.clone(),
+        })
+    }
+
+    /// Returns the user if the server accepts the password
+    pub async fn log_in(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<LoginUser>, LdapError> {
+        // Binds without a password are unauthenticated and succeed for every DN
+        if username.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        let dn = self
+            .bind_dn
+            .replace("{username}", &ldap3::dn_escape(username));
+        let server = self.url.to_string();
+        let accepted = tokio::time::timeout(BIND_TIMEOUT, self.bind(&server, &dn, password))
+            .await
+            .map_err(|_| TimeoutSnafu { server }.into_error(NoneError))??;
+
+        Ok(accepted.then(|| LoginUser {
+            id: username.to_string(),
+            name: username.to_string(),
+        }))
+    }
+
+    /// Sends a simple bind request and returns whether the server accepted the credentials
+    async fn bind(&self, server: &str, dn: &str, password: &str) -> Result<bool, LdapError> {
+        let settings = LdapConnSettings::new().set_conn_timeout(BIND_TIMEOUT);
+        let (connection, mut ldap) = LdapConnAsync::from_url_with_settings(settings, &self.url)
+            .await
+            .context(ConnectionSnafu { server })?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.drive().await {
+                warn!(error = %e, "LDAP connection failed");
+            }
+        });
+
+        let result = ldap
+            .simple_bind(dn, password)
+            .await
+            .context(ConnectionSnafu { server })?;
+        // Unbinding closes the connection, we have our answer either way
+        let _ = ldap.unbind().await;
+
+        match result.rc {
+            0 => Ok(true),
+            INVALID_CREDENTIALS => Ok(false),
+            code => BindFailedSnafu {
+                code,
+                message: result.text,
+            }
+            .fail(),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::auth::provider::LoginUser;
 use crate::config::OidcConfig;
 use derive_more::Display;
 use oauth2::basic::{BasicErrorResponseType, BasicRevocationErrorResponse};
@@ -277,7 +278,7 @@ impl Oidc {
         flow_id: OidcFlowId,
         auth_token: &str,
         state: &str,
-    ) -> Result<OidcUser, OidcError> {
+    ) -> Result<LoginUser, OidcError> {
         let pending_auth = self
             .pending_auths
             .lock()
@@ -336,7 +337,7 @@ impl Oidc {
             .unwrap_or("<not provided>")
             .to_string();
 
-        let user = OidcUser { id, name };
+        let user = LoginUser { id, name };
 
         if self
             .oidc_config
@@ -353,8 +354,8 @@ impl Oidc {
         &self,
         token_response: &CoreTokenResponse,
         claims: &CoreIdTokenClaims,
-        user: OidcUser,
-    ) -> Result<OidcUser, OidcError> {
+        user: LoginUser,
+    ) -> Result<LoginUser, OidcError> {
         let info_request = self
             .oidc_client
             .user_info(
@@ -379,12 +380,6 @@ impl Oidc {
             None => user.name,
         };
 
-        Ok(OidcUser { id, name })
+        Ok(LoginUser { id, name })
     }
 }
-
-#[derive(Debug, Clone)]
-pub struct OidcUser {
-    pub id: String,
-    pub name: String,
-}
@@ -0,0 +1,105 @@
+use crate::auth::provider::LoginUser;
+use crate::config::StaticUserConfig;
+use pbkdf2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use pbkdf2::{Algorithm, Params, Pbkdf2};
+use snafu::{Location, Snafu};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, Snafu)]
+pub enum PasswordError {
+    #[snafu(display("The password hash of user `{username}` is malformed at {location}"))]
+    MalformedHash {
+        username: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// Hashes a password for a static user in the config. The result is a PBKDF2-HMAC-SHA256 hash in
+/// the PHC string format, using as many rounds as OWASP recommends.
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).expect("Salt has a valid size");
+    Pbkdf2
+        .hash_password_customized(password.as_bytes(), None, None, Params::default(), &salt)
+        .expect("Default PBKDF2 params are valid")
+        .to_string()
+}
+
+fn password_matches(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash)
+        .and_then(|hash| Pbkdf2.verify_password(password.as_bytes(), &hash))
+        .is_ok()
+}
+
+struct StaticUser {
+    display_name: String,
+    /// Checked to be a PBKDF2 hash in the PHC string format when loading the config
+    password_hash: String,
+}
+
+/// The users listed in the config
+#[derive(Clone)]
+pub struct StaticUsers {
+    users: Arc<HashMap<String, StaticUser>>,
+    /// Checked for unknown users, so they take as long as wrong passwords
+    dummy_hash: Arc<String>,
+}
+
+impl StaticUsers {
+    pub fn new(users: &[StaticUserConfig]) -> Result<Self, PasswordError> {
+        let mut parsed = HashMap::new();
+        for user in users {
+            let valid = PasswordHash::new(&user.password_hash)
+                .is_ok_and(|it| Algorithm::try_from(it.algorithm).is_ok());
+            if !valid {
+                return MalformedHashSnafu {
+                    username: user.username.clone(),
+                }
+                .fail();
+            }
+            let display_name = user
+                .display_name
+                .clone()
+                .unwrap_or_else(|| user.username.clone());
+            parsed.insert(
+                user.username.clone(),
+                StaticUser {
+                    display_name,
+                    password_hash: user.password_hash.clone(),
+                },
+            );
+        }
+
+        Ok(Self {
+            users: Arc::new(parsed),
+            dummy_hash: Arc::new(hash_password("")),
+        })
+    }
+
+    /// Returns the user if the password is correct
+    pub async fn log_in(&self, username: &str, password: &str) -> Option<LoginUser> {
+        let users = self.users.clone();
+        let dummy_hash = self.dummy_hash.clone();
+        let username = username.to_string();
+        let password = password.to_string();
+
+        // Hashing takes a while, which should not block the runtime
+        tokio::task::spawn_blocking(move || {
+            let Some(user) = users.get(&username) else {
+                password_matches(&dummy_hash, &password);
+                return None;
+            };
+            if !password_matches(&user.password_hash, &password) {
+                return None;
+            }
+
+            Some(LoginUser {
+                id: username,
+                name: user.display_name.clone(),
+            })
+        })
+        .await
+        .expect("Password check panicked")
+    }
+}
@@ -0,0 +1,114 @@
+use crate::auth::ldap::{Ldap, LdapError};
+use crate::auth::oidc::{Oidc, OidcError};
+use crate::auth::password::{PasswordError, StaticUsers};
+use crate::config::{AuthConfig, OidcConfig};
+use serde::Serialize;
+use snafu::{IntoError, Location, NoneError, ResultExt, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum AuthProviderError {
+    #[snafu(display("Logging in through OIDC needs an `[oidc]` section at {location}"))]
+    MissingOidcConfig {
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not set up OIDC at {location}"))]
+    OidcSetup {
+        source: OidcError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not read the static users at {location}"))]
+    StaticUsersSetup {
+        source: PasswordError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not set up LDAP at {location}"))]
+    LdapSetup {
+        source: LdapError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// A user who proved who they are to the auth provider
+#[derive(Debug, Clone)]
+pub struct LoginUser {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuthProviderKind {
+    Oidc,
+    Static,
+    Ldap,
+}
+
+/// Checks who users are. OIDC redirects users to the provider, the others take a username and
+/// password.
+#[derive(Clone)]
+pub enum AuthProvider {
+    Oidc(Box<Oidc>),
+    Static(StaticUsers),
+    Ldap(Ldap),
+}
+
+impl AuthProvider {
+    pub async fn from_config(
+        config: &AuthConfig,
+        oidc_config: Option<&OidcConfig>,
+    ) -> Result<Self, AuthProviderError> {
+        Ok(match config {
+            AuthConfig::Oidc => {
+                let Some(oidc_config) = oidc_config else {
+                    return Err(MissingOidcConfigSnafu.into_error(NoneError));
+                };
+                let oidc = Oidc::build_new(oidc_config.clone())
+                    .await
+                    .context(OidcSetupSnafu)?;
+                Self::Oidc(Box::new(oidc))
+            }
+            AuthConfig::Static { users } => {
+                Self::Static(StaticUsers::new(users).context(StaticUsersSetupSnafu)?)
+            }
+            AuthConfig::Ldap(ldap_config) => {
+                Self::Ldap(Ldap::new(ldap_config).context(LdapSetupSnafu)?)
+            }
+        })
+    }
+
+    pub fn kind(&self) -> AuthProviderKind {
+        match self {
+            Self::Oidc(_) => AuthProviderKind::Oidc,
+            Self::Static(_) => AuthProviderKind::Static,
+            Self::Ldap(_) => AuthProviderKind::Ldap,
+        }
+    }
+
+    pub fn oidc(&self) -> Option<&Oidc> {
+        match self {
+            Self::Oidc(oidc) => Some(oidc.as_ref()),
+            _ => None,
+        }
+    }
+
+    pub fn takes_passwords(&self) -> bool {
+        self.oidc().is_none()
+    }
+
+    /// Returns the user if the password is correct. OIDC takes no passwords, so nobody logs in.
+    pub async fn log_in_with_password(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<LoginUser>, LdapError> {
+        match self {
+            Self::Oidc(_) => Ok(None),
+            Self::Static(users) => Ok(users.log_in(username, password).await),
+            Self::Ldap(ldap) => ldap.log_in(username, password).await,
+        }
+    }
+}
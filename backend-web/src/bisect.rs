@@ -0,0 +1,242 @@
+use crate::error::{Result, WebError};
+use crate::types::{AppState, Bisect, FinishedTestSummary, TaskId, TeamId, TestId, WorkItem};
+use jiff::Timestamp;
+use snafu::{Report, location};
+use std::time::{Duration, SystemTime};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+/// How often we look for bisect steps whose task finished
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Starts searching for the first revision between the good and bad one that fails the test. The
+/// bad revision must descend from the good one.
+pub async fn start_bisect(
+    state: &AppState,
+    team: &TeamId,
+    test_id: &TestId,
+    good: &str,
+    bad: &str,
+) -> Result<Bisect> {
+    let course = state.db.get_team(team).await?.course;
+    let test = state.db.fetch_test(test_id).await?;
+    let Some(test) = test.filter(|it| it.course == course) else {
+        return Err(WebError::named_not_found(
+            format!("Test `{test_id}`"),
+            location!(),
+        ));
+    };
+
+    let repo = state.db.get_repo(team).await?;
+    state.local_repos.update_repo(&repo).await?;
+    let mut revisions = Vec::new();
+    for revision in [good, bad] {
+        let Some(resolved) = state.local_repos.get_revision(&repo, revision).await? else {
+            return Err(WebError::named_not_found(
+                format!("Revision `{revision}` in your team repository"),
+                location!(),
+            ));
+        };
+        revisions.push(resolved);
+    }
+    let (good, bad) = (&revisions[0], &revisions[1]);
+
+    let candidates = state
+        .local_repos
+        .get_revisions_between(&repo, good, bad)
+        .await?
+        .into_iter()
+        .map(|it| it.to_string())
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return Err(WebError::named_bad_request(
+            format!("Revision `{bad}` does not descend from `{good}`"),
+            location!(),
+        ));
+    }
+
+    // A bad revision directly after the good one needs no steps
+    let done = candidates.len() == 1;
+    let now = Timestamp::now();
+    let bisect = Bisect {
+        id: Uuid::new_v4().to_string().into(),
+        team_id: team.clone(),
+        test_id: test.id,
+        good_revision: good.to_string(),
+        bad_revision: bad.to_string(),
+        good_index: -1,
+        bad_index: candidates.len() as i64 - 1,
+        first_bad_revision: done.then(|| bad.to_string()),
+        candidates,
+        created_at: now,
+        finished_at: done.then_some(now),
+        steps: Vec::new(),
+    };
+    let first_step = match bisect.next_candidate() {
+        Some(next) => Some(step_task(state, &bisect, &bisect.candidates[next]).await?),
+        None => None,
+    };
+    let first_task = first_step.as_ref().map(|it| it.id.clone());
+    state.db.add_bisect(&bisect, first_step).await?;
+    info!(
+        bisect = %bisect.id,
+        team = %team,
+        test = %bisect.test_id,
+        candidates = bisect.candidates.len(),
+        task = ?first_task,
+        "Started bisect"
+    );
+
+    Ok(state.db.get_bisect(&bisect.id).await?.unwrap_or(bisect))
+}
+
+/// Evaluates the steps of running bisects once their task finished and queues the next ones
+#[instrument(skip_all)]
+pub async fn run_bisector(state: AppState) {
+    loop {
+        match state.db.get_unfinished_bisects().await {
+            Ok(bisects) => {
+                for bisect in bisects {
+                    let bisect_id = bisect.id.clone();
+                    if let Err(e) = advance_bisect(&state, bisect).await {
+                        warn!(
+                            bisect = %bisect_id,
+                            error = %Report::from_error(e),
+                            "Failed to advance bisect"
+                        );
+                    }
+                }
+            }
+            Err(e) => warn!(error = %Report::from_error(e), "Failed to fetch running bisects"),
+        }
+
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+async fn advance_bisect(state: &AppState, mut bisect: Bisect) -> Result<()> {
+    if let Some(step) = bisect.running_step().cloned() {
+        let Some(passed) = step_outcome(state, &bisect.test_id, &step.task_id).await? else {
+            return Ok(());
+        };
+        let Some(index) = bisect.candidates.iter().position(|it| *it == step.revision) else {
+            return Err(WebError::internal_error(
+                format!("Bisect step `{}` tested an unknown revision", step.task_id),
+                location!(),
+            ));
+        };
+
+        if passed {
+            bisect.good_index = bisect.good_index.max(index as i64);
+        } else {
+            bisect.bad_index = bisect.bad_index.min(index as i64);
+        }
+        if bisect.next_candidate().is_none() {
+            bisect.first_bad_revision = Some(bisect.candidates[bisect.bad_index as usize].clone());
+        }
+        state
+            .db
+            .finish_bisect_step(&bisect, &step.task_id, passed)
+            .await?;
+        info!(
+            bisect = %bisect.id,
+            revision = %step.revision,
+            passed = passed,
+            first_bad = ?bisect.first_bad_revision,
+            "Bisect step finished"
+        );
+    }
+
+    match bisect.next_candidate() {
+        Some(next) => queue_step(state, &bisect, &bisect.candidates[next]).await,
+        None => Ok(()),
+    }
+}
+
+/// Whether the test passed in the task, or none if the task did not finish yet. Tasks that vanished
+/// without finishing, e.g. because an admin removed them from the queue, are forgotten, so the
+/// revision is queued again.
+async fn step_outcome(
+    state: &AppState,
+    test_id: &TestId,
+    task_id: &TaskId,
+) -> Result<Option<bool>> {
+    if state.db.fetch_task_end_time(task_id).await?.is_none() {
+        let pending = state.db.fetch_queued_task(task_id).await?.is_some()
+            || state
+                .executor
+                .lock()
+                .unwrap()
+                .get_running_task(task_id)
+                .is_some();
+        // It might have finished while we looked
+        if !pending && state.db.fetch_task_end_time(task_id).await?.is_none() {
+            warn!(task = %task_id, "Bisect step vanished without finishing, queueing it again");
+            state.db.remove_bisect_step(task_id).await?;
+        }
+        return Ok(None);
+    }
+
+    // Failed builds have no results, which counts as failing. Every profile has to pass.
+    let results = state
+        .db
+        .get_finished_test_summaries(task_id)
+        .await?
+        .into_iter()
+        .filter(|it| &it.test_id == test_id)
+        .collect::<Vec<_>>();
+
+    Ok(Some(
+        !results.is_empty() && results.iter().all(FinishedTestSummary::passed),
+    ))
+}
+
+async fn queue_step(state: &AppState, bisect: &Bisect, revision: &str) -> Result<()> {
+    let task = step_task(state, bisect, revision).await?;
+    let task_id = task.id.clone();
+    let revision = task.revision.clone();
+    state.db.queue_bisect_step(&bisect.id, task).await?;
+    info!(
+        bisect = %bisect.id,
+        task = %task_id,
+        revision = %revision,
+        "Queued bisect step"
+    );
+
+    Ok(())
+}
+
+/// The task testing the revision in a step of the bisect
+async fn step_task(state: &AppState, bisect: &Bisect, revision: &str) -> Result<WorkItem> {
+    let repo = state.db.get_repo(&bisect.team_id).await?;
+    let Some(revision) = state.local_repos.get_revision(&repo, revision).await? else {
+        return Err(WebError::named_not_found(
+            format!(
+                "Revision `{revision}` in the repository of `{}`",
+                bisect.team_id
+            ),
+            location!(),
+        ));
+    };
+    let commit_message = state
+        .local_repos
+        .get_revision_message(&repo, &revision)
+        .await?;
+    let commit = state
+        .local_repos
+        .get_revision_metadata(&repo, &revision)
+        .await?;
+
+    Ok(WorkItem {
+        id: Uuid::new_v4().to_string().into(),
+        team: bisect.team_id.clone(),
+        revision: revision.to_string(),
+        commit_message,
+        insert_time: SystemTime::now(),
+        // Steps only run one test, other tasks of the same tree should not point to them
+        tree_hash: None,
+        identical_to: None,
+        rerun_of: None,
+        commit: Some(commit),
+    })
+}
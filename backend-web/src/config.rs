@@ -1,9 +1,16 @@
-use crate::types::{FinishedTestSummary, TeamId, UserId};
+use crate::types::{CourseId, FinishedTestSummary, TeamId, UserId};
 use jiff::{Timestamp, Zoned};
+use regex::Regex;
 use serde::{Deserialize, Deserializer};
+use shared::{
+    BinaryEnvironment, BindMount, CategoryFileset, CommandProfile, ContainerPermissions,
+    ContainerUser, ExecutionOutput, FilesetFile, PinnedEnvironment,
+};
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
 use std::time::Duration;
 
 #[derive(Debug, Deserialize)]
@@ -11,12 +18,20 @@ pub struct Config {
     pub database_path: PathBuf,
     pub jwt_secret: String,
     pub teams: Vec<TeamEntry>,
+    #[serde(default)]
+    pub team_sync: TeamSync,
     pub execution: ExecutionConfig,
     pub grading: GradingConfig,
     pub github: Option<GithubConfig>,
     pub test: TestConfig,
-    pub oidc: OidcConfig,
+    /// Required if users log in through OIDC
+    pub oidc: Option<OidcConfig>,
+    #[serde(default)]
+    pub auth: AuthConfig,
     pub ssh: Option<SshConfig>,
+    pub test_mirror: Option<TestMirrorConfig>,
+    pub task_notifications: Option<TaskNotificationConfig>,
+    pub retention: Option<RetentionConfig>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,6 +41,22 @@ pub struct TeamEntry {
     pub members: Vec<UserId>,
     #[serde(default)]
     pub is_admin: bool,
+    /// The course the team takes part in. Courses the config names are created on startup.
+    #[serde(default)]
+    pub course: CourseId,
+}
+
+/// How the teams of the config are applied to the database on startup
+#[derive(Debug, Copy, Clone, Default, Deserialize)]
+pub enum TeamSync {
+    /// The config is the source of truth. Teams and members it does not list are removed, so
+    /// changes made through the admin endpoints are lost on restart. Members who redeemed an
+    /// invite of a listed team are kept.
+    Config,
+    /// The config only creates teams that do not exist yet. Afterwards they are managed through
+    /// the admin endpoints.
+    #[default]
+    Seed,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -40,16 +71,134 @@ pub struct ExecutionConfig {
     pub test_timeout: Duration,
     pub build_image: String,
     pub reference_compiler_image: Option<String>,
+    pub reference_outputs: Option<ReferenceOutputConfig>,
+    /// Explain common causes of failed builds. Replaces the built-in rules if given.
+    #[serde(default = "default_build_hints")]
+    pub build_hints: Vec<BuildHintRule>,
+    /// Record the output of builds and tests as it appears over time, so it can be replayed
+    #[serde(default)]
+    pub record_output: bool,
 
     pub local_repo_path: PathBuf,
+    /// Fetch Git LFS objects and check them out when exporting a submission
+    #[serde(default)]
+    pub git_lfs: bool,
+    /// Only mirror this many commits of every branch, to speed up updating large repositories
+    pub shallow_clone_depth: Option<NonZeroU32>,
+    /// Stores only the start of huge outputs in the database and the rest in files
+    pub output_storage: Option<OutputStorageConfig>,
 }
 
 impl ExecutionConfig {
-    pub fn tasting_disabled(&self) -> bool {
-        self.reference_compiler_image.is_none()
+    /// The hints of all rules matching the output of a failed build, in the order of the rules
+    pub fn build_hints_for(&self, output: &ExecutionOutput) -> Vec<String> {
+        let (texts, exit_code) = match output {
+            ExecutionOutput::Aborted(aborted) => (
+                vec![
+                    aborted.stdout.as_str(),
+                    aborted.stderr.as_str(),
+                    aborted.infrastructure_log.as_deref().unwrap_or_default(),
+                ],
+                None,
+            ),
+            ExecutionOutput::Error(error) => (vec![error.message.as_str()], None),
+            ExecutionOutput::Success(_) => return Vec::new(),
+            ExecutionOutput::Failure { execution, .. } | ExecutionOutput::Timeout(execution) => (
+                vec![
+                    execution.stdout.as_str(),
+                    execution.stderr.as_str(),
+                    execution.infrastructure_log.as_deref().unwrap_or_default(),
+                ],
+                execution.exit_status,
+            ),
+        };
+
+        let mut hints: Vec<String> = Vec::new();
+        for rule in &self.build_hints {
+            if rule.exit_code.is_some() && rule.exit_code != exit_code {
+                continue;
+            }
+            if !texts.iter().any(|text| rule.pattern.is_match(text)) {
+                continue;
+            }
+            if !hints.contains(&rule.hint) {
+                hints.push(rule.hint.clone());
+            }
+        }
+
+        hints
     }
 }
 
+/// Keeps huge outputs, e.g. of an accidental endless print loop, out of the database
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutputStorageConfig {
+    /// The directory the whole stdout and stderr of shortened executions are written to
+    pub dir: PathBuf,
+    /// How many bytes of each stdout and stderr are kept in the database
+    #[serde(default = "default_stored_output_bytes")]
+    pub stored_bytes: usize,
+}
+
+fn default_stored_output_bytes() -> usize {
+    1024 * 1024
+}
+
+/// Attaches a hint to failed builds whose output matches
+#[derive(Debug, Clone, Deserialize)]
+pub struct BuildHintRule {
+    /// A regex searched for in the stdout, stderr and infrastructure log of the build
+    #[serde(deserialize_with = "parse_regex")]
+    pub pattern: Regex,
+    /// Only builds exiting with this code match
+    pub exit_code: Option<i32>,
+    /// Shown to the team next to the build output
+    pub hint: String,
+}
+
+fn default_build_hints() -> Vec<BuildHintRule> {
+    let rule = |pattern: &str, exit_code: Option<i32>, hint: &str| BuildHintRule {
+        pattern: Regex::new(pattern).expect("built-in build hint patterns are valid"),
+        exit_code,
+        hint: hint.to_string(),
+    };
+
+    vec![
+        rule(
+            r"build\.sh.*(?i:no such file or directory|not found)",
+            None,
+            "The build script was not found. \
+            Make sure `build.sh` is committed in the root of your repository.",
+        ),
+        rule(
+            r"build\.sh.*(?i:permission denied)",
+            None,
+            "The build script is not executable. \
+            Run `git update-index --chmod=+x build.sh` and commit the change.",
+        ),
+        rule(
+            r"OutOfMemoryError|Java heap space|Gradle build daemon disappeared unexpectedly",
+            None,
+            "The build ran out of memory. \
+            Build with `--no-daemon` and lower `org.gradle.jvmargs` in `gradle.properties`.",
+        ),
+        rule(
+            "",
+            Some(137),
+            "The build was killed, most likely because it ran out of memory.",
+        ),
+    ]
+}
+
+/// Computes the expected output of uploaded tests that do not specify one
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReferenceOutputConfig {
+    /// The docker image containing the reference compiler
+    pub image: String,
+    /// The command running the reference compiler
+    pub compile_command: Vec<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct GithubConfig {
     pub app_id: u64,
@@ -66,10 +215,68 @@ pub struct GithubConfig {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct TestConfig {
+    /// The categories of the default course. They are moved to [courses](Self::courses) when the
+    /// config is loaded.
+    #[serde(default)]
+    categories: HashMap<String, TestCategory>,
+    /// The categories of every course, keyed by course. Courses without categories have none.
+    #[serde(default)]
+    pub courses: HashMap<CourseId, CourseConfig>,
+    /// Signs exported test suite archives. Defaults to the jwt secret, share it with the
+    /// instance that should import them.
+    pub archive_secret: Option<String>,
+    /// The license of uploaded tests that do not name their own, e.g. `CC0-1.0`
+    pub default_license: Option<String>,
+    #[serde(default)]
+    pub tasting_policy: TastingPolicy,
+}
+
+/// What happens to uploaded tests that fail on the reference compiler. Passing tests and tests of
+/// admins are always accepted.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TastingPolicy {
+    /// The test is accepted if the uploader ignores the failure
+    #[default]
+    AcceptAll,
+    /// The test is rejected until its owner uploads a version that passes
+    RequireReferencePass,
+    /// The test waits for an admin to accept or reject it
+    RequireAdminReview,
+}
+
+/// The categories of a course, with their deadlines, grading formulas and profiles
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CourseConfig {
+    #[serde(default)]
     pub categories: HashMap<String, TestCategory>,
+    /// The loaded filesets of all categories that have one, keyed by category
+    #[serde(skip)]
+    pub filesets: HashMap<String, CategoryFileset>,
 }
 
+static NO_COURSE_CONFIG: LazyLock<CourseConfig> = LazyLock::new(CourseConfig::default);
+
 impl TestConfig {
+    /// Moves the categories of the default course to the other courses
+    pub fn merge_default_course(&mut self) {
+        let categories = std::mem::take(&mut self.categories);
+        if !categories.is_empty() {
+            self.courses
+                .entry(CourseId::default())
+                .or_default()
+                .categories
+                .extend(categories);
+        }
+    }
+
+    /// Returns the categories of the course, which are empty for courses the config does not name
+    pub fn course(&self, course: &CourseId) -> &CourseConfig {
+        self.courses.get(course).unwrap_or(&NO_COURSE_CONFIG)
+    }
+}
+
+impl CourseConfig {
     pub fn active_categories(&self) -> Vec<&str> {
         self.categories
             .iter()
@@ -78,6 +285,59 @@ impl TestConfig {
             .collect()
     }
 
+    /// Returns whether tests of the given category skip executing the binary
+    pub fn is_compile_only(&self, category: &str) -> bool {
+        self.categories
+            .get(category)
+            .is_some_and(|category| category.compile_only)
+    }
+
+    /// Returns the command profiles tests of the given category are run with
+    pub fn profiles_for(&self, category: &str) -> Vec<CommandProfile> {
+        self.categories
+            .get(category)
+            .map(|category| category.profiles.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn runner_prefix_for(&self, category: &str) -> Vec<String> {
+        self.categories
+            .get(category)
+            .map(|category| category.runner_prefix.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the user and extra mounts the test containers of the given category run with
+    pub fn permissions_for(&self, category: &str) -> ContainerPermissions {
+        self.categories
+            .get(category)
+            .map(TestCategory::permissions)
+            .unwrap_or_default()
+    }
+
+    /// Returns how the compiled binaries of the given category are run
+    pub fn binary_environment_for(&self, category: &str) -> BinaryEnvironment {
+        self.categories
+            .get(category)
+            .and_then(|category| category.binary_environment.as_ref())
+            .map(BinaryEnvironmentConfig::to_binary_environment)
+            .unwrap_or_default()
+    }
+
+    /// Returns the permissions of a build shared by tests of the given categories
+    pub fn build_permissions_for(&self, categories: &[&str]) -> ContainerPermissions {
+        let mut permissions = ContainerPermissions::default();
+        for category in categories {
+            permissions.merge(&self.permissions_for(category));
+        }
+        permissions
+    }
+
+    /// Returns the fileset tests of the given category need in their working directory
+    pub fn fileset_for(&self, category: &str) -> Option<CategoryFileset> {
+        self.filesets.get(category).cloned()
+    }
+
     /// Sorted by end time of lab
     pub fn sorted_categories(&self) -> Vec<&str> {
         let mut categories = self.categories.iter().collect::<Vec<_>>();
@@ -133,9 +393,44 @@ pub struct TestCategory {
     pub labs_end_at: Zoned,
     pub tests_end_at: Zoned,
     pub grading_formula: Option<evalexpr::Node>,
+    /// Tests in this category only run the compiler and never execute the produced binary
+    #[serde(default)]
+    pub compile_only: bool,
+    /// Every test in this category is run once per profile, with the profile's extra compiler
+    /// arguments
+    #[serde(default)]
+    pub profiles: Vec<CommandProfile>,
+    /// A directory on the server whose files are placed into the working directory of every test
+    /// in this category before it is compiled
+    pub fileset: Option<PathBuf>,
+    /// A command the compiled binaries of this category are run with, e.g. an emulator like
+    /// `qemu-riscv64`. The image must contain it.
+    #[serde(default)]
+    pub runner_prefix: Vec<String>,
+    /// The user to run the build and tests of this category as instead of root. All categories
+    /// setting a user must agree, as their tests share a build.
+    pub container_user: Option<ContainerUser>,
+    /// Absolute paths in the container that get an empty directory writable by every user
+    #[serde(default)]
+    pub writable_paths: Vec<String>,
+    /// Runner directories mounted into the containers. Runners refuse to mount anything outside
+    /// the directories their operator allowed.
+    #[serde(default)]
+    pub bind_mounts: Vec<BindMount>,
+    /// Run the compiled binaries with a different locale, time zone or umask than the compiler,
+    /// and optionally without address space layout randomization
+    pub binary_environment: Option<BinaryEnvironmentConfig>,
 }
 
 impl TestCategory {
+    pub fn permissions(&self) -> ContainerPermissions {
+        ContainerPermissions {
+            user: self.container_user,
+            writable_paths: self.writable_paths.clone(),
+            bind_mounts: self.bind_mounts.clone(),
+        }
+    }
+
     pub fn is_after_test_deadline(&self) -> bool {
         self.tests_end_at.timestamp() < Timestamp::now()
     }
@@ -150,6 +445,36 @@ impl TestCategory {
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct BinaryEnvironmentConfig {
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub umask: Option<u32>,
+    #[serde(default)]
+    pub disable_aslr: bool,
+}
+
+impl BinaryEnvironmentConfig {
+    /// Fills in the missing settings from the environment every command is pinned to
+    pub fn to_binary_environment(&self) -> BinaryEnvironment {
+        let pinned = (self.locale.is_some() || self.timezone.is_some() || self.umask.is_some())
+            .then(|| {
+                let default = PinnedEnvironment::default();
+                PinnedEnvironment {
+                    locale: self.locale.clone().unwrap_or(default.locale),
+                    timezone: self.timezone.clone().unwrap_or(default.timezone),
+                    umask: self.umask.unwrap_or(default.umask),
+                    source_date_epoch: default.source_date_epoch,
+                }
+            });
+
+        BinaryEnvironment {
+            pinned,
+            disable_aslr: self.disable_aslr,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct OidcConfig {
     pub client_id: String,
@@ -159,9 +484,117 @@ pub struct OidcConfig {
     pub scopes: Vec<String>,
 }
 
+/// How users prove who they are. Every provider hands out the same login tokens.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum AuthConfig {
+    /// Redirects users to the provider configured in `[oidc]`
+    #[default]
+    Oidc,
+    /// Checks usernames and passwords against the users listed in the config
+    Static { users: Vec<StaticUserConfig> },
+    /// Checks usernames and passwords by binding to an LDAP server as the user
+    Ldap(LdapConfig),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StaticUserConfig {
+    /// The name the user logs in with, which doubles as their user id
+    pub username: String,
+    /// Defaults to the username
+    pub display_name: Option<String>,
+    /// Printed by `backend --hash-password`
+    pub password_hash: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LdapConfig {
+    /// The server to bind to, e.g. `ldaps://ldap.example.com`. `ldap://` sends passwords in plain
+    /// text.
+    pub url: String,
+    /// The DN to bind as, `{username}` is replaced by the escaped username. The username doubles as
+    /// the user id.
+    pub bind_dn: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct SshConfig {
+    #[serde(default)]
     pub team_to_key: HashMap<TeamId, String>,
+    /// Where keys generated by the backend are stored. They take precedence over `team_to_key`.
+    pub key_dir: Option<PathBuf>,
+}
+
+/// A git repository the public test suite is pushed to whenever tests change
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestMirrorConfig {
+    /// The remote to push to, e.g. `git@github.com:course/tests.git`
+    pub remote: String,
+    #[serde(default = "default_mirror_branch")]
+    pub branch: String,
+    /// A private SSH key with write access to the remote
+    pub deploy_key: Option<String>,
+    /// Where the working copy of the mirror is kept
+    pub local_path: PathBuf,
+}
+
+fn default_mirror_branch() -> String {
+    "main".to_string()
+}
+
+/// Deletes old finished tasks to keep the database small. Finalized and manually submitted tasks,
+/// the tasks currently selected for grading, the best task and the newest task of every team are
+/// always kept.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetentionConfig {
+    /// Tasks that finished longer ago are pruned
+    #[serde(default, deserialize_with = "parse_optional_duration")]
+    pub max_age: Option<Duration>,
+    /// Older tasks of teams with more tasks than this are pruned
+    pub max_tasks_per_team: Option<u32>,
+    /// How often tasks are pruned
+    #[serde(
+        deserialize_with = "parse_duration",
+        default = "default_retention_interval"
+    )]
+    pub interval: Duration,
+}
+
+fn default_retention_interval() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+/// Notifications about finished tasks, sent to users that asked for them
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaskNotificationConfig {
+    /// Used to link to the task. Tasks are not linked if it is missing.
+    pub frontend_url: Option<String>,
+    /// E-mail notifications are only available if this is set
+    pub smtp: Option<SmtpConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// Defaults to the standard port of the chosen security
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// The sender of the mails, e.g. `crow <crow@example.com>`
+    pub from: String,
+}
+
+#[derive(Debug, Copy, Clone, Default, Deserialize)]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection using STARTTLS
+    #[default]
+    StartTls,
+    /// Connect using TLS directly
+    Tls,
+    /// No encryption at all, only sensible for a relay on the same machine
+    None,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -169,6 +602,35 @@ pub struct GradingConfig {
     pub snapshot_path: PathBuf,
 }
 
+/// Reads all files below the directory, with paths relative to it. Files must be valid UTF-8.
+pub fn read_fileset(dir: &Path) -> std::io::Result<Vec<FilesetFile>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let relative = path
+                .strip_prefix(dir)
+                .expect("path is below the fileset directory")
+                .components()
+                .map(|it| it.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push(FilesetFile {
+                path: relative,
+                contents: std::fs::read_to_string(&path)?,
+            });
+        }
+    }
+
+    Ok(files)
+}
+
 fn parse_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
 where
     D: Deserializer<'de>,
@@ -176,3 +638,20 @@ where
     let s: String = Deserialize::deserialize(deserializer)?;
     parse_duration::parse(&s).map_err(serde::de::Error::custom)
 }
+
+fn parse_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = Deserialize::deserialize(deserializer)?;
+    Regex::new(&s).map_err(serde::de::Error::custom)
+}
+
+fn parse_optional_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+    s.map(|it| parse_duration::parse(&it).map_err(serde::de::Error::custom))
+        .transpose()
+}
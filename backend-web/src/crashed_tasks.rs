@@ -0,0 +1,87 @@
+use crate::types::{AppState, MAX_LEASE_RECLAIMS, TaskId, WorkItem};
+use shared::{ExecutionOutput, FinishedCompilerTask, FinishedTaskInfo, InternalError};
+use snafu::Report;
+use std::time::{Duration, SystemTime};
+use tracing::{info, instrument, warn};
+
+/// How often we look for tasks whose runner vanished
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Finalizes tasks whose runner vanished after reporting some test results, using whatever was
+/// recorded until then. Tasks nothing usable was recorded for are handed out again, unless they
+/// lost their runner too often and are recorded as failed.
+#[instrument(skip_all)]
+pub async fn run_crashed_task_finalizer(state: AppState) {
+    loop {
+        let crashed = state.executor.lock().unwrap().crashed_tasks();
+        for task_id in crashed {
+            if let Err(e) = finalize_crashed_task(&state, &task_id).await {
+                warn!(
+                    task = %task_id,
+                    error = %Report::from_error(e),
+                    "Failed to finalize crashed task, running it again"
+                );
+            }
+            state.executor.lock().unwrap().crash_handled(&task_id);
+        }
+
+        let given_up = state.executor.lock().unwrap().given_up_tasks();
+        for task in given_up {
+            if let Err(e) = finalize_given_up_task(&state, &task).await {
+                warn!(
+                    task = %task.id,
+                    error = %Report::from_error(e),
+                    "Failed to finalize given up task, running it again"
+                );
+            }
+            state.executor.lock().unwrap().crash_handled(&task.id);
+        }
+
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+async fn finalize_crashed_task(state: &AppState, task_id: &TaskId) -> crate::error::Result<()> {
+    let Some(task) = state.db.fetch_partial_task(task_id).await? else {
+        info!(task = %task_id, "No partial results for crashed task, running it again");
+        return Ok(());
+    };
+    info!(task = %task_id, "Finalizing crashed task from partial results");
+
+    state.db.add_finished_task(&task).await
+}
+
+/// Records the task with the results reported so far, or as a failed build if there are none
+async fn finalize_given_up_task(state: &AppState, task: &WorkItem) -> crate::error::Result<()> {
+    if let Some(partial) = state.db.fetch_partial_task(&task.id).await? {
+        info!(task = %task.id, "Finalizing given up task from partial results");
+        return state.db.add_finished_task(&partial).await;
+    }
+    info!(task = %task.id, "Finalizing given up task as failed");
+
+    let now = SystemTime::now();
+    let message = format!(
+        "The runner executing this task vanished {} times, so it was given up. \
+         Please submit it again.",
+        MAX_LEASE_RECLAIMS + 1
+    );
+    let finished = FinishedCompilerTask::BuildFailed {
+        info: FinishedTaskInfo {
+            task_id: task.id.to_string(),
+            start: now,
+            end: now,
+            team_id: task.team.to_string(),
+            revision_id: task.revision.clone(),
+            commit_message: task.commit_message.clone(),
+            environment: None,
+            rerun_of: task.rerun_of.as_ref().map(ToString::to_string),
+            commit: task.commit.clone(),
+        },
+        build_output: ExecutionOutput::Error(InternalError {
+            message,
+            runtime: Duration::ZERO,
+        }),
+    };
+
+    state.db.add_finished_task(&finished).await
+}
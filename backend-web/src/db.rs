@@ -1,40 +1,84 @@
+mod api_token;
+mod bisect;
+mod consistency;
+mod course;
 mod external;
+mod fileset;
+mod milestone;
+mod notification;
+mod partial_task;
 mod queue;
 mod repo;
+mod retention;
+mod runner;
 mod task;
+mod task_notification;
 mod team;
 mod test;
 mod user;
 
 pub use self::user::UserForAuth;
-use crate::auth::oidc::OidcUser;
-use crate::config::{TeamEntry, TestCategory};
+use crate::auth::provider::LoginUser;
+use crate::config::{TeamEntry, TeamSync, TestCategory};
 use crate::error::{Result, SqlxSnafu, WebError};
+use crate::output_storage::{FullOutput, OutputStorage};
 use crate::types::{
+    ApiToken, ApiTokenId, Bisect, BisectId, ConsistencyReport, Course, CourseId, CourseMilestone,
     CreatedExternalRun, ExternalRunId, ExternalRunStatus, FinalSubmittedTask,
-    FinishedCompilerTaskSummary, FinishedTestSummary, FullUserForAdmin, OwnUser, Repo, TaskId,
-    Team, TeamId, TeamIntegrationToken, Test, TestId, TestSummary, TestWithTasteTesting, User,
+    FinishedCompilerTaskSummary, FinishedTestSummary, FullUserForAdmin, ManagedTeam, Notification,
+    NotificationId, OutputStream, OwnUser, Page, PrunableTask, QueueStatisticsDay, ReferenceOutput,
+    Repo, TaskComparison, TaskExportRow, TaskId, TaskNotificationDelivery,
+    TaskNotificationDeliveryId, TaskNotificationSettings, TaskPerformance, Team, TeamActivityDay,
+    TeamId, TeamIntegrationToken, TeamInvite, TeamMilestones, Test, TestAcceptance, TestId,
+    TestMetrics, TestSearch, TestStats, TestSuiteSnapshot, TestSummary, TestWithTasteTesting, User,
     UserId, UserRole, WorkItem,
 };
 use jiff::Timestamp;
-use shared::{FinishedCompilerTask, TestExecutionOutput, indent};
+use shared::{
+    FinishedCompilerTask, FinishedTaskInfo, RunnerId, RunnerUpdate, TestExecutionOutput, indent,
+};
 use snafu::{Report, ResultExt, location};
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
 use sqlx::{Pool, Sqlite, SqlitePool, query};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
-use tracing::{Instrument, info_span, instrument};
+use tracing::{Instrument, info_span, instrument, warn};
 
 #[derive(Clone)]
 pub struct Database {
     lock: Arc<RwLock<Pool<Sqlite>>>,
     db_path: PathBuf,
+    output_storage: Option<OutputStorage>,
 }
 
 impl Database {
-    pub async fn new(db_path: &Path) -> std::result::Result<Self, sqlx::Error> {
+    pub async fn new(
+        db_path: &Path,
+        output_storage: Option<OutputStorage>,
+    ) -> std::result::Result<Self, sqlx::Error> {
+        let pool = Self::open_pool(db_path).await?;
+
+        // This might duplicate the database according to the docs:
+        // https://sqlite.org/lang_vacuum.html#how_vacuum_works
+        query!("VACUUM")
+            .execute(&mut *pool.acquire().await?)
+            .await?;
+        // Therefore, we now also checkpoint it
+        query("PRAGMA WAL_CHECKPOINT(TRUNCATE)")
+            .execute(&mut *pool.acquire().await?)
+            .await?;
+
+        Ok(Self {
+            lock: Arc::new(RwLock::new(pool)),
+            db_path: db_path.to_path_buf(),
+            output_storage,
+        })
+    }
+
+    async fn open_pool(db_path: &Path) -> std::result::Result<Pool<Sqlite>, sqlx::Error> {
         let pool = SqlitePool::connect_with(
             SqliteConnectOptions::default()
                 .foreign_keys(true)
@@ -51,20 +95,7 @@ impl Database {
 
         sqlx::migrate!().run(&pool).await?;
 
-        // This might duplicate the database according to the docs:
-        // https://sqlite.org/lang_vacuum.html#how_vacuum_works
-        query!("VACUUM")
-            .execute(&mut *pool.acquire().await?)
-            .await?;
-        // Therefore, we now also checkpoint it
-        query("PRAGMA WAL_CHECKPOINT(TRUNCATE)")
-            .execute(&mut *pool.acquire().await?)
-            .await?;
-
-        Ok(Self {
-            lock: Arc::new(RwLock::new(pool)),
-            db_path: db_path.to_path_buf(),
-        })
+        Ok(pool)
     }
 
     async fn write_lock(&self) -> RwLockWriteGuard<'_, Pool<Sqlite>> {
@@ -91,14 +122,14 @@ impl Database {
         user::fetch_users(&mut *pool.acquire().await.context(SqlxSnafu)?).await
     }
 
-    pub async fn synchronize_oidc_user(
+    pub async fn synchronize_login_user(
         &self,
-        user: OidcUser,
+        user: LoginUser,
         team: Option<TeamId>,
         role: Option<UserRole>,
     ) -> Result<OwnUser> {
         let pool = self.write_lock().await;
-        user::synchronize_oidc_user(
+        user::synchronize_login_user(
             &mut *pool.acquire().await.context(SqlxSnafu)?,
             user,
             team,
@@ -160,9 +191,108 @@ impl Database {
             .await
     }
 
-    pub async fn sync_teams(&self, teams: &[TeamEntry]) -> Result<()> {
+    pub async fn sync_teams(&self, teams: &[TeamEntry], strategy: TeamSync) -> Result<()> {
+        let pool = self.write_lock().await;
+        team::sync_teams(&*pool, teams, strategy).await
+    }
+
+    pub async fn get_team_membership(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<(TeamId, UserRole)>> {
+        let pool = self.read_lock().await;
+        team::get_team_membership(&mut *pool.acquire().await.context(SqlxSnafu)?, user_id).await
+    }
+
+    pub async fn get_managed_teams(&self) -> Result<Vec<ManagedTeam>> {
+        let pool = self.read_lock().await;
+        team::get_managed_teams(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+    }
+
+    pub async fn create_team(&self, team: &ManagedTeam) -> Result<ManagedTeam> {
+        let pool = self.write_lock().await;
+        team::create_team(&*pool, team).await
+    }
+
+    pub async fn patch_team(
+        &self,
+        team_id: &TeamId,
+        display_name: Option<&str>,
+        is_admin: Option<bool>,
+        course: Option<&CourseId>,
+    ) -> Result<ManagedTeam> {
+        let pool = self.write_lock().await;
+        team::patch_team(&*pool, team_id, display_name, is_admin, course).await
+    }
+
+    pub async fn add_team_member(&self, team_id: &TeamId, user_id: &UserId) -> Result<ManagedTeam> {
+        let pool = self.write_lock().await;
+        team::add_team_member(&*pool, team_id, user_id).await
+    }
+
+    pub async fn remove_team_member(
+        &self,
+        team_id: &TeamId,
+        user_id: &UserId,
+    ) -> Result<ManagedTeam> {
+        let pool = self.write_lock().await;
+        team::remove_team_member(&*pool, team_id, user_id).await
+    }
+
+    pub async fn get_team_invites(&self) -> Result<Vec<TeamInvite>> {
+        let pool = self.read_lock().await;
+        team::get_team_invites(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+    }
+
+    pub async fn create_team_invite(&self, invite: &TeamInvite) -> Result<()> {
         let pool = self.write_lock().await;
-        team::sync_teams(&*pool, teams).await
+        team::create_team_invite(&*pool, invite).await
+    }
+
+    pub async fn delete_team_invite(&self, code: &str) -> Result<()> {
+        let pool = self.write_lock().await;
+        team::delete_team_invite(&mut *pool.acquire().await.context(SqlxSnafu)?, code).await
+    }
+
+    pub async fn redeem_team_invite(&self, user_id: &UserId, code: &str) -> Result<Team> {
+        let pool = self.write_lock().await;
+        team::redeem_team_invite(&*pool, user_id, code).await
+    }
+
+    pub async fn get_courses(&self) -> Result<Vec<Course>> {
+        let pool = self.read_lock().await;
+        course::get_courses(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+    }
+
+    pub async fn fetch_course(&self, course_id: &CourseId) -> Result<Option<Course>> {
+        let pool = self.read_lock().await;
+        course::fetch_course(&mut *pool.acquire().await.context(SqlxSnafu)?, course_id).await
+    }
+
+    pub async fn create_course(&self, course: &Course) -> Result<Course> {
+        let pool = self.write_lock().await;
+        course::create_course(&*pool, course).await
+    }
+
+    pub async fn patch_course(
+        &self,
+        course_id: &CourseId,
+        display_name: Option<&str>,
+        archived: Option<bool>,
+    ) -> Result<Course> {
+        let pool = self.write_lock().await;
+        course::patch_course(&*pool, course_id, display_name, archived).await
+    }
+
+    /// Creates the course if it does not exist yet, see [course::ensure_course]
+    pub async fn ensure_course(&self, course_id: &CourseId) -> Result<()> {
+        let pool = self.write_lock().await;
+        course::ensure_course(&mut *pool.acquire().await.context(SqlxSnafu)?, course_id).await
+    }
+
+    pub async fn get_team_courses(&self) -> Result<HashMap<TeamId, CourseId>> {
+        let pool = self.read_lock().await;
+        course::get_team_courses(&mut *pool.acquire().await.context(SqlxSnafu)?).await
     }
 
     pub async fn queue_task(&self, task: WorkItem) -> Result<()> {
@@ -180,27 +310,209 @@ impl Database {
         queue::fetch_queued_task(&mut *pool.acquire().await.context(SqlxSnafu)?, task_id).await
     }
 
+    /// Returns a queued or running task for the given revision, if any. Running tasks stay in
+    /// the queue until they are finished.
+    pub async fn fetch_queued_task_for_revision(
+        &self,
+        team_id: &TeamId,
+        revision: &str,
+    ) -> Result<Option<WorkItem>> {
+        let pool = self.read_lock().await;
+        queue::fetch_queued_task_for_revision(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            team_id,
+            revision,
+        )
+        .await
+    }
+
+    /// Returns the average duration of the `sample_size` most recently finished tasks
+    pub async fn get_average_task_duration(&self, sample_size: i64) -> Result<Option<Duration>> {
+        let pool = self.read_lock().await;
+        queue::get_average_task_duration(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            sample_size,
+        )
+        .await
+    }
+
+    pub async fn get_queue_statistics(&self, since: Timestamp) -> Result<Vec<QueueStatisticsDay>> {
+        let pool = self.read_lock().await;
+        queue::get_queue_statistics(&mut *pool.acquire().await.context(SqlxSnafu)?, since).await
+    }
+
+    pub async fn get_runner_role(&self, runner_id: &RunnerId) -> Result<Option<bool>> {
+        let pool = self.read_lock().await;
+        runner::get_runner_role(&mut *pool.acquire().await.context(SqlxSnafu)?, runner_id).await
+    }
+
+    pub async fn set_runner_role(
+        &self,
+        runner_id: &RunnerId,
+        test_taster: Option<bool>,
+    ) -> Result<()> {
+        let pool = self.write_lock().await;
+        runner::set_runner_role(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            runner_id,
+            test_taster,
+        )
+        .await
+    }
+
     pub async fn add_finished_task(&self, result: &FinishedCompilerTask) -> Result<()> {
         let pool = self.write_lock().await;
         let mut con = pool.begin().await.context(SqlxSnafu)?;
 
-        let queue_time =
-            queue::remove_queued_task(&mut con, &(result.info().task_id.clone().into())).await?;
+        let task_id: TaskId = result.info().task_id.clone().into();
+        let queued = queue::fetch_queued_task(&mut con, &task_id).await?;
+        let (tree_hash, rerun_of, commit) = queued
+            .map(|it| (it.tree_hash, it.rerun_of, it.commit))
+            .unwrap_or_default();
+        let queue_time = queue::remove_queued_task(&mut con, &task_id).await?;
         let queue_time =
             queue_time.unwrap_or(Timestamp::try_from(result.info().start).expect("valid time"));
-        task::add_finished_task(&mut con, result, queue_time).await?;
+        task::add_finished_task(&mut con, self.output_storage.as_ref(), result, queue_time).await?;
+        milestone::record_milestones(&mut con, result, queue_time).await?;
+        if let Some(tree_hash) = tree_hash {
+            task::set_task_tree_hash(&mut con, &task_id, &tree_hash).await?;
+        }
+        if let Some(rerun_of) = rerun_of {
+            task::set_task_rerun_of(&mut con, &task_id, &rerun_of).await?;
+        }
+        if let Some(commit) = commit {
+            task::set_task_commit_metadata(&mut con, &task_id, &commit).await?;
+        }
 
         con.commit().await.context(SqlxSnafu)?;
 
         Ok(())
     }
 
+    /// Records the results a runner reported for a running task, so the task can still be
+    /// finalized if the runner vanishes before it is done
+    pub async fn record_partial_update(
+        &self,
+        task_id: &TaskId,
+        update: &RunnerUpdate,
+    ) -> Result<()> {
+        let pool = self.write_lock().await;
+        let con = &mut *pool.acquire().await.context(SqlxSnafu)?;
+
+        match update {
+            // The task is started from scratch, e.g. after its previous runner lost the lease
+            RunnerUpdate::StartedBuild => partial_task::clear_partial_task(con, task_id).await,
+            RunnerUpdate::FinishedBuild { result } => {
+                partial_task::record_partial_build(con, task_id, result).await
+            }
+            RunnerUpdate::FinishedTest { result } => {
+                partial_task::record_partial_test(con, task_id, result).await
+            }
+            RunnerUpdate::StartedTest { .. } | RunnerUpdate::Done => Ok(()),
+        }
+    }
+
+    /// Assembles a finished task from the results recorded for a queued task. Tests that were not
+    /// reported are missing from it.
+    pub async fn fetch_partial_task(
+        &self,
+        task_id: &TaskId,
+    ) -> Result<Option<FinishedCompilerTask>> {
+        let pool = self.read_lock().await;
+        let con = &mut *pool.acquire().await.context(SqlxSnafu)?;
+
+        let Some(queued) = queue::fetch_queued_task(con, task_id).await? else {
+            return Ok(None);
+        };
+        let Some(partial) = partial_task::fetch_partial_task(con, task_id).await? else {
+            return Ok(None);
+        };
+        if partial.tests.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(FinishedCompilerTask::RanTests {
+            info: FinishedTaskInfo {
+                task_id: task_id.to_string(),
+                start: partial.start,
+                end: SystemTime::now(),
+                team_id: queued.team.to_string(),
+                revision_id: queued.revision,
+                commit_message: queued.commit_message,
+                environment: None,
+                rerun_of: queued.rerun_of.map(|it| it.to_string()),
+                commit: queued.commit,
+            },
+            build_output: partial.build_output,
+            tests: partial.tests,
+        }))
+    }
+
+    /// Returns the most recent finished task of the team built from the given source tree
+    pub async fn fetch_task_with_tree_hash(
+        &self,
+        team_id: &TeamId,
+        tree_hash: &str,
+    ) -> Result<Option<TaskId>> {
+        let pool = self.read_lock().await;
+        task::fetch_task_with_tree_hash(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            team_id,
+            tree_hash,
+        )
+        .await
+    }
+
+    pub async fn fetch_task_end_time(&self, task_id: &TaskId) -> Result<Option<Timestamp>> {
+        let pool = self.read_lock().await;
+        task::fetch_task_end_time(&mut *pool.acquire().await.context(SqlxSnafu)?, task_id).await
+    }
+
     /// Returns the task as well as any outdated tests in it.
     pub async fn get_task(&self, task_id: &TaskId) -> Result<(FinishedCompilerTask, Vec<TestId>)> {
         let pool = self.read_lock().await;
         task::get_task(&*pool, task_id).await
     }
 
+    pub async fn get_full_output(
+        &self,
+        task_id: &TaskId,
+        execution_id: &str,
+        stream: OutputStream,
+    ) -> Result<Option<(Option<TestId>, FullOutput)>> {
+        let pool = self.read_lock().await;
+        task::get_full_output(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            task_id,
+            execution_id,
+            stream,
+        )
+        .await
+    }
+
+    pub async fn get_build_log_chunk(
+        &self,
+        task_id: &TaskId,
+        stream: OutputStream,
+        offset: u64,
+        limit: u64,
+    ) -> Result<(Vec<u8>, u64)> {
+        let pool = self.read_lock().await;
+        task::get_build_log_chunk(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            task_id,
+            stream,
+            offset,
+            limit,
+        )
+        .await
+    }
+
+    pub async fn compare_tasks(&self, base: &TaskId, head: &TaskId) -> Result<TaskComparison> {
+        let pool = self.read_lock().await;
+        task::compare_tasks(&*pool, base, head).await
+    }
+
     pub async fn get_finished_test_summaries(
         &self,
         task_id: &TaskId,
@@ -219,6 +531,62 @@ impl Database {
         task::get_recent_tasks(&*pool, team_id, count as i64).await
     }
 
+    pub async fn search_tasks(
+        &self,
+        team_id: &TeamId,
+        query: Option<&str>,
+        page: Page,
+    ) -> Result<(Vec<FinishedCompilerTaskSummary>, i64)> {
+        let pool = self.read_lock().await;
+        task::search_tasks(&*pool, team_id, query, page).await
+    }
+
+    pub async fn get_team_milestones(&self, team_id: &TeamId) -> Result<TeamMilestones> {
+        let pool = self.read_lock().await;
+        milestone::get_team_milestones(&mut *pool.acquire().await.context(SqlxSnafu)?, team_id)
+            .await
+    }
+
+    pub async fn get_course_milestones(&self) -> Result<Vec<CourseMilestone>> {
+        let pool = self.read_lock().await;
+        milestone::get_course_milestones(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+    }
+
+    pub async fn get_team_activity(
+        &self,
+        team_id: &TeamId,
+        since: Timestamp,
+    ) -> Result<Vec<TeamActivityDay>> {
+        let pool = self.read_lock().await;
+        task::get_team_activity(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            team_id,
+            since,
+        )
+        .await
+    }
+
+    pub async fn get_team_performance(&self, team_id: &TeamId) -> Result<Vec<TaskPerformance>> {
+        let pool = self.read_lock().await;
+        task::get_team_performance(&mut *pool.acquire().await.context(SqlxSnafu)?, team_id).await
+    }
+
+    pub async fn get_task_export_page(
+        &self,
+        after: (Timestamp, &TaskId),
+        to: Timestamp,
+        limit: u32,
+    ) -> Result<Vec<TaskExportRow>> {
+        let pool = self.read_lock().await;
+        task::get_task_export_page(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            after,
+            to,
+            limit,
+        )
+        .await
+    }
+
     pub async fn get_top_task_per_team(
         &self,
     ) -> Result<HashMap<TeamId, FinishedCompilerTaskSummary>> {
@@ -229,17 +597,20 @@ impl Database {
     pub async fn get_final_submitted_task_for_team_and_category(
         &self,
         team_id: &TeamId,
+        course: &CourseId,
         category: &str,
         meta: &TestCategory,
         respect_finalized: bool,
     ) -> Result<Option<FinalSubmittedTask>> {
         let pool = self.read_lock().await;
-        task::get_final_submitted_task(&*pool, team_id, category, meta, respect_finalized).await
+        task::get_final_submitted_task(&*pool, team_id, course, category, meta, respect_finalized)
+            .await
     }
 
     pub async fn set_final_submitted_task(
         &self,
         team_id: &TeamId,
+        course: &CourseId,
         user_id: &UserId,
         task_id: &TaskId,
         categories: impl Iterator<Item = &str>,
@@ -248,6 +619,7 @@ impl Database {
         task::set_final_submitted_task(
             &mut *pool.acquire().await.context(SqlxSnafu)?,
             team_id,
+            course,
             user_id,
             task_id,
             categories,
@@ -258,6 +630,7 @@ impl Database {
     pub async fn finalize_submission(
         &self,
         team_id: &TeamId,
+        course: &CourseId,
         task_id: &TaskId,
         category: &str,
     ) -> Result<()> {
@@ -265,6 +638,7 @@ impl Database {
         task::finalize_submission(
             &mut *pool.acquire().await.context(SqlxSnafu)?,
             team_id,
+            course,
             task_id,
             category,
         )
@@ -274,12 +648,14 @@ impl Database {
     pub async fn fetch_finalized_task_id(
         &self,
         team_id: &TeamId,
+        course: &CourseId,
         category: &str,
     ) -> Result<Option<TaskId>> {
         let pool = self.read_lock().await;
         task::fetch_finalized_task_id(
             &mut *pool.acquire().await.context(SqlxSnafu)?,
             team_id,
+            course,
             category,
         )
         .await
@@ -288,19 +664,48 @@ impl Database {
         &self,
         test: Test,
         test_tasting: Option<TestExecutionOutput>,
+        reference_output: Option<&ReferenceOutput>,
     ) -> Result<Test> {
         let pool = self.write_lock().await;
         test::add_test(
             &mut *pool.acquire().await.context(SqlxSnafu)?,
             test,
             test_tasting,
+            reference_output,
         )
         .await
     }
 
-    pub async fn get_test_summaries(&self) -> Result<Vec<TestSummary>> {
+    pub async fn fetch_reference_output(
+        &self,
+        test_id: &TestId,
+    ) -> Result<Option<ReferenceOutput>> {
+        let pool = self.read_lock().await;
+        test::fetch_reference_output(&mut *pool.acquire().await.context(SqlxSnafu)?, test_id).await
+    }
+
+    pub async fn get_test_summaries(
+        &self,
+        search: &TestSearch,
+        page: Page,
+    ) -> Result<(Vec<TestSummary>, i64)> {
+        let pool = self.read_lock().await;
+        test::get_tests_summaries(&*pool, search, page).await
+    }
+
+    pub async fn get_test_metrics(&self) -> Result<Vec<TestMetrics>> {
         let pool = self.read_lock().await;
-        test::get_tests_summaries(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+        test::get_test_metrics(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+    }
+
+    pub async fn get_test_stats(&self, test_id: &TestId) -> Result<TestStats> {
+        let pool = self.read_lock().await;
+        test::get_test_stats(&mut *pool.acquire().await.context(SqlxSnafu)?, test_id).await
+    }
+
+    pub async fn import_tests(&self, tests: &[Test]) -> Result<()> {
+        let pool = self.write_lock().await;
+        test::import_tests(&*pool, tests).await
     }
 
     pub async fn get_tests(&self) -> Result<Vec<Test>> {
@@ -308,11 +713,27 @@ impl Database {
         test::get_tests(&mut *pool.acquire().await.context(SqlxSnafu)?).await
     }
 
+    /// Returns the tests that count, see [TestAcceptance]
+    pub async fn get_accepted_tests(&self) -> Result<Vec<Test>> {
+        let pool = self.read_lock().await;
+        test::get_accepted_tests(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+    }
+
     pub async fn fetch_test(&self, test_id: &TestId) -> Result<Option<Test>> {
         let pool = self.read_lock().await;
         test::fetch_test(&mut *pool.acquire().await.context(SqlxSnafu)?, test_id).await
     }
 
+    pub async fn fetch_tests(&self, test_ids: &[TestId]) -> Result<Vec<Test>> {
+        let pool = self.read_lock().await;
+        test::fetch_tests(&mut *pool.acquire().await.context(SqlxSnafu)?, test_ids).await
+    }
+
+    pub async fn get_hidden_test_ids(&self, team: &TeamId) -> Result<HashSet<TestId>> {
+        let pool = self.read_lock().await;
+        test::get_hidden_test_ids(&mut *pool.acquire().await.context(SqlxSnafu)?, team).await
+    }
+
     pub async fn fetch_test_with_tasting(
         &self,
         test_id: &TestId,
@@ -326,11 +747,358 @@ impl Database {
         test::delete_test(&mut *pool.acquire().await.context(SqlxSnafu)?, test_id).await
     }
 
+    pub async fn get_provisional_tests(&self) -> Result<Vec<TestWithTasteTesting>> {
+        let pool = self.read_lock().await;
+        test::get_provisional_tests(&*pool).await
+    }
+
+    pub async fn promote_test(&self, test_id: &TestId) -> Result<Test> {
+        let pool = self.write_lock().await;
+        test::promote_test(&*pool, test_id).await
+    }
+
+    /// Deletes a test and tells its owner why
+    pub async fn reject_test(&self, test_id: &TestId, notification: &Notification) -> Result<()> {
+        let pool = self.write_lock().await;
+        let mut con = pool.begin().await.context(SqlxSnafu)?;
+
+        test::delete_test(&mut con, test_id).await?;
+        notification::add_notification(&mut con, notification).await?;
+
+        con.commit().await.context(SqlxSnafu)?;
+
+        Ok(())
+    }
+
+    /// Accepts or rejects a test and tells its owner, if there is a notification
+    pub async fn set_test_acceptance(
+        &self,
+        test_id: &TestId,
+        acceptance: &TestAcceptance,
+        notification: Option<&Notification>,
+    ) -> Result<()> {
+        let pool = self.write_lock().await;
+        let mut con = pool.begin().await.context(SqlxSnafu)?;
+
+        test::set_test_acceptance(&mut con, test_id, acceptance).await?;
+        if let Some(notification) = notification {
+            notification::add_notification(&mut con, notification).await?;
+        }
+
+        con.commit().await.context(SqlxSnafu)?;
+
+        Ok(())
+    }
+
     pub async fn rehash_tests(&self) -> Result<()> {
         let pool = self.write_lock().await;
         test::rehash(&*pool).await
     }
 
+    /// Records the hash of a category fileset and returns its version, see
+    /// [fileset::sync_category_fileset]
+    pub async fn sync_category_fileset(
+        &self,
+        course: &CourseId,
+        category: &str,
+        hash: Option<&str>,
+    ) -> Result<i64> {
+        let pool = self.write_lock().await;
+        fileset::sync_category_fileset(&*pool, course, category, hash).await
+    }
+
+    pub async fn freeze_test_suite(
+        &self,
+        course: &CourseId,
+        category: &str,
+        frozen_by: Option<&UserId>,
+    ) -> Result<TestSuiteSnapshot> {
+        let pool = self.write_lock().await;
+        test::freeze_test_suite(&*pool, course, category, frozen_by).await
+    }
+
+    pub async fn get_test_suite_snapshots(&self) -> Result<Vec<TestSuiteSnapshot>> {
+        let pool = self.read_lock().await;
+        test::get_test_suite_snapshots(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+    }
+
+    pub async fn get_queued_frozen_reruns(&self) -> Result<HashMap<TaskId, Vec<String>>> {
+        let pool = self.read_lock().await;
+        queue::get_queued_frozen_reruns(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+    }
+
+    /// Adds the bisect together with the task of its first step, so the bisector never sees it
+    /// without one
+    pub async fn add_bisect(&self, bisect: &Bisect, first_step: Option<WorkItem>) -> Result<()> {
+        let pool = self.write_lock().await;
+        let mut con = pool.begin().await.context(SqlxSnafu)?;
+
+        bisect::add_bisect(&mut con, bisect).await?;
+        if let Some(task) = first_step {
+            bisect::add_bisect_step(&mut con, &bisect.id, &task.id, &task.revision).await?;
+            queue::queue_task(&mut con, task).await?;
+        }
+
+        con.commit().await.context(SqlxSnafu)?;
+
+        Ok(())
+    }
+
+    pub async fn get_bisect(&self, bisect_id: &BisectId) -> Result<Option<Bisect>> {
+        let pool = self.read_lock().await;
+        bisect::get_bisect(&*pool, bisect_id).await
+    }
+
+    pub async fn get_unfinished_bisects(&self) -> Result<Vec<Bisect>> {
+        let pool = self.read_lock().await;
+        let mut con = pool.acquire().await.context(SqlxSnafu)?;
+
+        let mut bisects = Vec::new();
+        for bisect_id in bisect::get_unfinished_bisect_ids(&mut con).await? {
+            bisects.extend(bisect::get_bisect(&mut *con, &bisect_id).await?);
+        }
+
+        Ok(bisects)
+    }
+
+    /// Queues the task of the next step of the bisect
+    pub async fn queue_bisect_step(&self, bisect_id: &BisectId, task: WorkItem) -> Result<()> {
+        let pool = self.write_lock().await;
+        let mut con = pool.begin().await.context(SqlxSnafu)?;
+
+        bisect::add_bisect_step(&mut con, bisect_id, &task.id, &task.revision).await?;
+        queue::queue_task(&mut con, task).await?;
+
+        con.commit().await.context(SqlxSnafu)?;
+
+        Ok(())
+    }
+
+    pub async fn finish_bisect_step(
+        &self,
+        bisect: &Bisect,
+        task_id: &TaskId,
+        passed: bool,
+    ) -> Result<()> {
+        let pool = self.write_lock().await;
+        bisect::finish_bisect_step(&*pool, bisect, task_id, passed).await
+    }
+
+    pub async fn remove_bisect_step(&self, task_id: &TaskId) -> Result<()> {
+        let pool = self.write_lock().await;
+        bisect::remove_bisect_step(&mut *pool.acquire().await.context(SqlxSnafu)?, task_id).await
+    }
+
+    pub async fn get_queued_bisect_tests(&self) -> Result<HashMap<TaskId, TestId>> {
+        let pool = self.read_lock().await;
+        bisect::get_queued_bisect_tests(&mut *pool.acquire().await.context(SqlxSnafu)?).await
+    }
+
+    pub async fn is_bisect_step(&self, task_id: &TaskId) -> Result<bool> {
+        let pool = self.read_lock().await;
+        bisect::is_bisect_step(&mut *pool.acquire().await.context(SqlxSnafu)?, task_id).await
+    }
+
+    pub async fn add_api_token(&self, token: &ApiToken, token_hash: &str) -> Result<()> {
+        let pool = self.write_lock().await;
+        api_token::add_api_token(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            token,
+            token_hash,
+        )
+        .await
+    }
+
+    pub async fn get_api_tokens(&self, team: &TeamId) -> Result<Vec<ApiToken>> {
+        let pool = self.read_lock().await;
+        api_token::get_api_tokens(&mut *pool.acquire().await.context(SqlxSnafu)?, team).await
+    }
+
+    pub async fn fetch_api_token_by_hash(&self, token_hash: &str) -> Result<Option<ApiToken>> {
+        let pool = self.read_lock().await;
+        api_token::fetch_api_token_by_hash(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            token_hash,
+        )
+        .await
+    }
+
+    pub async fn mark_api_token_used(&self, id: &ApiTokenId) -> Result<()> {
+        let pool = self.write_lock().await;
+        api_token::mark_api_token_used(&mut *pool.acquire().await.context(SqlxSnafu)?, id).await
+    }
+
+    pub async fn delete_api_token(&self, team: &TeamId, id: &ApiTokenId) -> Result<()> {
+        let pool = self.write_lock().await;
+        api_token::delete_api_token(&mut *pool.acquire().await.context(SqlxSnafu)?, team, id).await
+    }
+
+    pub async fn get_notifications(&self, team: &TeamId) -> Result<Vec<Notification>> {
+        let pool = self.read_lock().await;
+        notification::get_notifications(&mut *pool.acquire().await.context(SqlxSnafu)?, team).await
+    }
+
+    pub async fn mark_notification_read(&self, team: &TeamId, id: &NotificationId) -> Result<()> {
+        let pool = self.write_lock().await;
+        notification::mark_notification_read(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            team,
+            id,
+        )
+        .await
+    }
+
+    pub async fn get_task_notification_settings(
+        &self,
+        user: &UserId,
+    ) -> Result<Option<TaskNotificationSettings>> {
+        let pool = self.read_lock().await;
+        task_notification::get_task_notification_settings(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            user,
+        )
+        .await
+    }
+
+    pub async fn set_task_notification_settings(
+        &self,
+        user: &UserId,
+        settings: &TaskNotificationSettings,
+    ) -> Result<()> {
+        let pool = self.write_lock().await;
+        task_notification::set_task_notification_settings(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            user,
+            settings,
+        )
+        .await
+    }
+
+    pub async fn get_task_notification_settings_for_team(
+        &self,
+        team: &TeamId,
+    ) -> Result<Vec<(UserId, TaskNotificationSettings)>> {
+        let pool = self.read_lock().await;
+        task_notification::get_task_notification_settings_for_team(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            team,
+        )
+        .await
+    }
+
+    pub async fn add_task_notification_deliveries(
+        &self,
+        deliveries: &[TaskNotificationDelivery],
+    ) -> Result<()> {
+        let pool = self.write_lock().await;
+        let mut con = pool.begin().await.context(SqlxSnafu)?;
+
+        for delivery in deliveries {
+            task_notification::add_task_notification_delivery(&mut con, delivery).await?;
+        }
+
+        con.commit().await.context(SqlxSnafu)?;
+
+        Ok(())
+    }
+
+    pub async fn get_due_task_notification_deliveries(
+        &self,
+    ) -> Result<Vec<TaskNotificationDelivery>> {
+        let pool = self.read_lock().await;
+        task_notification::get_due_task_notification_deliveries(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            Timestamp::now(),
+        )
+        .await
+    }
+
+    pub async fn delete_task_notification_delivery(
+        &self,
+        id: &TaskNotificationDeliveryId,
+    ) -> Result<()> {
+        let pool = self.write_lock().await;
+        task_notification::delete_task_notification_delivery(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            id,
+        )
+        .await
+    }
+
+    pub async fn reschedule_task_notification_delivery(
+        &self,
+        id: &TaskNotificationDeliveryId,
+        next_attempt: Timestamp,
+        error: &str,
+    ) -> Result<()> {
+        let pool = self.write_lock().await;
+        task_notification::reschedule_task_notification_delivery(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            id,
+            next_attempt,
+            error,
+        )
+        .await
+    }
+
+    pub async fn get_prunable_tasks(
+        &self,
+        finished_before: Option<Timestamp>,
+        max_tasks_per_team: Option<u32>,
+    ) -> Result<Vec<PrunableTask>> {
+        let pool = self.read_lock().await;
+        retention::get_prunable_tasks(
+            &mut *pool.acquire().await.context(SqlxSnafu)?,
+            finished_before,
+            max_tasks_per_team,
+        )
+        .await
+    }
+
+    /// Returns how many build and test outputs belong to the tasks
+    pub async fn count_task_execution_results(&self, task_ids: &[TaskId]) -> Result<u64> {
+        let pool = self.read_lock().await;
+        let mut con = pool.acquire().await.context(SqlxSnafu)?;
+
+        let mut count = 0;
+        for task_id in task_ids {
+            count += retention::count_task_execution_results(&mut con, task_id).await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Deletes the tasks with all their results. Returns the number of deleted outputs.
+    pub async fn delete_tasks(&self, task_ids: &[TaskId]) -> Result<u64> {
+        let pool = self.write_lock().await;
+        let mut con = pool.begin().await.context(SqlxSnafu)?;
+
+        let mut deleted = 0;
+        let mut full_outputs = Vec::new();
+        for task_id in task_ids {
+            let (outputs, files) = retention::delete_task(&mut con, task_id).await?;
+            deleted += outputs;
+            full_outputs.extend(files);
+        }
+
+        con.commit().await.context(SqlxSnafu)?;
+        OutputStorage::remove(&full_outputs).await;
+
+        Ok(deleted)
+    }
+
+    /// Looks for rows violating the consistency rules of the database and, if `fix` is set,
+    /// deletes them
+    pub async fn check_consistency(&self, fix: bool) -> Result<ConsistencyReport> {
+        if fix {
+            let pool = self.write_lock().await;
+            consistency::check_consistency(&pool, true).await
+        } else {
+            let pool = self.read_lock().await;
+            consistency::check_consistency(&pool, false).await
+        }
+    }
+
     pub async fn add_external_run(&self, run: &CreatedExternalRun) -> Result<()> {
         let pool = self.write_lock().await;
         external::add_external_run(&mut *pool.acquire().await.context(SqlxSnafu)?, run).await
@@ -397,8 +1165,76 @@ impl Database {
 
     pub async fn snapshot_db(&self, path: &Path) -> Result<()> {
         // Guard against concurrent writes to the database
-        let _ = self.write_lock().await;
+        let _guard = self.write_lock().await;
+
+        self.backup_db(path).await
+    }
+
+    /// Replaces the database with the one at `snapshot_db`. The current database is backed up to
+    /// `backup_path` first and put back in place if the snapshot can not be opened.
+    #[instrument(skip_all)]
+    pub async fn restore_db(&self, snapshot_db: &Path, backup_path: &Path) -> Result<()> {
+        // Nobody may use the database while we swap it out
+        let mut pool = self.write_lock().await;
+
+        self.backup_db(backup_path).await?;
+
+        // Copy it next to the database first, so the swap itself is an atomic rename
+        let staged = self.db_path.with_extension("restore");
+        tokio::fs::copy(snapshot_db, &staged).await.map_err(|e| {
+            WebError::internal_error(Report::from_error(&e).to_string(), location!())
+        })?;
+
+        pool.close().await;
+        self.replace_db_file(&staged).await?;
+
+        match Self::open_pool(&self.db_path).await {
+            Ok(new_pool) => {
+                *pool = new_pool;
+                Ok(())
+            }
+            Err(e) => {
+                warn!(
+                    error = %Report::from_error(&e),
+                    "Failed to open restored database, putting the backup back"
+                );
+                tokio::fs::copy(backup_path, &staged).await.map_err(|e| {
+                    WebError::internal_error(Report::from_error(&e).to_string(), location!())
+                })?;
+                self.replace_db_file(&staged).await?;
+                *pool = Self::open_pool(&self.db_path).await.context(SqlxSnafu)?;
+
+                Err(e).context(SqlxSnafu)
+            }
+        }
+    }
+
+    async fn replace_db_file(&self, new_db: &Path) -> Result<()> {
+        tokio::fs::rename(new_db, &self.db_path)
+            .await
+            .map_err(|e| {
+                WebError::internal_error(Report::from_error(&e).to_string(), location!())
+            })?;
+
+        // Stale journal files belong to the old database and must not be applied to the new one
+        for suffix in ["-wal", "-shm"] {
+            let mut journal = self.db_path.clone().into_os_string();
+            journal.push(suffix);
+            match tokio::fs::remove_file(&journal).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(WebError::internal_error(
+                        Report::from_error(&e).to_string(),
+                        location!(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
 
+    async fn backup_db(&self, path: &Path) -> Result<()> {
         // Thanks sqlx.
         // https://github.com/launchbadge/sqlx/issues/190
 
@@ -425,3 +1261,13 @@ impl Database {
         Ok(())
     }
 }
+
+/// A `LIKE` pattern matching anything containing `query` literally. Use with `ESCAPE '\'`.
+fn contains_pattern(query: &str) -> String {
+    let escaped = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{escaped}%")
+}
@@ -0,0 +1,151 @@
+use crate::error::{Result, SqlxSnafu, WebError};
+use crate::types::{ApiToken, ApiTokenId, TeamId, UserId};
+use jiff::Timestamp;
+use snafu::{ResultExt, location};
+use sqlx::{SqliteConnection, query};
+use tracing::{Instrument, info_span, instrument};
+
+#[instrument(skip_all)]
+pub(super) async fn add_api_token(
+    con: &mut SqliteConnection,
+    token: &ApiToken,
+    token_hash: &str,
+) -> Result<()> {
+    let scopes = serde_json::to_string(&token.scopes).expect("Unexpected json serialize error");
+    let created_at = token.created_at.as_millisecond();
+    query!(
+        r#"
+        INSERT INTO ApiTokens
+            (id, team_id, name, token_hash, scopes, created_by, created_at)
+        VALUES
+            (?, ?, ?, ?, ?, ?, ?)
+        "#,
+        token.id,
+        token.team,
+        token.name,
+        token_hash,
+        scopes,
+        token.created_by,
+        created_at
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_add_api_token"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_api_tokens(
+    con: &mut SqliteConnection,
+    team: &TeamId,
+) -> Result<Vec<ApiToken>> {
+    query!(
+        r#"
+        SELECT
+            id as "id!: ApiTokenId",
+            team_id as "team_id!: TeamId",
+            name,
+            scopes,
+            created_by as "created_by!: UserId",
+            created_at,
+            last_used_at
+        FROM ApiTokens
+        WHERE team_id = ?
+        ORDER BY created_at
+        "#,
+        team
+    )
+    .map(|row| ApiToken {
+        id: row.id,
+        team: row.team_id,
+        name: row.name,
+        scopes: serde_json::from_str(&row.scopes).expect("Unexpected json serialize error"),
+        created_by: row.created_by,
+        created_at: Timestamp::from_millisecond(row.created_at).expect("time is valid"),
+        last_used_at: row
+            .last_used_at
+            .map(|it| Timestamp::from_millisecond(it).expect("time is valid")),
+    })
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_api_tokens"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn fetch_api_token_by_hash(
+    con: &mut SqliteConnection,
+    token_hash: &str,
+) -> Result<Option<ApiToken>> {
+    query!(
+        r#"
+        SELECT
+            id as "id!: ApiTokenId",
+            team_id as "team_id!: TeamId",
+            name,
+            scopes,
+            created_by as "created_by!: UserId",
+            created_at,
+            last_used_at
+        FROM ApiTokens
+        WHERE token_hash = ?
+        "#,
+        token_hash
+    )
+    .map(|row| ApiToken {
+        id: row.id,
+        team: row.team_id,
+        name: row.name,
+        scopes: serde_json::from_str(&row.scopes).expect("Unexpected json serialize error"),
+        created_by: row.created_by,
+        created_at: Timestamp::from_millisecond(row.created_at).expect("time is valid"),
+        last_used_at: row
+            .last_used_at
+            .map(|it| Timestamp::from_millisecond(it).expect("time is valid")),
+    })
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_fetch_api_token_by_hash"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn mark_api_token_used(con: &mut SqliteConnection, id: &ApiTokenId) -> Result<()> {
+    let now = Timestamp::now().as_millisecond();
+    query!(
+        "UPDATE ApiTokens SET last_used_at = ? WHERE id = ?",
+        now,
+        id
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_mark_api_token_used"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn delete_api_token(
+    con: &mut SqliteConnection,
+    team: &TeamId,
+    id: &ApiTokenId,
+) -> Result<()> {
+    let res = query!(
+        "DELETE FROM ApiTokens WHERE id = ? AND team_id = ?",
+        id,
+        team
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_delete_api_token"))
+    .await
+    .context(SqlxSnafu)?;
+
+    if res.rows_affected() == 0 {
+        return Err(WebError::not_found(location!()));
+    }
+
+    Ok(())
+}
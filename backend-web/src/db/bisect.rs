@@ -0,0 +1,269 @@
+use crate::error::{Result, SqlxSnafu};
+use crate::types::{Bisect, BisectId, BisectStep, TaskId, TeamId, TestId};
+use jiff::Timestamp;
+use snafu::ResultExt;
+use sqlx::{Acquire, Sqlite, SqliteConnection, query};
+use std::collections::HashMap;
+use tracing::{Instrument, info_span, instrument};
+
+#[instrument(skip_all)]
+pub(super) async fn add_bisect(con: &mut SqliteConnection, bisect: &Bisect) -> Result<()> {
+    let candidates =
+        serde_json::to_string(&bisect.candidates).expect("Unexpected json serialize error");
+    let created_at = bisect.created_at.as_millisecond();
+    let finished_at = bisect.finished_at.map(|it| it.as_millisecond());
+
+    query!(
+        r#"
+        INSERT INTO Bisects
+            (
+                id, team_id, test_id, good_revision, bad_revision, candidates, good_index,
+                bad_index, first_bad_revision, created_at, finished_at
+            )
+        VALUES
+            (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        bisect.id,
+        bisect.team_id,
+        bisect.test_id,
+        bisect.good_revision,
+        bisect.bad_revision,
+        candidates,
+        bisect.good_index,
+        bisect.bad_index,
+        bisect.first_bad_revision,
+        created_at,
+        finished_at
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_add_bisect"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_bisect(
+    con: impl Acquire<'_, Database = Sqlite>,
+    bisect_id: &BisectId,
+) -> Result<Option<Bisect>> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let bisect = query!(
+        r#"
+        SELECT
+            id as "id!: BisectId",
+            team_id as "team_id!: TeamId",
+            test_id as "test_id!: TestId",
+            good_revision,
+            bad_revision,
+            candidates,
+            good_index,
+            bad_index,
+            first_bad_revision,
+            created_at,
+            finished_at
+        FROM Bisects
+        WHERE id = ?
+        "#,
+        bisect_id
+    )
+    .map(|row| Bisect {
+        id: row.id,
+        team_id: row.team_id,
+        test_id: row.test_id,
+        good_revision: row.good_revision,
+        bad_revision: row.bad_revision,
+        candidates: serde_json::from_str(&row.candidates).expect("Unexpected json serialize error"),
+        good_index: row.good_index,
+        bad_index: row.bad_index,
+        first_bad_revision: row.first_bad_revision,
+        created_at: Timestamp::from_millisecond(row.created_at).expect("time is valid"),
+        finished_at: row
+            .finished_at
+            .map(|it| Timestamp::from_millisecond(it).expect("time is valid")),
+        steps: Vec::new(),
+    })
+    .fetch_optional(&mut *con)
+    .instrument(info_span!("sqlx_get_bisect"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let Some(mut bisect) = bisect else {
+        return Ok(None);
+    };
+    bisect.steps = get_bisect_steps(&mut con, bisect_id).await?;
+
+    Ok(Some(bisect))
+}
+
+async fn get_bisect_steps(
+    con: &mut SqliteConnection,
+    bisect_id: &BisectId,
+) -> Result<Vec<BisectStep>> {
+    query!(
+        r#"
+        SELECT
+            task_id as "task_id!: TaskId",
+            revision,
+            passed as "passed?: bool",
+            queued_at
+        FROM BisectSteps
+        WHERE bisect_id = ?
+        ORDER BY queued_at
+        "#,
+        bisect_id
+    )
+    .map(|row| BisectStep {
+        task_id: row.task_id,
+        revision: row.revision,
+        passed: row.passed,
+        queued_at: Timestamp::from_millisecond(row.queued_at).expect("time is valid"),
+    })
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_bisect_steps"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_unfinished_bisect_ids(con: &mut SqliteConnection) -> Result<Vec<BisectId>> {
+    query!(
+        r#"
+        SELECT id as "id!: BisectId"
+        FROM Bisects
+        WHERE finished_at IS NULL
+        ORDER BY created_at
+        "#
+    )
+    .map(|row| row.id)
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_unfinished_bisect_ids"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn add_bisect_step(
+    con: &mut SqliteConnection,
+    bisect_id: &BisectId,
+    task_id: &TaskId,
+    revision: &str,
+) -> Result<()> {
+    let queued_at = Timestamp::now().as_millisecond();
+
+    query!(
+        r#"
+        INSERT INTO BisectSteps
+            (task_id, bisect_id, revision, queued_at)
+        VALUES
+            (?, ?, ?, ?)
+        "#,
+        task_id,
+        bisect_id,
+        revision,
+        queued_at
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_add_bisect_step"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+/// Records the outcome of the step and the narrowed down range of the bisect
+#[instrument(skip_all)]
+pub(super) async fn finish_bisect_step(
+    con: impl Acquire<'_, Database = Sqlite>,
+    bisect: &Bisect,
+    task_id: &TaskId,
+    passed: bool,
+) -> Result<()> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    query!(
+        "UPDATE BisectSteps SET passed = ? WHERE task_id = ?",
+        passed,
+        task_id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_finish_bisect_step"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let finished_at = bisect
+        .first_bad_revision
+        .as_ref()
+        .map(|_| Timestamp::now().as_millisecond());
+    query!(
+        r#"
+        UPDATE Bisects
+        SET good_index = ?, bad_index = ?, first_bad_revision = ?, finished_at = ?
+        WHERE id = ?
+        "#,
+        bisect.good_index,
+        bisect.bad_index,
+        bisect.first_bad_revision,
+        finished_at,
+        bisect.id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_update_bisect_range"))
+    .await
+    .context(SqlxSnafu)?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+/// Forgets a step whose task vanished without finishing, so it can be queued again
+#[instrument(skip_all)]
+pub(super) async fn remove_bisect_step(con: &mut SqliteConnection, task_id: &TaskId) -> Result<()> {
+    query!("DELETE FROM BisectSteps WHERE task_id = ?", task_id)
+        .execute(con)
+        .instrument(info_span!("sqlx_remove_bisect_step"))
+        .await
+        .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+/// Returns the test each queued bisect step runs
+#[instrument(skip_all)]
+pub(super) async fn get_queued_bisect_tests(
+    con: &mut SqliteConnection,
+) -> Result<HashMap<TaskId, TestId>> {
+    let rows = query!(
+        r#"
+        SELECT
+            BisectSteps.task_id as "task_id!: TaskId",
+            Bisects.test_id as "test_id!: TestId"
+        FROM BisectSteps
+        JOIN Bisects ON Bisects.id = BisectSteps.bisect_id
+        JOIN Queue ON Queue.id = BisectSteps.task_id
+        "#
+    )
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_queued_bisect_tests"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.task_id, row.test_id))
+        .collect())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn is_bisect_step(con: &mut SqliteConnection, task_id: &TaskId) -> Result<bool> {
+    let step = query!("SELECT task_id FROM BisectSteps WHERE task_id = ?", task_id)
+        .fetch_optional(con)
+        .instrument(info_span!("sqlx_is_bisect_step"))
+        .await
+        .context(SqlxSnafu)?;
+
+    Ok(step.is_some())
+}
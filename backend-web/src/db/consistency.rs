@@ -0,0 +1,179 @@
+use crate::error::{Result, SqlxSnafu};
+use crate::types::{ConsistencyIssue, ConsistencyReport};
+use snafu::ResultExt;
+use sqlx::{Pool, Sqlite, SqliteConnection, query};
+use tracing::{Instrument, info_span, instrument};
+
+/// Collects the rows violating a consistency rule and, if `fix` is set, deletes them in the same
+/// transaction. Outputs only orphaned by the repair are deleted as well.
+#[instrument(skip_all)]
+pub(super) async fn check_consistency(pool: &Pool<Sqlite>, fix: bool) -> Result<ConsistencyReport> {
+    let mut con = pool.begin().await.context(SqlxSnafu)?;
+
+    let mut report = ConsistencyReport {
+        fixed: fix,
+        orphaned_executions: ConsistencyIssue::new(get_orphaned_executions(&mut con).await?),
+        results_of_deleted_tests: ConsistencyIssue::new(
+            get_results_of_deleted_tests(&mut con).await?,
+        ),
+        finalized_missing_tasks: ConsistencyIssue::new(
+            get_finalized_missing_tasks(&mut con).await?,
+        ),
+        queued_for_unknown_teams: ConsistencyIssue::new(
+            get_queued_for_unknown_teams(&mut con).await?,
+        ),
+    };
+    if !fix {
+        return Ok(report);
+    }
+
+    report.results_of_deleted_tests.repaired = delete_results_of_deleted_tests(&mut con).await?;
+    report.finalized_missing_tasks.repaired = delete_finalized_missing_tasks(&mut con).await?;
+    report.queued_for_unknown_teams.repaired = delete_queued_for_unknown_teams(&mut con).await?;
+    // Last, as deleting the results above can orphan their outputs
+    report.orphaned_executions.repaired = delete_orphaned_executions(&mut con).await?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(report)
+}
+
+/// Returns the outputs no task, test result or test tasting refers to anymore
+#[instrument(skip_all)]
+async fn get_orphaned_executions(con: &mut SqliteConnection) -> Result<Vec<String>> {
+    query!(
+        r#"
+        SELECT execution_id as "execution_id!: String"
+        FROM ExecutionResults
+        WHERE execution_id NOT IN (SELECT execution_id FROM Tasks WHERE execution_id IS NOT NULL)
+            AND execution_id NOT IN (SELECT compiler_exec_id FROM TestResults)
+            AND execution_id NOT IN (
+                SELECT binary_exec_id FROM TestResults WHERE binary_exec_id IS NOT NULL
+            )
+            AND execution_id NOT IN (SELECT compiler_exec_id FROM TestTastingResults)
+            AND execution_id NOT IN (
+                SELECT binary_exec_id FROM TestTastingResults WHERE binary_exec_id IS NOT NULL
+            )
+        ORDER BY execution_id
+        "#
+    )
+    .map(|row| row.execution_id)
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_orphaned_executions"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+async fn delete_orphaned_executions(con: &mut SqliteConnection) -> Result<u64> {
+    Ok(query!(
+        r#"
+        DELETE FROM ExecutionResults
+        WHERE execution_id NOT IN (SELECT execution_id FROM Tasks WHERE execution_id IS NOT NULL)
+            AND execution_id NOT IN (SELECT compiler_exec_id FROM TestResults)
+            AND execution_id NOT IN (
+                SELECT binary_exec_id FROM TestResults WHERE binary_exec_id IS NOT NULL
+            )
+            AND execution_id NOT IN (SELECT compiler_exec_id FROM TestTastingResults)
+            AND execution_id NOT IN (
+                SELECT binary_exec_id FROM TestTastingResults WHERE binary_exec_id IS NOT NULL
+            )
+        "#
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_delete_orphaned_executions"))
+    .await
+    .context(SqlxSnafu)?
+    .rows_affected())
+}
+
+/// Returns the test results of deleted tests as `<task>/<test>`
+#[instrument(skip_all)]
+async fn get_results_of_deleted_tests(con: &mut SqliteConnection) -> Result<Vec<String>> {
+    query!(
+        r#"
+        SELECT DISTINCT task_id || '/' || test_id as "id!: String"
+        FROM TestResults
+        WHERE test_id NOT IN (SELECT id FROM Tests)
+        ORDER BY task_id, test_id
+        "#
+    )
+    .map(|row| row.id)
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_results_of_deleted_tests"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+async fn delete_results_of_deleted_tests(con: &mut SqliteConnection) -> Result<u64> {
+    Ok(
+        query!("DELETE FROM TestResults WHERE test_id NOT IN (SELECT id FROM Tests)")
+            .execute(con)
+            .instrument(info_span!("sqlx_delete_results_of_deleted_tests"))
+            .await
+            .context(SqlxSnafu)?
+            .rows_affected(),
+    )
+}
+
+/// Returns the finalized submissions whose task is gone as `<team>/<course>/<category>`
+#[instrument(skip_all)]
+async fn get_finalized_missing_tasks(con: &mut SqliteConnection) -> Result<Vec<String>> {
+    query!(
+        r#"
+        SELECT team_id || '/' || course_id || '/' || category as "id!: String"
+        FROM FinalizedSubmittedTasks
+        WHERE task_id NOT IN (SELECT task_id FROM Tasks)
+        ORDER BY team_id, course_id, category
+        "#
+    )
+    .map(|row| row.id)
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_finalized_missing_tasks"))
+    .await
+    .context(SqlxSnafu)
+}
+
+/// Removes the finalized submissions whose task is gone, so the automatic selection applies again
+#[instrument(skip_all)]
+async fn delete_finalized_missing_tasks(con: &mut SqliteConnection) -> Result<u64> {
+    Ok(query!(
+        "DELETE FROM FinalizedSubmittedTasks WHERE task_id NOT IN (SELECT task_id FROM Tasks)"
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_delete_finalized_missing_tasks"))
+    .await
+    .context(SqlxSnafu)?
+    .rows_affected())
+}
+
+/// Returns the queued tasks of teams that do not exist
+#[instrument(skip_all)]
+async fn get_queued_for_unknown_teams(con: &mut SqliteConnection) -> Result<Vec<String>> {
+    query!(
+        r#"
+        SELECT id as "id!: String"
+        FROM Queue
+        WHERE team NOT IN (SELECT id FROM Teams)
+        ORDER BY insert_time
+        "#
+    )
+    .map(|row| row.id)
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_queued_for_unknown_teams"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+async fn delete_queued_for_unknown_teams(con: &mut SqliteConnection) -> Result<u64> {
+    Ok(
+        query!("DELETE FROM Queue WHERE team NOT IN (SELECT id FROM Teams)")
+            .execute(con)
+            .instrument(info_span!("sqlx_delete_queued_for_unknown_teams"))
+            .await
+            .context(SqlxSnafu)?
+            .rows_affected(),
+    )
+}
@@ -0,0 +1,185 @@
+use crate::error::{Result, SqlxSnafu, WebError};
+use crate::types::{Course, CourseId, TeamId};
+use jiff::Timestamp;
+use snafu::{ResultExt, location};
+use sqlx::{Acquire, Sqlite, SqliteConnection, query, query_as};
+use std::collections::HashMap;
+use tracing::{Instrument, info_span, instrument};
+
+#[instrument(skip_all)]
+pub(super) async fn get_courses(con: &mut SqliteConnection) -> Result<Vec<Course>> {
+    query_as!(
+        DbCourse,
+        r#"
+        SELECT
+            id as "id!: CourseId",
+            display_name,
+            archived,
+            created_at
+        FROM Courses
+        ORDER BY created_at
+        "#
+    )
+    .map(Course::from)
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_courses"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn fetch_course(
+    con: &mut SqliteConnection,
+    course_id: &CourseId,
+) -> Result<Option<Course>> {
+    query_as!(
+        DbCourse,
+        r#"
+        SELECT
+            id as "id!: CourseId",
+            display_name,
+            archived,
+            created_at
+        FROM Courses
+        WHERE id = ?
+        "#,
+        course_id
+    )
+    .map(Course::from)
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_fetch_course"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn create_course(
+    con: impl Acquire<'_, Database = Sqlite>,
+    course: &Course,
+) -> Result<Course> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    if fetch_course(&mut con, &course.id).await?.is_some() {
+        return Err(WebError::named_bad_request(
+            format!("course `{}` already exists", course.id),
+            location!(),
+        ));
+    }
+
+    let created_at = course.created_at.as_millisecond();
+    query!(
+        r#"
+        INSERT INTO Courses
+            (id, display_name, archived, created_at)
+        VALUES
+            (?, ?, ?, ?)
+        "#,
+        course.id,
+        course.display_name,
+        course.archived,
+        created_at
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_create_course"))
+    .await
+    .context(SqlxSnafu)?;
+    let course = fetch_course(&mut con, &course.id)
+        .await?
+        .expect("course was just created");
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(course)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn patch_course(
+    con: impl Acquire<'_, Database = Sqlite>,
+    course_id: &CourseId,
+    display_name: Option<&str>,
+    archived: Option<bool>,
+) -> Result<Course> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+    let Some(course) = fetch_course(&mut con, course_id).await? else {
+        return Err(WebError::named_not_found(
+            format!("course `{course_id}`"),
+            location!(),
+        ));
+    };
+
+    let display_name = display_name.unwrap_or(&course.display_name);
+    let archived = archived.unwrap_or(course.archived);
+    query!(
+        "UPDATE Courses SET display_name = ?, archived = ? WHERE id = ?",
+        display_name,
+        archived,
+        course_id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_patch_course"))
+    .await
+    .context(SqlxSnafu)?;
+    let course = fetch_course(&mut con, course_id)
+        .await?
+        .expect("course existed before");
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(course)
+}
+
+/// Creates the course under its id if it does not exist yet, so teams of the config can name
+/// courses that were never created explicitly
+#[instrument(skip_all)]
+pub(super) async fn ensure_course(con: &mut SqliteConnection, course_id: &CourseId) -> Result<()> {
+    let created_at = Timestamp::now().as_millisecond();
+    query!(
+        r#"
+        INSERT INTO Courses
+            (id, display_name, created_at)
+        VALUES
+            (?, ?, ?)
+        ON CONFLICT DO NOTHING
+        "#,
+        course_id,
+        course_id,
+        created_at
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_ensure_course"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_team_courses(
+    con: &mut SqliteConnection,
+) -> Result<HashMap<TeamId, CourseId>> {
+    query!(r#"SELECT id as "id!: TeamId", course_id as "course_id!: CourseId" FROM Teams"#)
+        .map(|it| (it.id, it.course_id))
+        .fetch_all(con)
+        .instrument(info_span!("sqlx_get_team_courses"))
+        .await
+        .context(SqlxSnafu)
+        .map(|it| it.into_iter().collect())
+}
+
+struct DbCourse {
+    id: CourseId,
+    display_name: String,
+    archived: bool,
+    created_at: i64,
+}
+
+impl From<DbCourse> for Course {
+    fn from(value: DbCourse) -> Self {
+        Self {
+            id: value.id,
+            display_name: value.display_name,
+            archived: value.archived,
+            created_at: Timestamp::from_millisecond(value.created_at).expect("time is valid"),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::error::{Result, SqlxSnafu};
+use crate::types::CourseId;
+use jiff::Timestamp;
+use snafu::ResultExt;
+use sqlx::{Acquire, Sqlite, query};
+use tracing::{Instrument, info_span, instrument};
+
+/// Records the current hash of a category fileset, bumping its version if it changed. A missing
+/// fileset is recorded as an empty hash. Returns the current version.
+#[instrument(skip_all)]
+pub(super) async fn sync_category_fileset(
+    con: impl Acquire<'_, Database = Sqlite>,
+    course: &CourseId,
+    category: &str,
+    hash: Option<&str>,
+) -> Result<i64> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+    let hash = hash.unwrap_or_default();
+
+    let existing = query!(
+        r#"
+        SELECT hash, version as "version!: i64"
+        FROM CategoryFilesets
+        WHERE course_id = ? AND category = ?
+        "#,
+        course,
+        category
+    )
+    .fetch_optional(&mut *con)
+    .instrument(info_span!("sqlx_get_category_fileset"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let version = match existing {
+        Some(existing) if existing.hash == hash => return Ok(existing.version),
+        Some(existing) => existing.version + 1,
+        // Categories never having a fileset are not worth recording
+        None if hash.is_empty() => return Ok(0),
+        None => 1,
+    };
+
+    let updated_at = Timestamp::now().as_millisecond();
+    query!(
+        r#"
+        INSERT INTO CategoryFilesets
+            (course_id, category, hash, version, updated_at)
+        VALUES
+            (?, ?, ?, ?, ?)
+        ON CONFLICT DO UPDATE SET
+            hash = excluded.hash,
+            version = excluded.version,
+            updated_at = excluded.updated_at
+        "#,
+        course,
+        category,
+        hash,
+        version,
+        updated_at
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_update_category_fileset"))
+    .await
+    .context(SqlxSnafu)?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(version)
+}
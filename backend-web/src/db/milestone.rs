@@ -0,0 +1,193 @@
+use crate::error::{Result, SqlxSnafu};
+use crate::types::{
+    CategoryMilestone, CourseMilestone, MILESTONE_THRESHOLDS, TaskId, TeamId, TeamMilestones,
+    TestFirstPass, TestId,
+};
+use jiff::Timestamp;
+use shared::{FinishedCompilerTask, TestExecutionOutput};
+use snafu::ResultExt;
+use sqlx::{SqliteConnection, query};
+use std::collections::HashMap;
+use tracing::{Instrument, info_span, instrument};
+
+/// Records the tests and category thresholds the task passed, unless the team passed them
+/// earlier already
+#[instrument(skip_all)]
+pub(super) async fn record_milestones(
+    con: &mut SqliteConnection,
+    result: &FinishedCompilerTask,
+    queue_time: Timestamp,
+) -> Result<()> {
+    let FinishedCompilerTask::RanTests { info, tests, .. } = result else {
+        return Ok(());
+    };
+
+    // A test only passed if all of its profiles passed
+    let mut passed_tests: HashMap<&str, (Option<&str>, bool)> = HashMap::new();
+    for test in tests {
+        let entry = passed_tests
+            .entry(test.test_id.as_str())
+            .or_insert((test.category.as_deref(), true));
+        entry.1 &= matches!(test.output, TestExecutionOutput::Success { .. });
+    }
+
+    // (passed, total) per category
+    let mut categories: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (category, passed) in passed_tests.values() {
+        let Some(category) = category else {
+            continue;
+        };
+        let entry = categories.entry(category).or_default();
+        entry.0 += usize::from(*passed);
+        entry.1 += 1;
+    }
+
+    let reached_at = queue_time.as_millisecond();
+    for (test_id, (_, passed)) in &passed_tests {
+        if !passed {
+            continue;
+        }
+        query!(
+            r#"
+            INSERT INTO TestFirstPasses
+                (team_id, test_id, task_id, passed_at)
+            VALUES
+                (?, ?, ?, ?)
+            ON CONFLICT (team_id, test_id) DO UPDATE
+                SET task_id = excluded.task_id, passed_at = excluded.passed_at
+                WHERE excluded.passed_at < TestFirstPasses.passed_at
+            "#,
+            info.team_id,
+            test_id,
+            info.task_id,
+            reached_at
+        )
+        .execute(&mut *con)
+        .instrument(info_span!("sqlx_record_test_first_pass"))
+        .await
+        .context(SqlxSnafu)?;
+    }
+
+    for (category, (passed, total)) in categories {
+        for threshold in MILESTONE_THRESHOLDS {
+            if passed * 100 < total * usize::from(threshold) {
+                continue;
+            }
+            query!(
+                r#"
+                INSERT INTO CategoryMilestones
+                    (team_id, category, threshold, task_id, reached_at)
+                VALUES
+                    (?, ?, ?, ?, ?)
+                ON CONFLICT (team_id, category, threshold) DO UPDATE
+                    SET task_id = excluded.task_id, reached_at = excluded.reached_at
+                    WHERE excluded.reached_at < CategoryMilestones.reached_at
+                "#,
+                info.team_id,
+                category,
+                threshold,
+                info.task_id,
+                reached_at
+            )
+            .execute(&mut *con)
+            .instrument(info_span!("sqlx_record_category_milestone"))
+            .await
+            .context(SqlxSnafu)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_team_milestones(
+    con: &mut SqliteConnection,
+    team_id: &TeamId,
+) -> Result<TeamMilestones> {
+    let tests = query!(
+        r#"
+        SELECT
+            test_id as "test_id!: TestId",
+            task_id as "task_id?: TaskId",
+            passed_at
+        FROM TestFirstPasses
+        WHERE team_id = ?
+        ORDER BY passed_at
+        "#,
+        team_id
+    )
+    .map(|row| TestFirstPass {
+        test_id: row.test_id,
+        task_id: row.task_id,
+        passed_at: Timestamp::from_millisecond(row.passed_at).expect("time is valid"),
+    })
+    .fetch_all(&mut *con)
+    .instrument(info_span!("sqlx_get_team_test_first_passes"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let categories = query!(
+        r#"
+        SELECT
+            category,
+            threshold,
+            task_id as "task_id?: TaskId",
+            reached_at
+        FROM CategoryMilestones
+        WHERE team_id = ?
+        ORDER BY reached_at, threshold
+        "#,
+        team_id
+    )
+    .map(|row| CategoryMilestone {
+        category: row.category,
+        threshold: row.threshold as u8,
+        task_id: row.task_id,
+        reached_at: Timestamp::from_millisecond(row.reached_at).expect("time is valid"),
+    })
+    .fetch_all(&mut *con)
+    .instrument(info_span!("sqlx_get_team_category_milestones"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(TeamMilestones {
+        team: team_id.clone(),
+        tests,
+        categories,
+    })
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_course_milestones(
+    con: &mut SqliteConnection,
+) -> Result<Vec<CourseMilestone>> {
+    let rows = query!(
+        r#"
+        SELECT category, threshold, reached_at
+        FROM CategoryMilestones
+        ORDER BY category, threshold, reached_at
+        "#
+    )
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_course_milestones"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let mut milestones: Vec<CourseMilestone> = Vec::new();
+    for row in rows {
+        let threshold = row.threshold as u8;
+        let reached_at = Timestamp::from_millisecond(row.reached_at).expect("time is valid");
+        match milestones.last_mut() {
+            Some(last) if last.category == row.category && last.threshold == threshold => {
+                last.reached_at.push(reached_at);
+            }
+            _ => milestones.push(CourseMilestone {
+                category: row.category,
+                threshold,
+                reached_at: vec![reached_at],
+            }),
+        }
+    }
+
+    Ok(milestones)
+}
@@ -0,0 +1,98 @@
+use crate::error::{Result, SqlxSnafu, WebError};
+use crate::types::{Notification, NotificationId, TeamId};
+use jiff::Timestamp;
+use snafu::{ResultExt, location};
+use sqlx::{SqliteConnection, query};
+use tracing::{Instrument, info_span, instrument};
+
+#[instrument(skip_all)]
+pub(super) async fn add_notification(
+    con: &mut SqliteConnection,
+    notification: &Notification,
+) -> Result<()> {
+    let created_at = notification.created_at.as_millisecond();
+    let read_at = notification.read_at.map(|it| it.as_millisecond());
+    query!(
+        r#"
+        INSERT INTO Notifications
+            (id, team_id, message, created_at, read_at)
+        VALUES
+            (?, ?, ?, ?, ?)
+        "#,
+        notification.id,
+        notification.team,
+        notification.message,
+        created_at,
+        read_at
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_add_notification"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+/// Returns the notifications of the team, newest first
+#[instrument(skip_all)]
+pub(super) async fn get_notifications(
+    con: &mut SqliteConnection,
+    team: &TeamId,
+) -> Result<Vec<Notification>> {
+    query!(
+        r#"
+        SELECT
+            id as "id!: NotificationId",
+            team_id as "team_id!: TeamId",
+            message,
+            created_at,
+            read_at
+        FROM Notifications
+        WHERE team_id = ?
+        ORDER BY created_at DESC
+        "#,
+        team
+    )
+    .map(|row| Notification {
+        id: row.id,
+        team: row.team_id,
+        message: row.message,
+        created_at: Timestamp::from_millisecond(row.created_at).expect("time is valid"),
+        read_at: row
+            .read_at
+            .map(|it| Timestamp::from_millisecond(it).expect("time is valid")),
+    })
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_notifications"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn mark_notification_read(
+    con: &mut SqliteConnection,
+    team: &TeamId,
+    id: &NotificationId,
+) -> Result<()> {
+    let now = Timestamp::now().as_millisecond();
+    let res = query!(
+        r#"
+        UPDATE Notifications
+        SET read_at = COALESCE(read_at, ?)
+        WHERE id = ? AND team_id = ?
+        "#,
+        now,
+        id,
+        team
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_mark_notification_read"))
+    .await
+    .context(SqlxSnafu)?;
+
+    if res.rows_affected() == 0 {
+        return Err(WebError::not_found(location!()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::error::{Result, SqlxSnafu};
+use crate::types::TaskId;
+use shared::{FinishedExecution, FinishedTest};
+use snafu::ResultExt;
+use sqlx::{SqliteConnection, query};
+use std::time::{Duration, SystemTime};
+use tracing::{Instrument, info_span, instrument};
+
+/// The results a runner reported for a task before it vanished
+pub(super) struct PartialTask {
+    pub start: SystemTime,
+    pub build_output: FinishedExecution,
+    pub tests: Vec<FinishedTest>,
+}
+
+/// Removes everything recorded for a previous attempt at the task
+#[instrument(skip_all)]
+pub(super) async fn clear_partial_task(con: &mut SqliteConnection, task_id: &TaskId) -> Result<()> {
+    query!("DELETE FROM PartialTaskBuilds WHERE task_id = ?", task_id)
+        .execute(con)
+        .instrument(info_span!("sqlx_clear_partial_task"))
+        .await
+        .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn record_partial_build(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+    build_output: &FinishedExecution,
+) -> Result<()> {
+    let start_time = SystemTime::now()
+        .checked_sub(build_output.runtime)
+        .unwrap_or(SystemTime::now())
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis() as i64;
+    let build_output =
+        serde_json::to_string(build_output).expect("Unexpected json serialize error");
+
+    // Tasks that were finished in the meantime are no longer queued
+    query!(
+        r#"
+        INSERT INTO PartialTaskBuilds
+            (task_id, start_time, build_output)
+        SELECT ?, ?, ?
+        WHERE EXISTS (SELECT 1 FROM Queue WHERE id = ?)
+        ON CONFLICT (task_id) DO UPDATE
+            SET start_time = excluded.start_time, build_output = excluded.build_output
+        "#,
+        task_id,
+        start_time,
+        build_output,
+        task_id
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_record_partial_build"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn record_partial_test(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+    test: &FinishedTest,
+) -> Result<()> {
+    let profile = test.profile.as_deref().unwrap_or_default();
+    let result = serde_json::to_string(test).expect("Unexpected json serialize error");
+
+    // Tests reported without a finished build can not be turned into a task
+    query!(
+        r#"
+        INSERT INTO PartialTestResults
+            (task_id, test_id, profile, result)
+        SELECT ?, ?, ?, ?
+        WHERE EXISTS (SELECT 1 FROM PartialTaskBuilds WHERE task_id = ?)
+        ON CONFLICT (task_id, test_id, profile) DO UPDATE
+            SET result = excluded.result
+        "#,
+        task_id,
+        test.test_id,
+        profile,
+        result,
+        task_id
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_record_partial_test"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn fetch_partial_task(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+) -> Result<Option<PartialTask>> {
+    let Some(build) = query!(
+        "SELECT start_time, build_output FROM PartialTaskBuilds WHERE task_id = ?",
+        task_id
+    )
+    .fetch_optional(&mut *con)
+    .instrument(info_span!("sqlx_fetch_partial_task_build"))
+    .await
+    .context(SqlxSnafu)?
+    else {
+        return Ok(None);
+    };
+
+    let tests = query!(
+        r#"
+        SELECT result
+        FROM PartialTestResults
+        WHERE task_id = ?
+        ORDER BY test_id, profile
+        "#,
+        task_id
+    )
+    .map(|it| serde_json::from_str(&it.result).expect("Unexpected json serialize error"))
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_fetch_partial_task_tests"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(Some(PartialTask {
+        start: SystemTime::UNIX_EPOCH + Duration::from_millis(build.start_time as u64),
+        build_output: serde_json::from_str(&build.build_output)
+            .expect("Unexpected json serialize error"),
+        tests,
+    }))
+}
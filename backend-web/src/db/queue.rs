@@ -1,8 +1,9 @@
 use crate::error::{Result, SqlxSnafu};
-use crate::types::{TaskId, TeamId, WorkItem};
+use crate::types::{QueueStatisticsDay, TaskId, TeamId, WorkItem};
 use jiff::Timestamp;
 use snafu::ResultExt;
 use sqlx::{SqliteConnection, query};
+use std::collections::HashMap;
 use std::ops::Add;
 use std::time::{Duration, SystemTime};
 use tracing::{Instrument, info_span, instrument};
@@ -14,18 +15,35 @@ pub(super) async fn queue_task(con: &mut SqliteConnection, task: WorkItem) -> Re
         .duration_since(SystemTime::UNIX_EPOCH)
         .expect("time went backwards")
         .as_millis() as i64;
+    let commit = task
+        .commit
+        .as_ref()
+        .map(|it| serde_json::to_string(it).expect("Unexpected json serialize error"));
 
     query!(
-        "INSERT INTO Queue (id, team, revision, commit_message, insert_time) VALUES (?, ?, ?, ?, ?)",
+        r#"
+        INSERT INTO Queue
+            (
+                id, team, revision, commit_message, insert_time, tree_hash, identical_to,
+                rerun_of, commit_metadata
+            )
+        VALUES
+            (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
         task.id,
         task.team,
         task.revision,
         task.commit_message,
-        insert_time
+        insert_time,
+        task.tree_hash,
+        task.identical_to,
+        task.rerun_of,
+        commit
     )
     .execute(con)
     .instrument(info_span!("sqlx_insert_queue"))
-    .await.context(SqlxSnafu)?;
+    .await
+    .context(SqlxSnafu)?;
 
     Ok(())
 }
@@ -59,7 +77,11 @@ pub(super) async fn get_queued_tasks(con: &mut SqliteConnection) -> Result<Vec<W
             team as "team!: TeamId",
             revision,
             commit_message,
-            insert_time as "insert_time!: u64"
+            insert_time as "insert_time!: u64",
+            tree_hash,
+            identical_to as "identical_to?: TaskId",
+            rerun_of as "rerun_of?: TaskId",
+            commit_metadata
         FROM Queue"#
     )
     .map(|row| WorkItem {
@@ -68,6 +90,12 @@ pub(super) async fn get_queued_tasks(con: &mut SqliteConnection) -> Result<Vec<W
         revision: row.revision,
         commit_message: row.commit_message,
         insert_time: SystemTime::UNIX_EPOCH.add(Duration::from_millis(row.insert_time)),
+        tree_hash: row.tree_hash,
+        identical_to: row.identical_to,
+        rerun_of: row.rerun_of,
+        commit: row
+            .commit_metadata
+            .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error")),
     })
     .fetch_all(con)
     .instrument(info_span!("sqlx_get_queue"))
@@ -87,7 +115,11 @@ pub(super) async fn fetch_queued_task(
             team as "team!: TeamId",
             revision,
             commit_message,
-            insert_time as "insert_time!: u64"
+            insert_time as "insert_time!: u64",
+            tree_hash,
+            identical_to as "identical_to?: TaskId",
+            rerun_of as "rerun_of?: TaskId",
+            commit_metadata
         FROM Queue
         WHERE id = ?
         "#,
@@ -99,9 +131,152 @@ pub(super) async fn fetch_queued_task(
         revision: row.revision,
         commit_message: row.commit_message,
         insert_time: SystemTime::UNIX_EPOCH.add(Duration::from_millis(row.insert_time)),
+        tree_hash: row.tree_hash,
+        identical_to: row.identical_to,
+        rerun_of: row.rerun_of,
+        commit: row
+            .commit_metadata
+            .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error")),
     })
     .fetch_optional(con)
     .instrument(info_span!("sqlx_get_queued_task"))
     .await
     .context(SqlxSnafu)
 }
+
+#[instrument(skip_all)]
+pub(super) async fn fetch_queued_task_for_revision(
+    con: &mut SqliteConnection,
+    team_id: &TeamId,
+    revision: &str,
+) -> Result<Option<WorkItem>> {
+    query!(
+        r#"
+        SELECT
+            id as "id!: TaskId",
+            team as "team!: TeamId",
+            revision,
+            commit_message,
+            insert_time as "insert_time!: u64",
+            tree_hash,
+            identical_to as "identical_to?: TaskId",
+            rerun_of as "rerun_of?: TaskId",
+            commit_metadata
+        FROM Queue
+        -- Bisect steps only run a single test, so they can not stand in for a full task
+        WHERE team = ? AND revision = ? AND id NOT IN (SELECT task_id FROM BisectSteps)
+        ORDER BY insert_time
+        LIMIT 1
+        "#,
+        team_id,
+        revision
+    )
+    .map(|row| WorkItem {
+        id: row.id,
+        team: row.team,
+        revision: row.revision,
+        commit_message: row.commit_message,
+        insert_time: SystemTime::UNIX_EPOCH.add(Duration::from_millis(row.insert_time)),
+        tree_hash: row.tree_hash,
+        identical_to: row.identical_to,
+        rerun_of: row.rerun_of,
+        commit: row
+            .commit_metadata
+            .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error")),
+    })
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_get_queued_task_for_revision"))
+    .await
+    .context(SqlxSnafu)
+}
+
+/// Returns the frozen categories each queued task was queued as a final submission rerun for
+#[instrument(skip_all)]
+pub(super) async fn get_queued_frozen_reruns(
+    con: &mut SqliteConnection,
+) -> Result<HashMap<TaskId, Vec<String>>> {
+    let rows = query!(
+        r#"
+        SELECT
+            FinalizedSubmittedTasks.task_id as "task_id!: TaskId",
+            FinalizedSubmittedTasks.category
+        FROM FinalizedSubmittedTasks
+        JOIN Queue ON Queue.id = FinalizedSubmittedTasks.task_id
+        JOIN TestSuiteSnapshots ON TestSuiteSnapshots.course_id = FinalizedSubmittedTasks.course_id
+            AND TestSuiteSnapshots.category = FinalizedSubmittedTasks.category
+        "#
+    )
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_queued_frozen_reruns"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(rows.into_iter().fold(HashMap::new(), |mut acc, row| {
+        acc.entry(row.task_id).or_default().push(row.category);
+        acc
+    }))
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_average_task_duration(
+    con: &mut SqliteConnection,
+    sample_size: i64,
+) -> Result<Option<Duration>> {
+    let average = query!(
+        r#"
+        SELECT
+            AVG(end_time - start_time) as "average: f64"
+        FROM (
+            SELECT start_time, end_time FROM Tasks ORDER BY end_time DESC LIMIT ?
+        )
+        "#,
+        sample_size
+    )
+    .fetch_one(con)
+    .instrument(info_span!("sqlx_get_average_task_duration"))
+    .await
+    .context(SqlxSnafu)?
+    .average;
+
+    Ok(average.map(|it| Duration::from_millis(it.max(0.0) as u64)))
+}
+
+/// The queue wait and execution durations in milliseconds of all tasks queued since the given
+/// time, grouped by the UTC day they were queued on
+#[instrument(skip_all)]
+pub(super) async fn get_queue_statistics(
+    con: &mut SqliteConnection,
+    since: Timestamp,
+) -> Result<Vec<QueueStatisticsDay>> {
+    let since = since.as_millisecond();
+    let rows = query!(
+        r#"
+        SELECT
+            date(queue_time / 1000, 'unixepoch') as "day!: String",
+            MAX(start_time - queue_time, 0) as "wait_ms!: i64",
+            MAX(end_time - start_time, 0) as "execution_ms!: i64"
+        FROM Tasks
+        WHERE queue_time >= ?
+        ORDER BY queue_time
+        "#,
+        since
+    )
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_queue_statistics"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let mut days: Vec<(String, Vec<(u64, u64)>)> = Vec::new();
+    for row in rows {
+        let sample = (row.wait_ms as u64, row.execution_ms as u64);
+        match days.last_mut() {
+            Some((day, samples)) if *day == row.day => samples.push(sample),
+            _ => days.push((row.day, vec![sample])),
+        }
+    }
+
+    Ok(days
+        .into_iter()
+        .map(|(day, samples)| QueueStatisticsDay::from_samples(day, &samples))
+        .collect())
+}
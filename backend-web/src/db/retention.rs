@@ -0,0 +1,152 @@
+use crate::error::{Result, SqlxSnafu};
+use crate::types::{PrunableTask, TaskId, TeamId};
+use jiff::Timestamp;
+use snafu::ResultExt;
+use sqlx::{SqliteConnection, query};
+use std::path::PathBuf;
+use tracing::{Instrument, info_span, instrument};
+
+/// Returns the tasks that finished before the cutoff or are not among the newest
+/// `max_tasks_per_team` of their team, oldest first. Finalized and manually submitted tasks and
+/// the newest task of every team are never returned.
+#[instrument(skip_all)]
+pub(super) async fn get_prunable_tasks(
+    con: &mut SqliteConnection,
+    finished_before: Option<Timestamp>,
+    max_tasks_per_team: Option<u32>,
+) -> Result<Vec<PrunableTask>> {
+    let finished_before = finished_before.map_or(i64::MIN, |it| it.as_millisecond());
+    let max_tasks_per_team = max_tasks_per_team.map_or(i64::MAX, i64::from);
+
+    query!(
+        r#"
+        WITH RankedTasks AS (
+            SELECT
+                task_id,
+                team_id,
+                end_time,
+                ROW_NUMBER() OVER (PARTITION BY team_id ORDER BY queue_time DESC) AS position
+            FROM Tasks
+        )
+        SELECT
+            task_id as "task_id!: TaskId",
+            team_id as "team_id!: TeamId",
+            end_time
+        FROM RankedTasks
+        WHERE position > 1
+            AND (end_time < ? OR position > ?)
+            AND task_id NOT IN (SELECT task_id FROM FinalizedSubmittedTasks)
+            AND task_id NOT IN (
+                SELECT task_id FROM ManuallySubmittedTasks WHERE task_id IS NOT NULL
+            )
+        ORDER BY end_time
+        "#,
+        finished_before,
+        max_tasks_per_team
+    )
+    .map(|row| PrunableTask {
+        task_id: row.task_id,
+        team_id: row.team_id,
+        end_time: Timestamp::from_millisecond(row.end_time).expect("time is valid"),
+    })
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_prunable_tasks"))
+    .await
+    .context(SqlxSnafu)
+}
+
+/// Returns how many build and test outputs belong to the task
+#[instrument(skip_all)]
+pub(super) async fn count_task_execution_results(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+) -> Result<u64> {
+    let count = query!(
+        r#"
+        SELECT COUNT(*) as "count!: i64"
+        FROM ExecutionResults
+        WHERE execution_id IN (
+            SELECT compiler_exec_id FROM TestResults WHERE task_id = ?
+            UNION
+            SELECT binary_exec_id FROM TestResults WHERE task_id = ?
+            UNION
+            SELECT execution_id FROM Tasks WHERE task_id = ?
+        )
+        "#,
+        task_id,
+        task_id,
+        task_id
+    )
+    .fetch_one(con)
+    .instrument(info_span!("sqlx_count_task_execution_results"))
+    .await
+    .context(SqlxSnafu)?
+    .count;
+
+    Ok(count as u64)
+}
+
+/// Deletes the task with its test results and outputs. Returns the number of deleted outputs and
+/// the files of their full outputs, which the caller removes once the deletion is committed.
+#[instrument(skip_all)]
+pub(super) async fn delete_task(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+) -> Result<(u64, Vec<PathBuf>)> {
+    let full_outputs = query!(
+        r#"
+        SELECT full_stdout_path, full_stderr_path
+        FROM ExecutionResults
+        WHERE execution_id IN (
+            SELECT compiler_exec_id FROM TestResults WHERE task_id = ?
+            UNION
+            SELECT binary_exec_id FROM TestResults WHERE task_id = ?
+            UNION
+            SELECT execution_id FROM Tasks WHERE task_id = ?
+        )
+            AND (full_stdout_path IS NOT NULL OR full_stderr_path IS NOT NULL)
+        "#,
+        task_id,
+        task_id,
+        task_id
+    )
+    .fetch_all(&mut *con)
+    .instrument(info_span!("sqlx_get_task_full_outputs"))
+    .await
+    .context(SqlxSnafu)?
+    .into_iter()
+    .flat_map(|row| [row.full_stdout_path, row.full_stderr_path])
+    .flatten()
+    .map(PathBuf::from)
+    .collect();
+
+    // The outputs are referenced by the results, so they must be collected before the results go
+    let deleted = query!(
+        r#"
+        DELETE FROM ExecutionResults
+        WHERE execution_id IN (
+            SELECT compiler_exec_id FROM TestResults WHERE task_id = ?
+            UNION
+            SELECT binary_exec_id FROM TestResults WHERE task_id = ?
+            UNION
+            SELECT execution_id FROM Tasks WHERE task_id = ?
+        )
+        "#,
+        task_id,
+        task_id,
+        task_id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_delete_task_execution_results"))
+    .await
+    .context(SqlxSnafu)?
+    .rows_affected();
+
+    query!("DELETE FROM Tasks WHERE task_id = ?", task_id)
+        .execute(&mut *con)
+        .instrument(info_span!("sqlx_delete_task"))
+        .await
+        .context(SqlxSnafu)?;
+
+    Ok((deleted, full_outputs))
+}
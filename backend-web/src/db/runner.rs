@@ -0,0 +1,51 @@
+use crate::error::{Result, SqlxSnafu};
+use shared::RunnerId;
+use snafu::ResultExt;
+use sqlx::{SqliteConnection, query, query_scalar};
+use tracing::{Instrument, info_span, instrument};
+
+/// Whether the runner was assigned to test tasting by an admin, if it was assigned a role at all
+#[instrument(skip_all)]
+pub(super) async fn get_runner_role(
+    con: &mut SqliteConnection,
+    runner_id: &RunnerId,
+) -> Result<Option<bool>> {
+    let runner_id = runner_id.to_string();
+    query_scalar!(
+        r#"SELECT test_taster as "test_taster: bool" FROM RunnerRoles WHERE runner_id = ?"#,
+        runner_id
+    )
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_get_runner_role"))
+    .await
+    .context(SqlxSnafu)
+}
+
+/// Assigns the runner a role, or lets it use the one it registers with again if `None`
+#[instrument(skip_all)]
+pub(super) async fn set_runner_role(
+    con: &mut SqliteConnection,
+    runner_id: &RunnerId,
+    test_taster: Option<bool>,
+) -> Result<()> {
+    let runner_id = runner_id.to_string();
+    match test_taster {
+        Some(test_taster) => query!(
+            "INSERT INTO RunnerRoles (runner_id, test_taster) VALUES (?, ?)
+             ON CONFLICT (runner_id) DO UPDATE SET test_taster = excluded.test_taster",
+            runner_id,
+            test_taster
+        )
+        .execute(con)
+        .instrument(info_span!("sqlx_set_runner_role"))
+        .await
+        .context(SqlxSnafu)?,
+        None => query!("DELETE FROM RunnerRoles WHERE runner_id = ?", runner_id)
+            .execute(con)
+            .instrument(info_span!("sqlx_clear_runner_role"))
+            .await
+            .context(SqlxSnafu)?,
+    };
+
+    Ok(())
+}
@@ -1,17 +1,19 @@
-use crate::UserId;
+use super::contains_pattern;
 use crate::config::TestCategory;
 use crate::error::{Result, SqlxSnafu, WebError};
+use crate::output_storage::{FullOutput, OutputStorage, StoredOutput};
 use crate::types::{
-    ExecutionExitStatus, FinalSubmittedTask, FinishedCompilerTaskSummary, FinishedTestSummary,
-    TaskId, TeamId, TestId,
+    CourseId, ExecutionExitStatus, FinalSubmittedTask, FinishedCompilerTaskSummary,
+    FinishedTestSummary, OutputStream, Page, TaskComparison, TaskExportRow, TaskId,
+    TaskPerformance, TeamActivityDay, TeamId, TestId, TestStatusChange, UserId,
 };
 use jiff::Timestamp;
 use shared::{
-    AbortedExecution, ExecutionOutput, FinishedCompilerTask, FinishedExecution, FinishedTaskInfo,
-    FinishedTest, InternalError, TestExecutionOutput, TestExecutionOutputType,
+    AbortedExecution, CommitMetadata, ExecutionOutput, FinishedCompilerTask, FinishedExecution,
+    FinishedTaskInfo, FinishedTest, InternalError, TestExecutionOutput, TestExecutionOutputType,
 };
 use snafu::{ResultExt, location};
-use sqlx::{Acquire, Sqlite, SqliteConnection, query};
+use sqlx::{Acquire, Sqlite, SqliteConnection, query, query_as};
 use std::collections::HashMap;
 use std::ops::Add;
 use std::time::{Duration, SystemTime};
@@ -20,6 +22,7 @@ use tracing::{Instrument, info_span, instrument};
 #[instrument(skip_all)]
 pub(super) async fn add_finished_task(
     con: impl Acquire<'_, Database = Sqlite>,
+    storage: Option<&OutputStorage>,
     result: &FinishedCompilerTask,
     queue_time: Timestamp,
 ) -> Result<()> {
@@ -41,7 +44,7 @@ pub(super) async fn add_finished_task(
 
     match result {
         FinishedCompilerTask::BuildFailed { build_output, .. } => {
-            let id = record_execution_output(&mut con, build_output).await?;
+            let id = record_execution_output(&mut con, storage, build_output).await?;
             record_task(&mut con, result, start_time, end_time, &id, queue_time).await?;
         }
         FinishedCompilerTask::RanTests {
@@ -52,6 +55,7 @@ pub(super) async fn add_finished_task(
             let build_id = uuid::Uuid::new_v4().to_string();
             record_finished_execution(
                 &mut con,
+                storage,
                 &build_id,
                 build_output,
                 &None,
@@ -65,23 +69,37 @@ pub(super) async fn add_finished_task(
 
             for test in tests {
                 let (compiler_exec_id, binary_exec_id) =
-                    record_test_execution(&mut con, &test.output).await?;
+                    record_test_execution(&mut con, storage, &test.output).await?;
                 let status = TestExecutionOutputType::from(&test.output).to_string();
+                let profile = test.profile.as_deref().unwrap_or_default();
+                let binary_size = test.binary_size.map(|it| it as i64);
+                let compile_duration_ms = test.compile_duration_ms.map(|it| it as i64);
+                let invocation = serde_json::to_string(&test.invocation)
+                    .expect("Unexpected json serialize error");
+                let diagnostics = Some(&test.diagnostics)
+                    .filter(|it| !it.is_empty())
+                    .map(|it| serde_json::to_string(it).expect("Unexpected json serialize error"));
 
                 query!(
                     r#"
                     INSERT INTO TestResults
-                        (task_id, test_id, compiler_exec_id, binary_exec_id, status,
-                         provisional_for_category)
+                        (task_id, test_id, profile, compiler_exec_id, binary_exec_id, status,
+                         provisional_for_category, binary_size, compile_duration_ms, invocation,
+                         diagnostics)
                     VALUES
-                        (?, ?, ?, ?, ?, ?)
+                        (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                     "#,
                     result.info().task_id,
                     test.test_id,
+                    profile,
                     compiler_exec_id,
                     binary_exec_id,
                     status,
-                    test.provisional_for_category
+                    test.provisional_for_category,
+                    binary_size,
+                    compile_duration_ms,
+                    invocation,
+                    diagnostics
                 )
                 .execute(&mut *con)
                 .instrument(info_span!("sqlx_add_finished_insert_test"))
@@ -96,6 +114,103 @@ pub(super) async fn add_finished_task(
     Ok(())
 }
 
+#[instrument(skip_all)]
+pub(super) async fn set_task_tree_hash(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+    tree_hash: &str,
+) -> Result<()> {
+    query!(
+        "UPDATE Tasks SET tree_hash = ? WHERE task_id = ?",
+        tree_hash,
+        task_id
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_set_task_tree_hash"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn set_task_commit_metadata(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+    commit: &CommitMetadata,
+) -> Result<()> {
+    let commit = serde_json::to_string(commit).expect("Unexpected json serialize error");
+    query!(
+        "UPDATE Tasks SET commit_metadata = ? WHERE task_id = ?",
+        commit,
+        task_id
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_set_task_commit_metadata"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn set_task_rerun_of(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+    rerun_of: &TaskId,
+) -> Result<()> {
+    query!(
+        "UPDATE Tasks SET rerun_of = ? WHERE task_id = ?",
+        rerun_of,
+        task_id
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_set_task_rerun_of"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+/// Returns the most recent finished task of the team that was built from the given tree
+#[instrument(skip_all)]
+pub(super) async fn fetch_task_with_tree_hash(
+    con: &mut SqliteConnection,
+    team_id: &TeamId,
+    tree_hash: &str,
+) -> Result<Option<TaskId>> {
+    query!(
+        r#"
+        SELECT task_id as "task_id!: TaskId"
+        FROM Tasks
+        WHERE team_id = ? AND tree_hash = ?
+        ORDER BY queue_time DESC
+        LIMIT 1
+        "#,
+        team_id,
+        tree_hash
+    )
+    .map(|it| it.task_id)
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_fetch_task_with_tree_hash"))
+    .await
+    .context(SqlxSnafu)
+}
+
+/// Returns when the task finished, or [None] if there is no finished task with that id
+#[instrument(skip_all)]
+pub(super) async fn fetch_task_end_time(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+) -> Result<Option<Timestamp>> {
+    query!("SELECT end_time FROM Tasks WHERE task_id = ?", task_id)
+        .map(|it| Timestamp::from_millisecond(it.end_time).expect("time is valid"))
+        .fetch_optional(con)
+        .instrument(info_span!("sqlx_fetch_task_end_time"))
+        .await
+        .context(SqlxSnafu)
+}
+
 #[instrument(skip_all)]
 pub(super) async fn get_task(
     con: impl Acquire<'_, Database = Sqlite>,
@@ -112,7 +227,10 @@ pub(super) async fn get_task(
             team_id as "team_id!: TeamId",
             revision as "revision_id!: String",
             commit_message as "commit_message!: String",
-            execution_id as "execution_id!: String"
+            execution_id as "execution_id!: String",
+            environment,
+            rerun_of,
+            commit_metadata
         FROM Tasks
         WHERE task_id = ?
         "#,
@@ -137,6 +255,13 @@ pub(super) async fn get_task(
         revision_id: task.revision_id,
         commit_message: task.commit_message,
         team_id: task.team_id.to_string(),
+        environment: task
+            .environment
+            .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error")),
+        rerun_of: task.rerun_of,
+        commit: task
+            .commit_metadata
+            .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error")),
     };
 
     let outdated_tests = get_outdated_tests(&mut con, task_id)
@@ -154,10 +279,15 @@ pub(super) async fn get_task(
         r#"
         SELECT
             test_id,
+            profile,
             compiler_exec_id as "compiler_exec_id!",
             binary_exec_id,
             status,
             provisional_for_category as "provisional_for_category?",
+            binary_size,
+            compile_duration_ms,
+            invocation,
+            diagnostics,
             (SELECT category FROM Tests WHERE id = test_id) as "category?"
         FROM TestResults
         WHERE task_id = ?"#,
@@ -183,6 +313,17 @@ pub(super) async fn get_task(
             category: test.category,
             output: execution_output,
             provisional_for_category: test.provisional_for_category,
+            profile: Some(test.profile).filter(|it| !it.is_empty()),
+            binary_size: test.binary_size.map(|it| it as u64),
+            compile_duration_ms: test.compile_duration_ms.map(|it| it as u64),
+            invocation: test
+                .invocation
+                .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error"))
+                .unwrap_or_default(),
+            diagnostics: test
+                .diagnostics
+                .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error"))
+                .unwrap_or_default(),
         })
     }
 
@@ -208,7 +349,7 @@ pub(super) async fn get_recent_tasks(
         SELECT
             task_id as "task_id!: TaskId"
         FROM Tasks
-        WHERE team_id = ?
+        WHERE team_id = ? AND bisect_id IS NULL
         ORDER BY start_time DESC
         LIMIT ?
         "#,
@@ -233,6 +374,333 @@ pub(super) async fn get_recent_tasks(
     Ok(finished_tasks)
 }
 
+/// Compares the test results of the head task with the ones of the base task. Tests are matched
+/// by their id and profile.
+#[instrument(skip_all)]
+pub(super) async fn compare_tasks(
+    con: impl Acquire<'_, Database = Sqlite>,
+    base: &TaskId,
+    head: &TaskId,
+) -> Result<TaskComparison> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let base_summary = get_task_summary(&mut con, base).await?;
+    let head_summary = get_task_summary(&mut con, head).await?;
+
+    let key = |test: &FinishedTestSummary| (test.test_id.clone(), test.profile.clone());
+    let base_tests = get_finished_test_summaries(&mut con, base)
+        .await?
+        .iter()
+        .map(|test| (key(test), test.output.clone()))
+        .collect::<HashMap<_, _>>();
+    let head_tests = get_finished_test_summaries(&mut con, head)
+        .await?
+        .iter()
+        .map(|test| (key(test), test.output.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut keys = base_tests
+        .keys()
+        .chain(head_tests.keys())
+        .cloned()
+        .collect::<Vec<_>>();
+    keys.sort_by(|(a_id, a_profile), (b_id, b_profile)| {
+        (a_id.to_string(), a_profile).cmp(&(b_id.to_string(), b_profile))
+    });
+    keys.dedup();
+
+    let mut regressed = Vec::new();
+    let mut newly_passing = Vec::new();
+    let mut changed = Vec::new();
+    for (test_id, profile) in keys {
+        let key = (test_id, profile);
+        let base = base_tests.get(&key).cloned();
+        let head = head_tests.get(&key).cloned();
+        let (test_id, profile) = key;
+        if base == head {
+            continue;
+        }
+
+        let target = match (&base, &head) {
+            (Some(ExecutionExitStatus::Success), Some(_)) => &mut regressed,
+            (_, Some(ExecutionExitStatus::Success)) => &mut newly_passing,
+            _ => &mut changed,
+        };
+        target.push(TestStatusChange {
+            test_id,
+            profile,
+            base,
+            head,
+        });
+    }
+
+    Ok(TaskComparison {
+        base: base_summary,
+        head: head_summary,
+        regressed,
+        newly_passing,
+        changed,
+    })
+}
+
+/// Returns the team's matching tasks on the page, newest first, and the total number of
+/// matching tasks
+#[instrument(skip_all)]
+pub(super) async fn search_tasks(
+    con: impl Acquire<'_, Database = Sqlite>,
+    team_id: &TeamId,
+    query: Option<&str>,
+    page: Page,
+) -> Result<(Vec<FinishedCompilerTaskSummary>, i64)> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let pattern = query.map(contains_pattern);
+    let tasks = query!(
+        r#"
+        SELECT
+            task_id as "task_id!: TaskId"
+        FROM Tasks
+        WHERE team_id = ? AND bisect_id IS NULL
+            AND (? IS NULL
+                OR task_id LIKE ? ESCAPE '\'
+                OR revision LIKE ? ESCAPE '\'
+                OR commit_message LIKE ? ESCAPE '\')
+        ORDER BY start_time DESC
+        LIMIT ? OFFSET ?
+        "#,
+        team_id,
+        pattern,
+        pattern,
+        pattern,
+        pattern,
+        page.limit,
+        page.offset
+    )
+    .map(|it| it.task_id)
+    .fetch_all(&mut *con)
+    .instrument(info_span!("sqlx_search_tasks_query"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let total = query!(
+        r#"
+        SELECT COUNT(*) as "count!: i64"
+        FROM Tasks
+        WHERE team_id = ? AND bisect_id IS NULL
+            AND (? IS NULL
+                OR task_id LIKE ? ESCAPE '\'
+                OR revision LIKE ? ESCAPE '\'
+                OR commit_message LIKE ? ESCAPE '\')
+        "#,
+        team_id,
+        pattern,
+        pattern,
+        pattern,
+        pattern
+    )
+    .map(|it| it.count)
+    .fetch_one(&mut *con)
+    .instrument(info_span!("sqlx_search_tasks_count"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let mut finished_tasks = Vec::new();
+    for task in tasks {
+        let task = get_task_summary(&mut con, &task)
+            .instrument(info_span!("sqlx_search_tasks_inner"))
+            .await?;
+        finished_tasks.push(task);
+    }
+
+    Ok((finished_tasks, total))
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_team_activity(
+    con: &mut SqliteConnection,
+    team_id: &TeamId,
+    since: Timestamp,
+) -> Result<Vec<TeamActivityDay>> {
+    let success_status = TestExecutionOutputType::Success.to_string();
+    let since = since.as_millisecond();
+    query_as!(
+        TeamActivityDay,
+        r#"
+        WITH TaskPassRates AS (
+            SELECT
+                date(Tasks.queue_time / 1000, 'unixepoch') as day,
+                COALESCE(
+                    (SELECT AVG(TestResults.status == ?)
+                     FROM TestResults
+                     WHERE TestResults.task_id = Tasks.task_id),
+                    0
+                ) as pass_rate
+            FROM Tasks
+            WHERE Tasks.team_id = ? AND Tasks.queue_time >= ? AND Tasks.bisect_id IS NULL
+        ),
+        Days AS (
+            SELECT day, COUNT(*) as submissions, MAX(pass_rate) as best_pass_rate
+            FROM TaskPassRates
+            GROUP BY day
+        )
+        SELECT
+            day as "day!: String",
+            submissions as "submissions!: i64",
+            best_pass_rate as "best_pass_rate!: f64",
+            best_pass_rate - LAG(best_pass_rate) OVER (ORDER BY day)
+                as "pass_rate_delta?: f64"
+        FROM Days
+        ORDER BY day
+        "#,
+        success_status,
+        team_id,
+        since
+    )
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_team_activity"))
+    .await
+    .context(SqlxSnafu)
+}
+
+/// The binary sizes and compile times of all tasks of the team that measured any, oldest first
+#[instrument(skip_all)]
+pub(super) async fn get_team_performance(
+    con: &mut SqliteConnection,
+    team_id: &TeamId,
+) -> Result<Vec<TaskPerformance>> {
+    query!(
+        r#"
+        WITH Measured AS (
+            SELECT
+                Tasks.task_id,
+                Tasks.revision,
+                Tasks.queue_time,
+                COUNT(TestResults.binary_size) as measured_tests,
+                AVG(TestResults.binary_size) as average_binary_size,
+                MAX(TestResults.binary_size) as max_binary_size,
+                AVG(TestResults.compile_duration_ms) as average_compile_duration_ms,
+                SUM(TestResults.compile_duration_ms) as total_compile_duration_ms
+            FROM Tasks
+            JOIN TestResults ON TestResults.task_id = Tasks.task_id
+            WHERE Tasks.team_id = ? AND Tasks.bisect_id IS NULL
+            GROUP BY Tasks.task_id
+            HAVING COUNT(TestResults.binary_size) > 0
+                OR COUNT(TestResults.compile_duration_ms) > 0
+        )
+        SELECT
+            task_id as "task_id!: TaskId",
+            revision as "revision!: String",
+            queue_time as "queue_time!: i64",
+            measured_tests as "measured_tests!: i64",
+            average_binary_size as "average_binary_size?: f64",
+            max_binary_size as "max_binary_size?: i64",
+            average_compile_duration_ms as "average_compile_duration_ms?: f64",
+            total_compile_duration_ms as "total_compile_duration_ms?: i64",
+            average_binary_size - LAG(average_binary_size) OVER (ORDER BY queue_time)
+                as "binary_size_delta?: f64",
+            average_compile_duration_ms - LAG(average_compile_duration_ms) OVER (ORDER BY queue_time)
+                as "compile_duration_delta_ms?: f64"
+        FROM Measured
+        ORDER BY queue_time
+        "#,
+        team_id
+    )
+    .map(|row| TaskPerformance {
+        task_id: row.task_id,
+        revision: row.revision,
+        queue_time: Timestamp::from_millisecond(row.queue_time).expect("time is valid"),
+        measured_tests: row.measured_tests,
+        average_binary_size: row.average_binary_size,
+        max_binary_size: row.max_binary_size,
+        average_compile_duration_ms: row.average_compile_duration_ms,
+        total_compile_duration_ms: row.total_compile_duration_ms,
+        binary_size_delta: row.binary_size_delta,
+        compile_duration_delta_ms: row.compile_duration_delta_ms,
+    })
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_team_performance"))
+    .await
+    .context(SqlxSnafu)
+}
+
+/// One page of the tasks queued in `[after, to)`, ordered by queue time and id. Pass the queue
+/// time and id of the last task of the previous page as `after` to continue, or the start of the
+/// range and an empty id for the first page.
+#[instrument(skip_all)]
+pub(super) async fn get_task_export_page(
+    con: &mut SqliteConnection,
+    after: (Timestamp, &TaskId),
+    to: Timestamp,
+    limit: u32,
+) -> Result<Vec<TaskExportRow>> {
+    let after_time = after.0.as_millisecond();
+    let after_id = after.1;
+    let to = to.as_millisecond();
+    let rows = query!(
+        r#"
+        WITH PageTasks AS (
+            SELECT task_id, team_id, queue_time, start_time, end_time, execution_id
+            FROM Tasks
+            WHERE (queue_time, task_id) > (?, ?) AND queue_time < ? AND bisect_id IS NULL
+            ORDER BY queue_time, task_id
+            LIMIT ?
+        ),
+        TaskTests AS (
+            SELECT TestResults.task_id, Tests.category, MIN(TestResults.status = 'Success') as passed
+            FROM TestResults
+            JOIN Tests ON Tests.id = TestResults.test_id
+            WHERE TestResults.task_id IN (SELECT task_id FROM PageTasks)
+            GROUP BY TestResults.task_id, TestResults.test_id
+        )
+        SELECT
+            PageTasks.task_id as "task_id!: TaskId",
+            PageTasks.team_id as "team_id!: TeamId",
+            PageTasks.queue_time as "queue_time!: i64",
+            PageTasks.start_time as "start_time!: i64",
+            PageTasks.end_time as "end_time!: i64",
+            ExecutionResults.result as "build_status?: String",
+            TaskTests.category as "category?: String",
+            SUM(TaskTests.passed) as "passed?: i64",
+            COUNT(TaskTests.passed) as "total!: i64"
+        FROM PageTasks
+        LEFT JOIN ExecutionResults ON ExecutionResults.execution_id = PageTasks.execution_id
+        LEFT JOIN TaskTests ON TaskTests.task_id = PageTasks.task_id
+        GROUP BY PageTasks.task_id, TaskTests.category
+        ORDER BY PageTasks.queue_time, PageTasks.task_id
+        "#,
+        after_time,
+        after_id,
+        to,
+        limit
+    )
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_task_export_page"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let mut tasks: Vec<TaskExportRow> = Vec::new();
+    for row in rows {
+        if tasks.last().is_none_or(|it| it.task_id != row.task_id) {
+            tasks.push(TaskExportRow {
+                task_id: row.task_id,
+                team_id: row.team_id,
+                queue_time: Timestamp::from_millisecond(row.queue_time).expect("time is valid"),
+                start_time: Timestamp::from_millisecond(row.start_time).expect("time is valid"),
+                end_time: Timestamp::from_millisecond(row.end_time).expect("time is valid"),
+                build_status: row.build_status,
+                category_counts: HashMap::new(),
+            });
+        }
+        // Tasks without tests have a single row without a category
+        if let (Some(task), Some(category)) = (tasks.last_mut(), row.category) {
+            task.category_counts
+                .insert(category, (row.passed.unwrap_or(0), row.total));
+        }
+    }
+
+    Ok(tasks)
+}
+
 #[instrument(skip_all)]
 pub(super) async fn get_test_execution(
     con: &mut SqliteConnection,
@@ -258,6 +726,102 @@ async fn get_execution(con: &mut SqliteConnection, execution_id: &str) -> Result
     Ok(execution)
 }
 
+/// Returns at most `limit` bytes of the build output of the task, starting at byte `offset`, and
+/// the total length of that output in bytes. Only the requested bytes are read, so large logs can
+/// be loaded piece by piece.
+#[instrument(skip_all)]
+pub(super) async fn get_build_log_chunk(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+    stream: OutputStream,
+    offset: u64,
+    limit: u64,
+) -> Result<(Vec<u8>, u64)> {
+    let stderr = stream == OutputStream::Stderr;
+    let offset = offset as i64;
+    let limit = limit as i64;
+    let chunk = query!(
+        r#"
+        SELECT
+            length(Log.output) as "total!: i64",
+            substr(Log.output, ? + 1, ?) as "chunk!: Vec<u8>"
+        FROM (
+            SELECT
+                CAST(CASE WHEN ? THEN ER.stderr ELSE ER.stdout END AS BLOB) as output
+            FROM Tasks
+            JOIN ExecutionResults ER ON ER.execution_id = Tasks.execution_id
+            WHERE Tasks.task_id = ?
+        ) as Log
+        "#,
+        offset,
+        limit,
+        stderr,
+        task_id
+    )
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_get_build_log_chunk"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let Some(chunk) = chunk else {
+        return Err(WebError::not_found(location!()));
+    };
+
+    Ok((chunk.chunk, chunk.total as u64))
+}
+
+/// Returns the whole output of an execution of the task and the test it belongs to, which is none
+/// for the build. Executions of other tasks are not found.
+#[instrument(skip_all)]
+pub(super) async fn get_full_output(
+    con: &mut SqliteConnection,
+    task_id: &TaskId,
+    execution_id: &str,
+    stream: OutputStream,
+) -> Result<Option<(Option<TestId>, FullOutput)>> {
+    let stderr = stream == OutputStream::Stderr;
+    let output = query!(
+        r#"
+        SELECT
+            CASE WHEN ? THEN ER.stderr ELSE ER.stdout END as "text!: String",
+            CASE WHEN ? THEN ER.full_stderr_path ELSE ER.full_stdout_path END as "path?: String",
+            (
+                SELECT test_id FROM TestResults
+                WHERE task_id = ?
+                    AND (compiler_exec_id = ER.execution_id OR binary_exec_id = ER.execution_id)
+                LIMIT 1
+            ) as "test_id?: TestId",
+            EXISTS(
+                SELECT 1 FROM Tasks WHERE task_id = ? AND execution_id = ER.execution_id
+            ) as "is_build!: bool"
+        FROM ExecutionResults ER
+        WHERE ER.execution_id = ?
+        "#,
+        stderr,
+        stderr,
+        task_id,
+        task_id,
+        execution_id
+    )
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_get_full_output"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let Some(output) = output else {
+        return Ok(None);
+    };
+    if output.test_id.is_none() && !output.is_build {
+        return Ok(None);
+    }
+    let full_output = match output.path {
+        Some(path) => FullOutput::File(path.into()),
+        None => FullOutput::Inline(output.text),
+    };
+
+    Ok(Some((output.test_id, full_output)))
+}
+
 #[instrument(skip_all)]
 async fn fetch_execution(
     con: &mut SqliteConnection,
@@ -271,9 +835,13 @@ async fn fetch_execution(
             stderr,
             accumulated_errors,
             error,
+            infrastructure_log,
+            recording,
+            resource_usage,
             result as "result!: ExecutionExitStatus",
             duration_ms as "duration_ms!: u64",
-            exit_code as "exit_code?: i32"
+            exit_code as "exit_code?: i32",
+            (full_stdout_path IS NOT NULL OR full_stderr_path IS NOT NULL) as "shortened!: bool"
         FROM ExecutionResults
         WHERE execution_id = ?"#,
         execution_id
@@ -286,12 +854,22 @@ async fn fetch_execution(
     let Some(execution) = execution else {
         return Ok(None);
     };
+    let recording = execution
+        .recording
+        .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error"));
+    let resource_usage = execution
+        .resource_usage
+        .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error"));
+    let full_output_id = execution.shortened.then(|| execution_id.to_string());
 
     Ok(Some(match execution.result {
         ExecutionExitStatus::Aborted => ExecutionOutput::Aborted(AbortedExecution {
             stdout: execution.stdout,
             stderr: execution.stderr,
             runtime: Duration::from_millis(execution.duration_ms),
+            infrastructure_log: execution.infrastructure_log,
+            recording,
+            full_output_id,
         }),
         ExecutionExitStatus::Error => ExecutionOutput::Error(InternalError {
             message: execution.error.unwrap_or("N/A".to_string()),
@@ -303,6 +881,10 @@ async fn fetch_execution(
                 stderr: execution.stderr,
                 runtime: Duration::from_millis(execution.duration_ms),
                 exit_status: execution.exit_code,
+                infrastructure_log: execution.infrastructure_log,
+                recording,
+                resource_usage,
+                full_output_id,
             },
             accumulated_errors: execution.accumulated_errors,
         },
@@ -311,12 +893,20 @@ async fn fetch_execution(
             stderr: execution.stderr,
             runtime: Duration::from_millis(execution.duration_ms),
             exit_status: execution.exit_code,
+            infrastructure_log: execution.infrastructure_log,
+            recording,
+            resource_usage,
+            full_output_id,
         }),
         ExecutionExitStatus::Timeout => ExecutionOutput::Timeout(FinishedExecution {
             stdout: execution.stdout,
             stderr: execution.stderr,
             runtime: Duration::from_millis(execution.duration_ms),
             exit_status: execution.exit_code,
+            infrastructure_log: execution.infrastructure_log,
+            recording,
+            resource_usage,
+            full_output_id,
         }),
     }))
 }
@@ -324,11 +914,12 @@ async fn fetch_execution(
 #[instrument(skip_all)]
 pub(super) async fn record_test_execution(
     con: &mut SqliteConnection,
+    storage: Option<&OutputStorage>,
     e: &TestExecutionOutput,
 ) -> Result<(String, Option<String>)> {
-    let compiler_exec_id = record_execution_output(con, e.compiler_output()).await?;
+    let compiler_exec_id = record_execution_output(con, storage, e.compiler_output()).await?;
     let binary_exec_id = match &e.binary_output() {
-        Some(output) => Some(record_execution_output(con, output).await?),
+        Some(output) => Some(record_execution_output(con, storage, output).await?),
         None => None,
     };
 
@@ -338,12 +929,13 @@ pub(super) async fn record_test_execution(
 #[instrument(skip_all)]
 pub(super) async fn record_execution_output(
     con: &mut SqliteConnection,
+    storage: Option<&OutputStorage>,
     e: &ExecutionOutput,
 ) -> Result<String> {
     let execution_id = uuid::Uuid::new_v4().to_string();
 
     match e {
-        ExecutionOutput::Aborted(e) => record_aborted(con, &execution_id, e).await?,
+        ExecutionOutput::Aborted(e) => record_aborted(con, storage, &execution_id, e).await?,
         ExecutionOutput::Error(e) => record_internal_error(con, &execution_id, e).await?,
         ExecutionOutput::Failure {
             execution,
@@ -351,6 +943,7 @@ pub(super) async fn record_execution_output(
         } => {
             record_finished_execution(
                 con,
+                storage,
                 &execution_id,
                 execution,
                 accumulated_errors,
@@ -359,12 +952,26 @@ pub(super) async fn record_execution_output(
             .await?
         }
         ExecutionOutput::Success(e) => {
-            record_finished_execution(con, &execution_id, e, &None, ExecutionExitStatus::Success)
-                .await?
+            record_finished_execution(
+                con,
+                storage,
+                &execution_id,
+                e,
+                &None,
+                ExecutionExitStatus::Success,
+            )
+            .await?
         }
         ExecutionOutput::Timeout(e) => {
-            record_finished_execution(con, &execution_id, e, &None, ExecutionExitStatus::Timeout)
-                .await?
+            record_finished_execution(
+                con,
+                storage,
+                &execution_id,
+                e,
+                &None,
+                ExecutionExitStatus::Timeout,
+            )
+            .await?
         }
     }
 
@@ -380,13 +987,19 @@ async fn record_task(
     build_id: &str,
     queue_time: i64,
 ) -> Result<()> {
+    let environment = result
+        .info()
+        .environment
+        .as_ref()
+        .map(|it| serde_json::to_string(it).expect("Unexpected json serialize error"));
+
     query!(
         r#"
         INSERT INTO Tasks
             (task_id, team_id, revision, commit_message, start_time, end_time, execution_id,
-             queue_time)
+             queue_time, environment, bisect_id)
         VALUES
-            (?, ?, ?, ?, ?, ?, ?, ?)
+            (?, ?, ?, ?, ?, ?, ?, ?, ?, (SELECT bisect_id FROM BisectSteps WHERE task_id = ?))
         "#,
         result.info().task_id,
         result.info().team_id,
@@ -395,7 +1008,9 @@ async fn record_task(
         start_time,
         end_time,
         build_id,
-        queue_time
+        queue_time,
+        environment,
+        result.info().task_id
     )
     .execute(&mut *con)
     .instrument(info_span!("sqlx_add_finished_insert_task"))
@@ -408,27 +1023,50 @@ async fn record_task(
 #[instrument(skip_all)]
 async fn record_finished_execution(
     con: &mut SqliteConnection,
+    storage: Option<&OutputStorage>,
     execution_id: &str,
     e: &FinishedExecution,
     accumulated_errors: &Option<String>,
     status: ExecutionExitStatus,
 ) -> Result<()> {
     let runtime = e.runtime.as_millis() as i64;
+    let stdout = store_output(storage, execution_id, OutputStream::Stdout, &e.stdout).await?;
+    let stderr = store_output(storage, execution_id, OutputStream::Stderr, &e.stderr).await?;
+    let stdout_path = stdout
+        .full_output
+        .map(|it| it.to_string_lossy().to_string());
+    let stderr_path = stderr
+        .full_output
+        .map(|it| it.to_string_lossy().to_string());
+    let recording = e
+        .recording
+        .as_ref()
+        .map(|it| serde_json::to_string(it).expect("Unexpected json serialize error"));
+    let resource_usage = e
+        .resource_usage
+        .as_ref()
+        .map(|it| serde_json::to_string(it).expect("Unexpected json serialize error"));
 
     query!(
         "INSERT INTO ExecutionResults
-            (execution_id, stdout, stderr, error, accumulated_errors, result, duration_ms, exit_code)
+            (execution_id, stdout, stderr, error, accumulated_errors, result, duration_ms, exit_code,
+             infrastructure_log, recording, resource_usage, full_stdout_path, full_stderr_path)
          VALUES
-            (?, ?, ?, ?, ?, ?, ?, ?)
+            (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         ",
         execution_id,
-        e.stdout,
-        e.stderr,
+        stdout.text,
+        stderr.text,
         None::<&str>,
         accumulated_errors,
         status,
         runtime,
-        e.exit_status
+        e.exit_status,
+        e.infrastructure_log,
+        recording,
+        resource_usage,
+        stdout_path,
+        stderr_path
     )
     .execute(&mut *con)
     .instrument(info_span!("sqlx_record_finished_execution"))
@@ -437,6 +1075,22 @@ async fn record_finished_execution(
     Ok(())
 }
 
+/// Shortens the output if it is too large for the configured [OutputStorage]
+async fn store_output(
+    storage: Option<&OutputStorage>,
+    execution_id: &str,
+    stream: OutputStream,
+    text: &str,
+) -> Result<StoredOutput> {
+    match storage {
+        Some(storage) => Ok(storage.store(execution_id, stream, text).await?),
+        None => Ok(StoredOutput {
+            text: text.to_string(),
+            full_output: None,
+        }),
+    }
+}
+
 #[instrument(skip_all)]
 async fn record_internal_error(
     con: &mut SqliteConnection,
@@ -468,23 +1122,41 @@ async fn record_internal_error(
 #[instrument(skip_all)]
 async fn record_aborted(
     con: &mut SqliteConnection,
+    storage: Option<&OutputStorage>,
     execution_id: &str,
     e: &AbortedExecution,
 ) -> Result<()> {
     let runtime = e.runtime.as_millis() as i64;
+    let stdout = store_output(storage, execution_id, OutputStream::Stdout, &e.stdout).await?;
+    let stderr = store_output(storage, execution_id, OutputStream::Stderr, &e.stderr).await?;
+    let stdout_path = stdout
+        .full_output
+        .map(|it| it.to_string_lossy().to_string());
+    let stderr_path = stderr
+        .full_output
+        .map(|it| it.to_string_lossy().to_string());
+    let recording = e
+        .recording
+        .as_ref()
+        .map(|it| serde_json::to_string(it).expect("Unexpected json serialize error"));
     query!(
         "INSERT INTO ExecutionResults
-            (execution_id, stdout, stderr, error, result, duration_ms, exit_code)
+            (execution_id, stdout, stderr, error, result, duration_ms, exit_code, infrastructure_log,
+             recording, full_stdout_path, full_stderr_path)
         VALUES
-            (?, ?, ?, ?, ?, ?, ?)
+            (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         ",
         execution_id,
-        e.stdout,
-        e.stderr,
+        stdout.text,
+        stderr.text,
         None::<&str>,
         ExecutionExitStatus::Aborted,
         runtime,
-        None::<i32>
+        None::<i32>,
+        e.infrastructure_log,
+        recording,
+        stdout_path,
+        stderr_path
     )
     .execute(&mut *con)
     .instrument(info_span!("sqlx_record_aborted"))
@@ -508,7 +1180,7 @@ pub(super) async fn get_top_task_per_team(
                 COUNT(test_id) as "passed_count"
             FROM TestResults
             JOIN Tasks ON Tasks.task_id = TestResults.task_id
-            WHERE TestResults.status = ?
+            WHERE TestResults.status = ? AND Tasks.bisect_id IS NULL
             GROUP BY Tasks.task_id
         )
         SELECT
@@ -553,6 +1225,9 @@ async fn get_task_summary(
             revision as "revision_id!: String",
             commit_message as "commit_message!: String",
             execution_id as "execution_id!: String",
+            environment,
+            rerun_of,
+            commit_metadata,
             (
                 SELECT result FROM ExecutionResults ER WHERE ER.execution_id = Tasks.execution_id
             ) as "build_result!: ExecutionExitStatus"
@@ -579,6 +1254,13 @@ async fn get_task_summary(
         revision_id: task.revision_id,
         commit_message: task.commit_message,
         team_id: task.team_id.to_string(),
+        environment: task
+            .environment
+            .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error")),
+        rerun_of: task.rerun_of,
+        commit: task
+            .commit_metadata
+            .map(|it| serde_json::from_str(&it).expect("Unexpected json serialize error")),
     };
 
     if task.build_result != ExecutionExitStatus::Success {
@@ -605,6 +1287,7 @@ pub async fn get_finished_test_summaries(
     con: &mut SqliteConnection,
     task_id: &TaskId,
 ) -> Result<Vec<FinishedTestSummary>> {
+    let skipped_status = TestExecutionOutputType::Skipped.to_string();
     query!(
         r#"
         SELECT
@@ -617,17 +1300,22 @@ pub async fn get_finished_test_summaries(
                 SELECT result FROM ExecutionResults
                 WHERE execution_id = compiler_exec_id
             ) as "compiler_status!: ExecutionExitStatus",
+            status = ? as "skipped!: bool",
             provisional_for_category as "provisional_for_category?",
-            (SELECT category FROM Tests WHERE id = test_id) as "category?"
+            (SELECT category FROM Tests WHERE id = test_id) as "category?",
+            profile
         FROM TestResults
         WHERE task_id = ?"#,
+        skipped_status,
         task_id
     )
     .map(|it| FinishedTestSummary {
         test_id: it.test_id,
         output: it.binary_status.unwrap_or(it.compiler_status),
+        skipped: it.skipped,
         provisional_for_category: it.provisional_for_category,
         category: it.category,
+        profile: Some(it.profile).filter(|it| !it.is_empty()),
     })
     .fetch_all(&mut *con)
     .instrument(info_span!("sqlx_get_task_summary_tests"))
@@ -642,7 +1330,13 @@ async fn get_outdated_tests(con: &mut SqliteConnection, task_id: &TaskId) -> Res
         FROM TestResults
         JOIN Tests ON Tests.id = TestResults.test_id
         JOIN Tasks ON Tasks.task_id = TestResults.task_id
-        WHERE Tasks.task_id = ? AND Tests.last_updated > Tasks.queue_time
+        LEFT JOIN CategoryFilesets ON CategoryFilesets.course_id = Tests.course_id
+            AND CategoryFilesets.category = Tests.category
+        WHERE Tasks.task_id = ?
+            AND (
+                Tests.last_updated > Tasks.queue_time
+                OR CategoryFilesets.updated_at > Tasks.queue_time
+            )
         "#,
         task_id
     )
@@ -657,6 +1351,7 @@ async fn get_outdated_tests(con: &mut SqliteConnection, task_id: &TaskId) -> Res
 pub(super) async fn get_final_submitted_task(
     con: impl Acquire<'_, Database = Sqlite>,
     team_id: &TeamId,
+    course: &CourseId,
     category: &str,
     meta: &TestCategory,
     respect_finalized: bool,
@@ -664,7 +1359,7 @@ pub(super) async fn get_final_submitted_task(
     let mut con = con.begin().await.context(SqlxSnafu)?;
 
     if respect_finalized {
-        let finalized = fetch_finalized_task(&mut con, team_id, category)
+        let finalized = fetch_finalized_task(&mut con, team_id, course, category)
             .instrument(info_span!("sqlx_get_final_submitted_task"))
             .await?;
 
@@ -680,9 +1375,10 @@ pub(super) async fn get_final_submitted_task(
             user_id as "user_id!: UserId",
             update_time
         FROM ManuallySubmittedTasks
-        WHERE team_id = ? AND category = ? AND task_id IS NOT NULL
+        WHERE team_id = ? AND course_id = ? AND category = ? AND task_id IS NOT NULL
         "#,
         team_id,
+        course,
         category
     )
     .fetch_optional(&mut *con)
@@ -702,7 +1398,7 @@ pub(super) async fn get_final_submitted_task(
         }));
     }
 
-    let task = get_top_task_for_team_and_category(&mut con, team_id, category, meta)
+    let task = get_top_task_for_team_and_category(&mut con, team_id, course, category, meta)
         .instrument(info_span!("sqlx_get_final_submitted_task_inner"))
         .await?;
 
@@ -713,6 +1409,7 @@ pub(super) async fn get_final_submitted_task(
 pub(super) async fn set_final_submitted_task(
     con: impl Acquire<'_, Database = Sqlite>,
     team_id: &TeamId,
+    course: &CourseId,
     user_id: &UserId,
     task_id: &TaskId,
     categories: impl Iterator<Item = &str>,
@@ -740,15 +1437,16 @@ pub(super) async fn set_final_submitted_task(
         query!(
             r#"
             INSERT INTO ManuallySubmittedTasks
-                (team_id, category, task_id, user_id, update_time)
+                (team_id, course_id, category, task_id, user_id, update_time)
             VALUES
-                (?, ?, ?, ?, ?)
+                (?, ?, ?, ?, ?, ?)
             ON CONFLICT DO UPDATE SET
                 task_id = excluded.task_id,
                 user_id = excluded.user_id,
                 update_time = excluded.update_time
             "#,
             team_id,
+            course,
             category,
             task_id,
             user_id,
@@ -769,6 +1467,7 @@ pub(super) async fn set_final_submitted_task(
 async fn get_top_task_for_team_and_category(
     con: &mut SqliteConnection,
     team_id: &TeamId,
+    course: &CourseId,
     category: &str,
     meta: &TestCategory,
 ) -> Result<Option<FinishedCompilerTaskSummary>> {
@@ -788,6 +1487,8 @@ async fn get_top_task_for_team_and_category(
                 Tasks.team_id = ?
             AND TestResults.status = ?
             AND Tasks.queue_time BETWEEN ? AND ?
+            AND Tasks.bisect_id IS NULL
+            AND Tests.course_id = ?
             AND Tests.category = ?
             AND (Tests.provisional_for_category IS NULL OR Tests.provisional_for_category != ?)
         GROUP BY Tasks.task_id
@@ -798,6 +1499,7 @@ async fn get_top_task_for_team_and_category(
         ExecutionExitStatus::Success,
         starts_at,
         ends_at,
+        course,
         category,
         category,
     )
@@ -817,19 +1519,21 @@ async fn get_top_task_for_team_and_category(
 pub(super) async fn finalize_submission(
     con: &mut SqliteConnection,
     team_id: &TeamId,
+    course: &CourseId,
     task_id: &TaskId,
     category: &str,
 ) -> Result<()> {
     query!(
         r#"
         INSERT INTO FinalizedSubmittedTasks
-            (team_id, task_id, category)
+            (team_id, course_id, task_id, category)
         VALUES
-            (?, ?, ?)
+            (?, ?, ?, ?)
         ON CONFLICT DO UPDATE SET
             task_id = excluded.task_id
         "#,
         team_id,
+        course,
         task_id,
         category
     )
@@ -845,15 +1549,17 @@ pub(super) async fn finalize_submission(
 pub(super) async fn fetch_finalized_task_id(
     con: &mut SqliteConnection,
     team_id: &TeamId,
+    course: &CourseId,
     category: &str,
 ) -> Result<Option<TaskId>> {
     query!(
         r#"
         SELECT task_id as "task_id: TaskId"
         FROM FinalizedSubmittedTasks
-        WHERE team_id = ? AND category = ?
+        WHERE team_id = ? AND course_id = ? AND category = ?
         "#,
         team_id,
+        course,
         category
     )
     .map(|it| it.task_id)
@@ -867,9 +1573,10 @@ pub(super) async fn fetch_finalized_task_id(
 pub(super) async fn fetch_finalized_task(
     con: &mut SqliteConnection,
     team_id: &TeamId,
+    course: &CourseId,
     category: &str,
 ) -> Result<Option<FinishedCompilerTaskSummary>> {
-    let task_id = fetch_finalized_task_id(con, team_id, category)
+    let task_id = fetch_finalized_task_id(con, team_id, course, category)
         .instrument(info_span!("sqlx_fetch_finalized_task_id_inner"))
         .await?;
 
@@ -0,0 +1,217 @@
+use crate::error::{Result, SqlxSnafu};
+use crate::types::{
+    TaskNotificationChannel, TaskNotificationDelivery, TaskNotificationDeliveryId,
+    TaskNotificationSettings, TeamId, UserId,
+};
+use jiff::Timestamp;
+use snafu::ResultExt;
+use sqlx::{SqliteConnection, query};
+use tracing::{Instrument, info_span, instrument};
+
+#[instrument(skip_all)]
+pub(super) async fn get_task_notification_settings(
+    con: &mut SqliteConnection,
+    user: &UserId,
+) -> Result<Option<TaskNotificationSettings>> {
+    query!(
+        r#"
+        SELECT
+            on_success as "on_success!: bool",
+            on_failure as "on_failure!: bool",
+            email,
+            webhook_url
+        FROM TaskNotificationSettings
+        WHERE user_id = ?
+        "#,
+        user
+    )
+    .map(|row| TaskNotificationSettings {
+        on_success: row.on_success,
+        on_failure: row.on_failure,
+        email: row.email,
+        webhook_url: row.webhook_url,
+    })
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_get_task_notification_settings"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn set_task_notification_settings(
+    con: &mut SqliteConnection,
+    user: &UserId,
+    settings: &TaskNotificationSettings,
+) -> Result<()> {
+    query!(
+        r#"
+        INSERT INTO TaskNotificationSettings
+            (user_id, on_success, on_failure, email, webhook_url)
+        VALUES
+            (?, ?, ?, ?, ?)
+        ON CONFLICT (user_id) DO UPDATE
+            SET on_success = excluded.on_success,
+                on_failure = excluded.on_failure,
+                email = excluded.email,
+                webhook_url = excluded.webhook_url
+        "#,
+        user,
+        settings.on_success,
+        settings.on_failure,
+        settings.email,
+        settings.webhook_url
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_set_task_notification_settings"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+/// Returns the settings of every member of the team that configured any
+#[instrument(skip_all)]
+pub(super) async fn get_task_notification_settings_for_team(
+    con: &mut SqliteConnection,
+    team: &TeamId,
+) -> Result<Vec<(UserId, TaskNotificationSettings)>> {
+    query!(
+        r#"
+        SELECT
+            TaskNotificationSettings.user_id as "user_id!: UserId",
+            on_success as "on_success!: bool",
+            on_failure as "on_failure!: bool",
+            email,
+            webhook_url
+        FROM TaskNotificationSettings
+        JOIN Users ON Users.id = TaskNotificationSettings.user_id
+        WHERE Users.team = ?
+        "#,
+        team
+    )
+    .map(|row| {
+        (
+            row.user_id,
+            TaskNotificationSettings {
+                on_success: row.on_success,
+                on_failure: row.on_failure,
+                email: row.email,
+                webhook_url: row.webhook_url,
+            },
+        )
+    })
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_task_notification_settings_for_team"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn add_task_notification_delivery(
+    con: &mut SqliteConnection,
+    delivery: &TaskNotificationDelivery,
+) -> Result<()> {
+    let payload =
+        serde_json::to_string(&delivery.payload).expect("Unexpected json serialize error");
+    let next_attempt = delivery.next_attempt.as_millisecond();
+    query!(
+        r#"
+        INSERT INTO TaskNotificationDeliveries
+            (id, user_id, task_id, channel, target, payload, attempts, next_attempt)
+        VALUES
+            (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        delivery.id,
+        delivery.user,
+        delivery.payload.task_id,
+        delivery.channel,
+        delivery.target,
+        payload,
+        delivery.attempts,
+        next_attempt
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_add_task_notification_delivery"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+/// Returns the deliveries whose next attempt is not in the future, oldest first
+#[instrument(skip_all)]
+pub(super) async fn get_due_task_notification_deliveries(
+    con: &mut SqliteConnection,
+    now: Timestamp,
+) -> Result<Vec<TaskNotificationDelivery>> {
+    let now = now.as_millisecond();
+    query!(
+        r#"
+        SELECT
+            id as "id!: TaskNotificationDeliveryId",
+            user_id as "user_id!: UserId",
+            channel as "channel!: TaskNotificationChannel",
+            target,
+            payload,
+            attempts as "attempts!: u32",
+            next_attempt
+        FROM TaskNotificationDeliveries
+        WHERE next_attempt <= ?
+        ORDER BY next_attempt
+        "#,
+        now
+    )
+    .map(|row| TaskNotificationDelivery {
+        id: row.id,
+        user: row.user_id,
+        channel: row.channel,
+        target: row.target,
+        payload: serde_json::from_str(&row.payload).expect("Unexpected json serialize error"),
+        attempts: row.attempts,
+        next_attempt: Timestamp::from_millisecond(row.next_attempt).expect("time is valid"),
+    })
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_due_task_notification_deliveries"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn delete_task_notification_delivery(
+    con: &mut SqliteConnection,
+    id: &TaskNotificationDeliveryId,
+) -> Result<()> {
+    query!("DELETE FROM TaskNotificationDeliveries WHERE id = ?", id)
+        .execute(con)
+        .instrument(info_span!("sqlx_delete_task_notification_delivery"))
+        .await
+        .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn reschedule_task_notification_delivery(
+    con: &mut SqliteConnection,
+    id: &TaskNotificationDeliveryId,
+    next_attempt: Timestamp,
+    error: &str,
+) -> Result<()> {
+    let next_attempt = next_attempt.as_millisecond();
+    query!(
+        r#"
+        UPDATE TaskNotificationDeliveries
+        SET attempts = attempts + 1, next_attempt = ?, last_error = ?
+        WHERE id = ?
+        "#,
+        next_attempt,
+        error,
+        id
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_reschedule_task_notification_delivery"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
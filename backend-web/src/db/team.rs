@@ -1,16 +1,19 @@
-use crate::config::TeamEntry;
+use crate::config::{TeamEntry, TeamSync};
 use crate::error::{Result, SqlxSnafu, WebError};
-use crate::types::{Team, TeamId, TeamIntegrationToken, User, UserRole};
+use crate::types::{
+    CourseId, ManagedTeam, Team, TeamId, TeamIntegrationToken, TeamInvite, User, UserId, UserRole,
+};
+use jiff::Timestamp;
 use snafu::{ResultExt, location};
 use sqlx::{Acquire, Sqlite, SqliteConnection, query, query_as};
-use std::collections::HashSet;
-use tracing::{Instrument, info, info_span, instrument, warn};
+use std::collections::{HashMap, HashSet};
+use tracing::{Instrument, info, info_span, instrument};
 
 #[instrument(skip_all)]
 pub(super) async fn get_team(con: &mut SqliteConnection, team_id: &TeamId) -> Result<Team> {
     let team = query_as!(
         Team,
-        r#"SELECT id as "id!", display_name FROM Teams WHERE id = ?"#,
+        r#"SELECT id as "id!", display_name, course_id as "course!: CourseId" FROM Teams WHERE id = ?"#,
         team_id
     )
     .fetch_optional(con)
@@ -26,11 +29,14 @@ pub(super) async fn get_team(con: &mut SqliteConnection, team_id: &TeamId) -> Re
 
 #[instrument(skip_all)]
 pub(super) async fn get_teams(con: &mut SqliteConnection) -> Result<Vec<Team>> {
-    query_as!(Team, r#"SELECT id as "id!", display_name FROM Teams"#,)
-        .fetch_all(con)
-        .instrument(info_span!("sqlx_get_teams"))
-        .await
-        .context(SqlxSnafu)
+    query_as!(
+        Team,
+        r#"SELECT id as "id!", display_name, course_id as "course!: CourseId" FROM Teams"#,
+    )
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_teams"))
+    .await
+    .context(SqlxSnafu)
 }
 
 #[instrument(skip_all)]
@@ -84,13 +90,442 @@ pub(super) async fn fetch_team_by_integration_token(
     .context(SqlxSnafu)
 }
 
+#[instrument(skip_all)]
+pub(super) async fn get_team_membership(
+    con: &mut SqliteConnection,
+    user_id: &UserId,
+) -> Result<Option<(TeamId, UserRole)>> {
+    query!(
+        r#"
+        SELECT Teams.id as "id!: TeamId", Teams.is_admin
+        FROM TeamMembers
+        JOIN Teams ON TeamMembers.team_id = Teams.id
+        WHERE TeamMembers.user_id = ?
+        "#,
+        user_id
+    )
+    .map(|it| (it.id, role_of(it.is_admin)))
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_get_team_membership"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_managed_teams(con: &mut SqliteConnection) -> Result<Vec<ManagedTeam>> {
+    let teams = query!(
+        r#"
+        SELECT id as "id!: TeamId", display_name, is_admin, course_id as "course_id!: CourseId"
+        FROM Teams
+        "#
+    )
+    .fetch_all(&mut *con)
+    .instrument(info_span!("sqlx_get_managed_teams"))
+    .await
+    .context(SqlxSnafu)?;
+    let mut members: HashMap<TeamId, Vec<UserId>> = HashMap::new();
+    for member in query!(
+        r#"SELECT user_id as "user_id!: UserId", team_id as "team_id!: TeamId" FROM TeamMembers"#
+    )
+    .fetch_all(&mut *con)
+    .instrument(info_span!("sqlx_get_managed_teams_members"))
+    .await
+    .context(SqlxSnafu)?
+    {
+        members
+            .entry(member.team_id)
+            .or_default()
+            .push(member.user_id);
+    }
+
+    Ok(teams
+        .into_iter()
+        .map(|team| ManagedTeam {
+            members: members.remove(&team.id).unwrap_or_default(),
+            id: team.id,
+            display_name: team.display_name,
+            is_admin: team.is_admin,
+            course: team.course_id,
+        })
+        .collect())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_managed_team(
+    con: &mut SqliteConnection,
+    team_id: &TeamId,
+) -> Result<ManagedTeam> {
+    let team = get_team(&mut *con, team_id).await?;
+    let is_admin = query!("SELECT is_admin FROM Teams WHERE id = ?", team_id)
+        .map(|it| it.is_admin)
+        .fetch_one(&mut *con)
+        .instrument(info_span!("sqlx_get_managed_team"))
+        .await
+        .context(SqlxSnafu)?;
+    let members = query!(
+        r#"SELECT user_id as "user_id!: UserId" FROM TeamMembers WHERE team_id = ?"#,
+        team_id
+    )
+    .map(|it| it.user_id)
+    .fetch_all(&mut *con)
+    .instrument(info_span!("sqlx_get_managed_team_members"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(ManagedTeam {
+        id: team.id,
+        display_name: team.display_name,
+        is_admin,
+        course: team.course,
+        members,
+    })
+}
+
+#[instrument(skip_all)]
+pub(super) async fn create_team(
+    con: impl Acquire<'_, Database = Sqlite>,
+    team: &ManagedTeam,
+) -> Result<ManagedTeam> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let exists = query!("SELECT id FROM Teams WHERE id = ?", team.id)
+        .fetch_optional(&mut *con)
+        .instrument(info_span!("sqlx_create_team_exists"))
+        .await
+        .context(SqlxSnafu)?
+        .is_some();
+    if exists {
+        return Err(WebError::named_bad_request(
+            format!("team `{}` already exists", team.id),
+            location!(),
+        ));
+    }
+
+    if super::course::fetch_course(&mut con, &team.course)
+        .await?
+        .is_none()
+    {
+        return Err(WebError::named_not_found(
+            format!("course `{}`", team.course),
+            location!(),
+        ));
+    }
+    insert_team(
+        &mut con,
+        &team.id,
+        &team.display_name,
+        team.is_admin,
+        &team.course,
+    )
+    .await?;
+    for member in &team.members {
+        assign_member(&mut con, &team.id, team.is_admin, member).await?;
+    }
+    let team = get_managed_team(&mut con, &team.id).await?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(team)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn patch_team(
+    con: impl Acquire<'_, Database = Sqlite>,
+    team_id: &TeamId,
+    display_name: Option<&str>,
+    is_admin: Option<bool>,
+    course: Option<&CourseId>,
+) -> Result<ManagedTeam> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+    let team = get_managed_team(&mut con, team_id).await?;
+
+    let display_name = display_name.unwrap_or(&team.display_name);
+    let is_admin = is_admin.unwrap_or(team.is_admin);
+    let course = course.unwrap_or(&team.course);
+    if super::course::fetch_course(&mut con, course)
+        .await?
+        .is_none()
+    {
+        return Err(WebError::named_not_found(
+            format!("course `{course}`"),
+            location!(),
+        ));
+    }
+    query!(
+        "UPDATE Teams SET display_name = ?, is_admin = ?, course_id = ? WHERE id = ?",
+        display_name,
+        is_admin,
+        course,
+        team_id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_patch_team"))
+    .await
+    .context(SqlxSnafu)?;
+
+    // Members that already logged in keep the role of the team
+    let role = role_of(is_admin);
+    query!("UPDATE Users SET role = ? WHERE team = ?", role, team_id)
+        .execute(&mut *con)
+        .instrument(info_span!("sqlx_patch_team_roles"))
+        .await
+        .context(SqlxSnafu)?;
+    let team = get_managed_team(&mut con, team_id).await?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(team)
+}
+
+/// Adds the user to the team, removing them from their previous one
+#[instrument(skip_all)]
+pub(super) async fn add_team_member(
+    con: impl Acquire<'_, Database = Sqlite>,
+    team_id: &TeamId,
+    user_id: &UserId,
+) -> Result<ManagedTeam> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+    let team = get_managed_team(&mut con, team_id).await?;
+
+    assign_member(&mut con, team_id, team.is_admin, user_id).await?;
+    let team = get_managed_team(&mut con, team_id).await?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(team)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn remove_team_member(
+    con: impl Acquire<'_, Database = Sqlite>,
+    team_id: &TeamId,
+    user_id: &UserId,
+) -> Result<ManagedTeam> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let res = query!(
+        "DELETE FROM TeamMembers WHERE user_id = ? AND team_id = ?",
+        user_id,
+        team_id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_remove_team_member"))
+    .await
+    .context(SqlxSnafu)?;
+    if res.rows_affected() == 0 {
+        return Err(WebError::named_not_found(
+            format!("member `{user_id}` of team `{team_id}`"),
+            location!(),
+        ));
+    }
+
+    // Otherwise the next config team sync would add them again
+    query!(
+        "DELETE FROM TeamInviteRedemptions WHERE user_id = ?",
+        user_id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_remove_team_member_redemption"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let role = UserRole::Regular;
+    query!(
+        "UPDATE Users SET team = null, role = ? WHERE id = ? AND team = ?",
+        role,
+        user_id,
+        team_id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_remove_team_member_user"))
+    .await
+    .context(SqlxSnafu)?;
+    let team = get_managed_team(&mut con, team_id).await?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(team)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_team_invites(con: &mut SqliteConnection) -> Result<Vec<TeamInvite>> {
+    query!(
+        r#"
+        SELECT
+            code as "code!",
+            team_id as "team_id!: TeamId",
+            created_by as "created_by!: UserId",
+            created_at,
+            expires_at,
+            max_uses,
+            (
+                SELECT COUNT(*) FROM TeamInviteRedemptions
+                WHERE TeamInviteRedemptions.code = TeamInvites.code
+            ) as "uses!: i64"
+        FROM TeamInvites
+        ORDER BY created_at
+        "#
+    )
+    .map(|row| TeamInvite {
+        code: row.code,
+        team_id: row.team_id,
+        created_by: row.created_by,
+        created_at: Timestamp::from_millisecond(row.created_at).expect("time is valid"),
+        expires_at: row
+            .expires_at
+            .map(|it| Timestamp::from_millisecond(it).expect("time is valid")),
+        max_uses: row.max_uses,
+        uses: row.uses,
+    })
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_team_invites"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn create_team_invite(
+    con: impl Acquire<'_, Database = Sqlite>,
+    invite: &TeamInvite,
+) -> Result<()> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+    let exists = query!("SELECT id FROM Teams WHERE id = ?", invite.team_id)
+        .fetch_optional(&mut *con)
+        .instrument(info_span!("sqlx_create_team_invite_exists"))
+        .await
+        .context(SqlxSnafu)?
+        .is_some();
+    if !exists {
+        return Err(WebError::named_not_found(
+            format!("team `{}`", invite.team_id),
+            location!(),
+        ));
+    }
+
+    let created_at = invite.created_at.as_millisecond();
+    let expires_at = invite.expires_at.map(|it| it.as_millisecond());
+    query!(
+        r#"
+        INSERT INTO TeamInvites
+            (code, team_id, created_by, created_at, expires_at, max_uses)
+        VALUES
+            (?, ?, ?, ?, ?, ?)
+        "#,
+        invite.code,
+        invite.team_id,
+        invite.created_by,
+        created_at,
+        expires_at,
+        invite.max_uses
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_create_team_invite"))
+    .await
+    .context(SqlxSnafu)?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+/// Deletes the invite. Users who joined with it stay in the team until the next config team sync.
+#[instrument(skip_all)]
+pub(super) async fn delete_team_invite(con: &mut SqliteConnection, code: &str) -> Result<()> {
+    let res = query!("DELETE FROM TeamInvites WHERE code = ?", code)
+        .execute(con)
+        .instrument(info_span!("sqlx_delete_team_invite"))
+        .await
+        .context(SqlxSnafu)?;
+    if res.rows_affected() == 0 {
+        return Err(WebError::named_not_found(
+            format!("invite `{code}`"),
+            location!(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Adds a user without a team to the team of the invite
+#[instrument(skip_all)]
+pub(super) async fn redeem_team_invite(
+    con: impl Acquire<'_, Database = Sqlite>,
+    user_id: &UserId,
+    code: &str,
+) -> Result<Team> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let invite = get_team_invites(&mut con)
+        .await?
+        .into_iter()
+        .find(|it| it.code == code);
+    let Some(invite) = invite else {
+        return Err(WebError::named_not_found(
+            "invite code".to_string(),
+            location!(),
+        ));
+    };
+    if !invite.is_usable(Timestamp::now()) {
+        return Err(WebError::named_bad_request(
+            "invite code is expired or used up".to_string(),
+            location!(),
+        ));
+    }
+    if get_team_membership(&mut con, user_id).await?.is_some() {
+        return Err(WebError::named_bad_request(
+            "you already are in a team".to_string(),
+            location!(),
+        ));
+    }
+
+    let redeemed_at = Timestamp::now().as_millisecond();
+    query!(
+        r#"
+        INSERT INTO TeamInviteRedemptions
+            (user_id, code, redeemed_at)
+        VALUES
+            (?, ?, ?)
+        ON CONFLICT DO UPDATE SET
+            code = excluded.code,
+            redeemed_at = excluded.redeemed_at
+        "#,
+        user_id,
+        invite.code,
+        redeemed_at
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_redeem_team_invite"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let team = get_managed_team(&mut con, &invite.team_id).await?;
+    assign_member(&mut con, &team.id, team.is_admin, user_id).await?;
+    let team = get_team(&mut con, &team.id).await?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(team)
+}
+
 #[instrument(skip_all)]
 pub(super) async fn sync_teams(
     con: impl Acquire<'_, Database = Sqlite>,
     teams: &[TeamEntry],
+    strategy: TeamSync,
 ) -> Result<()> {
     let mut con = con.begin().await.context(SqlxSnafu)?;
 
+    match strategy {
+        TeamSync::Config => replace_teams(&mut con, teams).await?,
+        TeamSync::Seed => seed_teams(&mut con, teams).await?,
+    }
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+async fn replace_teams(con: &mut SqliteConnection, teams: &[TeamEntry]) -> Result<()> {
     query!("PRAGMA defer_foreign_keys = ON")
         .execute(&mut *con)
         .instrument(info_span!("sqlx_sync_teams_pragma"))
@@ -103,61 +538,65 @@ pub(super) async fn sync_teams(
         .instrument(info_span!("sqlx_sync_teams_update_users"))
         .await
         .context(SqlxSnafu)?;
-
-    for team in teams {
-        query!(
-            r#"
-            INSERT INTO Teams
-                (id, display_name)
-            VALUES
-                (?, ?)
-            ON CONFLICT DO UPDATE SET
-                display_name = excluded.display_name"#,
-            team.id,
-            team.display_name
-        )
+    query!("DELETE FROM TeamMembers")
         .execute(&mut *con)
-        .instrument(info_span!("sqlx_sync_teams_insert"))
+        .instrument(info_span!("sqlx_sync_teams_delete_members"))
         .await
         .context(SqlxSnafu)?;
 
+    for team in teams {
+        super::course::ensure_course(&mut *con, &team.course).await?;
+        insert_team(
+            &mut *con,
+            &team.id,
+            &team.display_name,
+            team.is_admin,
+            &team.course,
+        )
+        .await?;
         for member in &team.members {
-            let role = if team.is_admin {
-                UserRole::Admin
-            } else {
-                UserRole::Regular
-            };
-            let res = query!(
-                "UPDATE Users SET team = ?, role = ? WHERE id = ?",
-                team.id,
-                role,
-                member
-            )
-            .execute(&mut *con)
-            .instrument(info_span!("sqlx_sync_teams_update_user"))
-            .await
-            .context(SqlxSnafu)?;
-
-            if res.rows_affected() == 0 {
-                warn!(user = ?member, team = ?team.id, "User not found when adding to team");
-            }
+            assign_member(&mut *con, &team.id, team.is_admin, member).await?;
         }
+    }
 
-        let new_integration_token = uuid::Uuid::new_v4().to_string();
-        query!(
-            r#"
-            INSERT INTO TeamIntegrationTokens
-                (team_id, token)
-            VALUES
-                (?, ?)
-            ON CONFLICT DO NOTHING
-            "#,
-            team.id,
-            new_integration_token
+    let expected_teams = teams
+        .iter()
+        .map(|team| team.id.to_string())
+        .collect::<HashSet<_>>();
+
+    // The config does not know who joined with an invite. Its own members take precedence.
+    let config_members = teams
+        .iter()
+        .flat_map(|team| &team.members)
+        .collect::<HashSet<_>>();
+    let redemptions = query!(
+        r#"
+        SELECT
+            TeamInviteRedemptions.user_id as "user_id!: UserId",
+            Teams.id as "team_id!: TeamId",
+            Teams.is_admin
+        FROM TeamInviteRedemptions
+        JOIN TeamInvites ON TeamInvites.code = TeamInviteRedemptions.code
+        JOIN Teams ON Teams.id = TeamInvites.team_id
+        "#
+    )
+    .fetch_all(&mut *con)
+    .instrument(info_span!("sqlx_sync_teams_get_redemptions"))
+    .await
+    .context(SqlxSnafu)?;
+    for redemption in redemptions {
+        if config_members.contains(&redemption.user_id)
+            || !expected_teams.contains(&redemption.team_id.to_string())
+        {
+            continue;
+        }
+        assign_member(
+            &mut *con,
+            &redemption.team_id,
+            redemption.is_admin,
+            &redemption.user_id,
         )
-        .execute(&mut *con)
-        .await
-        .context(SqlxSnafu)?;
+        .await?;
     }
 
     let existing_teams = query!(r#"SELECT id as "id!" FROM Teams"#)
@@ -166,10 +605,6 @@ pub(super) async fn sync_teams(
         .instrument(info_span!("sqlx_sync_teams_get_existing"))
         .await
         .context(SqlxSnafu)?;
-    let expected_teams = teams
-        .iter()
-        .map(|team| team.id.to_string())
-        .collect::<HashSet<_>>();
 
     for team in existing_teams {
         if !expected_teams.contains(&team) {
@@ -182,7 +617,132 @@ pub(super) async fn sync_teams(
         }
     }
 
-    con.commit().await.context(SqlxSnafu)?;
+    Ok(())
+}
+
+/// Creates the teams that do not exist yet and leaves all others alone
+async fn seed_teams(con: &mut SqliteConnection, teams: &[TeamEntry]) -> Result<()> {
+    for team in teams {
+        let exists = query!("SELECT id FROM Teams WHERE id = ?", team.id)
+            .fetch_optional(&mut *con)
+            .instrument(info_span!("sqlx_seed_teams_exists"))
+            .await
+            .context(SqlxSnafu)?
+            .is_some();
+        if exists {
+            continue;
+        }
+
+        info!(team = ?team.id, "Seeding team");
+        super::course::ensure_course(&mut *con, &team.course).await?;
+        insert_team(
+            &mut *con,
+            &team.id,
+            &team.display_name,
+            team.is_admin,
+            &team.course,
+        )
+        .await?;
+        for member in &team.members {
+            assign_member(&mut *con, &team.id, team.is_admin, member).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn insert_team(
+    con: &mut SqliteConnection,
+    team_id: &TeamId,
+    display_name: &str,
+    is_admin: bool,
+    course: &CourseId,
+) -> Result<()> {
+    query!(
+        r#"
+        INSERT INTO Teams
+            (id, display_name, is_admin, course_id)
+        VALUES
+            (?, ?, ?, ?)
+        ON CONFLICT DO UPDATE SET
+            display_name = excluded.display_name,
+            is_admin = excluded.is_admin,
+            course_id = excluded.course_id"#,
+        team_id,
+        display_name,
+        is_admin,
+        course
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_insert_team"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let new_integration_token = uuid::Uuid::new_v4().to_string();
+    query!(
+        r#"
+        INSERT INTO TeamIntegrationTokens
+            (team_id, token)
+        VALUES
+            (?, ?)
+        ON CONFLICT DO NOTHING
+        "#,
+        team_id,
+        new_integration_token
+    )
+    .execute(&mut *con)
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(())
+}
+
+/// Records the membership and moves the user to the team right away if they logged in before
+async fn assign_member(
+    con: &mut SqliteConnection,
+    team_id: &TeamId,
+    is_admin: bool,
+    user_id: &UserId,
+) -> Result<()> {
+    query!(
+        r#"
+        INSERT INTO TeamMembers
+            (user_id, team_id)
+        VALUES
+            (?, ?)
+        ON CONFLICT DO UPDATE SET
+            team_id = excluded.team_id"#,
+        user_id,
+        team_id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_assign_member"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let role = role_of(is_admin);
+    let res = query!(
+        "UPDATE Users SET team = ?, role = ? WHERE id = ?",
+        team_id,
+        role,
+        user_id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_assign_member_user"))
+    .await
+    .context(SqlxSnafu)?;
+
+    if res.rows_affected() == 0 {
+        info!(user = ?user_id, team = ?team_id, "User joins the team on their first login");
+    }
 
     Ok(())
 }
+
+fn role_of(is_admin: bool) -> UserRole {
+    if is_admin {
+        UserRole::Admin
+    } else {
+        UserRole::Regular
+    }
+}
@@ -1,10 +1,15 @@
+use super::contains_pattern;
 use crate::error::{Result, SqlxSnafu, WebError};
-use crate::types::{TeamId, Test, TestId, TestSummary, TestWithTasteTesting};
+use crate::types::{
+    CourseId, Page, ReferenceOutput, TeamId, Test, TestAcceptance, TestAcceptanceState, TestId,
+    TestMetrics, TestSearch, TestStats, TestSuiteSnapshot, TestSummary, TestTastingResult,
+    TestVisibility, TestWithTasteTesting, UserId,
+};
 use jiff::Timestamp;
-use sha2::{Digest, Sha256};
 use shared::{TestExecutionOutput, TestExecutionOutputType};
 use snafu::{ResultExt, location};
 use sqlx::{Acquire, Sqlite, SqliteConnection, query, query_as};
+use std::collections::HashSet;
 use tracing::{Instrument, info_span, instrument};
 
 #[instrument(skip_all)]
@@ -12,6 +17,7 @@ pub(super) async fn add_test(
     con: impl Acquire<'_, Database = Sqlite>,
     test: Test,
     test_tasting: Option<TestExecutionOutput>,
+    reference_output: Option<&ReferenceOutput>,
 ) -> Result<Test> {
     let mut con = con.begin().await.context(SqlxSnafu)?;
 
@@ -20,16 +26,22 @@ pub(super) async fn add_test(
     let binary_modifiers =
         serde_json::to_string(&test.binary_modifiers).expect("Unexpected json serialize error");
 
-    let hash = hash_test(&test);
+    let hash = test.hash();
 
     let last_updated = test.last_updated.as_millisecond();
+    let timeout_ms = test.timeout_ms.map(|it| it as i64);
+    let depends_on =
+        serde_json::to_string(&test.depends_on).expect("Unexpected json serialize error");
+    let (visibility, visible_from) = visibility_to_db(test.visibility);
+    let (acceptance, acceptance_message) = acceptance_to_db(&test.acceptance);
     query!(
         r#"
         INSERT INTO Tests
-            (id, owner, category, compiler_modifiers, binary_modifiers, admin_authored, hash,
-             provisional_for_category, last_updated)
+            (id, owner, course_id, category, compiler_modifiers, binary_modifiers,
+             admin_authored, hash, provisional_for_category, timeout_ms, depends_on, practice,
+             license, visibility, visible_from, acceptance, acceptance_message, last_updated)
         VALUES
-            (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         ON CONFLICT DO UPDATE SET
             compiler_modifiers = excluded.compiler_modifiers,
             binary_modifiers = excluded.binary_modifiers,
@@ -37,16 +49,33 @@ pub(super) async fn add_test(
             category = excluded.category,
             hash = excluded.hash,
             last_updated = excluded.last_updated,
-            provisional_for_category = excluded.provisional_for_category
+            provisional_for_category = excluded.provisional_for_category,
+            timeout_ms = excluded.timeout_ms,
+            depends_on = excluded.depends_on,
+            practice = excluded.practice,
+            license = excluded.license,
+            visibility = excluded.visibility,
+            visible_from = excluded.visible_from,
+            acceptance = excluded.acceptance,
+            acceptance_message = excluded.acceptance_message
         "#,
         test.id,
         test.owner,
+        test.course,
         test.category,
         compiler_modifiers,
         binary_modifiers,
         test.admin_authored,
         hash,
         test.provisional_for_category,
+        timeout_ms,
+        depends_on,
+        test.practice,
+        test.license,
+        visibility,
+        visible_from,
+        acceptance,
+        acceptance_message,
         last_updated,
     )
     .execute(&mut *con)
@@ -54,9 +83,10 @@ pub(super) async fn add_test(
     .await
     .context(SqlxSnafu)?;
 
+    // Test tastings run the reference compiler, whose output is small enough to keep in full
     if let Some(output) = test_tasting {
         let (compiler_exec, test_exec) =
-            super::task::record_test_execution(&mut con, &output).await?;
+            super::task::record_test_execution(&mut con, None, &output).await?;
         let status = TestExecutionOutputType::from(&output).to_string();
         query!(
             r#"
@@ -80,18 +110,29 @@ pub(super) async fn add_test(
         .context(SqlxSnafu)?;
     }
 
+    set_reference_output(&mut con, &test.id, reference_output).await?;
+
     let test = query_as!(
         DbTest,
         r#"
         SELECT
             id as "id!: TestId",
             owner as "owner!: TeamId",
+            course_id as "course_id!: CourseId",
             category,
             compiler_modifiers,
             binary_modifiers,
             admin_authored,
             provisional_for_category,
             limited_to_category,
+            timeout_ms,
+            depends_on,
+            practice,
+            license,
+            visibility,
+            visible_from,
+            acceptance,
+            acceptance_message,
             last_updated
         FROM Tests
         WHERE id = ?"#,
@@ -108,21 +149,82 @@ pub(super) async fn add_test(
     Ok(test)
 }
 
-fn hash_test(test: &Test) -> String {
-    let compiler_modifiers =
-        serde_json::to_string(&test.compiler_modifiers).expect("Unexpected json serialize error");
-    let binary_modifiers =
-        serde_json::to_string(&test.binary_modifiers).expect("Unexpected json serialize error");
+/// Stores imported tests, replacing existing tests with the same id in full. Imported tests are
+/// accepted, as an admin picked them.
+#[instrument(skip_all)]
+pub(super) async fn import_tests(
+    con: impl Acquire<'_, Database = Sqlite>,
+    tests: &[Test],
+) -> Result<()> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
 
-    let mut hash = Sha256::new();
-    hash.update(compiler_modifiers.as_bytes());
-    hash.update(binary_modifiers.as_bytes());
-    hash.update(test.owner.to_string().as_bytes());
-    hash.update([test.admin_authored as u8]);
-    hash.update([test.limited_to_category as u8]);
-    hash.update(test.category.as_bytes());
+    for test in tests {
+        let compiler_modifiers = serde_json::to_string(&test.compiler_modifiers)
+            .expect("Unexpected json serialize error");
+        let binary_modifiers =
+            serde_json::to_string(&test.binary_modifiers).expect("Unexpected json serialize error");
+        let hash = test.hash();
+        let last_updated = test.last_updated.as_millisecond();
+        let timeout_ms = test.timeout_ms.map(|it| it as i64);
+        let depends_on =
+            serde_json::to_string(&test.depends_on).expect("Unexpected json serialize error");
+        let (visibility, visible_from) = visibility_to_db(test.visibility);
 
-    format!("{:x}", hash.finalize())
+        query!(
+            r#"
+            INSERT INTO Tests
+                (id, owner, course_id, category, compiler_modifiers, binary_modifiers,
+                 admin_authored, hash, provisional_for_category, limited_to_category, timeout_ms,
+                 depends_on, practice, license, visibility, visible_from, last_updated)
+            VALUES
+                (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT DO UPDATE SET
+                owner = excluded.owner,
+                course_id = excluded.course_id,
+                compiler_modifiers = excluded.compiler_modifiers,
+                binary_modifiers = excluded.binary_modifiers,
+                admin_authored = excluded.admin_authored,
+                category = excluded.category,
+                hash = excluded.hash,
+                last_updated = excluded.last_updated,
+                provisional_for_category = excluded.provisional_for_category,
+                limited_to_category = excluded.limited_to_category,
+                timeout_ms = excluded.timeout_ms,
+                depends_on = excluded.depends_on,
+                practice = excluded.practice,
+                license = excluded.license,
+                visibility = excluded.visibility,
+                visible_from = excluded.visible_from,
+                acceptance = 'Accepted',
+                acceptance_message = NULL
+            "#,
+            test.id,
+            test.owner,
+            test.course,
+            test.category,
+            compiler_modifiers,
+            binary_modifiers,
+            test.admin_authored,
+            hash,
+            test.provisional_for_category,
+            test.limited_to_category,
+            timeout_ms,
+            depends_on,
+            test.practice,
+            test.license,
+            visibility,
+            visible_from,
+            last_updated,
+        )
+        .execute(&mut *con)
+        .instrument(info_span!("sqlx_import_test"))
+        .await
+        .context(SqlxSnafu)?;
+    }
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(())
 }
 
 #[instrument(skip_all)]
@@ -133,12 +235,21 @@ pub(super) async fn get_tests(con: &mut SqliteConnection) -> Result<Vec<Test>> {
         SELECT
             id as "id!: TestId",
             owner as "owner!: TeamId",
+            course_id as "course_id!: CourseId",
             category,
             compiler_modifiers,
             binary_modifiers,
             admin_authored,
             provisional_for_category,
             limited_to_category,
+            timeout_ms,
+            depends_on,
+            practice,
+            license,
+            visibility,
+            visible_from,
+            acceptance,
+            acceptance_message,
             last_updated
         FROM Tests
         "#
@@ -150,11 +261,57 @@ pub(super) async fn get_tests(con: &mut SqliteConnection) -> Result<Vec<Test>> {
     .context(SqlxSnafu)
 }
 
+/// Returns the tests that count, see [TestAcceptance]
 #[instrument(skip_all)]
-pub(super) async fn get_tests_summaries(con: &mut SqliteConnection) -> Result<Vec<TestSummary>> {
-    let success_status = TestExecutionOutputType::Success.to_string();
+pub(super) async fn get_accepted_tests(con: &mut SqliteConnection) -> Result<Vec<Test>> {
     query_as!(
-        TestSummary,
+        DbTest,
+        r#"
+        SELECT
+            id as "id!: TestId",
+            owner as "owner!: TeamId",
+            course_id as "course_id!: CourseId",
+            category,
+            compiler_modifiers,
+            binary_modifiers,
+            admin_authored,
+            provisional_for_category,
+            limited_to_category,
+            timeout_ms,
+            depends_on,
+            practice,
+            license,
+            visibility,
+            visible_from,
+            acceptance,
+            acceptance_message,
+            last_updated
+        FROM Tests
+        WHERE acceptance = 'Accepted'
+        "#
+    )
+    .map(Test::from)
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_accepted_tests"))
+    .await
+    .context(SqlxSnafu)
+}
+
+/// Returns the matching tests on the page, and the total number of matching tests
+#[instrument(skip_all)]
+pub(super) async fn get_tests_summaries(
+    con: impl Acquire<'_, Database = Sqlite>,
+    search: &TestSearch,
+    page: Page,
+) -> Result<(Vec<TestSummary>, i64)> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let success_status = TestExecutionOutputType::Success.to_string();
+    let pattern = search.query.as_deref().map(contains_pattern);
+    let now = Timestamp::now().as_millisecond();
+    let acceptance = search.acceptance.map(acceptance_state_to_db);
+    let tests = query_as!(
+        DbTestSummary,
         r#"
         SELECT
             Tests.id as "id!: TestId",
@@ -167,16 +324,229 @@ pub(super) async fn get_tests_summaries(con: &mut SqliteConnection) -> Result<Ve
                 as "test_taste_success?: bool",
             Tests.provisional_for_category,
             Tests.limited_to_category,
+            Tests.practice,
+            Tests.visibility,
+            Tests.visible_from,
+            Tests.acceptance,
+            Tests.acceptance_message,
             Tests.last_updated as "last_updated!: DbMillis"
         FROM Tests
         JOIN Teams ON Tests.owner = Teams.id
+        WHERE (? IS NULL OR Tests.id LIKE ? ESCAPE '\' OR Teams.display_name LIKE ? ESCAPE '\')
+            AND (? IS NULL OR Tests.category = ?)
+            AND (? IS NULL OR Tests.owner = ?)
+            AND (? IS NULL OR Tests.course_id = ?)
+            AND (? IS NULL OR Tests.acceptance = ?)
+            AND (? IS NULL OR Tests.owner = ? OR (Tests.acceptance = 'Accepted'
+                AND (Tests.visibility = 'Public'
+                    OR (Tests.visibility = 'HiddenUntil' AND Tests.visible_from <= ?))))
+        ORDER BY Tests.category, Tests.id
+        LIMIT ? OFFSET ?
+        "#,
+        success_status,
+        pattern,
+        pattern,
+        pattern,
+        search.category,
+        search.category,
+        search.creator,
+        search.creator,
+        search.course,
+        search.course,
+        acceptance,
+        acceptance,
+        search.visible_to,
+        search.visible_to,
+        now,
+        page.limit,
+        page.offset
+    )
+    .map(TestSummary::from)
+    .fetch_all(&mut *con)
+    .instrument(info_span!("sqlx_get_test_summaries"))
+    .await
+    .context(SqlxSnafu)?;
+
+    let total = query!(
+        r#"
+        SELECT COUNT(*) as "count!: i64"
+        FROM Tests
+        JOIN Teams ON Tests.owner = Teams.id
+        WHERE (? IS NULL OR Tests.id LIKE ? ESCAPE '\' OR Teams.display_name LIKE ? ESCAPE '\')
+            AND (? IS NULL OR Tests.category = ?)
+            AND (? IS NULL OR Tests.owner = ?)
+            AND (? IS NULL OR Tests.course_id = ?)
+            AND (? IS NULL OR Tests.acceptance = ?)
+            AND (? IS NULL OR Tests.owner = ? OR (Tests.acceptance = 'Accepted'
+                AND (Tests.visibility = 'Public'
+                    OR (Tests.visibility = 'HiddenUntil' AND Tests.visible_from <= ?))))
+        "#,
+        pattern,
+        pattern,
+        pattern,
+        search.category,
+        search.category,
+        search.creator,
+        search.creator,
+        search.course,
+        search.course,
+        acceptance,
+        acceptance,
+        search.visible_to,
+        search.visible_to,
+        now
+    )
+    .map(|it| it.count)
+    .fetch_one(&mut *con)
+    .instrument(info_span!("sqlx_get_test_summaries_count"))
+    .await
+    .context(SqlxSnafu)?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok((tests, total))
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_test_metrics(con: &mut SqliteConnection) -> Result<Vec<TestMetrics>> {
+    let success_status = TestExecutionOutputType::Success.to_string();
+    // Only results of the finalized task *for the test's category* are considered, so every team
+    // contributes at most one result per test. It passed only if it passed under every profile.
+    let metrics = query!(
+        r#"
+        WITH FinalResults AS (
+            SELECT
+                FinalizedSubmittedTasks.team_id,
+                FinalizedSubmittedTasks.category,
+                TestResults.test_id,
+                MIN(TestResults.status == ?) as passed
+            FROM FinalizedSubmittedTasks
+            JOIN TestResults ON TestResults.task_id = FinalizedSubmittedTasks.task_id
+            JOIN Tests ON Tests.id = TestResults.test_id
+                AND Tests.course_id = FinalizedSubmittedTasks.course_id
+                AND Tests.category = FinalizedSubmittedTasks.category
+            GROUP BY
+                FinalizedSubmittedTasks.team_id,
+                FinalizedSubmittedTasks.category,
+                TestResults.test_id
+        ),
+        FailureCounts AS (
+            SELECT team_id, category, COUNT(*) as failures
+            FROM FinalResults
+            WHERE NOT passed
+            GROUP BY team_id, category
+        )
+        SELECT
+            Tests.id as "id!: TestId",
+            Tests.category,
+            Tests.last_updated,
+            COUNT(FinalResults.team_id) as "final_task_count!: i64",
+            COALESCE(SUM(FinalResults.passed), 0) as "passed!: i64",
+            COALESCE(SUM(NOT FinalResults.passed AND FailureCounts.failures = 1), 0)
+                as "unique_failures!: i64"
+        FROM Tests
+        LEFT JOIN FinalResults ON FinalResults.test_id = Tests.id
+        LEFT JOIN FailureCounts ON FailureCounts.team_id = FinalResults.team_id
+            AND FailureCounts.category = FinalResults.category
+        GROUP BY Tests.id
         "#,
         success_status
     )
     .fetch_all(con)
-    .instrument(info_span!("sqlx_get_test_summaries"))
+    .instrument(info_span!("sqlx_get_test_metrics"))
     .await
-    .context(SqlxSnafu)
+    .context(SqlxSnafu)?;
+
+    Ok(metrics
+        .into_iter()
+        .map(|it| TestMetrics {
+            id: it.id,
+            category: it.category,
+            final_task_count: it.final_task_count,
+            pass_rate: if it.final_task_count > 0 {
+                Some(it.passed as f64 / it.final_task_count as f64)
+            } else {
+                None
+            },
+            unique_failures: it.unique_failures,
+            last_updated: DbMillis(it.last_updated).into(),
+        })
+        .collect())
+}
+
+/// Aggregates the results of the latest task of every team that ran the test. A task only passes
+/// if the test passed under every profile.
+#[instrument(skip_all)]
+pub(super) async fn get_test_stats(
+    con: &mut SqliteConnection,
+    test_id: &TestId,
+) -> Result<TestStats> {
+    let success_status = TestExecutionOutputType::Success.to_string();
+    let stats = query!(
+        r#"
+        WITH TaskResults AS (
+            SELECT
+                MIN(TestResults.status == ?) as passed,
+                AVG(ExecutionResults.duration_ms) as runtime_ms,
+                ROW_NUMBER() OVER (PARTITION BY Tasks.team_id ORDER BY Tasks.queue_time DESC)
+                    as position
+            FROM TestResults
+            JOIN Tasks ON Tasks.task_id = TestResults.task_id
+            LEFT JOIN ExecutionResults
+                ON ExecutionResults.execution_id = TestResults.binary_exec_id
+            WHERE TestResults.test_id = ? AND Tasks.bisect_id IS NULL
+            GROUP BY Tasks.task_id
+        )
+        SELECT
+            COALESCE(SUM(passed), 0) as "passing_teams!: i64",
+            COALESCE(SUM(NOT passed), 0) as "failing_teams!: i64",
+            AVG(runtime_ms) as "average_runtime_ms?: f64"
+        FROM TaskResults
+        WHERE position = 1
+        "#,
+        success_status,
+        test_id
+    )
+    .fetch_one(con)
+    .instrument(info_span!("sqlx_get_test_stats"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(TestStats {
+        test_id: test_id.clone(),
+        passing_teams: stats.passing_teams,
+        failing_teams: stats.failing_teams,
+        average_runtime_ms: stats.average_runtime_ms,
+    })
+}
+
+/// The tests the team may not see, see [TestVisibility]
+#[instrument(skip_all)]
+pub(super) async fn get_hidden_test_ids(
+    con: &mut SqliteConnection,
+    team: &TeamId,
+) -> Result<HashSet<TestId>> {
+    let now = Timestamp::now().as_millisecond();
+    let ids = query!(
+        r#"
+        SELECT id as "id!: TestId"
+        FROM Tests
+        WHERE course_id != (SELECT course_id FROM Teams WHERE id = ?)
+            OR (owner != ?
+                AND (visibility = 'TeamOnly'
+                    OR (visibility = 'HiddenUntil' AND visible_from > ?)))
+        "#,
+        team,
+        team,
+        now
+    )
+    .map(|it| it.id)
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_hidden_test_ids"))
+    .await
+    .context(SqlxSnafu)?;
+
+    Ok(ids.into_iter().collect())
 }
 
 #[instrument(skip_all)]
@@ -190,12 +560,21 @@ pub(super) async fn fetch_test(
         SELECT
             id as "id!: TestId",
             owner as "owner!: TeamId",
+            course_id as "course_id!: CourseId",
             category,
             compiler_modifiers,
             binary_modifiers,
             admin_authored,
             provisional_for_category,
             limited_to_category,
+            timeout_ms,
+            depends_on,
+            practice,
+            license,
+            visibility,
+            visible_from,
+            acceptance,
+            acceptance_message,
             last_updated
         FROM Tests
         WHERE id = ?
@@ -211,6 +590,22 @@ pub(super) async fn fetch_test(
     Ok(test)
 }
 
+/// Fetches the tests with the given ids, leaving out the ones that do not exist
+#[instrument(skip_all)]
+pub(super) async fn fetch_tests(
+    con: impl Acquire<'_, Database = Sqlite>,
+    test_ids: &[TestId],
+) -> Result<Vec<Test>> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let mut tests = Vec::with_capacity(test_ids.len());
+    for test_id in test_ids {
+        tests.extend(fetch_test(&mut con, test_id).await?);
+    }
+
+    Ok(tests)
+}
+
 #[instrument(skip_all)]
 pub(super) async fn fetch_test_with_tasting(
     con: impl Acquire<'_, Database = Sqlite>,
@@ -221,7 +616,117 @@ pub(super) async fn fetch_test_with_tasting(
     let Some(test) = fetch_test(&mut con, test_id).await? else {
         return Ok(None);
     };
+    let test_tasting_result = fetch_test_tasting(&mut con, test_id).await?;
+    let reference_output = fetch_reference_output(&mut con, test_id).await?;
+
+    Ok(Some(TestWithTasteTesting {
+        test,
+        test_tasting_result,
+        reference_output,
+    }))
+}
+
+/// Accepts or rejects a test without touching its contents
+#[instrument(skip_all)]
+pub(super) async fn set_test_acceptance(
+    con: &mut SqliteConnection,
+    test_id: &TestId,
+    acceptance: &TestAcceptance,
+) -> Result<()> {
+    let (acceptance, acceptance_message) = acceptance_to_db(acceptance);
+    let res = query!(
+        "UPDATE Tests SET acceptance = ?, acceptance_message = ? WHERE id = ?",
+        acceptance,
+        acceptance_message,
+        test_id
+    )
+    .execute(con)
+    .instrument(info_span!("sqlx_set_test_acceptance"))
+    .await
+    .context(SqlxSnafu)?;
+
+    if res.rows_affected() == 0 {
+        return Err(WebError::not_found(location!()));
+    }
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+async fn set_reference_output(
+    con: &mut SqliteConnection,
+    test_id: &TestId,
+    reference_output: Option<&ReferenceOutput>,
+) -> Result<()> {
+    let Some(reference_output) = reference_output else {
+        query!("DELETE FROM ReferenceOutputs WHERE test_id = ?", test_id)
+            .execute(&mut *con)
+            .instrument(info_span!("sqlx_delete_reference_output"))
+            .await
+            .context(SqlxSnafu)?;
+        return Ok(());
+    };
+
+    let compile_command = serde_json::to_string(&reference_output.compile_command)
+        .expect("Unexpected json serialize error");
+    let computed_at = reference_output.computed_at.as_millisecond();
+    query!(
+        r#"
+        INSERT INTO ReferenceOutputs
+            (test_id, output, image, compile_command, computed_at)
+        VALUES
+            (?, ?, ?, ?, ?)
+        ON CONFLICT DO UPDATE SET
+            output = excluded.output,
+            image = excluded.image,
+            compile_command = excluded.compile_command,
+            computed_at = excluded.computed_at
+        "#,
+        test_id,
+        reference_output.output,
+        reference_output.image,
+        compile_command,
+        computed_at
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_set_reference_output"))
+    .await
+    .context(SqlxSnafu)?;
 
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn fetch_reference_output(
+    con: &mut SqliteConnection,
+    test_id: &TestId,
+) -> Result<Option<ReferenceOutput>> {
+    query!(
+        r#"
+        SELECT output, image, compile_command, computed_at
+        FROM ReferenceOutputs
+        WHERE test_id = ?
+        "#,
+        test_id
+    )
+    .map(|row| ReferenceOutput {
+        output: row.output,
+        image: row.image,
+        compile_command: serde_json::from_str(&row.compile_command)
+            .expect("Unexpected json deserialize error"),
+        computed_at: Timestamp::from_millisecond(row.computed_at).expect("time is valid"),
+    })
+    .fetch_optional(con)
+    .instrument(info_span!("sqlx_fetch_reference_output"))
+    .await
+    .context(SqlxSnafu)
+}
+
+#[instrument(skip_all)]
+async fn fetch_test_tasting(
+    con: &mut SqliteConnection,
+    test_id: &TestId,
+) -> Result<Option<TestTastingResult>> {
     let taste_test_execution = query!(
         "SELECT compiler_exec_id, binary_exec_id, status FROM TestTastingResults WHERE test_id = ?",
         test_id
@@ -230,10 +735,10 @@ pub(super) async fn fetch_test_with_tasting(
     .await
     .context(SqlxSnafu)?;
 
-    let test_tasting_result = match taste_test_execution {
+    Ok(match taste_test_execution {
         Some(exec) => Some(
             super::task::get_test_execution(
-                &mut con,
+                con,
                 &exec.compiler_exec_id,
                 exec.binary_exec_id,
                 exec.status.parse().unwrap(),
@@ -242,12 +747,67 @@ pub(super) async fn fetch_test_with_tasting(
             .into(),
         ),
         None => None,
+    })
+}
+
+/// Returns all provisional tests with the result of tasting them on the reference compiler
+#[instrument(skip_all)]
+pub(super) async fn get_provisional_tests(
+    con: impl Acquire<'_, Database = Sqlite>,
+) -> Result<Vec<TestWithTasteTesting>> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let mut result = Vec::new();
+    for test in get_accepted_tests(&mut con).await? {
+        if test.provisional_for_category.is_none() {
+            continue;
+        }
+        let test_tasting_result = fetch_test_tasting(&mut con, &test.id).await?;
+        let reference_output = fetch_reference_output(&mut con, &test.id).await?;
+        result.push(TestWithTasteTesting {
+            test,
+            test_tasting_result,
+            reference_output,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Turns a provisional test into a regular one, so it counts for its category
+#[instrument(skip_all)]
+pub(super) async fn promote_test(
+    con: impl Acquire<'_, Database = Sqlite>,
+    test_id: &TestId,
+) -> Result<Test> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let Some(mut test) = fetch_test(&mut con, test_id).await? else {
+        return Err(WebError::not_found(location!()));
     };
+    test.provisional_for_category = None;
+    test.last_updated = Timestamp::now();
 
-    Ok(Some(TestWithTasteTesting {
-        test,
-        test_tasting_result,
-    }))
+    let hash = test.hash();
+    let last_updated = test.last_updated.as_millisecond();
+    query!(
+        r#"
+        UPDATE Tests
+        SET provisional_for_category = NULL, hash = ?, last_updated = ?
+        WHERE id = ?
+        "#,
+        hash,
+        last_updated,
+        test.id
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_promote_test"))
+    .await
+    .context(SqlxSnafu)?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(test)
 }
 
 #[instrument(skip_all)]
@@ -270,7 +830,7 @@ pub(super) async fn rehash(con: impl Acquire<'_, Database = Sqlite>) -> Result<(
     let mut con = con.begin().await.context(SqlxSnafu)?;
 
     for test in get_tests(&mut con).await? {
-        let hash = hash_test(&test);
+        let hash = test.hash();
         query!("UPDATE Tests SET hash = ? WHERE id = ?", hash, test.id)
             .execute(&mut *con)
             .instrument(info_span!("sqlx_rehash_inner"))
@@ -283,15 +843,106 @@ pub(super) async fn rehash(con: impl Acquire<'_, Database = Sqlite>) -> Result<(
     Ok(())
 }
 
+/// Snapshots all tests counting towards the category, replacing any previous snapshot
+#[instrument(skip_all)]
+pub(super) async fn freeze_test_suite(
+    con: impl Acquire<'_, Database = Sqlite>,
+    course: &CourseId,
+    category: &str,
+    frozen_by: Option<&UserId>,
+) -> Result<TestSuiteSnapshot> {
+    let mut con = con.begin().await.context(SqlxSnafu)?;
+
+    let tests = get_accepted_tests(&mut con)
+        .await?
+        .into_iter()
+        .filter(|test| test.course == *course && test.category == category)
+        .filter(|test| test.provisional_for_category.as_deref() != Some(category))
+        .filter(|test| !test.practice)
+        .collect::<Vec<_>>();
+    let snapshot = TestSuiteSnapshot {
+        course: course.clone(),
+        category: category.to_string(),
+        frozen_at: Timestamp::now(),
+        frozen_by: frozen_by.cloned(),
+        tests,
+    };
+
+    let frozen_at = snapshot.frozen_at.as_millisecond();
+    let tests = serde_json::to_string(&snapshot.tests).expect("Unexpected json serialize error");
+    query!(
+        r#"
+        INSERT INTO TestSuiteSnapshots
+            (course_id, category, frozen_at, frozen_by, tests)
+        VALUES
+            (?, ?, ?, ?, ?)
+        ON CONFLICT DO UPDATE SET
+            frozen_at = excluded.frozen_at,
+            frozen_by = excluded.frozen_by,
+            tests = excluded.tests
+        "#,
+        snapshot.course,
+        snapshot.category,
+        frozen_at,
+        snapshot.frozen_by,
+        tests
+    )
+    .execute(&mut *con)
+    .instrument(info_span!("sqlx_freeze_test_suite"))
+    .await
+    .context(SqlxSnafu)?;
+
+    con.commit().await.context(SqlxSnafu)?;
+
+    Ok(snapshot)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_test_suite_snapshots(
+    con: &mut SqliteConnection,
+) -> Result<Vec<TestSuiteSnapshot>> {
+    query!(
+        r#"
+        SELECT
+            course_id as "course_id!: CourseId",
+            category as "category!",
+            frozen_at,
+            frozen_by as "frozen_by?: UserId",
+            tests
+        FROM TestSuiteSnapshots
+        "#
+    )
+    .map(|row| TestSuiteSnapshot {
+        course: row.course_id,
+        category: row.category,
+        frozen_at: DbMillis(row.frozen_at).into(),
+        frozen_by: row.frozen_by,
+        tests: serde_json::from_str(&row.tests).expect("Unexpected json serialize error"),
+    })
+    .fetch_all(con)
+    .instrument(info_span!("sqlx_get_test_suite_snapshots"))
+    .await
+    .context(SqlxSnafu)
+}
+
 struct DbTest {
     id: TestId,
     owner: TeamId,
+    course_id: CourseId,
     category: String,
     compiler_modifiers: String,
     binary_modifiers: String,
     admin_authored: bool,
     provisional_for_category: Option<String>,
     limited_to_category: bool,
+    timeout_ms: Option<i64>,
+    depends_on: String,
+    practice: bool,
+    license: Option<String>,
+    visibility: String,
+    visible_from: Option<i64>,
+    acceptance: String,
+    acceptance_message: Option<String>,
     last_updated: i64,
 }
 
@@ -300,6 +951,7 @@ impl From<DbTest> for Test {
         Self {
             id: value.id,
             owner: value.owner,
+            course: value.course_id,
             category: value.category,
             compiler_modifiers: serde_json::from_str(&value.compiler_modifiers)
                 .expect("Unexpected json serialize error"),
@@ -308,11 +960,101 @@ impl From<DbTest> for Test {
             admin_authored: value.admin_authored,
             provisional_for_category: value.provisional_for_category,
             limited_to_category: value.limited_to_category,
+            timeout_ms: value.timeout_ms.map(|it| it as u64),
+            depends_on: serde_json::from_str(&value.depends_on)
+                .expect("Unexpected json serialize error"),
+            practice: value.practice,
+            license: value.license,
+            visibility: visibility_from_db(&value.visibility, value.visible_from),
+            acceptance: acceptance_from_db(&value.acceptance, value.acceptance_message),
             last_updated: DbMillis(value.last_updated).into(),
         }
     }
 }
 
+struct DbTestSummary {
+    id: TestId,
+    creator_id: TeamId,
+    creator_name: String,
+    admin_authored: bool,
+    category: String,
+    hash: String,
+    test_taste_success: Option<bool>,
+    provisional_for_category: Option<String>,
+    limited_to_category: bool,
+    practice: bool,
+    visibility: String,
+    visible_from: Option<i64>,
+    acceptance: String,
+    acceptance_message: Option<String>,
+    last_updated: DbMillis,
+}
+
+impl From<DbTestSummary> for TestSummary {
+    fn from(value: DbTestSummary) -> Self {
+        Self {
+            id: value.id,
+            creator_id: value.creator_id,
+            creator_name: value.creator_name,
+            admin_authored: value.admin_authored,
+            category: value.category,
+            hash: value.hash,
+            test_taste_success: value.test_taste_success,
+            provisional_for_category: value.provisional_for_category,
+            limited_to_category: value.limited_to_category,
+            practice: value.practice,
+            visibility: visibility_from_db(&value.visibility, value.visible_from),
+            acceptance: acceptance_from_db(&value.acceptance, value.acceptance_message),
+            last_updated: value.last_updated.into(),
+        }
+    }
+}
+
+/// Splits the visibility into the `visibility` and `visible_from` columns
+fn visibility_to_db(visibility: TestVisibility) -> (&'static str, Option<i64>) {
+    match visibility {
+        TestVisibility::Public => ("Public", None),
+        TestVisibility::TeamOnly => ("TeamOnly", None),
+        TestVisibility::HiddenUntil { until } => ("HiddenUntil", Some(until.as_millisecond())),
+    }
+}
+
+fn visibility_from_db(visibility: &str, visible_from: Option<i64>) -> TestVisibility {
+    match (visibility, visible_from) {
+        ("TeamOnly", _) => TestVisibility::TeamOnly,
+        ("HiddenUntil", Some(until)) => TestVisibility::HiddenUntil {
+            until: DbMillis(until).into(),
+        },
+        _ => TestVisibility::Public,
+    }
+}
+
+/// Splits the acceptance into the `acceptance` and `acceptance_message` columns
+fn acceptance_to_db(acceptance: &TestAcceptance) -> (&'static str, Option<&str>) {
+    match acceptance {
+        TestAcceptance::Accepted => ("Accepted", None),
+        TestAcceptance::Pending { message } => ("Pending", Some(message)),
+        TestAcceptance::Rejected { message } => ("Rejected", Some(message)),
+    }
+}
+
+fn acceptance_state_to_db(state: TestAcceptanceState) -> &'static str {
+    match state {
+        TestAcceptanceState::Accepted => "Accepted",
+        TestAcceptanceState::Pending => "Pending",
+        TestAcceptanceState::Rejected => "Rejected",
+    }
+}
+
+fn acceptance_from_db(acceptance: &str, message: Option<String>) -> TestAcceptance {
+    let message = message.unwrap_or_default();
+    match acceptance {
+        "Pending" => TestAcceptance::Pending { message },
+        "Rejected" => TestAcceptance::Rejected { message },
+        _ => TestAcceptance::Accepted,
+    }
+}
+
 #[derive(Debug, Clone, Copy, sqlx::Type)]
 struct DbMillis(i64);
 
@@ -1,6 +1,6 @@
-use crate::auth::oidc::OidcUser;
+use crate::auth::provider::LoginUser;
 use crate::error::{Result, SqlxSnafu, WebError};
-use crate::types::{FullUserForAdmin, OwnUser, TeamId, User, UserId, UserRole};
+use crate::types::{CourseId, FullUserForAdmin, OwnUser, TeamId, User, UserId, UserRole};
 use snafu::{ResultExt, location};
 use sqlx::{SqliteConnection, query};
 use tracing::{Instrument, instrument, trace_span};
@@ -10,12 +10,19 @@ pub(super) async fn get_user_for_login(
     con: &mut SqliteConnection,
     user_id: &UserId,
 ) -> Result<Option<UserForAuth>> {
-    sqlx::query_as("SELECT * FROM Users WHERE id = ?")
-        .bind(user_id)
-        .fetch_optional(con)
-        .instrument(trace_span!("sqlx_get_user_login"))
-        .await
-        .context(SqlxSnafu)
+    sqlx::query_as(
+        r#"
+        SELECT Users.*, COALESCE(Teams.course_id, 'default') as course
+        FROM Users
+        LEFT JOIN Teams ON Teams.id = Users.team
+        WHERE Users.id = ?
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(con)
+    .instrument(trace_span!("sqlx_get_user_login"))
+    .await
+    .context(SqlxSnafu)
 }
 
 #[instrument(skip_all)]
@@ -51,9 +58,9 @@ pub(super) async fn fetch_users(con: &mut SqliteConnection) -> Result<Vec<FullUs
 }
 
 #[instrument(skip_all)]
-pub(super) async fn synchronize_oidc_user(
+pub(super) async fn synchronize_login_user(
     con: &mut SqliteConnection,
-    user: OidcUser,
+    user: LoginUser,
     team: Option<TeamId>,
     role: Option<UserRole>,
 ) -> Result<OwnUser> {
@@ -86,6 +93,8 @@ pub(super) async fn synchronize_oidc_user(
 #[derive(sqlx::FromRow)]
 pub struct UserForAuth {
     pub role: UserRole,
+    /// The course of the team of the user, or the default course without a team
+    pub course: CourseId,
     #[sqlx(flatten)]
     pub user: User,
 }
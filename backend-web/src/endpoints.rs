@@ -1,57 +1,127 @@
 mod admin;
 mod executor;
+mod login;
 mod oidc;
 mod streaming;
 mod tasks;
 mod team;
 mod test;
 mod user;
+mod version;
 
+pub use self::admin::accept_test;
+pub use self::admin::add_team_member;
+pub use self::admin::approve_provisional_test;
+pub use self::admin::course_milestones;
+pub use self::admin::create_course;
+pub use self::admin::create_team;
+pub use self::admin::create_team_invite;
+pub use self::admin::delete_team_invite;
+pub use self::admin::export_tasks_csv;
+pub use self::admin::export_test_archive;
+pub use self::admin::freeze_test_suite;
+pub use self::admin::get_consistency_report;
+pub use self::admin::get_runner_logs;
+pub use self::admin::import_test_archive;
+pub use self::admin::lint_tests;
+pub use self::admin::list_courses;
+pub use self::admin::list_managed_teams;
+pub use self::admin::list_provisional_tests;
+pub use self::admin::list_snapshots;
+pub use self::admin::list_team_invites;
+pub use self::admin::patch_course;
+pub use self::admin::patch_team;
+pub use self::admin::preview_grading_formula;
+pub use self::admin::prune_old_tasks;
 pub use self::admin::rehash_tests;
+pub use self::admin::reject_provisional_test;
+pub use self::admin::reject_test;
+pub use self::admin::remove_team_member;
+pub use self::admin::request_runner_logs;
 pub use self::admin::rerun_submissions;
+pub use self::admin::rerun_task;
+pub use self::admin::restore_snapshot;
+pub use self::admin::set_runner_role;
+pub use self::admin::simulate_queue_load;
 pub use self::admin::snapshot_state;
 pub use self::admin::team_statistics;
+pub use self::admin::test_metrics;
 pub use self::executor::get_test_tasting_work;
 pub use self::executor::get_work;
 pub use self::executor::get_work_tar;
 pub use self::executor::runner_done;
+pub use self::executor::runner_logs;
 pub use self::executor::runner_ping;
 pub use self::executor::runner_register;
 pub use self::executor::runner_update;
 pub use self::executor::taste_testing_done;
+pub use self::login::get_login_provider;
+pub use self::login::login_password;
 pub use self::oidc::login_oidc;
 pub use self::oidc::login_oidc_callback;
 pub use self::streaming::get_running_task_info;
 pub use self::streaming::head_running_task_info;
+pub use self::tasks::compare_tasks;
 pub use self::tasks::executor_info;
+pub use self::tasks::get_bisect;
+pub use self::tasks::get_build_log;
 pub use self::tasks::get_queue;
+pub use self::tasks::get_queue_position;
+pub use self::tasks::get_queue_statistics;
 pub use self::tasks::get_queued_task;
+pub use self::tasks::get_raw_output;
+pub use self::tasks::get_repro_bundle;
 pub use self::tasks::get_task;
+pub use self::tasks::get_task_test;
 pub use self::tasks::get_top_task_per_team;
+pub use self::tasks::head_task;
 pub use self::tasks::integration_get_task_status;
 pub use self::tasks::integration_request_revision;
 pub use self::tasks::request_revision;
+pub use self::tasks::start_bisect;
+pub use self::team::create_api_token;
+pub use self::team::delete_api_token;
+pub use self::team::generate_team_ssh_key;
 pub use self::team::get_final_tasks;
 pub use self::team::get_n_recent_tasks;
+pub use self::team::get_notifications;
 pub use self::team::get_recent_tasks;
 pub use self::team::get_tasks_for_team;
+pub use self::team::get_team_activity;
 pub use self::team::get_team_info;
+pub use self::team::get_team_milestones;
+pub use self::team::get_team_performance;
 pub use self::team::get_team_repo;
+pub use self::team::get_team_ssh_key;
+pub use self::team::list_api_tokens;
+pub use self::team::mark_notification_read;
 pub use self::team::set_final_task;
 pub use self::team::set_team_repo;
 pub use self::test::delete_test;
+pub use self::test::export_tests;
 pub use self::test::get_test;
+pub use self::test::get_test_details;
+pub use self::test::get_test_stats;
+pub use self::test::head_test;
+pub use self::test::list_test_suite_snapshots;
 pub use self::test::list_tests;
 pub use self::test::set_test;
 pub use self::user::get_integration_status;
+pub use self::user::get_task_notification_settings;
 pub use self::user::list_users;
+pub use self::user::redeem_team_invite;
+pub use self::user::set_task_notification_settings;
 pub use self::user::show_me_myself;
+pub use self::version::get_version;
+use crate::auth::Claims;
 use crate::error::{HttpError, WebError};
-use axum::extract::rejection::{JsonRejection, PathRejection};
+use crate::types::AppState;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
 use axum::extract::{FromRequest, FromRequestParts};
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, StatusCode, header};
 use axum::response::IntoResponse;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use snafu::location;
 
 // create an extractor that internally uses `axum::Json` but has a custom rejection
@@ -63,12 +133,74 @@ pub struct Json<T>(T);
 #[from_request(via(axum::extract::Path), rejection(WebError))]
 pub struct Path<T>(T);
 
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(WebError))]
+pub struct Query<T>(T);
+
 impl<T: Serialize> IntoResponse for Json<T> {
     fn into_response(self) -> axum::response::Response {
         axum::Json(self.0).into_response()
     }
 }
 
+/// Sends the value as JSON with an `ETag` of its contents, or only `304 Not Modified` if the client
+/// already has this version. The tag is weak, as compression changes the bytes that are sent.
+fn json_with_etag<T: Serialize>(
+    request_headers: &HeaderMap,
+    value: &T,
+) -> axum::response::Response {
+    let body = serde_json::to_vec(value).expect("Unexpected json serialize error");
+    let etag = format!("W/\"{:x}\"", Sha256::digest(&body));
+    // Caches may keep the response, but have to ask whether it is still current
+    let headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+    ];
+
+    if matches_if_none_match(request_headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    (headers, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+/// Rejects `action` if the course of a non-admin is archived. Archived courses stay readable.
+async fn ensure_course_open(
+    state: &AppState,
+    claims: &Claims,
+    action: &str,
+) -> Result<(), WebError> {
+    if claims.is_admin() {
+        return Ok(());
+    }
+    let archived = state
+        .db
+        .fetch_course(&claims.course)
+        .await?
+        .is_some_and(|it| it.archived);
+    if archived {
+        return Err(WebError::named_unauthorized(
+            format!("{action} in the archived course `{}`", claims.course),
+            location!(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Compares the tags of `If-None-Match` weakly with ours, as RFC 9110 requires
+fn matches_if_none_match(request_headers: &HeaderMap, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+
+    request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|it| it.to_str().ok())
+        .flat_map(|it| it.split(','))
+        .map(str::trim)
+        .any(|it| it == "*" || it.trim_start_matches("W/") == etag)
+}
+
 impl HttpError for JsonRejection {
     fn to_http_code(&self) -> StatusCode {
         StatusCode::BAD_REQUEST
@@ -100,3 +232,19 @@ impl From<PathRejection> for WebError {
         Self::http_error(value, location!())
     }
 }
+
+impl HttpError for QueryRejection {
+    fn to_http_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn to_error_code(&self) -> &'static str {
+        "invalid_query"
+    }
+}
+
+impl From<QueryRejection> for WebError {
+    fn from(value: QueryRejection) -> Self {
+        Self::http_error(value, location!())
+    }
+}
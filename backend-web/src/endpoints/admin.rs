@@ -1,17 +1,29 @@
-use crate::auth::Claims;
-use crate::endpoints::Json;
+use crate::auth::{Claims, Keys};
+use crate::endpoints::{Json, Query};
 use crate::error::{Result, WebError};
-use crate::grading_formulas::{GradingPoints, get_grading_points_for_task};
+use crate::grading_formulas::{
+    GradingPoints, apply_test_suite_snapshots, formula_to_string, get_grading_points_for_task,
+    get_points_for_task,
+};
+use crate::task_export;
+use crate::test_archive;
+use crate::test_lint;
 use crate::types::{
-    AppState, FinishedCompilerTaskStatistics, FinishedCompilerTaskSummary, TaskId, TeamId, Test,
-    TestId, WorkItem,
+    AppState, ConsistencyReport, Course, CourseId, CourseMilestone, FinishedCompilerTaskStatistics,
+    FinishedCompilerTaskSummary, ManagedTeam, Notification, ProjectedQueueHour,
+    QueueSimulationParams, RetentionReport, RunnerLogsForFrontend, TaskId, TeamId, TeamInvite,
+    Test, TestAcceptance, TestConflictResolution, TestId, TestImportReport, TestLintReport,
+    TestMetrics, TestSuiteSnapshotSummary, TestWithTasteTesting, UserId, WorkItem, simulate_queue,
 };
+use axum::body::{Body, Bytes};
 use axum::extract::{Path, State};
-use serde::Serialize;
-use shared::TestModifier;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use shared::{RunnerId, TestModifier};
 use snafu::{Report, location};
-use std::collections::HashMap;
-use std::time::SystemTime;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
 use tracing::{error, info, instrument, warn};
 use uuid::Uuid;
 
@@ -39,6 +51,101 @@ pub async fn snapshot_state(
     Ok(Json(res))
 }
 
+#[instrument(skip_all)]
+pub async fn list_snapshots(State(state): State<AppState>) -> Result<Json<Vec<SnapshotInfo>>> {
+    let mut snapshots = read_snapshots(&state)
+        .await
+        .map_err(|e| WebError::internal_error(Report::from_error(&e).to_string(), location!()))?;
+    snapshots.sort_by_key(|it| std::cmp::Reverse(it.created_at));
+
+    Ok(Json(snapshots))
+}
+
+async fn read_snapshots(state: &AppState) -> std::io::Result<Vec<SnapshotInfo>> {
+    let mut snapshots = Vec::new();
+    let snapshot_path = &state.grading_config.snapshot_path;
+    if !tokio::fs::try_exists(snapshot_path).await? {
+        return Ok(snapshots);
+    }
+
+    let mut entries = tokio::fs::read_dir(snapshot_path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Snapshots in progress are written to hidden folders
+        if name.starts_with('.') {
+            continue;
+        }
+        let Ok(metadata) = tokio::fs::metadata(entry.path().join("crow.db")).await else {
+            continue;
+        };
+        let created_at = match name.parse::<jiff::Timestamp>() {
+            Ok(time) => time,
+            Err(_) => metadata
+                .modified()
+                .ok()
+                .and_then(|it| jiff::Timestamp::try_from(it).ok())
+                .unwrap_or(jiff::Timestamp::UNIX_EPOCH),
+        };
+
+        snapshots.push(SnapshotInfo {
+            name,
+            created_at,
+            db_size_bytes: metadata.len(),
+        });
+    }
+
+    Ok(snapshots)
+}
+
+#[instrument(skip_all)]
+pub async fn restore_snapshot(
+    State(state): State<AppState>,
+    claims: Claims,
+    Json(payload): Json<RestoreSnapshotPayload>,
+) -> Result<Json<RestoreResponse>> {
+    // Only accept names we listed ourselves, so nobody can point us at arbitrary files
+    let snapshots = read_snapshots(&state)
+        .await
+        .map_err(|e| WebError::internal_error(Report::from_error(&e).to_string(), location!()))?;
+    if !snapshots.iter().any(|it| it.name == payload.name) {
+        return Err(WebError::named_not_found(
+            format!("snapshot `{}`", payload.name),
+            location!(),
+        ));
+    }
+
+    let backup_path = state
+        .grading_config
+        .snapshot_path
+        .join(format!("crow-before-restore-{}.db", jiff::Timestamp::now()));
+
+    info!(
+        triggered_by = %claims.sub,
+        snapshot = %payload.name,
+        backup = %backup_path.display(),
+        "Restoring database snapshot"
+    );
+
+    let snapshot_db = state
+        .grading_config
+        .snapshot_path
+        .join(&payload.name)
+        .join("crow.db");
+    if let Err(e) = state.db.restore_db(&snapshot_db, &backup_path).await {
+        error!(
+            error = %Report::from_error(&e),
+            snapshot = %payload.name,
+            "Failed to restore snapshot"
+        );
+        return Err(e);
+    }
+
+    Ok(Json(RestoreResponse {
+        restored: payload.name,
+        backup: backup_path.display().to_string(),
+    }))
+}
+
 #[instrument(skip_all)]
 async fn snapshot(state: &AppState) -> Result<SnapshotResponse> {
     let mut errors = Vec::new();
@@ -98,13 +205,16 @@ pub async fn rerun_submissions(
     State(state): State<AppState>,
     Path(category_name): Path<String>,
     claims: Claims,
+    Query(query): Query<CourseQuery>,
 ) -> Result<Json<RerunResponse>> {
     info!(triggered_by = %claims.sub, "Rerunning submissions");
     let mut errors = Vec::new();
     let mut submitted = Vec::new();
 
+    let course = query.course.as_ref().unwrap_or(&claims.course);
     let category_meta = state
         .test_config
+        .course(course)
         .categories
         .get(&category_name)
         .ok_or_else(|| {
@@ -114,10 +224,14 @@ pub async fn rerun_submissions(
     let mut new_tasks = HashMap::new();
 
     for team in state.db.get_teams().await? {
+        if team.course != *course {
+            continue;
+        }
         let final_task = state
             .db
             .get_final_submitted_task_for_team_and_category(
                 &team.id,
+                course,
                 &category_name,
                 category_meta,
                 false, // we do not care, we want to recompute/rerun it
@@ -125,8 +239,12 @@ pub async fn rerun_submissions(
             .await?;
 
         if let Some(task) = final_task {
-            let revision = task.summary().info().revision_id.clone();
-            new_tasks.insert(team.id, revision);
+            let info = task.summary().info();
+            let rerun_of = TaskId::from(info.task_id.clone());
+            new_tasks.insert(
+                team.id,
+                (info.revision_id.clone(), rerun_of, info.commit.clone()),
+            );
         } else {
             errors.push(format!(
                 "No final task found for team `{}` in category `{}`",
@@ -135,7 +253,7 @@ pub async fn rerun_submissions(
         }
     }
 
-    for (team, revision) in new_tasks {
+    for (team, (revision, rerun_of, commit)) in new_tasks {
         info!(
             team = %team,
             revision = %revision,
@@ -152,11 +270,16 @@ pub async fn rerun_submissions(
                 revision,
                 commit_message: format!("Grading rerun for category `{category_name}`"),
                 insert_time: SystemTime::now(),
+                // reruns are deliberate, there is no point in flagging them
+                tree_hash: None,
+                identical_to: None,
+                rerun_of: Some(rerun_of),
+                commit,
             })
             .await?;
         state
             .db
-            .finalize_submission(&team, &task_id, &category_name)
+            .finalize_submission(&team, course, &task_id, &category_name)
             .await?;
 
         submitted.push((team, task_id));
@@ -165,11 +288,104 @@ pub async fn rerun_submissions(
     Ok(Json(RerunResponse { errors, submitted }))
 }
 
+/// Queues the revision of a finished task again, so it is tested against the current test suite.
+/// The new task links back to the original one.
+#[instrument(skip_all)]
+pub async fn rerun_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<TaskId>,
+    claims: Claims,
+) -> Result<Json<WorkItem>> {
+    let (task, _) = state.db.get_task(&task_id).await?;
+    let info = task.info();
+
+    let rerun = WorkItem {
+        id: TaskId::from(Uuid::new_v4().to_string()),
+        team: TeamId::from(info.team_id.clone()),
+        revision: info.revision_id.clone(),
+        commit_message: info.commit_message.clone(),
+        insert_time: SystemTime::now(),
+        tree_hash: None,
+        identical_to: None,
+        rerun_of: Some(task_id.clone()),
+        commit: info.commit.clone(),
+    };
+    state.db.queue_task(rerun.clone()).await?;
+    info!(
+        task = %task_id,
+        rerun = %rerun.id,
+        team = %rerun.team,
+        triggered_by = %claims.sub,
+        "Rerunning task"
+    );
+
+    Ok(Json(rerun))
+}
+
+/// Re-freezes the test suite of a category with the current tests. Categories are frozen
+/// automatically at their lab deadline, this allows admins to include later fixes.
+#[instrument(skip_all)]
+pub async fn freeze_test_suite(
+    State(state): State<AppState>,
+    Path(category_name): Path<String>,
+    claims: Claims,
+    Query(query): Query<CourseQuery>,
+) -> Result<Json<TestSuiteSnapshotSummary>> {
+    let course = query.course.as_ref().unwrap_or(&claims.course);
+    if !state
+        .test_config
+        .course(course)
+        .categories
+        .contains_key(&category_name)
+    {
+        return Err(WebError::named_not_found(
+            format!("Category `{category_name}`"),
+            location!(),
+        ));
+    }
+
+    let snapshot = state
+        .db
+        .freeze_test_suite(course, &category_name, Some(&claims.sub))
+        .await?;
+    info!(
+        course = %course,
+        category = %category_name,
+        tests = snapshot.tests.len(),
+        triggered_by = %claims.sub,
+        "Froze test suite"
+    );
+
+    Ok(Json((&snapshot).into()))
+}
+
 #[instrument(skip_all)]
 pub async fn rehash_tests(State(state): State<AppState>, claims: Claims) -> Result<()> {
     info!(triggered_by = %claims.sub, "Rehashing tests");
 
-    state.db.rehash_tests().await
+    state.db.rehash_tests().await?;
+    state.test_mirror.request_sync();
+    Ok(())
+}
+
+/// Flags duplicated and contradicting tests as well as tests breaking the rules of their category
+#[instrument(skip_all)]
+pub async fn lint_tests(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> Result<Json<TestLintReport>> {
+    let tests = state.db.get_accepted_tests().await?;
+    let report = test_lint::lint_tests(&tests, &state.test_config);
+    info!(
+        triggered_by = %claims.sub,
+        tests = tests.len(),
+        duplicates = report.duplicates.len(),
+        conflicts = report.conflicts.len(),
+        violations = report.violations.len(),
+        "Linted tests"
+    );
+
+    Ok(Json(report))
 }
 
 #[instrument(skip_all)]
@@ -181,7 +397,7 @@ pub async fn team_statistics(
     let tests: HashMap<TeamId, Vec<Test>> =
         state
             .db
-            .get_tests()
+            .get_accepted_tests()
             .await?
             .into_iter()
             .fold(HashMap::new(), |mut acc, test| {
@@ -189,7 +405,10 @@ pub async fn team_statistics(
                 acc
             });
 
+    let snapshots = state.db.get_test_suite_snapshots().await?;
+
     for team in state.db.get_teams().await? {
+        let course_config = state.test_config.course(&team.course);
         let id = team.id;
         let mut entry = TeamStatistics {
             team: id.clone(),
@@ -199,17 +418,21 @@ pub async fn team_statistics(
         for test in tests.get(&id).unwrap_or(&Vec::new()) {
             entry.absorb(test);
         }
-        for (category, meta) in &state.test_config.categories {
-            let finalized_task = state.db.fetch_finalized_task_id(&id, category).await?;
+        for (category, meta) in &course_config.categories {
+            let finalized_task = state
+                .db
+                .fetch_finalized_task_id(&id, &team.course, category)
+                .await?;
 
             if let Some(finalized_task) = finalized_task {
                 let summaries = state
                     .db
                     .get_finished_test_summaries(&finalized_task)
                     .await?;
-                let summaries = state.test_config.get_counting_tests(category, &summaries);
+                let summaries = apply_test_suite_snapshots(summaries, &team.course, &snapshots);
+                let summaries = course_config.get_counting_tests(category, &summaries);
                 let points =
-                    get_grading_points_for_task(&state.test_config, category, meta, &summaries)?;
+                    get_grading_points_for_task(course_config, category, meta, &summaries)?;
                 let finalized_task = FinalizedTask {
                     task_id: finalized_task,
                     statistics: FinishedCompilerTaskStatistics::from(summaries.as_slice()),
@@ -226,12 +449,1011 @@ pub async fn team_statistics(
     Ok(Json(entries))
 }
 
+/// Evaluates a candidate grading formula against the final tasks of all teams in a category, so
+/// its effect can be checked before it is put into the config
+#[instrument(skip_all)]
+pub async fn preview_grading_formula(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<CourseQuery>,
+    Json(payload): Json<GradingPreviewPayload>,
+) -> Result<Json<GradingPreviewResponse>> {
+    let course = query.course.as_ref().unwrap_or(&claims.course);
+    let course_config = state.test_config.course(course);
+    let Some(meta) = course_config.categories.get(&payload.category) else {
+        return Err(WebError::named_not_found(
+            format!("Category `{}`", payload.category),
+            location!(),
+        ));
+    };
+    let formula = match evalexpr::build_operator_tree(&payload.formula) {
+        Ok(formula) => formula,
+        Err(e) => {
+            return Err(WebError::named_bad_request(
+                format!("grading formula: {e}"),
+                location!(),
+            ));
+        }
+    };
+
+    let snapshots = state.db.get_test_suite_snapshots().await?;
+    let mut teams = Vec::new();
+    for team in state.db.get_teams().await? {
+        if team.course != *course {
+            continue;
+        }
+        let Some(task_id) = state
+            .db
+            .fetch_finalized_task_id(&team.id, course, &payload.category)
+            .await?
+        else {
+            teams.push(GradingPreviewEntry {
+                team: team.id,
+                task_id: None,
+                points: None,
+                current_points: None,
+                error: None,
+            });
+            continue;
+        };
+
+        let summaries = state.db.get_finished_test_summaries(&task_id).await?;
+        let summaries = apply_test_suite_snapshots(summaries, course, &snapshots);
+        let summaries = course_config.get_counting_tests(&payload.category, &summaries);
+        let current_points = meta
+            .grading_formula
+            .as_ref()
+            .and_then(|current| get_points_for_task(current, &summaries).ok())
+            .map(|it| it.points);
+        let (points, error) = match get_points_for_task(&formula, &summaries) {
+            Ok(points) => (Some(points.points), None),
+            Err(e) => (None, Some(Report::from_error(&e).to_string())),
+        };
+
+        teams.push(GradingPreviewEntry {
+            team: team.id,
+            task_id: Some(task_id),
+            points,
+            current_points,
+            error,
+        });
+    }
+
+    Ok(Json(GradingPreviewResponse {
+        formula: formula_to_string(&formula),
+        teams,
+    }))
+}
+
+#[instrument(skip_all)]
+pub async fn test_metrics(
+    State(state): State<AppState>,
+    _claims: Claims,
+) -> Result<Json<Vec<TestMetrics>>> {
+    Ok(Json(state.db.get_test_metrics().await?))
+}
+
+/// How many recently finished tasks are used to estimate the task duration of a simulation
+const SIMULATION_SAMPLE_SIZE: i64 = 100;
+/// The longest period a queue simulation may cover
+const MAX_SIMULATION_HOURS: u32 = 24 * 7;
+
+#[instrument(skip_all)]
+pub async fn simulate_queue_load(
+    State(state): State<AppState>,
+    _claims: Claims,
+    Query(query): Query<QueueSimulationQuery>,
+) -> Result<Json<QueueSimulationResponse>> {
+    if query.runners == 0 {
+        return Err(WebError::named_bad_request(
+            "At least one runner is needed".to_string(),
+            location!(),
+        ));
+    }
+    if !query.submissions_per_hour.is_finite() || query.submissions_per_hour < 0.0 {
+        return Err(WebError::named_bad_request(
+            "Submissions per hour must be a non-negative number".to_string(),
+            location!(),
+        ));
+    }
+    let hours = query.hours.unwrap_or(48);
+    if hours == 0 || hours > MAX_SIMULATION_HOURS {
+        return Err(WebError::named_bad_request(
+            format!("Hours must be between 1 and {MAX_SIMULATION_HOURS}"),
+            location!(),
+        ));
+    }
+
+    let task_duration = match query.average_task_duration_ms {
+        Some(ms) => Some(Duration::from_millis(ms)),
+        None => {
+            state
+                .db
+                .get_average_task_duration(SIMULATION_SAMPLE_SIZE)
+                .await?
+        }
+    };
+    let Some(task_duration) = task_duration.filter(|it| !it.is_zero()) else {
+        return Err(WebError::named_bad_request(
+            "No finished tasks to estimate the task duration from".to_string(),
+            location!(),
+        ));
+    };
+
+    let tasting_runners = state.test_tasting.lock().unwrap().get_tasting_runners();
+    let runners = state.executor.lock().unwrap().get_runners(tasting_runners);
+    let executing_tasks = runners
+        .iter()
+        .flat_map(|runner| runner.working_on.as_ref().and_then(|it| it.task()))
+        .map(|it| it.id.clone())
+        .collect::<Vec<_>>();
+    let queued_tasks = state
+        .db
+        .get_queued_tasks()
+        .await?
+        .into_iter()
+        .filter(|item| !executing_tasks.contains(&item.id))
+        .count();
+
+    let params = QueueSimulationParams {
+        runners: query.runners,
+        submissions_per_hour: query.submissions_per_hour,
+        task_duration,
+        queued_tasks,
+        running_tasks: executing_tasks.len(),
+        hours,
+    };
+    let projection = simulate_queue(&params);
+
+    Ok(Json(QueueSimulationResponse {
+        runners: params.runners,
+        current_runners: runners.iter().filter(|it| !it.test_taster).count(),
+        submissions_per_hour: params.submissions_per_hour,
+        average_task_duration_ms: task_duration.as_millis() as u64,
+        queued_tasks,
+        running_tasks: params.running_tasks,
+        projection,
+    }))
+}
+
+#[instrument(skip_all)]
+pub async fn course_milestones(
+    State(state): State<AppState>,
+    _claims: Claims,
+) -> Result<Json<CourseMilestonesResponse>> {
+    Ok(Json(CourseMilestonesResponse {
+        team_count: state.db.get_teams().await?.len(),
+        milestones: state.db.get_course_milestones().await?,
+    }))
+}
+
+#[instrument(skip_all)]
+pub async fn list_provisional_tests(
+    State(state): State<AppState>,
+    _claims: Claims,
+) -> Result<Json<Vec<TestWithTasteTesting>>> {
+    Ok(Json(state.db.get_provisional_tests().await?))
+}
+
+/// Turns a provisional test into a regular test of its category
+#[instrument(skip_all)]
+pub async fn approve_provisional_test(
+    State(state): State<AppState>,
+    Path(test_id): Path<TestId>,
+    claims: Claims,
+) -> Result<Json<Test>> {
+    fetch_provisional_test(&state, &test_id).await?;
+
+    let test = state.db.promote_test(&test_id).await?;
+    info!(test = %test_id, approved_by = %claims.sub, "Approved provisional test");
+    state.test_mirror.request_sync();
+
+    Ok(Json(test))
+}
+
+/// Deletes a provisional test and notifies its owner about the reason
+#[instrument(skip_all)]
+pub async fn reject_provisional_test(
+    State(state): State<AppState>,
+    Path(test_id): Path<TestId>,
+    claims: Claims,
+    Json(payload): Json<RejectTestPayload>,
+) -> Result<()> {
+    let test = fetch_provisional_test(&state, &test_id).await?;
+
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return Err(WebError::named_bad_request(
+            "rejection without a reason".to_string(),
+            location!(),
+        ));
+    }
+
+    let notification = Notification {
+        id: Uuid::new_v4().to_string().into(),
+        team: test.owner.clone(),
+        message: format!(
+            "Your test `{}` for category `{}` was rejected: {reason}",
+            test.id, test.category
+        ),
+        created_at: jiff::Timestamp::now(),
+        read_at: None,
+    };
+    state.db.reject_test(&test_id, &notification).await?;
+    info!(
+        test = %test_id,
+        owner = %test.owner,
+        rejected_by = %claims.sub,
+        reason,
+        "Rejected provisional test"
+    );
+
+    Ok(())
+}
+
+/// Accepts a test the tasting policy held back, so it is run and distributed
+#[instrument(skip_all)]
+pub async fn accept_test(
+    State(state): State<AppState>,
+    Path(test_id): Path<TestId>,
+    claims: Claims,
+) -> Result<()> {
+    state
+        .db
+        .set_test_acceptance(&test_id, &TestAcceptance::Accepted, None)
+        .await?;
+    info!(test = %test_id, accepted_by = %claims.sub, "Accepted test");
+    state.test_mirror.request_sync();
+
+    Ok(())
+}
+
+/// Rejects a test, so it no longer counts until its owner uploads it again
+#[instrument(skip_all)]
+pub async fn reject_test(
+    State(state): State<AppState>,
+    Path(test_id): Path<TestId>,
+    claims: Claims,
+    Json(payload): Json<RejectTestPayload>,
+) -> Result<()> {
+    let Some(test) = state.db.fetch_test(&test_id).await? else {
+        return Err(WebError::not_found(location!()));
+    };
+
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return Err(WebError::named_bad_request(
+            "rejection without a reason".to_string(),
+            location!(),
+        ));
+    }
+
+    let acceptance = TestAcceptance::Rejected {
+        message: format!("The test was rejected by an admin: {reason}"),
+    };
+    let notification = Notification {
+        id: Uuid::new_v4().to_string().into(),
+        team: test.owner.clone(),
+        message: format!("Your test `{test_id}` was rejected: {reason}"),
+        created_at: jiff::Timestamp::now(),
+        read_at: None,
+    };
+    state
+        .db
+        .set_test_acceptance(&test_id, &acceptance, Some(&notification))
+        .await?;
+    info!(
+        test = %test_id,
+        owner = %test.owner,
+        rejected_by = %claims.sub,
+        reason,
+        "Rejected test"
+    );
+    state.test_mirror.request_sync();
+
+    Ok(())
+}
+
+/// Deletes the tasks the retention policy no longer keeps, or only lists them on a dry run
+#[instrument(skip_all)]
+pub async fn prune_old_tasks(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<PruneTasksQuery>,
+) -> Result<Json<RetentionReport>> {
+    let Some(config) = &state.retention_config else {
+        return Err(WebError::named_bad_request(
+            "No retention policy is configured".to_string(),
+            location!(),
+        ));
+    };
+    info!(triggered_by = %claims.sub, dry_run = query.dry_run, "Pruning old tasks");
+
+    let report = crate::task_retention::prune_tasks(&state, config, query.dry_run).await?;
+    info!(
+        tasks = report.tasks.len(),
+        execution_results = report.execution_results,
+        dry_run = query.dry_run,
+        "Pruned old tasks"
+    );
+
+    Ok(Json(report))
+}
+
+/// Checks the database for orphaned and dangling rows, optionally deleting them
+#[instrument(skip_all)]
+pub async fn get_consistency_report(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<ConsistencyQuery>,
+) -> Result<Json<ConsistencyReport>> {
+    info!(triggered_by = %claims.sub, fix = query.fix, "Checking database consistency");
+
+    let report = state.db.check_consistency(query.fix).await?;
+    info!(
+        orphaned_executions = report.orphaned_executions.count,
+        results_of_deleted_tests = report.results_of_deleted_tests.count,
+        finalized_missing_tasks = report.finalized_missing_tasks.count,
+        queued_for_unknown_teams = report.queued_for_unknown_teams.count,
+        fix = query.fix,
+        "Checked database consistency"
+    );
+
+    Ok(Json(report))
+}
+
+/// Exports every test with its metadata as a signed archive, e.g. to bootstrap the next course
+/// instance with
+#[instrument(skip_all)]
+pub async fn export_test_archive(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> Result<Response> {
+    let tests = state.db.get_accepted_tests().await?;
+    let archive = test_archive::write_archive(&tests, &test_archive_keys(&state))?;
+    info!(tests = tests.len(), triggered_by = %claims.sub, "Exported test suite archive");
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"crow-test-suite.zip\"",
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+/// Streams the metadata of the tasks queued in a time range as CSV, for research on how teams
+/// work. Contains neither output nor source, and can pseudonymize teams and tasks.
+#[instrument(skip_all)]
+pub async fn export_tasks_csv(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<TaskExportQuery>,
+) -> Result<Response> {
+    let from = query.from.unwrap_or(jiff::Timestamp::UNIX_EPOCH);
+    let to = query.to.unwrap_or_else(jiff::Timestamp::now);
+    if from >= to {
+        return Err(WebError::named_bad_request(
+            "`from` must be before `to`".to_string(),
+            location!(),
+        ));
+    }
+    // A random salt still hides the teams, but its pseudonyms do not match any other export
+    let salt = query.pseudonymize.then(|| {
+        query
+            .salt
+            .unwrap_or_else(|| Uuid::new_v4().simple().to_string())
+    });
+    info!(
+        triggered_by = %claims.sub,
+        %from,
+        %to,
+        pseudonymize = query.pseudonymize,
+        "Exporting tasks"
+    );
+
+    let csv = task_export::export_tasks_csv(state, from, to, salt);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"crow-tasks.csv\"",
+            ),
+        ],
+        Body::from_stream(csv),
+    )
+        .into_response())
+}
+
+/// Imports the tests of an archive exported by [export_test_archive]. Tests of teams that do not
+/// exist here are handed to the importing team.
+#[instrument(skip_all)]
+pub async fn import_test_archive(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<ImportTestsQuery>,
+    archive: Bytes,
+) -> Result<Json<TestImportReport>> {
+    let tests = test_archive::read_archive(&archive, &test_archive_keys(&state))?;
+    let course = query.course.as_ref().unwrap_or(&claims.course);
+    if state.db.fetch_course(course).await?.is_none() {
+        return Err(WebError::named_not_found(
+            format!("course `{course}`"),
+            location!(),
+        ));
+    }
+
+    let existing_tests = state
+        .db
+        .get_tests()
+        .await?
+        .into_iter()
+        .map(|it| it.id)
+        .collect::<HashSet<_>>();
+    let teams = state
+        .db
+        .get_teams()
+        .await?
+        .into_iter()
+        .map(|it| it.id)
+        .collect::<HashSet<_>>();
+    let categories = state
+        .test_config
+        .course(course)
+        .categories
+        .keys()
+        .map(String::as_str)
+        .collect::<HashSet<_>>();
+
+    let (tests, report) = test_archive::plan_import(
+        tests,
+        &existing_tests,
+        &teams,
+        &categories,
+        query.conflict,
+        &claims.team,
+        course,
+    );
+    state.db.import_tests(&tests).await?;
+    state.test_mirror.request_sync();
+
+    info!(
+        imported = report.imported.len(),
+        overwritten = report.overwritten.len(),
+        skipped = report.skipped.len(),
+        renamed = report.renamed.len(),
+        reassigned = report.reassigned.len(),
+        unknown_categories = ?report.unknown_categories,
+        triggered_by = %claims.sub,
+        "Imported test suite archive"
+    );
+
+    Ok(Json(report))
+}
+
+fn test_archive_keys(state: &AppState) -> Keys {
+    match &state.test_config.archive_secret {
+        Some(secret) => Keys::new(secret.as_bytes()),
+        None => state.jwt_keys.clone(),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn list_managed_teams(State(state): State<AppState>) -> Result<Json<Vec<ManagedTeam>>> {
+    Ok(Json(state.db.get_managed_teams().await?))
+}
+
+/// Creates a team in the database. Unless `team_sync` is `Seed`, it is removed again on restart if
+/// the config does not list it.
+#[instrument(skip_all)]
+pub async fn create_team(
+    State(state): State<AppState>,
+    claims: Claims,
+    Json(payload): Json<CreateTeamPayload>,
+) -> Result<Json<ManagedTeam>> {
+    validate_team_id(&payload.id)?;
+    validate_team_display_name(&payload.display_name)?;
+
+    let team = state
+        .db
+        .create_team(&ManagedTeam {
+            id: payload.id,
+            display_name: payload.display_name,
+            is_admin: payload.is_admin,
+            course: payload.course,
+            members: payload.members,
+        })
+        .await?;
+    info!(team = %team.id, triggered_by = %claims.sub, "Created team");
+
+    Ok(Json(team))
+}
+
+#[instrument(skip_all)]
+pub async fn patch_team(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(team_id): Path<TeamId>,
+    Json(payload): Json<PatchTeamPayload>,
+) -> Result<Json<ManagedTeam>> {
+    if let Some(display_name) = &payload.display_name {
+        validate_team_display_name(display_name)?;
+    }
+
+    let team = state
+        .db
+        .patch_team(
+            &team_id,
+            payload.display_name.as_deref(),
+            payload.is_admin,
+            payload.course.as_ref(),
+        )
+        .await?;
+    info!(
+        team = %team.id,
+        display_name = ?payload.display_name,
+        is_admin = ?payload.is_admin,
+        course = ?payload.course,
+        triggered_by = %claims.sub,
+        "Updated team"
+    );
+
+    Ok(Json(team))
+}
+
+#[instrument(skip_all)]
+pub async fn list_courses(State(state): State<AppState>) -> Result<Json<Vec<Course>>> {
+    Ok(Json(state.db.get_courses().await?))
+}
+
+#[instrument(skip_all)]
+pub async fn create_course(
+    State(state): State<AppState>,
+    claims: Claims,
+    Json(payload): Json<CreateCoursePayload>,
+) -> Result<Json<Course>> {
+    validate_course_id(&payload.id)?;
+    validate_course_display_name(&payload.display_name)?;
+
+    let course = state
+        .db
+        .create_course(&Course {
+            id: payload.id,
+            display_name: payload.display_name,
+            archived: false,
+            created_at: jiff::Timestamp::now(),
+        })
+        .await?;
+    info!(course = %course.id, triggered_by = %claims.sub, "Created course");
+
+    Ok(Json(course))
+}
+
+#[instrument(skip_all)]
+pub async fn patch_course(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(course_id): Path<CourseId>,
+    Json(payload): Json<PatchCoursePayload>,
+) -> Result<Json<Course>> {
+    if let Some(display_name) = &payload.display_name {
+        validate_course_display_name(display_name)?;
+    }
+
+    let course = state
+        .db
+        .patch_course(
+            &course_id,
+            payload.display_name.as_deref(),
+            payload.archived,
+        )
+        .await?;
+    info!(
+        course = %course.id,
+        display_name = ?payload.display_name,
+        archived = ?payload.archived,
+        triggered_by = %claims.sub,
+        "Updated course"
+    );
+
+    Ok(Json(course))
+}
+
+#[instrument(skip_all)]
+pub async fn add_team_member(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path((team_id, user_id)): Path<(TeamId, UserId)>,
+) -> Result<Json<ManagedTeam>> {
+    let team = state.db.add_team_member(&team_id, &user_id).await?;
+    info!(team = %team_id, user = %user_id, triggered_by = %claims.sub, "Added team member");
+
+    Ok(Json(team))
+}
+
+#[instrument(skip_all)]
+pub async fn remove_team_member(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path((team_id, user_id)): Path<(TeamId, UserId)>,
+) -> Result<Json<ManagedTeam>> {
+    let team = state.db.remove_team_member(&team_id, &user_id).await?;
+    info!(team = %team_id, user = %user_id, triggered_by = %claims.sub, "Removed team member");
+
+    Ok(Json(team))
+}
+
+#[instrument(skip_all)]
+pub async fn list_team_invites(State(state): State<AppState>) -> Result<Json<Vec<TeamInvite>>> {
+    Ok(Json(state.db.get_team_invites().await?))
+}
+
+/// Creates a code users without a team can redeem to join the team
+#[instrument(skip_all)]
+pub async fn create_team_invite(
+    State(state): State<AppState>,
+    claims: Claims,
+    Json(payload): Json<CreateTeamInvitePayload>,
+) -> Result<Json<TeamInvite>> {
+    if payload.max_uses < 1 {
+        return Err(WebError::named_bad_request(
+            "maximum number of uses, it must be at least one".to_string(),
+            location!(),
+        ));
+    }
+
+    let invite = TeamInvite {
+        code: Uuid::new_v4().simple().to_string()[..16].to_string(),
+        team_id: payload.team_id,
+        created_by: claims.sub.clone(),
+        created_at: jiff::Timestamp::now(),
+        expires_at: payload.expires_at,
+        max_uses: payload.max_uses,
+        uses: 0,
+    };
+    state.db.create_team_invite(&invite).await?;
+    info!(
+        team = %invite.team_id,
+        max_uses = invite.max_uses,
+        expires_at = ?invite.expires_at,
+        triggered_by = %claims.sub,
+        "Created team invite"
+    );
+
+    Ok(Json(invite))
+}
+
+#[instrument(skip_all)]
+pub async fn delete_team_invite(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(code): Path<String>,
+) -> Result<()> {
+    state.db.delete_team_invite(&code).await?;
+    info!(code = %code, triggered_by = %claims.sub, "Deleted team invite");
+
+    Ok(())
+}
+
+/// Moves a runner between grading and test tasting without restarting it. The role survives
+/// restarts of the runner and the backend, and reaches the runner with its next ping.
+#[instrument(skip_all)]
+pub async fn set_runner_role(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(runner_id): Path<RunnerId>,
+    Json(payload): Json<SetRunnerRolePayload>,
+) -> Result<()> {
+    state
+        .db
+        .set_runner_role(&runner_id, payload.test_taster)
+        .await?;
+    state
+        .executor
+        .lock()
+        .unwrap()
+        .assign_role(&runner_id, payload.test_taster);
+    info!(
+        runner = %runner_id,
+        test_taster = ?payload.test_taster,
+        triggered_by = %claims.sub,
+        "Assigned runner role"
+    );
+
+    Ok(())
+}
+
+/// Asks a runner to push its recent logs. They arrive with its next ping, i.e. within about 15
+/// seconds, and can then be read with [get_runner_logs].
+#[instrument(skip_all)]
+pub async fn request_runner_logs(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(runner_id): Path<RunnerId>,
+) -> Result<()> {
+    if !state.executor.lock().unwrap().request_logs(&runner_id) {
+        return Err(WebError::named_not_found(
+            format!("Runner `{runner_id}`"),
+            location!(),
+        ));
+    }
+    info!(runner = %runner_id, triggered_by = %claims.sub, "Requested runner logs");
+
+    Ok(())
+}
+
+#[instrument(skip_all)]
+pub async fn get_runner_logs(
+    State(state): State<AppState>,
+    Path(runner_id): Path<RunnerId>,
+) -> Result<Json<RunnerLogsForFrontend>> {
+    let logs = state.executor.lock().unwrap().get_logs(&runner_id);
+
+    logs.map(Json)
+        .ok_or_else(|| WebError::named_not_found(format!("Runner `{runner_id}`"), location!()))
+}
+
+fn validate_team_id(team_id: &TeamId) -> Result<()> {
+    let id = team_id.to_string();
+    let valid = !id.is_empty()
+        && id.len() <= 36
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(WebError::named_bad_request(
+            format!("team id `{id}`, use at most 36 alphanumeric characters, `-` or `_`"),
+            location!(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn validate_team_display_name(display_name: &str) -> Result<()> {
+    if display_name.trim().is_empty() || display_name.len() >= 255 {
+        return Err(WebError::named_bad_request(
+            "team display name, it must not be blank and shorter than 255 bytes".to_string(),
+            location!(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn validate_course_id(course_id: &CourseId) -> Result<()> {
+    let id = course_id.to_string();
+    let valid = !id.is_empty()
+        && id.len() <= 36
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(WebError::named_bad_request(
+            format!("course id `{id}`, use at most 36 alphanumeric characters, `-` or `_`"),
+            location!(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn validate_course_display_name(display_name: &str) -> Result<()> {
+    if display_name.trim().is_empty() || display_name.len() >= 255 {
+        return Err(WebError::named_bad_request(
+            "course display name, it must not be blank and shorter than 255 bytes".to_string(),
+            location!(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn fetch_provisional_test(state: &AppState, test_id: &TestId) -> Result<Test> {
+    let Some(test) = state.db.fetch_test(test_id).await? else {
+        return Err(WebError::not_found(location!()));
+    };
+    if test.provisional_for_category.is_none() {
+        return Err(WebError::named_bad_request(
+            format!("review of test `{test_id}` that is not provisional"),
+            location!(),
+        ));
+    }
+
+    Ok(test)
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct SnapshotResponse {
     pub errors: Vec<String>,
     pub exported: Vec<TeamId>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub name: String,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub created_at: jiff::Timestamp,
+    pub db_size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RestoreSnapshotPayload {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTeamPayload {
+    pub id: TeamId,
+    pub display_name: String,
+    #[serde(default)]
+    pub is_admin: bool,
+    #[serde(default)]
+    pub course: CourseId,
+    #[serde(default)]
+    pub members: Vec<UserId>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchTeamPayload {
+    pub display_name: Option<String>,
+    pub is_admin: Option<bool>,
+    /// Moves the team to another course. Its existing tests stay in the course they were made in.
+    pub course: Option<CourseId>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTeamInvitePayload {
+    pub team_id: TeamId,
+    #[serde(default = "default_invite_uses")]
+    pub max_uses: i64,
+    pub expires_at: Option<jiff::Timestamp>,
+}
+
+fn default_invite_uses() -> i64 {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCoursePayload {
+    pub id: CourseId,
+    pub display_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchCoursePayload {
+    pub display_name: Option<String>,
+    /// Archived courses stay readable, but their teams can no longer submit or upload tests
+    pub archived: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRunnerRolePayload {
+    /// Clears the assigned role if missing, so the runner uses the one it registers with
+    pub test_taster: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GradingPreviewPayload {
+    pub formula: String,
+    pub category: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GradingPreviewResponse {
+    /// The parsed formula, with explicit parentheses
+    pub formula: String,
+    pub teams: Vec<GradingPreviewEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GradingPreviewEntry {
+    pub team: TeamId,
+    /// The final task of the team in the category. Teams without one get no points.
+    pub task_id: Option<TaskId>,
+    pub points: Option<f64>,
+    /// The points with the formula of the config, for comparison
+    pub current_points: Option<f64>,
+    /// Why the formula could not be evaluated for the team
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RejectTestPayload {
+    /// Shown to the owner of the test
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConsistencyQuery {
+    #[serde(default)]
+    pub fix: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CourseQuery {
+    /// The course of the category, the one of the requesting team if missing
+    pub course: Option<CourseId>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportTestsQuery {
+    #[serde(default)]
+    pub conflict: TestConflictResolution,
+    /// The course the tests are imported into, the one of the importing team if missing
+    pub course: Option<CourseId>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaskExportQuery {
+    /// Only tasks queued at or after this, defaults to all
+    pub from: Option<jiff::Timestamp>,
+    /// Only tasks queued before this, defaults to now
+    pub to: Option<jiff::Timestamp>,
+    /// Replace team and task ids with salted hashes
+    #[serde(default)]
+    pub pseudonymize: bool,
+    /// Reuse a salt to get the same pseudonyms as a previous export. Random if absent.
+    pub salt: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PruneTasksQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueueSimulationQuery {
+    pub runners: usize,
+    pub submissions_per_hour: f64,
+    /// Defaults to the average of recently finished tasks
+    pub average_task_duration_ms: Option<u64>,
+    pub hours: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueSimulationResponse {
+    pub runners: usize,
+    /// The runners currently connected, for comparison
+    pub current_runners: usize,
+    pub submissions_per_hour: f64,
+    pub average_task_duration_ms: u64,
+    pub queued_tasks: usize,
+    pub running_tasks: usize,
+    pub projection: Vec<ProjectedQueueHour>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreResponse {
+    pub restored: String,
+    /// Where the database was backed up to before restoring
+    pub backup: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CourseMilestonesResponse {
+    pub team_count: usize,
+    pub milestones: Vec<CourseMilestone>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct RerunResponse {
     pub errors: Vec<String>,
@@ -1,6 +1,7 @@
 use super::Json;
 use crate::error::{HttpError, Result, WebError};
-use crate::types::AppState;
+use crate::task_notifications::enqueue_task_notifications;
+use crate::types::{AppState, CourseId, TaskId, Test, TestSuiteSnapshot, WorkItem};
 use axum::body::Body;
 use axum::extract::State;
 use axum::http::StatusCode;
@@ -9,10 +10,12 @@ use axum_extra::TypedHeader;
 use axum_extra::headers::Authorization;
 use axum_extra::headers::authorization::Basic;
 use shared::{
-    CompilerTask, CompilerTest, FinishedCompilerTask, RunnerId, RunnerInfo, RunnerUpdate,
-    RunnerWorkResponse, RunnerWorkTasteTestDone, RunnerWorkTasteTestResponse, WorkTasteTestTask,
+    CompilerTask, CompilerTest, FinishedCompilerTask, RunnerId, RunnerInfo, RunnerLogs,
+    RunnerPingResponse, RunnerWorkResponse, RunnerWorkTasteTestDone, RunnerWorkTasteTestResponse,
+    TaskRunnerUpdate, WorkTasteTestTask,
 };
 use snafu::{IntoError, Location, NoneError, Report, Snafu, ensure, location};
+use std::time::Duration;
 use tokio_util::io::ReaderStream;
 use tracing::{debug, info, instrument, warn};
 
@@ -34,6 +37,15 @@ pub enum ExecutorError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display(
+        "Runner `{runner_id}` no longer holds the lease for task `{task_id}` at {location}"
+    ))]
+    LeaseLost {
+        task_id: String,
+        runner_id: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
     #[snafu(display("There was no work assigned to you we could tar at {location}"))]
     NoWorkWhenTaring {
         #[snafu(implicit)]
@@ -58,6 +70,7 @@ impl HttpError for ExecutorError {
         match self {
             Self::RunnerImpersonation { .. } => StatusCode::UNAUTHORIZED,
             Self::UnknownTask { .. } => StatusCode::NOT_FOUND,
+            Self::LeaseLost { .. } => StatusCode::CONFLICT,
             Self::NoWorkWhenTaring { .. } => StatusCode::NOT_FOUND,
             Self::UnknownRevisionRequested { .. } => StatusCode::NOT_FOUND,
             Self::WorkTarOpen { .. } => StatusCode::INTERNAL_SERVER_ERROR,
@@ -68,6 +81,7 @@ impl HttpError for ExecutorError {
         match self {
             Self::RunnerImpersonation { .. } => "runner_impersonation",
             Self::UnknownTask { .. } => "unknown_task",
+            Self::LeaseLost { .. } => "lease_lost",
             Self::NoWorkWhenTaring { .. } => "no_work_when_taring",
             Self::UnknownRevisionRequested { .. } => "unknown_revision_requested",
             Self::WorkTarOpen { .. } => "work_tar_open",
@@ -96,7 +110,12 @@ pub async fn runner_register(
         }
     );
 
-    state.executor.lock().unwrap().register_runner(&runner);
+    let assigned_role = state.db.get_runner_role(&runner.id).await?;
+    state
+        .executor
+        .lock()
+        .unwrap()
+        .register_runner(&runner, assigned_role);
 
     Ok(())
 }
@@ -105,17 +124,24 @@ pub async fn runner_register(
 pub async fn runner_update(
     State(state): State<AppState>,
     TypedHeader(auth): TypedHeader<Authorization<Basic>>,
-    Json(update): Json<RunnerUpdate>,
+    Json(update): Json<TaskRunnerUpdate>,
 ) -> Result<()> {
     let runner_id: RunnerId = auth.username().to_string().into();
 
     // TODO: Think about protocol errors more
     debug!(runner = %runner_id, update = ?update, "Runner update");
-    state
-        .executor
-        .lock()
-        .unwrap()
-        .update_task(&runner_id, update.into());
+    let task_id = state.executor.lock().unwrap().update_task(
+        &runner_id,
+        update.task_id.map(TaskId::from).as_ref(),
+        update.update.clone().into(),
+    );
+
+    if let Some(task_id) = task_id {
+        state
+            .db
+            .record_partial_update(&task_id, &update.update)
+            .await?;
+    }
 
     Ok(())
 }
@@ -144,6 +170,21 @@ pub async fn runner_done(
         .into());
     }
 
+    let leased_task = state
+        .executor
+        .lock()
+        .unwrap()
+        .get_current_task(&auth.username().to_string().into());
+    if leased_task.map(|it| it.id.to_string()).as_ref() != Some(&task.info().task_id) {
+        warn!(task = %task.info().task_id, "Runner finished task without holding its lease");
+        return Err(LeaseLostSnafu {
+            task_id: task.info().task_id.clone(),
+            runner_id: auth.username().to_string(),
+        }
+        .into_error(NoneError)
+        .into());
+    }
+
     if let Err(e) = state.db.add_finished_task(&task).await {
         warn!(
             task = %task.info().task_id,
@@ -159,6 +200,24 @@ pub async fn runner_done(
         .unwrap()
         .finish_task(&auth.username().to_string().into());
 
+    // The task is safely stored, failing to notify about it should not make the runner retry.
+    // Bisect steps are reported by their bisect instead.
+    let is_bisect_step = state
+        .db
+        .is_bisect_step(&task.info().task_id.clone().into())
+        .await
+        .unwrap_or(false);
+    if is_bisect_step {
+        return Ok(());
+    }
+    if let Err(e) = enqueue_task_notifications(&state, &task).await {
+        warn!(
+            task = %task.info().task_id,
+            error = %Report::from_error(&e),
+            "Failed to queue task notifications"
+        );
+    }
+
     Ok(())
 }
 
@@ -166,12 +225,42 @@ pub async fn runner_done(
 pub async fn runner_ping(
     State(state): State<AppState>,
     TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+) -> Result<Json<RunnerPingResponse>> {
+    let runner_id = auth.username().to_string().into();
+    let (leased_task, test_taster, send_logs) = {
+        let mut executor = state.executor.lock().unwrap();
+        let leased_task = executor.runner_pinged(&runner_id);
+        (
+            leased_task,
+            executor.is_test_taster(&runner_id),
+            executor.take_logs_request(&runner_id),
+        )
+    };
+
+    Ok(Json(RunnerPingResponse {
+        leased_task: leased_task.map(|it| it.to_string()),
+        test_taster,
+        send_logs,
+    }))
+}
+
+/// Receives the recent logs a runner was asked for in a ping response
+#[instrument(skip_all)]
+pub async fn runner_logs(
+    State(state): State<AppState>,
+    TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+    Json(logs): Json<RunnerLogs>,
 ) -> Result<()> {
-    state
-        .executor
-        .lock()
-        .unwrap()
-        .runner_pinged(&auth.username().to_string().into());
+    let runner_id: RunnerId = auth.username().to_string().into();
+    let lines = logs.lines.len();
+    if !state.executor.lock().unwrap().store_logs(&runner_id, logs) {
+        return Err(WebError::named_not_found(
+            format!("Runner `{runner_id}`"),
+            location!(),
+        ));
+    }
+    debug!(runner = %runner_id, lines = %lines, "Received runner logs");
+
     Ok(())
 }
 
@@ -189,6 +278,23 @@ pub async fn get_work(
         .into_error(NoneError)
         .into());
     }
+    let (tasting_runners, open_tastings) = {
+        let tasting = state.test_tasting.lock().unwrap();
+        (tasting.get_tasting_runners(), tasting.open_tastings())
+    };
+    // Test tasters prefer tastings and only help out with full tasks when they have nothing to
+    // taste. Runners moved to tasting learn about it with their next ping.
+    let taster_waits = {
+        let executor = state.executor.lock().unwrap();
+        executor.is_test_taster(&runner.id) == Some(true)
+            && !executor.taster_may_grade(&runner.id, open_tastings, &tasting_runners)
+    };
+    if taster_waits {
+        return Ok(Json(RunnerWorkResponse {
+            task: None,
+            reset: false,
+        }));
+    }
     if let Some(task) = runner.current_task {
         warn!(runner = %runner.id, task = %task, "Runner already has a task, resetting it");
         return Ok(Json(RunnerWorkResponse {
@@ -197,41 +303,34 @@ pub async fn get_work(
         }));
     }
 
-    let current_categories = state.test_config.active_categories();
     let queued_tasks = state.db.get_queued_tasks().await?;
-    let tests: Vec<CompilerTest> = state
-        .db
-        .get_tests()
-        .await?
-        .into_iter()
-        .filter(|test| {
-            !test.limited_to_category || current_categories.contains(&test.category.as_str())
-        })
-        .map(|test| CompilerTest {
-            test_id: test.id.to_string(),
-            category: test.category,
-            timeout: state.execution_config.test_timeout,
-            compile_command: state.execution_config.compile_command.clone(),
-            binary_arguments: state.execution_config.binary_arguments.clone(),
-            binary_modifiers: test.binary_modifiers,
-            compiler_modifiers: test.compiler_modifiers,
-            // we leave it out if the category is not active, so it is not marked as provisional
-            // on new runs after the category finished.
-            provisional_for_category: test
-                .provisional_for_category
-                .filter(|it| current_categories.contains(&it.as_str())),
-        })
-        .collect();
-
-    let test_ids = tests
-        .iter()
-        .map(|it| it.test_id.clone().into())
-        .collect::<Vec<_>>();
+    let all_tests = state.db.get_accepted_tests().await?;
+    let snapshots = state.db.get_test_suite_snapshots().await?;
+    let frozen_reruns = state.db.get_queued_frozen_reruns().await?;
+    let team_courses = state.db.get_team_courses().await?;
+    let bisect_tests = state.db.get_queued_bisect_tests().await?;
+    let course_of = |task: &WorkItem| team_courses.get(&task.team).cloned().unwrap_or_default();
+    let tests_for = |task: &WorkItem| {
+        let course = course_of(task);
+        let frozen_categories = frozen_reruns.get(&task.id).map(Vec::as_slice);
+        let mut tests = tests_for_task(
+            &course,
+            &all_tests,
+            &snapshots,
+            frozen_categories.unwrap_or_default(),
+            &state.test_config.course(&course).active_categories(),
+        );
+        // Bisect steps only run the test they bisect
+        if let Some(test_id) = bisect_tests.get(&task.id) {
+            tests.retain(|it| &it.id == test_id);
+        }
+        tests
+    };
 
     let task = match state.executor.lock().unwrap().assign_work(
         &runner,
         &queued_tasks,
-        test_ids,
+        |task| tests_for(task).into_iter().map(|it| it.id).collect(),
         state.queue,
     ) {
         Err(e) => {
@@ -255,6 +354,48 @@ pub async fn get_work(
         }));
     };
 
+    let course_config = state.test_config.course(&course_of(&task));
+    let current_categories = course_config.active_categories();
+    let tests: Vec<CompilerTest> = tests_for(&task)
+        .into_iter()
+        .map(|test| CompilerTest {
+            compile_only: course_config.is_compile_only(&test.category),
+            profiles: course_config.profiles_for(&test.category),
+            runner_prefix: course_config.runner_prefix_for(&test.category),
+            permissions: course_config.permissions_for(&test.category),
+            binary_environment: course_config.binary_environment_for(&test.category),
+            test_id: test.id.to_string(),
+            depends_on: test.depends_on.iter().map(ToString::to_string).collect(),
+            category: test.category,
+            timeout: test
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(state.execution_config.test_timeout),
+            compile_command: state.execution_config.compile_command.clone(),
+            binary_arguments: state.execution_config.binary_arguments.clone(),
+            binary_modifiers: test.binary_modifiers,
+            compiler_modifiers: test.compiler_modifiers,
+            // we leave it out if the category is not active, so it is not marked as provisional
+            // on new runs after the category finished.
+            provisional_for_category: test
+                .provisional_for_category
+                .filter(|it| current_categories.contains(&it.as_str())),
+        })
+        .collect();
+
+    let filesets = tests
+        .iter()
+        .filter_map(|test| {
+            let fileset = course_config.fileset_for(&test.category)?;
+            Some((test.category.clone(), fileset))
+        })
+        .collect();
+    let categories = tests
+        .iter()
+        .map(|it| it.category.as_str())
+        .collect::<Vec<_>>();
+    let build_permissions = course_config.build_permissions_for(&categories);
+
     let task = CompilerTask {
         task_id: task.id.to_string(),
         team_id: task.team.to_string(),
@@ -264,6 +405,9 @@ pub async fn get_work(
         build_command: state.execution_config.build_command,
         build_timeout: state.execution_config.build_timeout,
         tests,
+        filesets,
+        build_permissions,
+        record_output: state.execution_config.record_output,
     };
 
     Ok(Json(RunnerWorkResponse {
@@ -272,6 +416,46 @@ pub async fn get_work(
     }))
 }
 
+/// Selects the tests to run for a task, practice tests are never run. Reruns of final submissions
+/// for frozen categories use the frozen tests of those categories instead of their current
+/// versions.
+fn tests_for_task(
+    course: &CourseId,
+    all_tests: &[Test],
+    snapshots: &[TestSuiteSnapshot],
+    frozen_categories: &[String],
+    current_categories: &[&str],
+) -> Vec<Test> {
+    let snapshots = snapshots
+        .iter()
+        .filter(|it| it.course == *course && frozen_categories.contains(&it.category))
+        .collect::<Vec<_>>();
+
+    let mut tests = all_tests
+        .iter()
+        .filter(|test| test.course == *course)
+        .filter(|test| !test.practice)
+        .filter(|test| {
+            !test.limited_to_category || current_categories.contains(&test.category.as_str())
+        })
+        .filter(|test| {
+            !snapshots
+                .iter()
+                .any(|it| it.category == test.category || it.contains(&test.id))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    tests.extend(
+        snapshots
+            .iter()
+            .flat_map(|it| &it.tests)
+            .filter(|test| test.course == *course)
+            .cloned(),
+    );
+
+    tests
+}
+
 #[instrument(skip_all)]
 pub async fn get_work_tar(
     State(state): State<AppState>,
@@ -329,25 +513,42 @@ pub async fn get_test_tasting_work(
     State(state): State<AppState>,
     TypedHeader(auth): TypedHeader<Authorization<Basic>>,
 ) -> Result<Json<RunnerWorkTasteTestResponse>> {
-    let Some(image_id) = state.execution_config.reference_compiler_image else {
-        return Ok(Json(RunnerWorkTasteTestResponse { task: None }));
-    };
     let runner_id = auth.username().to_string().into();
+    // The runner was moved to grading and will learn about it with its next ping
+    if state.executor.lock().unwrap().is_test_taster(&runner_id) == Some(false) {
+        return Ok(Json(RunnerWorkTasteTestResponse { task: None }));
+    }
 
     let task = state.test_tasting.lock().unwrap().poll_tasting(runner_id);
-    let task = task.map(|task| WorkTasteTestTask {
-        id: task.taste_id.clone(),
-        test: CompilerTest {
-            test_id: task.test.id.to_string(),
-            category: task.test.category,
-            timeout: state.execution_config.test_timeout,
-            compile_command: state.execution_config.compile_command,
-            binary_arguments: state.execution_config.binary_arguments,
-            compiler_modifiers: task.test.compiler_modifiers,
-            binary_modifiers: task.test.binary_modifiers,
-            provisional_for_category: task.test.provisional_for_category,
-        },
-        image_id,
+    let task = task.map(|task| {
+        let course_config = state.test_config.course(&task.test.course);
+        WorkTasteTestTask {
+            fileset: course_config.fileset_for(&task.test.category),
+            id: task.taste_id.clone(),
+            test: CompilerTest {
+                compile_only: course_config.is_compile_only(&task.test.category),
+                runner_prefix: course_config.runner_prefix_for(&task.test.category),
+                permissions: course_config.permissions_for(&task.test.category),
+                binary_environment: course_config.binary_environment_for(&task.test.category),
+                test_id: task.test.id.to_string(),
+                category: task.test.category,
+                timeout: task
+                    .test
+                    .timeout_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or(state.execution_config.test_timeout),
+                compile_command: task.compile_command,
+                binary_arguments: state.execution_config.binary_arguments,
+                compiler_modifiers: task.test.compiler_modifiers,
+                binary_modifiers: task.test.binary_modifiers,
+                // Tasting runs a single test, so there is nothing to wait for
+                depends_on: Vec::new(),
+                provisional_for_category: task.test.provisional_for_category,
+                // taste testing only checks the test against the reference compiler once
+                profiles: Vec::new(),
+            },
+            image_id: task.image_id,
+        }
     });
 
     Ok(Json(RunnerWorkTasteTestResponse { task }))
@@ -0,0 +1,94 @@
+use crate::auth::create_jwt;
+use crate::auth::ldap::LdapError;
+use crate::auth::provider::{AuthProviderKind, LoginUser};
+use crate::endpoints::Json;
+use crate::endpoints::user::LoginResponse;
+use crate::error::{HttpError, Result, WebError};
+use crate::types::{AppState, UserRole};
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use snafu::{Report, location};
+use tracing::{info, warn};
+
+/// Tells the frontend whether to redirect to the OIDC provider or ask for a password
+pub async fn get_login_provider(State(state): State<AppState>) -> Json<LoginProviderResponse> {
+    Json(LoginProviderResponse {
+        provider: state.auth.kind(),
+    })
+}
+
+pub async fn login_password(
+    State(state): State<AppState>,
+    Json(payload): Json<PasswordLoginPayload>,
+) -> Result<Json<LoginResponse>> {
+    if !state.auth.takes_passwords() {
+        return Err(WebError::named_bad_request(
+            "Password logins are disabled, log in through OIDC".to_string(),
+            location!(),
+        ));
+    }
+
+    let res = state
+        .auth
+        .log_in_with_password(&payload.username, &payload.password)
+        .await;
+    let user = match res {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            info!(user = %payload.username, "Password login with wrong credentials");
+            return Err(WebError::invalid_credentials(location!()));
+        }
+        Err(e) => {
+            warn!(user = %payload.username, error = %Report::from_error(&e), "Password login failed");
+            return Err(WebError::http_error(e, location!()));
+        }
+    };
+
+    let response = log_in(&state, user).await?;
+    info!(
+        user = %response.user.user.id,
+        user_name = %response.user.user.display_name,
+        "Password login successful"
+    );
+
+    Ok(Json(response))
+}
+
+/// Stores the user the provider vouched for and hands out their token, the same for all providers
+pub(super) async fn log_in(state: &AppState, user: LoginUser) -> Result<LoginResponse> {
+    let mapping = state
+        .db
+        .get_team_membership(&user.id.clone().into())
+        .await?;
+    let role = mapping.as_ref().map(|it| it.1);
+    let team = mapping.map(|it| it.0);
+    let own_user = state.db.synchronize_login_user(user, team, role).await?;
+    let token = create_jwt(own_user.user.id.clone(), &state.jwt_keys, UserRole::Regular)?;
+
+    Ok(LoginResponse {
+        user: own_user,
+        token,
+    })
+}
+
+#[derive(Serialize)]
+pub struct LoginProviderResponse {
+    provider: AuthProviderKind,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordLoginPayload {
+    username: String,
+    password: String,
+}
+
+impl HttpError for LdapError {
+    fn to_http_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    fn to_error_code(&self) -> &'static str {
+        "ldap_error"
+    }
+}
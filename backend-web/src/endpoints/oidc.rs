@@ -1,10 +1,10 @@
-use crate::auth::create_jwt;
 use crate::auth::oidc::{OidcError, OidcFlowId};
 use crate::endpoints::Json;
+use crate::endpoints::login::log_in;
 use crate::endpoints::user::LoginResponse;
 use crate::error::WebError;
 use crate::error::{HttpError, Result};
-use crate::types::{AppState, UserRole};
+use crate::types::AppState;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::Redirect;
@@ -18,7 +18,13 @@ pub async fn login_oidc(
     State(state): State<AppState>,
     cookies: CookieJar,
 ) -> Result<(CookieJar, Redirect)> {
-    let oidc_auth_redirect = state.oidc.get_oidc_auth_redirect().await;
+    let Some(oidc) = state.auth.oidc() else {
+        return Err(WebError::named_not_found(
+            "OIDC login".to_string(),
+            location!(),
+        ));
+    };
+    let oidc_auth_redirect = oidc.get_oidc_auth_redirect().await;
     let cookies = cookies.add(
         Cookie::build(("oidc_flow_id", oidc_auth_redirect.flow_id.to_string()))
             .http_only(true)
@@ -47,8 +53,13 @@ pub async fn login_oidc_callback(
 
     info!(flow_id = %flow_id, "Handling OIDC callback");
 
-    let res = state
-        .oidc
+    let Some(oidc) = state.auth.oidc() else {
+        return Err(WebError::named_not_found(
+            "OIDC login".to_string(),
+            location!(),
+        ));
+    };
+    let res = oidc
         .handle_oidc_callback(
             flow_id.clone(),
             &oidc_callback_payload.code,
@@ -64,15 +75,8 @@ pub async fn login_oidc_callback(
         }
     };
 
-    let mapping = state.team_mapping.get(&user.id.clone().into()).cloned();
-    let role = mapping.as_ref().map(|it| it.1);
-    let team = mapping.map(|it| it.0);
-    let own_user = state
-        .db
-        .synchronize_oidc_user(user.clone(), team, role)
-        .await?;
-    let user = &own_user.user;
-    let jwt = create_jwt(user.id.clone(), &state.jwt_keys, UserRole::Regular)?;
+    let response = log_in(&state, user).await?;
+    let user = &response.user.user;
 
     info!(
         flow_id = flow_id.to_string(),
@@ -81,13 +85,7 @@ pub async fn login_oidc_callback(
         "OIDC login successful"
     );
 
-    Ok((
-        cookies.remove("oidc_flow_id"),
-        Json(LoginResponse {
-            user: own_user,
-            token: jwt,
-        }),
-    ))
+    Ok((cookies.remove("oidc_flow_id"), Json(response)))
 }
 
 #[derive(Deserialize)]
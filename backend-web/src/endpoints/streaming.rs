@@ -1,18 +1,25 @@
-use super::Path;
+use super::{Path, Query};
 use crate::auth::Claims;
 use crate::error::WebError;
-use crate::types::{AppState, RunningTaskState, TaskId, TeamId};
+use crate::types::{AppState, RunningTaskState, TaskId, TeamId, TestId};
 use axum::extract::ws::{Message, WebSocket};
 use axum::extract::{State, WebSocketUpgrade};
+use axum::http::HeaderMap;
 use axum::response::Response;
 use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
 use serde_json::json;
 use snafu::{IntoError, Location, NoneError, Report, ResultExt, Snafu, location};
+use std::collections::HashSet;
 use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::{select, time};
 use tracing::{debug, info, instrument};
 
+/// Proxies close connections that are idle for too long
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
 #[derive(Debug, Snafu)]
 enum WebsocketError {
     #[snafu(display("Client sent no hello at {location}"))]
@@ -44,12 +51,24 @@ enum WebsocketError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Failed to send keep-alive at {location}"))]
+    KeepAliveSend {
+        source: axum::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
     #[snafu(display("Failed to send update at {location}"))]
     UpdateSend {
         source: axum::Error,
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Could not fetch the hidden tests at {location}"))]
+    HiddenTests {
+        source: WebError,
+        #[snafu(implicit)]
+        location: Location,
+    },
     #[snafu(display("No claims sent within timeout"))]
     NoClaimsSent { source: time::error::Elapsed },
 }
@@ -76,8 +95,17 @@ pub async fn head_running_task_info(
 pub async fn get_running_task_info(
     State(app_state): State<AppState>,
     Path(task_id): Path<TaskId>,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
     ws: WebSocketUpgrade,
 ) -> Result<Response, WebError> {
+    // Browsers can not set headers for websockets, so they use the query parameter instead
+    let last_event_id = headers
+        .get("Last-Event-ID")
+        .and_then(|it| it.to_str().ok())
+        .and_then(|it| it.parse::<u64>().ok())
+        .or(query.last_event_id);
+
     let Some(state) = app_state
         .executor
         .lock()
@@ -87,9 +115,9 @@ pub async fn get_running_task_info(
         return Err(WebError::not_found(location!()));
     };
 
-    Ok(ws.on_upgrade(|ws| async move {
+    Ok(ws.on_upgrade(move |ws| async move {
         let (mut ws_write, ws_read) = ws.split();
-        let res = handle_websocket(&app_state, state, ws_read, &mut ws_write).await;
+        let res = handle_websocket(&app_state, state, last_event_id, ws_read, &mut ws_write).await;
         if let Err(e) = res {
             debug!(error = %Report::from_error(&e), "Error handling websocket");
             let _ = ws_write
@@ -105,20 +133,30 @@ pub async fn get_running_task_info(
 async fn handle_websocket(
     app_state: &AppState,
     state: RunningTaskState,
+    last_event_id: Option<u64>,
     mut ws_read: SplitStream<WebSocket>,
     ws_write: &mut SplitSink<WebSocket, Message>,
 ) -> Result<(), WebsocketError> {
     // Get greeted
-    let _claims = time::timeout(
+    let claims = time::timeout(
         Duration::from_secs(10),
         read_client_claims(app_state, &mut ws_read),
     )
     .await
     .context(NoClaimsSentSnafu)??;
+    let hidden_tests = if claims.is_admin() {
+        HashSet::new()
+    } else {
+        app_state
+            .db
+            .get_hidden_test_ids(&claims.team)
+            .await
+            .context(HiddenTestsSnafu)?
+    };
 
     select! {
         _ = answer_pings(ws_read) => Ok(()),
-        e = stream_events(state, ws_write) => e
+        e = stream_events(state, last_event_id, &hidden_tests, ws_write) => e
     }
 }
 
@@ -153,19 +191,45 @@ async fn read_client_claims(
     Ok(claims)
 }
 
+/// Sends all updates after `last_event_id` and then every new one as it arrives
 #[instrument(skip_all)]
 async fn stream_events(
     mut state: RunningTaskState,
+    last_event_id: Option<u64>,
+    hidden_tests: &HashSet<TestId>,
     write: &mut SplitSink<WebSocket, Message>,
 ) -> Result<(), WebsocketError> {
-    for update in state.so_far {
+    let is_new = |id: u64| last_event_id.is_none_or(|last| id > last);
+
+    for update in state.so_far.into_iter().filter(|it| is_new(it.id)) {
+        let update = update.mask_hidden_tests(hidden_tests);
         write
             .send(Message::Text(serde_json::to_string(&update).unwrap()))
             .await
             .context(InitialDataSendSnafu)?;
     }
 
-    while let Ok(update) = state.receiver.recv().await {
+    let mut keep_alive = time::interval(KEEP_ALIVE_INTERVAL);
+    loop {
+        let update = select! {
+            update = state.receiver.recv() => update,
+            _ = keep_alive.tick() => {
+                write
+                    .send(Message::Ping(Vec::new()))
+                    .await
+                    .context(KeepAliveSendSnafu)?;
+                continue;
+            }
+        };
+        let update = match update {
+            Ok(update) => update,
+            // The client can reconnect with the id of the last update it received
+            Err(RecvError::Lagged(_)) | Err(RecvError::Closed) => break,
+        };
+        if !is_new(update.id) {
+            continue;
+        }
+        let update = update.mask_hidden_tests(hidden_tests);
         write
             .send(Message::Text(serde_json::to_string(&update).unwrap()))
             .await
@@ -174,3 +238,8 @@ async fn stream_events(
 
     Ok(())
 }
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    last_event_id: Option<u64>,
+}
@@ -1,23 +1,33 @@
-use super::{Json, Path};
-use crate::auth::Claims;
+use super::test::http_date;
+use super::{Json, Path, Query, json_with_etag};
+use crate::auth::{Claims, ScopedClaims, scopes};
 use crate::error::{Result, WebError};
+use crate::output_storage::{FullOutput, OutputStorage};
 use crate::types::{
-    AppState, ExecutorInfo, FinishedCompilerTaskStatistics, FinishedCompilerTaskSummary,
-    QueuedTaskStatus, RunnerForFrontend, TaskId, TeamId, TestId, WorkItem,
+    AppState, Bisect, BisectId, ExecutorInfo, FailureCluster, FinishedCompilerTaskStatistics,
+    FinishedCompilerTaskSummary, OutputStream, QueueStatisticsDay, QueuedTaskStatus,
+    RunnerForFrontend, TaskId, TeamId, TestId, WorkItem, cluster_failures,
 };
+use crate::{bisect, output_storage, repro_bundle};
+use axum::body::Body;
 use axum::extract::State;
+use axum::http::{HeaderMap, HeaderName, header};
 use axum::response::{IntoResponse, Response};
 use axum_extra::TypedHeader;
 use axum_extra::headers::Authorization;
 use axum_extra::headers::authorization::Bearer;
+use jiff::{SignedDuration, Timestamp};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use shared::repro::ReproManifest;
 use shared::{
-    ExecutionOutput, FinishedCompilerTask, FinishedExecution, FinishedTaskInfo, FinishedTest,
+    CompilerTest, ExecutionOutput, FinishedCompilerTask, FinishedExecution, FinishedTaskInfo,
+    FinishedTest, PinnedEnvironment, TestExecutionOutput, TestInvocation,
 };
 use snafu::location;
-use std::collections::HashMap;
-use std::time::SystemTime;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+use tokio_util::io::ReaderStream;
 use tracing::{info, instrument};
 use uuid::Uuid;
 
@@ -26,6 +36,7 @@ pub async fn integration_request_revision(
     State(state): State<AppState>,
     TypedHeader(Authorization(auth)): TypedHeader<Authorization<Bearer>>,
     Path(revision): Path<String>,
+    Query(query): Query<QueueTaskQuery>,
     payload: Option<Json<IntegrationRequestRevisionPayload>>,
 ) -> Result<Response> {
     let token = auth.token().to_string().into();
@@ -35,7 +46,14 @@ pub async fn integration_request_revision(
 
     info!(revision = %revision, team = %team_id, "Integration requested revision run");
 
-    queue_task(state, &revision, team_id, payload.map(|it| it.0)).await
+    queue_task(
+        state,
+        &revision,
+        team_id,
+        query.force,
+        payload.map(|it| it.0),
+    )
+    .await
 }
 
 #[instrument(skip_all)]
@@ -81,27 +99,102 @@ pub async fn integration_get_task_status(
 #[instrument(skip_all)]
 pub async fn request_revision(
     State(state): State<AppState>,
-    claims: Claims,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::QueueWrite>,
     Path(revision): Path<String>,
+    Query(query): Query<QueueTaskQuery>,
 ) -> Result<Response> {
-    queue_task(state, &revision, claims.team, None).await
+    queue_task(state, &revision, claims.team, query.force, None).await
+}
+
+/// Searches the revisions between a passing and a failing one for the first one failing the test.
+/// Every step is a queued task running only that test, which can be followed through its stream
+/// like any other task.
+#[instrument(skip_all)]
+pub async fn start_bisect(
+    State(state): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::QueueWrite>,
+    Json(payload): Json<StartBisectPayload>,
+) -> Result<Json<Bisect>> {
+    let test_id: TestId = payload.test_id.into();
+    if hidden_tests_for(&state, &claims).await?.contains(&test_id) {
+        return Err(WebError::named_not_found(
+            format!("Test `{test_id}`"),
+            location!(),
+        ));
+    }
+
+    let bisect =
+        bisect::start_bisect(&state, &claims.team, &test_id, &payload.good, &payload.bad).await?;
+
+    Ok(Json(bisect))
+}
+
+/// Shows the progress of a bisect of the own team
+#[instrument(skip_all)]
+pub async fn get_bisect(
+    State(state): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::QueueRead>,
+    Path(bisect_id): Path<BisectId>,
+) -> Result<Json<Bisect>> {
+    let bisect = state.db.get_bisect(&bisect_id).await?;
+    let Some(bisect) = bisect.filter(|it| claims.is_admin() || it.team_id == claims.team) else {
+        return Err(WebError::named_not_found(
+            format!("Bisect `{bisect_id}`"),
+            location!(),
+        ));
+    };
+
+    Ok(Json(bisect))
 }
 
 async fn queue_task(
     state: AppState,
     revision: &str,
     team: TeamId,
+    force: bool,
     overrides: Option<IntegrationRequestRevisionPayload>,
 ) -> Result<Response> {
+    let course = state.db.get_team(&team).await?.course;
+    if state
+        .db
+        .fetch_course(&course)
+        .await?
+        .is_some_and(|it| it.archived)
+    {
+        return Err(WebError::named_unauthorized(
+            format!("submit to the archived course `{course}`"),
+            location!(),
+        ));
+    }
+
     // Update repo to ensure revision is present
     let repo = state.db.get_repo(&team).await?;
     state.local_repos.update_repo(&repo).await?;
     let Some(revision) = state.local_repos.get_revision(&repo, revision).await? else {
         return Err(WebError::named_not_found(
-            format!("Revision `{revision}`"),
+            format!("Revision `{revision}` in your team repository"),
             location!(),
         ));
     };
+
+    // The same revision might be submitted by an integration and manually at the same time.
+    // Running it twice is just a waste of runner time.
+    if !force {
+        let existing = state
+            .db
+            .fetch_queued_task_for_revision(&team, &revision.to_string())
+            .await?;
+        if let Some(existing) = existing {
+            info!(
+                task_id = %existing.id,
+                revision = %revision,
+                team = %team,
+                "Revision already queued, not queueing it again"
+            );
+            return Ok(Json(json!({ "taskId": existing.id, "deduplicated": true })).into_response());
+        }
+    }
+
     let commit_message = match overrides.as_ref().map(|it| it.commit_message.clone()) {
         Some(message) => message,
         None => {
@@ -112,6 +205,27 @@ async fn queue_task(
         }
     };
 
+    let commit = state
+        .local_repos
+        .get_revision_metadata(&repo, &revision)
+        .await?;
+
+    // Near deadlines teams tend to resubmit unchanged sources (e.g. after rebasing or with an
+    // empty commit). Point them to the existing results instead of silently grading it again.
+    let tree_hash = state.local_repos.get_tree_hash(&repo, &revision).await?;
+    let identical_to = state
+        .db
+        .fetch_task_with_tree_hash(&team, &tree_hash)
+        .await?;
+    if let Some(identical_to) = &identical_to {
+        info!(
+            revision = %revision,
+            team = %team,
+            identical_to = %identical_to,
+            "Queued revision has the same source tree as a finished task"
+        );
+    }
+
     let task_id: TaskId = Uuid::new_v4().to_string().into();
     let task = WorkItem {
         id: task_id.clone(),
@@ -119,6 +233,10 @@ async fn queue_task(
         revision: revision.to_string(),
         commit_message,
         insert_time: SystemTime::now(),
+        tree_hash: Some(tree_hash),
+        identical_to: identical_to.clone(),
+        rerun_of: None,
+        commit: Some(commit),
     };
     state.db.queue_task(task.clone()).await?;
 
@@ -138,13 +256,18 @@ async fn queue_task(
         "Queued task"
     );
 
-    Ok(Json(json!({ "taskId": task_id })).into_response())
+    Ok(Json(json!({
+        "taskId": task_id,
+        "deduplicated": false,
+        "identicalTo": identical_to
+    }))
+    .into_response())
 }
 
 #[instrument(skip_all)]
 pub async fn get_queue(
     State(state): State<AppState>,
-    _claims: Claims,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::QueueRead>,
 ) -> Result<Json<QueueResponse>> {
     let tasting_runners = state.test_tasting.lock().unwrap().get_tasting_runners();
     let runners = state.executor.lock().unwrap().get_runners(tasting_runners);
@@ -166,6 +289,14 @@ pub async fn get_queue(
 
     executing_tasks.extend(queue);
 
+    // Runners are shared, but every course only sees its own tasks
+    if !claims.is_admin() {
+        let team_courses = state.db.get_team_courses().await?;
+        executing_tasks.retain(|task| {
+            team_courses.get(&task.team).cloned().unwrap_or_default() == claims.course
+        });
+    }
+
     Ok(Json(QueueResponse {
         queue: executing_tasks,
         runners,
@@ -175,38 +306,447 @@ pub async fn get_queue(
 #[instrument(skip_all)]
 pub async fn get_queued_task(
     State(state): State<AppState>,
-    _claims: Claims,
+    _claims: ScopedClaims<scopes::QueueRead>,
     Path(task_id): Path<TaskId>,
-) -> Result<Json<WorkItem>> {
-    if state
-        .executor
-        .lock()
-        .unwrap()
-        .get_running_task(&task_id)
-        .is_some()
-    {
+) -> Result<Json<QueuedTaskResponse>> {
+    let Some(position) = get_queue_position_of(&state, &task_id).await? else {
         return Err(WebError::not_found(location!()));
-    }
-    let Some(item) = state.db.fetch_queued_task(&task_id).await? else {
+    };
+    let Some(task) = state.db.fetch_queued_task(&task_id).await? else {
         return Err(WebError::not_found(location!()));
     };
 
-    Ok(Json(item))
+    Ok(Json(QueuedTaskResponse { task, position }))
 }
 
+#[instrument(skip_all)]
+pub async fn get_queue_position(
+    State(state): State<AppState>,
+    _claims: ScopedClaims<scopes::QueueRead>,
+    Path(task_id): Path<TaskId>,
+) -> Result<Json<QueuePosition>> {
+    let Some(position) = get_queue_position_of(&state, &task_id).await? else {
+        return Err(WebError::not_found(location!()));
+    };
+
+    Ok(Json(position))
+}
+
+/// How many recently finished tasks are used to estimate how long a task takes
+const ETA_SAMPLE_SIZE: i64 = 20;
+
+/// Returns the position of a waiting task, or [None] if it is unknown or already running.
+async fn get_queue_position_of(
+    state: &AppState,
+    task_id: &TaskId,
+) -> Result<Option<QueuePosition>> {
+    let tasting_runners = state.test_tasting.lock().unwrap().get_tasting_runners();
+    let runners = state.executor.lock().unwrap().get_runners(tasting_runners);
+
+    let executing_tasks = runners
+        .iter()
+        .flat_map(|runner| runner.working_on.as_ref().and_then(|it| it.task()))
+        .map(|it| it.id.clone())
+        .collect::<Vec<_>>();
+    if executing_tasks.contains(task_id) {
+        return Ok(None);
+    }
+
+    let queue = state
+        .db
+        .get_queued_tasks()
+        .await?
+        .into_iter()
+        .filter(|item| !executing_tasks.contains(&item.id))
+        .collect::<Vec<_>>();
+    let queue = state.queue.lock().unwrap().reorder_queue(queue);
+
+    let Some(position) = queue.iter().position(|it| &it.id == task_id) else {
+        return Ok(None);
+    };
+    let active_runners = runners.iter().filter(|it| !it.test_taster).count();
+    let average_task_duration = state.db.get_average_task_duration(ETA_SAMPLE_SIZE).await?;
+
+    // Every runner works through the tasks ahead of us in parallel, then ours needs to run.
+    // Without runners there is nothing to estimate.
+    let eta = average_task_duration
+        .filter(|_| active_runners > 0)
+        .map(|average| {
+            let rounds_ahead = position.div_ceil(active_runners) as u32;
+            average * (rounds_ahead + 1)
+        });
+
+    Ok(Some(QueuePosition {
+        position,
+        active_runners,
+        average_task_duration_ms: average_task_duration.map(|it| it.as_millis() as u64),
+        eta_ms: eta.map(|it| it.as_millis() as u64),
+    }))
+}
+
+/// How many bytes of each output a task contains, unless the full output is asked for
+const INLINE_OUTPUT_LIMIT: usize = 8 * 1024;
+
+/// Sends the finished task. Tests only include the start of their output, unless the full output
+/// is asked for, as hundreds of tests can easily produce megabytes of it.
 #[instrument(skip_all)]
 pub async fn get_task(
     State(state): State<AppState>,
-    _claims: Claims,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TasksRead>,
+    Path(task_id): Path<TaskId>,
+    Query(query): Query<GetTaskQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let mut task: FinishedCompilerTaskWithOutdated = state.db.get_task(&task_id).await?.into();
+    task.mask_hidden_tests(&hidden_tests_for(&state, &claims).await?);
+    match &mut task {
+        // Computed when reading, so changed rules also apply to older builds
+        FinishedCompilerTaskWithOutdated::BuildFailed {
+            build_output,
+            build_hints,
+            ..
+        } => {
+            *build_hints = state.execution_config.build_hints_for(build_output);
+        }
+        FinishedCompilerTaskWithOutdated::RanTests { tests, .. } => {
+            if !query.include_output {
+                for test in tests.iter_mut() {
+                    edit_output_texts(test, truncate_output_text);
+                }
+            }
+        }
+    }
+
+    Ok(json_with_etag(&headers, &task))
+}
+
+/// Lists the tests that regressed, newly pass or changed their status between two tasks of the
+/// same team
+#[instrument(skip_all)]
+pub async fn compare_tasks(
+    State(state): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TasksRead>,
+    Query(query): Query<CompareTasksQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let mut comparison = state.db.compare_tasks(&query.base, &query.head).await?;
+    if comparison.base.info().team_id != comparison.head.info().team_id {
+        return Err(WebError::named_bad_request(
+            "Only tasks of the same team can be compared".to_string(),
+            location!(),
+        ));
+    }
+
+    let hidden = hidden_tests_for(&state, &claims).await?;
+    for changes in [
+        &mut comparison.regressed,
+        &mut comparison.newly_passing,
+        &mut comparison.changed,
+    ] {
+        changes.retain(|it| !hidden.contains(&it.test_id));
+    }
+
+    Ok(json_with_etag(&headers, &comparison))
+}
+
+/// Sends a single test of a finished task with its full output, so clients can load it when they
+/// need it
+#[instrument(skip_all)]
+pub async fn get_task_test(
+    State(state): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TasksRead>,
+    Path((task_id, test_id)): Path<(TaskId, TestId)>,
+    Query(query): Query<TaskTestQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if hidden_tests_for(&state, &claims).await?.contains(&test_id) {
+        return Err(WebError::named_not_found(
+            format!("Test `{test_id}` in task `{task_id}`"),
+            location!(),
+        ));
+    }
+    let (task, _) = state.db.get_task(&task_id).await?;
+    let FinishedCompilerTask::RanTests { tests, .. } = task else {
+        return Err(WebError::named_bad_request(
+            "The build of the task failed, so it ran no tests".to_string(),
+            location!(),
+        ));
+    };
+    let Some(test) = tests
+        .into_iter()
+        .find(|it| it.test_id == test_id.to_string() && it.profile == query.profile)
+    else {
+        return Err(WebError::named_not_found(
+            format!("Test `{test_id}` in task `{task_id}`"),
+            location!(),
+        ));
+    };
+
+    Ok(json_with_etag(&headers, &test))
+}
+
+/// The most bytes of a build log sent at once
+const BUILD_LOG_CHUNK_LIMIT: u64 = 1024 * 1024;
+
+/// Sends a part of the build output of a finished task, so clients can show large logs lazily.
+/// The chunk starts at byte `offset` and the `X-Log-Length` header holds the length of the whole
+/// log. Chunks may split UTF-8 characters.
+#[instrument(skip_all)]
+pub async fn get_build_log(
+    State(state): State<AppState>,
+    _claims: ScopedClaims<scopes::TasksRead>,
+    Path(task_id): Path<TaskId>,
+    Query(query): Query<BuildLogQuery>,
+) -> Result<Response> {
+    let limit = query
+        .limit
+        .unwrap_or(BUILD_LOG_CHUNK_LIMIT)
+        .clamp(1, BUILD_LOG_CHUNK_LIMIT);
+    let (chunk, total) = state
+        .db
+        .get_build_log_chunk(&task_id, query.stream, query.offset, limit)
+        .await?;
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (HeaderName::from_static("x-log-length"), total.to_string()),
+        ],
+        chunk,
+    )
+        .into_response())
+}
+
+/// Sends the whole stdout or stderr of an execution of the task, including what did not fit into
+/// the database
+#[instrument(skip_all)]
+pub async fn get_raw_output(
+    State(state): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TasksRead>,
+    Path((task_id, execution_id)): Path<(TaskId, String)>,
+    Query(query): Query<RawOutputQuery>,
+) -> Result<Response> {
+    let not_found = || {
+        WebError::named_not_found(
+            format!("Output `{execution_id}` of task `{task_id}`"),
+            location!(),
+        )
+    };
+    let Some((test_id, output)) = state
+        .db
+        .get_full_output(&task_id, &execution_id, query.stream)
+        .await?
+    else {
+        return Err(not_found());
+    };
+    // The build output is never hidden
+    let hidden = match test_id {
+        Some(test_id) => hidden_tests_for(&state, &claims).await?.contains(&test_id),
+        None => false,
+    };
+    if hidden {
+        return Err(not_found());
+    }
+
+    let body = match output {
+        FullOutput::Inline(text) => Body::from(text),
+        FullOutput::File(path) => {
+            Body::from_stream(ReaderStream::new(OutputStorage::open(&path).await?))
+        }
+    };
+
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response())
+}
+
+/// The tests whose names and outputs the team may not see in its tasks
+pub(super) async fn hidden_tests_for(state: &AppState, claims: &Claims) -> Result<HashSet<TestId>> {
+    if claims.is_admin() {
+        return Ok(HashSet::new());
+    }
+
+    state.db.get_hidden_test_ids(&claims.team).await
+}
+
+/// Calls `edit` with every text in the outputs of the test and drops their recordings, which would
+/// contain the texts as well
+fn edit_output_texts(test: &mut FinishedTest, edit: impl Fn(&mut String)) {
+    let outputs = match &mut test.output {
+        TestExecutionOutput::BinaryFailed {
+            compiler_output,
+            binary_output,
+        }
+        | TestExecutionOutput::Success {
+            compiler_output,
+            binary_output: Some(binary_output),
+        } => vec![compiler_output, binary_output],
+        TestExecutionOutput::CompilerFailed { compiler_output }
+        | TestExecutionOutput::Success {
+            compiler_output,
+            binary_output: None,
+        } => vec![compiler_output],
+        TestExecutionOutput::Error { output_so_far }
+        | TestExecutionOutput::Skipped { output_so_far } => vec![output_so_far],
+    };
+
+    for output in outputs {
+        match output {
+            ExecutionOutput::Aborted(aborted) => {
+                edit(&mut aborted.stdout);
+                edit(&mut aborted.stderr);
+            }
+            ExecutionOutput::Error(error) => edit(&mut error.message),
+            ExecutionOutput::Failure {
+                execution,
+                accumulated_errors,
+            } => {
+                edit_execution_texts(execution, &edit);
+                accumulated_errors.iter_mut().for_each(&edit);
+            }
+            ExecutionOutput::Success(execution) | ExecutionOutput::Timeout(execution) => {
+                edit_execution_texts(execution, &edit)
+            }
+        }
+    }
+}
+
+fn edit_execution_texts(execution: &mut FinishedExecution, edit: impl Fn(&mut String)) {
+    edit(&mut execution.stdout);
+    edit(&mut execution.stderr);
+    execution.recording = None;
+}
+
+/// Shortens the text to [INLINE_OUTPUT_LIMIT] bytes
+fn truncate_output_text(text: &mut String) {
+    output_storage::truncate_text(text, INLINE_OUTPUT_LIMIT);
+}
+
+/// Tells whether the task finished without sending it. Finished tasks do not change, so their id
+/// doubles as `ETag`.
+#[instrument(skip_all)]
+pub async fn head_task(
+    State(state): State<AppState>,
+    _claims: ScopedClaims<scopes::TasksRead>,
     Path(task_id): Path<TaskId>,
-) -> Result<Json<FinishedCompilerTaskWithOutdated>> {
-    Ok(Json(state.db.get_task(&task_id).await?.into()))
+) -> Result<Response> {
+    let Some(end_time) = state.db.fetch_task_end_time(&task_id).await? else {
+        return Err(WebError::not_found(location!()));
+    };
+
+    Ok([
+        (header::ETAG, format!("\"{task_id}\"")),
+        (header::LAST_MODIFIED, http_date(end_time)),
+    ]
+    .into_response())
+}
+
+/// Bundles everything needed to rerun one test of the task outside of crow, so "works locally,
+/// fails on crow" can be debugged with `crow-client repro`. Picks the first failed profile the
+/// test ran with, unless one is given.
+#[instrument(skip_all)]
+pub async fn get_repro_bundle(
+    State(state): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TasksRead>,
+    Path((task_id, test_id)): Path<(TaskId, TestId)>,
+    Query(query): Query<ReproBundleQuery>,
+) -> Result<Response> {
+    if hidden_tests_for(&state, &claims).await?.contains(&test_id) {
+        return Err(WebError::named_not_found(
+            format!("Test `{test_id}` in task `{task_id}`"),
+            location!(),
+        ));
+    }
+    let (task, _) = state.db.get_task(&task_id).await?;
+    let FinishedCompilerTask::RanTests { info, tests, .. } = task else {
+        return Err(WebError::named_bad_request(
+            "The build of the task failed, so it ran no tests".to_string(),
+            location!(),
+        ));
+    };
+    let mut runs = tests
+        .into_iter()
+        .filter(|it| it.test_id == test_id.to_string())
+        .filter(|it| query.profile.is_none() || it.profile == query.profile)
+        .collect::<Vec<_>>();
+    runs.sort_by_key(|it| matches!(it.output, TestExecutionOutput::Success { .. }));
+    let Some(run) = runs.into_iter().next() else {
+        return Err(WebError::named_not_found(
+            format!("Test `{test_id}` in task `{task_id}`"),
+            location!(),
+        ));
+    };
+    let Some(test) = state.db.fetch_test(&test_id).await? else {
+        return Err(WebError::named_not_found(
+            format!("Test `{test_id}`"),
+            location!(),
+        ));
+    };
+
+    let course_config = state.test_config.course(&test.course);
+    let test = CompilerTest {
+        compile_only: course_config.is_compile_only(&test.category),
+        profiles: course_config.profiles_for(&test.category),
+        runner_prefix: course_config.runner_prefix_for(&test.category),
+        permissions: course_config.permissions_for(&test.category),
+        binary_environment: course_config.binary_environment_for(&test.category),
+        test_id: test.id.to_string(),
+        depends_on: test.depends_on.iter().map(ToString::to_string).collect(),
+        category: test.category,
+        timeout: test
+            .timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(state.execution_config.test_timeout),
+        compile_command: state.execution_config.compile_command.clone(),
+        binary_arguments: state.execution_config.binary_arguments.clone(),
+        binary_modifiers: test.binary_modifiers,
+        compiler_modifiers: test.compiler_modifiers,
+        provisional_for_category: test.provisional_for_category,
+    };
+    let fileset = course_config.fileset_for(&test.category);
+    let Some((profile, test)) = test
+        .profile_variants()
+        .into_iter()
+        .find(|(profile, _)| *profile == run.profile)
+    else {
+        return Err(WebError::named_not_found(
+            format!("Profile `{}`", run.profile.unwrap_or_default()),
+            location!(),
+        ));
+    };
+
+    let manifest = ReproManifest {
+        task_id: info.task_id,
+        team_id: info.team_id,
+        revision_id: info.revision_id,
+        image: state.execution_config.build_image.clone(),
+        build_command: state.execution_config.build_command.clone(),
+        build_timeout: state.execution_config.build_timeout,
+        test,
+        profile,
+        invocation: run.invocation,
+        environment: PinnedEnvironment::default(),
+    };
+    let bundle = repro_bundle::write_bundle(&manifest, fileset.as_ref())?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"crow-repro-{task_id}-{test_id}.tar.gz\""),
+            ),
+        ],
+        bundle,
+    )
+        .into_response())
 }
 
 #[instrument(skip_all)]
 pub async fn get_top_task_per_team(
     State(state): State<AppState>,
-    _claims: Claims,
+    claims: Claims,
 ) -> Result<Json<HashMap<TeamId, ApiFinishedCompilerTaskSummary>>> {
     let top_tasks = state.db.get_top_task_per_team().await?;
     let teams = state
@@ -214,14 +754,19 @@ pub async fn get_top_task_per_team(
         .get_teams()
         .await?
         .into_iter()
-        .map(|team| (team.id, team.display_name))
+        .map(|team| (team.id.clone(), team))
         .collect::<HashMap<_, _>>();
 
+    // Teams only compare themselves with the teams of their course
     let top_tasks = top_tasks
         .into_iter()
-        .map(|(team_id, task)| {
-            let team_name = teams.get(&team_id).cloned().unwrap_or_default();
-            (team_id, ApiFinishedCompilerTaskSummary { task, team_name })
+        .filter_map(|(team_id, task)| {
+            let team = teams.get(&team_id)?;
+            if !claims.is_admin() && team.course != claims.course {
+                return None;
+            }
+            let team_name = team.display_name.clone();
+            Some((team_id, ApiFinishedCompilerTaskSummary { task, team_name }))
         })
         .collect::<HashMap<_, _>>();
 
@@ -233,8 +778,37 @@ pub async fn executor_info(
     State(state): State<AppState>,
     _claims: Claims,
 ) -> Result<Json<ExecutorInfo>> {
-    let tasting_runners = state.test_tasting.lock().unwrap().get_tasting_runners();
-    Ok(Json(state.executor.lock().unwrap().info(tasting_runners)))
+    let (tasting_runners, open_tastings) = {
+        let tasting = state.test_tasting.lock().unwrap();
+        (tasting.get_tasting_runners(), tasting.open_tastings())
+    };
+    Ok(Json(
+        state
+            .executor
+            .lock()
+            .unwrap()
+            .info(tasting_runners, open_tastings),
+    ))
+}
+
+const MAX_QUEUE_STATISTICS_DAYS: u32 = 366;
+
+/// Percentiles of queue wait and execution durations per day, to tell when more runners are needed
+#[instrument(skip_all)]
+pub async fn get_queue_statistics(
+    State(state): State<AppState>,
+    Query(query): Query<QueueStatisticsQuery>,
+) -> Result<Json<Vec<QueueStatisticsDay>>> {
+    let days = query.days.unwrap_or(30);
+    if days == 0 || days > MAX_QUEUE_STATISTICS_DAYS {
+        return Err(WebError::named_bad_request(
+            format!("Days must be between 1 and {MAX_QUEUE_STATISTICS_DAYS}"),
+            location!(),
+        ));
+    }
+    let since = Timestamp::now() - SignedDuration::from_hours(24 * i64::from(days));
+
+    Ok(Json(state.db.get_queue_statistics(since).await?))
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -244,6 +818,25 @@ pub struct QueueResponse {
     pub runners: Vec<RunnerForFrontend>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuePosition {
+    /// The number of waiting tasks that will be executed before this one
+    pub position: usize,
+    pub active_runners: usize,
+    pub average_task_duration_ms: Option<u64>,
+    /// The estimated time until the task is finished
+    pub eta_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedTaskResponse {
+    #[serde(flatten)]
+    pub task: WorkItem,
+    pub position: QueuePosition,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct IntegrationRequestRevisionPayload {
@@ -251,6 +844,71 @@ pub struct IntegrationRequestRevisionPayload {
     pub checked_commit: Option<String>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartBisectPayload {
+    pub test_id: String,
+    /// A revision passing the test
+    pub good: String,
+    /// A revision failing the test, descending from the good one
+    pub bad: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueueStatisticsQuery {
+    /// How many days back to report, defaults to 30
+    pub days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetTaskQuery {
+    /// Send the full output of every test instead of only its start
+    #[serde(default)]
+    pub include_output: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompareTasksQuery {
+    pub base: TaskId,
+    pub head: TaskId,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaskTestQuery {
+    /// The profile the test ran with, if its category defines any
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BuildLogQuery {
+    #[serde(default)]
+    pub stream: OutputStream,
+    /// The byte of the log the chunk starts at
+    #[serde(default)]
+    pub offset: u64,
+    /// The most bytes to send, capped by the server
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawOutputQuery {
+    #[serde(default)]
+    pub stream: OutputStream,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReproBundleQuery {
+    /// The profile of the test to reproduce, defaults to the first failed one
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueueTaskQuery {
+    /// Queue the revision even if a task for it is already queued or running
+    #[serde(default)]
+    pub force: bool,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct IntegrationTaskStatusResponse {
@@ -265,6 +923,8 @@ pub enum FinishedCompilerTaskWithOutdated {
         info: FinishedTaskInfo,
         build_output: ExecutionOutput,
         outdated: Vec<TestId>,
+        /// Explanations of common causes of the failure
+        build_hints: Vec<String>,
     },
     #[serde(rename_all = "camelCase")]
     RanTests {
@@ -273,9 +933,49 @@ pub enum FinishedCompilerTaskWithOutdated {
         tests: Vec<FinishedTest>,
         outdated: Vec<TestId>,
         statistics: FinishedCompilerTaskStatistics,
+        failure_clusters: Vec<FailureCluster>,
     },
 }
 
+impl FinishedCompilerTaskWithOutdated {
+    /// Replaces the ids of hidden tests by their [TestId::masked] pseudonym and clears what they
+    /// printed and ran, which would give them away
+    fn mask_hidden_tests(&mut self, hidden: &HashSet<TestId>) {
+        if hidden.is_empty() {
+            return;
+        }
+        let mask = |id: &mut TestId| {
+            if hidden.contains(id) {
+                *id = id.masked();
+            }
+        };
+
+        match self {
+            Self::BuildFailed { outdated, .. } => outdated.iter_mut().for_each(mask),
+            Self::RanTests {
+                tests,
+                outdated,
+                failure_clusters,
+                ..
+            } => {
+                outdated.iter_mut().for_each(mask);
+                for cluster in failure_clusters {
+                    cluster.test_ids.iter_mut().for_each(mask);
+                }
+                for test in tests {
+                    let id = TestId::from(test.test_id.clone());
+                    if hidden.contains(&id) {
+                        test.test_id = id.masked().to_string();
+                        test.invocation = TestInvocation::default();
+                        test.diagnostics.clear();
+                        edit_output_texts(test, String::clear);
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl From<(FinishedCompilerTask, Vec<TestId>)> for FinishedCompilerTaskWithOutdated {
     fn from((task, outdated): (FinishedCompilerTask, Vec<TestId>)) -> Self {
         match task {
@@ -283,6 +983,7 @@ impl From<(FinishedCompilerTask, Vec<TestId>)> for FinishedCompilerTaskWithOutda
                 info,
                 build_output,
                 outdated,
+                build_hints: Vec::new(),
             },
             FinishedCompilerTask::RanTests {
                 info,
@@ -290,12 +991,14 @@ impl From<(FinishedCompilerTask, Vec<TestId>)> for FinishedCompilerTaskWithOutda
                 tests,
             } => {
                 let statistics = tests.as_slice().into();
+                let failure_clusters = cluster_failures(&tests);
                 Self::RanTests {
                     info,
                     build_output,
                     tests,
                     outdated,
                     statistics,
+                    failure_clusters,
                 }
             }
         }
@@ -1,17 +1,27 @@
-use super::{Json, Path};
-use crate::auth::Claims;
+use super::tasks::hidden_tests_for;
+use super::{Json, Path, Query, json_with_etag};
+use crate::auth;
+use crate::auth::{Claims, ScopedClaims, scopes};
 use crate::config::TestCategory;
 use crate::error::{Result, WebError};
-use crate::grading_formulas::{GradingPoints, get_grading_points_for_task};
+use crate::grading_formulas::{
+    GradingPoints, apply_test_suite_snapshots, get_grading_points_for_task,
+};
 use crate::storage::GitError;
 use crate::types::{
-    AppState, FinalSubmittedTask, FinishedCompilerTaskSummary, Repo, TaskId, TeamId, TeamInfo,
+    ApiToken, ApiTokenId, ApiTokenScope, AppState, FinalSubmittedTask, FinishedCompilerTaskSummary,
+    Notification, NotificationId, Page, Repo, TaskId, TeamActivityDay, TeamId, TeamInfo,
+    TeamMilestones, TeamPerformance, TeamSshKey,
 };
 use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 use snafu::{Report, location};
 use std::collections::{HashMap, HashSet};
 use tracing::{info, instrument};
+use uuid::Uuid;
 
 #[instrument(skip_all)]
 pub async fn set_team_repo(
@@ -73,23 +83,145 @@ pub async fn get_team_repo(
     Ok(Json(db.get_repo(&claims.team).await?))
 }
 
+/// The public key teams add as a deploy key, so their private repository can be cloned
+#[instrument(skip_all)]
+pub async fn get_team_ssh_key(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(team_id): Path<TeamId>,
+) -> Result<Json<TeamSshKey>> {
+    let team = if claims.is_admin() {
+        team_id
+    } else {
+        claims.team
+    };
+
+    match state.local_repos.ssh_keys().public_key(&team).await? {
+        Some(public_key) => Ok(Json(TeamSshKey {
+            team,
+            public_key: public_key.trim().to_string(),
+        })),
+        None => Err(WebError::named_not_found(
+            format!("ssh key of `{team}`"),
+            location!(),
+        )),
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn generate_team_ssh_key(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(team_id): Path<TeamId>,
+) -> Result<Json<TeamSshKey>> {
+    // Fails for unknown teams
+    let team = state.db.get_team(&team_id).await?;
+    let public_key = state.local_repos.ssh_keys().generate(&team.id).await?;
+
+    info!(team = %team.id, user = %claims.sub, "Generated team ssh key");
+
+    Ok(Json(TeamSshKey {
+        team: team.id,
+        public_key,
+    }))
+}
+
 #[instrument(skip_all)]
 pub async fn get_n_recent_tasks(
     State(AppState { db, .. }): State<AppState>,
-    claims: Claims,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TasksRead>,
     Path(count): Path<u32>,
-) -> Result<Json<Vec<FinishedCompilerTaskSummary>>> {
+    headers: HeaderMap,
+) -> Result<Response> {
     let count = if count == 0 { u32::MAX } else { count };
+    let tasks = db.get_recent_tasks(&claims.team, count).await?;
 
-    Ok(Json(db.get_recent_tasks(&claims.team, count).await?))
+    Ok(json_with_etag(&headers, &tasks))
 }
 
 #[instrument(skip_all)]
 pub async fn get_recent_tasks(
     State(AppState { db, .. }): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TasksRead>,
+    Query(query): Query<RecentTasksQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let page = Page::new(query.page.unwrap_or(1), query.per_page.unwrap_or(10));
+    let search = query.q.as_deref().filter(|it| !it.is_empty());
+    let (tasks, total) = db.search_tasks(&claims.team, search, page).await?;
+
+    Ok(json_with_etag(
+        &headers,
+        &RecentTasksResponse { tasks, total },
+    ))
+}
+
+#[instrument(skip_all)]
+pub async fn get_team_activity(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> Result<Json<Vec<TeamActivityDay>>> {
+    // The semester starts with the first category
+    let since = state
+        .test_config
+        .course(&claims.course)
+        .categories
+        .values()
+        .map(|it| it.starts_at.timestamp())
+        .min()
+        .unwrap_or(Timestamp::UNIX_EPOCH);
+
+    Ok(Json(state.db.get_team_activity(&claims.team, since).await?))
+}
+
+/// How the binary sizes and compile times of the team developed over its tasks
+#[instrument(skip_all)]
+pub async fn get_team_performance(
+    State(AppState { db, .. }): State<AppState>,
+    claims: Claims,
+    Path(team_id): Path<TeamId>,
+) -> Result<Json<TeamPerformance>> {
+    let team_id = if claims.is_admin() {
+        team_id
+    } else {
+        claims.team
+    };
+    let tasks = db.get_team_performance(&team_id).await?;
+
+    Ok(Json(TeamPerformance { team_id, tasks }))
+}
+
+#[instrument(skip_all)]
+pub async fn get_team_milestones(
+    State(state): State<AppState>,
     claims: Claims,
-) -> Result<Json<Vec<FinishedCompilerTaskSummary>>> {
-    Ok(Json(db.get_recent_tasks(&claims.team, 10).await?))
+) -> Result<Json<TeamMilestones>> {
+    let mut milestones = state.db.get_team_milestones(&claims.team).await?;
+    // Passing a test the team may not see must not reveal its name
+    let hidden = hidden_tests_for(&state, &claims).await?;
+    milestones.tests.retain(|it| !hidden.contains(&it.test_id));
+
+    Ok(Json(milestones))
+}
+
+#[instrument(skip_all)]
+pub async fn get_notifications(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> Result<Json<Vec<Notification>>> {
+    Ok(Json(state.db.get_notifications(&claims.team).await?))
+}
+
+#[instrument(skip_all)]
+pub async fn mark_notification_read(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(notification_id): Path<NotificationId>,
+) -> Result<()> {
+    state
+        .db
+        .mark_notification_read(&claims.team, &notification_id)
+        .await
 }
 
 #[instrument(skip_all)]
@@ -97,8 +229,11 @@ pub async fn get_tasks_for_team(
     State(AppState { db, .. }): State<AppState>,
     Path(team_id): Path<TeamId>,
     _claims: Claims,
-) -> Result<Json<Vec<FinishedCompilerTaskSummary>>> {
-    Ok(Json(db.get_recent_tasks(&team_id, u32::MAX).await?))
+    headers: HeaderMap,
+) -> Result<Response> {
+    let tasks = db.get_recent_tasks(&team_id, u32::MAX).await?;
+
+    Ok(json_with_etag(&headers, &tasks))
 }
 
 #[instrument(skip_all)]
@@ -107,24 +242,29 @@ pub async fn get_final_tasks(
     claims: Claims,
 ) -> Result<Json<HashMap<String, FinalSubmittedTaskWithPoints>>> {
     let mut result = HashMap::new();
+    let snapshots = state.db.get_test_suite_snapshots().await?;
 
-    for (name, meta) in &state.test_config.categories {
+    let course_config = state.test_config.course(&claims.course);
+    for (name, meta) in &course_config.categories {
         if let Some(task) = state
             .db
-            .get_final_submitted_task_for_team_and_category(&claims.team, name, meta, true)
+            .get_final_submitted_task_for_team_and_category(
+                &claims.team,
+                &claims.course,
+                name,
+                meta,
+                true,
+            )
             .await?
         {
             // Only calculate points for finalized tasks
             let points = if matches!(task, FinalSubmittedTask::Finalized { .. }) {
-                get_grading_points_for_task(
-                    &state.test_config,
-                    name,
-                    meta,
-                    &state
-                        .db
-                        .get_finished_test_summaries(&task.task_id())
-                        .await?,
-                )?
+                let summaries = state
+                    .db
+                    .get_finished_test_summaries(&task.task_id())
+                    .await?;
+                let summaries = apply_test_suite_snapshots(summaries, &claims.course, &snapshots);
+                get_grading_points_for_task(course_config, name, meta, &summaries)?
             } else {
                 None
             };
@@ -142,10 +282,16 @@ pub async fn set_final_task(
     Json(payload): Json<SetFinalTaskPayload>,
 ) -> Result<()> {
     let mut current_categories = HashMap::new();
-    for (name, category) in &state.test_config.categories {
+    for (name, category) in &state.test_config.course(&claims.course).categories {
         let task = state
             .db
-            .get_final_submitted_task_for_team_and_category(&claims.team, name, category, true)
+            .get_final_submitted_task_for_team_and_category(
+                &claims.team,
+                &claims.course,
+                name,
+                category,
+                true,
+            )
             .await?;
         if let Some(task) = task {
             if task.task_id() == payload.task_id {
@@ -154,13 +300,14 @@ pub async fn set_final_task(
         }
     }
 
-    ensure_added_categories_not_past_due(&state, &payload, &mut current_categories)?;
+    ensure_added_categories_not_past_due(&state, &claims, &payload, &mut current_categories)?;
     ensure_removed_categories_not_past_due(&state, &claims, &payload).await?;
 
     state
         .db
         .set_final_submitted_task(
             &claims.team,
+            &claims.course,
             &claims.sub,
             &payload.task_id,
             payload.categories.iter().map(|s| s.as_str()),
@@ -180,6 +327,7 @@ pub async fn set_final_task(
 
 fn ensure_added_categories_not_past_due(
     state: &AppState,
+    claims: &Claims,
     payload: &SetFinalTaskPayload,
     current_categories: &mut HashMap<String, TestCategory>,
 ) -> Result<()> {
@@ -189,6 +337,7 @@ fn ensure_added_categories_not_past_due(
         }
         let category = state
             .test_config
+            .course(&claims.course)
             .categories
             .get(new_category)
             .ok_or_else(|| {
@@ -210,11 +359,17 @@ async fn ensure_removed_categories_not_past_due(
     claims: &Claims,
     payload: &SetFinalTaskPayload,
 ) -> Result<()> {
-    for (name, category) in &state.test_config.categories {
+    for (name, category) in &state.test_config.course(&claims.course).categories {
         if category.is_after_labs_deadline() && !payload.categories.contains(name) {
             let current_submitted_task = state
                 .db
-                .get_final_submitted_task_for_team_and_category(&claims.team, name, category, true)
+                .get_final_submitted_task_for_team_and_category(
+                    &claims.team,
+                    &claims.course,
+                    name,
+                    category,
+                    true,
+                )
                 .await?;
             if let Some(current_submitted_task) = current_submitted_task {
                 if current_submitted_task.task_id() == payload.task_id {
@@ -248,6 +403,111 @@ pub async fn get_team_info(
     }))
 }
 
+#[instrument(skip_all)]
+pub async fn list_api_tokens(
+    State(AppState { db, .. }): State<AppState>,
+    claims: Claims,
+) -> Result<Json<Vec<ApiToken>>> {
+    Ok(Json(db.get_api_tokens(&claims.team).await?))
+}
+
+#[instrument(skip_all)]
+pub async fn create_api_token(
+    State(AppState { db, .. }): State<AppState>,
+    claims: Claims,
+    Json(payload): Json<CreateApiTokenPayload>,
+) -> Result<Json<CreatedApiToken>> {
+    let name = payload.name.trim();
+    if name.is_empty() || name.len() >= 255 {
+        return Err(WebError::named_bad_request(
+            "Token name must be between 1 and 254 characters".to_string(),
+            location!(),
+        ));
+    }
+    if payload.scopes.is_empty() {
+        return Err(WebError::named_bad_request(
+            "Token needs at least one scope".to_string(),
+            location!(),
+        ));
+    }
+
+    let mut scopes = payload.scopes;
+    scopes.dedup();
+
+    let (secret, hash) = auth::create_api_token();
+    let token = ApiToken {
+        id: Uuid::new_v4().to_string().into(),
+        team: claims.team.clone(),
+        name: name.to_string(),
+        scopes,
+        created_by: claims.sub.clone(),
+        created_at: Timestamp::now(),
+        last_used_at: None,
+    };
+    db.add_api_token(&token, &hash).await?;
+
+    info!(
+        team = %claims.team,
+        user = %claims.sub,
+        token = %token.id,
+        scopes = ?token.scopes,
+        "Created API token"
+    );
+
+    Ok(Json(CreatedApiToken {
+        token: secret,
+        info: token,
+    }))
+}
+
+#[instrument(skip_all)]
+pub async fn delete_api_token(
+    State(AppState { db, .. }): State<AppState>,
+    claims: Claims,
+    Path(token_id): Path<ApiTokenId>,
+) -> Result<()> {
+    db.delete_api_token(&claims.team, &token_id).await?;
+
+    info!(
+        team = %claims.team,
+        user = %claims.sub,
+        token = %token_id,
+        "Deleted API token"
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiTokenPayload {
+    name: String,
+    scopes: Vec<ApiTokenScope>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedApiToken {
+    /// The token itself. It is only returned once, we just store its hash.
+    pub token: String,
+    pub info: ApiToken,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecentTasksQuery {
+    q: Option<String>,
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentTasksResponse {
+    pub tasks: Vec<FinishedCompilerTaskSummary>,
+    /// The number of matching tasks, across all pages
+    pub total: i64,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TeamPatchPayload {
@@ -1,29 +1,68 @@
-use super::{Json, Path};
-use crate::auth::Claims;
+use super::{Json, Path, Query, ensure_course_open, json_with_etag};
+use crate::auth::{Claims, ScopedClaims, scopes};
+use crate::config::{ReferenceOutputConfig, TastingPolicy};
 use crate::error::{Result, WebError};
-use crate::types::{AppState, Test, TestId, TestSummary, TestWithTasteTesting};
+use crate::types::{
+    AppState, CourseId, Page, ReferenceOutput, TeamId, Test, TestAcceptance, TestAcceptanceState,
+    TestId, TestSearch, TestStats, TestSuiteSnapshotSummary, TestSummary, TestVisibility,
+    TestWithTasteTesting,
+};
 use axum::extract::State;
+use axum::http::{HeaderMap, header};
+use axum::response::{IntoResponse, Response};
 use jiff::{Timestamp, Zoned};
 use serde::{Deserialize, Serialize};
-use shared::{TestExecutionOutput, TestModifier};
+use shared::hash::TEST_HASH_VERSION;
+use shared::validation::{TestValidationInput, validate_test};
+use shared::{ExecutionOutput, TestExecutionOutput, TestModifier, TestModifierExt};
 use snafu::location;
 use std::collections::HashMap;
+use std::io::{Cursor, Write};
 use tracing::{debug, info, instrument};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
 
 #[instrument(skip_all)]
 pub async fn list_tests(
     State(state): State<AppState>,
-    _claims: Claims,
-) -> Result<Json<ListTestsResponse>> {
-    Ok(Json(ListTestsResponse {
-        tests: state.db.get_test_summaries().await?,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TestsRead>,
+    Query(query): Query<ListTestsQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    // Without pagination everything is returned, the CLI relies on that
+    let page = match (query.page, query.per_page) {
+        (None, None) => Page::all(),
+        (page, per_page) => Page::new(page.unwrap_or(1), per_page.unwrap_or(DEFAULT_PER_PAGE)),
+    };
+    let search = TestSearch {
+        query: query.q.filter(|it| !it.is_empty()),
+        category: query.category,
+        creator: query.creator,
+        // Admins see all courses unless they pick one
+        course: if claims.is_admin() {
+            query.course
+        } else {
+            Some(claims.course.clone())
+        },
+        visible_to: Some(claims.team.clone()).filter(|_| !claims.is_admin()),
+        acceptance: query.acceptance,
+    };
+    let (tests, total) = state.db.get_test_summaries(&search, page).await?;
+
+    let response = ListTestsResponse {
+        tests,
+        total,
         categories: state
             .test_config
+            .course(search.course.as_ref().unwrap_or(&claims.course))
             .categories
-            .into_iter()
-            .map(|(name, category)| (name, category.into()))
+            .iter()
+            .map(|(name, category)| (name.clone(), category.clone().into()))
             .collect(),
-    }))
+        hash_version: TEST_HASH_VERSION,
+    };
+
+    Ok(json_with_etag(&headers, &response))
 }
 
 #[instrument(skip_all)]
@@ -33,11 +72,16 @@ pub async fn set_test(
     Path(test_id): Path<TestId>,
     Json(payload): Json<AddTestPayload>,
 ) -> Result<Json<SetTestResponse>> {
+    ensure_course_open(&state, &claims, "upload tests").await?;
+
     let db = &state.db;
     let mut owner = claims.team.clone();
+    let mut course = claims.course.clone();
     let mut admin_authored = claims.is_admin();
     let mut limited_to_category = false;
     let mut provisional_for_category = None;
+    let mut timeout_ms = None;
+    let mut visibility = TestVisibility::default();
 
     if let Some(existing) = db.fetch_test(&test_id).await? {
         if existing.owner != claims.team && !claims.is_admin() {
@@ -45,14 +89,86 @@ pub async fn set_test(
         }
         // Even if an admin edits a test, this stays the same
         owner = existing.owner;
+        course = existing.course;
         admin_authored = existing.admin_authored;
         limited_to_category = existing.limited_to_category;
         provisional_for_category = existing.provisional_for_category;
+        timeout_ms = existing.timeout_ms;
+        visibility = existing.visibility;
     }
 
-    let Some(category_meta) = state.test_config.categories.get(&payload.category) else {
+    if claims.is_admin() {
+        timeout_ms = payload.timeout_ms;
+    } else if payload.timeout_ms.is_some() && payload.timeout_ms != timeout_ms {
+        return Err(WebError::named_unauthorized(
+            "set a test timeout".to_string(),
+            location!(),
+        ));
+    }
+
+    // Clients that do not know about visibility keep the current one
+    match payload.visibility {
+        Some(requested) if requested != visibility && !claims.is_admin() => {
+            return Err(WebError::named_unauthorized(
+                "change the visibility of a test".to_string(),
+                location!(),
+            ));
+        }
+        Some(requested) => visibility = requested,
+        None => {}
+    }
+
+    let Some(category_meta) = state
+        .test_config
+        .course(&course)
+        .categories
+        .get(&payload.category)
+    else {
         return Err(WebError::named_not_found(payload.category, location!()));
     };
+    let depends_on = payload
+        .depends_on
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let problems = validate_test(&TestValidationInput {
+        id: &test_id.to_string(),
+        compiler_modifiers: &payload.compiler_modifiers,
+        binary_modifiers: &payload.binary_modifiers,
+        depends_on: &depends_on,
+        license: payload.license.as_deref(),
+        compile_only: category_meta.compile_only,
+    });
+    if !problems.is_empty() {
+        return Err(WebError::named_bad_request(
+            problems
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+            location!(),
+        ));
+    }
+
+    for dependency in &payload.depends_on {
+        let Some(dependency_test) = db
+            .fetch_test(dependency)
+            .await?
+            .filter(|it| claims.can_see_test(it))
+        else {
+            return Err(WebError::named_not_found(
+                format!("dependency `{dependency}`"),
+                location!(),
+            ));
+        };
+        // The server never runs practice tests, so they could never pass
+        if dependency_test.practice && !payload.practice {
+            return Err(WebError::named_bad_request(
+                format!("test `{test_id}` depending on practice test `{dependency}`"),
+                location!(),
+            ));
+        }
+    }
 
     let provisional = category_meta.is_after_test_deadline();
     // If the time is up you can no longer edit finalized tests as a normal user
@@ -76,20 +192,63 @@ pub async fn set_test(
         };
     }
 
-    let test = Test {
+    let mut test = Test {
         id: test_id,
         owner: owner.clone(),
+        course,
         admin_authored,
         category: payload.category,
         compiler_modifiers: payload.compiler_modifiers,
         binary_modifiers: payload.binary_modifiers,
         limited_to_category,
         provisional_for_category,
+        timeout_ms,
+        depends_on: payload.depends_on,
+        practice: payload.practice,
+        license: payload.license,
+        visibility,
+        acceptance: TestAcceptance::Accepted,
         last_updated: Timestamp::now(),
     };
 
-    // Let the reference compiler taste it first
-    let taste_testing_result = do_test_tasting(&state, &test).await?;
+    verify_declared_hash(&test, payload.hash, payload.hash_version)?;
+
+    if test.license.is_none() {
+        test.license = state.test_config.default_license.clone();
+    }
+
+    // Let the reference compiler fill in the expected output if the author left it out
+    let reference_output = match &state.execution_config.reference_outputs {
+        Some(config) if needs_reference_output(&test, category_meta.compile_only) => {
+            match compute_reference_output(&state, &test, config).await? {
+                Ok(reference_output) => {
+                    test.binary_modifiers.push(TestModifier::ExpectedOutput {
+                        output: reference_output.output.clone(),
+                    });
+                    Some(reference_output)
+                }
+                Err(output) if !payload.ignore_test_tasting => {
+                    info!(
+                        test_id = %test.id,
+                        owner = %owner,
+                        team = %claims.team,
+                        user = %claims.sub,
+                        "Test failed computing its reference output"
+                    );
+                    return Ok(Json(SetTestResponse::TastingFailed { output }));
+                }
+                Err(_) => None,
+            }
+        }
+        _ => keep_reference_output(&state, &test).await?,
+    };
+
+    // Let the reference compiler taste it first. Practice tests do not cost runner time.
+    let taste_testing_result = if test.practice {
+        None
+    } else {
+        do_test_tasting(&state, &test).await?
+    };
 
     if let Some(result) = &taste_testing_result {
         if !matches!(result, TestExecutionOutput::Success { .. }) && !payload.ignore_test_tasting {
@@ -106,55 +265,375 @@ pub async fn set_test(
         }
     }
 
+    // Admins are assumed to know what they upload
+    test.acceptance = if claims.is_admin() {
+        TestAcceptance::Accepted
+    } else {
+        apply_tasting_policy(
+            state.test_config.tasting_policy,
+            taste_testing_result.as_ref(),
+        )
+    };
+
     info!(
         test_id = %test.id,
         owner = %owner,
         team = %claims.team,
         user = %claims.sub,
+        acceptance = ?test.acceptance,
         "Adding/modifying test"
     );
 
-    Ok(Json(SetTestResponse::TestAdded(
-        db.add_test(test, taste_testing_result).await?,
-    )))
+    let test = db
+        .add_test(test, taste_testing_result, reference_output.as_ref())
+        .await?;
+    state.test_mirror.request_sync();
+
+    Ok(Json(SetTestResponse::TestAdded(test)))
 }
 
-async fn do_test_tasting(state: &AppState, test: &Test) -> Result<Option<TestExecutionOutput>> {
-    if !state.execution_config.tasting_disabled() {
-        let taste_result = state.test_tasting.lock().unwrap().add_tasting(test.clone());
-        let taste_result = match taste_result.await {
-            Ok(output) => output,
-            Err(_) => {
-                return Err(WebError::internal_error(
-                    "No test result received".to_string(),
-                    location!(),
-                ));
-            }
-        };
+/// Decides whether a tasted test counts. Tests that passed always do. Tests that could not be
+/// tasted are held back for review unless every test is accepted, as nothing vouches for them.
+fn apply_tasting_policy(
+    policy: TastingPolicy,
+    tasting_result: Option<&TestExecutionOutput>,
+) -> TestAcceptance {
+    let problem = match tasting_result {
+        Some(TestExecutionOutput::Success { .. }) => return TestAcceptance::Accepted,
+        None => {
+            return match policy {
+                TastingPolicy::AcceptAll => TestAcceptance::Accepted,
+                TastingPolicy::RequireReferencePass | TastingPolicy::RequireAdminReview => {
+                    TestAcceptance::Pending {
+                        message: "The test waits for an admin to review it, as it could not be \
+                                  tasted on the reference compiler."
+                            .to_string(),
+                    }
+                }
+            };
+        }
+        Some(TestExecutionOutput::CompilerFailed { .. }) => {
+            "the reference compiler did not behave as the test expects"
+        }
+        Some(TestExecutionOutput::BinaryFailed { .. }) => {
+            "the binary of the reference compiler did not behave as the test expects"
+        }
+        Some(TestExecutionOutput::Error { .. }) => "it could not be run on the reference compiler",
+        Some(TestExecutionOutput::Skipped { .. }) => "it was skipped on the reference compiler",
+    };
 
-        return Ok(Some(taste_result));
-    } else {
+    match policy {
+        TastingPolicy::AcceptAll => TestAcceptance::Accepted,
+        TastingPolicy::RequireReferencePass => TestAcceptance::Rejected {
+            message: format!(
+                "The test was rejected, as {problem}. Upload a fixed version to have it accepted."
+            ),
+        },
+        TastingPolicy::RequireAdminReview => TestAcceptance::Pending {
+            message: format!("The test waits for an admin to review it, as {problem}."),
+        },
+    }
+}
+
+/// Rejects uploads where the client computed a different hash than we would store, as the hashes
+/// of the client and server would otherwise silently drift apart.
+fn verify_declared_hash(
+    test: &Test,
+    declared_hash: Option<String>,
+    declared_version: Option<u32>,
+) -> Result<()> {
+    if let Some(version) = declared_version.filter(|it| *it != TEST_HASH_VERSION) {
+        return Err(WebError::named_bad_request(
+            format!(
+                "test hash version `{version}` of your client does not match the server \
+                version `{TEST_HASH_VERSION}`. Please update your client"
+            ),
+            location!(),
+        ));
+    }
+
+    let Some(declared_hash) = declared_hash else {
+        return Ok(());
+    };
+    let actual_hash = test.hash();
+    if declared_hash != actual_hash {
+        return Err(WebError::named_bad_request(
+            format!(
+                "declared test hash `{declared_hash}` does not match `{actual_hash}` computed by \
+                the server (hash version `{TEST_HASH_VERSION}`) for owner `{}`, category `{}`, \
+                admin authored `{}`, limited to category `{}` and timeout `{:?}`",
+                test.owner,
+                test.category,
+                test.admin_authored,
+                test.limited_to_category,
+                test.timeout_ms
+            ),
+            location!(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn do_test_tasting(state: &AppState, test: &Test) -> Result<Option<TestExecutionOutput>> {
+    let Some(image_id) = &state.execution_config.reference_compiler_image else {
         debug!(test_id = %test.id, "Tasting disabled, skipping it for");
+        return Ok(None);
+    };
+    let compile_command = state.execution_config.compile_command.clone();
+
+    Ok(Some(
+        run_on_reference_compiler(state, test, image_id.clone(), compile_command).await?,
+    ))
+}
+
+async fn run_on_reference_compiler(
+    state: &AppState,
+    test: &Test,
+    image_id: String,
+    compile_command: Vec<String>,
+) -> Result<TestExecutionOutput> {
+    let taste_result = {
+        let mut tasting = state.test_tasting.lock().unwrap();
+        tasting.add_tasting(test.clone(), image_id, compile_command)
+    };
+
+    match taste_result.await {
+        Ok(output) => Ok(output),
+        Err(_) => Err(WebError::internal_error(
+            "No test result received".to_string(),
+            location!(),
+        )),
     }
-    Ok(None)
+}
+
+/// Whether the test runs a binary without saying what it should print
+fn needs_reference_output(test: &Test, compile_only: bool) -> bool {
+    let modifiers = test.binary_modifiers.as_slice();
+
+    !compile_only
+        && !test.practice
+        && !modifiers.is_empty()
+        && modifiers.full_output().is_none()
+        && modifiers.output_alternatives().is_none()
+        && !modifiers.should_timeout()
+}
+
+/// Runs the test on the reference compiler and returns what its binary printed. If the binary did
+/// not run successfully, the output of the run is returned instead.
+async fn compute_reference_output(
+    state: &AppState,
+    test: &Test,
+    config: &ReferenceOutputConfig,
+) -> Result<std::result::Result<ReferenceOutput, TestExecutionOutput>> {
+    let output = run_on_reference_compiler(
+        state,
+        test,
+        config.image.clone(),
+        config.compile_command.clone(),
+    )
+    .await?;
+    let Some(ExecutionOutput::Success(execution)) = output.binary_output() else {
+        return Ok(Err(output));
+    };
+    info!(test_id = %test.id, image = %config.image, "Computed reference output");
+
+    Ok(Ok(ReferenceOutput {
+        output: execution.stdout.clone(),
+        image: config.image.clone(),
+        compile_command: config.compile_command.clone(),
+        computed_at: Timestamp::now(),
+    }))
+}
+
+/// Keeps the origin of an expected output the reference compiler computed earlier, as long as
+/// the test still expects it. Authors re-uploading a test they downloaded send that output along.
+async fn keep_reference_output(state: &AppState, test: &Test) -> Result<Option<ReferenceOutput>> {
+    let Some(existing) = state.db.fetch_reference_output(&test.id).await? else {
+        return Ok(None);
+    };
+    let expected = test.binary_modifiers.as_slice().full_output();
+
+    Ok(Some(existing).filter(|it| expected.as_deref() == Some(it.output.as_str())))
 }
 
 #[instrument(skip_all)]
 pub async fn get_test(
     State(AppState { db, .. }): State<AppState>,
-    _claims: Claims,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TestsRead>,
     Path(test_id): Path<TestId>,
 ) -> Result<Json<TestWithTasteTesting>> {
-    let Some(test) = db.fetch_test_with_tasting(&test_id).await? else {
+    // Hidden tests do not exist for teams that may not see them
+    let Some(test) = db
+        .fetch_test_with_tasting(&test_id)
+        .await?
+        .filter(|it| claims.can_see_test(&it.test))
+    else {
         return Err(WebError::not_found(location!()));
     };
     Ok(Json(test))
 }
 
+/// Fetches many tests in one request, so clients syncing a whole suite do not need one request per
+/// test. Tests that do not exist or are hidden from the team are left out of the response.
+#[instrument(skip_all)]
+pub async fn get_test_details(
+    State(AppState { db, .. }): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TestsRead>,
+    Query(query): Query<TestDetailsQuery>,
+) -> Result<Json<TestDetailsResponse>> {
+    let test_ids = query
+        .ids
+        .split(',')
+        .filter(|it| !it.is_empty())
+        .map(|it| TestId::from(it.to_string()))
+        .collect::<Vec<_>>();
+    if test_ids.len() > MAX_TEST_DETAILS {
+        return Err(WebError::named_bad_request(
+            format!("at most {MAX_TEST_DETAILS} tests can be fetched at once"),
+            location!(),
+        ));
+    }
+
+    let tests = db
+        .fetch_tests(&test_ids)
+        .await?
+        .into_iter()
+        .filter(|it| claims.can_see_test(it))
+        .map(|test| HashedTest {
+            hash: test.hash(),
+            test,
+        })
+        .collect();
+
+    Ok(Json(TestDetailsResponse { tests }))
+}
+
+/// How many teams currently pass or fail the test, so its author can spot tests that are trivial,
+/// impossible or broken. Only the owner of the test and admins may see them.
+#[instrument(skip_all)]
+pub async fn get_test_stats(
+    State(AppState { db, .. }): State<AppState>,
+    claims: Claims,
+    Path(test_id): Path<TestId>,
+) -> Result<Json<TestStats>> {
+    let Some(test) = db.fetch_test(&test_id).await? else {
+        return Err(WebError::not_found(location!()));
+    };
+    if !claims.is_admin() && test.owner != claims.team {
+        return Err(WebError::unauthorized(location!()));
+    }
+
+    Ok(Json(db.get_test_stats(&test_id).await?))
+}
+
+/// Tells whether the test exists without sending it. The `ETag` is the test hash, so clients can
+/// skip downloading tests they already have.
+#[instrument(skip_all)]
+pub async fn head_test(
+    State(AppState { db, .. }): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TestsRead>,
+    Path(test_id): Path<TestId>,
+) -> Result<Response> {
+    let Some(test) = db
+        .fetch_test(&test_id)
+        .await?
+        .filter(|it| claims.can_see_test(it))
+    else {
+        return Err(WebError::not_found(location!()));
+    };
+
+    Ok([
+        (header::ETAG, format!("\"{}\"", test.hash())),
+        (header::LAST_MODIFIED, http_date(test.last_updated)),
+    ]
+    .into_response())
+}
+
+/// Formats the timestamp as required by HTTP headers, e.g. `Last-Modified`
+pub(super) fn http_date(timestamp: Timestamp) -> String {
+    timestamp.strftime("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Renders the tests into a zip of `<category>/<id>.crow-test.md` files, the layout `crow
+/// sync-tests` produces. Without a category, tests limited to an inactive category are left out,
+/// as they would not be executed either.
+#[instrument(skip_all)]
+pub async fn export_tests(
+    State(state): State<AppState>,
+    ScopedClaims { claims, .. }: ScopedClaims<scopes::TestsRead>,
+    Query(query): Query<ExportTestsQuery>,
+) -> Result<Response> {
+    if query.category.as_ref().is_some_and(|it| {
+        !state
+            .test_config
+            .course(&claims.course)
+            .categories
+            .contains_key(it)
+    }) {
+        return Err(WebError::not_found(location!()));
+    }
+
+    let mut tests = state.db.get_accepted_tests().await?;
+    tests.retain(|it| claims.can_see_test(it));
+    match &query.category {
+        Some(category) => tests.retain(|it| &it.category == category),
+        None => tests.retain(|it| {
+            let active_categories = state.test_config.course(&it.course).active_categories();
+            !it.limited_to_category || active_categories.contains(&it.category.as_str())
+        }),
+    }
+
+    let zip_error = |e: std::io::Error| {
+        WebError::internal_error(format!("could not write test bundle: {e}"), location!())
+    };
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for test in &tests {
+        zip.start_file(
+            format!("{}/{}.crow-test.md", test.category, test.id),
+            SimpleFileOptions::default(),
+        )
+        .map_err(|e| zip_error(e.into()))?;
+        zip.write_all(test.to_markdown().as_bytes())
+            .map_err(zip_error)?;
+    }
+    let bundle = zip.finish().map_err(|e| zip_error(e.into()))?.into_inner();
+
+    let file_name = match &query.category {
+        Some(category) => format!("tests-{category}.zip"),
+        None => "tests.zip".to_string(),
+    };
+    info!(tests = tests.len(), file_name, "Exported test bundle");
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        bundle,
+    )
+        .into_response())
+}
+
+#[instrument(skip_all)]
+pub async fn list_test_suite_snapshots(
+    State(AppState { db, .. }): State<AppState>,
+    _claims: Claims,
+) -> Result<Json<Vec<TestSuiteSnapshotSummary>>> {
+    let snapshots = db.get_test_suite_snapshots().await?;
+    Ok(Json(snapshots.iter().map(Into::into).collect()))
+}
+
 #[instrument(skip_all)]
 pub async fn delete_test(
     State(AppState {
-        db, test_config, ..
+        db,
+        test_config,
+        test_mirror,
+        ..
     }): State<AppState>,
     claims: Claims,
     Path(test_id): Path<TestId>,
@@ -168,7 +647,11 @@ pub async fn delete_test(
             return Err(WebError::unauthorized(location!()));
         }
 
-        if let Some(category) = test_config.categories.get(&test.category) {
+        if let Some(category) = test_config
+            .course(&test.course)
+            .categories
+            .get(&test.category)
+        {
             if test.provisional_for_category.is_none() && category.is_after_test_deadline() {
                 return Err(WebError::named_unauthorized(
                     "delete a finalized test".to_string(),
@@ -186,6 +669,7 @@ pub async fn delete_test(
     );
 
     db.delete_test(&test_id).await?;
+    test_mirror.request_sync();
     Ok(())
 }
 
@@ -196,13 +680,77 @@ pub struct AddTestPayload {
     pub binary_modifiers: Vec<TestModifier>,
     pub category: String,
     pub ignore_test_tasting: bool,
+    /// Only admins may change this
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Tests that must pass before this one is run
+    #[serde(default)]
+    pub depends_on: Vec<TestId>,
+    /// Practice tests are only run locally and never by the server
+    #[serde(default)]
+    pub practice: bool,
+    /// Who may see the test. Only admins may change it, existing tests keep theirs if it is unset.
+    #[serde(default)]
+    pub visibility: Option<TestVisibility>,
+    /// The license and attribution of the test. Defaults to the license of the course.
+    #[serde(default)]
+    pub license: Option<String>,
+    /// The hash the client expects the test to have after the upload
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// The version of the hash algorithm the client used to compute `hash`
+    #[serde(default)]
+    pub hash_version: Option<u32>,
+}
+
+const DEFAULT_PER_PAGE: u32 = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct ListTestsQuery {
+    q: Option<String>,
+    category: Option<String>,
+    creator: Option<TeamId>,
+    course: Option<CourseId>,
+    acceptance: Option<TestAcceptanceState>,
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+const MAX_TEST_DETAILS: usize = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct TestDetailsQuery {
+    /// Comma separated, test ids can not contain commas
+    ids: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestDetailsResponse {
+    tests: Vec<HashedTest>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashedTest {
+    #[serde(flatten)]
+    test: Test,
+    hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportTestsQuery {
+    category: Option<String>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ListTestsResponse {
     pub tests: Vec<TestSummary>,
+    /// The number of tests matching the filters, across all pages
+    pub total: i64,
     pub categories: HashMap<String, TestCategory>,
+    pub hash_version: u32,
 }
 
 #[derive(Serialize)]
@@ -214,6 +762,7 @@ pub struct TestCategory {
     pub labs_end_at: Zoned,
     #[serde(serialize_with = "zoned_as_millis")]
     pub tests_end_at: Zoned,
+    pub compile_only: bool,
 }
 
 impl From<crate::config::TestCategory> for TestCategory {
@@ -222,12 +771,14 @@ impl From<crate::config::TestCategory> for TestCategory {
             starts_at: value.starts_at,
             labs_end_at: value.labs_end_at,
             tests_end_at: value.tests_end_at,
+            compile_only: value.compile_only,
         }
     }
 }
 
 #[derive(Serialize)]
 #[serde(tag = "type")]
+#[allow(clippy::large_enum_variant)] // sent once per upload, the size does not matter
 pub enum SetTestResponse {
     TestAdded(Test),
     TastingFailed { output: TestExecutionOutput },
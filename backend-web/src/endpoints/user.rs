@@ -1,10 +1,15 @@
 use crate::auth::Claims;
 use crate::endpoints::Json;
-use crate::error::Result;
-use crate::types::{AppState, FullUserForAdmin, OwnUser, Team, TeamId, TeamIntegrationToken};
+use crate::error::{Result, WebError};
+use crate::task_notifications::{TaskNotificationError, resolve_webhook_target};
+use crate::types::{
+    AppState, FullUserForAdmin, OwnUser, TaskNotificationSettings, Team, TeamId,
+    TeamIntegrationToken,
+};
 use axum::extract::State;
-use serde::Serialize;
-use tracing::instrument;
+use serde::{Deserialize, Serialize};
+use snafu::location;
+use tracing::{info, instrument};
 
 #[instrument(skip_all)]
 pub async fn show_me_myself(
@@ -20,6 +25,33 @@ pub async fn show_me_myself(
     Ok(Json(MeResponse { user, team }))
 }
 
+/// Lets a user without a team join the team of an invite code
+#[instrument(skip_all)]
+pub async fn redeem_team_invite(
+    State(state): State<AppState>,
+    claims: Claims<Option<TeamId>>,
+    Json(payload): Json<RedeemInvitePayload>,
+) -> Result<Json<MeResponse>> {
+    if let Some(team) = &claims.team {
+        return Err(WebError::named_bad_request(
+            format!("invite, you already are in team `{team}`"),
+            location!(),
+        ));
+    }
+
+    let team = state
+        .db
+        .redeem_team_invite(&claims.sub, payload.code.trim())
+        .await?;
+    info!(user = %claims.sub, team = %team.id, "User joined team with invite");
+    let user = state.db.get_user(&claims.sub).await?;
+
+    Ok(Json(MeResponse {
+        user,
+        team: Some(team),
+    }))
+}
+
 #[instrument(skip_all)]
 pub async fn get_integration_status(
     State(state): State<AppState>,
@@ -33,6 +65,75 @@ pub async fn get_integration_status(
     Ok(Json(IntegrationInfoResponse { token, github }))
 }
 
+#[instrument(skip_all)]
+pub async fn get_task_notification_settings(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> Result<Json<TaskNotificationSettingsResponse>> {
+    let settings = state
+        .db
+        .get_task_notification_settings(&claims.sub)
+        .await?
+        .unwrap_or_default();
+
+    Ok(Json(TaskNotificationSettingsResponse {
+        settings,
+        available: state.task_notifier.is_enabled(),
+        email_available: state.task_notifier.email_available(),
+    }))
+}
+
+#[instrument(skip_all)]
+pub async fn set_task_notification_settings(
+    State(state): State<AppState>,
+    claims: Claims,
+    Json(settings): Json<TaskNotificationSettings>,
+) -> Result<Json<TaskNotificationSettings>> {
+    let settings = TaskNotificationSettings {
+        email: settings.email.filter(|it| !it.trim().is_empty()),
+        webhook_url: settings.webhook_url.filter(|it| !it.trim().is_empty()),
+        ..settings
+    };
+
+    if let Some(email) = &settings.email {
+        if !state.task_notifier.email_available() {
+            return Err(WebError::named_bad_request(
+                "E-mail notifications are not available".to_string(),
+                location!(),
+            ));
+        }
+        if email.parse::<lettre::Address>().is_err() {
+            return Err(WebError::named_bad_request(
+                format!("Invalid e-mail address `{email}`"),
+                location!(),
+            ));
+        }
+    }
+    if let Some(webhook_url) = &settings.webhook_url {
+        let message = match resolve_webhook_target(webhook_url).await {
+            Ok(_) => None,
+            Err(TaskNotificationError::ResolveWebhook { .. }) => {
+                Some(format!("Could not resolve webhook URL `{webhook_url}`"))
+            }
+            Err(TaskNotificationError::InternalWebhook { .. }) => Some(format!(
+                "Webhook URL `{webhook_url}` points to an internal address"
+            )),
+            Err(_) => Some(format!("Invalid webhook URL `{webhook_url}`")),
+        };
+        if let Some(message) = message {
+            return Err(WebError::named_bad_request(message, location!()));
+        }
+    }
+
+    state
+        .db
+        .set_task_notification_settings(&claims.sub, &settings)
+        .await?;
+    info!(user = %claims.sub, "Updated task notification settings");
+
+    Ok(Json(settings))
+}
+
 #[instrument(skip_all)]
 pub async fn list_users(
     State(AppState { db, .. }): State<AppState>,
@@ -66,6 +167,11 @@ pub struct MeResponse {
     pub team: Option<Team>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RedeemInvitePayload {
+    pub code: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct LoginResponse {
     pub user: OwnUser,
@@ -91,6 +197,15 @@ pub struct IntegrationInfoResponse {
     pub github: Option<GithubIntegrationInfoResponse>,
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskNotificationSettingsResponse {
+    pub settings: TaskNotificationSettings,
+    /// Whether task notifications are configured at all
+    pub available: bool,
+    pub email_available: bool,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UserInfo {
@@ -0,0 +1,28 @@
+use super::Json;
+use crate::error::Result;
+use crate::types::AppState;
+use axum::extract::State;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use tracing::instrument;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionResponse {
+    pub version: &'static str,
+    /// Set by the build, missing for plain cargo builds
+    pub git_commit: Option<&'static str>,
+    /// Set by the build, missing for plain cargo builds
+    pub build_date: Option<&'static str>,
+    pub runner_versions: BTreeSet<String>,
+}
+
+#[instrument(skip_all)]
+pub async fn get_version(State(state): State<AppState>) -> Result<Json<VersionResponse>> {
+    Ok(Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: option_env!("CROW_GIT_COMMIT"),
+        build_date: option_env!("CROW_BUILD_DATE"),
+        runner_versions: state.executor.lock().unwrap().runner_versions(),
+    }))
+}
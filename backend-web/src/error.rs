@@ -1,7 +1,8 @@
 use axum::Json;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use serde_json::{Value, json};
+use serde::Serialize;
+use serde_json::{Map, Value, json};
 use snafu::{Location, Report, Snafu};
 use std::fmt::Debug;
 use tracing::warn;
@@ -13,6 +14,11 @@ pub trait HttpError: Debug + snafu::AsErrorSource + Send + Sync {
 
     fn to_error_code(&self) -> &'static str;
 
+    /// A human-readable message without any internal details like source locations.
+    fn to_message(&self) -> String {
+        strip_location(&self.as_error_source().to_string()).to_string()
+    }
+
     fn to_extra(&self) -> Option<Value> {
         None
     }
@@ -161,6 +167,21 @@ impl HttpError for WebError {
         }
     }
 
+    fn to_message(&self) -> String {
+        match self {
+            Self::Unauthorized { .. } => "No permission to access this resource".to_string(),
+            Self::NamedUnauthorized { what, .. } => format!("No permission to {what}"),
+            Self::InvalidCredentials { .. } => "Invalid credentials".to_string(),
+            Self::NamedNotFound { what, .. } => format!("{what} not found"),
+            Self::NamedBadRequest { what, .. } => format!("Bad request: {what}"),
+            Self::NotFound { .. } => "Not found".to_string(),
+            Self::NotInTeam { .. } => "You are not in a team".to_string(),
+            Self::FromHttp { source, .. } => source.to_message(),
+            Self::Sqlx { .. } => "Internal database error".to_string(),
+            Self::InternalError { message, .. } => format!("Internal error: {message}"),
+        }
+    }
+
     fn to_extra(&self) -> Option<Value> {
         match self {
             Self::Unauthorized { .. } => None,
@@ -177,20 +198,48 @@ impl HttpError for WebError {
     }
 }
 
+/// Most of our error messages end in ` at {location}`, which is of no use to API consumers.
+fn strip_location(message: &str) -> &str {
+    match message.rsplit_once(" at ") {
+        Some((rest, location)) if location.contains(".rs:") => rest,
+        _ => message,
+    }
+}
+
+/// The JSON body of every error response. Clients can rely on `code` and the keys in `context`
+/// being stable, while `message` is meant for humans and `error` contains the full report for
+/// debugging.
+#[derive(Debug, Serialize)]
+pub struct ErrorPayload {
+    pub code: &'static str,
+    pub message: String,
+    pub context: Map<String, Value>,
+    pub error: String,
+}
+
+impl ErrorPayload {
+    pub fn from_error<E: HttpError>(error: &E) -> Self {
+        let context = match error.to_extra() {
+            None => Map::new(),
+            Some(Value::Object(map)) => map,
+            Some(other) => Map::from_iter([("value".to_string(), other)]),
+        };
+
+        Self {
+            code: error.to_error_code(),
+            message: error.to_message(),
+            context,
+            error: Report::from_error(error.as_error_source()).to_string(),
+        }
+    }
+}
+
 impl IntoResponse for WebError {
     fn into_response(self) -> Response {
         if self.to_http_code() == StatusCode::INTERNAL_SERVER_ERROR {
             warn!(err = %Report::from_error(&self), "Returned internal error to user");
         }
 
-        (
-            self.to_http_code(),
-            Json(json!({
-                "error": Report::from_error(&self).to_string(),
-                "code": self.to_error_code(),
-                "extra": self.to_extra(),
-            })),
-        )
-            .into_response()
+        (self.to_http_code(), Json(ErrorPayload::from_error(&self))).into_response()
     }
 }
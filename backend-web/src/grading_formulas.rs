@@ -1,6 +1,6 @@
-use crate::config::{TestCategory, TestConfig};
+use crate::config::{CourseConfig, TestCategory};
 use crate::error::WebError;
-use crate::types::{ExecutionExitStatus, FinishedTestSummary};
+use crate::types::{CourseId, FinishedTestSummary, TestSuiteSnapshot, merge_profile_results};
 use evalexpr::{
     ContextWithMutableVariables, DefaultNumericTypes, HashMapContext, Node, Operator, Value,
 };
@@ -18,7 +18,7 @@ struct CategoryInfo {
 impl CategoryInfo {
     fn update(&mut self, test: &FinishedTestSummary) {
         self.total_tests += 1;
-        if test.output == ExecutionExitStatus::Success {
+        if test.passed() {
             self.passed_tests += 1;
         }
     }
@@ -45,23 +45,24 @@ pub fn get_points_for_task(
         return Ok(GradingPoints::new(0.0, formula_to_string(formula)));
     }
 
+    // Tests run under several profiles still count once
     let categories: HashMap<String, CategoryInfo> =
-        tests.iter().fold(HashMap::new(), |mut acc, test| {
-            let Some(category) = &test.borrow().category else {
-                return acc;
-            };
-            if let Some(provisional_category) = &test.borrow().provisional_for_category {
-                // This test was provisional for its category, so we do not count it.
-                if provisional_category == category {
+        merge_profile_results(tests)
+            .into_iter()
+            .fold(HashMap::new(), |mut acc, test| {
+                let Some(category) = &test.category else {
                     return acc;
+                };
+                if let Some(provisional_category) = &test.provisional_for_category {
+                    // This test was provisional for its category, so we do not count it.
+                    if provisional_category == category {
+                        return acc;
+                    }
                 }
-            }
 
-            acc.entry(category.clone())
-                .or_default()
-                .update(test.borrow());
-            acc
-        });
+                acc.entry(category.clone()).or_default().update(test);
+                acc
+            });
 
     get_points(formula, categories)
 }
@@ -117,7 +118,7 @@ fn slugify(name: &str) -> String {
     name.trim_matches('_').to_string()
 }
 
-fn formula_to_string(formula: &Node) -> String {
+pub fn formula_to_string(formula: &Node) -> String {
     let operator = formula.operator();
     let children = formula
         .children()
@@ -163,11 +164,40 @@ fn formula_to_string(formula: &Node) -> String {
     }
 }
 
+/// Attributes test results to the categories the tests were frozen in. Results of tests that were
+/// added to a frozen category after its snapshot was taken no longer belong to any category.
+/// Only snapshots of the given course apply, as other courses may reuse the category names.
+pub fn apply_test_suite_snapshots(
+    summaries: Vec<FinishedTestSummary>,
+    course: &CourseId,
+    snapshots: &[TestSuiteSnapshot],
+) -> Vec<FinishedTestSummary> {
+    let snapshots = snapshots
+        .iter()
+        .filter(|it| it.course == *course)
+        .collect::<Vec<_>>();
+    summaries
+        .into_iter()
+        .map(|mut summary| {
+            if let Some(snapshot) = snapshots.iter().find(|it| it.contains(&summary.test_id)) {
+                summary.category = Some(snapshot.category.clone());
+                summary.provisional_for_category = None;
+            } else if snapshots
+                .iter()
+                .any(|it| summary.category.as_ref() == Some(&it.category))
+            {
+                summary.category = None;
+            }
+            summary
+        })
+        .collect()
+}
+
 /// This answers the question: "This task was submitted in category X, how many points does it get?"
 /// To answer this, we need to look at all test results for this task and then only count those
 /// where the test category is either from *earlier* or the test is not provisional.
 pub fn get_grading_points_for_task(
-    test_config: &TestConfig,
+    course_config: &CourseConfig,
     category_name: &str,
     meta: &TestCategory,
     summaries: &[impl Borrow<FinishedTestSummary>],
@@ -177,7 +207,7 @@ pub fn get_grading_points_for_task(
         return Ok(None);
     };
 
-    let summaries = test_config.get_counting_tests(category_name, summaries);
+    let summaries = course_config.get_counting_tests(category_name, summaries);
 
     // Fetch grading points for the task using the grading formula
     let points = get_points_for_task(grading_formula, &summaries);
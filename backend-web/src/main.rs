@@ -1,24 +1,43 @@
-use crate::auth::oidc::Oidc;
+use crate::auth::password::hash_password;
+use crate::auth::provider::AuthProvider;
 use crate::auth::{Claims, Keys};
-use crate::config::{Config, TeamEntry};
+use crate::config::{Config, TestConfig, read_fileset};
 use crate::db::Database;
 use crate::endpoints::{
-    delete_test, executor_info, get_final_tasks, get_integration_status, get_n_recent_tasks,
-    get_queue, get_queued_task, get_recent_tasks, get_running_task_info, get_task,
-    get_tasks_for_team, get_team_info, get_team_repo, get_test, get_test_tasting_work,
-    get_top_task_per_team, get_work, get_work_tar, head_running_task_info,
-    integration_get_task_status, integration_request_revision, list_tests, list_users, login_oidc,
-    login_oidc_callback, rehash_tests, request_revision, rerun_submissions, runner_done,
-    runner_ping, runner_register, runner_update, set_final_task, set_team_repo, set_test,
-    show_me_myself, snapshot_state, taste_testing_done, team_statistics,
+    accept_test, add_team_member, approve_provisional_test, compare_tasks, course_milestones,
+    create_api_token, create_course, create_team, create_team_invite, delete_api_token,
+    delete_team_invite, delete_test, executor_info, export_tasks_csv, export_test_archive,
+    export_tests, freeze_test_suite, generate_team_ssh_key, get_bisect, get_build_log,
+    get_consistency_report, get_final_tasks, get_integration_status, get_login_provider,
+    get_n_recent_tasks, get_notifications, get_queue, get_queue_position, get_queue_statistics,
+    get_queued_task, get_raw_output, get_recent_tasks, get_repro_bundle, get_runner_logs,
+    get_running_task_info, get_task, get_task_notification_settings, get_task_test,
+    get_tasks_for_team, get_team_activity, get_team_info, get_team_milestones,
+    get_team_performance, get_team_repo, get_team_ssh_key, get_test, get_test_details,
+    get_test_stats, get_test_tasting_work, get_top_task_per_team, get_version, get_work,
+    get_work_tar, head_running_task_info, head_task, head_test, import_test_archive,
+    integration_get_task_status, integration_request_revision, lint_tests, list_api_tokens,
+    list_courses, list_managed_teams, list_provisional_tests, list_snapshots, list_team_invites,
+    list_test_suite_snapshots, list_tests, list_users, login_oidc, login_oidc_callback,
+    login_password, mark_notification_read, patch_course, patch_team, preview_grading_formula,
+    prune_old_tasks, redeem_team_invite, rehash_tests, reject_provisional_test, reject_test,
+    remove_team_member, request_revision, request_runner_logs, rerun_submissions, rerun_task,
+    restore_snapshot, runner_done, runner_logs, runner_ping, runner_register, runner_update,
+    set_final_task, set_runner_role, set_task_notification_settings, set_team_repo, set_test,
+    show_me_myself, simulate_queue_load, snapshot_state, start_bisect, taste_testing_done,
+    team_statistics, test_metrics,
 };
 use crate::error::WebError;
-use crate::storage::LocalRepos;
-use crate::types::{AppState, TeamId, UserId, UserRole};
+use crate::output_storage::OutputStorage;
+use crate::ssh_keys::SshKeyStore;
+use crate::storage::{CloneOptions, LocalRepos};
+use crate::task_notifications::TaskNotifier;
+use crate::test_mirror::TestMirror;
+use crate::types::{AppState, UserRole};
 use axum::extract::{DefaultBodyLimit, Request, State};
 use axum::middleware::Next;
 use axum::response::IntoResponse;
-use axum::routing::{delete, get, head, post, put};
+use axum::routing::{delete, get, head, patch, post, put};
 use axum::{Router, middleware};
 use axum_extra::TypedHeader;
 use axum_extra::headers::Authorization;
@@ -28,28 +47,44 @@ use axum_prometheus::{GenericMetricLayer, Handle, PrometheusMetricLayerBuilder};
 use clap::Parser;
 use clap::builder::Styles;
 use clap::builder::styling::AnsiColor;
-use snafu::{Report, ResultExt, Whatever, location};
-use std::collections::HashMap;
+use shared::CategoryFileset;
+use shared::hash::hash_fileset;
+use snafu::{Report, ResultExt, Whatever, location, whatever};
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::{env, fs};
 use tokio::select;
 use tokio::signal::unix::{SignalKind, signal};
+use tower_http::compression::predicate::{NotForContentType, Predicate};
+use tower_http::compression::{CompressionLayer, DefaultPredicate};
 use tower_http::cors::CorsLayer;
+use tower_http::decompression::RequestDecompressionLayer;
 use tower_http::trace::TraceLayer;
-use tracing::{Instrument, Span, error, instrument, warn};
+use tracing::{Instrument, Span, error, info, instrument, warn};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{Layer, Registry};
 
 mod auth;
+mod bisect;
 mod config;
+mod crashed_tasks;
 mod db;
 mod endpoints;
 mod error;
 mod grading_formulas;
 mod integration;
+mod output_storage;
+mod repro_bundle;
+mod ssh_keys;
 mod storage;
+mod task_export;
+mod task_notifications;
+mod task_retention;
+mod test_archive;
+mod test_lint;
+mod test_mirror;
+mod test_suite_freezer;
 mod types;
 
 // noinspection DuplicatedCode
@@ -64,7 +99,11 @@ const CLAP_STYLE: Styles = Styles::styled()
 #[command(version, about, long_about = None, styles = CLAP_STYLE)]
 struct Args {
     /// Path to the config file
-    config_file: PathBuf,
+    #[clap(required_unless_present = "hash_password")]
+    config_file: Option<PathBuf>,
+    /// Read a password from stdin, print its hash for a static user in the config and exit
+    #[clap(long)]
+    hash_password: bool,
 }
 
 fn logger_config() -> Box<dyn Layer<Registry> + Send + Sync> {
@@ -98,25 +137,52 @@ async fn main() {
 
 async fn main_impl() -> Result<(), Whatever> {
     let args = Args::parse();
-    let config_file = args.config_file;
+    if args.hash_password {
+        let mut password = String::new();
+        std::io::stdin()
+            .read_line(&mut password)
+            .whatever_context("Could not read the password")?;
+        println!("{}", hash_password(password.trim_end_matches(['\r', '\n'])));
+        return Ok(());
+    }
+    let config_file = args.config_file.expect("clap requires the config file");
     if !config_file.exists() || !config_file.is_file() {
         eprintln!("Config file {config_file:?} does not exist or is not a file!");
         std::process::exit(1);
     }
 
-    let config: Config =
+    let mut config: Config =
         toml::from_str(&fs::read_to_string(config_file).whatever_context("File not readable")?)
             .whatever_context("Invalid config")?;
+    config.test.merge_default_course();
 
-    let db = Database::new(&config.database_path)
-        .await
-        .whatever_context("Database error")?;
+    let db = Database::new(
+        &config.database_path,
+        config
+            .execution
+            .output_storage
+            .as_ref()
+            .map(OutputStorage::new),
+    )
+    .await
+    .whatever_context("Database error")?;
 
-    db.sync_teams(&config.teams)
+    db.sync_teams(&config.teams, config.team_sync)
         .await
         .whatever_context("Error syncing teams")?;
+    for course in config.test.courses.keys() {
+        db.ensure_course(course)
+            .await
+            .whatever_context("Error creating configured courses")?;
+    }
+    load_category_filesets(&db, &mut config.test).await?;
+    validate_container_permissions(&config.test)?;
 
     let local_repo_path = config.execution.local_repo_path.clone();
+    let clone_options = CloneOptions {
+        lfs: config.execution.git_lfs,
+        shallow_depth: config.execution.shallow_clone_depth,
+    };
     let state = AppState::new(
         db,
         Keys::new(config.jwt_secret.as_bytes()),
@@ -124,11 +190,13 @@ async fn main_impl() -> Result<(), Whatever> {
         config.execution,
         config.grading,
         config.test,
-        get_team_mapping(config.teams),
-        LocalRepos::new(local_repo_path, config.ssh),
-        Oidc::build_new(config.oidc.clone())
+        LocalRepos::new(local_repo_path, SshKeyStore::new(config.ssh), clone_options),
+        TestMirror::new(config.test_mirror.is_some()),
+        TaskNotifier::new(config.task_notifications.as_ref()),
+        config.retention.clone(),
+        AuthProvider::from_config(&config.auth, config.oidc.as_ref())
             .await
-            .whatever_context("OIDC error")?,
+            .whatever_context("Auth provider error")?,
     );
 
     if let Some(github_config) = config.github.clone() {
@@ -143,6 +211,25 @@ async fn main_impl() -> Result<(), Whatever> {
         });
     }
 
+    tokio::spawn(test_suite_freezer::run_test_suite_freezer(state.clone()));
+    tokio::spawn(crashed_tasks::run_crashed_task_finalizer(state.clone()));
+    tokio::spawn(bisect::run_bisector(state.clone()));
+    if let Some(mirror_config) = config.test_mirror {
+        tokio::spawn(test_mirror::run_test_mirror(state.clone(), mirror_config));
+    }
+    if let Some(notification_config) = config.task_notifications {
+        tokio::spawn(task_notifications::run_task_notification_sender(
+            state.clone(),
+            notification_config,
+        ));
+    }
+    if let Some(retention_config) = config.retention {
+        tokio::spawn(task_retention::run_task_retention(
+            state.clone(),
+            retention_config,
+        ));
+    }
+
     let (prometheus_layer, metric_handle) = PrometheusMetricLayerBuilder::new()
         .with_prefix("compilers-backend")
         .with_default_metrics()
@@ -157,20 +244,75 @@ async fn main_impl() -> Result<(), Whatever> {
     b
 }
 
-fn get_team_mapping(teams: Vec<TeamEntry>) -> HashMap<UserId, (TeamId, UserRole)> {
-    teams
-        .into_iter()
-        .flat_map(|team| {
-            let role = if team.is_admin {
-                UserRole::Admin
-            } else {
-                UserRole::Regular
+/// Reads the filesets of all categories and records their versions, so results of tests run with
+/// an older fileset are marked as outdated
+fn validate_container_permissions(test_config: &TestConfig) -> Result<(), Whatever> {
+    for (course, course_config) in &test_config.courses {
+        let mut user = None;
+        for (name, category) in &course_config.categories {
+            if let Some(path) = category.permissions().find_invalid_path() {
+                whatever!(
+                    "Category `{name}` of course `{course}` uses `{path}`, but paths must be \
+                    absolute without `..`"
+                );
+            }
+            let Some(category_user) = category.container_user else {
+                continue;
             };
-            team.members
-                .into_iter()
-                .map(move |user| (user, (team.id.clone(), role)))
-        })
-        .collect()
+            match user {
+                Some((other, other_user)) if other_user != category_user => {
+                    whatever!(
+                        "Categories `{other}` and `{name}` of course `{course}` use different \
+                        container users, but their tests share a build"
+                    );
+                }
+                _ => user = Some((name, category_user)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn load_category_filesets(
+    db: &Database,
+    test_config: &mut TestConfig,
+) -> Result<(), Whatever> {
+    for (course, course_config) in &mut test_config.courses {
+        for (name, category) in &course_config.categories {
+            let files = match &category.fileset {
+                Some(dir) => Some(read_fileset(dir).with_whatever_context(|_| {
+                    format!("Could not read fileset of category `{name}` at {dir:?}")
+                })?),
+                None => None,
+            };
+            let hash = files.as_deref().map(hash_fileset);
+            let version = db
+                .sync_category_fileset(course, name, hash.as_deref())
+                .await
+                .whatever_context("Error syncing category filesets")?;
+
+            if let (Some(files), Some(hash)) = (files, hash) {
+                info!(
+                    course = %course,
+                    category = %name,
+                    version,
+                    files = files.len(),
+                    "Loaded category fileset"
+                );
+                course_config.filesets.insert(
+                    name.clone(),
+                    CategoryFileset {
+                        version,
+                        hash,
+                        files,
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(())
 }
 
 #[instrument(skip_all)]
@@ -215,6 +357,14 @@ async fn main_server(
             "/executor/info",
             get(executor_info).layer(authed_admin.clone()),
         )
+        .route(
+            "/executor/runners/:runner_id/role",
+            put(set_runner_role).layer(authed_admin.clone()),
+        )
+        .route(
+            "/executor/logs",
+            post(runner_logs).layer(authed_runner.clone()),
+        )
         .route(
             "/executor/ping",
             post(runner_ping).layer(authed_runner.clone()),
@@ -248,51 +398,240 @@ async fn main_server(
             get(integration_get_task_status),
         )
         .route("/queue", get(get_queue))
+        .route("/queue/bisect", post(start_bisect))
+        .route("/queue/bisect/:bisect_id", get(get_bisect))
         .route("/queue/rev/:revision", put(request_revision))
         .route("/queue/task/:task_id", get(get_queued_task))
+        .route("/queue/position/:task_id", get(get_queue_position))
         .route("/repo/:team_id", get(get_team_repo))
         .route("/repo/:team_id", put(set_team_repo))
+        .route("/repo/:team_id/ssh-key", get(get_team_ssh_key))
+        .route(
+            "/repo/:team_id/ssh-key",
+            post(generate_team_ssh_key).layer(authed_admin.clone()),
+        )
+        .route("/tasks/compare", get(compare_tasks))
         .route("/tasks/:task_id", get(get_task))
+        .route("/tasks/:task_id", head(head_task))
+        .route("/tasks/:task_id/build-log", get(get_build_log))
+        .route(
+            "/tasks/:task_id/output/:execution_id/raw",
+            get(get_raw_output),
+        )
+        .route("/tasks/:task_id/repro/:test_id", get(get_repro_bundle))
         .route("/tasks/:task_id/stream", get(get_running_task_info))
         .route("/tasks/:task_id/stream", head(head_running_task_info))
+        .route("/tasks/:task_id/tests/:test_id", get(get_task_test))
+        .route("/team/activity", get(get_team_activity))
         .route("/team/info/:team_id", get(get_team_info))
+        .route("/team/milestones", get(get_team_milestones))
+        .route("/team/notifications", get(get_notifications))
+        .route("/team/performance/:team_id", get(get_team_performance))
+        .route(
+            "/team/notifications/:notification_id/read",
+            post(mark_notification_read),
+        )
         .route(
             "/team/tasks/:team_id",
             get(get_tasks_for_team).layer(authed_admin.clone()),
         )
         .route("/team/recent-tasks", get(get_recent_tasks))
+        .route("/team/tokens", get(list_api_tokens))
+        .route("/team/tokens", post(create_api_token))
+        .route("/team/tokens/:token_id", delete(delete_api_token))
         .route("/team/recent-tasks/:count", get(get_n_recent_tasks))
         .route("/team/final-tasks", get(get_final_tasks))
         .route("/team/final-tasks", put(set_final_task))
         .route("/tests", get(list_tests))
+        .route("/tests/details", get(get_test_details))
+        .route("/tests/export", get(export_tests))
         .route("/tests/:test_id", delete(delete_test))
         .route("/tests/:test_id", get(get_test))
-        .route("/tests/:test_id", put(set_test))
+        .route("/tests/:test_id", head(head_test))
+        // Large tests are gzipped by the client, the body limit applies to the decompressed body
+        .route(
+            "/tests/:test_id",
+            put(set_test).layer(RequestDecompressionLayer::new()),
+        )
+        .route("/tests/:test_id/stats", get(get_test_stats))
+        .route("/test-suite-snapshots", get(list_test_suite_snapshots))
         .route("/top-tasks", get(get_top_task_per_team))
         .route("/users", get(list_users).layer(authed_admin.clone()))
         .route("/users/me", get(show_me_myself))
         .route("/users/me/integrations", get(get_integration_status))
+        .route("/users/me/invite", post(redeem_team_invite))
+        .route(
+            "/users/me/task-notifications",
+            get(get_task_notification_settings),
+        )
+        .route(
+            "/users/me/task-notifications",
+            put(set_task_notification_settings),
+        )
         .route(
             "/admin/snapshot",
             post(snapshot_state).layer(authed_admin.clone()),
         )
+        .route(
+            "/admin/snapshots",
+            get(list_snapshots).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/restore",
+            post(restore_snapshot).layer(authed_admin.clone()),
+        )
         .route(
             "/admin/rerun_submissions/:category",
             post(rerun_submissions).layer(authed_admin.clone()),
         )
+        .route(
+            "/admin/rerun-task/:task_id",
+            post(rerun_task).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/freeze_tests/:category",
+            post(freeze_test_suite).layer(authed_admin.clone()),
+        )
         .route(
             "/admin/rehash_tests",
             post(rehash_tests).layer(authed_admin.clone()),
         )
+        .route(
+            "/admin/lint-tests",
+            post(lint_tests).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/provisional_tests",
+            get(list_provisional_tests).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/provisional_tests/:test_id/approve",
+            post(approve_provisional_test).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/provisional_tests/:test_id/reject",
+            post(reject_provisional_test).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/tests/:test_id/accept",
+            post(accept_test).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/tests/:test_id/reject",
+            post(reject_test).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/queue_simulation",
+            get(simulate_queue_load).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/milestones",
+            get(course_milestones).layer(authed_admin.clone()),
+        )
         .route(
             "/admin/team_statistics",
             get(team_statistics).layer(authed_admin.clone()),
         )
+        .route(
+            "/admin/grading-preview",
+            post(preview_grading_formula).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/test-metrics",
+            get(test_metrics).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/retention/prune",
+            post(prune_old_tasks).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/export/tasks.csv",
+            get(export_tasks_csv).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/tests-export",
+            get(export_test_archive).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/tests-import",
+            post(import_test_archive).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/courses",
+            get(list_courses).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/courses",
+            post(create_course).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/courses/:course_id",
+            patch(patch_course).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/teams",
+            get(list_managed_teams).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/teams",
+            post(create_team).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/teams/:team_id",
+            patch(patch_team).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/teams/:team_id/members/:user_id",
+            put(add_team_member).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/teams/:team_id/members/:user_id",
+            delete(remove_team_member).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/invites",
+            get(list_team_invites).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/invites",
+            post(create_team_invite).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/invites/:code",
+            delete(delete_team_invite).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/runner-logs/:runner_id",
+            get(get_runner_logs).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/runner-logs/:runner_id",
+            post(request_runner_logs).layer(authed_admin.clone()),
+        )
+        .route(
+            "/admin/consistency",
+            get(get_consistency_report).layer(authed_admin.clone()),
+        )
+        .route(
+            "/statistics/queue",
+            get(get_queue_statistics).layer(authed_admin.clone()),
+        )
+        .route("/version", get(get_version))
         .route("/login", get(login_oidc))
+        .route("/login/provider", get(get_login_provider))
+        .route("/login/password", post(login_password))
         .route("/login/oidc/callback", post(login_oidc_callback))
         .layer(DefaultBodyLimit::max(25 * 1024 * 1024)) // 25 MiB
         .layer(prometheus_layer)
         .layer(CorsLayer::very_permissive()) // TODO: Make nicer
+        // Archives are compressed already
+        .layer(
+            CompressionLayer::new().compress_when(
+                DefaultPredicate::new()
+                    .and(NotForContentType::const_new("application/gzip"))
+                    .and(NotForContentType::const_new("application/zip")),
+            ),
+        )
         .layer(TraceLayer::new_for_http())
         .with_state(state);
 
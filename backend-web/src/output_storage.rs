@@ -0,0 +1,143 @@
+use crate::config::OutputStorageConfig;
+use crate::error::{HttpError, WebError};
+use crate::types::OutputStream;
+use axum::http::StatusCode;
+use snafu::{Location, Report, ResultExt, Snafu, location};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+#[derive(Debug, Snafu)]
+pub enum OutputStorageError {
+    #[snafu(display("Could not write the full output to `{}` at {location}", path.display()))]
+    WriteOutput {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not read the full output from `{}` at {location}", path.display()))]
+    ReadOutput {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+impl HttpError for OutputStorageError {
+    fn to_http_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    fn to_error_code(&self) -> &'static str {
+        "output_storage_error"
+    }
+}
+
+impl From<OutputStorageError> for WebError {
+    fn from(value: OutputStorageError) -> Self {
+        warn!(error = ?Report::from_error(&value), "An output storage error occurred");
+
+        Self::http_error(value, location!())
+    }
+}
+
+/// An output as it is stored in the database
+pub struct StoredOutput {
+    pub text: String,
+    /// The file holding the whole output, if the text is shortened
+    pub full_output: Option<PathBuf>,
+}
+
+/// The whole stdout or stderr of an execution
+pub enum FullOutput {
+    /// The output fit into the database
+    Inline(String),
+    File(PathBuf),
+}
+
+/// Writes outputs over the configured size to files, so only their start ends up in the database
+#[derive(Debug, Clone)]
+pub struct OutputStorage {
+    dir: PathBuf,
+    stored_bytes: usize,
+}
+
+impl OutputStorage {
+    pub fn new(config: &OutputStorageConfig) -> Self {
+        Self {
+            dir: config.dir.clone(),
+            stored_bytes: config.stored_bytes,
+        }
+    }
+
+    /// Returns the output to store in the database, writing the whole output to a file first if
+    /// it is too large
+    pub async fn store(
+        &self,
+        execution_id: &str,
+        stream: OutputStream,
+        text: &str,
+    ) -> Result<StoredOutput, OutputStorageError> {
+        if text.len() <= self.stored_bytes {
+            return Ok(StoredOutput {
+                text: text.to_string(),
+                full_output: None,
+            });
+        }
+
+        let extension = match stream {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        };
+        let path = self.dir.join(format!("{execution_id}.{extension}"));
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .context(WriteOutputSnafu { path: &self.dir })?;
+        tokio::fs::write(&path, text)
+            .await
+            .context(WriteOutputSnafu { path: &path })?;
+
+        let mut text = text.to_string();
+        truncate_text(&mut text, self.stored_bytes);
+
+        Ok(StoredOutput {
+            text,
+            full_output: Some(path),
+        })
+    }
+
+    pub async fn open(path: &Path) -> Result<tokio::fs::File, OutputStorageError> {
+        tokio::fs::File::open(path)
+            .await
+            .context(ReadOutputSnafu { path })
+    }
+
+    /// Removes the files of deleted executions. Failures are only logged, as the executions are
+    /// gone already.
+    pub async fn remove(paths: &[PathBuf]) {
+        for path in paths {
+            if let Err(e) = tokio::fs::remove_file(path).await {
+                warn!(
+                    path = %path.display(),
+                    error = %Report::from_error(e),
+                    "Could not remove full output"
+                );
+            }
+        }
+    }
+}
+
+/// Shortens the text to at most `limit` bytes and notes how many bytes were omitted
+pub fn truncate_text(text: &mut String, limit: usize) {
+    if text.len() <= limit {
+        return;
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let omitted = text.len() - end;
+    text.truncate(end);
+    text.push_str(&format!("\n[... {omitted} more bytes]"));
+}
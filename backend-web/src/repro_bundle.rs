@@ -0,0 +1,157 @@
+use crate::error::{HttpError, WebError};
+use axum::http::StatusCode;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use shared::execute::{OUTPUT_BINARY_NAME, modifier_arguments};
+use shared::repro::{
+    BINARY_FILES_DIR, COMPILER_FILES_DIR, FILESET_DIR, MANIFEST_FILE, RUNC_DIR, ReproManifest,
+    STDIN_FILE,
+};
+use shared::{CategoryFileset, TestModifier, TestModifierExt, runc, shell_command_line};
+use snafu::{Location, Report, ResultExt, Snafu, location};
+use std::path::Path;
+use tracing::warn;
+
+const README_FILE: &str = "README.md";
+
+#[derive(Debug, Snafu)]
+pub enum ReproBundleError {
+    #[snafu(display("Could not write `{path}` to the bundle at {location}"))]
+    WriteEntry {
+        path: String,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not finish the bundle at {location}"))]
+    Finish {
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+impl HttpError for ReproBundleError {
+    fn to_http_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    fn to_error_code(&self) -> &'static str {
+        "repro_bundle_error"
+    }
+}
+
+impl From<ReproBundleError> for WebError {
+    fn from(value: ReproBundleError) -> Self {
+        warn!(error = ?Report::from_error(&value), "A repro bundle error occurred");
+
+        Self::http_error(value, location!())
+    }
+}
+
+/// Packs the manifest, the input files of the test, the files of its category and the runc config
+/// templates into a gzipped tarball. The contents only depend on the arguments, so bundling the
+/// same run twice yields identical bytes.
+pub fn write_bundle(
+    manifest: &ReproManifest,
+    fileset: Option<&CategoryFileset>,
+) -> Result<Vec<u8>, ReproBundleError> {
+    let mut entries = vec![
+        (
+            MANIFEST_FILE.to_string(),
+            serde_json::to_string_pretty(manifest).expect("Unexpected json serialize error"),
+        ),
+        (README_FILE.to_string(), readme(manifest)),
+        (
+            format!("{RUNC_DIR}/runc-read-write.json"),
+            runc::READ_WRITE_TEMPLATE.to_string(),
+        ),
+        (
+            format!("{RUNC_DIR}/runc-overlay.json"),
+            runc::OVERLAY_TEMPLATE.to_string(),
+        ),
+    ];
+    for (dir, modifiers) in [
+        (COMPILER_FILES_DIR, &manifest.test.compiler_modifiers),
+        (BINARY_FILES_DIR, &manifest.test.binary_modifiers),
+    ] {
+        entries.extend(program_files(dir, modifiers));
+    }
+    for file in fileset.iter().flat_map(|it| &it.files) {
+        if file.has_safe_path() {
+            entries.push((
+                format!("{FILESET_DIR}/{}", file.path),
+                file.contents.clone(),
+            ));
+        }
+    }
+
+    let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, contents) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(manifest.environment.source_date_epoch);
+        tar.append_data(&mut header, &path, contents.as_bytes())
+            .context(WriteEntrySnafu { path })?;
+    }
+
+    tar.into_inner()
+        .and_then(GzEncoder::finish)
+        .context(FinishSnafu)
+}
+
+/// The argument files and stdin of a program, placed in `dir`
+fn program_files(dir: &str, modifiers: &[TestModifier]) -> Vec<(String, String)> {
+    let (_, files) = modifier_arguments(modifiers, Path::new("/"));
+    let mut files = files
+        .into_iter()
+        .map(|(name, contents)| (format!("{dir}/{name}"), contents))
+        .collect::<Vec<_>>();
+    files.push((format!("{dir}/{STDIN_FILE}"), modifiers.full_input()));
+
+    files
+}
+
+fn readme(manifest: &ReproManifest) -> String {
+    let command = |command: &Option<Vec<String>>| {
+        command
+            .as_deref()
+            .map(shell_command_line)
+            .unwrap_or_else(|| "(not run)".to_string())
+    };
+    let profile = manifest
+        .profile
+        .as_deref()
+        .map(|it| format!(" with profile `{it}`"))
+        .unwrap_or_default();
+
+    format!(
+        "# Reproducing test `{test}` of task `{task}`\n\
+        \n\
+        The task built revision `{revision}` of team `{team}` in the image `{image}` and ran the \
+        test{profile}.\n\
+        Run `crow-client repro {task} {test}` in your repository to rerun it in docker, or follow \
+        the steps below.\n\
+        \n\
+        1. Build your compiler in `/work` with `{build}`\n\
+        2. Copy `{FILESET_DIR}/` into `/work`\n\
+        3. Copy the files of `{COMPILER_FILES_DIR}/` to `/` and run the compiler in `/work`, \
+        with `{COMPILER_FILES_DIR}/{STDIN_FILE}` as stdin:\n   `{compiler}`\n\
+        4. Copy the files of `{BINARY_FILES_DIR}/` to `/` and run `/{OUTPUT_BINARY_NAME}` in \
+        `/work`, with `{BINARY_FILES_DIR}/{STDIN_FILE}` as stdin:\n   `{binary}`\n\
+        \n\
+        Every command runs with the environment variables `CROW_TASK_ID`, `CROW_TEST_ID`, \
+        `CROW_CATEGORY` and `CROW_TIMEOUT_MS` and the pinned locale, timezone, umask and \
+        `SOURCE_DATE_EPOCH` of `{MANIFEST_FILE}`. The expectations of the test are listed there \
+        as well. `{RUNC_DIR}/` contains the templates of the runc configs the executor fills in.\n",
+        test = manifest.test.test_id,
+        task = manifest.task_id,
+        revision = manifest.revision_id,
+        team = manifest.team_id,
+        image = manifest.image,
+        build = shell_command_line(&manifest.build_command),
+        compiler = command(&manifest.invocation.compiler),
+        binary = command(&manifest.invocation.binary),
+    )
+}
@@ -0,0 +1,180 @@
+use crate::config::SshConfig;
+use crate::error::{HttpError, WebError};
+use crate::storage::HandleExitcode;
+use crate::types::TeamId;
+use axum::http::StatusCode;
+use snafu::{Location, Report, ResultExt, Snafu, ensure, location};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+#[derive(Debug, Snafu)]
+pub enum SshKeyError {
+    #[snafu(display("No `ssh.key_dir` is configured to store generated keys in at {location}"))]
+    NoKeyDir {
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Team `{team}` can not be used as a key file name at {location}"))]
+    InvalidTeamName {
+        team: TeamId,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not access key file `{}` at {location}", path.display()))]
+    KeyFile {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not generate a key for `{team}` at {location}"))]
+    Keygen {
+        team: TeamId,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+impl HttpError for SshKeyError {
+    fn to_http_code(&self) -> StatusCode {
+        match self {
+            Self::NoKeyDir { .. } | Self::InvalidTeamName { .. } => StatusCode::BAD_REQUEST,
+            Self::KeyFile { .. } | Self::Keygen { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn to_error_code(&self) -> &'static str {
+        "ssh_key_error"
+    }
+}
+
+impl From<SshKeyError> for WebError {
+    fn from(value: SshKeyError) -> Self {
+        warn!(error = ?Report::from_error(&value), "An ssh key error occurred");
+
+        Self::http_error(value, location!())
+    }
+}
+
+/// The private SSH keys used to access team repositories. Keys generated by the backend are kept
+/// as `<team>` and `<team>.pub` in the key directory and take precedence over the static keys of
+/// the config.
+#[derive(Debug, Clone)]
+pub struct SshKeyStore {
+    key_dir: Option<PathBuf>,
+    static_keys: Arc<HashMap<TeamId, String>>,
+}
+
+impl SshKeyStore {
+    pub fn new(config: Option<SshConfig>) -> Self {
+        let (key_dir, static_keys) = match config {
+            Some(config) => (config.key_dir, config.team_to_key),
+            None => (None, HashMap::new()),
+        };
+
+        Self {
+            key_dir,
+            static_keys: Arc::new(static_keys),
+        }
+    }
+
+    /// The private key to access the repository of the team with, if any
+    pub async fn private_key(&self, team: &TeamId) -> Option<String> {
+        match self.read_generated(team, "").await {
+            Ok(Some(key)) => return Some(key),
+            Ok(None) => {}
+            Err(e) => {
+                warn!(
+                    error = %Report::from_error(&e),
+                    team = %team,
+                    "Could not read generated ssh key, falling back to the config"
+                );
+            }
+        }
+
+        self.static_keys.get(team).cloned()
+    }
+
+    /// The public key of the generated key of the team. Keys from the config have none.
+    pub async fn public_key(&self, team: &TeamId) -> Result<Option<String>, SshKeyError> {
+        self.read_generated(team, ".pub").await
+    }
+
+    /// Generates a new ed25519 key for the team, replacing any previous one, and returns its
+    /// public key
+    pub async fn generate(&self, team: &TeamId) -> Result<String, SshKeyError> {
+        let private_path = self.key_path(team, "")?;
+        let key_dir = private_path
+            .parent()
+            .expect("key path has a parent")
+            .to_path_buf();
+        tokio::fs::create_dir_all(&key_dir)
+            .await
+            .context(KeyFileSnafu { path: &key_dir })?;
+
+        // Generate next to the final location, so moving the key there is atomic
+        let scratch = tempfile::tempdir_in(&key_dir).context(KeyFileSnafu { path: &key_dir })?;
+        let scratch_key = scratch.path().join("key");
+        Command::new("ssh-keygen")
+            .arg("-q")
+            .args(["-t", "ed25519"])
+            .args(["-N", ""])
+            .args(["-C", &format!("crow-{team}")])
+            .arg("-f")
+            .arg(&scratch_key)
+            .handle_exitcode()
+            .await
+            .context(KeygenSnafu { team: team.clone() })?;
+
+        let public_path = self.key_path(team, ".pub")?;
+        rename(&scratch_key.with_extension("pub"), &public_path).await?;
+        rename(&scratch_key, &private_path).await?;
+
+        info!(team = %team, "Generated ssh key");
+
+        tokio::fs::read_to_string(&public_path)
+            .await
+            .map(|it| it.trim().to_string())
+            .context(KeyFileSnafu { path: public_path })
+    }
+
+    async fn read_generated(
+        &self,
+        team: &TeamId,
+        suffix: &str,
+    ) -> Result<Option<String>, SshKeyError> {
+        if self.key_dir.is_none() {
+            return Ok(None);
+        }
+        let path = self.key_path(team, suffix)?;
+
+        match tokio::fs::read_to_string(&path).await {
+            Ok(key) => Ok(Some(key)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context(KeyFileSnafu { path }),
+        }
+    }
+
+    fn key_path(&self, team: &TeamId, suffix: &str) -> Result<PathBuf, SshKeyError> {
+        let Some(key_dir) = &self.key_dir else {
+            return NoKeyDirSnafu.fail();
+        };
+        let name = team.to_string();
+        ensure!(
+            !name.is_empty() && !name.starts_with('.') && !name.contains('/'),
+            InvalidTeamNameSnafu { team: team.clone() }
+        );
+
+        Ok(key_dir.join(format!("{name}{suffix}")))
+    }
+}
+
+async fn rename(from: &Path, to: &Path) -> Result<(), SshKeyError> {
+    tokio::fs::rename(from, to)
+        .await
+        .context(KeyFileSnafu { path: to })
+}
@@ -1,14 +1,16 @@
-use crate::config::SshConfig;
 use crate::error::{HttpError, WebError};
+use crate::ssh_keys::SshKeyStore;
 use crate::types::{Repo, TeamId};
 use axum::http::StatusCode;
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
-use shared::indent;
-use snafu::{Location, Snafu, location};
+use shared::{CommitMetadata, indent};
+use snafu::{Location, OptionExt, Snafu, location};
 use snafu::{Report, ResultExt};
+use std::num::NonZeroU32;
 use std::path::{Path, PathBuf};
 use std::process::Output;
+use std::time::{Duration, SystemTime};
 use sync::mpsc;
 use tokio::process::Command;
 use tokio::sync;
@@ -42,6 +44,16 @@ pub enum GitError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display(
+        "Failed to check out LFS objects at revision `{revision}` for `{team}` at {location}"
+    ))]
+    LfsNotCheckedOut {
+        source: std::io::Error,
+        team: TeamId,
+        revision: RevisionId,
+        #[snafu(implicit)]
+        location: Location,
+    },
     #[snafu(display(
         "Failed to clean repository at revision `{revision}` for `{team}` at {location}"
     ))]
@@ -118,6 +130,28 @@ pub enum GitError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Failed to look up tree of revision `{revision}` at {location}"))]
+    LookupTree {
+        source: std::io::Error,
+        revision: RevisionId,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Failed to list the revisions from `{good}` to `{bad}` at {location}"))]
+    ListRevisions {
+        source: std::io::Error,
+        good: RevisionId,
+        bad: RevisionId,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Unexpected metadata `{output}` of revision `{revision}` at {location}"))]
+    InvalidCommitMetadata {
+        output: String,
+        revision: RevisionId,
+        #[snafu(implicit)]
+        location: Location,
+    },
     #[snafu(display("Failed to export repository `{}` for `{team}` at {location}", path.display()))]
     NotExported {
         team: TeamId,
@@ -150,20 +184,41 @@ impl From<GitError> for WebError {
 #[sqlx(transparent)]
 pub struct RevisionId(String);
 
+/// How the local mirrors of the team repositories are cloned and exported
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CloneOptions {
+    /// Fetch the Git LFS objects of a revision and check them out when exporting it. Otherwise,
+    /// exports contain the LFS pointer files.
+    pub lfs: bool,
+    /// Only clone and fetch this many commits of every branch
+    pub shallow_depth: Option<NonZeroU32>,
+}
+
 #[derive(Debug, Clone)]
 pub struct LocalRepos {
     path: PathBuf,
     updater: mpsc::Sender<RepoUpdateRequest>,
+    ssh_keys: SshKeyStore,
+    options: CloneOptions,
 }
 
 impl LocalRepos {
-    pub fn new(path: PathBuf, ssh_config: Option<SshConfig>) -> Self {
+    pub fn new(path: PathBuf, ssh_keys: SshKeyStore, options: CloneOptions) -> Self {
         let (tx, rx) = mpsc::channel(100);
 
         // Start the repo updater in the background
-        tokio::spawn(repo_updater(rx, ssh_config));
+        tokio::spawn(repo_updater(rx, ssh_keys.clone(), options));
+
+        Self {
+            path,
+            updater: tx,
+            ssh_keys,
+            options,
+        }
+    }
 
-        Self { path, updater: tx }
+    pub fn ssh_keys(&self) -> &SshKeyStore {
+        &self.ssh_keys
     }
 
     pub async fn update_repo(&self, repo: &Repo) -> Result<(), GitError> {
@@ -195,7 +250,7 @@ impl LocalRepos {
                 path = %path.display(),
                 "Repo does not exist, cloning it"
             );
-            clone_mirror(repo, &path, None).await?;
+            clone_mirror(repo, &path, None, self.options).await?;
         }
 
         debug!(
@@ -315,6 +370,108 @@ impl LocalRepos {
             .collect())
     }
 
+    /// Returns the author and commit time of the revision
+    pub async fn get_revision_metadata(
+        &self,
+        repo: &Repo,
+        revision_id: &RevisionId,
+    ) -> Result<CommitMetadata, GitError> {
+        let path = self.get_repo_path(&repo.team);
+
+        let output = Command::new("git")
+            .arg("show")
+            .arg("--no-patch")
+            .arg("--format=%an%x00%ae%x00%ct")
+            .arg("--end-of-options")
+            .arg(revision_id.to_string())
+            .current_dir(&path)
+            .handle_exitcode()
+            .await
+            .context(LookupCommitRevSnafu {
+                revision: revision_id.to_string(),
+            })?;
+
+        let output = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let mut parts = output.split('\0');
+        let (Some(author_name), Some(author_email), Some(committed_at)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return InvalidCommitMetadataSnafu {
+                output,
+                revision: revision_id.clone(),
+            }
+            .fail();
+        };
+        let committed_at =
+            committed_at
+                .parse::<u64>()
+                .ok()
+                .context(InvalidCommitMetadataSnafu {
+                    output: output.clone(),
+                    revision: revision_id.clone(),
+                })?;
+
+        Ok(CommitMetadata {
+            author_name: author_name.to_string(),
+            author_email: author_email.to_string(),
+            committed_at: SystemTime::UNIX_EPOCH + Duration::from_secs(committed_at),
+        })
+    }
+
+    /// Returns the hash of the source tree of the revision. Revisions with the same tree contain
+    /// exactly the same files.
+    pub async fn get_tree_hash(
+        &self,
+        repo: &Repo,
+        revision_id: &RevisionId,
+    ) -> Result<String, GitError> {
+        let path = self.get_repo_path(&repo.team);
+
+        let output = Command::new("git")
+            .arg("rev-parse")
+            .arg("--verify")
+            .arg("--end-of-options")
+            .arg(format!("{revision_id}^{{tree}}"))
+            .current_dir(&path)
+            .handle_exitcode()
+            .await
+            .context(LookupTreeSnafu {
+                revision: revision_id.clone(),
+            })?;
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Returns the revisions that descend from the good revision and are ancestors of the bad
+    /// one, oldest first. The bad revision is the last one, the good one is excluded.
+    pub async fn get_revisions_between(
+        &self,
+        repo: &Repo,
+        good: &RevisionId,
+        bad: &RevisionId,
+    ) -> Result<Vec<RevisionId>, GitError> {
+        let path = self.get_repo_path(&repo.team);
+
+        let output = Command::new("git")
+            .arg("rev-list")
+            .arg("--reverse")
+            .arg("--ancestry-path")
+            .arg("--end-of-options")
+            .arg(format!("{good}..{bad}"))
+            .current_dir(&path)
+            .handle_exitcode()
+            .await
+            .context(ListRevisionsSnafu {
+                good: good.clone(),
+                bad: bad.clone(),
+            })?;
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|it| RevisionId(it.trim().to_string()))
+            .collect())
+    }
+
     pub async fn export_repo(
         &self,
         repo: &Repo,
@@ -328,12 +485,15 @@ impl LocalRepos {
             revision: revision.clone(),
         })?;
 
+        // The LFS objects are not in the mirror, so smudging the pointers would fail. They are
+        // checked out separately below, if enabled.
         Command::new("git")
             .arg("clone")
             .arg("--recursive")
             .arg("--recurse-submodules")
             .arg(&path)
             .arg(tempdir.path())
+            .env("GIT_LFS_SKIP_SMUDGE", "1")
             .handle_exitcode()
             .await
             .context(NotClonedSnafu {
@@ -344,6 +504,7 @@ impl LocalRepos {
             .arg("checkout")
             .arg(revision.to_string())
             .current_dir(tempdir.path())
+            .env("GIT_LFS_SKIP_SMUDGE", "1")
             .handle_exitcode()
             .await
             .context(NotCheckedOutSnafu {
@@ -358,6 +519,7 @@ impl LocalRepos {
             .arg("--init")
             .arg("--recursive")
             .current_dir(tempdir.path())
+            .env("GIT_LFS_SKIP_SMUDGE", "1")
             .handle_exitcode()
             .await
             .context(SubmodulesNotUpdatedSnafu {
@@ -365,6 +527,11 @@ impl LocalRepos {
                 revision: revision.clone(),
             })?;
 
+        if self.options.lfs {
+            self.checkout_lfs_objects(repo, &path, tempdir.path(), revision)
+                .await?;
+        }
+
         Command::new("git")
             .arg("clean")
             .arg("-fdx")
@@ -395,15 +562,67 @@ impl LocalRepos {
         Ok(())
     }
 
+    /// Fetches the LFS objects of the revision into the mirror, where they are kept for later
+    /// exports, and replaces the pointer files in the checkout with them
+    async fn checkout_lfs_objects(
+        &self,
+        repo: &Repo,
+        mirror: &Path,
+        checkout: &Path,
+        revision: &RevisionId,
+    ) -> Result<(), GitError> {
+        let ssh_key = self.ssh_keys.private_key(&repo.team).await;
+        Command::new("git")
+            .arg("lfs")
+            .arg("fetch")
+            .arg("origin")
+            .arg(revision.to_string())
+            .current_dir(mirror)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .with_ssh_key(ssh_key.as_ref())
+            .handle_exitcode()
+            .await
+            .context(LfsNotCheckedOutSnafu {
+                team: repo.team.clone(),
+                revision: revision.clone(),
+            })?;
+
+        // Read the objects straight from the storage of the mirror instead of copying them
+        let storage = std::path::absolute(mirror.join("lfs")).context(LfsNotCheckedOutSnafu {
+            team: repo.team.clone(),
+            revision: revision.clone(),
+        })?;
+        Command::new("git")
+            .arg("-c")
+            .arg(format!("lfs.storage={}", storage.display()))
+            .arg("lfs")
+            .arg("checkout")
+            .current_dir(checkout)
+            .handle_exitcode()
+            .await
+            .context(LfsNotCheckedOutSnafu {
+                team: repo.team.clone(),
+                revision: revision.clone(),
+            })?;
+
+        Ok(())
+    }
+
     fn get_repo_path(&self, team: &TeamId) -> PathBuf {
         self.path.join(team.to_string())
     }
 }
 
-async fn clone_mirror(repo: &Repo, path: &Path, ssh_key: Option<&String>) -> Result<(), GitError> {
+async fn clone_mirror(
+    repo: &Repo,
+    path: &Path,
+    ssh_key: Option<&String>,
+    options: CloneOptions,
+) -> Result<(), GitError> {
     Command::new("git")
         .arg("clone")
         .arg("--mirror")
+        .args(depth_args(options))
         .arg(&repo.url)
         .arg(path)
         .env("GIT_TERMINAL_PROMPT", "0")
@@ -417,9 +636,14 @@ async fn clone_mirror(repo: &Repo, path: &Path, ssh_key: Option<&String>) -> Res
     Ok(())
 }
 
-async fn update_mirror(repo: &Repo, path: &Path, ssh_key: Option<&String>) -> Result<(), GitError> {
+async fn update_mirror(
+    repo: &Repo,
+    path: &Path,
+    ssh_key: Option<&String>,
+    options: CloneOptions,
+) -> Result<(), GitError> {
     if !path.exists() {
-        return clone_mirror(repo, path, ssh_key).await;
+        return clone_mirror(repo, path, ssh_key, options).await;
     }
 
     let current_url = Command::new("git")
@@ -455,13 +679,14 @@ async fn update_mirror(repo: &Repo, path: &Path, ssh_key: Option<&String>) -> Re
                 team: repo.team.clone(),
                 path: path.to_path_buf(),
             })?;
-        return clone_mirror(repo, path, ssh_key).await;
+        return clone_mirror(repo, path, ssh_key, options).await;
     }
 
     Command::new("git")
         .arg("fetch")
         .arg("--all")
         .arg("--prune")
+        .args(depth_args(options))
         .current_dir(path)
         .with_ssh_key(ssh_key)
         .handle_exitcode()
@@ -474,14 +699,24 @@ async fn update_mirror(repo: &Repo, path: &Path, ssh_key: Option<&String>) -> Re
     Ok(())
 }
 
-async fn repo_updater(mut rx: Receiver<RepoUpdateRequest>, ssh_config: Option<SshConfig>) {
-    let team_to_key = ssh_config.map(|it| it.team_to_key).unwrap_or_default();
+/// Limits the history a clone or fetch transfers, for shallow mirrors
+fn depth_args(options: CloneOptions) -> Vec<String> {
+    options
+        .shallow_depth
+        .map(|depth| vec![format!("--depth={depth}")])
+        .unwrap_or_default()
+}
 
+async fn repo_updater(
+    mut rx: Receiver<RepoUpdateRequest>,
+    ssh_keys: SshKeyStore,
+    options: CloneOptions,
+) {
     while let Some(request) = rx.recv().await {
         match request {
             RepoUpdateRequest::UpdateRepo { repo, path, done } => {
-                let ssh_key = team_to_key.get(&repo.team);
-                let res = update_mirror(&repo, &path, ssh_key).await;
+                let ssh_key = ssh_keys.private_key(&repo.team).await;
+                let res = update_mirror(&repo, &path, ssh_key.as_ref(), options).await;
                 if let Err(e) = done.send(res) {
                     warn!(
                         error = ?e,
@@ -497,13 +732,14 @@ async fn repo_updater(mut rx: Receiver<RepoUpdateRequest>, ssh_config: Option<Ss
                 path,
                 done,
             } => {
-                let ssh_key = team_to_key.get(&repo.team);
+                let ssh_key = ssh_keys.private_key(&repo.team).await;
                 let res = Command::new("git")
                     .arg("fetch")
+                    .args(depth_args(options))
                     .arg("origin")
                     .arg(&revision)
                     .current_dir(&path)
-                    .with_ssh_key(ssh_key)
+                    .with_ssh_key(ssh_key.as_ref())
                     .handle_exitcode()
                     .await;
 
@@ -563,7 +799,7 @@ pub enum RepoUpdateRequest {
     },
 }
 
-enum OptionalSsh<'a> {
+pub(crate) enum OptionalSsh<'a> {
     WithoutSsh(&'a mut Command),
     WithSsh {
         command: &'a mut Command,
@@ -571,7 +807,7 @@ enum OptionalSsh<'a> {
     },
 }
 
-trait HandleExitcode {
+pub(crate) trait HandleExitcode {
     async fn handle_exitcode(self) -> std::io::Result<Output>;
 }
 
@@ -633,7 +869,7 @@ impl HandleExitcode for &mut Command {
     }
 }
 
-trait WithSsh<'a> {
+pub(crate) trait WithSsh<'a> {
     fn with_ssh_key(self, key: Option<&String>) -> OptionalSsh<'a>;
 }
 
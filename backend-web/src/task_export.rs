@@ -0,0 +1,147 @@
+use crate::error::WebError;
+use crate::types::{AppState, TaskExportRow, TaskId};
+use futures_util::{Stream, StreamExt, stream};
+use jiff::Timestamp;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Tasks are read in pages, so large ranges neither block the database for long nor have to fit
+/// into memory at once
+const EXPORT_PAGE_SIZE: u32 = 500;
+/// The number of hex characters a pseudonym keeps of its hash
+const PSEUDONYM_LENGTH: usize = 16;
+
+/// Streams the metadata of the tasks queued in `[from, to)` as CSV, one chunk per page of tasks.
+/// With a salt, team and task ids are replaced by salted hashes. The same salt yields the same
+/// pseudonyms, so exports can be joined without revealing who is behind them.
+pub fn export_tasks_csv(
+    state: AppState,
+    from: Timestamp,
+    to: Timestamp,
+    salt: Option<String>,
+) -> impl Stream<Item = Result<String, WebError>> {
+    let mut categories = state
+        .test_config
+        .courses
+        .values()
+        .flat_map(|it| it.categories.keys())
+        .cloned()
+        .collect::<Vec<_>>();
+    categories.sort();
+    categories.dedup();
+    let writer = Arc::new(TaskCsvWriter { categories, salt });
+
+    let header = stream::once(std::future::ready(Ok(writer.header())));
+    let pages = stream::try_unfold(Some((from, TaskId::from(String::new()))), move |cursor| {
+        let state = state.clone();
+        let writer = writer.clone();
+        async move {
+            let Some((after_time, after_id)) = cursor else {
+                return Ok(None);
+            };
+            let tasks = state
+                .db
+                .get_task_export_page((after_time, &after_id), to, EXPORT_PAGE_SIZE)
+                .await?;
+
+            let next = tasks
+                .last()
+                .filter(|_| tasks.len() >= EXPORT_PAGE_SIZE as usize)
+                .map(|it| (it.queue_time, it.task_id.clone()));
+            let chunk = tasks.iter().map(|it| writer.row(it)).collect::<String>();
+
+            Ok(Some((chunk, next)))
+        }
+    });
+
+    header.chain(pages)
+}
+
+struct TaskCsvWriter {
+    /// The categories configured in any course, sorted, each with a passed and a total column
+    categories: Vec<String>,
+    salt: Option<String>,
+}
+
+impl TaskCsvWriter {
+    fn header(&self) -> String {
+        let mut columns = [
+            "task_id",
+            "team",
+            "queue_time",
+            "start_time",
+            "end_time",
+            "queue_wait_ms",
+            "duration_ms",
+            "build_status",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        for category in &self.categories {
+            columns.push(format!("{category}_passed"));
+            columns.push(format!("{category}_total"));
+        }
+
+        csv_line(&columns)
+    }
+
+    fn row(&self, task: &TaskExportRow) -> String {
+        let queue_wait = task
+            .start_time
+            .duration_since(task.queue_time)
+            .as_millis()
+            .max(0);
+        let duration = task
+            .end_time
+            .duration_since(task.start_time)
+            .as_millis()
+            .max(0);
+
+        let mut columns = vec![
+            self.pseudonymize(task.task_id.to_string()),
+            self.pseudonymize(task.team_id.to_string()),
+            task.queue_time.to_string(),
+            task.start_time.to_string(),
+            task.end_time.to_string(),
+            queue_wait.to_string(),
+            duration.to_string(),
+            task.build_status.clone().unwrap_or_default(),
+        ];
+        for category in &self.categories {
+            let (passed, total) = task.category_counts.get(category).unwrap_or(&(0, 0));
+            columns.push(passed.to_string());
+            columns.push(total.to_string());
+        }
+
+        csv_line(&columns)
+    }
+
+    fn pseudonymize(&self, id: String) -> String {
+        let Some(salt) = &self.salt else {
+            return id;
+        };
+        let mut hash = format!("{:x}", Sha256::digest(format!("{salt}:{id}").as_bytes()));
+        hash.truncate(PSEUDONYM_LENGTH);
+
+        hash
+    }
+}
+
+/// Joins the columns to a line as described in RFC 4180, quoting them where needed
+fn csv_line(columns: &[String]) -> String {
+    let mut line = String::new();
+    for (index, column) in columns.iter().enumerate() {
+        if index > 0 {
+            line.push(',');
+        }
+        if column.contains([',', '"', '\r', '\n']) {
+            let _ = write!(line, "\"{}\"", column.replace('"', "\"\""));
+        } else {
+            line.push_str(column);
+        }
+    }
+    line.push_str("\r\n");
+
+    line
+}
@@ -0,0 +1,473 @@
+use crate::config::{SmtpConfig, SmtpSecurity, TaskNotificationConfig};
+use crate::error::WebError;
+use crate::types::{
+    AppState, TaskNotificationChannel, TaskNotificationDelivery, TaskNotificationPayload,
+    TaskOutcome, TeamId,
+};
+use jiff::Timestamp;
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use shared::{FinishedCompilerTask, TestExecutionOutput};
+use snafu::{Location, Report, ResultExt, Snafu};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, error, info, instrument, warn};
+use url::{Host, Url};
+use uuid::Uuid;
+
+/// How often we look for deliveries whose retry is due, if nothing new was queued
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// The delay before the first retry. It doubles with every further attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(60);
+/// Deliveries failing this often are dropped
+const MAX_ATTEMPTS: u32 = 6;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Snafu)]
+pub enum TaskNotificationError {
+    #[snafu(display("Webhook `{url}` is no valid http(s) URL at {location}"))]
+    InvalidWebhook {
+        url: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not resolve webhook `{url}` at {location}"))]
+    ResolveWebhook {
+        url: String,
+        source: io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Webhook `{url}` points to the internal address `{address}` at {location}"))]
+    InternalWebhook {
+        url: String,
+        address: IpAddr,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not reach webhook `{url}` at {location}"))]
+    Webhook {
+        url: String,
+        source: reqwest::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Invalid e-mail address `{address}` at {location}"))]
+    Address {
+        address: String,
+        source: lettre::address::AddressError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not build the e-mail at {location}"))]
+    BuildEmail {
+        source: lettre::error::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not send the e-mail at {location}"))]
+    Smtp {
+        source: lettre::transport::smtp::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("E-mail notifications are not configured at {location}"))]
+    EmailUnavailable {
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// Schedules the delivery of task notifications
+#[derive(Debug, Clone)]
+pub struct TaskNotifier {
+    notify: Option<Arc<Notify>>,
+    frontend_url: Option<String>,
+    email_available: bool,
+}
+
+impl TaskNotifier {
+    pub fn new(config: Option<&TaskNotificationConfig>) -> Self {
+        Self {
+            notify: config.map(|_| Arc::new(Notify::new())),
+            frontend_url: config.and_then(|it| it.frontend_url.clone()),
+            email_available: config.is_some_and(|it| it.smtp.is_some()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.notify.is_some()
+    }
+
+    pub fn email_available(&self) -> bool {
+        self.email_available
+    }
+}
+
+/// Queues a notification for every member of the task's team that wants to hear about it. Does
+/// nothing if task notifications are not configured.
+#[instrument(skip_all)]
+pub async fn enqueue_task_notifications(
+    state: &AppState,
+    task: &FinishedCompilerTask,
+) -> Result<(), WebError> {
+    let notifier = &state.task_notifier;
+    let Some(notify) = &notifier.notify else {
+        return Ok(());
+    };
+
+    let payload = build_payload(task, notifier.frontend_url.as_deref());
+    let now = Timestamp::now();
+    let mut deliveries = Vec::new();
+    for (user, settings) in state
+        .db
+        .get_task_notification_settings_for_team(&payload.team)
+        .await?
+    {
+        let wanted = match payload.outcome {
+            TaskOutcome::Success => settings.on_success,
+            TaskOutcome::TestsFailed | TaskOutcome::BuildFailed => settings.on_failure,
+        };
+        if !wanted {
+            continue;
+        }
+
+        let email = settings
+            .email
+            .filter(|_| notifier.email_available)
+            .map(|it| (TaskNotificationChannel::Email, it));
+        let webhook = settings
+            .webhook_url
+            .map(|it| (TaskNotificationChannel::Webhook, it));
+        for (channel, target) in email.into_iter().chain(webhook) {
+            deliveries.push(TaskNotificationDelivery {
+                id: Uuid::new_v4().to_string().into(),
+                user: user.clone(),
+                channel,
+                target,
+                payload: payload.clone(),
+                attempts: 0,
+                next_attempt: now,
+            });
+        }
+    }
+
+    if deliveries.is_empty() {
+        return Ok(());
+    }
+    debug!(
+        task = %payload.task_id,
+        count = deliveries.len(),
+        "Queued task notifications"
+    );
+    state
+        .db
+        .add_task_notification_deliveries(&deliveries)
+        .await?;
+    notify.notify_one();
+
+    Ok(())
+}
+
+fn build_payload(
+    task: &FinishedCompilerTask,
+    frontend_url: Option<&str>,
+) -> TaskNotificationPayload {
+    let info = task.info();
+    let (outcome, passed_tests, total_tests) = match task {
+        FinishedCompilerTask::BuildFailed { .. } => (TaskOutcome::BuildFailed, 0, 0),
+        FinishedCompilerTask::RanTests { tests, .. } => {
+            let passed = tests
+                .iter()
+                .filter(|it| matches!(it.output, TestExecutionOutput::Success { .. }))
+                .count();
+            let outcome = if passed == tests.len() {
+                TaskOutcome::Success
+            } else {
+                TaskOutcome::TestsFailed
+            };
+            (outcome, passed, tests.len())
+        }
+    };
+
+    TaskNotificationPayload {
+        task_id: info.task_id.clone().into(),
+        team: TeamId::from(info.team_id.clone()),
+        revision: info.revision_id.clone(),
+        commit_message: info.commit_message.clone(),
+        outcome,
+        passed_tests,
+        total_tests,
+        url: frontend_url.map(|it| format!("{it}/task-detail/{}", info.task_id)),
+    }
+}
+
+struct Sender {
+    mailer: Option<(AsyncSmtpTransport<Tokio1Executor>, Mailbox)>,
+}
+
+/// Sends queued task notifications whenever new ones arrive, retrying failed ones with an
+/// exponential backoff
+#[instrument(skip_all)]
+pub async fn run_task_notification_sender(state: AppState, config: TaskNotificationConfig) {
+    let Some(notify) = state.task_notifier.notify.clone() else {
+        return;
+    };
+
+    let mailer = match config.smtp.as_ref().map(build_mailer).transpose() {
+        Ok(mailer) => mailer,
+        Err(e) => {
+            error!(
+                error = %Report::from_error(e),
+                "Invalid SMTP config, e-mail notifications will fail"
+            );
+            None
+        }
+    };
+    let sender = Sender { mailer };
+
+    loop {
+        if let Err(e) = send_due_notifications(&state, &sender).await {
+            warn!(error = %Report::from_error(e), "Failed to send task notifications");
+        }
+        tokio::select! {
+            _ = notify.notified() => {}
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+fn build_mailer(
+    config: &SmtpConfig,
+) -> Result<(AsyncSmtpTransport<Tokio1Executor>, Mailbox), TaskNotificationError> {
+    let mut builder = match config.security {
+        SmtpSecurity::StartTls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host).context(SmtpSnafu)?
+        }
+        SmtpSecurity::Tls => {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host).context(SmtpSnafu)?
+        }
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+    };
+    if let Some(port) = config.port {
+        builder = builder.port(port);
+    }
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    let from = config.from.parse::<Mailbox>().context(AddressSnafu {
+        address: config.from.clone(),
+    })?;
+
+    Ok((builder.build(), from))
+}
+
+async fn send_due_notifications(state: &AppState, sender: &Sender) -> Result<(), WebError> {
+    for delivery in state.db.get_due_task_notification_deliveries().await? {
+        let res = match delivery.channel {
+            TaskNotificationChannel::Email => send_email(sender, &delivery).await,
+            TaskNotificationChannel::Webhook => send_webhook(&delivery).await,
+        };
+        let Err(e) = res else {
+            info!(
+                task = %delivery.payload.task_id,
+                user = %delivery.user,
+                channel = %delivery.channel,
+                "Sent task notification"
+            );
+            state
+                .db
+                .delete_task_notification_delivery(&delivery.id)
+                .await?;
+            continue;
+        };
+
+        let attempts = delivery.attempts + 1;
+        if attempts >= MAX_ATTEMPTS {
+            warn!(
+                task = %delivery.payload.task_id,
+                user = %delivery.user,
+                channel = %delivery.channel,
+                error = %Report::from_error(&e),
+                "Giving up on task notification"
+            );
+            state
+                .db
+                .delete_task_notification_delivery(&delivery.id)
+                .await?;
+            continue;
+        }
+
+        let delay = RETRY_BASE_DELAY * 2u32.pow(delivery.attempts);
+        let next_attempt = Timestamp::now()
+            .checked_add(delay)
+            .expect("retry delay is small");
+        debug!(
+            task = %delivery.payload.task_id,
+            user = %delivery.user,
+            attempts,
+            error = %Report::from_error(&e),
+            "Task notification failed, retrying later"
+        );
+        state
+            .db
+            .reschedule_task_notification_delivery(
+                &delivery.id,
+                next_attempt,
+                &Report::from_error(&e).to_string(),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// A webhook whose host resolved to public addresses only
+pub struct WebhookTarget {
+    url: Url,
+    domain: Option<String>,
+    addresses: Vec<SocketAddr>,
+}
+
+/// Resolves the host of a webhook and rejects it if any of its addresses lies in the internal
+/// network. Users could otherwise make the backend send requests to itself or its neighbours.
+pub async fn resolve_webhook_target(url: &str) -> Result<WebhookTarget, TaskNotificationError> {
+    let parsed = Url::parse(url)
+        .ok()
+        .filter(|it| it.scheme() == "https" || it.scheme() == "http");
+    let Some(parsed) = parsed else {
+        return InvalidWebhookSnafu { url }.fail();
+    };
+    let (Some(host), Some(port)) = (parsed.host(), parsed.port_or_known_default()) else {
+        return InvalidWebhookSnafu { url }.fail();
+    };
+
+    let (domain, addresses) = match host {
+        Host::Ipv4(ip) => (None, vec![SocketAddr::new(ip.into(), port)]),
+        Host::Ipv6(ip) => (None, vec![SocketAddr::new(ip.into(), port)]),
+        Host::Domain(domain) => {
+            let addresses: Vec<_> = tokio::net::lookup_host((domain, port))
+                .await
+                .context(ResolveWebhookSnafu { url })?
+                .collect();
+            if addresses.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "no addresses"))
+                    .context(ResolveWebhookSnafu { url });
+            }
+            (Some(domain.to_string()), addresses)
+        }
+    };
+    if let Some(address) = addresses.iter().find(|it| is_internal_address(it.ip())) {
+        return InternalWebhookSnafu {
+            url,
+            address: address.ip(),
+        }
+        .fail();
+    }
+
+    Ok(WebhookTarget {
+        url: parsed,
+        domain,
+        addresses,
+    })
+}
+
+fn is_internal_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // "This network" and the carrier-grade NAT shared address space
+                || first == 0
+                || (first == 100 && (second & 0b1100_0000) == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal_address(ip.into()),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+            }
+        },
+    }
+}
+
+async fn send_webhook(delivery: &TaskNotificationDelivery) -> Result<(), TaskNotificationError> {
+    let target = resolve_webhook_target(&delivery.target).await?;
+
+    // Pin the connection to the addresses we checked, so the host can not rebind to an internal
+    // one in between. Redirects could lead anywhere and are not followed.
+    let mut client = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none());
+    if let Some(domain) = &target.domain {
+        client = client.resolve_to_addrs(domain, &target.addresses);
+    }
+
+    client
+        .build()
+        .expect("Could not build http client")
+        .post(target.url)
+        .json(&delivery.payload)
+        .send()
+        .await
+        .and_then(|it| it.error_for_status())
+        .context(WebhookSnafu {
+            url: delivery.target.clone(),
+        })?;
+
+    Ok(())
+}
+
+async fn send_email(
+    sender: &Sender,
+    delivery: &TaskNotificationDelivery,
+) -> Result<(), TaskNotificationError> {
+    let Some((mailer, from)) = &sender.mailer else {
+        return EmailUnavailableSnafu.fail();
+    };
+    let to = delivery.target.parse::<Mailbox>().context(AddressSnafu {
+        address: delivery.target.clone(),
+    })?;
+
+    let payload = &delivery.payload;
+    let summary = match payload.outcome {
+        TaskOutcome::Success => format!("all {} tests passed", payload.total_tests),
+        TaskOutcome::TestsFailed => format!(
+            "{} of {} tests passed",
+            payload.passed_tests, payload.total_tests
+        ),
+        TaskOutcome::BuildFailed => "the build failed".to_string(),
+    };
+    let mut body = format!(
+        "Your task for revision {} ({}) finished: {summary}.\n",
+        payload.revision, payload.commit_message
+    );
+    if let Some(url) = &payload.url {
+        body.push_str(&format!("\nDetails: {url}\n"));
+    }
+
+    let message = Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(format!("[crow] Task finished: {summary}"))
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)
+        .context(BuildEmailSnafu)?;
+    mailer.send(message).await.context(SmtpSnafu)?;
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use crate::config::RetentionConfig;
+use crate::error::WebError;
+use crate::types::{AppState, RetentionReport, TaskId};
+use jiff::Timestamp;
+use snafu::Report;
+use std::collections::HashSet;
+use tracing::{info, instrument, warn};
+
+/// Tasks are deleted in batches, so other writers are not blocked for long
+const DELETE_BATCH_SIZE: usize = 100;
+
+/// Periodically deletes the tasks the retention policy no longer keeps
+#[instrument(skip_all)]
+pub async fn run_task_retention(state: AppState, config: RetentionConfig) {
+    loop {
+        match prune_tasks(&state, &config, false).await {
+            Ok(report) if !report.tasks.is_empty() => {
+                info!(
+                    tasks = report.tasks.len(),
+                    execution_results = report.execution_results,
+                    "Pruned old tasks"
+                );
+            }
+            Ok(_) => {}
+            Err(e) => warn!(error = %Report::from_error(e), "Failed to prune old tasks"),
+        }
+        tokio::time::sleep(config.interval).await;
+    }
+}
+
+/// Collects the tasks the policy no longer keeps and deletes them, unless this is a dry run
+pub async fn prune_tasks(
+    state: &AppState,
+    config: &RetentionConfig,
+    dry_run: bool,
+) -> Result<RetentionReport, WebError> {
+    let finished_before = config
+        .max_age
+        .map(|it| Timestamp::now().checked_sub(it).unwrap_or(Timestamp::MIN));
+    let selected = get_selected_tasks(state).await?;
+    let tasks = state
+        .db
+        .get_prunable_tasks(finished_before, config.max_tasks_per_team)
+        .await?
+        .into_iter()
+        .filter(|it| !selected.contains(&it.task_id))
+        .collect::<Vec<_>>();
+    let task_ids = tasks
+        .iter()
+        .map(|it| it.task_id.clone())
+        .collect::<Vec<_>>();
+
+    let execution_results = if dry_run {
+        state.db.count_task_execution_results(&task_ids).await?
+    } else {
+        let mut deleted = 0;
+        for batch in task_ids.chunks(DELETE_BATCH_SIZE) {
+            deleted += state.db.delete_tasks(batch).await?;
+        }
+        deleted
+    };
+
+    Ok(RetentionReport {
+        dry_run,
+        tasks,
+        execution_results,
+    })
+}
+
+/// The tasks currently graded in some category and the best task of every team, which are shown
+/// to users even if they are old
+async fn get_selected_tasks(state: &AppState) -> Result<HashSet<TaskId>, WebError> {
+    let mut selected = state
+        .db
+        .get_top_task_per_team()
+        .await?
+        .into_values()
+        .map(|it| TaskId::from(it.info().task_id.clone()))
+        .collect::<HashSet<_>>();
+
+    for team in state.db.get_teams().await? {
+        for (category, meta) in &state.test_config.course(&team.course).categories {
+            let task = state
+                .db
+                .get_final_submitted_task_for_team_and_category(
+                    &team.id,
+                    &team.course,
+                    category,
+                    meta,
+                    true,
+                )
+                .await?;
+            selected.extend(task.map(|it| it.task_id()));
+        }
+    }
+
+    Ok(selected)
+}
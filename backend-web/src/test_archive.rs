@@ -0,0 +1,276 @@
+use crate::auth::{Keys, sign_test_archive, verify_test_archive};
+use crate::error::{HttpError, WebError};
+use crate::types::{
+    CourseId, RenamedTest, TeamId, Test, TestConflictResolution, TestId, TestImportReport,
+};
+use axum::http::StatusCode;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snafu::{Location, Report, ResultExt, Snafu, ensure, location};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+use tracing::warn;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// Bumped whenever archives of older versions can no longer be read
+const ARCHIVE_FORMAT_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "tests.json";
+const SIGNATURE_FILE: &str = "tests.json.sig";
+
+#[derive(Debug, Snafu)]
+pub enum TestArchiveError {
+    #[snafu(display("Could not sign the archive at {location}"))]
+    Sign {
+        source: jsonwebtoken::errors::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not write the archive at {location}"))]
+    WriteArchive {
+        source: zip::result::ZipError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("The upload is no valid test suite archive at {location}"))]
+    ReadArchive {
+        source: zip::result::ZipError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not read `{name}` from the archive at {location}"))]
+    ReadEntry {
+        name: &'static str,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("The archive signature is invalid at {location}"))]
+    InvalidSignature {
+        source: jsonwebtoken::errors::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("The archive was modified after it was signed at {location}"))]
+    DigestMismatch {
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not parse the archived tests at {location}"))]
+    ParseManifest {
+        source: serde_json::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Archive format version {version} is not supported at {location}"))]
+    UnsupportedVersion {
+        version: u32,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Archived test `{test_id}` does not match its hash at {location}"))]
+    HashMismatch {
+        test_id: TestId,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+impl HttpError for TestArchiveError {
+    fn to_http_code(&self) -> StatusCode {
+        match self {
+            Self::Sign { .. } | Self::WriteArchive { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn to_error_code(&self) -> &'static str {
+        "test_archive_error"
+    }
+}
+
+impl From<TestArchiveError> for WebError {
+    fn from(value: TestArchiveError) -> Self {
+        warn!(error = ?Report::from_error(&value), "A test archive error occurred");
+
+        Self::http_error(value, location!())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveManifest {
+    format_version: u32,
+    #[serde(with = "jiff::fmt::serde::timestamp::millisecond::required")]
+    exported_at: Timestamp,
+    categories: BTreeSet<String>,
+    tests: Vec<ArchivedTest>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchivedTest {
+    #[serde(flatten)]
+    test: Test,
+    hash: String,
+}
+
+/// Packs the tests into a zip with their metadata and a signature over it
+pub fn write_archive(tests: &[Test], keys: &Keys) -> Result<Vec<u8>, TestArchiveError> {
+    let manifest = ArchiveManifest {
+        format_version: ARCHIVE_FORMAT_VERSION,
+        exported_at: Timestamp::now(),
+        categories: tests.iter().map(|it| it.category.clone()).collect(),
+        tests: tests
+            .iter()
+            .map(|test| ArchivedTest {
+                test: test.clone(),
+                hash: test.hash(),
+            })
+            .collect(),
+    };
+    let manifest = serde_json::to_vec(&manifest).expect("Unexpected json serialize error");
+    let signature =
+        sign_test_archive(format!("{:x}", Sha256::digest(&manifest)), keys).context(SignSnafu)?;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in [
+        (MANIFEST_FILE, manifest),
+        (SIGNATURE_FILE, signature.into()),
+    ] {
+        zip.start_file(name, SimpleFileOptions::default())
+            .context(WriteArchiveSnafu)?;
+        zip.write_all(&contents)
+            .map_err(Into::into)
+            .context(WriteArchiveSnafu)?;
+    }
+
+    Ok(zip.finish().context(WriteArchiveSnafu)?.into_inner())
+}
+
+/// Verifies the signature and hashes of an archive and returns the tests in it
+pub fn read_archive(archive: &[u8], keys: &Keys) -> Result<Vec<Test>, TestArchiveError> {
+    let mut zip = ZipArchive::new(Cursor::new(archive)).context(ReadArchiveSnafu)?;
+    let manifest = read_entry(&mut zip, MANIFEST_FILE)?;
+    let signature = read_entry(&mut zip, SIGNATURE_FILE)?;
+
+    let signature = String::from_utf8_lossy(&signature);
+    let signed_digest =
+        verify_test_archive(signature.trim(), keys).context(InvalidSignatureSnafu)?;
+    ensure!(
+        signed_digest == format!("{:x}", Sha256::digest(&manifest)),
+        DigestMismatchSnafu
+    );
+
+    let manifest: ArchiveManifest =
+        serde_json::from_slice(&manifest).context(ParseManifestSnafu)?;
+    ensure!(
+        manifest.format_version == ARCHIVE_FORMAT_VERSION,
+        UnsupportedVersionSnafu {
+            version: manifest.format_version
+        }
+    );
+
+    manifest
+        .tests
+        .into_iter()
+        .map(|archived| {
+            ensure!(
+                archived.test.hash() == archived.hash,
+                HashMismatchSnafu {
+                    test_id: archived.test.id.clone()
+                }
+            );
+            Ok(archived.test)
+        })
+        .collect()
+}
+
+fn read_entry(
+    zip: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &'static str,
+) -> Result<Vec<u8>, TestArchiveError> {
+    let mut entry = zip.by_name(name).context(ReadArchiveSnafu)?;
+    let mut contents = Vec::new();
+    entry
+        .read_to_end(&mut contents)
+        .context(ReadEntrySnafu { name })?;
+
+    Ok(contents)
+}
+
+/// Resolves id conflicts with the existing tests and hands tests of unknown teams to the
+/// importing one. All tests move into the course of the importing team, so archives carry test
+/// suites over to the next semester. Returns the tests to store and what happened to each of them.
+pub fn plan_import(
+    tests: Vec<Test>,
+    existing_tests: &HashSet<TestId>,
+    teams: &HashSet<TeamId>,
+    categories: &HashSet<&str>,
+    resolution: TestConflictResolution,
+    importing_team: &TeamId,
+    course: &CourseId,
+) -> (Vec<Test>, TestImportReport) {
+    let mut report = TestImportReport::default();
+    let mut taken = existing_tests.clone();
+    let mut renames = HashMap::new();
+    let mut planned = Vec::new();
+
+    for mut test in tests {
+        if !existing_tests.contains(&test.id) {
+            report.imported.push(test.id.clone());
+        } else {
+            match resolution {
+                TestConflictResolution::Skip => {
+                    report.skipped.push(test.id);
+                    continue;
+                }
+                TestConflictResolution::Overwrite => report.overwritten.push(test.id.clone()),
+                TestConflictResolution::Rename => {
+                    let new_id = unused_test_id(&test.id, &taken);
+                    report.renamed.push(RenamedTest {
+                        from: test.id.clone(),
+                        to: new_id.clone(),
+                    });
+                    renames.insert(test.id.clone(), new_id.clone());
+                    test.id = new_id;
+                }
+            }
+        }
+        taken.insert(test.id.clone());
+        test.course = course.clone();
+
+        if !teams.contains(&test.owner) {
+            test.owner = importing_team.clone();
+            report.reassigned.push(test.id.clone());
+        }
+        if !categories.contains(test.category.as_str())
+            && !report.unknown_categories.contains(&test.category)
+        {
+            report.unknown_categories.push(test.category.clone());
+        }
+        planned.push(test);
+    }
+
+    // Dependencies between archived tests follow them to their new ids
+    for test in &mut planned {
+        for dependency in &mut test.depends_on {
+            if let Some(new_id) = renames.get(dependency) {
+                *dependency = new_id.clone();
+            }
+        }
+    }
+
+    (planned, report)
+}
+
+fn unused_test_id(id: &TestId, taken: &HashSet<TestId>) -> TestId {
+    (1..)
+        .map(|attempt| match attempt {
+            1 => TestId::from(format!("{id}-imported")),
+            n => TestId::from(format!("{id}-imported-{n}")),
+        })
+        .find(|candidate| !taken.contains(candidate))
+        .expect("there are infinitely many candidates")
+}
@@ -0,0 +1,121 @@
+use crate::config::TestConfig;
+use crate::types::{Test, TestId, TestLintReport, TestLintViolation};
+use shared::TestModifier;
+use shared::validation::{TestValidationInput, validate_test};
+use std::collections::{HashMap, HashSet};
+
+/// Looks for redundant, contradicting and invalid tests in the suite
+pub fn lint_tests(tests: &[Test], test_config: &TestConfig) -> TestLintReport {
+    // Modifiers have no equality, their JSON does
+    let mut by_modifiers: HashMap<String, Vec<TestId>> = HashMap::new();
+    let mut by_input: HashMap<String, HashMap<String, Vec<TestId>>> = HashMap::new();
+
+    for test in tests {
+        let (compiler_input, compiler_expectations) = split_modifiers(&test.compiler_modifiers);
+        let (binary_input, binary_expectations) = split_modifiers(&test.binary_modifiers);
+
+        by_modifiers
+            .entry(key(&(
+                &test.course,
+                &test.compiler_modifiers,
+                &test.binary_modifiers,
+            )))
+            .or_default()
+            .push(test.id.clone());
+
+        // Tests without any input are all alike, comparing their expectations tells nothing
+        if compiler_input.is_empty() && binary_input.is_empty() {
+            continue;
+        }
+        by_input
+            .entry(key(&(&test.course, compiler_input, binary_input)))
+            .or_default()
+            .entry(key(&(compiler_expectations, binary_expectations)))
+            .or_default()
+            .push(test.id.clone());
+    }
+
+    let duplicates = by_modifiers
+        .into_values()
+        .filter(|it| it.len() > 1)
+        .collect();
+    let conflicts = by_input
+        .into_values()
+        .filter(|it| it.len() > 1)
+        .map(|it| it.into_values().flatten().collect())
+        .collect();
+
+    TestLintReport {
+        duplicates: sorted_groups(duplicates),
+        conflicts: sorted_groups(conflicts),
+        violations: violations(tests, test_config),
+    }
+}
+
+fn violations(tests: &[Test], test_config: &TestConfig) -> Vec<TestLintViolation> {
+    let test_ids = tests.iter().map(|it| &it.id).collect::<HashSet<_>>();
+    let mut violations = Vec::new();
+    let mut add = |test: &Test, problem: String| {
+        violations.push(TestLintViolation {
+            test_id: test.id.clone(),
+            problem,
+        })
+    };
+
+    for test in tests {
+        let categories = &test_config.course(&test.course).categories;
+        let Some(category) = categories.get(&test.category) else {
+            add(test, format!("unknown category `{}`", test.category));
+            continue;
+        };
+
+        let depends_on = test
+            .depends_on
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let problems = validate_test(&TestValidationInput {
+            id: &test.id.to_string(),
+            compiler_modifiers: &test.compiler_modifiers,
+            binary_modifiers: &test.binary_modifiers,
+            depends_on: &depends_on,
+            license: test.license.as_deref(),
+            compile_only: category.compile_only,
+        });
+        for problem in problems {
+            add(test, problem.to_string());
+        }
+
+        for dependency in test.depends_on.iter().filter(|it| !test_ids.contains(it)) {
+            add(test, format!("dependency on unknown test `{dependency}`"));
+        }
+    }
+
+    violations
+}
+
+/// Splits the modifiers into the ones passing input to the program and the ones saying what is
+/// expected of it
+fn split_modifiers(modifiers: &[TestModifier]) -> (Vec<&TestModifier>, Vec<&TestModifier>) {
+    modifiers.iter().partition(|it| {
+        matches!(
+            it,
+            TestModifier::ProgramArgument { .. }
+                | TestModifier::ProgramArgumentFile { .. }
+                | TestModifier::ProgramInput { .. }
+        )
+    })
+}
+
+fn key(value: &impl serde::Serialize) -> String {
+    serde_json::to_string(value).expect("Unexpected json serialize error")
+}
+
+fn sorted_groups(mut groups: Vec<Vec<TestId>>) -> Vec<Vec<TestId>> {
+    for group in &mut groups {
+        group.sort_by_key(ToString::to_string);
+    }
+    groups.sort_by_key(|it| it[0].to_string());
+
+    groups
+}
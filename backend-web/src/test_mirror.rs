@@ -0,0 +1,199 @@
+use crate::config::TestMirrorConfig;
+use crate::error::WebError;
+use crate::storage::{HandleExitcode, WithSsh};
+use crate::types::AppState;
+use jiff::Timestamp;
+use snafu::{Location, Report, ResultExt, Snafu};
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::Notify;
+use tracing::{debug, info, instrument, warn};
+
+/// How long we wait for further changes before pushing, so bulk edits end up in a single commit
+const DEBOUNCE: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Snafu)]
+pub enum TestMirrorError {
+    #[snafu(display("Could not fetch the tests at {location}"))]
+    FetchTests {
+        source: WebError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not run `git {command}` in mirror `{}` at {location}", path.display()))]
+    Git {
+        command: String,
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not write `{}` at {location}", path.display()))]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// Schedules pushes of the public test suite to the configured git mirror
+#[derive(Debug, Clone)]
+pub struct TestMirror {
+    notify: Option<Arc<Notify>>,
+}
+
+impl TestMirror {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            notify: enabled.then(|| Arc::new(Notify::new())),
+        }
+    }
+
+    /// Syncs the mirror soon. Does nothing if no mirror is configured.
+    pub fn request_sync(&self) {
+        if let Some(notify) = &self.notify {
+            notify.notify_one();
+        }
+    }
+}
+
+/// Keeps the mirror in sync with the test suite. Syncs once on startup and then whenever a sync
+/// was requested.
+#[instrument(skip_all)]
+pub async fn run_test_mirror(state: AppState, config: TestMirrorConfig) {
+    let Some(notify) = state.test_mirror.notify.clone() else {
+        return;
+    };
+
+    loop {
+        if let Err(e) = sync_mirror(&state, &config).await {
+            warn!(error = %Report::from_error(e), "Failed to sync test mirror");
+        }
+        notify.notified().await;
+        tokio::time::sleep(DEBOUNCE).await;
+    }
+}
+
+/// Renders all public tests into `<category>/<id>.crow-test.md`, as `crow sync-tests` does, and
+/// pushes them if anything changed. Provisional tests are left out until they are accepted.
+async fn sync_mirror(state: &AppState, config: &TestMirrorConfig) -> Result<(), TestMirrorError> {
+    let path = config.local_path.as_path();
+    let deploy_key = config.deploy_key.as_ref();
+
+    if path.join(".git").exists() {
+        git(path, &["remote", "set-url", "origin", &config.remote], None).await?;
+    } else {
+        tokio::fs::create_dir_all(path)
+            .await
+            .context(WriteSnafu { path })?;
+        git(path, &["init"], None).await?;
+        git(path, &["remote", "add", "origin", &config.remote], None).await?;
+    }
+    git(path, &["checkout", "-B", &config.branch], None).await?;
+    // A fresh remote does not have the branch yet
+    match git(path, &["fetch", "origin", &config.branch], deploy_key).await {
+        Ok(_) => {
+            git(path, &["reset", "--hard", "FETCH_HEAD"], None).await?;
+        }
+        Err(e) => debug!(error = %Report::from_error(e), "Could not fetch test mirror"),
+    }
+
+    remove_test_directories(path).await?;
+
+    let tests = state
+        .db
+        .get_accepted_tests()
+        .await
+        .context(FetchTestsSnafu)?;
+    // The mirror is public, so hidden tests only appear with the first sync after they are revealed
+    let now = Timestamp::now();
+    let tests = tests
+        .into_iter()
+        .filter(|it| it.provisional_for_category.is_none())
+        .filter(|it| it.visibility.is_public_at(now))
+        .collect::<Vec<_>>();
+    for test in &tests {
+        let dir = path.join(&test.category);
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .context(WriteSnafu { path: &dir })?;
+        let file = dir.join(format!("{}.crow-test.md", test.id));
+        tokio::fs::write(&file, test.to_markdown())
+            .await
+            .context(WriteSnafu { path: file })?;
+    }
+
+    git(path, &["add", "-A"], None).await?;
+    let status = git(path, &["status", "--porcelain"], None).await?;
+    if status.stdout.is_empty() {
+        debug!("Test mirror is up to date");
+        return Ok(());
+    }
+
+    let message = format!("Update test suite ({} tests)", tests.len());
+    git(
+        path,
+        &[
+            "-c",
+            "user.name=crow",
+            "-c",
+            "user.email=crow@localhost",
+            "commit",
+            "-m",
+            &message,
+        ],
+        None,
+    )
+    .await?;
+    git(
+        path,
+        &["push", "origin", &format!("HEAD:{}", config.branch)],
+        deploy_key,
+    )
+    .await?;
+
+    info!(tests = tests.len(), "Pushed test suite to mirror");
+
+    Ok(())
+}
+
+/// Every directory besides `.git` holds the tests of a category. Files at the top level, like a
+/// README, are kept.
+async fn remove_test_directories(path: &Path) -> Result<(), TestMirrorError> {
+    let mut entries = tokio::fs::read_dir(path)
+        .await
+        .context(WriteSnafu { path })?;
+
+    while let Some(entry) = entries.next_entry().await.context(WriteSnafu { path })? {
+        let entry_path = entry.path();
+        if entry.file_name() == ".git" || !entry_path.is_dir() {
+            continue;
+        }
+        tokio::fs::remove_dir_all(&entry_path)
+            .await
+            .context(WriteSnafu { path: entry_path })?;
+    }
+
+    Ok(())
+}
+
+async fn git(
+    path: &Path,
+    args: &[&str],
+    ssh_key: Option<&String>,
+) -> Result<Output, TestMirrorError> {
+    Command::new("git")
+        .args(args)
+        .current_dir(path)
+        .with_ssh_key(ssh_key)
+        .handle_exitcode()
+        .await
+        .context(GitSnafu {
+            command: args.join(" "),
+            path,
+        })
+}
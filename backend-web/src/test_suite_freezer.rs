@@ -0,0 +1,44 @@
+use crate::types::AppState;
+use snafu::Report;
+use std::time::Duration;
+use tracing::{info, instrument, warn};
+
+/// How often we check whether a category reached its lab deadline
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Freezes the test suite of every category once its lab deadline passed. Categories that are
+/// already frozen are left alone, admins can explicitly re-freeze them.
+#[instrument(skip_all)]
+pub async fn run_test_suite_freezer(state: AppState) {
+    loop {
+        if let Err(e) = freeze_finished_categories(&state).await {
+            warn!(error = %Report::from_error(e), "Failed to freeze test suites");
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+async fn freeze_finished_categories(state: &AppState) -> crate::error::Result<()> {
+    let snapshots = state.db.get_test_suite_snapshots().await?;
+
+    for (course, config) in &state.test_config.courses {
+        for (name, category) in &config.categories {
+            if !category.is_after_labs_deadline()
+                || snapshots
+                    .iter()
+                    .any(|it| &it.course == course && &it.category == name)
+            {
+                continue;
+            }
+            let snapshot = state.db.freeze_test_suite(course, name, None).await?;
+            info!(
+                course = %course,
+                category = %name,
+                tests = snapshot.tests.len(),
+                "Froze test suite after lab deadline"
+            );
+        }
+    }
+
+    Ok(())
+}
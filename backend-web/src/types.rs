@@ -1,48 +1,116 @@
+pub use self::api_token::ApiToken;
+pub use self::api_token::ApiTokenId;
+pub use self::api_token::ApiTokenScope;
+pub use self::bisect::Bisect;
+pub use self::bisect::BisectId;
+pub use self::bisect::BisectStep;
+pub use self::consistency::ConsistencyIssue;
+pub use self::consistency::ConsistencyReport;
+pub use self::course::Course;
+pub use self::course::CourseId;
 pub use self::execution::ExecutionExitStatus;
 pub use self::execution::Executor;
 pub use self::execution::ExecutorInfo;
+pub use self::execution::MAX_LEASE_RECLAIMS;
+pub use self::execution::OutputStream;
 pub use self::execution::QueuedTaskStatus;
 pub use self::execution::RunnerForFrontend;
+pub use self::execution::RunnerLogsForFrontend;
 pub use self::execution::RunningTaskState;
 pub use self::execution::TaskId;
 pub use self::execution::WorkItem;
 pub use self::external::CreatedExternalRun;
 pub use self::external::ExternalRunId;
 pub use self::external::ExternalRunStatus;
+pub use self::milestone::CategoryMilestone;
+pub use self::milestone::CourseMilestone;
+pub use self::milestone::MILESTONE_THRESHOLDS;
+pub use self::milestone::TeamMilestones;
+pub use self::milestone::TestFirstPass;
+pub use self::notification::Notification;
+pub use self::notification::NotificationId;
+pub use self::queue::ProjectedQueueHour;
+pub use self::queue::QueueSimulationParams;
+pub use self::queue::QueueStatisticsDay;
+pub use self::queue::simulate_queue;
 pub use self::repo::Repo;
+pub use self::repo::TeamSshKey;
+pub use self::retention::PrunableTask;
+pub use self::retention::RetentionReport;
+pub use self::task::FailureCluster;
 pub use self::task::FinalSubmittedTask;
 pub use self::task::FinishedCompilerTaskStatistics;
 pub use self::task::FinishedCompilerTaskSummary;
 pub use self::task::FinishedTestSummary;
+pub use self::task::TaskComparison;
+pub use self::task::TaskExportRow;
+pub use self::task::TaskPerformance;
+pub use self::task::TeamActivityDay;
+pub use self::task::TeamPerformance;
+pub use self::task::TestStatusChange;
+pub use self::task::cluster_failures;
+pub use self::task::merge_profile_results;
+pub use self::task_notification::TaskNotificationChannel;
+pub use self::task_notification::TaskNotificationDelivery;
+pub use self::task_notification::TaskNotificationDeliveryId;
+pub use self::task_notification::TaskNotificationPayload;
+pub use self::task_notification::TaskNotificationSettings;
+pub use self::task_notification::TaskOutcome;
+pub use self::test::ReferenceOutput;
+pub use self::test::RenamedTest;
 pub use self::test::Test;
+pub use self::test::TestAcceptance;
+pub use self::test::TestAcceptanceState;
+pub use self::test::TestConflictResolution;
 pub use self::test::TestId;
+pub use self::test::TestImportReport;
+pub use self::test::TestLintReport;
+pub use self::test::TestLintViolation;
+pub use self::test::TestMetrics;
+pub use self::test::TestSearch;
+pub use self::test::TestStats;
+pub use self::test::TestSuiteSnapshot;
+pub use self::test::TestSuiteSnapshotSummary;
 pub use self::test::TestSummary;
+pub use self::test::TestTastingResult;
+pub use self::test::TestVisibility;
 pub use self::test::TestWithTasteTesting;
 pub use self::test_tasting::TestTasting;
 pub use self::user::FullUserForAdmin;
+pub use self::user::ManagedTeam;
 pub use self::user::OwnUser;
 pub use self::user::Team;
 pub use self::user::TeamId;
 pub use self::user::TeamInfo;
 pub use self::user::TeamIntegrationToken;
+pub use self::user::TeamInvite;
 pub use self::user::User;
 pub use self::user::UserId;
 pub use self::user::UserRole;
 use crate::auth::Keys;
-use crate::auth::oidc::Oidc;
-use crate::config::{ExecutionConfig, GradingConfig, TestConfig};
+use crate::auth::provider::AuthProvider;
+use crate::config::{ExecutionConfig, GradingConfig, RetentionConfig, TestConfig};
 use crate::db::Database;
 use crate::storage::LocalRepos;
+use crate::task_notifications::TaskNotifier;
+use crate::test_mirror::TestMirror;
 use crate::types::queue::Queue;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
+mod api_token;
+mod bisect;
+mod consistency;
+mod course;
 mod execution;
 mod external;
+mod milestone;
+mod notification;
 mod queue;
 mod repo;
+mod retention;
 mod task;
+mod task_notification;
 mod test;
 mod test_tasting;
 mod user;
@@ -50,6 +118,36 @@ mod user;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JwtIssuer(pub String);
 
+/// The slice of a list endpoint's results to return
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    /// Negative for no limit
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Page {
+    pub const MAX_PER_PAGE: u32 = 500;
+
+    /// Everything at once
+    pub fn all() -> Self {
+        Self {
+            limit: -1,
+            offset: 0,
+        }
+    }
+
+    /// Pages start at 1. `per_page` is capped at [Self::MAX_PER_PAGE].
+    pub fn new(page: u32, per_page: u32) -> Self {
+        let per_page = per_page.clamp(1, Self::MAX_PER_PAGE) as i64;
+
+        Self {
+            limit: per_page,
+            offset: page.saturating_sub(1) as i64 * per_page,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: Database,
@@ -57,13 +155,15 @@ pub struct AppState {
     pub execution_config: ExecutionConfig,
     pub grading_config: GradingConfig,
     pub test_config: TestConfig,
-    pub team_mapping: HashMap<UserId, (TeamId, UserRole)>,
     pub executor: Arc<Mutex<Executor>>,
     pub test_tasting: Arc<Mutex<TestTasting>>,
     pub queue: Arc<Mutex<Queue>>,
     pub local_repos: LocalRepos,
+    pub test_mirror: TestMirror,
+    pub task_notifier: TaskNotifier,
+    pub retention_config: Option<RetentionConfig>,
     pub github_app_name: Option<String>,
-    pub oidc: Oidc,
+    pub auth: AuthProvider,
 }
 
 impl AppState {
@@ -75,9 +175,11 @@ impl AppState {
         execution_config: ExecutionConfig,
         grading_config: GradingConfig,
         test_config: TestConfig,
-        team_mapping: HashMap<UserId, (TeamId, UserRole)>,
         local_repos: LocalRepos,
-        oidc: Oidc,
+        test_mirror: TestMirror,
+        task_notifier: TaskNotifier,
+        retention_config: Option<RetentionConfig>,
+        auth: AuthProvider,
     ) -> Self {
         Self {
             db,
@@ -85,13 +187,15 @@ impl AppState {
             execution_config,
             grading_config,
             test_config,
-            team_mapping,
             executor: Executor::new(),
             test_tasting: TestTasting::new(),
             queue: Arc::new(Mutex::new(Queue::new())),
             local_repos,
+            test_mirror,
+            task_notifier,
+            retention_config,
             github_app_name,
-            oidc,
+            auth,
         }
     }
 }
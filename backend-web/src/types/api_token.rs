@@ -0,0 +1,39 @@
+use crate::types::{TeamId, UserId};
+use derive_more::{Display, From};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Hash, From, PartialEq, Eq, Display, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct ApiTokenId(String);
+
+/// What an API token may be used for. Endpoints accepting API tokens require exactly one scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+pub enum ApiTokenScope {
+    #[serde(rename = "queue:read")]
+    #[display("queue:read")]
+    QueueRead,
+    #[serde(rename = "queue:write")]
+    #[display("queue:write")]
+    QueueWrite,
+    #[serde(rename = "tasks:read")]
+    #[display("tasks:read")]
+    TasksRead,
+    #[serde(rename = "tests:read")]
+    #[display("tests:read")]
+    TestsRead,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    pub id: ApiTokenId,
+    pub team: TeamId,
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    pub created_by: UserId,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub created_at: Timestamp,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::optional::serialize")]
+    pub last_used_at: Option<Timestamp>,
+}
@@ -0,0 +1,58 @@
+use crate::types::{TaskId, TeamId, TestId};
+use derive_more::{Display, From};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Hash, From, PartialEq, Eq, Display, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct BisectId(String);
+
+/// A search for the first revision between a passing and a failing one that fails a test, like
+/// `git bisect`. Every step is a queued task running only that test.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bisect {
+    pub id: BisectId,
+    pub team_id: TeamId,
+    pub test_id: TestId,
+    pub good_revision: String,
+    pub bad_revision: String,
+    /// The revisions after the good one up to and including the bad one, oldest first
+    pub candidates: Vec<String>,
+    /// The newest candidate known to pass, -1 for the good revision
+    pub good_index: i64,
+    /// The oldest candidate known to fail
+    pub bad_index: i64,
+    /// The first failing revision, once it was found
+    pub first_bad_revision: Option<String>,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub created_at: Timestamp,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::optional::serialize")]
+    pub finished_at: Option<Timestamp>,
+    /// Oldest first
+    pub steps: Vec<BisectStep>,
+}
+
+impl Bisect {
+    /// The step whose task did not finish yet
+    pub fn running_step(&self) -> Option<&BisectStep> {
+        self.steps.iter().find(|it| it.passed.is_none())
+    }
+
+    /// The candidate to test next, or none if the first failing one is known
+    pub fn next_candidate(&self) -> Option<usize> {
+        (self.bad_index - self.good_index > 1)
+            .then(|| ((self.good_index + self.bad_index) / 2) as usize)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BisectStep {
+    pub task_id: TaskId,
+    pub revision: String,
+    /// Absent while the task is queued or running
+    pub passed: Option<bool>,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub queued_at: Timestamp,
+}
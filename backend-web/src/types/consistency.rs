@@ -0,0 +1,40 @@
+use serde::Serialize;
+
+/// Rows violating one consistency rule
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyIssue {
+    pub count: u64,
+    /// The offending rows, at most [MAX_REPORTED_IDS] of them
+    pub ids: Vec<String>,
+    /// The rows deleted by the repair. Zero unless a repair was requested.
+    pub repaired: u64,
+}
+
+/// How many offending ids are listed for every issue
+pub const MAX_REPORTED_IDS: usize = 100;
+
+impl ConsistencyIssue {
+    pub fn new(ids: Vec<String>) -> Self {
+        Self {
+            count: ids.len() as u64,
+            ids: ids.into_iter().take(MAX_REPORTED_IDS).collect(),
+            repaired: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyReport {
+    /// Whether the safe repairs were applied
+    pub fixed: bool,
+    /// Build and test outputs no task, test result or test tasting refers to
+    pub orphaned_executions: ConsistencyIssue,
+    /// Test results of tests that no longer exist, as `<task>/<test>`
+    pub results_of_deleted_tests: ConsistencyIssue,
+    /// Finalized submissions whose task no longer exists, as `<team>/<category>`
+    pub finalized_missing_tasks: ConsistencyIssue,
+    /// Queued tasks of teams that no longer exist
+    pub queued_for_unknown_teams: ConsistencyIssue,
+}
@@ -0,0 +1,41 @@
+use derive_more::{Display, From};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+/// The course everything created before there were several of them belongs to
+const DEFAULT_COURSE: &str = "default";
+
+/// Courses share one deployment, but their teams, tests, tasks and queues are kept apart
+#[derive(
+    Debug,
+    Clone,
+    Hash,
+    From,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Display,
+    Serialize,
+    Deserialize,
+    sqlx::Type,
+)]
+#[sqlx(transparent)]
+pub struct CourseId(String);
+
+impl Default for CourseId {
+    fn default() -> Self {
+        Self(DEFAULT_COURSE.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Course {
+    pub id: CourseId,
+    pub display_name: String,
+    /// Archived courses are read-only, their teams can no longer submit tasks or tests
+    pub archived: bool,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub created_at: Timestamp,
+}
@@ -3,29 +3,52 @@ use crate::types::{FinishedTestSummary, TeamId, TestId};
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
 use shared::{
-    ExecutionOutput, FinishedCompilerTask, FinishedExecution, RunnerId, RunnerInfo,
-    TestExecutionOutput, deserialize_system_time, serialize_system_time,
+    CommitMetadata, ExecutionOutput, FinishedCompilerTask, FinishedExecution, RunnerId, RunnerInfo,
+    RunnerLogs, TestExecutionOutput, deserialize_system_time, serialize_system_time,
 };
 use snafu::{Location, Snafu, ensure};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 use tokio::select;
 use tokio::sync::broadcast;
 use tracing::warn;
 
+/// How long a runner may stay silent (no ping or update) before its task is reclaimed.
+/// Runners ping every 15 seconds, so this allows for a few missed pings.
+const LEASE_DURATION: Duration = Duration::from_secs(2 * 60);
+/// How often a task is handed out again after its runner vanished, before it is given up
+pub const MAX_LEASE_RECLAIMS: u32 = 3;
+
 #[derive(Debug, Clone)]
 pub struct Runner {
     pub info: RunnerInfo,
     pub working_on: Option<WorkItem>,
+    /// The point in time the lease for [Self::working_on] expires, unless it is renewed by a ping
+    /// or update before. Expired leases are reclaimed and the task can be handed out again.
+    pub lease_expires: SystemTime,
     pub last_ping: SystemTime,
+    /// The role of the runner. An admin may have assigned a different one than it registered with.
     pub test_taster: bool,
+    /// Whether an admin asked for the recent logs of the runner and it did not push them yet
+    pub logs_requested: bool,
+    pub logs: Option<ReceivedRunnerLogs>,
+}
+
+impl Runner {
+    fn has_live_lease(&self) -> bool {
+        self.working_on.is_some() && self.lease_expires > SystemTime::now()
+    }
+
+    fn renew_lease(&mut self) {
+        self.lease_expires = SystemTime::now() + LEASE_DURATION;
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
 pub enum RunnerWorkForFrontend {
-    Testing(WorkItem),
+    Testing(Box<WorkItem>),
     TestTasting,
 }
 
@@ -55,13 +78,33 @@ impl From<&Runner> for RunnerForFrontend {
         Self {
             id: value.info.id.clone(),
             info: value.info.info.clone(),
-            working_on: value.working_on.clone().map(RunnerWorkForFrontend::Testing),
+            working_on: value
+                .working_on
+                .clone()
+                .map(|it| RunnerWorkForFrontend::Testing(Box::new(it))),
             last_seen: value.last_ping,
             test_taster: value.test_taster,
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceivedRunnerLogs {
+    #[serde(serialize_with = "serialize_system_time")]
+    pub received_at: SystemTime,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunnerLogsForFrontend {
+    /// Whether newer logs were requested, but the runner did not push them yet
+    pub requested: bool,
+    /// The logs the runner pushed most recently
+    pub logs: Option<ReceivedRunnerLogs>,
+}
+
 struct InternalRunningTaskState {
     so_far: Vec<RunnerUpdateForFrontend>,
     sender: broadcast::Sender<RunnerUpdateForFrontend>,
@@ -90,6 +133,12 @@ pub enum RunnerUpdate {
         result: FinishedTestSummary,
     },
     Done,
+    /// A previous runner of the task vanished, so it is executed again from scratch
+    #[serde(rename_all = "camelCase")]
+    Requeued {
+        attempt: u32,
+        max_attempts: u32,
+    },
 }
 
 impl From<shared::RunnerUpdate> for RunnerUpdate {
@@ -108,18 +157,44 @@ impl From<shared::RunnerUpdate> for RunnerUpdate {
 
 #[derive(Debug, Clone, Serialize)]
 pub struct RunnerUpdateForFrontend {
+    /// Increases with every update of a task, so reconnecting clients can skip what they have
+    pub id: u64,
     update: RunnerUpdate,
     #[serde(serialize_with = "serialize_system_time")]
     time: SystemTime,
 }
 
-impl From<RunnerUpdate> for RunnerUpdateForFrontend {
-    fn from(value: RunnerUpdate) -> Self {
+impl RunnerUpdateForFrontend {
+    fn new(id: u64, update: RunnerUpdate) -> Self {
         Self {
-            update: value,
+            id,
+            update,
             time: SystemTime::now(),
         }
     }
+
+    /// Replaces the ids of hidden tests by their [TestId::masked] pseudonym
+    pub fn mask_hidden_tests(mut self, hidden: &HashSet<TestId>) -> Self {
+        match &mut self.update {
+            RunnerUpdate::AllTests { tests } => {
+                for test in tests.iter_mut().filter(|it| hidden.contains(*it)) {
+                    *test = test.masked();
+                }
+            }
+            RunnerUpdate::StartedTest { test_id } => {
+                let id = TestId::from(test_id.clone());
+                if hidden.contains(&id) {
+                    *test_id = id.masked().to_string();
+                }
+            }
+            RunnerUpdate::FinishedTest { result } if hidden.contains(&result.test_id) => {
+                result.test_id = result.test_id.masked();
+            }
+            _ => {}
+        }
+
+        self
+    }
 }
 
 #[derive(Debug, Snafu)]
@@ -136,11 +211,29 @@ pub enum ExecutorError {
 pub struct ExecutorInfo {
     pub runners: Vec<RunnerForFrontend>,
     pub in_progress: Vec<(TaskId, usize)>,
+    pub tasting_queue: TastingQueueInfo,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TastingQueueInfo {
+    /// Tastings waiting for a runner
+    pub open: usize,
+    /// Tastings a runner is working on
+    pub running: usize,
 }
 
 pub struct Executor {
     runners: HashMap<RunnerId, Runner>,
     in_progress: HashMap<TaskId, InternalRunningTaskState>,
+    /// Tasks whose runner vanished after reporting test results. They are not handed out again
+    /// until they were finalized from the recorded results.
+    crashed: HashSet<TaskId>,
+    /// How often the lease of a task expired, i.e. its runner vanished while holding it
+    lease_reclaims: HashMap<TaskId, u32>,
+    /// Tasks that were reclaimed more than [MAX_LEASE_RECLAIMS] times. They are not handed out
+    /// again until they were finalized as failed.
+    given_up: HashMap<TaskId, WorkItem>,
     _old_runner_cleanup: tokio::sync::oneshot::Sender<()>,
 }
 
@@ -151,6 +244,9 @@ impl Executor {
         let res = Arc::new(Mutex::new(Self {
             in_progress: HashMap::new(),
             runners: HashMap::new(),
+            crashed: HashSet::new(),
+            lease_reclaims: HashMap::new(),
+            given_up: HashMap::new(),
             _old_runner_cleanup: tx,
         }));
 
@@ -158,8 +254,11 @@ impl Executor {
         tokio::task::spawn(async move {
             let periodic = async move {
                 loop {
-                    tokio::time::sleep(Duration::from_secs(60)).await;
-                    res_clone.lock().unwrap().runners.retain(|_, v| {
+                    tokio::time::sleep(Duration::from_secs(15)).await;
+
+                    let mut executor = res_clone.lock().unwrap();
+                    executor.reclaim_expired_leases();
+                    executor.runners.retain(|_, v| {
                         let time_since_ping =
                             v.last_ping.elapsed().unwrap_or(Duration::from_secs(0));
 
@@ -189,8 +288,20 @@ impl Executor {
         result
     }
 
-    pub fn info(&self, tasting_runners: HashSet<RunnerId>) -> ExecutorInfo {
+    /// The distinct versions reported by all connected runners
+    pub fn runner_versions(&self) -> BTreeSet<String> {
+        self.runners
+            .values()
+            .map(|it| it.info.version.as_deref().unwrap_or("unknown").to_string())
+            .collect()
+    }
+
+    pub fn info(&self, tasting_runners: HashSet<RunnerId>, open_tastings: usize) -> ExecutorInfo {
         ExecutorInfo {
+            tasting_queue: TastingQueueInfo {
+                open: open_tastings,
+                running: tasting_runners.len(),
+            },
             runners: self.get_runners(tasting_runners),
             in_progress: self
                 .in_progress
@@ -200,24 +311,159 @@ impl Executor {
         }
     }
 
-    pub fn runner_pinged(&mut self, runner_id: &RunnerId) {
-        if let Some(runner) = self.runners.get_mut(runner_id) {
-            runner.last_ping = SystemTime::now();
+    /// Records a ping and renews the runner's lease. Returns the task the runner holds a lease for.
+    pub fn runner_pinged(&mut self, runner_id: &RunnerId) -> Option<TaskId> {
+        let runner = self.runners.get_mut(runner_id)?;
+        runner.last_ping = SystemTime::now();
+
+        if !runner.has_live_lease() {
+            return None;
+        }
+        runner.renew_lease();
+
+        runner.working_on.as_ref().map(|it| it.id.clone())
+    }
+
+    fn reclaim_expired_leases(&mut self) {
+        for runner in self.runners.values_mut() {
+            if runner.has_live_lease() {
+                continue;
+            }
+            let Some(task) = runner.working_on.take() else {
+                continue;
+            };
+            let reclaims = self.lease_reclaims.entry(task.id.clone()).or_default();
+            *reclaims += 1;
+            warn!(
+                runner = %runner.info.id,
+                task = %task.id,
+                reclaims = %reclaims,
+                "Lease expired, reclaiming task"
+            );
+            let reported_tests = self.in_progress.remove(&task.id).is_some_and(|state| {
+                state
+                    .so_far
+                    .iter()
+                    .any(|it| matches!(it.update, RunnerUpdate::FinishedTest { .. }))
+            });
+
+            if *reclaims > MAX_LEASE_RECLAIMS {
+                warn!(task = %task.id, "Task lost its runner too often, giving up");
+                self.given_up.insert(task.id.clone(), task);
+            } else if reported_tests {
+                self.crashed.insert(task.id);
+            }
+        }
+    }
+
+    /// Tasks whose runner vanished after reporting test results and that are waiting to be
+    /// finalized
+    pub fn crashed_tasks(&self) -> Vec<TaskId> {
+        self.crashed.iter().cloned().collect()
+    }
+
+    /// Tasks that lost their runner too often and are waiting to be finalized as failed
+    pub fn given_up_tasks(&self) -> Vec<WorkItem> {
+        self.given_up.values().cloned().collect()
+    }
+
+    /// Marks a crashed or given up task as handled, either because it was finalized or because it
+    /// should be handed out again
+    pub fn crash_handled(&mut self, task_id: &TaskId) {
+        self.crashed.remove(task_id);
+        if self.given_up.remove(task_id).is_some() {
+            self.lease_reclaims.remove(task_id);
         }
     }
 
-    pub fn register_runner(&mut self, runner_info: &RunnerInfo) {
+    /// Registers the runner with the role an admin assigned to it, or the one it asked for
+    pub fn register_runner(&mut self, runner_info: &RunnerInfo, assigned_role: Option<bool>) {
         self.runners.insert(
             runner_info.id.clone(),
             Runner {
                 info: runner_info.clone(),
                 working_on: None,
+                lease_expires: SystemTime::now(),
                 last_ping: SystemTime::now(),
-                test_taster: runner_info.test_taster,
+                test_taster: assigned_role.unwrap_or(runner_info.test_taster),
+                logs_requested: false,
+                logs: None,
             },
         );
     }
 
+    /// Changes the role of a connected runner. `None` restores the role it registered with.
+    pub fn assign_role(&mut self, runner_id: &RunnerId, test_taster: Option<bool>) {
+        if let Some(runner) = self.runners.get_mut(runner_id) {
+            runner.test_taster = test_taster.unwrap_or(runner.info.test_taster);
+        }
+    }
+
+    /// Asks the runner to push its recent logs with its next ping. Returns false if it is not
+    /// connected.
+    pub fn request_logs(&mut self, runner_id: &RunnerId) -> bool {
+        let Some(runner) = self.runners.get_mut(runner_id) else {
+            return false;
+        };
+        runner.logs_requested = true;
+
+        true
+    }
+
+    /// Whether the runner should push its logs, which is only reported once per request
+    pub fn take_logs_request(&mut self, runner_id: &RunnerId) -> bool {
+        self.runners
+            .get_mut(runner_id)
+            .is_some_and(|it| std::mem::take(&mut it.logs_requested))
+    }
+
+    /// Stores the logs a runner pushed. Returns false if it is not connected.
+    pub fn store_logs(&mut self, runner_id: &RunnerId, logs: RunnerLogs) -> bool {
+        let Some(runner) = self.runners.get_mut(runner_id) else {
+            return false;
+        };
+        runner.logs = Some(ReceivedRunnerLogs {
+            received_at: SystemTime::now(),
+            lines: logs.lines,
+        });
+
+        true
+    }
+
+    pub fn get_logs(&self, runner_id: &RunnerId) -> Option<RunnerLogsForFrontend> {
+        self.runners
+            .get(runner_id)
+            .map(|runner| RunnerLogsForFrontend {
+                requested: runner.logs_requested,
+                logs: runner.logs.clone(),
+            })
+    }
+
+    /// Whether the runner should taste tests instead of testing tasks, if it is connected
+    pub fn is_test_taster(&self, runner_id: &RunnerId) -> Option<bool> {
+        self.runners.get(runner_id).map(|it| it.test_taster)
+    }
+
+    /// Whether a test taster may pick up a full task. Tastings take priority, so it may only do
+    /// that while none is waiting and another taster stays free for the next one.
+    pub fn taster_may_grade(
+        &self,
+        runner_id: &RunnerId,
+        open_tastings: usize,
+        tasting_runners: &HashSet<RunnerId>,
+    ) -> bool {
+        if open_tastings > 0 {
+            return false;
+        }
+
+        self.runners.values().any(|it| {
+            it.info.id != *runner_id
+                && it.test_taster
+                && it.working_on.is_none()
+                && !tasting_runners.contains(&it.info.id)
+        })
+    }
+
     pub fn get_running_task(&self, id: &TaskId) -> Option<RunningTaskState> {
         self.in_progress.get(id).map(|it| RunningTaskState {
             so_far: it.so_far.clone(),
@@ -225,18 +471,26 @@ impl Executor {
         })
     }
 
-    pub fn update_task(&mut self, runner_id: &RunnerId, update: RunnerUpdate) {
-        let Some(runner) = self.runners.get(runner_id) else {
-            return;
-        };
-        let Some(task) = runner.working_on.as_ref() else {
-            return;
-        };
-        let Some(state) = self.in_progress.get_mut(&task.id) else {
-            return;
-        };
+    /// Forwards an update to the task the runner works on and returns that task. Updates tagged
+    /// with a different task, e.g. one the runner lost its lease for, are dropped.
+    pub fn update_task(
+        &mut self,
+        runner_id: &RunnerId,
+        task_id: Option<&TaskId>,
+        update: RunnerUpdate,
+    ) -> Option<TaskId> {
+        let runner = self.runners.get_mut(runner_id)?;
+        if !runner.has_live_lease() {
+            return None;
+        }
+        runner.renew_lease();
+        let task = runner.working_on.as_ref()?;
+        if task_id.is_some_and(|it| it != &task.id) {
+            return None;
+        }
+        let state = self.in_progress.get_mut(&task.id)?;
 
-        let update: RunnerUpdateForFrontend = update.into();
+        let update = RunnerUpdateForFrontend::new(state.so_far.len() as u64, update);
         state.so_far.push(update.clone());
 
         if let Err(e) = state.sender.send(update) {
@@ -247,13 +501,15 @@ impl Executor {
                 "Failed to send update to task"
             );
         }
+
+        Some(task.id.clone())
     }
 
     pub fn assign_work(
         &mut self,
         runner_info: &RunnerInfo,
         all_tasks: &[WorkItem],
-        test_ids: Vec<TestId>,
+        test_ids: impl FnOnce(&WorkItem) -> Vec<TestId>,
         queue: Arc<Mutex<Queue>>,
     ) -> Result<Option<WorkItem>, ExecutorError> {
         ensure!(
@@ -265,12 +521,18 @@ impl Executor {
 
         let runner_id = runner_info.id.clone();
 
+        // Otherwise we might hand out a task whose old state would be removed on the next sweep
+        self.reclaim_expired_leases();
+
         let taken: HashSet<TaskId> = self
             .runners
             .values()
             .filter(|it| it.info.id != runner_info.id)
+            .filter(|it| it.has_live_lease())
             .flat_map(|it| it.working_on.clone())
             .map(|it| it.id)
+            .chain(self.crashed.iter().cloned())
+            .chain(self.given_up.keys().cloned())
             .collect();
 
         let task = queue.lock().unwrap().poll_task(
@@ -283,6 +545,7 @@ impl Executor {
 
         let runner = self.runners.get_mut(&runner_id).unwrap();
         runner.working_on = task.clone();
+        runner.renew_lease();
 
         if let Some(task) = &task {
             let (sender, mut rx) = broadcast::channel(100);
@@ -290,26 +553,40 @@ impl Executor {
             // Drain dummy receiver so sending will always work
             tokio::spawn(async move { while rx.recv().await.is_ok() {} });
 
-            self.in_progress.insert(
-                task.id.clone(),
-                InternalRunningTaskState {
-                    so_far: vec![RunnerUpdate::AllTests { tests: test_ids }.into()],
-                    sender,
+            let mut so_far = vec![RunnerUpdateForFrontend::new(
+                0,
+                RunnerUpdate::AllTests {
+                    tests: test_ids(task),
                 },
-            );
+            )];
+            if let Some(reclaims) = self.lease_reclaims.get(&task.id) {
+                so_far.push(RunnerUpdateForFrontend::new(
+                    1,
+                    RunnerUpdate::Requeued {
+                        attempt: reclaims + 1,
+                        max_attempts: MAX_LEASE_RECLAIMS + 1,
+                    },
+                ));
+            }
+            self.in_progress
+                .insert(task.id.clone(), InternalRunningTaskState { so_far, sender });
         }
 
         Ok(task)
     }
 
     pub fn get_current_task(&self, id: &RunnerId) -> Option<WorkItem> {
-        self.runners.get(id).and_then(|it| it.working_on.clone())
+        self.runners
+            .get(id)
+            .filter(|it| it.has_live_lease())
+            .and_then(|it| it.working_on.clone())
     }
 
     pub fn finish_task(&mut self, runner: &RunnerId) {
         if let Some(runner) = self.runners.get_mut(runner) {
             if let Some(task) = &runner.working_on {
                 self.in_progress.remove(&task.id);
+                self.lease_reclaims.remove(&task.id);
             }
             runner.working_on = None;
         }
@@ -330,9 +607,20 @@ pub struct WorkItem {
     #[serde(serialize_with = "serialize_system_time")]
     #[serde(deserialize_with = "deserialize_system_time")]
     pub insert_time: SystemTime,
+    /// The git tree hash of the revision, if it was looked up when queueing
+    #[serde(skip)]
+    pub tree_hash: Option<String>,
+    /// A finished task of the same team that was built from an identical source tree
+    pub identical_to: Option<TaskId>,
+    /// The finished task this one reruns against the current test suite
+    #[serde(default)]
+    pub rerun_of: Option<TaskId>,
+    /// The author of the revision, if it was looked up when queueing
+    #[serde(default)]
+    pub commit: Option<CommitMetadata>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, From, sqlx::Type)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, From, sqlx::Type)]
 pub enum ExecutionExitStatus {
     Aborted,
     Error,
@@ -362,6 +650,15 @@ impl From<&TestExecutionOutput> for ExecutionExitStatus {
     }
 }
 
+/// One of the two outputs of an execution
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    #[default]
+    Stdout,
+    Stderr,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum QueuedTaskStatus {
     Aborted,
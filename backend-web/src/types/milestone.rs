@@ -0,0 +1,54 @@
+use crate::types::{TaskId, TeamId, TestId};
+use jiff::Timestamp;
+use serde::Serialize;
+
+/// The percentages of passed tests in a category that count as a milestone
+pub const MILESTONE_THRESHOLDS: [u8; 4] = [25, 50, 75, 100];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestFirstPass {
+    pub test_id: TestId,
+    /// The task that passed the test first. Absent if it was deleted.
+    pub task_id: Option<TaskId>,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub passed_at: Timestamp,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryMilestone {
+    pub category: String,
+    /// The percentage of passed tests in the category
+    pub threshold: u8,
+    /// The task that reached the milestone first. Absent if it was deleted.
+    pub task_id: Option<TaskId>,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub reached_at: Timestamp,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamMilestones {
+    pub team: TeamId,
+    pub tests: Vec<TestFirstPass>,
+    pub categories: Vec<CategoryMilestone>,
+}
+
+/// When the teams of the course reached a category milestone
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CourseMilestone {
+    pub category: String,
+    pub threshold: u8,
+    /// Sorted ascending, one entry per team that reached the milestone
+    #[serde(serialize_with = "serialize_timestamps")]
+    pub reached_at: Vec<Timestamp>,
+}
+
+fn serialize_timestamps<S: serde::Serializer>(
+    timestamps: &[Timestamp],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(timestamps.iter().map(|it| it.as_millisecond()))
+}
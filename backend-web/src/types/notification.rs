@@ -0,0 +1,21 @@
+use crate::types::TeamId;
+use derive_more::{Display, From};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Hash, From, PartialEq, Eq, Display, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct NotificationId(String);
+
+/// A message for all members of a team
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    pub id: NotificationId,
+    pub team: TeamId,
+    pub message: String,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub created_at: Timestamp,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::optional::serialize")]
+    pub read_at: Option<Timestamp>,
+}
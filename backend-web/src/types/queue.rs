@@ -1,5 +1,8 @@
 use crate::types::{TeamId, WorkItem};
-use std::collections::HashMap;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Queue {
@@ -69,3 +72,141 @@ impl Queue {
         )
     }
 }
+
+/// The hypothetical situation a queue simulation starts from
+#[derive(Debug, Clone)]
+pub struct QueueSimulationParams {
+    pub runners: usize,
+    pub submissions_per_hour: f64,
+    pub task_duration: Duration,
+    /// Tasks waiting in the queue when the simulation starts
+    pub queued_tasks: usize,
+    /// Tasks already being worked on when the simulation starts. They are assumed to be half done.
+    pub running_tasks: usize,
+    pub hours: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectedQueueHour {
+    pub hour: u32,
+    /// Submissions arriving during this hour. The initially queued tasks count towards the first.
+    pub arrivals: usize,
+    /// Tasks still waiting at the end of this hour
+    pub queue_length: usize,
+    /// Waiting time of the tasks arriving during this hour
+    pub average_wait_ms: u64,
+    pub max_wait_ms: u64,
+}
+
+/// Simulates a first-come-first-served queue with evenly spaced submissions, where every task
+/// takes exactly the given duration. Returns one projection per hour, or nothing without runners.
+pub fn simulate_queue(params: &QueueSimulationParams) -> Vec<ProjectedQueueHour> {
+    if params.runners == 0 {
+        return Vec::new();
+    }
+
+    let hour = Duration::from_secs(60 * 60);
+    let end = hour * params.hours;
+
+    // (arrival, start) of every task
+    let mut arrivals = vec![Duration::ZERO; params.queued_tasks];
+    if params.submissions_per_hour > 0.0 {
+        let spacing = hour.div_f64(params.submissions_per_hour);
+        let mut next = spacing;
+        while next < end {
+            arrivals.push(next);
+            next += spacing;
+        }
+    }
+
+    // The point in time each runner is free again
+    let mut free_at = (0..params.runners)
+        .map(|i| {
+            if i < params.running_tasks {
+                Reverse(params.task_duration / 2)
+            } else {
+                Reverse(Duration::ZERO)
+            }
+        })
+        .collect::<BinaryHeap<_>>();
+    let mut tasks = Vec::with_capacity(arrivals.len());
+    for arrival in arrivals {
+        let Some(Reverse(free)) = free_at.pop() else {
+            break;
+        };
+        let start = free.max(arrival);
+        free_at.push(Reverse(start + params.task_duration));
+        tasks.push((arrival, start));
+    }
+
+    (0..params.hours)
+        .map(|index| {
+            let hour_start = hour * index;
+            let hour_end = hour * (index + 1);
+            let waits = tasks
+                .iter()
+                .filter(|(arrival, _)| *arrival >= hour_start && *arrival < hour_end)
+                .map(|(arrival, start)| start.saturating_sub(*arrival))
+                .collect::<Vec<_>>();
+            let queue_length = tasks
+                .iter()
+                .filter(|(arrival, start)| *arrival < hour_end && *start > hour_end)
+                .count();
+            let total_wait = waits.iter().sum::<Duration>();
+
+            ProjectedQueueHour {
+                hour: index,
+                arrivals: waits.len(),
+                queue_length,
+                average_wait_ms: total_wait
+                    .checked_div(waits.len() as u32)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+                max_wait_ms: waits.iter().max().copied().unwrap_or_default().as_millis() as u64,
+            }
+        })
+        .collect()
+}
+
+/// How long the tasks queued on one day waited for a runner and took to execute
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStatisticsDay {
+    /// The UTC day in `YYYY-MM-DD` format
+    pub day: String,
+    pub tasks: usize,
+    pub wait_p50_ms: u64,
+    pub wait_p95_ms: u64,
+    pub execution_p50_ms: u64,
+    pub execution_p95_ms: u64,
+}
+
+impl QueueStatisticsDay {
+    /// Summarizes the `(wait, execution)` durations in milliseconds of the tasks of a day
+    pub fn from_samples(day: String, samples: &[(u64, u64)]) -> Self {
+        let mut waits = samples.iter().map(|it| it.0).collect::<Vec<_>>();
+        let mut executions = samples.iter().map(|it| it.1).collect::<Vec<_>>();
+        waits.sort_unstable();
+        executions.sort_unstable();
+
+        Self {
+            day,
+            tasks: samples.len(),
+            wait_p50_ms: percentile(&waits, 50),
+            wait_p95_ms: percentile(&waits, 95),
+            execution_p50_ms: percentile(&executions, 50),
+            execution_p95_ms: percentile(&executions, 95),
+        }
+    }
+}
+
+/// The nearest-rank percentile of sorted values, zero if there are none
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+
+    sorted[rank - 1]
+}
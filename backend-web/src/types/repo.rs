@@ -7,3 +7,11 @@ pub struct Repo {
     pub team: TeamId,
     pub url: String,
 }
+
+/// The public half of the ssh key the backend clones the repository of a team with
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamSshKey {
+    pub team: TeamId,
+    pub public_key: String,
+}
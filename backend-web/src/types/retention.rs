@@ -0,0 +1,23 @@
+use crate::types::{TaskId, TeamId};
+use jiff::Timestamp;
+use serde::Serialize;
+
+/// A finished task the retention policy no longer keeps
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrunableTask {
+    pub task_id: TaskId,
+    pub team_id: TeamId,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub end_time: Timestamp,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionReport {
+    /// If set, nothing was deleted and the report lists what would have been
+    pub dry_run: bool,
+    pub tasks: Vec<PrunableTask>,
+    /// The build and test outputs belonging to the tasks
+    pub execution_results: u64,
+}
@@ -1,15 +1,23 @@
-use crate::types::{ExecutionExitStatus, TaskId, TestId, UserId};
+use crate::types::{ExecutionExitStatus, TaskId, TeamId, TestId, UserId};
+use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
-use shared::{FinishedCompilerTask, FinishedTaskInfo, FinishedTest};
+use shared::{
+    ExecutionOutput, FinishedCompilerTask, FinishedTaskInfo, FinishedTest, TestExecutionOutput,
+};
 use std::borrow::Borrow;
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FinishedTestSummary {
     pub test_id: TestId,
     pub output: ExecutionExitStatus,
+    /// The test did not run, as a test it depends on did not pass. Its output is an error then.
+    #[serde(default)]
+    pub skipped: bool,
     pub provisional_for_category: Option<String>,
     pub category: Option<String>,
+    pub profile: Option<String>,
 }
 
 impl From<FinishedTest> for FinishedTestSummary {
@@ -17,8 +25,10 @@ impl From<FinishedTest> for FinishedTestSummary {
         Self {
             test_id: value.test_id.into(),
             output: (&value.output).into(),
+            skipped: matches!(value.output, TestExecutionOutput::Skipped { .. }),
             provisional_for_category: value.provisional_for_category,
             category: value.category,
+            profile: value.profile,
         }
     }
 }
@@ -28,12 +38,42 @@ impl From<&FinishedTest> for FinishedTestSummary {
         Self {
             test_id: value.test_id.clone().into(),
             output: (&value.output).into(),
+            skipped: matches!(value.output, TestExecutionOutput::Skipped { .. }),
             provisional_for_category: value.provisional_for_category.clone(),
             category: value.category.clone(),
+            profile: value.profile.clone(),
         }
     }
 }
 
+impl FinishedTestSummary {
+    pub fn passed(&self) -> bool {
+        !self.skipped && self.output == ExecutionExitStatus::Success
+    }
+}
+
+/// Collapses the results of every test under all its profiles into one, so each test counts once.
+/// The merged result is the first failing profile, a test only passes if it passed everywhere.
+pub fn merge_profile_results<T: Borrow<FinishedTestSummary>>(
+    tests: &[T],
+) -> Vec<&FinishedTestSummary> {
+    let mut merged: Vec<&FinishedTestSummary> = Vec::with_capacity(tests.len());
+    let mut indices: HashMap<&TestId, usize> = HashMap::new();
+    for test in tests {
+        let test = test.borrow();
+        match indices.get(&test.test_id) {
+            Some(&index) if merged[index].passed() && !test.passed() => merged[index] = test,
+            Some(_) => {}
+            None => {
+                indices.insert(&test.test_id, merged.len());
+                merged.push(test);
+            }
+        }
+    }
+
+    merged
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum FinishedCompilerTaskSummary {
@@ -100,8 +140,11 @@ impl CountWithProvisional {
 #[serde(rename_all = "camelCase")]
 pub struct FinishedCompilerTaskStatistics {
     abort: CountWithProvisional,
+    /// Internal errors, skipped tests are counted separately
     error: CountWithProvisional,
     failure: CountWithProvisional,
+    #[serde(default)]
+    skipped: CountWithProvisional,
     success: CountWithProvisional,
     timeout: CountWithProvisional,
     total: CountWithProvisional,
@@ -110,9 +153,10 @@ pub struct FinishedCompilerTaskStatistics {
 impl<T: Borrow<FinishedTestSummary>> From<&[T]> for FinishedCompilerTaskStatistics {
     fn from(tests: &[T]) -> Self {
         let mut statistics = Self::default();
-        for test in tests {
-            let provisional = test.borrow().provisional_for_category.is_some();
-            match &test.borrow().output {
+        for test in merge_profile_results(tests) {
+            let provisional = test.provisional_for_category.is_some();
+            match &test.output {
+                _ if test.skipped => statistics.skipped.inc(provisional),
                 ExecutionExitStatus::Aborted => statistics.abort.inc(provisional),
                 ExecutionExitStatus::Error => statistics.error.inc(provisional),
                 ExecutionExitStatus::Failure => statistics.failure.inc(provisional),
@@ -137,6 +181,123 @@ impl From<&[FinishedTest]> for FinishedCompilerTaskStatistics {
     }
 }
 
+/// Longer stderr lines are cut off, they rarely help to tell failures apart
+const MAX_CLUSTER_STDERR_LINE_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum FailureStage {
+    Compiler,
+    Binary,
+    Unknown,
+}
+
+/// What failing tests have in common if they likely fail for the same reason
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailureSignature {
+    pub stage: FailureStage,
+    pub status: ExecutionExitStatus,
+    pub signal: Option<i32>,
+    pub exit_code: Option<i32>,
+    pub first_stderr_line: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailureCluster {
+    #[serde(flatten)]
+    pub signature: FailureSignature,
+    pub test_ids: Vec<TestId>,
+}
+
+impl FailureSignature {
+    fn from_output(output: &TestExecutionOutput) -> Option<Self> {
+        let (stage, output) = match output {
+            // Skipped tests only failed because of their dependencies, which are clustered already
+            TestExecutionOutput::Success { .. } | TestExecutionOutput::Skipped { .. } => {
+                return None;
+            }
+            TestExecutionOutput::CompilerFailed { compiler_output } => {
+                (FailureStage::Compiler, compiler_output)
+            }
+            TestExecutionOutput::BinaryFailed { binary_output, .. } => {
+                (FailureStage::Binary, binary_output)
+            }
+            TestExecutionOutput::Error { output_so_far } => (FailureStage::Unknown, output_so_far),
+        };
+
+        let (exit_code, signal, stderr) = match output {
+            ExecutionOutput::Aborted(execution) => (None, None, execution.stderr.as_str()),
+            ExecutionOutput::Error(error) => (None, None, error.message.as_str()),
+            ExecutionOutput::Success(execution) | ExecutionOutput::Timeout(execution) => {
+                (execution.exit_status, None, execution.stderr.as_str())
+            }
+            ExecutionOutput::Failure {
+                execution,
+                accumulated_errors,
+            } => {
+                // The signal is not recorded separately, but the judge always mentions it
+                let signal = match execution.exit_status {
+                    None => accumulated_errors.as_deref().and_then(signal_from_errors),
+                    Some(_) => None,
+                };
+                (execution.exit_status, signal, execution.stderr.as_str())
+            }
+        };
+
+        Some(Self {
+            stage,
+            status: output.into(),
+            signal,
+            exit_code,
+            first_stderr_line: stderr
+                .lines()
+                .map(str::trim)
+                .find(|it| !it.is_empty())
+                .map(|it| it.chars().take(MAX_CLUSTER_STDERR_LINE_LENGTH).collect()),
+        })
+    }
+}
+
+fn signal_from_errors(errors: &str) -> Option<i32> {
+    let (_, rest) = errors.split_once("killed by signal ")?;
+    let digits: String = rest.chars().take_while(|it| it.is_ascii_digit()).collect();
+
+    digits.parse().ok()
+}
+
+/// Groups failed tests by their [FailureSignature]. The largest clusters come first.
+pub fn cluster_failures(tests: &[FinishedTest]) -> Vec<FailureCluster> {
+    let mut clusters: HashMap<FailureSignature, Vec<TestId>> = HashMap::new();
+    for test in tests {
+        if let Some(signature) = FailureSignature::from_output(&test.output) {
+            clusters
+                .entry(signature)
+                .or_default()
+                .push(test.test_id.clone().into());
+        }
+    }
+
+    let mut clusters = clusters
+        .into_iter()
+        .map(|(signature, mut test_ids)| {
+            test_ids.sort_by_key(|it| it.to_string());
+            FailureCluster {
+                signature,
+                test_ids,
+            }
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by(|a, b| {
+        b.test_ids
+            .len()
+            .cmp(&a.test_ids.len())
+            .then_with(|| a.test_ids[0].to_string().cmp(&b.test_ids[0].to_string()))
+    });
+
+    clusters
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
 pub enum FinalSubmittedTask {
@@ -169,3 +330,83 @@ impl FinalSubmittedTask {
         }
     }
 }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamActivityDay {
+    /// The UTC day in `YYYY-MM-DD` format
+    pub day: String,
+    pub submissions: i64,
+    /// The best fraction of passed tests of any submission on this day
+    pub best_pass_rate: f64,
+    /// The change of [Self::best_pass_rate] compared to the previous day with submissions.
+    /// Absent for the first day.
+    pub pass_rate_delta: Option<f64>,
+}
+
+/// The size of the binaries and the compile time of one task, aggregated over its tests
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskPerformance {
+    pub task_id: TaskId,
+    pub revision: String,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub queue_time: Timestamp,
+    /// The tests that produced a binary
+    pub measured_tests: i64,
+    pub average_binary_size: Option<f64>,
+    pub max_binary_size: Option<i64>,
+    pub average_compile_duration_ms: Option<f64>,
+    pub total_compile_duration_ms: Option<i64>,
+    /// The change of [Self::average_binary_size] compared to the previous measured task
+    pub binary_size_delta: Option<f64>,
+    /// The change of [Self::average_compile_duration_ms] compared to the previous measured task
+    pub compile_duration_delta_ms: Option<f64>,
+}
+
+/// The metadata of one task for research exports, without any of its output
+#[derive(Debug, Clone)]
+pub struct TaskExportRow {
+    pub task_id: TaskId,
+    pub team_id: TeamId,
+    pub queue_time: Timestamp,
+    pub start_time: Timestamp,
+    pub end_time: Timestamp,
+    /// The result of the build, absent if the task has no recorded build
+    pub build_status: Option<String>,
+    /// The `(passed, total)` tests per category
+    pub category_counts: HashMap<String, (i64, i64)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamPerformance {
+    pub team_id: TeamId,
+    /// Oldest first
+    pub tasks: Vec<TaskPerformance>,
+}
+
+/// How the test results of two tasks of a team differ, to spot regressions before pushing
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskComparison {
+    pub base: FinishedCompilerTaskSummary,
+    pub head: FinishedCompilerTaskSummary,
+    /// Tests passing in the base task, but failing in the head task
+    pub regressed: Vec<TestStatusChange>,
+    /// Tests passing in the head task, but not in the base task
+    pub newly_passing: Vec<TestStatusChange>,
+    /// Tests with any other change, e.g. failing differently or only run by one of the tasks
+    pub changed: Vec<TestStatusChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestStatusChange {
+    pub test_id: TestId,
+    pub profile: Option<String>,
+    /// Absent if the base task did not run the test
+    pub base: Option<ExecutionExitStatus>,
+    /// Absent if the head task did not run the test
+    pub head: Option<ExecutionExitStatus>,
+}
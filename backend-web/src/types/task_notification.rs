@@ -0,0 +1,62 @@
+use crate::types::{TaskId, TeamId, UserId};
+use derive_more::{Display, From};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+/// How a user wants to be told about finished tasks of their team
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskNotificationSettings {
+    /// Notify when every test passed
+    pub on_success: bool,
+    /// Notify when the build or any test failed
+    pub on_failure: bool,
+    pub email: Option<String>,
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Hash, From, PartialEq, Eq, Display, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct TaskNotificationDeliveryId(String);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Display, Serialize, Deserialize, sqlx::Type)]
+pub enum TaskNotificationChannel {
+    Email,
+    Webhook,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TaskOutcome {
+    Success,
+    TestsFailed,
+    BuildFailed,
+}
+
+/// What we tell users about a finished task. Sent as-is to webhooks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskNotificationPayload {
+    pub task_id: TaskId,
+    pub team: TeamId,
+    pub revision: String,
+    pub commit_message: String,
+    pub outcome: TaskOutcome,
+    pub passed_tests: usize,
+    pub total_tests: usize,
+    /// Where the task can be viewed in the frontend, if its URL is configured
+    pub url: Option<String>,
+}
+
+/// A notification that still needs to be sent out
+#[derive(Debug, Clone)]
+pub struct TaskNotificationDelivery {
+    pub id: TaskNotificationDeliveryId,
+    pub user: UserId,
+    pub channel: TaskNotificationChannel,
+    /// The e-mail address or webhook URL
+    pub target: String,
+    pub payload: TaskNotificationPayload,
+    /// How often sending it failed already
+    pub attempts: u32,
+    pub next_attempt: Timestamp,
+}
@@ -1,8 +1,11 @@
-use crate::types::TeamId;
+use crate::types::{CourseId, TeamId, UserId};
 use derive_more::{Display, From};
 use jiff::Timestamp;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use shared::hash::{TestHashInput, hash_test};
+use shared::markdown::{TestMarkdownInput, test_to_markdown};
 use shared::{TestExecutionOutput, TestModifier, validate_test_id};
 
 #[derive(Debug, Clone, Hash, From, PartialEq, Eq, Display, Serialize, sqlx::Type)]
@@ -22,8 +25,65 @@ impl<'de> Deserialize<'de> for TestId {
     }
 }
 
+impl TestId {
+    /// A stable pseudonym for tasks of teams that may not see the test, so they can tell whether
+    /// it passes without learning what it is called
+    pub fn masked(&self) -> Self {
+        let hash = format!("{:x}", Sha256::digest(self.0.as_bytes()));
+        Self(format!("hidden-{}", &hash[..8]))
+    }
+}
+
+/// Who may see a test besides its owner and admins. The executor runs every test regardless.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all_fields = "camelCase")]
+pub enum TestVisibility {
+    #[default]
+    Public,
+    /// Only the owning team sees the test
+    TeamOnly,
+    /// Hidden from other teams until the given time, e.g. the deadline of its category
+    HiddenUntil {
+        #[serde(with = "jiff::fmt::serde::timestamp::millisecond::required")]
+        until: Timestamp,
+    },
+}
+
+impl TestVisibility {
+    pub fn is_public_at(&self, now: Timestamp) -> bool {
+        match self {
+            Self::Public => true,
+            Self::TeamOnly => false,
+            Self::HiddenUntil { until } => now >= *until,
+        }
+    }
+}
+
+/// Whether a test counts, decided by the tasting policy when it is uploaded. Only accepted tests
+/// are run and distributed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum TestAcceptance {
+    #[default]
+    Accepted,
+    /// Waits for an admin to accept or reject it
+    Pending { message: String },
+    /// Stays rejected until its owner uploads a version that passes the policy
+    Rejected { message: String },
+}
+
+/// Filters tests by their [TestAcceptance]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TestAcceptanceState {
+    Accepted,
+    Pending,
+    Rejected,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
+#[allow(clippy::large_enum_variant)] // stored and sent once per test, the size does not matter
 pub enum TestTastingResult {
     Success,
     Failure { output: TestExecutionOutput },
@@ -45,22 +105,101 @@ pub struct Test {
     pub compiler_modifiers: Vec<TestModifier>,
     pub binary_modifiers: Vec<TestModifier>,
     pub owner: TeamId,
+    /// The course the test was created in. Only teams of that course see and run it.
+    #[serde(default)]
+    pub course: CourseId,
     pub admin_authored: bool,
     pub category: String,
     pub provisional_for_category: Option<String>,
     /// This test is not applicable to later categories and should not be run
     pub limited_to_category: bool,
+    /// Overrides the global test timeout. Can only be set by admins.
+    pub timeout_ms: Option<u64>,
+    /// Tests that must pass for this test to run. It is skipped if any of them fails.
+    #[serde(default)]
+    pub depends_on: Vec<TestId>,
+    /// Practice tests are distributed to be run locally, but never run on the server
+    #[serde(default)]
+    pub practice: bool,
+    /// The license and attribution of the test, usually an SPDX license identifier
+    #[serde(default)]
+    pub license: Option<String>,
+    #[serde(default)]
+    pub visibility: TestVisibility,
+    #[serde(default)]
+    pub acceptance: TestAcceptance,
     #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
     #[serde(deserialize_with = "jiff::fmt::serde::timestamp::millisecond::required::deserialize")]
     pub last_updated: Timestamp,
 }
 
+impl Test {
+    pub fn hash(&self) -> String {
+        let owner = self.owner.to_string();
+        let depends_on = self
+            .depends_on
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        hash_test(&TestHashInput {
+            compiler_modifiers: &self.compiler_modifiers,
+            binary_modifiers: &self.binary_modifiers,
+            owner: &owner,
+            admin_authored: self.admin_authored,
+            limited_to_category: self.limited_to_category,
+            category: &self.category,
+            timeout_ms: self.timeout_ms,
+            depends_on: &depends_on,
+            practice: self.practice,
+            license: self.license.as_deref(),
+        })
+    }
+
+    /// The test in the markdown format the client reads and writes
+    pub fn to_markdown(&self) -> String {
+        let owner = self.owner.to_string();
+        let depends_on = self
+            .depends_on
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        test_to_markdown(&TestMarkdownInput {
+            compiler_modifiers: &self.compiler_modifiers,
+            binary_modifiers: &self.binary_modifiers,
+            limited_to_category: self.limited_to_category,
+            admin_authored: self.admin_authored,
+            timeout_ms: self.timeout_ms,
+            depends_on: &depends_on,
+            practice: self.practice,
+            license: self.license.as_deref(),
+            creator: &owner,
+            hash: &self.hash(),
+        })
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TestWithTasteTesting {
     #[serde(flatten)]
     pub test: Test,
     pub test_tasting_result: Option<TestTastingResult>,
+    pub reference_output: Option<ReferenceOutput>,
+}
+
+/// An expected output the reference compiler computed for a test uploaded without one
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferenceOutput {
+    /// Already part of the test as an `ExpectedOutput` modifier
+    #[serde(skip)]
+    pub output: String,
+    pub image: String,
+    pub compile_command: Vec<String>,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub computed_at: Timestamp,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,7 +214,167 @@ pub struct TestSummary {
     pub test_taste_success: Option<bool>,
     pub provisional_for_category: Option<String>,
     pub limited_to_category: bool,
+    pub practice: bool,
+    pub visibility: TestVisibility,
+    #[serde(default)]
+    pub acceptance: TestAcceptance,
     #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
     #[serde(deserialize_with = "jiff::fmt::serde::timestamp::millisecond::required::deserialize")]
     pub last_updated: Timestamp,
 }
+
+/// Filters for listing tests. Unset filters match everything.
+#[derive(Debug, Clone, Default)]
+pub struct TestSearch {
+    /// Matched against the test id and the creator's name
+    pub query: Option<String>,
+    pub category: Option<String>,
+    pub creator: Option<TeamId>,
+    pub course: Option<CourseId>,
+    /// Only tests this team may see, see [TestVisibility]. Other teams' tests that are not
+    /// accepted are hidden as well.
+    pub visible_to: Option<TeamId>,
+    pub acceptance: Option<TestAcceptanceState>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestMetrics {
+    pub id: TestId,
+    pub category: String,
+    /// The number of finalized tasks of the test's category that ran the test
+    pub final_task_count: i64,
+    /// The fraction of those final tasks that passed the test. Absent if no final task ran it.
+    pub pass_rate: Option<f64>,
+    /// The number of teams for which this is the only failing test in their final task
+    pub unique_failures: i64,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub last_updated: Timestamp,
+}
+
+/// How the teams currently fare on a test, for its author. Only aggregates are shared, never which
+/// team passed.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestStats {
+    pub test_id: TestId,
+    /// Teams whose latest task running the test passed it
+    pub passing_teams: i64,
+    /// Teams whose latest task running the test failed it
+    pub failing_teams: i64,
+    /// The average runtime of the test binary in those tasks. Absent if no binary ran.
+    pub average_runtime_ms: Option<f64>,
+}
+
+/// What to do with an imported test whose id is already taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TestConflictResolution {
+    /// Keep the existing test and drop the imported one
+    #[default]
+    Skip,
+    /// Replace the existing test with the imported one
+    Overwrite,
+    /// Import the test under a new, unused id
+    Rename,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenamedTest {
+    pub from: TestId,
+    pub to: TestId,
+}
+
+/// The outcome of importing a test suite archive
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestImportReport {
+    /// Tests whose id was not taken yet
+    pub imported: Vec<TestId>,
+    pub overwritten: Vec<TestId>,
+    pub skipped: Vec<TestId>,
+    pub renamed: Vec<RenamedTest>,
+    /// Tests whose owning team does not exist here. They now belong to the importing team.
+    pub reassigned: Vec<TestId>,
+    /// Categories of imported tests that are not configured here
+    pub unknown_categories: Vec<String>,
+}
+
+/// Findings of linting the whole test suite, to help curating it. Tests are only compared with the
+/// tests of their own course.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestLintReport {
+    /// Groups of tests with identical compiler and binary modifiers
+    pub duplicates: Vec<Vec<TestId>>,
+    /// Groups of tests passing the same input to the compiler and binary, but expecting different
+    /// results
+    pub conflicts: Vec<Vec<TestId>>,
+    /// Tests breaking the rules of their category, e.g. because they predate a rule
+    pub violations: Vec<TestLintViolation>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestLintViolation {
+    pub test_id: TestId,
+    pub problem: String,
+}
+
+/// The tests counting towards a category, as they were when the category was frozen at its lab
+/// deadline. Grading and reruns of the category use these, even if the tests change later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestSuiteSnapshot {
+    #[serde(default)]
+    pub course: CourseId,
+    pub category: String,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    #[serde(deserialize_with = "jiff::fmt::serde::timestamp::millisecond::required::deserialize")]
+    pub frozen_at: Timestamp,
+    /// The admin who explicitly re-froze the category. Absent if it was frozen automatically.
+    pub frozen_by: Option<UserId>,
+    pub tests: Vec<Test>,
+}
+
+impl TestSuiteSnapshot {
+    pub fn contains(&self, test_id: &TestId) -> bool {
+        self.tests.iter().any(|it| &it.id == test_id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestSuiteSnapshotSummary {
+    pub category: String,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub frozen_at: Timestamp,
+    pub frozen_by: Option<UserId>,
+    pub tests: Vec<FrozenTest>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrozenTest {
+    pub id: TestId,
+    pub hash: String,
+}
+
+impl From<&TestSuiteSnapshot> for TestSuiteSnapshotSummary {
+    fn from(value: &TestSuiteSnapshot) -> Self {
+        Self {
+            category: value.category.clone(),
+            frozen_at: value.frozen_at,
+            frozen_by: value.frozen_by.clone(),
+            tests: value
+                .tests
+                .iter()
+                .map(|test| FrozenTest {
+                    id: test.id.clone(),
+                    hash: test.hash(),
+                })
+                .collect(),
+        }
+    }
+}
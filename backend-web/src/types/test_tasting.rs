@@ -60,11 +60,24 @@ impl TestTasting {
             .collect()
     }
 
-    pub fn add_tasting(&mut self, test: Test) -> oneshot::Receiver<TestExecutionOutput> {
+    /// The number of tastings waiting for a runner
+    pub fn open_tastings(&self) -> usize {
+        self.open_tastings.len()
+    }
+
+    /// Queues running the test on the reference compiler in the given image
+    pub fn add_tasting(
+        &mut self,
+        test: Test,
+        image_id: String,
+        compile_command: Vec<String>,
+    ) -> oneshot::Receiver<TestExecutionOutput> {
         let (tx, rx) = oneshot::channel();
         self.open_tastings.push(OpenTestTaste {
             result_channel: tx,
             test,
+            image_id,
+            compile_command,
             start_time: Instant::now(),
             id: uuid::Uuid::new_v4().to_string().into(),
         });
@@ -74,13 +87,17 @@ impl TestTasting {
 
     pub fn poll_tasting(&mut self, runner_id: RunnerId) -> Option<TestTastingTask> {
         let taste = self.open_tastings.pop()?;
-        let test = taste.test.clone();
-        let id = taste.id.clone();
+        let task = TestTastingTask {
+            test: taste.test.clone(),
+            taste_id: taste.id.clone(),
+            image_id: taste.image_id.clone(),
+            compile_command: taste.compile_command.clone(),
+        };
 
         self.in_progress_tastings
             .insert(taste.id.clone(), (taste, runner_id));
 
-        Some(TestTastingTask { test, taste_id: id })
+        Some(task)
     }
 
     pub fn finish_tasting(&mut self, id: TestTasteId, output: TestExecutionOutput) {
@@ -98,6 +115,8 @@ impl TestTasting {
 struct OpenTestTaste {
     pub result_channel: oneshot::Sender<TestExecutionOutput>,
     pub test: Test,
+    pub image_id: String,
+    pub compile_command: Vec<String>,
     pub start_time: Instant,
     pub id: TestTasteId,
 }
@@ -112,4 +131,6 @@ impl OpenTestTaste {
 pub struct TestTastingTask {
     pub test: Test,
     pub taste_id: TestTasteId,
+    pub image_id: String,
+    pub compile_command: Vec<String>,
 }
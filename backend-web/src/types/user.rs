@@ -1,4 +1,6 @@
+use crate::types::CourseId;
 use derive_more::{Display, From};
+use jiff::Timestamp;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -28,6 +30,7 @@ pub struct TeamIntegrationToken(String);
 pub struct Team {
     pub id: TeamId,
     pub display_name: String,
+    pub course: CourseId,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -38,6 +41,38 @@ pub struct TeamInfo {
     pub members: Vec<User>,
 }
 
+/// A team as managed by admins, with members that might not have logged in yet
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedTeam {
+    pub id: TeamId,
+    pub display_name: String,
+    pub is_admin: bool,
+    pub course: CourseId,
+    pub members: Vec<UserId>,
+}
+
+/// A code users can redeem to join a team, so admins need not know their ids in advance
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamInvite {
+    pub code: String,
+    pub team_id: TeamId,
+    pub created_by: UserId,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::required::serialize")]
+    pub created_at: Timestamp,
+    #[serde(serialize_with = "jiff::fmt::serde::timestamp::millisecond::optional::serialize")]
+    pub expires_at: Option<Timestamp>,
+    pub max_uses: i64,
+    pub uses: i64,
+}
+
+impl TeamInvite {
+    pub fn is_usable(&self, now: Timestamp) -> bool {
+        self.uses < self.max_uses && self.expires_at.is_none_or(|it| now < it)
+    }
+}
+
 #[derive(Debug, Clone, Hash, From, PartialEq, Eq, Display, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(transparent)]
 pub struct UserId(String);
@@ -44,6 +44,12 @@ pub enum AuthError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Could not delete password from keyring at {location}"))]
+    PasswordDelete {
+        source: keyring::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
     #[snafu(display("Http error at {location}"))]
     ReqwestError {
         source: reqwest::Error,
@@ -61,18 +67,31 @@ pub enum AuthError {
     },
 }
 
-pub fn get_stored_auth(frontend_url: &str) -> Result<BackendAuth, AuthError> {
+/// The keyring entry of the token of a profile. Without a profile, the entry predating profiles is
+/// used, so existing logins keep working.
+fn keyring_entry(profile: Option<&str>) -> Result<Entry, AuthError> {
+    let name = match profile {
+        Some(profile) => format!("backend-auth-{profile}"),
+        None => "backend-auth".to_string(),
+    };
+    Entry::new("crow-client", &name).context(EntryNameInvalidSnafu)
+}
+
+pub fn get_stored_auth(
+    frontend_url: &str,
+    profile: Option<&str>,
+) -> Result<BackendAuth, AuthError> {
     let token = std::env::var("CROW_CLIENT_AUTH_TOKEN").ok();
     if let Some(token) = token {
         info!("CROW_CLIENT_AUTH_TOKEN is set, using it as the auth token.");
         return Ok(BackendAuth(token));
     }
 
-    let entry = Entry::new("crow-client", "backend-auth").context(EntryNameInvalidSnafu)?;
+    let entry = keyring_entry(profile)?;
 
     let token = match entry.get_password() {
         Ok(token) => token,
-        Err(keyring::Error::NoEntry) => display_login(frontend_url),
+        Err(keyring::Error::NoEntry) => display_login(frontend_url, profile),
         Err(keyring::Error::Ambiguous(creds)) => {
             return Err(AmbiguousSnafu { count: creds.len() }.into_error(NoneError));
         }
@@ -82,7 +101,17 @@ pub fn get_stored_auth(frontend_url: &str) -> Result<BackendAuth, AuthError> {
     Ok(BackendAuth(token))
 }
 
-pub fn display_login(frontend_url: &str) -> ! {
+/// Like [get_stored_auth], but returns `None` instead of asking the user to log in
+pub fn try_get_stored_auth(profile: Option<&str>) -> Option<BackendAuth> {
+    if let Ok(token) = std::env::var("CROW_CLIENT_AUTH_TOKEN") {
+        return Some(BackendAuth(token));
+    }
+
+    let entry = keyring_entry(profile).ok()?;
+    entry.get_password().ok().map(BackendAuth)
+}
+
+pub fn display_login(frontend_url: &str, profile: Option<&str>) -> ! {
     let me = match std::env::current_exe() {
         Err(_) => "crow-client".to_string(),
         Ok(exe) => exe
@@ -90,6 +119,10 @@ pub fn display_login(frontend_url: &str) -> ! {
             .map(|f| f.to_string_lossy().to_string())
             .unwrap_or("crow-client".to_string()),
     };
+    let login = match profile {
+        Some(profile) => format!("{me} --profile {profile} login"),
+        None => format!("{me} login"),
+    };
     error!(
         "{}",
         st(style("Unauthenticated.").red())
@@ -101,7 +134,7 @@ pub fn display_login(frontend_url: &str) -> ! {
                     .cyan()
             )
             .append(" and then use the '")
-            .append(style(format!("{me} login")).bold().cyan())
+            .append(style(login).bold().cyan())
             .append("' command to authenticate."),
     );
     std::process::exit(1);
@@ -142,8 +175,8 @@ pub fn validate_token(
     .into_error(NoneError))
 }
 
-pub fn store_auth(auth: BackendAuth) -> Result<(), AuthError> {
-    let entry = Entry::new("crow-client", "backend-auth").context(EntryNameInvalidSnafu)?;
+pub fn store_auth(auth: BackendAuth, profile: Option<&str>) -> Result<(), AuthError> {
+    let entry = keyring_entry(profile)?;
     if let Err(e) = entry.set_password(&auth.0).context(PasswordWriteSnafu) {
         error!(
             "{}",
@@ -158,3 +191,11 @@ pub fn store_auth(auth: BackendAuth) -> Result<(), AuthError> {
 
     Ok(())
 }
+
+/// Forgets the token of a profile. Profiles that were never logged in are ignored.
+pub fn delete_auth(profile: Option<&str>) -> Result<(), AuthError> {
+    match keyring_entry(profile)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(PasswordDeleteSnafu.into_error(e)),
+    }
+}
@@ -1,4 +1,13 @@
+pub mod compare;
+pub mod completions;
+pub mod final_submit;
 pub mod login;
+pub mod new_test;
+pub mod profiles;
+pub mod report;
+pub mod repro;
 pub mod run_test;
+pub mod show_task;
 pub mod sync_tests;
 pub mod upload;
+pub mod watch;
@@ -0,0 +1,138 @@
+use crate::commands::final_submit::describe_task;
+use crate::context::{CliContext, CliContextError, TestStatusChange};
+use crate::error::{CompareSnafu, CrowClientError};
+use crate::util::st;
+use clap::Args;
+use console::{StyledObject, style};
+use snafu::{IntoError, Location, NoneError, ResultExt, Snafu};
+use tracing::info;
+
+#[derive(Debug, Snafu)]
+pub enum CompareError {
+    #[snafu(display("Could not fetch the recent tasks of your team at {location}"))]
+    FetchRecentTasks {
+        source: CliContextError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not compare task `{base}` with `{head}` at {location}"))]
+    FetchComparison {
+        base: String,
+        head: String,
+        source: CliContextError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Your team has no task to compare with at {location}"))]
+    NotEnoughTasks {
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct CliCompareArgs {
+    /// The task to compare against. Defaults to the most recent task of your team before the head
+    /// task.
+    #[clap(long)]
+    base: Option<String>,
+    /// The task whose changes are shown. Defaults to the most recent task of your team.
+    #[clap(long)]
+    head: Option<String>,
+    /// Also list tests that only changed how they fail or were only run by one of the tasks
+    #[clap(long = "all-changes", default_value = "false")]
+    all_changes: bool,
+}
+
+/// Returns false if any test regressed, so the command can guard a push
+pub fn command_compare(args: CliCompareArgs, ctx: CliContext) -> Result<bool, CrowClientError> {
+    let (base, head) = resolve_tasks(&ctx, args.base, args.head).context(CompareSnafu)?;
+    let comparison = ctx
+        .compare_tasks(&base, &head)
+        .context(FetchComparisonSnafu {
+            base: base.clone(),
+            head: head.clone(),
+        })
+        .context(CompareSnafu)?;
+
+    info!(
+        "{}",
+        st("Base ").append(style(describe_task(&comparison.base)).bold())
+    );
+    info!(
+        "{}",
+        st("Head ").append(style(describe_task(&comparison.head)).bold())
+    );
+
+    print_changes(style("Regressed").red().bold(), &comparison.regressed, true);
+    print_changes(
+        style("Newly passing").green().bold(),
+        &comparison.newly_passing,
+        true,
+    );
+    print_changes(
+        style("Changed").yellow().bold(),
+        &comparison.changed,
+        args.all_changes,
+    );
+
+    Ok(comparison.regressed.is_empty())
+}
+
+/// Fills in missing tasks from the recent tasks of the team
+fn resolve_tasks(
+    ctx: &CliContext,
+    base: Option<String>,
+    head: Option<String>,
+) -> Result<(String, String), CompareError> {
+    if let (Some(base), Some(head)) = (&base, &head) {
+        return Ok((base.clone(), head.clone()));
+    }
+
+    let recent = ctx
+        .get_recent_tasks(16)
+        .context(FetchRecentTasksSnafu)?
+        .into_iter()
+        .map(|it| it.info().task_id.clone())
+        .collect::<Vec<_>>();
+
+    let Some(head) = head.or_else(|| recent.first().cloned()) else {
+        return Err(NotEnoughTasksSnafu.into_error(NoneError));
+    };
+    // The task before the head, or the newest one if the head is older than the recent tasks
+    let base = base.or_else(|| match recent.iter().position(|it| *it == head) {
+        Some(index) => recent.get(index + 1).cloned(),
+        None => recent.first().cloned(),
+    });
+    let Some(base) = base else {
+        return Err(NotEnoughTasksSnafu.into_error(NoneError));
+    };
+
+    Ok((base, head))
+}
+
+fn print_changes(title: StyledObject<&str>, changes: &[TestStatusChange], list: bool) {
+    println!();
+    info!("{title} ({})", changes.len());
+    if !list {
+        return;
+    }
+
+    for change in changes {
+        let name = match &change.profile {
+            Some(profile) => format!("{} [{profile}]", change.test_id),
+            None => change.test_id.clone(),
+        };
+        let status = |status: &Option<String>| status.clone().unwrap_or("not run".to_string());
+        info!(
+            "  {} {}",
+            style(name).bold(),
+            style(format!(
+                "{} -> {}",
+                status(&change.base),
+                status(&change.head)
+            ))
+            .dim()
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use crate::error::{CompletionsSnafu, CrowClientError};
+use clap::{ArgGroup, Args, Command};
+use clap_complete::Shell;
+use snafu::ResultExt;
+use std::io::Write;
+
+#[derive(Args, Debug)]
+#[clap(group(ArgGroup::new("output").required(true).args(["shell", "man"])))]
+pub struct CliCompletionsArgs {
+    /// The shell to print completions for
+    shell: Option<Shell>,
+    /// Print a man page instead of shell completions
+    #[clap(long)]
+    man: bool,
+}
+
+pub fn command_completions(
+    args: &CliCompletionsArgs,
+    mut command: Command,
+) -> Result<(), CrowClientError> {
+    let mut stdout = std::io::stdout().lock();
+
+    if args.man {
+        clap_mangen::Man::new(command)
+            .render(&mut stdout)
+            .context(CompletionsSnafu)?;
+    } else if let Some(shell) = args.shell {
+        let name = command.get_name().to_string();
+        clap_complete::generate(shell, &mut command, name, &mut stdout);
+    }
+    stdout.flush().context(CompletionsSnafu)
+}
@@ -0,0 +1,237 @@
+use crate::commands::show_task::format_time;
+use crate::context::{CliContext, CliContextError, FinalTask, TaskSummary};
+use crate::error::{CrowClientError, FinalSubmitSnafu};
+use crate::util::st;
+use clap::Args;
+use console::style;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{MultiSelect, Select};
+use jiff::Timestamp;
+use snafu::{IntoError, Location, NoneError, ResultExt, Snafu};
+use std::collections::{BTreeSet, HashMap};
+use tracing::{info, warn};
+
+#[derive(Debug, Snafu)]
+pub enum FinalSubmitError {
+    #[snafu(display("Could not fetch the tasks and submissions of your team at {location}"))]
+    FetchState {
+        source: CliContextError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not submit task `{task_id}` at {location}"))]
+    Submit {
+        task_id: String,
+        source: CliContextError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Unknown category `{category}` at {location}"))]
+    UnknownCategory {
+        category: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Your team has no finished tasks yet at {location}"))]
+    NoTasks {
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("User aborted {what} at {location}"))]
+    UserAbort {
+        what: &'static str,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct CliFinalSubmitArgs {
+    /// The task to submit. Asks for one of the recent tasks of your team if missing.
+    #[clap(long)]
+    task_id: Option<String>,
+    /// A category to submit the task for, can be given multiple times. Replaces the categories the
+    /// task was submitted for before. Asks for them if missing.
+    #[clap(long = "category")]
+    categories: Vec<String>,
+    /// How many recent tasks to choose from
+    #[clap(long, default_value = "15")]
+    count: u32,
+}
+
+pub fn command_final_submit(
+    args: CliFinalSubmitArgs,
+    ctx: CliContext,
+) -> Result<bool, CrowClientError> {
+    let remote = ctx
+        .get_remote_tests()
+        .context(FetchStateSnafu)
+        .context(FinalSubmitSnafu)?;
+    let final_tasks = ctx
+        .get_final_tasks()
+        .context(FetchStateSnafu)
+        .context(FinalSubmitSnafu)?;
+
+    let task_id = match args.task_id {
+        Some(task_id) => task_id,
+        None => select_task(&ctx, args.count).context(FinalSubmitSnafu)?,
+    };
+
+    // Like in the web UI, only manual submissions of the task can be changed
+    let assigned = final_tasks
+        .iter()
+        .filter(|(_, task)| matches!(task, FinalTask::ManuallyOverridden { .. }))
+        .filter(|(_, task)| task.summary().info().task_id == task_id)
+        .map(|(category, _)| category.clone())
+        .collect::<BTreeSet<_>>();
+
+    let categories = if args.categories.is_empty() {
+        let now = Timestamp::now();
+        let mut available = remote
+            .categories
+            .iter()
+            .filter(|(_, meta)| meta.starts_at.timestamp() <= now)
+            .filter(|(_, meta)| now <= meta.labs_end_at.timestamp())
+            .map(|(name, _)| name.clone())
+            .collect::<BTreeSet<_>>();
+        available.extend(assigned.iter().cloned());
+        select_categories(&available, &assigned).context(FinalSubmitSnafu)?
+    } else {
+        for category in &args.categories {
+            if !remote.categories.contains_key(category) {
+                return Err(UnknownCategorySnafu {
+                    category: category.clone(),
+                }
+                .into_error(NoneError))
+                .context(FinalSubmitSnafu);
+            }
+        }
+        args.categories
+    };
+
+    ctx.set_final_task(&task_id, &categories)
+        .context(SubmitSnafu {
+            task_id: task_id.clone(),
+        })
+        .context(FinalSubmitSnafu)?;
+    if categories.is_empty() {
+        info!("Switched back to automatic submission");
+    } else {
+        info!(
+            "{}",
+            st("Will submit ")
+                .append(style(&task_id).bold())
+                .append(" for ")
+                .append(style(categories.join(", ")).bold())
+        );
+    }
+
+    let final_tasks = ctx
+        .get_final_tasks()
+        .context(FetchStateSnafu)
+        .context(FinalSubmitSnafu)?;
+    print_final_tasks(&final_tasks, &task_id);
+
+    Ok(true)
+}
+
+fn select_task(ctx: &CliContext, count: u32) -> Result<String, FinalSubmitError> {
+    let tasks = ctx.get_recent_tasks(count).context(FetchStateSnafu)?;
+    if tasks.is_empty() {
+        return Err(NoTasksSnafu.into_error(NoneError));
+    }
+
+    let items = tasks.iter().map(describe_task).collect::<Vec<_>>();
+    let selected = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select the task to submit")
+        .items(&items)
+        .default(0)
+        .interact_opt();
+
+    let Ok(Some(selected)) = selected else {
+        return Err(UserAbortSnafu {
+            what: "task selection",
+        }
+        .into_error(NoneError));
+    };
+
+    Ok(tasks[selected].info().task_id.clone())
+}
+
+fn select_categories(
+    available: &BTreeSet<String>,
+    assigned: &BTreeSet<String>,
+) -> Result<Vec<String>, FinalSubmitError> {
+    if available.is_empty() {
+        warn!("No category is open for submissions right now");
+        return Ok(Vec::new());
+    }
+
+    let available = available.iter().collect::<Vec<_>>();
+    let defaults = available
+        .iter()
+        .map(|it| assigned.contains(*it))
+        .collect::<Vec<_>>();
+    let selected = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select the categories to submit the task for (none selects automatically)")
+        .items(&available)
+        .defaults(&defaults)
+        .interact_opt();
+
+    let Ok(Some(selected)) = selected else {
+        return Err(UserAbortSnafu {
+            what: "category selection",
+        }
+        .into_error(NoneError));
+    };
+
+    Ok(selected
+        .into_iter()
+        .map(|it| available[it].clone())
+        .collect())
+}
+
+pub fn describe_task(task: &TaskSummary) -> String {
+    let info = task.info();
+    let result = match task {
+        TaskSummary::BuildFailed { .. } => "build failed".to_string(),
+        TaskSummary::RanTests { statistics, .. } => format!(
+            "{}/{} passed",
+            statistics.success.total, statistics.total.total
+        ),
+    };
+
+    format!(
+        "{} {} ({result}, {})",
+        &info.revision_id[..info.revision_id.len().min(7)],
+        info.commit_message.lines().next().unwrap_or_default(),
+        format_time(info.start)
+    )
+}
+
+fn print_final_tasks(final_tasks: &HashMap<String, FinalTask>, submitted: &str) {
+    let mut categories = final_tasks.keys().collect::<Vec<_>>();
+    categories.sort();
+
+    println!();
+    info!("{}", style("Graded tasks").bold());
+    for category in categories {
+        let task = &final_tasks[category];
+        let how = match task {
+            FinalTask::AutomaticallySelected { .. } => "automatically selected",
+            FinalTask::ManuallyOverridden { .. } => "manually selected",
+            FinalTask::Finalized { .. } => "finalized",
+        };
+        let marker = if task.summary().info().task_id == submitted {
+            style("●").green()
+        } else {
+            style("○").dim()
+        };
+        info!(
+            "  {marker} {} {} {}",
+            style(category).bold().bright().cyan(),
+            describe_task(task.summary()),
+            style(format!("[{how}]")).dim()
+        );
+    }
+}
@@ -8,7 +8,12 @@ use reqwest::blocking::Client;
 use snafu::ResultExt;
 use tracing::{error, info};
 
-pub fn command_login(client: Client, backend_url: &str, frontend_url: &str) -> Result<bool> {
+pub fn command_login(
+    client: Client,
+    backend_url: &str,
+    frontend_url: &str,
+    profile: Option<&str>,
+) -> Result<bool> {
     info!(
         "{}",
         st(style("Grab your token from "))
@@ -22,7 +27,7 @@ pub fn command_login(client: Client, backend_url: &str, frontend_url: &str) -> R
     );
 
     loop {
-        if login_iteration(&client, backend_url)? {
+        if login_iteration(&client, backend_url, profile)? {
             break;
         }
     }
@@ -36,7 +41,7 @@ pub fn command_login(client: Client, backend_url: &str, frontend_url: &str) -> R
     Ok(true)
 }
 
-fn login_iteration(client: &Client, backend_url: &str) -> Result<bool> {
+fn login_iteration(client: &Client, backend_url: &str, profile: Option<&str>) -> Result<bool> {
     let token = Password::with_theme(&ColorfulTheme::default())
         .with_prompt(style("Backend token").magenta().to_string())
         .interact();
@@ -55,7 +60,7 @@ fn login_iteration(client: &Client, backend_url: &str) -> Result<bool> {
         LoginResult::Success { auth, name } => (auth, name),
     };
 
-    store_auth(auth).context(AuthSnafu)?;
+    store_auth(auth, profile).context(AuthSnafu)?;
 
     info!("Welcome, {}!", style(name).green().bold().bright());
 
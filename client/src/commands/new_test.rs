@@ -0,0 +1,409 @@
+use crate::commands::upload::{CliUploadTestArgs, command_upload_test};
+use crate::context::{CliContext, Test, TestDetail, UserRole};
+use crate::error::{CrowClientError, NewTestSnafu};
+use crate::formats::to_markdown;
+use crate::util::st;
+use clap::Args;
+use console::style;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, Editor, Input, Select};
+use shared::{CompilerFailReason, CrashSignal, TestModifier, validate_test_id};
+use snafu::{IntoError, Location, NoneError, ResultExt, Snafu};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+#[derive(Debug, Snafu)]
+pub enum NewTestError {
+    #[snafu(display("User aborted {what} at {location}"))]
+    UserAbort {
+        what: &'static str,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Test name `{name}` invalid due to `{error}` at {location}"))]
+    TestName {
+        error: &'static str,
+        name: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not read `{}` at {location}", path.display()))]
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not write test to `{}` at {location}", path.display()))]
+    WriteTest {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct CliNewTestArgs {
+    /// The directory containing all tests. The test is written to
+    /// `<test-dir>/<category>/<name>.crow-test.md`.
+    #[clap(long = "test-dir", short = 'd')]
+    test_dir: PathBuf,
+    /// The test category
+    #[clap(short, long)]
+    category: Option<String>,
+    /// The test name
+    #[clap(short, long)]
+    name: Option<String>,
+    /// Whether to upload the test after writing it
+    #[clap(long)]
+    upload: Option<bool>,
+}
+
+/// What the test expects to happen when compiling and running the program
+enum Expectation {
+    CompilerFails(CompilerFailReason),
+    Succeeds,
+    ExitCode(u8),
+    Crashes(CrashSignal),
+    Timeout,
+}
+
+pub fn command_new_test(
+    args: CliNewTestArgs,
+    ctx: Option<CliContext>,
+) -> Result<bool, CrowClientError> {
+    let category = match args.category {
+        Some(category) => category,
+        None => prompt_category(&args.test_dir).context(NewTestSnafu)?,
+    };
+    let name = match args.name {
+        Some(name) => name,
+        None => prompt_name().context(NewTestSnafu)?,
+    };
+    if let Err(e) = validate_test_id(&name) {
+        return Err(TestNameSnafu { error: e, name }.into_error(NoneError)).context(NewTestSnafu);
+    }
+
+    let path = args
+        .test_dir
+        .join(&category)
+        .join(format!("{name}.crow-test.md"));
+    if path.exists()
+        && !confirm(
+            &format!("`{}` exists. Overwrite it?", path.display()),
+            false,
+        )?
+    {
+        return Err(UserAbortSnafu {
+            what: "overwriting the test",
+        }
+        .into_error(NoneError))
+        .context(NewTestSnafu);
+    }
+
+    let detail = prompt_detail().context(NewTestSnafu)?;
+
+    // The creator is part of the hash, so the test should name our team if we know it
+    let myself = ctx.as_ref().and_then(|ctx| match ctx.get_myself() {
+        Ok(myself) => Some(myself),
+        Err(e) => {
+            warn!("Could not fetch your team, the test will not name its creator: {e}");
+            None
+        }
+    });
+    let mut test = Test {
+        id: name.clone(),
+        creator_id: myself
+            .as_ref()
+            .and_then(|it| it.team.clone())
+            .unwrap_or_else(|| "unknown".to_string()),
+        admin_authored: myself.is_some_and(|it| it.role == Some(UserRole::Admin)),
+        limited_to_category: false,
+        category: category.clone(),
+        hash: String::new(),
+        last_updated: None,
+    };
+    test.hash = test.compute_hash(&detail);
+
+    write_test(&path, &test, &detail).context(NewTestSnafu)?;
+    info!(
+        "{}",
+        st("Wrote test to ").append(style(path.display()).green().bold())
+    );
+
+    let upload = match args.upload {
+        Some(upload) => upload,
+        None => confirm("Upload the test now?", false)?,
+    };
+    if !upload {
+        return Ok(true);
+    }
+    let Some(ctx) = ctx else {
+        warn!(
+            "{}",
+            st("You are not logged in. Run '")
+                .append(style("crow-client login").cyan())
+                .append("' and then '")
+                .append(style("upload-test").cyan())
+                .append("' to upload it.")
+        );
+        return Ok(false);
+    };
+
+    command_upload_test(CliUploadTestArgs::new(path, name, category), ctx)
+}
+
+fn confirm(prompt: &str, default: bool) -> Result<bool, CrowClientError> {
+    let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .interact_opt();
+
+    let Ok(Some(confirmed)) = confirmed else {
+        return Err(UserAbortSnafu {
+            what: "confirmation",
+        }
+        .into_error(NoneError))
+        .context(NewTestSnafu);
+    };
+
+    Ok(confirmed)
+}
+
+/// Offers the category directories `sync-tests` created, falling back to free input
+fn prompt_category(test_dir: &Path) -> Result<String, NewTestError> {
+    let mut categories = std::fs::read_dir(test_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|it| it.ok())
+                .filter(|it| it.path().is_dir())
+                .filter_map(|it| it.file_name().to_str().map(ToString::to_string))
+                .filter(|it| !it.starts_with('.'))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    categories.sort();
+
+    if !categories.is_empty() {
+        let mut items = categories.clone();
+        items.push("Other...".to_string());
+        let selected = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Select a category")
+            .items(&items)
+            .default(categories.len() - 1)
+            .interact_opt();
+        let Ok(Some(selected)) = selected else {
+            return Err(UserAbortSnafu {
+                what: "category selection",
+            }
+            .into_error(NoneError));
+        };
+        if selected < categories.len() {
+            return Ok(categories[selected].clone());
+        }
+    }
+
+    Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Enter the category")
+        .interact_text()
+        .map_err(|_| {
+            UserAbortSnafu {
+                what: "category input",
+            }
+            .into_error(NoneError)
+        })
+}
+
+fn prompt_name() -> Result<String, NewTestError> {
+    Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Enter the name of the new test")
+        .validate_with(|input: &String| validate_test_id(input))
+        .interact_text()
+        .map_err(|_| {
+            UserAbortSnafu {
+                what: "test name input",
+            }
+            .into_error(NoneError)
+        })
+}
+
+fn prompt_detail() -> Result<TestDetail, NewTestError> {
+    let program = prompt_program()?;
+    let expectation = prompt_expectation()?;
+
+    let mut compiler_modifiers = vec![TestModifier::ProgramArgumentFile { contents: program }];
+    let mut binary_modifiers = Vec::new();
+    let binary_outcome = match expectation {
+        Expectation::CompilerFails(reason) => {
+            compiler_modifiers.push(TestModifier::ShouldFail { reason });
+            None
+        }
+        Expectation::Succeeds => Some(TestModifier::ShouldSucceed),
+        Expectation::ExitCode(code) => Some(TestModifier::ExitCode { code }),
+        Expectation::Crashes(signal) => Some(TestModifier::ShouldCrash { signal }),
+        Expectation::Timeout => Some(TestModifier::ShouldTimeout),
+    };
+
+    if let Some(outcome) = binary_outcome {
+        compiler_modifiers.push(TestModifier::ShouldSucceed);
+
+        if let Some(input) = prompt_text("Does the program read input?", "program input")? {
+            binary_modifiers.push(TestModifier::ProgramInput { input });
+        }
+        if let Some(output) = prompt_text("Should the output be checked?", "expected output")? {
+            binary_modifiers.push(TestModifier::ExpectedOutput { output });
+        }
+        binary_modifiers.push(outcome);
+    }
+
+    Ok(TestDetail {
+        compiler_modifiers,
+        binary_modifiers,
+        timeout_ms: None,
+        depends_on: Vec::new(),
+        practice: false,
+        license: None,
+    })
+}
+
+fn prompt_program() -> Result<String, NewTestError> {
+    let selected = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Where is the program the test compiles?")
+        .items(&["In a file", "Write it in my editor"])
+        .default(0)
+        .interact_opt();
+    let Ok(Some(selected)) = selected else {
+        return Err(UserAbortSnafu {
+            what: "program selection",
+        }
+        .into_error(NoneError));
+    };
+
+    if selected == 1 {
+        return edit("program");
+    }
+
+    let path: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Path to the program")
+        .validate_with(|input: &String| {
+            if Path::new(input).is_file() {
+                Ok(())
+            } else {
+                Err("not a file")
+            }
+        })
+        .interact_text()
+        .map_err(|_| {
+            UserAbortSnafu {
+                what: "program path input",
+            }
+            .into_error(NoneError)
+        })?;
+
+    std::fs::read_to_string(&path).context(ReadFileSnafu { path })
+}
+
+fn prompt_expectation() -> Result<Expectation, NewTestError> {
+    let selected = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("What should happen?")
+        .items(&[
+            "The compiler rejects the program",
+            "The program runs successfully",
+            "The program exits with a specific exit code",
+            "The program crashes",
+            "The program does not terminate",
+        ])
+        .default(1)
+        .interact_opt();
+    let Ok(Some(selected)) = selected else {
+        return Err(UserAbortSnafu {
+            what: "expectation selection",
+        }
+        .into_error(NoneError));
+    };
+
+    Ok(match selected {
+        0 => {
+            let reasons = [
+                CompilerFailReason::Parsing,
+                CompilerFailReason::SemanticAnalysis,
+            ];
+            let reason = select_from("Why does the compiler reject it?", &reasons)?;
+            Expectation::CompilerFails(reason)
+        }
+        1 => Expectation::Succeeds,
+        2 => {
+            let code = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Expected exit code")
+                .interact_text()
+                .map_err(|_| {
+                    UserAbortSnafu {
+                        what: "exit code input",
+                    }
+                    .into_error(NoneError)
+                })?;
+            Expectation::ExitCode(code)
+        }
+        3 => {
+            let signals = [
+                CrashSignal::Abort,
+                CrashSignal::SegmentationFault,
+                CrashSignal::FloatingPointException,
+            ];
+            Expectation::Crashes(select_from("How does it crash?", &signals)?)
+        }
+        _ => Expectation::Timeout,
+    })
+}
+
+fn select_from<T: ToString + Clone>(prompt: &str, items: &[T]) -> Result<T, NewTestError> {
+    let selected = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .default(0)
+        .interact_opt();
+    let Ok(Some(selected)) = selected else {
+        return Err(UserAbortSnafu { what: "selection" }.into_error(NoneError));
+    };
+
+    Ok(items[selected].clone())
+}
+
+/// Asks whether the text is needed and lets the user write it in their editor if so
+fn prompt_text(question: &str, what: &'static str) -> Result<Option<String>, NewTestError> {
+    let wanted = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(question)
+        .default(false)
+        .interact_opt();
+    let Ok(Some(wanted)) = wanted else {
+        return Err(UserAbortSnafu { what }.into_error(NoneError));
+    };
+    if !wanted {
+        return Ok(None);
+    }
+
+    edit(what).map(Some)
+}
+
+fn edit(what: &'static str) -> Result<String, NewTestError> {
+    info!(
+        "{}",
+        st("Opening your editor for the ")
+            .append(style(what).cyan())
+            .append(", save and close it when you are done")
+    );
+    let Ok(Some(text)) = Editor::new().edit("") else {
+        return Err(UserAbortSnafu { what }.into_error(NoneError));
+    };
+
+    Ok(text)
+}
+
+fn write_test(path: &Path, test: &Test, detail: &TestDetail) -> Result<(), NewTestError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context(WriteTestSnafu { path })?;
+    }
+
+    std::fs::write(path, to_markdown(test, detail)).context(WriteTestSnafu { path })
+}
@@ -0,0 +1,97 @@
+use crate::auth::{delete_auth, try_get_stored_auth};
+use crate::error::{AuthSnafu, CrowClientError, ProfileSnafu};
+use crate::profile::{Profile, Profiles};
+use crate::util::st;
+use clap::{Args, Subcommand};
+use console::style;
+use snafu::ResultExt;
+use tracing::info;
+
+#[derive(Args, Debug)]
+pub struct CliProfilesArgs {
+    #[command(subcommand)]
+    command: Option<CliProfilesCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+enum CliProfilesCommand {
+    /// Lists all profiles. The default if no subcommand is given.
+    List,
+    /// Adds a profile or changes the urls of an existing one
+    Add(CliAddProfileArgs),
+    /// Removes a profile and forgets its token
+    Remove {
+        /// The name of the profile
+        name: String,
+    },
+}
+
+#[derive(Args, Debug)]
+struct CliAddProfileArgs {
+    /// The name to select the profile with using `--profile`
+    name: String,
+    /// The url of the backend, e.g. `http://localhost:3000/api`
+    #[clap(long)]
+    backend_url: String,
+    /// The url of the frontend, e.g. `http://localhost:5173`
+    #[clap(long)]
+    frontend_url: String,
+}
+
+pub fn command_profiles(args: CliProfilesArgs) -> Result<bool, CrowClientError> {
+    let mut profiles = Profiles::load().context(ProfileSnafu)?;
+
+    match args.command.unwrap_or(CliProfilesCommand::List) {
+        CliProfilesCommand::List => {
+            if profiles.profiles.is_empty() {
+                info!("No profiles configured");
+            }
+            for (name, profile) in &profiles.profiles {
+                let status = match try_get_stored_auth(Some(name)) {
+                    Some(_) => style("logged in").green(),
+                    None => style("logged out").yellow(),
+                };
+                info!(
+                    "{}",
+                    st(style(name).bold().cyan())
+                        .append(" (")
+                        .append(status)
+                        .append(")\n  backend:  ")
+                        .append(&profile.backend_url)
+                        .append("\n  frontend: ")
+                        .append(&profile.frontend_url)
+                );
+            }
+        }
+        CliProfilesCommand::Add(add) => {
+            profiles.profiles.insert(
+                add.name.clone(),
+                Profile {
+                    backend_url: add.backend_url,
+                    frontend_url: add.frontend_url,
+                },
+            );
+            profiles.save().context(ProfileSnafu)?;
+            info!(
+                "{}",
+                st("Saved profile ")
+                    .append(style(&add.name).bold().cyan())
+                    .append(". Log in to it with '")
+                    .append(style(format!("--profile {} login", add.name)).bold().cyan())
+                    .append("'.")
+            );
+        }
+        CliProfilesCommand::Remove { name } => {
+            profiles.get(&name).context(ProfileSnafu)?;
+            delete_auth(Some(&name)).context(AuthSnafu)?;
+            profiles.profiles.remove(&name);
+            profiles.save().context(ProfileSnafu)?;
+            info!(
+                "{}",
+                st("Removed profile ").append(style(&name).bold().cyan())
+            );
+        }
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,372 @@
+use crate::commands::show_task::{format_time, test_passed};
+use crate::commands::sync_tests::{FullTest, SyncTestsError, get_local_tests};
+use crate::error::{CrowClientError, GenerateReportSnafu};
+use clap::Args;
+use shared::{
+    ExecutionOutput, FinishedCompilerTask, FinishedExecution, FinishedTest, TestExecutionOutput,
+};
+use snafu::{Location, ResultExt, Snafu, ensure};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+#[derive(Debug, Snafu)]
+pub enum ReportError {
+    #[snafu(display("The directory `{}` does not exist at {location}", path.display()))]
+    MissingDirectory {
+        path: PathBuf,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not read the tasks in `{}` at {location}", path.display()))]
+    ReadTasks {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not parse the task in `{}` at {location}", path.display()))]
+    ParseTask {
+        path: PathBuf,
+        source: serde_json::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not read the test suite at {location}"))]
+    ReadTests {
+        source: SyncTestsError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not write the report `{}` at {location}", path.display()))]
+    WriteReport {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct CliReportArgs {
+    /// The directory containing the finished tasks, one `show-task --json` output per `.json` file
+    #[clap(long = "tasks", short = 't')]
+    task_dir: PathBuf,
+    /// The directory containing the test suite, as created by `sync-tests`
+    #[clap(long = "test-dir", short = 'd')]
+    test_dir: PathBuf,
+    /// The directory to write the reports to. Every team gets an own `<team>.html`.
+    #[clap(long = "output", short = 'o')]
+    output_dir: PathBuf,
+}
+
+pub fn command_report(args: CliReportArgs) -> Result<bool, CrowClientError> {
+    generate_report(&args).context(GenerateReportSnafu)?;
+
+    Ok(true)
+}
+
+fn generate_report(args: &CliReportArgs) -> Result<(), ReportError> {
+    for dir in [&args.task_dir, &args.test_dir] {
+        ensure!(dir.is_dir(), MissingDirectorySnafu { path: dir.clone() });
+    }
+
+    let tests = get_local_tests(&args.test_dir).context(ReadTestsSnafu)?;
+    let mut tasks_by_team: BTreeMap<String, Vec<FinishedCompilerTask>> = BTreeMap::new();
+    for task in read_tasks(&args.task_dir)? {
+        tasks_by_team
+            .entry(task.info().team_id.clone())
+            .or_default()
+            .push(task);
+    }
+
+    fs::create_dir_all(&args.output_dir).context(WriteReportSnafu {
+        path: args.output_dir.clone(),
+    })?;
+
+    for (team, tasks) in &mut tasks_by_team {
+        // Newest first, that is usually the one that is graded
+        tasks.sort_by_key(|it| Reverse(it.info().start));
+        let path = args.output_dir.join(format!("{}.html", file_name(team)));
+        fs::write(&path, render_team(team, tasks, &tests))
+            .context(WriteReportSnafu { path: path.clone() })?;
+        info!("Wrote report for `{team}` to `{}`", path.display());
+    }
+
+    let path = args.output_dir.join("index.html");
+    fs::write(&path, render_index(&tasks_by_team))
+        .context(WriteReportSnafu { path: path.clone() })?;
+    info!("Wrote index to `{}`", path.display());
+
+    Ok(())
+}
+
+fn read_tasks(task_dir: &Path) -> Result<Vec<FinishedCompilerTask>, ReportError> {
+    let entries = fs::read_dir(task_dir).context(ReadTasksSnafu { path: task_dir })?;
+
+    let mut tasks = Vec::new();
+    for entry in entries {
+        let path = entry.context(ReadTasksSnafu { path: task_dir })?.path();
+        if path.extension().is_none_or(|it| it != "json") {
+            continue;
+        }
+        let content = fs::read_to_string(&path).context(ReadTasksSnafu { path: &path })?;
+        tasks.push(serde_json::from_str(&content).context(ParseTaskSnafu { path: &path })?);
+    }
+
+    Ok(tasks)
+}
+
+fn render_index(tasks_by_team: &BTreeMap<String, Vec<FinishedCompilerTask>>) -> String {
+    let mut body = String::from("<h1>Crow report</h1>\n<table>\n");
+    body.push_str("<tr><th>Team</th><th>Tasks</th><th>Latest task</th><th>Passed</th></tr>\n");
+    for (team, tasks) in tasks_by_team {
+        let latest = &tasks[0];
+        let (passed, total) = passed_tests(latest);
+        let _ = writeln!(
+            body,
+            "<tr><td><a href=\"{}.html\">{}</a></td><td>{}</td><td>{}</td><td>{passed}/{total}</td></tr>",
+            escape_html(&file_name(team)),
+            escape_html(team),
+            tasks.len(),
+            format_time(latest.info().start),
+        );
+    }
+    body.push_str("</table>\n");
+
+    page("Crow report", &body)
+}
+
+fn render_team(team: &str, tasks: &[FinishedCompilerTask], tests: &[FullTest]) -> String {
+    let mut body = format!("<h1>Team {}</h1>\n", escape_html(team));
+    for task in tasks {
+        render_task(&mut body, task, tests);
+    }
+
+    page(&format!("Crow report for {team}"), &body)
+}
+
+fn render_task(body: &mut String, task: &FinishedCompilerTask, tests: &[FullTest]) {
+    let info = task.info();
+    let _ = writeln!(
+        body,
+        "<h2>Task {}</h2>\n<dl>\n<dt>Revision</dt><dd>{}</dd>\n<dt>Commit</dt><dd>{}</dd>\n\
+         <dt>Started</dt><dd>{}</dd>\n<dt>Finished</dt><dd>{}</dd>\n</dl>",
+        escape_html(&info.task_id),
+        escape_html(&info.revision_id),
+        escape_html(info.commit_message.lines().next().unwrap_or_default()),
+        format_time(info.start),
+        format_time(info.end),
+    );
+
+    let results = match task {
+        FinishedCompilerTask::BuildFailed { build_output, .. } => {
+            body.push_str("<p class=\"failure\">The build failed</p>\n");
+            render_output(body, "Build output", build_output);
+            return;
+        }
+        FinishedCompilerTask::RanTests {
+            build_output,
+            tests,
+            ..
+        } => {
+            render_execution(body, "Build output", build_output);
+            tests
+        }
+    };
+
+    render_scores(body, results);
+    render_outcomes(body, results, tests);
+}
+
+fn render_scores(body: &mut String, results: &[FinishedTest]) {
+    let mut by_category: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for result in results {
+        let entry = by_category
+            .entry(result.category.as_deref().unwrap_or("uncategorized"))
+            .or_default();
+        entry.1 += 1;
+        if test_passed(result) {
+            entry.0 += 1;
+        }
+    }
+
+    body.push_str("<h3>Scores</h3>\n<table>\n<tr><th>Category</th><th>Passed</th></tr>\n");
+    for (category, (passed, total)) in by_category {
+        let _ = writeln!(
+            body,
+            "<tr><td>{}</td><td>{passed}/{total}</td></tr>",
+            escape_html(category)
+        );
+    }
+    body.push_str("</table>\n");
+}
+
+fn render_outcomes(body: &mut String, results: &[FinishedTest], tests: &[FullTest]) {
+    let mut results = results.iter().collect::<Vec<_>>();
+    results.sort_by(|a, b| {
+        (&a.category, &a.test_id, &a.profile).cmp(&(&b.category, &b.test_id, &b.profile))
+    });
+
+    body.push_str("<h3>Tests</h3>\n");
+    for result in &results {
+        let name = match &result.profile {
+            Some(profile) => format!("{} ({profile})", result.test_id),
+            None => result.test_id.clone(),
+        };
+        let (class, outcome) = match &result.output {
+            TestExecutionOutput::Success { .. } => ("success", "passed"),
+            TestExecutionOutput::CompilerFailed { .. } => ("failure", "compiler failed"),
+            TestExecutionOutput::BinaryFailed { .. } => ("failure", "binary failed"),
+            TestExecutionOutput::Error { .. } => ("failure", "error"),
+            TestExecutionOutput::Skipped { .. } => ("failure", "skipped"),
+        };
+        let _ = writeln!(
+            body,
+            "<details>\n<summary class=\"{class}\">{} &mdash; {} &mdash; {outcome}</summary>",
+            escape_html(result.category.as_deref().unwrap_or("uncategorized")),
+            escape_html(&name),
+        );
+        render_output(body, "Compiler output", result.output.compiler_output());
+        if let Some(binary_output) = result.output.binary_output() {
+            render_output(body, "Binary output", binary_output);
+        }
+        body.push_str("</details>\n");
+    }
+
+    let not_run = tests
+        .iter()
+        .filter(|test| !results.iter().any(|it| it.test_id == test.test.id))
+        .collect::<Vec<_>>();
+    if not_run.is_empty() {
+        return;
+    }
+    body.push_str("<h3>Tests of the suite that were not run</h3>\n<ul>\n");
+    for test in not_run {
+        let _ = writeln!(
+            body,
+            "<li>{} &mdash; {}</li>",
+            escape_html(&test.test.category),
+            escape_html(&test.test.id)
+        );
+    }
+    body.push_str("</ul>\n");
+}
+
+fn render_output(body: &mut String, title: &str, output: &ExecutionOutput) {
+    let (status, execution) = match output {
+        ExecutionOutput::Success(execution) => ("successful".to_string(), execution),
+        ExecutionOutput::Timeout(execution) => ("timed out".to_string(), execution),
+        ExecutionOutput::Failure {
+            execution,
+            accumulated_errors,
+        } => {
+            let errors = accumulated_errors
+                .as_deref()
+                .unwrap_or("No specific errors provided");
+            let _ = writeln!(
+                body,
+                "<h4>{title}: errors</h4>\n<pre>{}</pre>",
+                escape_html(errors)
+            );
+            ("unsuccessful".to_string(), execution)
+        }
+        ExecutionOutput::Aborted(aborted) => {
+            let _ = writeln!(
+                body,
+                "<h4>{title}: aborted after {}s</h4>",
+                aborted.runtime.as_secs()
+            );
+            render_streams(body, &aborted.stdout, &aborted.stderr);
+            return;
+        }
+        ExecutionOutput::Error(error) => {
+            let _ = writeln!(
+                body,
+                "<h4>{title}: internal error after {}s</h4>\n<pre>{}</pre>",
+                error.runtime.as_secs(),
+                escape_html(&error.message)
+            );
+            return;
+        }
+    };
+
+    let _ = writeln!(
+        body,
+        "<h4>{title}: {status} after {}s</h4>",
+        execution.runtime.as_secs()
+    );
+    render_streams(body, &execution.stdout, &execution.stderr);
+}
+
+fn render_execution(body: &mut String, title: &str, execution: &FinishedExecution) {
+    let _ = writeln!(body, "<h3>{title}</h3>");
+    render_streams(body, &execution.stdout, &execution.stderr);
+}
+
+fn render_streams(body: &mut String, stdout: &str, stderr: &str) {
+    for (name, content) in [("Stdout", stdout), ("Stderr", stderr)] {
+        if content.trim().is_empty() {
+            continue;
+        }
+        let _ = writeln!(
+            body,
+            "<h5>{name}</h5>\n<pre>{}</pre>",
+            escape_html(content.trim())
+        );
+    }
+}
+
+fn passed_tests(task: &FinishedCompilerTask) -> (usize, usize) {
+    match task {
+        FinishedCompilerTask::BuildFailed { .. } => (0, 0),
+        FinishedCompilerTask::RanTests { tests, .. } => (
+            tests.iter().filter(|it| test_passed(it)).count(),
+            tests.len(),
+        ),
+    }
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape_html(title)
+    )
+}
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 70em; margin: auto; } \
+    table { border-collapse: collapse; } td, th { border: 1px solid #ccc; padding: 0.2em 0.6em; } \
+    pre { background: #f4f4f4; padding: 0.5em; overflow-x: auto; } \
+    .success { color: #1a7f37; } .failure { color: #cf222e; }";
+
+/// Team ids are chosen by admins, but we still do not want them to escape the output directory
+fn file_name(team: &str) -> String {
+    team.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,428 @@
+use crate::context::{CliContext, CliContextError};
+use crate::error::{CrowClientError, ReproSnafu};
+use crate::util::{
+    execute_locally, execution_output_to_string, print_test_invocation, print_test_output, st,
+};
+use clap::Args;
+use console::style;
+use flate2::read::GzDecoder;
+use shared::execute::{CommandResult, OUTPUT_BINARY_NAME, execute_test};
+use shared::repro::{FILESET_DIR, MANIFEST_FILE, ReproManifest};
+use shared::{PinnedEnvironment, TestExecutionOutput, indent};
+use snafu::{IntoError, Location, Report, ResultExt, Snafu};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{error, info, warn};
+use walkdir::WalkDir;
+
+/// Where the argument files and the compiled binary are placed in the container
+const FILES_IN_CONTAINER: &str = "/crow";
+
+#[derive(Debug, Snafu)]
+pub enum ReproError {
+    #[snafu(display(
+        "Could not fetch the bundle of test `{test_id}` in task `{task_id}` at {location}"
+    ))]
+    FetchBundle {
+        task_id: String,
+        test_id: String,
+        source: CliContextError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not unpack the bundle to `{}` at {location}", path.display()))]
+    Unpack {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not parse the manifest of the bundle at {location}"))]
+    ParseManifest {
+        source: serde_json::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not list the files of `{}` with git at {location}", path.display()))]
+    ListSources {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not copy `{}` at {location}", path.display()))]
+    CopyFile {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not walk the fileset at {location}"))]
+    WalkFileset {
+        source: walkdir::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not start a container with `{runtime}` at {location}"))]
+    StartContainer {
+        runtime: String,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not run the build in the container at {location}"))]
+    RunBuild {
+        source: Box<dyn std::error::Error + Send + Sync>,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct CliReproArgs {
+    /// The id of the task the test ran in
+    task_id: String,
+    /// The id of the test to reproduce
+    test_id: String,
+    /// The profile the test ran with, defaults to the first failed one
+    #[clap(long = "profile")]
+    profile: Option<String>,
+    /// Where to unpack the bundle. Defaults to `crow-repro-<task>-<test>`.
+    #[clap(long = "out", short = 'o')]
+    out_dir: Option<PathBuf>,
+    /// The git repository of your compiler. Its tracked and untracked but not ignored files are
+    /// built, like crow builds your commits.
+    #[clap(long = "source", short = 's', default_value = ".")]
+    source_dir: PathBuf,
+    /// The container runtime to run the test with, e.g. `podman`
+    #[clap(long = "runtime", default_value = "docker")]
+    runtime: String,
+    /// Only download and unpack the bundle, e.g. to run it with runc yourself
+    #[clap(long = "no-run", default_value = "false")]
+    no_run: bool,
+}
+
+/// Downloads the reproducibility bundle of a test and reruns the test against the local sources in
+/// a container of the image crow used
+pub fn command_repro(args: CliReproArgs, ctx: CliContext) -> Result<bool, CrowClientError> {
+    let bundle = ctx
+        .get_repro_bundle(&args.task_id, &args.test_id, args.profile.as_deref())
+        .context(FetchBundleSnafu {
+            task_id: args.task_id.clone(),
+            test_id: args.test_id.clone(),
+        })
+        .context(ReproSnafu)?;
+
+    let out_dir = args
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("crow-repro-{}-{}", args.task_id, args.test_id)));
+    tar::Archive::new(GzDecoder::new(bundle.as_slice()))
+        .unpack(&out_dir)
+        .context(UnpackSnafu {
+            path: out_dir.clone(),
+        })
+        .context(ReproSnafu)?;
+    let manifest = fs::read_to_string(out_dir.join(MANIFEST_FILE))
+        .context(UnpackSnafu {
+            path: out_dir.join(MANIFEST_FILE),
+        })
+        .context(ReproSnafu)?;
+    let manifest: ReproManifest = serde_json::from_str(&manifest)
+        .context(ParseManifestSnafu)
+        .context(ReproSnafu)?;
+    info!(
+        "{}",
+        st("Unpacked the bundle to ").append(style(out_dir.display()).bold())
+    );
+
+    if args.no_run {
+        return Ok(true);
+    }
+
+    run_repro(&args, &manifest, &out_dir).context(ReproSnafu)
+}
+
+fn run_repro(
+    args: &CliReproArgs,
+    manifest: &ReproManifest,
+    out_dir: &Path,
+) -> Result<bool, ReproError> {
+    let tempdir = tempfile::tempdir().context(CopyFileSnafu {
+        path: std::env::temp_dir(),
+    })?;
+    let work_dir = tempdir.path().join("work");
+    let files_dir = tempdir.path().join("files");
+    for dir in [&work_dir, &files_dir] {
+        fs::create_dir(dir).context(CopyFileSnafu { path: dir.clone() })?;
+    }
+    copy_sources(&args.source_dir, &work_dir)?;
+
+    info!(
+        "{}",
+        st("Starting a container of ").append(style(&manifest.image).bold())
+    );
+    let container = Container::start(&args.runtime, &manifest.image, &work_dir, &files_dir)?;
+
+    info!(
+        "{}",
+        st("Building with ").append(style(manifest.build_command.join(" ")).cyan())
+    );
+    let build = container
+        .execute(
+            &manifest.build_command,
+            &container.environment(manifest, &manifest.environment, None),
+            manifest.build_timeout,
+            String::new(),
+            &work_dir,
+        )
+        .context(RunBuildSnafu)?;
+    match build {
+        CommandResult::Unprocessed((status, _)) if status.success() => {}
+        CommandResult::Unprocessed((status, execution)) => {
+            error!(
+                "{}\n{}{}",
+                style(format!("Build failed with {status}")).red().bold(),
+                execution.stdout,
+                execution.stderr
+            );
+            return Ok(false);
+        }
+        CommandResult::ProcessedFailed(output) => {
+            error!(
+                "{}\n{}",
+                style("Build failed").red().bold(),
+                indent(&execution_output_to_string(&output), 2)
+            );
+            return Ok(false);
+        }
+    }
+    copy_fileset(&out_dir.join(FILESET_DIR), &work_dir)?;
+
+    let test = &manifest.test;
+    if test.binary_environment.disable_aslr {
+        warn!("crow ran the binary without ASLR, the container here keeps it enabled");
+    }
+    let binary_in_container = Path::new(FILES_IN_CONTAINER).join(OUTPUT_BINARY_NAME);
+    let (res, invocation) = execute_test(
+        test,
+        &files_dir,
+        &files_dir.join(OUTPUT_BINARY_NAME),
+        Path::new(FILES_IN_CONTAINER),
+        &work_dir,
+        |path, cmd, timeout, stdin| {
+            let timeout = timeout.unwrap_or(test.timeout);
+            // Only the compiled binary needs to be emulated, not the compiler
+            let (mut command, pinned) = if path == binary_in_container {
+                let pinned = test.binary_environment.pinned.as_ref();
+                (test.runner_prefix.clone(), pinned)
+            } else {
+                (Vec::new(), None)
+            };
+            command.push(path.display().to_string());
+            command.extend(cmd.iter().cloned());
+
+            let pinned = pinned.unwrap_or(&manifest.environment);
+            let env = container.environment(manifest, pinned, Some(timeout));
+            container.execute(&command, &env, timeout, stdin, &work_dir)
+        },
+    );
+
+    print_test_output(&res);
+    print_test_invocation(&invocation);
+    if invocation != manifest.invocation {
+        warn!(
+            "{}",
+            st("The commands differ from the ones crow ran, argument files are placed in ")
+                .append(style(FILES_IN_CONTAINER).bold())
+                .append(" instead of ")
+                .append(style("/").bold())
+        );
+    }
+
+    Ok(matches!(res, TestExecutionOutput::Success { .. }))
+}
+
+/// Copies the files git knows about, which mirrors what crow exports from a commit, but includes
+/// uncommitted changes
+fn copy_sources(source_dir: &Path, target: &Path) -> Result<(), ReproError> {
+    let output = Command::new("git")
+        .args([
+            "ls-files",
+            "-z",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+        ])
+        .current_dir(source_dir)
+        .output()
+        .context(ListSourcesSnafu {
+            path: source_dir.to_path_buf(),
+        })?;
+    if !output.status.success() {
+        return Err(ListSourcesSnafu {
+            path: source_dir.to_path_buf(),
+        }
+        .into_error(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )));
+    }
+
+    let files = String::from_utf8_lossy(&output.stdout).to_string();
+    for file in files.split('\0').filter(|it| !it.is_empty()) {
+        let source = source_dir.join(file);
+        // Deleted files are still listed, and submodules are directories
+        if !source.is_file() {
+            continue;
+        }
+        copy_file(&source, &target.join(file))?;
+    }
+
+    Ok(())
+}
+
+fn copy_fileset(fileset_dir: &Path, target: &Path) -> Result<(), ReproError> {
+    if !fileset_dir.exists() {
+        return Ok(());
+    }
+    for entry in WalkDir::new(fileset_dir) {
+        let entry = entry.context(WalkFilesetSnafu)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(fileset_dir)
+            .expect("walked paths are below the root");
+        copy_file(entry.path(), &target.join(relative))?;
+    }
+
+    Ok(())
+}
+
+fn copy_file(source: &Path, target: &Path) -> Result<(), ReproError> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).context(CopyFileSnafu {
+            path: parent.to_path_buf(),
+        })?;
+    }
+    fs::copy(source, target).context(CopyFileSnafu {
+        path: source.to_path_buf(),
+    })?;
+
+    Ok(())
+}
+
+/// A container idling until commands are executed in it. Removed on drop.
+struct Container {
+    runtime: String,
+    id: String,
+}
+
+impl Container {
+    fn start(
+        runtime: &str,
+        image: &str,
+        work_dir: &Path,
+        files_dir: &Path,
+    ) -> Result<Self, ReproError> {
+        // Run as the owner of the directories, so we can clean up what the container wrote
+        let metadata = fs::metadata(work_dir).context(CopyFileSnafu {
+            path: work_dir.to_path_buf(),
+        })?;
+        let output = Command::new(runtime)
+            .arg("run")
+            .arg("--detach")
+            .arg("--rm")
+            .arg(format!("--user={}:{}", metadata.uid(), metadata.gid()))
+            .arg(format!("--volume={}:/work", work_dir.display()))
+            .arg(format!(
+                "--volume={}:{FILES_IN_CONTAINER}",
+                files_dir.display()
+            ))
+            .arg("--workdir=/work")
+            .arg("--entrypoint=sleep")
+            .arg(image)
+            .arg("infinity")
+            .output()
+            .context(StartContainerSnafu { runtime })?;
+        if !output.status.success() {
+            return Err(
+                StartContainerSnafu { runtime }.into_error(std::io::Error::other(
+                    String::from_utf8_lossy(&output.stderr).to_string(),
+                )),
+            );
+        }
+
+        Ok(Self {
+            runtime: runtime.to_string(),
+            id: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        })
+    }
+
+    /// The variables the executor sets for the build, or for a test if it has a timeout
+    fn environment(
+        &self,
+        manifest: &ReproManifest,
+        pinned: &PinnedEnvironment,
+        test_timeout: Option<std::time::Duration>,
+    ) -> Vec<(String, String)> {
+        let mut env = pinned
+            .variables()
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect::<Vec<_>>();
+        env.push(("CROW_TASK_ID".to_string(), manifest.task_id.clone()));
+        let timeout = match test_timeout {
+            Some(timeout) => {
+                env.push(("CROW_TEST_ID".to_string(), manifest.test.test_id.clone()));
+                env.push(("CROW_CATEGORY".to_string(), manifest.test.category.clone()));
+                timeout
+            }
+            None => manifest.build_timeout,
+        };
+        env.push((
+            "CROW_TIMEOUT_MS".to_string(),
+            timeout.as_millis().to_string(),
+        ));
+
+        env
+    }
+
+    fn execute(
+        &self,
+        command: &[String],
+        env: &[(String, String)],
+        timeout: std::time::Duration,
+        stdin: String,
+        work_dir: &Path,
+    ) -> Result<CommandResult, Box<dyn std::error::Error + Send + Sync>> {
+        let mut args = vec!["exec".to_string(), "--interactive".to_string()];
+        for (key, value) in env {
+            args.push(format!("--env={key}={value}"));
+        }
+        args.push(self.id.clone());
+        args.extend(command.iter().cloned());
+
+        execute_locally(
+            Path::new(&self.runtime),
+            &args,
+            Some(timeout),
+            stdin,
+            work_dir,
+        )
+    }
+}
+
+impl Drop for Container {
+    fn drop(&mut self) {
+        let res = Command::new(&self.runtime)
+            .args(["rm", "--force", &self.id])
+            .output();
+        if let Err(e) = res {
+            warn!(error = %Report::from_error(e), container = %self.id, "Could not remove the container");
+        }
+    }
+}
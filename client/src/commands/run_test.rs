@@ -1,18 +1,26 @@
 use crate::commands::sync_tests::{FullTest, get_local_tests};
+use crate::context::CliContext;
 use crate::error::{CrowClientError, RunTestSnafu, SyncTestsSnafu, TempdirSnafu};
 use crate::formats::{FormatError, from_markdown};
-use crate::util::{infer_test_metadata_from_path, print_test_output};
+use crate::remote_cache::{CachedRemoteTest, refresh_remote_cache};
+use crate::util::{infer_test_metadata_from_path, print_test_invocation, print_test_output, st};
 use clap::Args;
 use console::style;
+use jiff::tz::TimeZone;
 use jiff::{Timestamp, Unit};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 use rayon::ThreadPoolBuilder;
-use shared::execute::execute_test;
-use shared::{CompilerTest, TestExecutionOutput};
+use shared::execute::{OUTPUT_BINARY_NAME, execute_test};
+use shared::{
+    BinaryEnvironment, CompilerTest, ContainerPermissions, TestExecutionOutput, TestInvocation,
+};
 use snafu::{IntoError, Location, NoneError, Report, ResultExt, Snafu, ensure, location};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::time::Duration;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use walkdir::WalkDir;
 
 #[derive(Debug, Snafu)]
@@ -100,28 +108,37 @@ pub struct CliRunTestsArgs {
     /// Only show failing tests in the output
     #[clap(long = "only-failing", default_value = "false")]
     only_failing: bool,
+    /// Run the tests in a random order to catch tests depending on each other. Use `--jobs 1` for
+    /// a fully reproducible order.
+    #[clap(long = "shuffle", default_value = "false")]
+    shuffle: bool,
+    /// The seed for `--shuffle`, to reproduce the order of an earlier run. Implies `--shuffle`.
+    #[clap(long = "seed")]
+    seed: Option<u64>,
 }
 
-pub fn command_run_tests(args: CliRunTestsArgs) -> Result<bool, CrowClientError> {
+pub fn command_run_tests(
+    args: CliRunTestsArgs,
+    ctx: Option<CliContext>,
+) -> Result<bool, CrowClientError> {
     let mut tests = get_local_tests(&args.test_dir).context(SyncTestsSnafu)?;
-    let mut categories = tests.iter().map(|it| &it.test.category).collect::<Vec<_>>();
-    categories.sort();
-    let newest_category = categories.last().map(|it| it.to_string());
+    select_tests(&mut tests, args.category.as_deref());
 
-    if let Some(category) = &args.category {
-        info!("Running only tests belonging to category `{}`", category);
-        tests.retain(|it| it.test.category == *category);
-    } else if let Some(newest_category) = &newest_category {
+    let seed =
+        (args.shuffle || args.seed.is_some()).then(|| args.seed.unwrap_or_else(rand::random));
+    if let Some(seed) = seed {
         info!(
-            "No category specified, running all tests. \
-            Ignoring tests limited to any category besides `{}` to prevent unwanted errors.",
-            newest_category
+            "{}",
+            st("Shuffling tests with seed ").append(style(seed).bold())
         );
-        tests.retain(|it| !it.test.limited_to_category || it.test.category == *newest_category);
+        tests.shuffle(&mut StdRng::seed_from_u64(seed));
     }
 
     let mut failures = 0;
+    let mut outdated = 0;
     let mut errors = 0;
+    // Only fetched on the first failure, passing runs should not need the network
+    let mut remote_tests: Option<Option<Vec<CachedRemoteTest>>> = None;
     let mut successes = 0;
     let separator_width = 80;
     let test_count = tests.len();
@@ -157,7 +174,7 @@ pub fn command_run_tests(args: CliRunTestsArgs) -> Result<bool, CrowClientError>
         let remaining_padding = separator_width - test.id.len() - 2;
         let elapsed = start.duration_until(Timestamp::now());
 
-        let print_test = !(args.only_failing && matches!(res, Ok((true, _))));
+        let print_test = !(args.only_failing && matches!(res, Ok((true, _, _))));
 
         if print_test {
             print!(
@@ -171,7 +188,7 @@ pub fn command_run_tests(args: CliRunTestsArgs) -> Result<bool, CrowClientError>
                 "    {} {}",
                 style(format!(
                     "{}/{} completed",
-                    successes + failures + errors,
+                    successes + failures + outdated + errors,
                     test_count
                 ))
                 .bold()
@@ -186,15 +203,44 @@ pub fn command_run_tests(args: CliRunTestsArgs) -> Result<bool, CrowClientError>
         }
 
         match res {
-            Ok((true, res)) => {
+            Ok((true, res, _)) => {
                 if print_test {
                     print_test_output(&res);
                 }
                 successes += 1;
             }
-            Ok((false, res)) => {
-                print_test_output(&res);
-                failures += 1;
+            Ok((false, res, invocation)) => {
+                let remote_tests = remote_tests
+                    .get_or_insert_with(|| refresh_remote_cache(&args.test_dir, ctx.as_ref()));
+                let upstream = remote_tests
+                    .iter()
+                    .flatten()
+                    .find(|it| it.id == test.id && it.category == test.category)
+                    .filter(|it| it.hash != test.hash);
+
+                if let Some(upstream) = upstream {
+                    let date = upstream
+                        .last_updated
+                        .map(|it| {
+                            it.to_zoned(TimeZone::system())
+                                .strftime("%F %R")
+                                .to_string()
+                        })
+                        .unwrap_or("an unknown date".to_string());
+                    warn!(
+                        "{}",
+                        st("Test was updated upstream on ")
+                            .append(style(date).bold())
+                            .append(", run ")
+                            .append(style("sync-tests").cyan())
+                            .append(" to get the new version")
+                    );
+                    outdated += 1;
+                } else {
+                    print_test_output(&res);
+                    print_test_invocation(&invocation);
+                    failures += 1;
+                }
             }
             Err(err) => {
                 errors += 1;
@@ -215,19 +261,77 @@ pub fn command_run_tests(args: CliRunTestsArgs) -> Result<bool, CrowClientError>
         style(format!("{errors} errored")).red(),
         style(".").bright().cyan()
     );
+    if outdated > 0 {
+        warn!(
+            "{}",
+            st(style(format!("{outdated} failing")).magenta())
+                .append(" test")
+                .append(if outdated == 1 { " was" } else { "s were" })
+                .append(" updated upstream since your last sync. Run ")
+                .append(style("sync-tests").cyan())
+                .append(" and try again.")
+        );
+    }
+    if let Some(seed) = seed.filter(|_| failures > 0 || errors > 0) {
+        info!(
+            "{}",
+            st("Tests ran in a shuffled order, use ")
+                .append(style(format!("--seed {seed}")).cyan())
+                .append(" to reproduce it")
+        );
+    }
+
+    Ok(failures == 0 && outdated == 0 && errors == 0)
+}
 
-    Ok(failures == 0 && errors == 0)
+/// Keeps only the tests of the category. Without a category, tests limited to any category besides
+/// the latest (defined by lexical order) are dropped.
+pub fn select_tests(tests: &mut Vec<FullTest>, category: Option<&str>) {
+    let mut categories = tests.iter().map(|it| &it.test.category).collect::<Vec<_>>();
+    categories.sort();
+    let newest_category = categories.last().map(|it| it.to_string());
+
+    if let Some(category) = category {
+        info!("Running only tests belonging to category `{}`", category);
+        tests.retain(|it| it.test.category == category);
+    } else if let Some(newest_category) = &newest_category {
+        info!(
+            "No category specified, running all tests. \
+            Ignoring tests limited to any category besides `{}` to prevent unwanted errors.",
+            newest_category
+        );
+        tests.retain(|it| !it.test.limited_to_category || it.test.category == *newest_category);
+    }
+}
+
+/// Runs a single test from the test directory, returning whether it passed
+pub fn run_local_test(
+    test_dir: &Path,
+    test_id: &str,
+    compiler_run: &Path,
+) -> Result<(bool, TestExecutionOutput), CrowClientError> {
+    run_test(CliRunTestArgs {
+        test_dir: test_dir.to_path_buf(),
+        test_id: test_id.to_string(),
+        compiler_run: compiler_run.to_path_buf(),
+    })
+    .map(|(success, res, _)| (success, res))
 }
 
 pub fn command_run_test(args: CliRunTestArgs) -> Result<bool, CrowClientError> {
-    let (success, res) = run_test(args)?;
+    let (success, res, invocation) = run_test(args)?;
 
     print_test_output(&res);
+    if !success {
+        print_test_invocation(&invocation);
+    }
 
     Ok(success)
 }
 
-fn run_test(args: CliRunTestArgs) -> Result<(bool, TestExecutionOutput), CrowClientError> {
+fn run_test(
+    args: CliRunTestArgs,
+) -> Result<(bool, TestExecutionOutput, TestInvocation), CrowClientError> {
     verify_test_dir(&args).context(RunTestSnafu)?;
 
     let test = find_test(&args.test_dir, &args.test_id).context(RunTestSnafu)?;
@@ -241,7 +345,7 @@ fn run_test(args: CliRunTestArgs) -> Result<(bool, TestExecutionOutput), CrowCli
         })
         .context(RunTestSnafu)?;
 
-    let res = execute_test(
+    let (res, invocation) = execute_test(
         &CompilerTest {
             test_id: test.test.id,
             category: test.test.category,
@@ -251,14 +355,28 @@ fn run_test(args: CliRunTestArgs) -> Result<(bool, TestExecutionOutput), CrowCli
             compile_command: vec![compiler_run_path.as_os_str().to_string_lossy().to_string()],
             binary_arguments: vec![],
             provisional_for_category: None,
+            compile_only: false,
+            profiles: Vec::new(),
+            // A single test runs regardless of its dependencies
+            depends_on: Vec::new(),
+            runner_prefix: Vec::new(),
+            permissions: ContainerPermissions::default(),
+            binary_environment: BinaryEnvironment::default(),
         },
         tempdir.path(),
-        &tempdir.path().join("out.🦆"),
+        &tempdir.path().join(OUTPUT_BINARY_NAME),
         tempdir.path(),
-        crate::util::execute_locally,
+        tempdir.path(),
+        |path, cmd, timeout, stdin| {
+            crate::util::execute_locally(path, cmd, timeout, stdin, tempdir.path())
+        },
     );
 
-    Ok((matches!(res, TestExecutionOutput::Success { .. }), res))
+    Ok((
+        matches!(res, TestExecutionOutput::Success { .. }),
+        res,
+        invocation,
+    ))
 }
 
 fn verify_test_dir(args: &CliRunTestArgs) -> Result<(), RunTestError> {
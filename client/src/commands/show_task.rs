@@ -0,0 +1,215 @@
+use crate::context::{CliContext, CliContextError};
+use crate::error::{CrowClientError, ShowTaskSnafu};
+use crate::util::{execution_output_to_string, print_test_invocation, print_test_output, st};
+use clap::Args;
+use console::style;
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Unit};
+use shared::{FinishedCompilerTask, FinishedExecution, FinishedTest, TestExecutionOutput, indent};
+use snafu::{Location, ResultExt, Snafu};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+use tracing::{error, info};
+
+#[derive(Debug, Snafu)]
+pub enum ShowTaskError {
+    #[snafu(display("Could not fetch task `{task_id}` at {location}"))]
+    FetchTask {
+        task_id: String,
+        source: CliContextError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not parse task `{task_id}` at {location}"))]
+    ParseTask {
+        task_id: String,
+        source: serde_json::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct CliShowTaskArgs {
+    /// The id of the task to show
+    task_id: String,
+    /// Only list failed tests, but include their full compiler and binary output
+    #[clap(long = "failed-only", default_value = "false")]
+    failed_only: bool,
+    /// Print the task as returned by the server, for use in scripts
+    #[clap(long, default_value = "false")]
+    json: bool,
+}
+
+pub fn command_show_task(args: CliShowTaskArgs, ctx: CliContext) -> Result<bool, CrowClientError> {
+    let raw_task = ctx
+        .get_task(&args.task_id)
+        .context(FetchTaskSnafu {
+            task_id: args.task_id.clone(),
+        })
+        .context(ShowTaskSnafu)?;
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&raw_task).expect("Unexpected json serialize error")
+        );
+        return Ok(true);
+    }
+
+    let task: FinishedCompilerTask = serde_json::from_value(raw_task)
+        .context(ParseTaskSnafu {
+            task_id: args.task_id.clone(),
+        })
+        .context(ShowTaskSnafu)?;
+
+    print_task_info(&task);
+
+    match &task {
+        FinishedCompilerTask::BuildFailed { build_output, .. } => {
+            error!(
+                "{}",
+                st(style("The build failed\n").bright().red())
+                    .append(style("Build output:\n").bold())
+                    .append(indent(&execution_output_to_string(build_output), 2))
+            );
+        }
+        FinishedCompilerTask::RanTests {
+            build_output,
+            tests,
+            ..
+        } => {
+            print_build_output(build_output);
+            print_tests(tests, args.failed_only);
+        }
+    }
+
+    Ok(true)
+}
+
+fn print_task_info(task: &FinishedCompilerTask) {
+    let info = task.info();
+    let runtime = info
+        .end
+        .duration_since(info.start)
+        .unwrap_or_default()
+        .as_secs();
+
+    info!(
+        "{}",
+        st(style("Task ").bold())
+            .append(style(&info.task_id).bold().bright().cyan())
+            .append(style("\n  Revision: ").dim())
+            .append(&info.revision_id)
+            .append(style("\n  Commit:   ").dim())
+            .append(info.commit_message.lines().next().unwrap_or_default())
+            .append(style("\n  Team:     ").dim())
+            .append(&info.team_id)
+            .append(style("\n  Started:  ").dim())
+            .append(format_time(info.start))
+            .append(style(format!(" (ran for {runtime}s)")).dim())
+    );
+}
+
+fn print_build_output(build_output: &FinishedExecution) {
+    info!(
+        "{}",
+        st(style("The build ").bold())
+            .append(style("succeeded").green().bright())
+            .append(" after ")
+            .append(build_output.runtime.as_secs().to_string())
+            .append("s")
+            .append(style("\nStdout:\n").bold())
+            .append(indent(build_output.stdout.trim(), 1))
+            .append(style("\nStderr:\n").bold())
+            .append(indent(build_output.stderr.trim(), 1))
+    );
+}
+
+fn print_tests(tests: &[FinishedTest], failed_only: bool) {
+    let mut by_category: BTreeMap<&str, Vec<&FinishedTest>> = BTreeMap::new();
+    for test in tests {
+        by_category
+            .entry(test.category.as_deref().unwrap_or("uncategorized"))
+            .or_default()
+            .push(test);
+    }
+
+    for (category, mut tests) in by_category {
+        tests.sort_by(|a, b| a.test_id.cmp(&b.test_id));
+        let passed = tests.iter().filter(|it| test_passed(it)).count();
+
+        println!();
+        info!(
+            "{}",
+            st(style(category).bold().bright().cyan()).append(
+                style(format!(" ({passed}/{} passed)", tests.len()))
+                    .bold()
+                    .fg(if passed == tests.len() {
+                        console::Color::Green
+                    } else {
+                        console::Color::Yellow
+                    })
+            )
+        );
+
+        for test in tests {
+            let passed = test_passed(test);
+            if failed_only && passed {
+                continue;
+            }
+
+            let provisional = match &test.provisional_for_category {
+                Some(category) => format!(" (provisional for {category})"),
+                None => String::new(),
+            };
+            if passed {
+                info!(
+                    "  {} {}{}",
+                    style("✓").green(),
+                    test.test_id,
+                    style(provisional).dim()
+                );
+            } else {
+                info!(
+                    "  {} {} {}{}",
+                    style("✗").red(),
+                    test.test_id,
+                    style(failure_reason(&test.output)).red(),
+                    style(provisional).dim()
+                );
+            }
+
+            if failed_only {
+                print_test_output(&test.output);
+                print_test_invocation(&test.invocation);
+            }
+        }
+    }
+}
+
+pub fn test_passed(test: &FinishedTest) -> bool {
+    matches!(test.output, TestExecutionOutput::Success { .. })
+}
+
+fn failure_reason(output: &TestExecutionOutput) -> &'static str {
+    match output {
+        TestExecutionOutput::BinaryFailed { .. } => "(binary failed)",
+        TestExecutionOutput::CompilerFailed { .. } => "(compiler failed)",
+        TestExecutionOutput::Error { .. } => "(error)",
+        TestExecutionOutput::Skipped { .. } => "(skipped)",
+        TestExecutionOutput::Success { .. } => "",
+    }
+}
+
+pub fn format_time(time: SystemTime) -> String {
+    match Timestamp::try_from(time) {
+        Ok(time) => time
+            .round(Unit::Second)
+            .unwrap_or(time)
+            .to_zoned(TimeZone::system())
+            .strftime("%Y-%m-%d %H:%M:%S")
+            .to_string(),
+        Err(_) => "unknown".to_string(),
+    }
+}
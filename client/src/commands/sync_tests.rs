@@ -1,12 +1,16 @@
-use crate::context::{CliContext, CliContextError, Test, TestDetail};
+use crate::context::{CliContext, CliContextError, RemoteTestDetail, Test, TestDetail};
 use crate::error::{ContextSnafu, CrowClientError, SyncTestsSnafu};
 use crate::formats::{FormatError, from_markdown, to_markdown};
+use crate::remote_cache::write_remote_cache;
 use crate::util::{infer_test_metadata_from_path, st};
 use clap::Args;
 use console::style;
 use dialoguer::Confirm;
 use dialoguer::theme::ColorfulTheme;
-use sha2::{Digest, Sha256};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::ThreadPoolBuilder;
+use rayon::prelude::*;
+use shared::hash::TEST_HASH_VERSION;
 use snafu::{IntoError, Location, NoneError, Report, ResultExt, Snafu, ensure, location};
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
@@ -14,6 +18,11 @@ use std::process::Command;
 use tracing::{debug, error, info, warn};
 use walkdir::WalkDir;
 
+/// The number of tests fetched per request, which keeps the URL short enough for proxies
+const DOWNLOAD_BATCH_SIZE: usize = 50;
+/// The number of requests in flight at the same time
+const DOWNLOAD_PARALLELISM: usize = 4;
+
 #[derive(Debug, Snafu)]
 pub enum SyncTestsError {
     #[snafu(display("The test directory `{}` is a file at {location}", test_dir.display()))]
@@ -94,9 +103,9 @@ pub enum SyncTestsError {
         #[snafu(implicit)]
         location: Location,
     },
-    #[snafu(display("Could not download test detail for `{test_id}` at {location}"))]
-    FetchTestDetail {
-        test_id: String,
+    #[snafu(display("Could not download the details of {count} tests at {location}"))]
+    FetchTestDetails {
+        count: usize,
         source: CliContextError,
         #[snafu(implicit)]
         location: Location,
@@ -154,10 +163,16 @@ pub fn command_sync_tests(
 
     let remote = ctx.get_remote_tests().context(ContextSnafu)?;
     let local = get_local_tests(&test_dir).context(SyncTestsSnafu)?;
+    if let Err(e) = write_remote_cache(&test_dir, &remote.tests) {
+        warn!("{}", style(Report::from_error(e)).yellow());
+    }
 
     create_category_dirs(&test_dir, &remote.categories.keys().collect::<Vec<_>>())
         .context(SyncTestsSnafu)?;
 
+    // Everything is downloaded at once below, so the requests can be batched
+    let mut to_download: Vec<&Test> = Vec::new();
+
     let remote_only: Vec<&Test> = get_remote_only_tests(&remote.tests, &local);
     if !remote_only.is_empty() {
         info!(
@@ -166,9 +181,7 @@ pub fn command_sync_tests(
             style("new").green(),
             if remote_only.len() == 1 { "" } else { "s" }
         );
-        for test in remote_only {
-            download_remote_test(&test_dir, test, &ctx).context(SyncTestsSnafu)?;
-        }
+        to_download.extend(&remote_only);
     }
 
     let remote_changed: Vec<&Test> = get_remote_changed_tests(&remote.tests, &local);
@@ -179,14 +192,22 @@ pub fn command_sync_tests(
             style("changed").magenta(),
             if remote_changed.len() == 1 { "" } else { "s" }
         );
-        for test in &remote_changed {
-            download_remote_test(&test_dir, test, &ctx).context(SyncTestsSnafu)?;
-        }
+        to_download.extend(&remote_changed);
     }
 
+    // With a different hash algorithm every test would look inconsistent
+    let hash_version_matches = remote.hash_version.unwrap_or(1) == TEST_HASH_VERSION;
+    if !hash_version_matches {
+        warn!(
+            "{}",
+            st("The server uses a different test hash version than this client. ")
+                .append("Skipping the local consistency check, please update your client.")
+        );
+    }
     let inconsistent = get_locally_inconsistent_tests(&local)
         .context(SyncTestsSnafu)?
         .into_iter()
+        .filter(|_| hash_version_matches)
         .filter(|local| {
             remote.tests.iter().any(|remote| {
                 remote.id == local.test.id
@@ -210,12 +231,12 @@ pub fn command_sync_tests(
                 .iter()
                 .find(|remote| &remote.id == id && &remote.category == category);
 
-            if let Some(test) = remote_test {
-                download_remote_test(&test_dir, test, &ctx).context(SyncTestsSnafu)?;
-            }
+            to_download.extend(remote_test);
         }
     }
 
+    download_remote_tests(&test_dir, &to_download, &ctx).context(SyncTestsSnafu)?;
+
     let deleted_any = delete_local_only_tests(&test_dir, &remote.tests).context(SyncTestsSnafu)?;
 
     if args.commit_changes {
@@ -402,35 +423,113 @@ fn create_category_dirs(test_dir: &Path, categories: &[&String]) -> Result<(), S
     Ok(())
 }
 
-fn download_remote_test(
+fn download_remote_tests(
     test_dir: &Path,
-    test: &Test,
+    tests: &[&Test],
     context: &CliContext,
 ) -> Result<(), SyncTestsError> {
-    info!(
-        "{}",
-        st("  Downloading `")
-            .append(style(&test.id).bold().green())
-            .append("`  ")
-            .append(style(&test.category).dim().green())
+    if tests.is_empty() {
+        return Ok(());
+    }
+
+    let progress = ProgressBar::new(tests.len() as u64).with_style(
+        ProgressStyle::with_template("  {bar:40.green/dim} {pos}/{len} {wide_msg:.dim}")
+            .expect("progress template is valid"),
     );
-    let test_dir = test_dir.join(&test.category);
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(DOWNLOAD_PARALLELISM)
+        .build()
+        .unwrap();
+
+    let res = pool.install(|| {
+        tests
+            .par_chunks(DOWNLOAD_BATCH_SIZE)
+            .try_for_each(|batch| download_batch(test_dir, batch, context, &progress))
+    });
+    progress.finish_and_clear();
+
+    res
+}
 
-    let detail = context
-        .get_test_detail(&test.id)
-        .context(FetchTestDetailSnafu {
-            test_id: test.id.clone(),
-        })?;
+fn download_batch(
+    test_dir: &Path,
+    batch: &[&Test],
+    context: &CliContext,
+    progress: &ProgressBar,
+) -> Result<(), SyncTestsError> {
+    let ids = batch.iter().map(|it| it.id.as_str()).collect::<Vec<_>>();
+    let details = context
+        .get_test_details(&ids)
+        .context(FetchTestDetailsSnafu { count: ids.len() })?;
+
+    for &test in batch {
+        progress.inc(1);
+        progress.set_message(test.id.clone());
+
+        let Some(remote) = details.iter().find(|it| it.id == test.id) else {
+            progress.suspend(|| {
+                warn!(
+                    "{}",
+                    st("  Skipping `")
+                        .append(style(&test.id).bold().yellow())
+                        .append("`, it was deleted on the server")
+                )
+            });
+            continue;
+        };
+        write_remote_test(test_dir, test, remote, progress)?;
+    }
+
+    Ok(())
+}
 
-    let test_path = test_dir.join(format!("{}.crow-test.md", test.id));
+fn write_remote_test(
+    test_dir: &Path,
+    test: &Test,
+    remote: &RemoteTestDetail,
+    progress: &ProgressBar,
+) -> Result<(), SyncTestsError> {
+    let test_path = test.path(test_dir);
 
-    std::fs::write(test_path, to_markdown(test, &detail)).context(WriteTestSnafu {
+    // The test list may be outdated by now and the file on disk may already be current
+    if is_current_on_disk(&test.category, &test_path, &remote.hash) {
+        debug!(test = %test.id, "Test is already up to date on disk");
+        return Ok(());
+    }
+
+    progress.suspend(|| {
+        info!(
+            "{}",
+            st("  Downloading `")
+                .append(style(&test.id).bold().green())
+                .append("`  ")
+                .append(style(&test.category).dim().green())
+        )
+    });
+
+    // Store the hash matching the downloaded details, the test may have changed since the listing
+    let test = Test {
+        hash: remote.hash.clone(),
+        ..test.clone()
+    };
+    std::fs::write(test_path, to_markdown(&test, &remote.detail)).context(WriteTestSnafu {
         test_id: test.id.clone(),
     })?;
 
     Ok(())
 }
 
+/// Whether the file already holds an unmodified copy of the test with the given hash
+fn is_current_on_disk(category: &str, test_path: &Path, hash: &str) -> bool {
+    if !test_path.is_file() {
+        return false;
+    }
+    match parse_test(category, test_path) {
+        Ok(local) => local.test.hash == hash && local.test.compute_hash(&local.detail) == hash,
+        Err(_) => false,
+    }
+}
+
 fn get_remote_only_tests<'a>(remote: &'a [Test], local: &[FullTest]) -> Vec<&'a Test> {
     remote
         .iter()
@@ -461,22 +560,7 @@ fn get_locally_inconsistent_tests(
     let mut inconsistent = Vec::new();
 
     for local in local_tests {
-        let test = &local.test;
-        let detail = &local.detail;
-
-        let compiler_modifiers = serde_json::to_string(&detail.compiler_modifiers)
-            .expect("Unexpected json serialize error");
-        let binary_modifiers = serde_json::to_string(&detail.binary_modifiers)
-            .expect("Unexpected json serialize error");
-
-        let mut hash = Sha256::new();
-        hash.update(compiler_modifiers.as_bytes());
-        hash.update(binary_modifiers.as_bytes());
-        hash.update(test.creator_id.to_string().as_bytes());
-        hash.update([test.admin_authored as u8]);
-        hash.update([test.limited_to_category as u8]);
-        hash.update(test.category.as_bytes());
-        let actual_hash = format!("{:x}", hash.finalize());
+        let actual_hash = local.test.compute_hash(&local.detail);
 
         if local.test.hash != actual_hash {
             inconsistent.push(local);
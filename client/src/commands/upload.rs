@@ -1,4 +1,7 @@
-use crate::context::{CliContext, CliContextError, RemoteTests, SetTestResponse, TestCategory};
+use crate::context::{
+    CliContext, CliContextError, RemoteTests, SetTestResponse, Test, TestAcceptance, TestCategory,
+    UserRole,
+};
 use crate::error::{ContextSnafu, CrowClientError, UploadTestSnafu};
 use crate::formats::{FormatError, details_from_markdown};
 use crate::util::{infer_test_metadata_from_path, print_test_output};
@@ -8,10 +11,11 @@ use dialoguer::theme::ColorfulTheme;
 use dialoguer::{Confirm, FuzzySelect, Input, Select};
 use jiff::Timestamp;
 use shared::validate_test_id;
+use shared::validation::{TestValidationInput, validate_test};
 use snafu::{IntoError, Location, NoneError, ResultExt, Snafu, location};
 use std::collections::HashMap;
 use std::path::PathBuf;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Debug, Snafu)]
 pub enum UploadTestError {
@@ -26,13 +30,6 @@ pub enum UploadTestError {
         #[snafu(implicit)]
         location: Location,
     },
-    #[snafu(display("Test name `{name}` invalid due to `{error}` at {location}"))]
-    TestName {
-        error: &'static str,
-        name: String,
-        #[snafu(implicit)]
-        location: Location,
-    },
     #[snafu(display("Error reading input file `{}` at {location}", path.display()))]
     ReadTest {
         path: PathBuf,
@@ -47,6 +44,17 @@ pub enum UploadTestError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Category `{category}` does not exist at {location}"))]
+    UnknownCategory {
+        category: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("The server would reject the test at {location}"))]
+    Validation {
+        #[snafu(implicit)]
+        location: Location,
+    },
     #[snafu(display("Error uploading test at {location}"))]
     Uploading {
         source: CliContextError,
@@ -74,6 +82,19 @@ pub struct CliUploadTestArgs {
     infer_metadata_from_input: Option<bool>,
 }
 
+impl CliUploadTestArgs {
+    /// Uploads the test under the given name and category, asking only about test tasting
+    pub fn new(test: PathBuf, name: String, category: String) -> Self {
+        Self {
+            test,
+            name: Some(name),
+            category: Some(category),
+            taste_test: None,
+            infer_metadata_from_input: None,
+        }
+    }
+}
+
 pub fn command_upload_test(
     mut args: CliUploadTestArgs,
     ctx: CliContext,
@@ -112,9 +133,30 @@ pub fn command_upload_test(
         None => prompt_test_name(&category, &remote_tests, &my_team).context(UploadTestSnafu)?,
     };
 
-    if let Err(e) = validate_test_id(&name) {
-        return Err(TestNameSnafu { error: e, name }.into_error(NoneError))
+    let detail = details_from_markdown(&args.test)
+        .context(ReadTestSnafu {
+            path: args.test.to_path_buf(),
+        })
+        .context(UploadTestSnafu)?;
+
+    let Some(category_meta) = remote_tests.categories.get(&category) else {
+        return Err(UnknownCategorySnafu { category }.into_error(NoneError))
             .context(UploadTestSnafu);
+    };
+    let problems = validate_test(&TestValidationInput {
+        id: &name,
+        compiler_modifiers: &detail.compiler_modifiers,
+        binary_modifiers: &detail.binary_modifiers,
+        depends_on: &detail.depends_on,
+        license: detail.license.as_deref(),
+        compile_only: category_meta.compile_only,
+    });
+    if !problems.is_empty() {
+        error!("The test is invalid:");
+        for problem in &problems {
+            error!("  Bad {problem}");
+        }
+        return Err(ValidationSnafu.into_error(NoneError)).context(UploadTestSnafu);
     }
 
     let should_taste_test = match args.taste_test {
@@ -122,22 +164,40 @@ pub fn command_upload_test(
         Some(val) => val,
     };
 
-    let detail = details_from_markdown(&args.test)
-        .context(ReadTestSnafu {
-            path: args.test.to_path_buf(),
-        })
-        .context(UploadTestSnafu)?;
+    // Mirror what the server will store, so it can reject the upload if our hashes disagree
+    let existing = remote_tests.tests.iter().find(|test| test.id == name);
+    let expected_test = Test {
+        id: name.clone(),
+        creator_id: existing.map_or(my_team.clone(), |it| it.creator_id.clone()),
+        admin_authored: existing
+            .map_or(myself.role == Some(UserRole::Admin), |it| it.admin_authored),
+        limited_to_category: existing.is_some_and(|it| it.limited_to_category),
+        category: category.clone(),
+        hash: String::new(),
+        last_updated: None,
+    };
+    let expected_hash = expected_test.compute_hash(&detail);
 
     let res = ctx
-        .upload_test(&name, &category, &detail, should_taste_test)
+        .upload_test(&name, &category, &detail, should_taste_test, &expected_hash)
         .context(UploadingSnafu)
         .context(UploadTestSnafu)?;
 
     Ok(match res {
-        SetTestResponse::TestAdded(_) => {
-            info!("Test uploaded {}", style("successfully").green().bright());
-            true
-        }
+        SetTestResponse::TestAdded(added) => match added.acceptance {
+            Some(TestAcceptance::Pending { message }) => {
+                warn!("Test uploaded, but it does not count yet: {message}");
+                true
+            }
+            Some(TestAcceptance::Rejected { message }) => {
+                error!("Test uploaded, but it does not count: {message}");
+                false
+            }
+            Some(TestAcceptance::Accepted) | None => {
+                info!("Test uploaded {}", style("successfully").green().bright());
+                true
+            }
+        },
         SetTestResponse::TastingFailed { output } => {
             error!("Test failed test tasting");
             print_test_output(&output);
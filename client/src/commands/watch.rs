@@ -0,0 +1,261 @@
+use crate::commands::run_test::{run_local_test, select_tests};
+use crate::commands::sync_tests::{FullTest, SyncTestsError, get_local_tests};
+use crate::error::{CrowClientError, WatchSnafu};
+use crate::util::{print_test_output, st};
+use clap::Args;
+use console::style;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use rayon::ThreadPoolBuilder;
+use snafu::{Location, Report, ResultExt, Snafu};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// File changes arriving this shortly after each other trigger a single run
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Snafu)]
+pub enum WatchError {
+    #[snafu(display("Could not watch `{}` at {location}", path.display()))]
+    WatchDirectory {
+        path: PathBuf,
+        source: notify::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not read the tests at {location}"))]
+    ReadTests {
+        source: SyncTestsError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not run the build command `{command}` at {location}"))]
+    RunBuild {
+        command: String,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct CliWatchArgs {
+    /// The source directory of your compiler. Changes in it trigger a new run.
+    #[clap(long = "source", short = 's')]
+    source_dir: PathBuf,
+    /// The directory containing all tests
+    #[clap(long = "test-dir", short = 'd')]
+    test_dir: PathBuf,
+    /// The run binary for your compiler
+    #[clap(long = "compiler-run", short = 'c')]
+    compiler_run: PathBuf,
+    /// A shell command run in the source directory to rebuild your compiler before the tests
+    #[clap(long = "build", short = 'b')]
+    build_command: Option<String>,
+    /// How many tests to run in parallel. 0 for processor count.
+    #[clap(long = "jobs", short = 'j', default_value = "0")]
+    parallelism: usize,
+    /// The category of tests to run. If not set defaults to all, but will ignore
+    /// tests limited to a category that is not the latest (defined by lexical order).
+    #[clap(long = "category", short = 'l')]
+    category: Option<String>,
+    /// Print the output of failing tests, not just their names
+    #[clap(long = "show-output", default_value = "false")]
+    show_output: bool,
+}
+
+pub fn command_watch(args: CliWatchArgs) -> Result<bool, CrowClientError> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)
+        .context(WatchDirectorySnafu {
+            path: args.source_dir.clone(),
+        })
+        .context(WatchSnafu)?;
+    watcher
+        .watch(&args.source_dir, RecursiveMode::Recursive)
+        .context(WatchDirectorySnafu {
+            path: args.source_dir.clone(),
+        })
+        .context(WatchSnafu)?;
+
+    // Only the tests that failed last time are rerun, until all of them pass
+    let mut failing: Option<HashSet<String>> = None;
+    let mut rebuild = true;
+
+    loop {
+        let res = run_round(&args, failing.as_ref(), rebuild).context(WatchSnafu);
+        rebuild = true;
+        match res {
+            Ok(Some(failed)) if failed.is_empty() && failing.is_some() => {
+                info!(
+                    "{}",
+                    st(style("All previously failing tests pass").green())
+                        .append(", running all tests")
+                );
+                failing = None;
+                rebuild = false;
+                continue;
+            }
+            Ok(Some(failed)) => failing = Some(failed).filter(|it| !it.is_empty()),
+            Ok(None) => {}
+            Err(e) => error!("\n{}", style(Report::from_error(e)).red()),
+        }
+
+        info!("{}", style("Waiting for changes...").dim());
+        // Our own build likely touched the source directory, which should not trigger a new run
+        drain_events(&rx);
+        if !wait_for_change(&rx) {
+            return Ok(true);
+        }
+    }
+}
+
+/// Rebuilds the compiler if requested and runs the tests, restricted to `only` if given. Returns
+/// the ids of the failing tests, or nothing if the build failed.
+fn run_round(
+    args: &CliWatchArgs,
+    only: Option<&HashSet<String>>,
+    rebuild: bool,
+) -> Result<Option<HashSet<String>>, WatchError> {
+    if let Some(build_command) = args.build_command.as_ref().filter(|_| rebuild) {
+        info!("{}", st("Running ").append(style(build_command).cyan()));
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(build_command)
+            .current_dir(&args.source_dir)
+            .output()
+            .context(RunBuildSnafu {
+                command: build_command.clone(),
+            })?;
+        if !output.status.success() {
+            error!(
+                "{}\n{}{}",
+                style("Build failed").red().bold(),
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+            return Ok(None);
+        }
+    }
+
+    let mut tests = get_local_tests(&args.test_dir).context(ReadTestsSnafu)?;
+    select_tests(&mut tests, args.category.as_deref());
+    if let Some(only) = only {
+        tests.retain(|it| only.contains(&it.test.id));
+    }
+
+    let start = Instant::now();
+    let test_count = tests.len();
+    let results = run_tests(args, tests);
+
+    let mut failed = HashSet::new();
+    for (test, res) in &results {
+        match res {
+            Ok((true, _)) => {}
+            Ok((false, output)) => {
+                if args.show_output {
+                    info!("{}", style(&test.test.id).bold().red());
+                    print_test_output(output);
+                }
+                failed.insert(test.test.id.clone());
+            }
+            Err(e) => {
+                if args.show_output {
+                    error!("\n{}", style(Report::from_error(e)).red());
+                }
+                failed.insert(test.test.id.clone());
+            }
+        }
+    }
+
+    print_summary(test_count, &failed, only.is_some(), start.elapsed());
+
+    Ok(Some(failed))
+}
+
+type TestResult = Result<(bool, shared::TestExecutionOutput), CrowClientError>;
+
+fn run_tests(args: &CliWatchArgs, tests: Vec<FullTest>) -> Vec<(FullTest, TestResult)> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(args.parallelism)
+        .build()
+        .unwrap();
+    let (tx, rx) = mpsc::channel();
+
+    for test in tests {
+        let tx = tx.clone();
+        let test_dir = args.test_dir.clone();
+        let compiler_run = args.compiler_run.clone();
+
+        pool.spawn(move || {
+            let res = run_local_test(&test_dir, &test.test.id, &compiler_run);
+            tx.send((test, res)).unwrap();
+        });
+    }
+    // Explicitly drop the sender to close the channel
+    drop(tx);
+
+    let mut results = rx.iter().collect::<Vec<_>>();
+    results.sort_by(|a, b| a.0.test.id.cmp(&b.0.test.id));
+    results
+}
+
+fn print_summary(test_count: usize, failed: &HashSet<String>, rerun: bool, elapsed: Duration) {
+    let passed = test_count - failed.len();
+    let mut summary = st(style(format!("✔ {passed} passed")).green())
+        .append("  ")
+        .append(if failed.is_empty() {
+            style(format!("✘ {} failed", failed.len())).dim()
+        } else {
+            style(format!("✘ {} failed", failed.len())).red().bold()
+        });
+    if rerun {
+        summary = summary.append(style("  (previously failing only)").dim());
+    }
+    summary = summary.append(style(format!("  in {:.1?}", elapsed)).dim());
+    info!("{}", summary);
+
+    if !failed.is_empty() {
+        let mut failed = failed.iter().map(String::as_str).collect::<Vec<_>>();
+        failed.sort_unstable();
+        info!("  {}", style(failed.join(", ")).red());
+    }
+}
+
+/// Blocks until a relevant file changed. Returns false if the watcher stopped.
+fn wait_for_change(rx: &Receiver<notify::Result<Event>>) -> bool {
+    loop {
+        match rx.recv() {
+            Ok(Ok(event)) if is_relevant(&event) => break,
+            Ok(_) => continue,
+            Err(_) => return false,
+        }
+    }
+
+    // Editors often write files in multiple steps, wait for them to settle
+    while let Ok(_event) = rx.recv_timeout(DEBOUNCE) {}
+
+    true
+}
+
+fn drain_events(rx: &Receiver<notify::Result<Event>>) {
+    while rx.try_recv().is_ok() {}
+}
+
+fn is_relevant(event: &Event) -> bool {
+    let changed = matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    );
+
+    changed && event.paths.iter().any(|it| !is_in_git_dir(it))
+}
+
+fn is_in_git_dir(path: &Path) -> bool {
+    path.components()
+        .any(|it| it == Component::Normal(".git".as_ref()))
+}
@@ -1,21 +1,28 @@
 use crate::auth::{BackendAuth, display_login};
+use flate2::Compression;
+use flate2::write::GzEncoder;
 use indicatif::ProgressBar;
-use jiff::Zoned;
 use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
 use reqwest::blocking::{Client, Response};
-use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE, HeaderMap, HeaderValue};
 use reqwest::{StatusCode, Url};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use shared::{TestExecutionOutput, TestModifier};
+use shared::hash::{TEST_HASH_VERSION, TestHashInput, hash_test};
+use shared::{FinishedTaskInfo, TestExecutionOutput, TestModifier};
 use snafu::{IntoError, Location, NoneError, ResultExt, Snafu};
 use std::collections::HashMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
+/// Test uploads larger than this are gzipped, as inputs of big programs compress well
+const COMPRESS_UPLOADS_ABOVE_BYTES: usize = 64 * 1024;
+
 #[derive(Debug, Snafu)]
 pub enum CliContextError {
     #[snafu(display("Request error at {location}"))]
@@ -30,6 +37,17 @@ pub enum CliContextError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display(
+        "{} (HTTP {status_code}, {}) at {location}",
+        payload.actionable_message(),
+        payload.code
+    ))]
+    Backend {
+        status_code: StatusCode,
+        payload: ErrorPayload,
+        #[snafu(implicit)]
+        location: Location,
+    },
     #[snafu(display(
         "Server replied with an unexpected code ({status_code}) saying {message} at {location}"
     ))]
@@ -47,6 +65,9 @@ pub struct CliContext {
     client: Client,
     backend_url: String,
     frontend_url: String,
+    /// The profile the auth belongs to, so login prompts name it
+    profile: Option<String>,
+    prompt_login: bool,
 }
 
 impl CliContext {
@@ -61,9 +82,23 @@ impl CliContext {
             client,
             backend_url,
             frontend_url,
+            profile: None,
+            prompt_login: true,
         }
     }
 
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Fails with the backend error instead of exiting with a login prompt if the stored auth is
+    /// rejected. Useful for optional requests the user did not directly ask for.
+    pub fn without_login_prompt(mut self) -> Self {
+        self.prompt_login = false;
+        self
+    }
+
     pub fn get_myself(&self) -> Result<Myself, CliContextError> {
         let res = self
             .client
@@ -87,9 +122,30 @@ impl CliContext {
         self.get_json_response(res)
     }
 
-    pub fn get_test_detail(&self, id: &str) -> Result<TestDetail, CliContextError> {
-        let mut url = Url::from_str(&format!("{}/tests", self.backend_url)).expect("url is valid");
+    /// Fetches the details and hashes of many tests in one request. Tests that were deleted on the
+    /// server in the meantime are missing from the result.
+    pub fn get_test_details(&self, ids: &[&str]) -> Result<Vec<RemoteTestDetail>, CliContextError> {
+        let mut url =
+            Url::from_str(&format!("{}/tests/details", self.backend_url)).expect("url is valid");
+        url.query_pairs_mut().append_pair("ids", &ids.join(","));
+
+        let res = self
+            .client
+            .get(url)
+            .headers(self.get_headers())
+            .send()
+            .context(ReqwestSnafu)?;
+
+        let response: TestDetailsResponse = self.get_json_response(res)?;
+        Ok(response.tests)
+    }
+
+    /// Fetches a finished task with the full output of its tests as raw JSON, so callers can print
+    /// it verbatim
+    pub fn get_task(&self, id: &str) -> Result<serde_json::Value, CliContextError> {
+        let mut url = Url::from_str(&format!("{}/tasks", self.backend_url)).expect("url is valid");
         url.path_segments_mut().expect("url is a base url").push(id);
+        url.query_pairs_mut().append_pair("include_output", "true");
 
         let res = self
             .client
@@ -101,12 +157,105 @@ impl CliContext {
         self.get_json_response(res)
     }
 
+    /// Fetches the summaries of the most recent tasks of the own team, newest first
+    pub fn get_recent_tasks(&self, count: u32) -> Result<Vec<TaskSummary>, CliContextError> {
+        let res = self
+            .client
+            .get(format!("{}/team/recent-tasks/{count}", self.backend_url))
+            .headers(self.get_headers())
+            .send()
+            .context(ReqwestSnafu)?;
+
+        self.get_json_response(res)
+    }
+
+    /// Fetches how the test results of the head task differ from the ones of the base task
+    pub fn compare_tasks(&self, base: &str, head: &str) -> Result<TaskComparison, CliContextError> {
+        let res = self
+            .client
+            .get(format!("{}/tasks/compare", self.backend_url))
+            .query(&[("base", base), ("head", head)])
+            .headers(self.get_headers())
+            .send()
+            .context(ReqwestSnafu)?;
+
+        self.get_json_response(res)
+    }
+
+    /// Fetches the task that will be graded for each category
+    pub fn get_final_tasks(&self) -> Result<HashMap<String, FinalTask>, CliContextError> {
+        let res = self
+            .client
+            .get(format!("{}/team/final-tasks", self.backend_url))
+            .headers(self.get_headers())
+            .send()
+            .context(ReqwestSnafu)?;
+
+        self.get_json_response(res)
+    }
+
+    /// Submits the task for exactly the given categories. Categories it was submitted for before
+    /// and that are missing fall back to the automatic selection.
+    pub fn set_final_task(
+        &self,
+        task_id: &str,
+        categories: &[String],
+    ) -> Result<(), CliContextError> {
+        let res = self
+            .client
+            .put(format!("{}/team/final-tasks", self.backend_url))
+            .headers(self.get_headers())
+            .json(&serde_json::json!({
+                "taskId": task_id,
+                "categories": categories,
+            }))
+            .send()
+            .context(ReqwestSnafu)?;
+
+        if res.status() == StatusCode::OK {
+            return Ok(());
+        }
+        // Errors are JSON, let the usual handling report them
+        self.get_json_response(res)
+    }
+
+    /// Fetches the gzipped reproducibility bundle of a test run in a task
+    pub fn get_repro_bundle(
+        &self,
+        task_id: &str,
+        test_id: &str,
+        profile: Option<&str>,
+    ) -> Result<Vec<u8>, CliContextError> {
+        let mut url = Url::from_str(&format!("{}/tasks", self.backend_url)).expect("url is valid");
+        url.path_segments_mut()
+            .expect("url is a base url")
+            .extend([task_id, "repro", test_id]);
+        if let Some(profile) = profile {
+            url.query_pairs_mut().append_pair("profile", profile);
+        }
+
+        let res = self
+            .client
+            .get(url)
+            .headers(self.get_headers())
+            .send()
+            .context(ReqwestSnafu)?;
+
+        if res.status() != StatusCode::OK {
+            // Errors are JSON, let the usual handling report them
+            return self.get_json_response(res);
+        }
+
+        Ok(res.bytes().context(ReqwestSnafu)?.to_vec())
+    }
+
     pub fn upload_test(
         &self,
         id: &str,
         category: &str,
         detail: &TestDetail,
         should_taste_test: bool,
+        expected_hash: &str,
     ) -> Result<SetTestResponse, CliContextError> {
         let url = Url::from_str(&format!("{}/tests/", self.backend_url))
             .expect("url is valid")
@@ -126,17 +275,35 @@ impl CliContext {
                 spinner.finish_with_message("Request completed");
             });
             let computing_task = s.spawn(move || {
-                self.client
+                let payload = serde_json::json!({
+                    "compilerModifiers": detail.compiler_modifiers,
+                    "binaryModifiers": detail.binary_modifiers,
+                    "category": category,
+                    "ignoreTestTasting": !should_taste_test,
+                    "timeoutMs": detail.timeout_ms,
+                    "dependsOn": detail.depends_on,
+                    "practice": detail.practice,
+                    "license": detail.license,
+                    "hash": expected_hash,
+                    "hashVersion": TEST_HASH_VERSION,
+                });
+                let payload =
+                    serde_json::to_vec(&payload).expect("Unexpected json serialize error");
+
+                let request = self
+                    .client
                     .put(url)
                     .headers(self.get_headers())
-                    .json(&serde_json::json!({
-                        "compilerModifiers": detail.compiler_modifiers,
-                        "binaryModifiers": detail.binary_modifiers,
-                        "category": category,
-                        "ignoreTestTasting": !should_taste_test,
-                    }))
-                    .send()
-                    .context(ReqwestSnafu)
+                    .header(CONTENT_TYPE, "application/json");
+                let request = if payload.len() > COMPRESS_UPLOADS_ABOVE_BYTES {
+                    request
+                        .header(CONTENT_ENCODING, "gzip")
+                        .body(gzip(&payload))
+                } else {
+                    request.body(payload)
+                };
+
+                request.send().context(ReqwestSnafu)
             });
             let res = computing_task.join().expect("computing task panicked");
             show_progress_bar.store(false, Ordering::Release);
@@ -164,26 +331,87 @@ impl CliContext {
         &self,
         response: Response,
     ) -> Result<T, CliContextError> {
-        if response.status() == StatusCode::UNAUTHORIZED {
-            display_login(&self.frontend_url)
+        if self.prompt_login && response.status() == StatusCode::UNAUTHORIZED {
+            display_login(&self.frontend_url, self.profile.as_deref())
         }
         if response.status() == StatusCode::OK {
             return response.json::<T>().context(DeserializationSnafu);
         }
 
+        let status_code = response.status();
+        let message = response.text().unwrap_or("N/A".to_string());
+
+        if let Ok(payload) = serde_json::from_str::<ErrorPayload>(&message) {
+            return Err(BackendSnafu {
+                status_code,
+                payload,
+            }
+            .into_error(NoneError));
+        }
+
         Err(BackendStatusCodeSnafu {
-            status_code: response.status(),
-            message: response.text().unwrap_or("N/A".to_string()),
+            status_code,
+            message,
         }
         .into_error(NoneError))
     }
 }
 
+/// The structured error body the backend sends for every failed request
+#[derive(Debug, Deserialize)]
+pub struct ErrorPayload {
+    pub code: String,
+    pub message: String,
+    #[serde(default)]
+    pub context: HashMap<String, serde_json::Value>,
+}
+
+impl ErrorPayload {
+    fn context_str(&self, key: &str) -> Option<&str> {
+        self.context.get(key).and_then(|it| it.as_str())
+    }
+
+    /// Renders the error in a way that tells the user what they can do about it
+    pub fn actionable_message(&self) -> String {
+        match self.code.as_str() {
+            "unauthorized" | "invalid_credentials" => {
+                "Your login is invalid or expired. Please log in again".to_string()
+            }
+            "named_unauthorized" => match self.context_str("what") {
+                Some(what) => format!("You are not allowed to {what}"),
+                None => self.message.clone(),
+            },
+            "not_in_team" => {
+                "You are not in a team yet. Ask your tutor to add you to one".to_string()
+            }
+            "named_not_found" => match self.context_str("what") {
+                Some(what) => format!("{what} could not be found"),
+                None => self.message.clone(),
+            },
+            "named_bad_request" | "invalid_json" | "invalid_path" | "invalid_query" => {
+                format!("The server rejected the request: {}", self.message)
+            }
+            "git_error" => format!(
+                "The server could not access your team repository: {}",
+                self.message
+            ),
+            "sql_error" | "internal_error" => format!(
+                "The server encountered an internal error, please contact an admin: {}",
+                self.message
+            ),
+            _ => self.message.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RemoteTests {
     pub tests: Vec<Test>,
     pub categories: HashMap<String, TestCategory>,
+    /// Missing for servers predating hash versioning
+    #[serde(default)]
+    pub hash_version: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -195,6 +423,8 @@ pub struct TestCategory {
     pub labs_end_at: Zoned,
     #[serde(deserialize_with = "zoned_as_millis")]
     pub _tests_end_at: Zoned,
+    #[serde(default)]
+    pub compile_only: bool,
 }
 fn zoned_as_millis<'de, D>(deserializer: D) -> Result<Zoned, D::Error>
 where
@@ -204,7 +434,7 @@ where
     Ok(res.to_zoned(TimeZone::system()))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Test {
     pub id: String,
@@ -214,6 +444,10 @@ pub struct Test {
     #[serde(skip_serializing, default)]
     pub category: String,
     pub hash: String,
+    /// Only known for tests fetched from the server
+    #[serde(skip_serializing, default)]
+    #[serde(deserialize_with = "jiff::fmt::serde::timestamp::millisecond::optional::deserialize")]
+    pub last_updated: Option<Timestamp>,
 }
 
 impl Test {
@@ -225,6 +459,22 @@ impl Test {
     pub fn local_file_paths(&self, root: &Path) -> Vec<PathBuf> {
         vec![self.path(root)]
     }
+
+    /// Computes the hash the server would assign to this test with the given details
+    pub fn compute_hash(&self, detail: &TestDetail) -> String {
+        hash_test(&TestHashInput {
+            compiler_modifiers: &detail.compiler_modifiers,
+            binary_modifiers: &detail.binary_modifiers,
+            owner: &self.creator_id,
+            admin_authored: self.admin_authored,
+            limited_to_category: self.limited_to_category,
+            category: &self.category,
+            timeout_ms: detail.timeout_ms,
+            depends_on: &detail.depends_on,
+            practice: detail.practice,
+            license: detail.license.as_deref(),
+        })
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -232,6 +482,33 @@ impl Test {
 pub struct TestDetail {
     pub compiler_modifiers: Vec<TestModifier>,
     pub binary_modifiers: Vec<TestModifier>,
+    /// Overrides the global test timeout, set by admins
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Tests that must pass before this one runs
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Practice tests are only run locally, never by the server
+    #[serde(default)]
+    pub practice: bool,
+    /// The license and attribution of the test
+    #[serde(default)]
+    pub license: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteTestDetail {
+    pub id: String,
+    pub hash: String,
+    #[serde(flatten)]
+    pub detail: TestDetail,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TestDetailsResponse {
+    tests: Vec<RemoteTestDetail>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -239,6 +516,14 @@ pub struct TestDetail {
 pub struct Myself {
     pub display_name: String,
     pub team: Option<String>,
+    #[serde(default)]
+    pub role: Option<UserRole>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum UserRole {
+    Regular,
+    Admin,
 }
 
 #[derive(Debug, Deserialize)]
@@ -247,9 +532,110 @@ pub struct MyselfResponse {
     pub user: Myself,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum TaskSummary {
+    #[serde(rename_all = "camelCase")]
+    BuildFailed { info: FinishedTaskInfo },
+    #[serde(rename_all = "camelCase")]
+    RanTests {
+        info: FinishedTaskInfo,
+        statistics: TaskStatistics,
+    },
+}
+
+impl TaskSummary {
+    pub fn info(&self) -> &FinishedTaskInfo {
+        match self {
+            Self::BuildFailed { info } => info,
+            Self::RanTests { info, .. } => info,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskStatistics {
+    pub success: TestCount,
+    pub total: TestCount,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCount {
+    pub total: usize,
+}
+
+/// How the test results of two tasks differ
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskComparison {
+    pub base: TaskSummary,
+    pub head: TaskSummary,
+    pub regressed: Vec<TestStatusChange>,
+    pub newly_passing: Vec<TestStatusChange>,
+    pub changed: Vec<TestStatusChange>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestStatusChange {
+    pub test_id: String,
+    pub profile: Option<String>,
+    /// Absent if the task did not run the test
+    pub base: Option<String>,
+    pub head: Option<String>,
+}
+
+/// How the task that is graded for a category was chosen
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum FinalTask {
+    #[serde(rename_all = "camelCase")]
+    AutomaticallySelected { summary: TaskSummary },
+    #[serde(rename_all = "camelCase")]
+    ManuallyOverridden { summary: TaskSummary },
+    #[serde(rename_all = "camelCase")]
+    Finalized { summary: TaskSummary },
+}
+
+impl FinalTask {
+    pub fn summary(&self) -> &TaskSummary {
+        match self {
+            Self::AutomaticallySelected { summary } => summary,
+            Self::ManuallyOverridden { summary } => summary,
+            Self::Finalized { summary } => summary,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(tag = "type")]
+#[allow(clippy::large_enum_variant)] // parsed once per upload, the size does not matter
 pub enum SetTestResponse {
-    TestAdded(#[allow(dead_code)] serde_json::Value),
+    TestAdded(AddedTest),
     TastingFailed { output: TestExecutionOutput },
 }
+
+#[derive(Deserialize)]
+pub struct AddedTest {
+    /// Absent on servers that accept every uploaded test
+    #[serde(default)]
+    pub acceptance: Option<TestAcceptance>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum TestAcceptance {
+    Accepted,
+    Pending { message: String },
+    Rejected { message: String },
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(data)
+        .expect("writing to a vec does not fail");
+    encoder.finish().expect("writing to a vec does not fail")
+}
@@ -1,8 +1,16 @@
 use super::auth::AuthError;
 use super::commands::sync_tests::SyncTestsError;
 use super::context::CliContextError;
+use crate::commands::compare::CompareError;
+use crate::commands::final_submit::FinalSubmitError;
+use crate::commands::new_test::NewTestError;
+use crate::commands::report::ReportError;
+use crate::commands::repro::ReproError;
 use crate::commands::run_test::RunTestError;
+use crate::commands::show_task::ShowTaskError;
 use crate::commands::upload::UploadTestError;
+use crate::commands::watch::WatchError;
+use crate::profile::ProfileError;
 use snafu::{Location, Snafu};
 
 #[derive(Debug, Snafu)]
@@ -44,6 +52,60 @@ pub enum CrowClientError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Error creating a test at {location}"))]
+    NewTest {
+        source: NewTestError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Error showing a task at {location}"))]
+    ShowTask {
+        source: ShowTaskError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Error submitting a task at {location}"))]
+    FinalSubmit {
+        source: FinalSubmitError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Error comparing tasks at {location}"))]
+    Compare {
+        source: CompareError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Error generating a report at {location}"))]
+    GenerateReport {
+        source: ReportError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Error reproducing a test run at {location}"))]
+    Repro {
+        source: ReproError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Error writing completions or the man page at {location}"))]
+    Completions {
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Error managing profiles at {location}"))]
+    Profile {
+        source: ProfileError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Error watching for changes at {location}"))]
+    Watch {
+        source: WatchError,
+        #[snafu(implicit)]
+        location: Location,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, CrowClientError>;
@@ -1,11 +1,11 @@
 use crate::context::{Test, TestDetail};
 use indexmap::IndexMap;
-use markdown::mdast::{Code, Text};
+use markdown::mdast::Code;
 use markdown::{ParseOptions, mdast};
-use mdast::{Heading, Node, Root};
-use shared::{CompilerFailReason, CrashSignal, TestModifier};
+use mdast::{Heading, Node};
+use shared::markdown::{Keys, TestMarkdownInput, test_to_markdown};
+use shared::{CompilerFailReason, CrashSignal, TestModifier, normalize_line_endings};
 use snafu::{IntoError, Location, NoneError, ResultExt, Snafu, ensure, location};
-use std::fmt::{Display, Formatter};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Snafu)]
@@ -43,46 +43,18 @@ pub enum FormatError {
     },
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum Keys {
-    CompilerModifiers,
-    BinaryModifiers,
-    Meta,
-    Hash,
-    Creator,
-    AdminAuthored,
-    LimitedToCategory,
-}
-
-impl Display for Keys {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::CompilerModifiers => write!(f, "Executing your compiler"),
-            Self::BinaryModifiers => write!(f, "Executing the compiled binary"),
-            Self::Meta => write!(f, "Meta"),
-            Self::Hash => write!(f, "Hash"),
-            Self::Creator => write!(f, "Creator"),
-            Self::AdminAuthored => write!(f, "Admin Authored"),
-            Self::LimitedToCategory => write!(f, "Limited to Category"),
-        }
-    }
-}
-
 pub fn from_markdown(
     path: &Path,
     category: String,
     id: String,
 ) -> Result<(Test, TestDetail), FormatError> {
-    let file = std::fs::read_to_string(path).context(FileReadSnafu {
-        path: path.to_path_buf(),
-    })?;
+    let file = read_normalized(path)?;
     let file = markdown::to_mdast(&file, &ParseOptions::default()).unwrap();
     let nodes_to_process = file.children().unwrap_or(&Vec::new()).clone();
 
     let mut nodes = associate_to_headings(nodes_to_process)?;
 
-    let meta = extract_key_values(extract_heading(Keys::Meta, &mut nodes)?, |_| true)?;
-    let mut meta = IndexMap::from_iter(meta);
+    let mut meta = extract_meta(extract_heading(Keys::Meta, &mut nodes)?)?;
     let hash = extract_value(Keys::Hash, &mut meta)?;
     let creator_id = extract_value(Keys::Creator, &mut meta)?;
     let admin_authored = extract_value(Keys::AdminAuthored, &mut meta)?
@@ -110,6 +82,7 @@ pub fn from_markdown(
         category,
         admin_authored,
         limited_to_category,
+        last_updated: None,
     };
     let test_detail = details_from_markdown(path)?;
 
@@ -117,9 +90,7 @@ pub fn from_markdown(
 }
 
 pub fn details_from_markdown(path: &Path) -> Result<TestDetail, FormatError> {
-    let file = std::fs::read_to_string(path).context(FileReadSnafu {
-        path: path.to_path_buf(),
-    })?;
+    let file = read_normalized(path)?;
     let file = markdown::to_mdast(&file, &ParseOptions::default()).unwrap();
     let nodes_to_process = file.children().unwrap_or(&Vec::new()).clone();
 
@@ -129,12 +100,63 @@ pub fn details_from_markdown(path: &Path) -> Result<TestDetail, FormatError> {
         extract_modifiers(extract_heading(Keys::CompilerModifiers, &mut nodes)?)?;
     let binary_modifiers = extract_modifiers(extract_heading(Keys::BinaryModifiers, &mut nodes)?)?;
 
+    // Tests written by hand do not need a meta section
+    let mut meta = match nodes.shift_remove(&Keys::Meta.to_string()) {
+        Some(meta) => extract_meta(meta)?,
+        None => IndexMap::new(),
+    };
+    let timeout_ms = extract_value(Keys::Timeout, &mut meta)
+        .ok()
+        .map(|it| {
+            it.trim().parse::<u64>().map_err(|e| {
+                MalformedModifierSnafu {
+                    message: format!("Could not parse timeout in milliseconds: {e}"),
+                }
+                .into_error(NoneError)
+            })
+        })
+        .transpose()?;
+    let depends_on = extract_value(Keys::DependsOn, &mut meta)
+        .map(|it| {
+            it.split(',')
+                .map(str::trim)
+                .filter(|it| !it.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    let practice = extract_value(Keys::Practice, &mut meta)
+        .unwrap_or("false".to_string())
+        .parse::<bool>()
+        .map_err(|e| {
+            MalformedModifierSnafu {
+                message: format!("Could not parse practice: {e}"),
+            }
+            .into_error(NoneError)
+        })?;
+    let license = extract_value(Keys::License, &mut meta)
+        .ok()
+        .map(|it| it.trim().to_string());
+
     Ok(TestDetail {
         compiler_modifiers,
         binary_modifiers,
+        timeout_ms,
+        depends_on,
+        practice,
+        license,
     })
 }
 
+/// Reads a test file, converting Windows line endings so they do not end up in modifiers
+fn read_normalized(path: &Path) -> Result<String, FormatError> {
+    let file = std::fs::read_to_string(path).context(FileReadSnafu {
+        path: path.to_path_buf(),
+    })?;
+
+    Ok(normalize_line_endings(&file))
+}
+
 fn associate_to_headings(nodes: Vec<Node>) -> Result<IndexMap<String, Vec<Node>>, FormatError> {
     let mut result = IndexMap::new();
     let mut current_batch = Vec::new();
@@ -192,18 +214,27 @@ fn extract_heading(
 fn extract_modifiers(nodes: Vec<Node>) -> Result<Vec<TestModifier>, FormatError> {
     let mut result = vec![];
 
-    for (name, val) in extract_key_values(nodes, modifier_requires_argument)? {
-        result.push(modifier_from_string(&name, val)?);
+    for (name, values) in extract_key_values(nodes, modifier_requires_argument)? {
+        result.push(modifier_from_string(&name, values)?);
     }
 
     Ok(result)
 }
 
+fn extract_meta(nodes: Vec<Node>) -> Result<IndexMap<String, Option<String>>, FormatError> {
+    Ok(extract_key_values(nodes, |_| true)?
+        .into_iter()
+        .map(|(key, values)| (key, values.into_iter().next()))
+        .collect())
+}
+
+/// Pairs each heading with the code blocks below it. Headings that need a value but have none
+/// are dropped.
 fn extract_key_values(
     mut nodes: Vec<Node>,
     needs_value: impl Fn(&str) -> bool,
-) -> Result<Vec<(String, Option<String>)>, FormatError> {
-    let mut result: Vec<(String, Option<String>)> = Vec::new();
+) -> Result<Vec<(String, Vec<String>)>, FormatError> {
+    let mut result: Vec<(String, Vec<String>)> = Vec::new();
 
     while !nodes.is_empty() {
         let node = nodes.remove(0);
@@ -215,17 +246,27 @@ fn extract_key_values(
             let header = header.trim().to_string();
 
             if !needs_value(&header) {
-                result.push((header, None));
+                result.push((header, Vec::new()));
                 continue;
             }
 
+            let mut values = Vec::new();
             while !nodes.is_empty() {
                 let node = nodes.remove(0);
                 if let Node::Code(Code { value, .. }) = node {
-                    result.push((header, Some(value)));
+                    values.push(value);
                     break;
                 }
             }
+            // Modifiers with several values have one code block after another
+            while matches!(nodes.first(), Some(Node::Code(_))) {
+                if let Node::Code(Code { value, .. }) = nodes.remove(0) {
+                    values.push(value);
+                }
+            }
+            if !values.is_empty() {
+                result.push((header, values));
+            }
         }
     }
 
@@ -246,99 +287,22 @@ fn extract_value(
 }
 
 pub fn to_markdown(test: &Test, detail: &TestDetail) -> String {
-    let mut root = Root {
-        children: vec![],
-        position: None,
-    };
-
-    root.children.extend(modifiers_to_markdown(
-        Keys::CompilerModifiers.to_string(),
-        &detail.compiler_modifiers,
-    ));
-    root.children.extend(modifiers_to_markdown(
-        Keys::BinaryModifiers.to_string(),
-        &detail.binary_modifiers,
-    ));
-
-    root.children
-        .extend(write_heading_value(&Keys::Meta.to_string(), 1, None));
-
-    root.children.extend(write_heading_value(
-        &Keys::LimitedToCategory.to_string(),
-        2,
-        Some(test.limited_to_category.to_string()),
-    ));
-
-    root.children.extend(write_heading_value(
-        &Keys::Creator.to_string(),
-        2,
-        Some(test.creator_id.clone()),
-    ));
-
-    root.children.extend(write_heading_value(
-        &Keys::AdminAuthored.to_string(),
-        2,
-        Some(test.admin_authored.to_string()),
-    ));
-
-    root.children.extend(write_heading_value(
-        &Keys::Hash.to_string(),
-        2,
-        Some(test.hash.clone()),
-    ));
-
-    mdast_util_to_markdown::to_markdown(&Node::Root(root)).expect("Could convert to markdown")
-}
-
-fn modifiers_to_markdown(heading: String, modifiers: &[TestModifier]) -> Vec<Node> {
-    let mut result = write_heading_value(&heading, 1, None);
-
-    result.extend(modifiers.iter().flat_map(modifier_to_markdown));
-
-    result
-}
-
-fn modifier_to_markdown(modifier: &TestModifier) -> Vec<Node> {
-    write_heading_value(modifier.name(), 2, modifier_arg_to_string(modifier))
-}
-
-fn modifier_arg_to_string(modifier: &TestModifier) -> Option<String> {
-    match modifier {
-        TestModifier::ExitCode { code } => Some(code.to_string()),
-        TestModifier::ExpectedOutput { output } => Some(output.to_string()),
-        TestModifier::ProgramArgument { arg } => Some(arg.to_string()),
-        TestModifier::ProgramArgumentFile { contents } => Some(contents.to_string()),
-        TestModifier::ProgramInput { input } => Some(input.to_string()),
-        TestModifier::ShouldCrash { signal } => Some(signal.to_string()),
-        TestModifier::ShouldFail { reason } => Some(reason.to_string()),
-        TestModifier::ShouldSucceed => None,
-        TestModifier::ShouldTimeout => None,
-    }
-}
-
-fn write_heading_value(heading: &str, depth: u8, value: Option<String>) -> Vec<Node> {
-    let mut res = vec![Node::Heading(Heading {
-        depth,
-        children: vec![Node::Text(Text {
-            value: heading.to_string(),
-            position: None,
-        })],
-        position: None,
-    })];
-
-    if let Some(value) = value {
-        res.push(Node::Code(Code {
-            value,
-            lang: None,
-            meta: None,
-            position: None,
-        }));
-    }
-
-    res
+    test_to_markdown(&TestMarkdownInput {
+        compiler_modifiers: &detail.compiler_modifiers,
+        binary_modifiers: &detail.binary_modifiers,
+        limited_to_category: test.limited_to_category,
+        admin_authored: test.admin_authored,
+        timeout_ms: detail.timeout_ms,
+        depends_on: &detail.depends_on,
+        practice: detail.practice,
+        license: detail.license.as_deref(),
+        creator: &test.creator_id,
+        hash: &test.hash,
+    })
 }
 
-fn modifier_from_string(type_: &str, value: Option<String>) -> Result<TestModifier, FormatError> {
+fn modifier_from_string(type_: &str, values: Vec<String>) -> Result<TestModifier, FormatError> {
+    let value = values.first().cloned();
     let res = match type_ {
         "ExitCode" => {
             let value = require_value("ExitCode", value)?;
@@ -354,6 +318,30 @@ fn modifier_from_string(type_: &str, value: Option<String>) -> Result<TestModifi
         "ExpectedOutput" => TestModifier::ExpectedOutput {
             output: require_value("ExpectedOutput", value)?,
         },
+        "ExpectedOutputAnyOf" => TestModifier::ExpectedOutputAnyOf { outputs: values },
+        "ExpectedFileOutput" | "ExpectedFile" => {
+            // The first line is the path, everything after it the contents
+            let value = require_value("ExpectedFileOutput", value)?;
+            let (path, contents) = value.split_once('\n').unwrap_or((&value, ""));
+            TestModifier::ExpectedFileOutput {
+                path: path.trim().to_string(),
+                contents: contents.to_string(),
+            }
+        }
+        "FloatTolerance" => {
+            let value = require_value("FloatTolerance", value)?;
+            TestModifier::FloatTolerance {
+                tolerance: value.trim().parse::<f64>().map_err(|e| {
+                    MalformedModifierSnafu {
+                        message: format!("Could not parse float tolerance: {e}"),
+                    }
+                    .into_error(NoneError)
+                })?,
+            }
+        }
+        "IgnoreLineEndings" => TestModifier::IgnoreLineEndings,
+        "IgnoreTrailingWhitespace" => TestModifier::IgnoreTrailingWhitespace,
+        "IgnoreTrailingNewlines" => TestModifier::IgnoreTrailingNewlines,
         "ProgramArgument" => TestModifier::ProgramArgument {
             arg: require_value("ProgramArgument", value)?,
         },
@@ -371,6 +359,7 @@ fn modifier_from_string(type_: &str, value: Option<String>) -> Result<TestModifi
         },
         "ShouldSucceed" => TestModifier::ShouldSucceed,
         "ShouldTimeout" => TestModifier::ShouldTimeout,
+        "UnorderedLines" => TestModifier::UnorderedLines,
         _ => {
             return Err(FormatError::MalformedModifier {
                 message: format!("Unknown modifier type `{type_}`"),
@@ -422,5 +411,13 @@ fn parse_fail_reason(val: &str) -> Result<CompilerFailReason, FormatError> {
 }
 
 fn modifier_requires_argument(modifier: &str) -> bool {
-    modifier != "ShouldSucceed" && modifier != "ShouldTimeout"
+    !matches!(
+        modifier,
+        "ShouldSucceed"
+            | "ShouldTimeout"
+            | "IgnoreLineEndings"
+            | "IgnoreTrailingWhitespace"
+            | "IgnoreTrailingNewlines"
+            | "UnorderedLines"
+    )
 }
@@ -6,20 +6,35 @@ mod commands;
 mod context;
 mod error;
 mod formats;
+mod profile;
+mod project;
+mod remote_cache;
 mod util;
 
 use self::error::Result;
-use crate::auth::get_stored_auth;
+use crate::auth::{get_stored_auth, try_get_stored_auth};
+use crate::commands::compare::{CliCompareArgs, command_compare};
+use crate::commands::completions::{CliCompletionsArgs, command_completions};
+use crate::commands::final_submit::{CliFinalSubmitArgs, command_final_submit};
 use crate::commands::login::command_login;
+use crate::commands::new_test::{CliNewTestArgs, command_new_test};
+use crate::commands::profiles::{CliProfilesArgs, command_profiles};
+use crate::commands::report::{CliReportArgs, command_report};
+use crate::commands::repro::{CliReproArgs, command_repro};
 use crate::commands::run_test::{CliRunTestArgs, CliRunTestsArgs};
+use crate::commands::show_task::{CliShowTaskArgs, command_show_task};
 use crate::commands::sync_tests::{CliSyncTestsArgs, command_sync_tests};
 use crate::commands::upload::CliUploadTestArgs;
+use crate::commands::watch::{CliWatchArgs, command_watch};
 use crate::context::CliContext;
 use crate::error::AuthSnafu;
+use crate::profile::{ProfileError, Profiles};
+use crate::project::ProjectConfig;
 use crate::util::st;
 use clap::builder::Styles;
 use clap::builder::styling::AnsiColor;
-use clap::{Parser, Subcommand};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use console::style;
 use reqwest::blocking::Client;
 use snafu::{OptionExt, Report, ResultExt, Whatever, ensure_whatever};
@@ -48,6 +63,10 @@ struct CliArgs {
     frontend_url: String,
     #[clap(long, default_value = "https://compiler.vads.kastel.kit.edu/api")]
     backend_url: String,
+    /// The profile to use the urls and token of, see `profiles`. Urls given on the command line
+    /// still take precedence.
+    #[clap(long, global = true)]
+    profile: Option<String>,
     #[clap(subcommand)]
     subcommand: CliCommand,
 }
@@ -65,6 +84,27 @@ enum CliCommand {
     RunTests(CliRunTestsArgs),
     /// Uploads a new test or updates an existing to crow
     UploadTest(CliUploadTestArgs),
+    /// Interactively creates a new local test and optionally uploads it
+    NewTest(CliNewTestArgs),
+    /// Shows the results of a finished task
+    ShowTask(CliShowTaskArgs),
+    /// Chooses the task that is graded for a category, like the submission dialog in the web UI
+    FinalSubmit(CliFinalSubmitArgs),
+    /// Lists the tests that regressed or newly pass between two tasks, by default the two most
+    /// recent ones of your team. Fails if any test regressed.
+    Compare(CliCompareArgs),
+    /// Renders a static HTML report per team from tasks exported with `show-task --json`, for
+    /// archiving results independently of crow
+    Report(CliReportArgs),
+    /// Downloads the bundle of a test run in a task and reruns the test against your local sources
+    /// in a container
+    Repro(CliReproArgs),
+    /// Rebuilds your compiler and reruns the failing tests whenever its source changes
+    Watch(CliWatchArgs),
+    /// Lists, adds or removes profiles for working against multiple backends
+    Profiles(CliProfilesArgs),
+    /// Prints shell completions or a man page for packaging the client
+    Completions(CliCompletionsArgs),
 }
 
 fn main() -> ExitCode {
@@ -81,23 +121,78 @@ fn main() -> ExitCode {
         )
         .init();
 
+    // A `.crow.toml` in the current repository decides the defaults, e.g. which backend to use
+    let project = match ProjectConfig::discover() {
+        Ok(project) => project,
+        Err(e) => {
+            error!("\n{}", style(Report::from_error(e)).bright().red());
+            return ExitCode::FAILURE;
+        }
+    };
+    let matches = project.apply(CliArgs::command()).get_matches();
+    let mut args = CliArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // Completions and man pages are redirected into files, which should not include our greetings
+    if let CliCommand::Completions(completions) = &args.subcommand {
+        return match command_completions(completions, CliArgs::command()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                error!("\n{}", style(Report::from_error(e)).bright().red());
+                ExitCode::FAILURE
+            }
+        };
+    }
+    if let Err(e) = apply_profile(&mut args, &matches) {
+        error!("\n{}", style(Report::from_error(e)).bright().red());
+        return ExitCode::FAILURE;
+    }
+    let update_backend_url = args.backend_url.clone();
+
     let res = Report::capture_into_result(|| {
-        let args = CliArgs::parse();
         let client = Client::new();
         let backend_url = &args.backend_url;
         let frontend_url = &args.frontend_url;
+        let profile = args.profile.as_deref();
 
         match args.subcommand {
-            CliCommand::Login => command_login(client, backend_url, frontend_url),
-            CliCommand::SyncTests(args) => {
-                command_sync_tests(args, get_context(backend_url, frontend_url, client)?)
-            }
+            CliCommand::Login => command_login(client, backend_url, frontend_url, profile),
+            CliCommand::SyncTests(args) => command_sync_tests(
+                args,
+                get_context(backend_url, frontend_url, profile, client)?,
+            ),
             CliCommand::RunTest(args) => commands::run_test::command_run_test(args),
-            CliCommand::RunTests(args) => commands::run_test::command_run_tests(args),
+            CliCommand::RunTests(args) => commands::run_test::command_run_tests(
+                args,
+                try_get_context(backend_url, frontend_url, profile, client),
+            ),
             CliCommand::UploadTest(args) => commands::upload::command_upload_test(
                 args,
-                get_context(backend_url, frontend_url, client)?,
+                get_context(backend_url, frontend_url, profile, client)?,
+            ),
+            CliCommand::NewTest(args) => command_new_test(
+                args,
+                try_get_context(backend_url, frontend_url, profile, client),
             ),
+            CliCommand::ShowTask(args) => command_show_task(
+                args,
+                get_context(backend_url, frontend_url, profile, client)?,
+            ),
+            CliCommand::FinalSubmit(args) => command_final_submit(
+                args,
+                get_context(backend_url, frontend_url, profile, client)?,
+            ),
+            CliCommand::Compare(args) => command_compare(
+                args,
+                get_context(backend_url, frontend_url, profile, client)?,
+            ),
+            CliCommand::Report(args) => command_report(args),
+            CliCommand::Repro(args) => command_repro(
+                args,
+                get_context(backend_url, frontend_url, profile, client)?,
+            ),
+            CliCommand::Watch(args) => command_watch(args),
+            CliCommand::Profiles(args) => command_profiles(args),
+            CliCommand::Completions(_) => unreachable!("completions are printed before"),
         }
     });
 
@@ -113,7 +208,7 @@ fn main() -> ExitCode {
     println!();
 
     // Do this at the end so it is not hidden by other output
-    if let Err(e) = check_updates() {
+    if let Err(e) = check_updates(&update_backend_url) {
         println!("{}", Report::from_error(e));
     }
 
@@ -140,16 +235,66 @@ fn main() -> ExitCode {
     res
 }
 
-fn get_context(backend_url: &str, frontend_url: &str, client: Client) -> Result<CliContext> {
-    Ok(CliContext::new(
-        get_stored_auth(frontend_url).context(AuthSnafu)?,
+/// Uses the urls of the selected profile, unless they were given on the command line
+fn apply_profile(
+    args: &mut CliArgs,
+    matches: &ArgMatches,
+) -> std::result::Result<(), ProfileError> {
+    let Some(name) = &args.profile else {
+        return Ok(());
+    };
+    let profiles = Profiles::load()?;
+    let profile = profiles.get(name)?;
+
+    let from_command_line = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if !from_command_line("backend_url") {
+        args.backend_url = profile.backend_url.clone();
+    }
+    if !from_command_line("frontend_url") {
+        args.frontend_url = profile.frontend_url.clone();
+    }
+
+    Ok(())
+}
+
+fn get_context(
+    backend_url: &str,
+    frontend_url: &str,
+    profile: Option<&str>,
+    client: Client,
+) -> Result<CliContext> {
+    let ctx = CliContext::new(
+        get_stored_auth(frontend_url, profile).context(AuthSnafu)?,
+        client,
+        backend_url.to_string(),
+        frontend_url.to_string(),
+    );
+
+    Ok(ctx.with_profile(profile.map(str::to_string)))
+}
+
+/// A context for optional requests, if the user is logged in. Never prompts for a login.
+fn try_get_context(
+    backend_url: &str,
+    frontend_url: &str,
+    profile: Option<&str>,
+    client: Client,
+) -> Option<CliContext> {
+    let auth = try_get_stored_auth(profile)?;
+    let ctx = CliContext::new(
+        auth,
         client,
         backend_url.to_string(),
         frontend_url.to_string(),
-    ))
+    );
+
+    Some(
+        ctx.with_profile(profile.map(str::to_string))
+            .without_login_prompt(),
+    )
 }
 
-fn check_updates() -> std::result::Result<(), Whatever> {
+fn check_updates(backend_url: &str) -> std::result::Result<(), Whatever> {
     if !should_perform_update_check()? {
         debug!("Skipping update check");
         return Ok(());
@@ -162,6 +307,11 @@ fn check_updates() -> std::result::Result<(), Whatever> {
 
     let client = Client::new();
 
+    // Servers predating the version endpoint can not be checked, so this is best effort only
+    if let Err(e) = check_server_compatibility(&client, backend_url, &my_version) {
+        debug!(error = %Report::from_error(e), "Could not check server compatibility");
+    }
+
     let remote_version = client
         .get("https://api.github.com/repos/I-Al-Istannen/crow/releases/latest")
         .header("User-Agent", "crow-client")
@@ -205,6 +355,50 @@ fn check_updates() -> std::result::Result<(), Whatever> {
     Ok(())
 }
 
+fn check_server_compatibility(
+    client: &Client,
+    backend_url: &str,
+    my_version: &semver::Version,
+) -> std::result::Result<(), Whatever> {
+    let server_info = client
+        .get(format!("{backend_url}/version"))
+        .send()
+        .and_then(|it| it.error_for_status())
+        .whatever_context("Could not fetch server version")?
+        .json::<serde_json::Value>()
+        .whatever_context("Could not parse server version response")?;
+
+    let server_version = server_info
+        .get("version")
+        .whatever_context("Could not find version")?
+        .as_str()
+        .whatever_context("version is not a string")?;
+    let server_version = semver::Version::parse(server_version)
+        .whatever_context("Could not parse server version")?;
+
+    // Breaking changes bump the major version, or the minor one while we are still at 0.x
+    let compatible = if my_version.major == 0 {
+        server_version.major == 0 && server_version.minor == my_version.minor
+    } else {
+        server_version.major == my_version.major
+    };
+
+    if !compatible {
+        println!();
+        warn!(
+            "{}",
+            st("The crow server runs version ")
+                .append(style(format!("{server_version}")).yellow().bold())
+                .append(", which might not be compatible with your client version ")
+                .append(style(format!("{my_version}")).red().bold())
+                .append(".")
+        );
+        println!();
+    }
+
+    Ok(())
+}
+
 fn should_perform_update_check() -> std::result::Result<bool, Whatever> {
     let temp_dir = tempfile::env::temp_dir();
     ensure_whatever!(
@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use snafu::{IntoError, Location, NoneError, OptionExt, ResultExt, Snafu};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+const CREDENTIALS_FILE: &str = "credentials.toml";
+
+#[derive(Debug, Snafu)]
+pub enum ProfileError {
+    #[snafu(display(
+        "Could not find a config directory, set `XDG_CONFIG_HOME` or `HOME` at {location}"
+    ))]
+    NoConfigDir {
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not read profiles `{}` at {location}", path.display()))]
+    ReadProfiles {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not parse profiles `{}` at {location}", path.display()))]
+    ParseProfiles {
+        path: PathBuf,
+        source: toml::de::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not write profiles `{}` at {location}", path.display()))]
+    WriteProfiles {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Unknown profile `{name}`, add it with `profiles add` at {location}"))]
+    UnknownProfile {
+        name: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// The named profiles in `~/.config/crow/credentials.toml`, each pointing to a different backend.
+/// Their tokens are kept in the system keyring next to the one of the default backend.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profiles {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub backend_url: String,
+    pub frontend_url: String,
+}
+
+impl Profiles {
+    pub fn path() -> Result<PathBuf, ProfileError> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|it| !it.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|it| PathBuf::from(it).join(".config")))
+            .context(NoConfigDirSnafu)?;
+
+        Ok(config_dir.join("crow").join(CREDENTIALS_FILE))
+    }
+
+    /// Reads the profiles, or returns none if the file does not exist yet
+    pub fn load() -> Result<Self, ProfileError> {
+        let path = Self::path()?;
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path).context(ReadProfilesSnafu { path: &path })?;
+        toml::from_str(&contents).context(ParseProfilesSnafu { path })
+    }
+
+    pub fn save(&self) -> Result<(), ProfileError> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(WriteProfilesSnafu { path: &path })?;
+        }
+
+        let contents = toml::to_string(self).expect("Unexpected toml serialize error");
+        std::fs::write(&path, contents).context(WriteProfilesSnafu { path })
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile, ProfileError> {
+        self.profiles.get(name).ok_or_else(|| {
+            UnknownProfileSnafu {
+                name: name.to_string(),
+            }
+            .into_error(NoneError)
+        })
+    }
+}
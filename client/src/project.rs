@@ -0,0 +1,142 @@
+use clap::Command;
+use clap::builder::ValueParser;
+use serde::Deserialize;
+use snafu::{Location, ResultExt, Snafu};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// The names of the project config, in the order they are looked for
+const PROJECT_CONFIG_FILES: [&str; 2] = ["crow.toml", ".crow.toml"];
+
+#[derive(Debug, Snafu)]
+pub enum ProjectConfigError {
+    #[snafu(display("Could not read project config `{}` at {location}", path.display()))]
+    ReadProjectConfig {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not parse project config `{}` at {location}", path.display()))]
+    ParseProjectConfig {
+        path: PathBuf,
+        source: toml::de::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// The contents of a `crow.toml` (or `.crow.toml`) in the root of the git repository the client
+/// is run in. It provides defaults for the command line, so the same client can be used for
+/// projects on different backends without repeating the paths of the project every time.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    backend_url: Option<String>,
+    frontend_url: Option<String>,
+    /// Relative to the repository root
+    test_dir: Option<PathBuf>,
+    /// The run binary of the compiler, relative to the repository root
+    compiler_run: Option<PathBuf>,
+    /// The shell command rebuilding the compiler
+    build_command: Option<String>,
+    /// The category used when `--category` is not given
+    category: Option<String>,
+    /// Maps short names accepted by `--category` to the category names of the backend
+    #[serde(default)]
+    categories: HashMap<String, String>,
+}
+
+impl ProjectConfig {
+    /// Reads the config of the git repository containing the current directory, if it has one
+    pub fn discover() -> Result<Self, ProjectConfigError> {
+        let Ok(cwd) = std::env::current_dir() else {
+            return Ok(Self::default());
+        };
+        let Some(repo_root) = cwd.ancestors().find(|dir| dir.join(".git").exists()) else {
+            return Ok(Self::default());
+        };
+
+        let Some(path) = PROJECT_CONFIG_FILES
+            .iter()
+            .map(|name| repo_root.join(name))
+            .find(|path| path.is_file())
+        else {
+            return Ok(Self::default());
+        };
+        debug!(path = %path.display(), "Using project config");
+
+        Self::read(&path, repo_root)
+    }
+
+    fn read(path: &Path, repo_root: &Path) -> Result<Self, ProjectConfigError> {
+        let contents = std::fs::read_to_string(path).context(ReadProjectConfigSnafu { path })?;
+        let mut config: Self =
+            toml::from_str(&contents).context(ParseProjectConfigSnafu { path })?;
+        config.test_dir = config.test_dir.map(|dir| repo_root.join(dir));
+        config.compiler_run = config.compiler_run.map(|path| repo_root.join(path));
+
+        Ok(config)
+    }
+
+    /// Uses the config as the default of the matching arguments. Values given on the command line
+    /// still take precedence.
+    pub fn apply(self, mut command: Command) -> Command {
+        if let Some(backend_url) = self.backend_url {
+            command = command.mut_arg("backend_url", |arg| arg.default_value(backend_url));
+        }
+        if let Some(frontend_url) = self.frontend_url {
+            command = command.mut_arg("frontend_url", |arg| arg.default_value(frontend_url));
+        }
+
+        let subcommands = command
+            .get_subcommands()
+            .map(|it| it.get_name().to_string())
+            .collect::<Vec<_>>();
+        for name in subcommands {
+            command = command.mut_subcommand(name, |mut subcommand| {
+                let has_arg = |subcommand: &Command, id: &str| {
+                    subcommand.get_arguments().any(|arg| arg.get_id() == id)
+                };
+                let defaults = [
+                    (
+                        "test_dir",
+                        self.test_dir.as_ref().map(|it| it.to_string_lossy()),
+                    ),
+                    (
+                        "compiler_run",
+                        self.compiler_run.as_ref().map(|it| it.to_string_lossy()),
+                    ),
+                    (
+                        "build_command",
+                        self.build_command.as_deref().map(Into::into),
+                    ),
+                    ("category", self.category.as_deref().map(Into::into)),
+                ];
+                for (id, default) in defaults {
+                    let Some(default) = default.filter(|_| has_arg(&subcommand, id)) else {
+                        continue;
+                    };
+                    let default = default.into_owned();
+                    subcommand =
+                        subcommand.mut_arg(id, |arg| arg.default_value(default).required(false));
+                }
+                if !self.categories.is_empty() && has_arg(&subcommand, "category") {
+                    let categories = self.categories.clone();
+                    subcommand = subcommand.mut_arg("category", |arg| {
+                        arg.value_parser(ValueParser::new(move |name: &str| {
+                            Ok::<_, Infallible>(
+                                categories.get(name).cloned().unwrap_or(name.to_string()),
+                            )
+                        }))
+                    });
+                }
+                subcommand
+            });
+        }
+
+        command
+    }
+}
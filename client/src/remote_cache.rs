@@ -0,0 +1,101 @@
+use crate::context::{CliContext, Test};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use snafu::{Location, Report, ResultExt, Snafu};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Name of the file in the test directory the remote test metadata is cached in
+const CACHE_FILE_NAME: &str = ".crow-remote-tests.json";
+
+#[derive(Debug, Snafu)]
+pub enum RemoteCacheError {
+    #[snafu(display("Could not serialize the remote test cache at {location}"))]
+    Serialize {
+        source: serde_json::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not write the remote test cache `{}` at {location}", path.display()))]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// What we last knew about a test on the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedRemoteTest {
+    pub id: String,
+    pub category: String,
+    pub hash: String,
+    pub last_updated: Option<Timestamp>,
+}
+
+impl From<&Test> for CachedRemoteTest {
+    fn from(value: &Test) -> Self {
+        Self {
+            id: value.id.clone(),
+            category: value.category.clone(),
+            hash: value.hash.clone(),
+            last_updated: value.last_updated,
+        }
+    }
+}
+
+pub fn write_remote_cache(test_dir: &Path, tests: &[Test]) -> Result<(), RemoteCacheError> {
+    let tests = tests
+        .iter()
+        .map(Into::into)
+        .collect::<Vec<CachedRemoteTest>>();
+    let json = serde_json::to_string_pretty(&tests).context(SerializeSnafu)?;
+    let path = test_dir.join(CACHE_FILE_NAME);
+
+    std::fs::write(&path, json).context(WriteSnafu { path })
+}
+
+pub fn read_remote_cache(test_dir: &Path) -> Option<Vec<CachedRemoteTest>> {
+    let path = test_dir.join(CACHE_FILE_NAME);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            debug!(path = %path.display(), error = %e, "Could not read remote test cache");
+            return None;
+        }
+    };
+
+    match serde_json::from_str(&content) {
+        Ok(tests) => Some(tests),
+        Err(e) => {
+            debug!(path = %path.display(), error = %e, "Remote test cache is malformed");
+            None
+        }
+    }
+}
+
+/// Refreshes the cache from the server if we can reach it, falling back to the cached state
+/// otherwise.
+pub fn refresh_remote_cache(
+    test_dir: &Path,
+    ctx: Option<&CliContext>,
+) -> Option<Vec<CachedRemoteTest>> {
+    let Some(ctx) = ctx else {
+        return read_remote_cache(test_dir);
+    };
+
+    match ctx.get_remote_tests() {
+        Ok(remote) => {
+            if let Err(e) = write_remote_cache(test_dir, &remote.tests) {
+                debug!(error = %Report::from_error(e), "Could not update remote test cache");
+            }
+            Some(remote.tests.iter().map(Into::into).collect())
+        }
+        Err(e) => {
+            debug!(error = %Report::from_error(e), "Could not fetch remote tests, using cache");
+            read_remote_cache(test_dir)
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use console::style;
 use shared::execute::{CommandResult, RunWithTimeoutError, run_with_timeout};
 use shared::exit::CrowExitStatus;
-use shared::{ExecutionOutput, FinishedExecution, TestExecutionOutput, indent};
+use shared::{
+    ExecutionOutput, FinishedExecution, PinnedEnvironment, TestExecutionOutput, TestInvocation,
+    indent, shell_command_line,
+};
 use snafu::Report;
 use std::collections::HashSet;
 use std::error::Error;
@@ -118,13 +121,37 @@ pub fn print_test_output(output: &TestExecutionOutput) {
                     .append(indent(&execution_output_to_string(output_so_far), 2))
             );
         }
+        TestExecutionOutput::Skipped { output_so_far } => {
+            warn!(
+                "{}",
+                st(style("The test was skipped\n").bright().yellow())
+                    .append(indent(&execution_output_to_string(output_so_far), 2))
+            );
+        }
         TestExecutionOutput::Success { .. } => {
             info!("{}", style("Test passed!").bright().bold().green());
         }
     }
 }
 
-fn execution_output_to_string(output: &ExecutionOutput) -> String {
+/// Prints the commands the test ran, so they can be reproduced by hand
+pub fn print_test_invocation(invocation: &TestInvocation) {
+    let commands = [
+        ("Compiler", &invocation.compiler),
+        ("Binary", &invocation.binary),
+    ];
+    for (name, command) in commands {
+        if let Some(command) = command {
+            info!(
+                "{}",
+                st(style(format!("{name} command: ")).bold())
+                    .append(style(shell_command_line(command)).dim())
+            );
+        }
+    }
+}
+
+pub fn execution_output_to_string(output: &ExecutionOutput) -> String {
     match output {
         ExecutionOutput::Aborted(e) => {
             format!(
@@ -212,9 +239,14 @@ pub fn execute_locally(
     cmd: &[String],
     timeout: Option<Duration>,
     stdin: String,
+    work_dir: &Path,
 ) -> Result<CommandResult, Box<dyn Error + Sync + Send>> {
+    // Mirror the environment of the executor, so local runs behave like remote ones.
+    // Only the umask is not pinned, as that would need to happen between fork and exec.
     let mut child = Command::new(path)
         .args(cmd)
+        .current_dir(work_dir)
+        .envs(PinnedEnvironment::default().variables())
         .process_group(0)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
@@ -231,6 +263,7 @@ pub fn execute_locally(
         &mut child.stderr.take().expect("stderr"),
         &mut child,
         timeout.unwrap_or(Duration::from_secs(5 * 60)), // Default to 5 minutes
+        None,
     );
     let (stdout, stderr, status, runtime) = match res {
         Ok((stdout, stderr, status, runtime)) => (stdout, stderr, status.into(), runtime),
@@ -266,6 +299,10 @@ pub fn execute_locally(
             stderr,
             runtime,
             exit_status: status.code(),
+            infrastructure_log: None,
+            recording: None,
+            resource_usage: None,
+            full_output_id: None,
         },
     )))
 }
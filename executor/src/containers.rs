@@ -1,14 +1,21 @@
+use crate::disk_quota::{DiskQuota, DiskQuotaError};
 use crate::docker::{Docker, DockerError, ImageId};
+use crate::metrics;
+use crate::metrics::MetricLabel;
 use derive_more::{Display, From};
-use serde::Deserialize;
-use shared::execute::{CommandResult, RunWithTimeoutError};
+use serde::{Deserialize, Serialize};
+use shared::execute::{CommandResult, OUTPUT_BINARY_NAME, RunWithTimeoutError};
 use shared::exit::CrowExitStatus;
+use shared::recording::OutputRecording;
 use shared::{
-    AbortedExecution, CompilerTest, ExecutionOutput, FinishedExecution, InternalError,
-    TestExecutionOutput, remove_directory_force,
+    AbortedExecution, CategoryFileset, CompilerTest, ContainerPermissions, ContainerUser,
+    ExecutionOutput, FinishedExecution, InternalError, PinnedEnvironment, ResourceUsage,
+    TestExecutionOutput, TestInvocation, remove_directory_force,
 };
 use snafu::{IntoError, Location, NoneError, Report, ResultExt, Snafu, ensure, location};
 use std::io::Write;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::{Child, ChildStderr, ChildStdout, Command, ExitStatus, Stdio};
 use std::sync::Arc;
@@ -19,8 +26,14 @@ use tempfile::{TempDir, TempPath};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-pub const CROW_SIGNAL_SHIM_MAGIC: &str = "crow-internal_KILLED_BY_SIGNAL: ";
+/// Prefixes the [ShimTrailer] the shim logs after the program exited
+pub const CROW_SHIM_TRAILER_MAGIC: &str = "crow-internal_SHIM_TRAILER: ";
 const CROW_SHIM_IN_CONTAINER_PATH: &str = "crow-shim";
+/// The descriptor the shim writes its diagnostics to inside the container. runc only passes on
+/// the descriptors directly following stdio.
+const SHIM_LOG_FD: RawFd = 3;
+const SHIM_LOG_FILE: &str = "shim.log";
+const RUNC_LOG_FILE: &str = "runc.log";
 
 #[derive(Snafu, Debug)]
 pub enum RunConfigError {
@@ -59,6 +72,12 @@ pub enum ContainerCreateError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Could not set up the disk quota at {location}"))]
+    DiskQuotaSetup {
+        source: DiskQuotaError,
+        #[snafu(implicit)]
+        location: Location,
+    },
 }
 
 #[derive(Snafu, Debug)]
@@ -223,6 +242,37 @@ pub enum TestRunError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Fileset path `{path}` leaves the working directory at {location}"))]
+    UnsafeFilesetPath {
+        path: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not write fileset file `{path}` into the container at {location}"))]
+    WriteFileset {
+        source: io::Error,
+        path: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+impl MetricLabel for TestRunError {
+    fn metric_label(&self) -> &'static str {
+        match self {
+            Self::Creation { .. } => "creation",
+            Self::PassInputToContainer { .. } => "pass_input_to_container",
+            Self::DriverInvalidJson { .. } => "driver_invalid_json",
+            Self::FindOwnExecutable { .. } => "find_own_executable",
+            Self::CopyExecutorToContainer { .. } => "copy_executor_to_container",
+            Self::ExecutionStart { .. } => "execution_start",
+            Self::RuncStart { .. } => "runc_start",
+            Self::Execution { .. } => "execution",
+            Self::BaseNotBuilt { .. } => "base_not_built",
+            Self::UnsafeFilesetPath { .. } => "unsafe_fileset_path",
+            Self::WriteFileset { .. } => "write_fileset",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -234,13 +284,18 @@ pub struct RuncLogMessage {
     pub time: String,
 }
 
+#[derive(Debug, Clone)]
 pub struct LimitsConfig {
     pub cpus: Option<u32>,
     pub memory_bytes: Option<usize>,
+    /// The number of processes and threads
+    pub pids: Option<u32>,
+    /// The space the container may write to disk, enforced by a [DiskQuota]
+    pub disk_bytes: Option<u64>,
 }
 
 impl LimitsConfig {
-    pub fn new(cpus: u32, memory_bytes: usize) -> Self {
+    pub fn new(cpus: u32, memory_bytes: usize, pids: u32, disk_bytes: u64) -> Self {
         Self {
             cpus: if cpus > 0 { Some(cpus) } else { None },
             memory_bytes: if memory_bytes > 0 {
@@ -248,9 +303,23 @@ impl LimitsConfig {
             } else {
                 None
             },
+            pids: if pids > 0 { Some(pids) } else { None },
+            disk_bytes: if disk_bytes > 0 {
+                Some(disk_bytes)
+            } else {
+                None
+            },
         }
     }
 
+    /// Mounts a quota filesystem in the work directory if the disk space is limited
+    fn mount_disk_quota(&self, workdir: &Path) -> Result<Option<DiskQuota>, ContainerCreateError> {
+        self.disk_bytes
+            .map(|bytes| DiskQuota::mount(workdir, bytes))
+            .transpose()
+            .context(DiskQuotaSetupSnafu)
+    }
+
     pub fn apply(&self, mut config: String) -> String {
         if let Some(cpus) = self.cpus {
             config = config.replace(
@@ -285,16 +354,171 @@ impl LimitsConfig {
             config = config.replace("{memory_limits}", "");
         }
 
+        if let Some(pids) = self.pids {
+            config = config.replace(
+                "{pids_limits}",
+                &format!(
+                    r#"
+                   "pids": {{
+                       "limit": {pids}
+                   }},
+                   "#
+                ),
+            )
+        } else {
+            config = config.replace("{pids_limits}", "");
+        }
+
         config
     }
 }
 
-pub enum ContainerConfig {
+/// How many processes and threads the container used, as reported by the shim from the cgroup of
+/// the container
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PidsUsage {
+    /// The most processes and threads alive at once. Only known on kernels with `pids.peak`.
+    pub peak: Option<u64>,
+    pub limit: Option<u64>,
+    /// How often creating a process or thread failed due to the limit
+    pub limit_hits: u64,
+}
+
+impl PidsUsage {
+    /// Reads the usage from inside the container, where the cgroup namespace makes our own cgroup
+    /// the root
+    pub fn read_own_cgroup() -> Self {
+        let read = |name: &str| fs::read_to_string(Path::new("/sys/fs/cgroup").join(name));
+
+        let limit_hits = read("pids.events")
+            .ok()
+            .and_then(|events| {
+                events.lines().find_map(|line| {
+                    line.strip_prefix("max ")
+                        .and_then(|it| it.trim().parse::<u64>().ok())
+                })
+            })
+            .unwrap_or(0);
+
+        Self {
+            peak: read("pids.peak").ok().and_then(|it| it.trim().parse().ok()),
+            // `max` means no limit and fails to parse
+            limit: read("pids.max").ok().and_then(|it| it.trim().parse().ok()),
+            limit_hits,
+        }
+    }
+}
+
+/// What the shim learned about the program it ran, logged as a single json line once it exited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShimTrailer {
+    /// The signal the program died of
+    pub signal: Option<i32>,
+    /// The last termination signal the shim received and passed on to the program
+    pub forwarded_signal: Option<i32>,
+    pub resource_usage: Option<ResourceUsage>,
+    pub pids: PidsUsage,
+}
+
+/// The `CROW_*` environment variables passed to build and test containers.
+///
+/// Build scripts and compilers may rely on these, so the names and formats are a stable contract:
+/// - `CROW_TASK_ID`: The id of the task the container belongs to
+/// - `CROW_TEST_ID`: The id of the test being executed. Unset in build containers.
+/// - `CROW_CATEGORY`: The category of the test being executed. Unset in build containers.
+/// - `CROW_TIMEOUT_MS`: The time in milliseconds the current command may run for
+///
+/// Additionally, the locale, timezone, umask and `SOURCE_DATE_EPOCH` are pinned to the values in
+/// [PinnedEnvironment]. Categories can pin the compiled binary to different ones.
+#[derive(Debug, Clone)]
+pub struct CrowEnvironment {
+    pub task_id: String,
+    pub test_id: Option<String>,
+    pub category: Option<String>,
+    pub timeout: Duration,
+    pub pinned: PinnedEnvironment,
+    /// Whether the shim disables address space layout randomization for the command
+    pub disable_aslr: bool,
+}
+
+impl CrowEnvironment {
+    pub fn for_build(task_id: String, timeout: Duration) -> Self {
+        Self {
+            task_id,
+            test_id: None,
+            category: None,
+            timeout,
+            pinned: PinnedEnvironment::default(),
+            disable_aslr: false,
+        }
+    }
+
+    pub fn for_test(task_id: String, test: &CompilerTest, timeout: Duration) -> Self {
+        Self {
+            task_id,
+            test_id: Some(test.test_id.clone()),
+            category: Some(test.category.clone()),
+            timeout,
+            pinned: PinnedEnvironment::default(),
+            disable_aslr: false,
+        }
+    }
+
+    /// The environment for running the compiled binary of the test, which its category may pin
+    /// differently than the compiler
+    pub fn for_binary(task_id: String, test: &CompilerTest, timeout: Duration) -> Self {
+        let binary = &test.binary_environment;
+        Self {
+            pinned: binary.pinned.clone().unwrap_or_default(),
+            disable_aslr: binary.disable_aslr,
+            ..Self::for_test(task_id, test, timeout)
+        }
+    }
+
+    fn variables(&self) -> Vec<String> {
+        let mut variables = self
+            .pinned
+            .variables()
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>();
+        variables.push(format!("CROW_TASK_ID={}", self.task_id));
+        if let Some(test_id) = &self.test_id {
+            variables.push(format!("CROW_TEST_ID={test_id}"));
+        }
+        if let Some(category) = &self.category {
+            variables.push(format!("CROW_CATEGORY={category}"));
+        }
+        variables.push(format!("CROW_TIMEOUT_MS={}", self.timeout.as_millis()));
+
+        variables
+    }
+
+    /// Appends the variables to the env array of the runc config and sets the umask
+    fn apply(&self, config: String) -> Result<String, RunConfigError> {
+        let mut entries = String::new();
+        for variable in self.variables() {
+            entries.push_str(",\n            ");
+            entries.push_str(&serde_json::to_string(&variable).context(ArgsNotJsonSnafu)?);
+        }
+
+        Ok(config
+            .replace("{crow_env}", &entries)
+            .replace("{umask}", &self.pinned.umask.to_string()))
+    }
+}
+
+pub enum ContainerConfig<'a> {
     WritableRootfs,
-    OverlayRootfs,
+    OverlayRootfs {
+        /// The directory to place the upper and work directories of the overlay in
+        layer_dir: &'a Path,
+    },
 }
 
-impl ContainerConfig {
+impl ContainerConfig<'_> {
+    #[allow(clippy::too_many_arguments)]
     pub fn apply_to_workdir(
         &self,
         rootfs: &Path,
@@ -302,19 +526,22 @@ impl ContainerConfig {
         args: &[String],
         exists_okay: bool,
         limits_config: &LimitsConfig,
+        env: &CrowEnvironment,
+        permissions: &ContainerPermissions,
     ) -> Result<PathBuf, RunConfigError> {
         let path_config = workdir.join("config.json");
 
         let root = match self {
             Self::WritableRootfs => {
-                let config = include_str!("../resources/runc-read-write.json")
+                let config = shared::runc::READ_WRITE_TEMPLATE
                     .replace("{rootfs}", &rootfs.display().to_string())
                     .replace("{host_uid}", &users::get_current_uid().to_string())
                     .replace(
                         "{args}",
                         &serde_json::to_string(args).context(ArgsNotJsonSnafu)?,
                     );
-                let config = limits_config.apply(config);
+                let config =
+                    apply_permissions(permissions, env.apply(limits_config.apply(config))?)?;
 
                 fs::write(&path_config, config).context(FileWriteSnafu {
                     path: path_config.to_path_buf(),
@@ -322,9 +549,10 @@ impl ContainerConfig {
 
                 rootfs.to_path_buf()
             }
-            Self::OverlayRootfs => {
-                let path_upper = workdir.join("overlay-upper");
-                let path_work = workdir.join("overlay-work");
+            Self::OverlayRootfs { layer_dir } => {
+                let setup_start = Instant::now();
+                let path_upper = layer_dir.join("overlay-upper");
+                let path_work = layer_dir.join("overlay-work");
 
                 if !exists_okay || !path_upper.exists() {
                     fs::create_dir(&path_upper).context(FileWriteSnafu {
@@ -337,7 +565,7 @@ impl ContainerConfig {
                     })?;
                 }
 
-                let config = include_str!("../resources/runc-overlay.json")
+                let config = shared::runc::OVERLAY_TEMPLATE
                     .replace("{rootfs}", &rootfs.display().to_string())
                     .replace("{host_uid}", &users::get_current_uid().to_string())
                     .replace(
@@ -347,11 +575,13 @@ impl ContainerConfig {
                     .replace("{lower_dir}", &rootfs.display().to_string())
                     .replace("{upper_dir}", &path_upper.display().to_string())
                     .replace("{work_dir}", &path_work.display().to_string());
-                let config = limits_config.apply(config);
+                let config =
+                    apply_permissions(permissions, env.apply(limits_config.apply(config))?)?;
 
                 fs::write(&path_config, config).context(FileWriteSnafu {
                     path: path_config.to_path_buf(),
                 })?;
+                metrics::record_duration(metrics::OVERLAY_SETUP_DURATION, setup_start.elapsed());
 
                 path_upper
             }
@@ -361,6 +591,42 @@ impl ContainerConfig {
     }
 }
 
+/// Runs the container as the configured user and appends the extra mounts to the runc config
+fn apply_permissions(
+    permissions: &ContainerPermissions,
+    config: String,
+) -> Result<String, RunConfigError> {
+    let user = permissions.user.unwrap_or(ContainerUser { uid: 0, gid: 0 });
+
+    let writable_paths = permissions.writable_paths.iter().map(|path| {
+        serde_json::json!({
+            "destination": path,
+            "type": "tmpfs",
+            "source": "tmpfs",
+            "options": ["nosuid", "nodev", "mode=1777"]
+        })
+    });
+    let bind_mounts = permissions.bind_mounts.iter().map(|mount| {
+        let access = if mount.writable { "rw" } else { "rro" };
+        serde_json::json!({
+            "destination": mount.destination,
+            "type": "bind",
+            "source": mount.source,
+            "options": ["rbind", access, "rprivate"]
+        })
+    });
+    let mut mounts = String::new();
+    for mount in writable_paths.chain(bind_mounts) {
+        mounts.push_str(",\n        ");
+        mounts.push_str(&serde_json::to_string(&mount).context(ArgsNotJsonSnafu)?);
+    }
+
+    Ok(config
+        .replace("{container_uid}", &user.uid.to_string())
+        .replace("{container_gid}", &user.gid.to_string())
+        .replace("{extra_mounts}", &mounts))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, From, Display)]
 pub struct ContainerId(String);
 
@@ -380,6 +646,9 @@ pub struct Built {
     pub stderr: String,
     pub exit_status: CrowExitStatus,
     pub runtime: Duration,
+    pub infrastructure_log: Option<String>,
+    pub recording: Option<OutputRecording>,
+    pub resource_usage: Option<ResourceUsage>,
 }
 
 #[derive(Debug, Clone)]
@@ -394,6 +663,8 @@ pub struct TaskContainer<T> {
     rootfs: PathBuf,
     container_id: ContainerId,
     do_cleanup: bool,
+    /// Holds everything the container writes, if its disk space is limited
+    disk_quota: Option<DiskQuota>,
     pub data: T,
 }
 
@@ -403,9 +674,17 @@ impl TaskContainer<()> {
         args: &[String],
         docker: &Docker,
         limits: &LimitsConfig,
+        env: &CrowEnvironment,
+        permissions: &ContainerPermissions,
     ) -> Result<TaskContainer<Created>, ContainerCreateError> {
+        let setup_start = Instant::now();
         let workdir = TempDir::new().context(TempDirCreationSnafu)?;
-        let path_rootfs = workdir.path().join("rootfs");
+        // The build writes into the copied rootfs, so the quota includes the image
+        let disk_quota = limits.mount_disk_quota(workdir.path())?;
+        let path_rootfs = disk_quota
+            .as_ref()
+            .map_or(workdir.path(), DiskQuota::path)
+            .join("rootfs");
 
         // We modify the rootfs during the build process (as these changes are replicated into each
         // container), so we need to copy it.
@@ -414,20 +693,53 @@ impl TaskContainer<()> {
             .context(ImageCopySnafu)?;
 
         ContainerConfig::WritableRootfs
-            .apply_to_workdir(&path_rootfs, workdir.path(), args, false, limits)
+            .apply_to_workdir(
+                &path_rootfs,
+                workdir.path(),
+                args,
+                false,
+                limits,
+                env,
+                permissions,
+            )
             .context(ConfigApplySnafu)?;
+        metrics::record_duration(metrics::CONTAINER_SETUP_DURATION, setup_start.elapsed());
 
         Ok(TaskContainer {
             workdir: workdir.into_path(),
             rootfs: path_rootfs,
             container_id: ContainerId(Uuid::new_v4().to_string()),
             do_cleanup: true,
+            disk_quota,
             data: Created,
         })
     }
 }
 
 impl TaskContainer<Created> {
+    /// Whether the image contains the program, either at the given absolute path or in one of the
+    /// usual binary directories
+    pub fn contains_program(&self, program: &str) -> bool {
+        let candidates = if program.starts_with('/') {
+            vec![self.rootfs.join(program.trim_start_matches('/'))]
+        } else {
+            [
+                "usr/local/sbin",
+                "usr/local/bin",
+                "usr/sbin",
+                "usr/bin",
+                "sbin",
+                "bin",
+            ]
+            .iter()
+            .map(|dir| self.rootfs.join(dir).join(program))
+            .collect()
+        };
+
+        // Links are not followed, they might be absolute and point into the container
+        candidates.iter().any(|it| fs::symlink_metadata(it).is_ok())
+    }
+
     pub fn integrate_source(&self, source_tar: TempPath) -> Result<(), IntegrateSourceError> {
         let work_path = self.rootfs.join("work");
         let tar_path = source_tar.to_path_buf();
@@ -464,7 +776,7 @@ impl TaskContainer<Created> {
     }
 
     pub fn run(mut self) -> io::Result<TaskContainer<Started>> {
-        let mut process = start_container(&self.workdir, &self.container_id)?;
+        let mut process = start_container(&self.workdir, &self.container_id, false)?;
 
         let stdout = process.stdout.take();
         let stderr = process.stderr.take();
@@ -477,6 +789,7 @@ impl TaskContainer<Created> {
             workdir: self.workdir.clone(),
             container_id: self.container_id.clone(),
             do_cleanup: true,
+            disk_quota: self.disk_quota.take(),
             data: Started {
                 stdout: stdout.unwrap(),
                 stderr: stderr.unwrap(),
@@ -491,8 +804,10 @@ impl TaskContainer<Started> {
         mut self,
         timeout: Duration,
         aborted: Arc<AtomicBool>,
+        record_output: bool,
     ) -> Result<TaskContainer<Built>, ExecutionOutput> {
         let start = Instant::now();
+        let mut recording = record_output.then(OutputRecording::default);
         let wait_result = wait_for_container(
             aborted,
             &self.container_id,
@@ -500,28 +815,34 @@ impl TaskContainer<Started> {
             &mut self.data.stderr,
             &mut self.data.process,
             timeout,
+            recording.as_mut(),
         );
-        let (exit_status, wait_result) =
-            match wait_result_to_command_result(&self.container_id, wait_result) {
-                Ok(res) => match res {
-                    CommandResult::ProcessedFailed(output) => return Err(output),
-                    CommandResult::Unprocessed((status, execution)) => {
-                        if !status.success() {
-                            return Err(ExecutionOutput::Failure {
-                                execution,
-                                accumulated_errors: None,
-                            });
-                        }
-                        (status, execution)
+        let (exit_status, wait_result) = match wait_result_to_command_result(
+            &self.container_id,
+            &self.workdir,
+            self.disk_quota.as_ref(),
+            recording,
+            wait_result,
+        ) {
+            Ok(res) => match res {
+                CommandResult::ProcessedFailed(output) => return Err(output),
+                CommandResult::Unprocessed((status, execution)) => {
+                    if !status.success() {
+                        return Err(ExecutionOutput::Failure {
+                            execution,
+                            accumulated_errors: None,
+                        });
                     }
-                },
-                Err(e) => {
-                    return Err(ExecutionOutput::Error(InternalError {
-                        message: Report::from_error(e).to_string(),
-                        runtime: start.elapsed(),
-                    }));
+                    (status, execution)
                 }
-            };
+            },
+            Err(e) => {
+                return Err(ExecutionOutput::Error(InternalError {
+                    message: Report::from_error(e).to_string(),
+                    runtime: start.elapsed(),
+                }));
+            }
+        };
 
         // Do not delete us on drop, we still live on in the new task container
         self.do_cleanup = false;
@@ -531,24 +852,32 @@ impl TaskContainer<Started> {
             workdir: self.workdir.clone(),
             container_id: self.container_id.clone(),
             do_cleanup: true,
+            disk_quota: self.disk_quota.take(),
             data: Built {
                 stdout: wait_result.stdout,
                 stderr: wait_result.stderr,
                 exit_status,
                 runtime: wait_result.runtime,
+                infrastructure_log: wait_result.infrastructure_log,
+                recording: wait_result.recording,
+                resource_usage: wait_result.resource_usage,
             },
         })
     }
 }
 
 impl TaskContainer<Built> {
+    #[allow(clippy::too_many_arguments)]
     pub fn run_test(
         &self,
+        task_id: &str,
         test: &CompilerTest,
+        fileset: Option<&CategoryFileset>,
         timeout: Duration,
         aborted: Arc<AtomicBool>,
         limits: &LimitsConfig,
-    ) -> Result<TestExecutionOutput, TestRunError> {
+        record_output: bool,
+    ) -> Result<TestRun, TestRunError> {
         if !self.data.exit_status.success() {
             return Err(BaseNotBuiltSnafu {
                 exit_status: self.data.exit_status,
@@ -556,23 +885,46 @@ impl TaskContainer<Built> {
             .into_error(NoneError));
         }
 
-        let mut test_container = TaskContainer::<ForTest<'_>>::new(self)?;
-        let output_binary_path = test_container.rootfs.join("out.🦆");
+        let mut test_container = TaskContainer::<ForTest<'_>>::new(self, limits)?;
+        let output_binary_path = test_container.rootfs.join(OUTPUT_BINARY_NAME);
+        let output_binary_in_container = Path::new("/").join(OUTPUT_BINARY_NAME);
+        if let Some(fileset) = fileset {
+            // The overlay merges this with the working directory of the build
+            materialize_fileset(&test_container.rootfs.join("work"), fileset)?;
+        }
 
-        let res = shared::execute::execute_test(
+        let (output, invocation) = shared::execute::execute_test(
             test,
             &test_container.rootfs.clone(),
             &output_binary_path,
             Path::new("/"),
+            // Programs run in `/work`, and everything they write lands in the upper layer
+            &test_container.rootfs.join("work"),
             |path, cmd, override_timeout, stdin| {
                 let timeout = override_timeout.unwrap_or(timeout);
+                // Only the compiled binary needs to be emulated, not the compiler
+                let (env, runner_prefix) = if path == output_binary_in_container {
+                    (
+                        CrowEnvironment::for_binary(task_id.to_string(), test, timeout),
+                        test.runner_prefix.as_slice(),
+                    )
+                } else {
+                    (
+                        CrowEnvironment::for_test(task_id.to_string(), test, timeout),
+                        &[][..],
+                    )
+                };
                 let res = test_container.execute_command(
                     path,
                     cmd,
+                    runner_prefix,
                     aborted.clone(),
                     timeout,
                     limits,
+                    &env,
+                    &test.permissions,
                     stdin,
+                    record_output,
                 );
                 match res {
                     Ok(res) => Ok(res),
@@ -581,12 +933,49 @@ impl TaskContainer<Built> {
             },
         );
 
-        Ok(res)
+        Ok(TestRun {
+            output,
+            invocation,
+            binary_size: fs::metadata(&output_binary_path).ok().map(|it| it.len()),
+        })
     }
 }
 
+/// The outcome of running a single test in a container
+pub struct TestRun {
+    pub output: TestExecutionOutput,
+    pub invocation: TestInvocation,
+    /// The size of the binary the compiler produced in bytes, if it produced one
+    pub binary_size: Option<u64>,
+}
+
+fn materialize_fileset(work_dir: &Path, fileset: &CategoryFileset) -> Result<(), TestRunError> {
+    for file in &fileset.files {
+        ensure!(
+            file.has_safe_path(),
+            UnsafeFilesetPathSnafu {
+                path: file.path.clone()
+            }
+        );
+        let path = work_dir.join(&file.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(WriteFilesetSnafu {
+                path: file.path.clone(),
+            })?;
+        }
+        fs::write(&path, &file.contents).context(WriteFilesetSnafu {
+            path: file.path.clone(),
+        })?;
+    }
+
+    Ok(())
+}
+
 impl<'a> TaskContainer<ForTest<'a>> {
-    pub fn new(outer: &'a TaskContainer<Built>) -> Result<Self, TestRunError> {
+    pub fn new(
+        outer: &'a TaskContainer<Built>,
+        limits: &LimitsConfig,
+    ) -> Result<Self, TestRunError> {
         if !outer.data.exit_status.success() {
             return Err(BaseNotBuiltSnafu {
                 exit_status: outer.data.exit_status,
@@ -598,8 +987,14 @@ impl<'a> TaskContainer<ForTest<'a>> {
             .context(TempDirCreationSnafu)
             .context(CreationSnafu)?;
         let container_id = ContainerId(Uuid::new_v4().to_string());
+        let disk_quota = limits
+            .mount_disk_quota(workdir.path())
+            .context(CreationSnafu)?;
 
-        let container_root = workdir.path().join("overlay-upper");
+        let container_root = disk_quota
+            .as_ref()
+            .map_or(workdir.path(), DiskQuota::path)
+            .join("overlay-upper");
         fs::create_dir(&container_root)
             .context(TempDirCreationSnafu)
             .context(CreationSnafu)?;
@@ -615,40 +1010,57 @@ impl<'a> TaskContainer<ForTest<'a>> {
             rootfs: container_root,
             container_id,
             do_cleanup: true,
+            disk_quota,
             data: ForTest { parent: outer },
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn execute_command(
         &mut self,
         binary_path: &Path,
         args: &[String],
+        runner_prefix: &[String],
         aborted: Arc<AtomicBool>,
         timeout: Duration,
         limits: &LimitsConfig,
+        env: &CrowEnvironment,
+        permissions: &ContainerPermissions,
         stdin: String,
+        record_output: bool,
     ) -> Result<CommandResult, TestRunError> {
         let mut full_command = vec![
             format!("/{CROW_SHIM_IN_CONTAINER_PATH}"),
             "shim".to_string(),
-            "--".to_string(),
-            binary_path.to_str().expect("path was Unicode").to_string(),
+            format!("--log-fd={SHIM_LOG_FD}"),
         ];
+        if env.disable_aslr {
+            full_command.push("--disable-aslr".to_string());
+        }
+        full_command.push("--".to_string());
+        full_command.extend_from_slice(runner_prefix);
+        full_command.push(binary_path.to_str().expect("path was Unicode").to_string());
         full_command.extend_from_slice(args);
 
-        ContainerConfig::OverlayRootfs
+        let layer_dir = self
+            .disk_quota
+            .as_ref()
+            .map_or(self.workdir.as_path(), DiskQuota::path);
+        ContainerConfig::OverlayRootfs { layer_dir }
             .apply_to_workdir(
                 &self.data.parent.rootfs,
                 &self.workdir,
                 &full_command,
                 true,
                 limits,
+                env,
+                permissions,
             )
             .context(ConfigApplySnafu)
             .context(CreationSnafu)?;
 
-        let mut process =
-            start_container(&self.workdir, &self.container_id).context(ExecutionStartSnafu)?;
+        let mut process = start_container(&self.workdir, &self.container_id, true)
+            .context(ExecutionStartSnafu)?;
 
         // Do this in a new thread to ensure it does not block ourselves, which would prevent
         // us from advancing the stdout of the child, creating a deadlock.
@@ -659,6 +1071,7 @@ impl<'a> TaskContainer<ForTest<'a>> {
                 .context(PassInputToContainerSnafu)
         });
 
+        let mut recording = record_output.then(OutputRecording::default);
         let res = wait_for_container(
             aborted,
             &self.container_id,
@@ -666,6 +1079,7 @@ impl<'a> TaskContainer<ForTest<'a>> {
             &mut process.stderr.take().unwrap(),
             &mut process,
             timeout,
+            recording.as_mut(),
         );
 
         // If we finished the stdin writing, and it had an error, we probably want to report it.
@@ -683,7 +1097,13 @@ impl<'a> TaskContainer<ForTest<'a>> {
             );
         }
 
-        wait_result_to_command_result(&self.container_id, res)
+        wait_result_to_command_result(
+            &self.container_id,
+            &self.workdir,
+            self.disk_quota.as_ref(),
+            recording,
+            res,
+        )
     }
 }
 
@@ -700,6 +1120,8 @@ impl<T> Drop for TaskContainer<T> {
                 "Failed to kill container"
             );
         }
+        // The quota lives in the work directory, so it must be unmounted before deleting it
+        drop(self.disk_quota.take());
         if let Err(e) = delete_container_dir(&self.container_id, &self.workdir) {
             error!(
                 error = %Report::from_error(e),
@@ -710,9 +1132,52 @@ impl<T> Drop for TaskContainer<T> {
     }
 }
 
-fn start_container(workdir: &Path, container_id: &ContainerId) -> io::Result<Child> {
-    Command::new("runc")
-        .arg("run")
+/// Starts the container, sending the logs of runc and, if requested, of the shim to files in the
+/// work directory instead of the stderr of the container
+fn start_container(
+    workdir: &Path,
+    container_id: &ContainerId,
+    with_shim_log: bool,
+) -> io::Result<Child> {
+    // The work directory is reused for all executions of a test
+    match fs::remove_file(workdir.join(RUNC_LOG_FILE)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    let mut command = Command::new("runc");
+    command
+        .arg("--log")
+        .arg(workdir.join(RUNC_LOG_FILE))
+        .arg("run");
+
+    // Must outlive the spawn, so the child can inherit it
+    let shim_log = if with_shim_log {
+        Some(fs::File::create(workdir.join(SHIM_LOG_FILE))?)
+    } else {
+        None
+    };
+    if let Some(shim_log) = &shim_log {
+        let fd = shim_log.as_raw_fd();
+        command.arg("--preserve-fds=1");
+        // SAFETY: Only async-signal-safe functions are called between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                // dup2 keeps the close-on-exec flag if both descriptors are the same
+                let res = if fd == SHIM_LOG_FD {
+                    libc::fcntl(fd, libc::F_SETFD, 0)
+                } else {
+                    libc::dup2(fd, SHIM_LOG_FD)
+                };
+                if res == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    command
         .arg(container_id.to_string())
         .current_dir(workdir)
         .stdout(Stdio::piped())
@@ -721,6 +1186,49 @@ fn start_container(workdir: &Path, container_id: &ContainerId) -> io::Result<Chi
         .spawn()
 }
 
+/// What runc and the shim logged while running a container
+struct InfrastructureLog {
+    log: Option<String>,
+    /// The signal the shim saw the program die of
+    signal: Option<i32>,
+    pids: Option<PidsUsage>,
+    resource_usage: Option<ResourceUsage>,
+}
+
+impl InfrastructureLog {
+    fn read(workdir: &Path) -> Self {
+        let runc_log = fs::read_to_string(workdir.join(RUNC_LOG_FILE)).unwrap_or_default();
+        let shim_log = fs::read_to_string(workdir.join(SHIM_LOG_FILE)).unwrap_or_default();
+
+        let mut trailer = None;
+        let mut lines = Vec::new();
+        for line in runc_log.lines().chain(shim_log.lines()) {
+            let parsed = line
+                .strip_prefix(CROW_SHIM_TRAILER_MAGIC)
+                .and_then(|rest| serde_json::from_str::<ShimTrailer>(rest).ok());
+            match parsed {
+                Some(parsed) => trailer = Some(parsed),
+                None => lines.push(line.to_string()),
+            }
+        }
+        if let Some(signal) = trailer.as_ref().and_then(|it| it.forwarded_signal) {
+            lines.push(format!("Forwarded signal {signal} to the program"));
+        }
+        if let Some(peak) = trailer.as_ref().and_then(|it| it.pids.peak) {
+            metrics::record_pids_peak(peak);
+            lines.push(format!("Peak number of processes and threads: {peak}"));
+        }
+        let log = lines.join("\n");
+
+        Self {
+            log: Some(log).filter(|it| !it.trim().is_empty()),
+            signal: trailer.as_ref().and_then(|it| it.signal),
+            resource_usage: trailer.as_ref().and_then(|it| it.resource_usage),
+            pids: trailer.map(|it| it.pids),
+        }
+    }
+}
+
 fn wait_for_container(
     aborted: Arc<AtomicBool>,
     container_id: &ContainerId,
@@ -728,8 +1236,10 @@ fn wait_for_container(
     stderr: &mut ChildStderr,
     process: &mut Child,
     timeout: Duration,
+    recording: Option<&mut OutputRecording>,
 ) -> Result<(String, String, ExitStatus, Duration), WaitForContainerError> {
-    let res = shared::execute::run_with_timeout(aborted, stdout, stderr, process, timeout);
+    let res =
+        shared::execute::run_with_timeout(aborted, stdout, stderr, process, timeout, recording);
 
     match res {
         Ok(res) => Ok(res),
@@ -874,11 +1384,17 @@ fn delete_container_dir(
 
 fn wait_result_to_command_result(
     container_id: &ContainerId,
+    workdir: &Path,
+    disk_quota: Option<&DiskQuota>,
+    recording: Option<OutputRecording>,
     res: Result<(String, String, ExitStatus, Duration), WaitForContainerError>,
 ) -> Result<CommandResult, TestRunError> {
-    let (stdout, mut stderr, exit_status, runtime) = match res {
+    let infrastructure_log = InfrastructureLog::read(workdir);
+    let (stdout, stderr, exit_status, runtime) = match res {
         Err(e) => {
-            if let Some(output) = execution_output_from_wait_error(&e) {
+            if let Some(output) =
+                execution_output_from_wait_error(&e, infrastructure_log.log, recording)
+            {
                 return Ok(CommandResult::ProcessedFailed(output));
             }
             return Err(e).context(ExecutionSnafu);
@@ -902,31 +1418,56 @@ fn wait_result_to_command_result(
         .into_error(NoneError));
     }
 
-    if let Some(last_line) = stderr.trim().lines().last() {
-        if let Some(rest) = last_line.strip_prefix(CROW_SIGNAL_SHIM_MAGIC) {
-            if let Ok(signal) = rest.trim().parse::<i32>() {
-                exit_status = CrowExitStatus::WithSignal { signal };
+    if let Some(signal) = infrastructure_log.signal {
+        exit_status = CrowExitStatus::WithSignal { signal };
+    }
 
-                // Remove our internal marker from stderr
-                let mut lines = stderr.lines().collect::<Vec<_>>();
-                lines.pop();
-                stderr = lines.join("\n");
-            }
-        }
+    let execution = FinishedExecution {
+        exit_status: exit_status.code(),
+        stdout,
+        stderr,
+        runtime,
+        infrastructure_log: infrastructure_log.log,
+        recording,
+        resource_usage: infrastructure_log.resource_usage,
+        full_output_id: None,
+    };
+
+    // Whatever the program did afterwards, it did not get the processes it asked for
+    if let Some(pids) = infrastructure_log.pids.filter(|it| it.limit_hits > 0) {
+        let limit = pids
+            .limit
+            .map(|it| format!(" of {it}"))
+            .unwrap_or(" of".to_string());
+        return Ok(CommandResult::ProcessedFailed(ExecutionOutput::Failure {
+            execution,
+            accumulated_errors: Some(format!(
+                "== ERROR ==\nThe program reached the limit{limit} processes and threads, \
+                creating more failed {} times.\n\n",
+                pids.limit_hits
+            )),
+        }));
     }
 
-    Ok(CommandResult::Unprocessed((
-        exit_status,
-        FinishedExecution {
-            exit_status: exit_status.code(),
-            stdout,
-            stderr,
-            runtime,
-        },
-    )))
+    if let Some(quota) = disk_quota.filter(|it| it.is_exhausted()) {
+        return Ok(CommandResult::ProcessedFailed(ExecutionOutput::Failure {
+            execution,
+            accumulated_errors: Some(format!(
+                "== ERROR ==\nDisk quota exceeded: the program used up the {} bytes it may \
+                write to disk.\n\n",
+                quota.bytes()
+            )),
+        }));
+    }
+
+    Ok(CommandResult::Unprocessed((exit_status, execution)))
 }
 
-pub fn execution_output_from_wait_error(error: &WaitForContainerError) -> Option<ExecutionOutput> {
+pub fn execution_output_from_wait_error(
+    error: &WaitForContainerError,
+    infrastructure_log: Option<String>,
+    recording: Option<OutputRecording>,
+) -> Option<ExecutionOutput> {
     if let WaitForContainerError::Timeout {
         runtime,
         stdout,
@@ -939,6 +1480,10 @@ pub fn execution_output_from_wait_error(error: &WaitForContainerError) -> Option
             stderr: stderr.clone(),
             runtime: *runtime,
             exit_status: None,
+            infrastructure_log,
+            recording,
+            resource_usage: None,
+            full_output_id: None,
         }));
     }
     if let WaitForContainerError::Aborted {
@@ -952,6 +1497,9 @@ pub fn execution_output_from_wait_error(error: &WaitForContainerError) -> Option
             stdout: stdout.clone(),
             stderr: stderr.clone(),
             runtime: *runtime,
+            infrastructure_log,
+            recording,
+            full_output_id: None,
         }));
     }
 
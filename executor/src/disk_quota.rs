@@ -0,0 +1,155 @@
+use snafu::{Location, Report, ResultExt, Snafu, ensure};
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, error};
+
+const IMAGE_FILE: &str = "disk.img";
+const MOUNT_DIR: &str = "disk";
+/// With less free space the filesystem counts as full, as ext4 rarely hands out its last blocks
+const EXHAUSTED_BELOW_BYTES: u64 = 1024 * 1024;
+
+#[derive(Snafu, Debug)]
+pub enum DiskQuotaError {
+    #[snafu(display("Could not create disk image `{path:?}` at {location}"))]
+    CreateImage {
+        source: io::Error,
+        path: PathBuf,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not run `{command}` at {location}"))]
+    CommandStart {
+        source: io::Error,
+        command: &'static str,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("`{command}` failed with {stdout} and {stderr} at {location}"))]
+    CommandFailed {
+        command: &'static str,
+        stdout: String,
+        stderr: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// A size limited ext4 filesystem on a sparse file in the work directory of a container, mounted
+/// via a loop device. Everything the container writes is placed in it, so a runaway program fills
+/// its quota instead of the disk of the runner. Setting it up needs the privileges to mount loop
+/// devices. Unmounted on drop.
+#[derive(Debug)]
+pub struct DiskQuota {
+    mount_point: PathBuf,
+    bytes: u64,
+}
+
+impl DiskQuota {
+    pub fn mount(workdir: &Path, bytes: u64) -> Result<Self, DiskQuotaError> {
+        let image = workdir.join(IMAGE_FILE);
+        let mount_point = workdir.join(MOUNT_DIR);
+
+        // Sparse, so the quota only takes up the space that is actually used
+        fs::File::create(&image)
+            .and_then(|file| file.set_len(bytes))
+            .context(CreateImageSnafu {
+                path: image.clone(),
+            })?;
+        fs::create_dir(&mount_point).context(CreateImageSnafu {
+            path: mount_point.clone(),
+        })?;
+
+        let owner = format!(
+            "root_owner={}:{}",
+            users::get_current_uid(),
+            users::get_current_gid()
+        );
+        run(
+            "mkfs.ext4",
+            Command::new("mkfs.ext4")
+                .arg("-q")
+                // Blocks reserved for root would be unusable for the container
+                .args(["-m", "0"])
+                .args(["-E", &owner])
+                .arg(&image),
+        )?;
+        run(
+            "mount",
+            Command::new("mount")
+                .args(["-o", "loop,nosuid,nodev"])
+                .arg(&image)
+                .arg(&mount_point),
+        )?;
+        debug!(mount_point = %mount_point.display(), bytes, "Mounted disk quota");
+
+        Ok(Self { mount_point, bytes })
+    }
+
+    /// The directory the container may write into
+    pub fn path(&self) -> &Path {
+        &self.mount_point
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Whether the filesystem ran out of space or inodes
+    pub fn is_exhausted(&self) -> bool {
+        let Ok(path) = CString::new(self.mount_point.as_os_str().as_bytes()) else {
+            return false;
+        };
+        let mut stats = MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: The path is a valid C string and the stats are written before they are read
+        let stats = unsafe {
+            if libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) != 0 {
+                return false;
+            }
+            stats.assume_init()
+        };
+
+        // The field types differ between platforms
+        #[allow(clippy::unnecessary_cast)]
+        let available = stats.f_bavail as u64 * stats.f_frsize as u64;
+        available < EXHAUSTED_BELOW_BYTES || stats.f_favail == 0
+    }
+}
+
+impl Drop for DiskQuota {
+    fn drop(&mut self) {
+        // Detach lazily, so a lingering process can not keep the work directory from being deleted
+        let res = run(
+            "umount",
+            Command::new("umount").arg("--lazy").arg(&self.mount_point),
+        );
+        if let Err(e) = res {
+            error!(
+                error = %Report::from_error(e),
+                mount_point = %self.mount_point.display(),
+                "Failed to unmount disk quota"
+            );
+        }
+    }
+}
+
+fn run(name: &'static str, command: &mut Command) -> Result<(), DiskQuotaError> {
+    let res = command
+        .output()
+        .context(CommandStartSnafu { command: name })?;
+
+    ensure!(
+        res.status.success(),
+        CommandFailedSnafu {
+            command: name,
+            stdout: String::from_utf8_lossy(&res.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+        }
+    );
+
+    Ok(())
+}
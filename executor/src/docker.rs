@@ -1,12 +1,16 @@
+use crate::metrics;
+use crate::mode_executor::config::RegistryAuth;
 use derive_more::Display;
 use file_guard::Lock;
 use file_guard::os::unix::FileGuardExt;
 use shared::exit::HandleExitcode;
 use shared::remove_directory_force;
 use snafu::{IntoError, Location, NoneError, ResultExt, Snafu, location};
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 use std::time::Instant;
 use tracing::{info, warn};
 
@@ -78,6 +82,22 @@ pub enum DockerError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Could not log in to registry `{registry}`: `{response}` at {location}"))]
+    RegistryLogin {
+        registry: String,
+        response: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display(
+        "Image `{image}` does not match its digest, the local image has `{actual}` at {location}"
+    ))]
+    DigestMismatch {
+        image: ImageId,
+        actual: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
     #[snafu(display("Could not export tar file at {location}"))]
     TarExportIo {
         source: std::io::Error,
@@ -95,6 +115,22 @@ pub enum DockerError {
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Display)]
 pub struct ImageId(pub String);
 
+impl ImageId {
+    /// The digest the image is pinned to, e.g. `sha256:...` for `alpine@sha256:...`
+    pub fn digest(&self) -> Option<&str> {
+        self.0.split_once('@').map(|(_, digest)| digest)
+    }
+
+    /// The registry host of the image. Like docker, the first path component is only a host if it
+    /// contains a `.` or `:` or is `localhost`; everything else comes from Docker Hub.
+    pub fn registry(&self) -> &str {
+        match self.0.split_once('/') {
+            Some((first, _)) if first.contains(['.', ':']) || first == "localhost" => first,
+            _ => "docker.io",
+        }
+    }
+}
+
 fn export_image_to_tar(image: &ImageId, target: &Path) -> Result<(), DockerError> {
     // Touch the file to ensure we can actually write to it
     File::create(target).context(TarExportIoSnafu)?;
@@ -131,12 +167,17 @@ fn export_image_to_tar(image: &ImageId, target: &Path) -> Result<(), DockerError
     Ok(())
 }
 
+#[derive(Clone)]
 pub struct Docker {
     cache_folder: Option<PathBuf>,
+    registries: BTreeMap<String, RegistryAuth>,
 }
 
 impl Docker {
-    pub fn new(cache_folder: Option<PathBuf>) -> Result<Self, DockerError> {
+    pub fn new(
+        cache_folder: Option<PathBuf>,
+        registries: BTreeMap<String, RegistryAuth>,
+    ) -> Result<Self, DockerError> {
         if let Some(cache) = &cache_folder {
             if cache.exists() {
                 remove_directory_force(cache).context(CacheDirDeleteSnafu {
@@ -148,7 +189,10 @@ impl Docker {
             })?;
         }
 
-        Ok(Self { cache_folder })
+        Ok(Self {
+            cache_folder,
+            registries,
+        })
     }
 
     pub fn export_image_unpacked(
@@ -156,11 +200,126 @@ impl Docker {
         image_name: &ImageId,
         target_folder: impl AsRef<Path>,
     ) -> Result<(), DockerError> {
+        self.ensure_image(image_name)?;
+
         if let Some(cache) = &self.cache_folder {
             return export_image_cached(image_name, target_folder, cache);
         }
         export_image_to_dir(image_name, target_folder.as_ref())
     }
+
+    /// Pulls the image if it is not present locally and verifies images pinned to a digest
+    /// actually have it. Tags are not pulled again once present.
+    fn ensure_image(&self, image: &ImageId) -> Result<(), DockerError> {
+        match get_docker_image_id(image) {
+            Ok(_) => {}
+            Err(DockerError::ImageNotFound { .. }) => self.pull_image(image)?,
+            Err(e) => return Err(e),
+        }
+
+        match image.digest() {
+            Some(digest) => verify_digest(image, digest),
+            None => Ok(()),
+        }
+    }
+
+    fn pull_image(&self, image: &ImageId) -> Result<(), DockerError> {
+        let registry = image.registry();
+        if let Some(auth) = self.registries.get(registry) {
+            login(registry, auth)?;
+        }
+
+        let now = Instant::now();
+        info!(image_name = %image, registry = %registry, "Pulling image");
+        Command::new("docker")
+            .arg("pull")
+            .arg("-q")
+            .arg(image.to_string())
+            .handle_exitcode()
+            .context(DockerCallSnafu {
+                message: "pulling image",
+            })?;
+        info!(
+            image_name = %image,
+            duration_s = %now.elapsed().as_secs(),
+            "Image pulled"
+        );
+
+        Ok(())
+    }
+}
+
+fn login(registry: &str, auth: &RegistryAuth) -> Result<(), DockerError> {
+    let mut child = Command::new("docker")
+        .arg("login")
+        .arg("--username")
+        .arg(&auth.username)
+        .arg("--password-stdin")
+        .arg(registry)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(DockerCallSnafu {
+            message: "logging in to registry",
+        })?;
+    // Dropping stdin afterwards closes it, so docker knows the token is complete
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(auth.token.as_bytes())
+        .context(DockerCallSnafu {
+            message: "passing registry token",
+        })?;
+    let output = child.wait_with_output().context(DockerCallSnafu {
+        message: "logging in to registry",
+    })?;
+
+    if !output.status.success() {
+        return RegistryLoginSnafu {
+            registry,
+            response: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+        .fail();
+    }
+
+    Ok(())
+}
+
+fn verify_digest(image: &ImageId, digest: &str) -> Result<(), DockerError> {
+    let output = Command::new("docker")
+        .arg("image")
+        .arg("inspect")
+        .arg(image.to_string())
+        .arg("--format")
+        .arg("{{ json .RepoDigests }}")
+        .handle_exitcode()
+        .context(DockerCallSnafu {
+            message: "reading image digests",
+        })?;
+    let response = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let Ok(repo_digests) = serde_json::from_str::<Option<Vec<String>>>(&response) else {
+        return UnknownDockerResponseSnafu {
+            message: "while reading image digests",
+            response,
+        }
+        .fail();
+    };
+    let repo_digests = repo_digests.unwrap_or_default();
+
+    let matches = repo_digests
+        .iter()
+        .any(|it| it.split_once('@').is_some_and(|(_, it)| it == digest));
+    if !matches {
+        return DigestMismatchSnafu {
+            image: image.clone(),
+            actual: repo_digests.join(", "),
+        }
+        .fail();
+    }
+
+    Ok(())
 }
 
 fn export_image_cached(
@@ -187,6 +346,7 @@ fn export_image_cached(
             path: lockfile_path.to_path_buf(),
         })?;
 
+    metrics::record_image_cache_lookup(cached_path.exists());
     if cached_path.exists() {
         let now = Instant::now();
 
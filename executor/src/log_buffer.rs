@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// How many of the most recent log lines are kept for admins to inspect
+const MAX_LINES: usize = 1000;
+
+static LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// A writer for the tracing formatter that keeps the most recent log lines in memory, so the
+/// runner can push them to the backend when an admin asks for them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogBuffer;
+
+impl LogBuffer {
+    /// The buffered lines, oldest first
+    pub fn recent_lines() -> Vec<String> {
+        LINES.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        let mut lines = LINES.lock().unwrap();
+        for line in text.lines() {
+            if lines.len() == MAX_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line.to_string());
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MakeWriter<'_> for LogBuffer {
+    type Writer = Self;
+
+    fn make_writer(&self) -> Self::Writer {
+        *self
+    }
+}
@@ -4,7 +4,10 @@
 #![allow(unsafe_code)]
 
 use crate::containers::{ContainerCreateError, TestRunError, WaitForContainerError};
-use crate::mode_executor::{CliExecutorArgs, run_executor};
+use crate::log_buffer::LogBuffer;
+use crate::metrics::MetricLabel;
+use crate::mode_executor::config::ConfigError;
+use crate::mode_executor::{CliExecutorCommand, run_executor_command};
 use crate::mode_shim::{CliShimArgs, run_shim};
 use clap::builder::Styles;
 use clap::builder::styling::AnsiColor;
@@ -15,7 +18,10 @@ use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
 mod containers;
+mod disk_quota;
 mod docker;
+mod log_buffer;
+mod metrics;
 mod mode_executor;
 mod mode_shim;
 mod task_executor;
@@ -79,6 +85,37 @@ pub enum AnyError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display("Could not export metrics at {location}"))]
+    Metrics {
+        source: metrics::MetricsError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Invalid executor configuration at {location}"))]
+    Config {
+        source: ConfigError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+impl MetricLabel for AnyError {
+    fn metric_label(&self) -> &'static str {
+        match self {
+            Self::Run { .. } => "run",
+            Self::Create { .. } => "create",
+            Self::WaitForBuild { .. } => "wait_for_build",
+            Self::TestRun { .. } => "test_run",
+            Self::ThreadPoolBuild { .. } => "thread_pool_build",
+            Self::Reqwest { .. } => "reqwest",
+            Self::TempFile { .. } => "temp_file",
+            Self::Shim { .. } => "shim",
+            Self::ShimWithSource { .. } => "shim_with_source",
+            Self::Docker { .. } => "docker",
+            Self::Metrics { .. } => "metrics",
+            Self::Config { .. } => "config",
+        }
+    }
 }
 
 // noinspection DuplicatedCode
@@ -101,16 +138,18 @@ struct CliArgs {
 }
 
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)] // parsed once at startup, the size does not matter
 enum CliCommand {
     /// Runs the executor fetching tasks from the server and executing them.
-    Executor(CliExecutorArgs),
-    /// Runs the executor in-container shim translating killed-by-signal
+    Executor(CliExecutorCommand),
+    /// Runs the executor in-container shim reporting signals and resource usage of the program
     Shim(CliShimArgs),
 }
 
 struct Endpoints {
     done: String,
     done_taste_test: String,
+    logs: String,
     ping: String,
     register: String,
     tar: String,
@@ -124,6 +163,7 @@ impl Endpoints {
         Self {
             done: format!("{base}/executor/done"),
             done_taste_test: format!("{base}/executor/done-taste-test"),
+            logs: format!("{base}/executor/logs"),
             ping: format!("{base}/executor/ping"),
             register: format!("{base}/executor/register"),
             tar: format!("{base}/executor/request-tar"),
@@ -138,6 +178,12 @@ fn main() -> Report<AnyError> {
     Report::capture(|| {
         tracing_subscriber::registry()
             .with(tracing_subscriber::fmt::layer())
+            // Kept for admins, who can ask the runner for its recent logs
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(LogBuffer),
+            )
             .with(
                 tracing_subscriber::EnvFilter::try_from_default_env()
                     .unwrap_or_else(|_| "info".into()),
@@ -147,7 +193,7 @@ fn main() -> Report<AnyError> {
         let args = CliArgs::parse();
 
         match args.subcommand {
-            CliCommand::Executor(args) => run_executor(args),
+            CliCommand::Executor(command) => run_executor_command(command),
             CliCommand::Shim(args) => run_shim(args),
         }
     })
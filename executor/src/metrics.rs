@@ -0,0 +1,130 @@
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
+use snafu::{Location, ResultExt, Snafu};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+pub const POLL_DURATION: &str = "executor_poll_duration_seconds";
+pub const TASK_DURATION: &str = "executor_task_duration_seconds";
+pub const BUILD_DURATION: &str = "executor_build_duration_seconds";
+pub const TEST_DURATION: &str = "executor_test_duration_seconds";
+pub const OVERLAY_SETUP_DURATION: &str = "executor_overlay_setup_duration_seconds";
+pub const FAILURES: &str = "executor_failures_total";
+pub const PIDS_PEAK: &str = "executor_test_pids_peak";
+pub const CONTAINER_SETUP_DURATION: &str = "executor_container_setup_duration_seconds";
+pub const TASKS_EXECUTED: &str = "executor_tasks_executed_total";
+pub const TESTS_EXECUTED: &str = "executor_tests_executed_total";
+pub const IMAGE_CACHE_LOOKUPS: &str = "executor_image_cache_lookups_total";
+pub const UPDATE_SPOOL_SIZE: &str = "executor_update_spool_size";
+pub const CURRENT_TASK: &str = "executor_current_task";
+
+#[derive(Debug, Snafu)]
+pub enum MetricsError {
+    #[snafu(display("Could not install the metrics recorder at {location}"))]
+    InstallRecorder {
+        source: BuildError,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not bind the metrics server to `{address}` at {location}"))]
+    Bind {
+        source: std::io::Error,
+        address: SocketAddr,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// Errors that are counted per variant in [FAILURES]
+pub trait MetricLabel {
+    fn metric_label(&self) -> &'static str;
+}
+
+/// Counts a failure, labelled with the stage it happened in and the error variant
+pub fn record_failure(stage: &'static str, error: &impl MetricLabel) {
+    metrics::counter!(FAILURES, "stage" => stage, "variant" => error.metric_label()).increment(1);
+}
+
+/// Records how long a phase took in the histogram `name`
+pub fn record_duration(name: &'static str, duration: Duration) {
+    metrics::histogram!(name).record(duration);
+}
+
+/// Records the most processes and threads a test container had alive at once in [PIDS_PEAK]
+pub fn record_pids_peak(peak: u64) {
+    metrics::histogram!(PIDS_PEAK).record(peak as f64);
+}
+
+/// Counts a finished task in [TASKS_EXECUTED], labelled with whether its build failed
+pub fn record_task_executed(outcome: &'static str) {
+    metrics::counter!(TASKS_EXECUTED, "outcome" => outcome).increment(1);
+}
+
+/// Counts a finished test in [TESTS_EXECUTED]. Its rate is the number of tests per second.
+pub fn record_test_executed() {
+    metrics::counter!(TESTS_EXECUTED).increment(1);
+}
+
+/// Counts a lookup of the image cache in [IMAGE_CACHE_LOOKUPS]
+pub fn record_image_cache_lookup(hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    metrics::counter!(IMAGE_CACHE_LOOKUPS, "result" => result).increment(1);
+}
+
+/// Tracks the runner updates that were produced but not yet sent to the backend in
+/// [UPDATE_SPOOL_SIZE]
+pub fn record_spool_change(added: usize, sent: usize) {
+    let gauge = metrics::gauge!(UPDATE_SPOOL_SIZE);
+    gauge.increment(added as f64);
+    gauge.decrement(sent as f64);
+}
+
+/// Sets [CURRENT_TASK] to 1 for the task while the runner executes it and back to 0 afterwards
+pub fn record_current_task(runner: &str, task: &str, running: bool) {
+    metrics::gauge!(CURRENT_TASK, "runner" => runner.to_string(), "task" => task.to_string())
+        .set(if running { 1.0 } else { 0.0 });
+}
+
+/// Installs the prometheus recorder and serves the metrics at `address` in a background thread.
+pub fn start_metrics_server(address: SocketAddr) -> Result<(), MetricsError> {
+    let handle = PrometheusBuilder::new()
+        .install_recorder()
+        .context(InstallRecorderSnafu)?;
+    let listener = TcpListener::bind(address).context(BindSnafu { address })?;
+    info!(address = %address, "Serving metrics");
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let res = stream.and_then(|stream| serve_metrics(stream, &handle));
+            if let Err(e) = res {
+                warn!(error = ?e, "Failed to serve metrics");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Answers every request with the rendered metrics. Prometheus only ever asks for them, so we do
+/// not bother looking at the path.
+fn serve_metrics(mut stream: TcpStream, handle: &PrometheusHandle) -> std::io::Result<()> {
+    // Read the request head, the body (if any) is irrelevant
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let body = handle.render();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
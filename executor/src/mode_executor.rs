@@ -1,25 +1,41 @@
 use crate::containers::LimitsConfig;
 use crate::docker::Docker;
-use crate::{AnyError, Endpoints, ReqwestSnafu};
-use clap::Args;
+use crate::log_buffer::LogBuffer;
+use crate::mode_executor::config::ExecutorConfig;
+use crate::{AnyError, ConfigSnafu, Endpoints, MetricsSnafu, ReqwestSnafu, metrics};
+use clap::{Args, Subcommand};
 use reqwest::blocking::{Client, ClientBuilder};
-use shared::{RunnerInfo, RunnerUpdate};
+use shared::{RunnerInfo, RunnerLogs, RunnerPingResponse, RunnerUpdate, TaskRunnerUpdate};
 use snafu::{Report, ResultExt, location};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
+pub mod config;
 mod test_compiler;
 mod test_tasting;
 
+/// The task this runner is currently executing. It is shared with the ping thread, which aborts
+/// the task once the backend reports that our lease for it was lost.
+pub type CurrentTask = Arc<Mutex<Option<LeasedTask>>>;
+
+pub struct LeasedTask {
+    pub task_id: String,
+    pub aborted: Arc<AtomicBool>,
+    /// Whether the backend reclaimed the task. Results of lost tasks are not reported.
+    pub lost: bool,
+}
+
 pub trait Iteration {
     fn iteration(
         &mut self,
-        args: &CliExecutorArgs,
+        args: &ExecutorConfig,
         endpoints: &Endpoints,
         current_backoff: &mut Duration,
         shutdown_requested: &Arc<AtomicBool>,
@@ -29,48 +45,144 @@ pub trait Iteration {
 }
 
 #[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct CliExecutorCommand {
+    #[command(subcommand)]
+    command: Option<CliExecutorSubcommand>,
+    #[command(flatten)]
+    args: CliExecutorArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum CliExecutorSubcommand {
+    /// Prints the effective configuration merged from the config file, environment and command
+    /// line, with the token redacted
+    PrintConfig(CliExecutorArgs),
+}
+
+/// Every value can also be set in the config file. The command line takes precedence over the
+/// environment, which takes precedence over the config file.
+#[derive(Args, Debug, Clone)]
 pub struct CliExecutorArgs {
+    /// A TOML file containing the executor configuration
+    #[clap(long, short = 'c', env = "CROW_EXECUTOR_CONFIG")]
+    pub config: Option<PathBuf>,
     /// A unique name for this runner
-    pub id: String,
+    #[clap(env = "CROW_EXECUTOR_ID")]
+    pub id: Option<String>,
     /// The runner token of the server
-    pub token: String,
+    #[clap(env = "CROW_EXECUTOR_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
     /// Endpoint to poll for work updates
-    pub endpoint: String,
+    #[clap(env = "CROW_EXECUTOR_ENDPOINT")]
+    pub endpoint: Option<String>,
+    /// A file containing the runner token. Ignored if the token is passed directly.
+    #[clap(long, env = "CROW_EXECUTOR_TOKEN_FILE")]
+    pub token_file: Option<PathBuf>,
     /// If set, this executor will request the reference compiler and then only accept tests
-    /// to validate against it
-    #[clap(long, default_value = "false")]
+    /// to validate against it. Admins can change the role while the executor runs.
+    #[clap(long, env = "CROW_EXECUTOR_TEST_TASTER")]
     pub test_taster: bool,
     /// The directory to cache docker images in. If not set, no cache will be used.
-    #[clap(long)]
+    #[clap(long, env = "CROW_EXECUTOR_IMAGE_CACHE_DIR")]
     pub image_cache_dir: Option<PathBuf>,
     /// The amount of tests to execute in parallel. If 0, the number of processors is used.
-    #[clap(long, short = 'j', default_value = "0")]
-    pub parallelism: usize,
-    /// The number of CPUs to allow the build container. 0 means no limit.
-    #[clap(long, default_value = "0")]
-    pub build_max_cpu: u32,
-    /// The max ram size in bytes to allow the build container. 0 means no limit.
-    #[clap(long, default_value_t = 5 * 1024 * 1024 * 1024)]
-    pub build_max_memory: usize,
-    /// The number of CPUs to allow the test containers. 0 means no limit.
-    #[clap(long, default_value = "1")]
-    pub test_max_cpu: u32,
+    /// Split evenly between concurrently running tasks. [default: 0]
+    #[clap(long, short = 'j', env = "CROW_EXECUTOR_PARALLELISM")]
+    pub parallelism: Option<usize>,
+    /// The number of tasks to execute concurrently. Each task registers as its own runner, named
+    /// `<id>-<n>`, and gets an even share of the processors and of the build cpu and memory
+    /// limits below, which then describe the whole machine. [default: 1]
+    #[clap(long, env = "CROW_EXECUTOR_CONCURRENT_TASKS")]
+    pub concurrent_tasks: Option<usize>,
+    /// The number of CPUs to allow the build container. 0 means no limit, or an even share of the
+    /// processors when running multiple tasks concurrently. Explicit limits are split evenly
+    /// between concurrently running tasks as well. [default: 0]
+    #[clap(long, env = "CROW_EXECUTOR_BUILD_MAX_CPU")]
+    pub build_max_cpu: Option<u32>,
+    /// The max ram size in bytes to allow the build container, split evenly between concurrently
+    /// running tasks. 0 means no limit. [default: 5 GiB]
+    #[clap(long, env = "CROW_EXECUTOR_BUILD_MAX_MEMORY")]
+    pub build_max_memory: Option<usize>,
+    /// The max number of processes and threads in the build container of each task. 0 means no
+    /// limit. [default: 0]
+    #[clap(long, env = "CROW_EXECUTOR_BUILD_MAX_PIDS")]
+    pub build_max_pids: Option<u32>,
+    /// The max disk space in bytes the build container of each task may use, including its
+    /// image. Needs the privileges to mount loop devices. 0 means no limit. [default: 0]
+    #[clap(long, env = "CROW_EXECUTOR_BUILD_MAX_DISK")]
+    pub build_max_disk: Option<u64>,
+    /// The number of CPUs to allow the test containers. 0 means no limit. [default: 1]
+    #[clap(long, env = "CROW_EXECUTOR_TEST_MAX_CPU")]
+    pub test_max_cpu: Option<u32>,
     /// The max ram size in bytes to allow the test containers. 0 means no limit.
-    #[clap(long, default_value_t = 2 * 1024 * 1024 * 1024)]
-    pub test_max_memory: usize,
+    /// [default: 2 GiB]
+    #[clap(long, env = "CROW_EXECUTOR_TEST_MAX_MEMORY")]
+    pub test_max_memory: Option<usize>,
+    /// The max number of processes and threads in each test container, so fork bombs can not
+    /// take down the runner. 0 means no limit. [default: 512]
+    #[clap(long, env = "CROW_EXECUTOR_TEST_MAX_PIDS")]
+    pub test_max_pids: Option<u32>,
+    /// The max disk space in bytes each test container may write. Needs the privileges to mount
+    /// loop devices. 0 means no limit. [default: 0]
+    #[clap(long, env = "CROW_EXECUTOR_TEST_MAX_DISK")]
+    pub test_max_disk: Option<u64>,
+    /// The address to serve prometheus metrics on, e.g. `0.0.0.0:9100`. If not set, no metrics
+    /// are exported.
+    #[clap(long, env = "CROW_EXECUTOR_METRICS_ADDRESS")]
+    pub metrics_address: Option<SocketAddr>,
+    /// The directory to place temporary build and test files in. Defaults to the system temp
+    /// directory.
+    #[clap(long, env = "CROW_EXECUTOR_WORK_DIR")]
+    pub work_dir: Option<PathBuf>,
+    /// Labels describing this runner, shown to admins in the runner overview. Can be repeated.
+    #[clap(long = "label", env = "CROW_EXECUTOR_LABELS", value_delimiter = ',')]
+    pub labels: Vec<String>,
+    /// Host directories categories may bind mount into containers. Mounts of anything else are
+    /// refused. Can be repeated.
+    #[clap(
+        long = "allowed-bind-mount",
+        env = "CROW_EXECUTOR_ALLOWED_BIND_MOUNTS",
+        value_delimiter = ','
+    )]
+    pub allowed_bind_mounts: Vec<PathBuf>,
 }
 
-pub fn run_executor(args: CliExecutorArgs) -> Result<(), AnyError> {
-    let endpoints = Endpoints::new(&args.endpoint);
+pub fn run_executor_command(command: CliExecutorCommand) -> Result<(), AnyError> {
+    match command.command {
+        Some(CliExecutorSubcommand::PrintConfig(args)) => {
+            let config = ExecutorConfig::resolve(args).context(ConfigSnafu)?;
+            print!("{}", config.to_toml());
+            Ok(())
+        }
+        None => {
+            let config = ExecutorConfig::resolve(command.args).context(ConfigSnafu)?;
+            config.apply_work_dir().context(ConfigSnafu)?;
+            run_executor(config)
+        }
+    }
+}
 
-    let mut current_backoff = Duration::from_secs(1);
+fn run_executor(args: ExecutorConfig) -> Result<(), AnyError> {
+    let endpoints = Endpoints::new(&args.endpoint);
     let shutdown_requested = Arc::new(AtomicBool::new(false));
 
-    register_termination_handler(&shutdown_requested);
-    start_periodic_pings(&endpoints, &args);
+    // Test tasting is cheap, so there is nothing to gain from running multiple tastings at once.
+    // Full tasks a taster picks up in between get the whole machine.
+    let slots = if args.test_taster {
+        1
+    } else {
+        args.concurrent_tasks.max(1)
+    };
+    let current_tasks: Vec<CurrentTask> = (0..slots).map(|_| Arc::new(Mutex::new(None))).collect();
 
-    let client = ClientBuilder::new().build().context(ReqwestSnafu)?;
-    let docker = match Docker::new(args.image_cache_dir.clone()) {
+    if let Some(address) = args.metrics_address {
+        metrics::start_metrics_server(address).context(MetricsSnafu)?;
+    }
+
+    register_termination_handler(&shutdown_requested, &current_tasks);
+
+    let docker = match Docker::new(args.image_cache_dir.clone(), args.registries.clone()) {
         Ok(docker) => docker,
         Err(e) => {
             return Err(AnyError::Docker {
@@ -80,72 +192,212 @@ pub fn run_executor(args: CliExecutorArgs) -> Result<(), AnyError> {
         }
     };
 
-    let mut iteration: Box<dyn Iteration> = if args.test_taster {
-        Box::new(test_tasting::TestTastingState::new(
-            docker,
-            LimitsConfig::new(args.test_max_cpu, args.test_max_memory),
-        ))
-    } else {
-        Box::new(test_compiler::TestCompilerState::new(
-            docker,
-            args.parallelism,
-            LimitsConfig::new(args.build_max_cpu, args.build_max_memory),
-            LimitsConfig::new(args.test_max_cpu, args.test_max_memory),
-        )?)
-    };
+    let results = thread::scope(|s| {
+        let handles = current_tasks
+            .into_iter()
+            .enumerate()
+            .map(|(slot, current_task)| {
+                let args = args.for_slot(slot, slots);
+                let docker = docker.clone();
+                let endpoints = &endpoints;
+                let shutdown_requested = &shutdown_requested;
+                s.spawn(move || {
+                    run_slot(&args, endpoints, docker, current_task, shutdown_requested)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|it| it.join().expect("executor slot panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    info!("Goodbye!");
+
+    results.into_iter().collect()
+}
+
+/// Claims and executes tasks one after another until a shutdown is requested
+fn run_slot(
+    args: &ExecutorConfig,
+    endpoints: &Endpoints,
+    docker: Docker,
+    current_task: CurrentTask,
+    shutdown_requested: &Arc<AtomicBool>,
+) -> Result<(), AnyError> {
+    let mut current_backoff = Duration::from_secs(1);
+
+    // Admins can move the runner between grading and tasting, the pings tell us about it
+    let test_taster = Arc::new(AtomicBool::new(args.test_taster));
+    start_periodic_pings(endpoints, args, current_task.clone(), test_taster.clone());
+
+    let client = ClientBuilder::new().build().context(ReqwestSnafu)?;
+
+    let mut iteration_tastes = args.test_taster;
+    let mut iteration =
+        new_iteration(args, docker.clone(), current_task.clone(), iteration_tastes)?;
 
     while !shutdown_requested.load(Ordering::Relaxed) {
+        let should_taste = test_taster.load(Ordering::Relaxed);
+        if should_taste != iteration_tastes {
+            info!(
+                runner = args.id,
+                test_taster = should_taste,
+                "Switching runner role"
+            );
+            iteration = new_iteration(args, docker.clone(), current_task.clone(), should_taste)?;
+            iteration_tastes = should_taste;
+        }
+
+        // Always the configured role, so the backend can restore it when the assignment is cleared
         let runner_info = RunnerInfo {
             id: args.id.clone().into(),
-            info: "hey".to_string(),
+            info: args.labels.join(", "),
             current_task: None,
             test_taster: args.test_taster,
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
         };
         let res = iteration.iteration(
-            &args,
-            &endpoints,
+            args,
+            endpoints,
             &mut current_backoff,
-            &shutdown_requested,
+            shutdown_requested,
             &client,
             &runner_info,
         );
         if let Err(e) = res {
+            metrics::record_failure("iteration", &e);
             // Emergency wait to prevent busy loops
             let mut emergency_backoff = Duration::from_secs(5);
             warn!(
+                runner = args.id,
                 backoff = ?emergency_backoff,
                 error = ?Report::from_error(e),
                 "Error during iteration"
             );
-            backoff(&mut emergency_backoff, &shutdown_requested);
+            backoff(&mut emergency_backoff, shutdown_requested);
         }
     }
 
-    info!("Goodbye!");
-
     Ok(())
 }
 
-fn start_periodic_pings(endpoints: &Endpoints, args: &CliExecutorArgs) {
+fn new_iteration(
+    args: &ExecutorConfig,
+    docker: Docker,
+    current_task: CurrentTask,
+    test_taster: bool,
+) -> Result<Box<dyn Iteration>, AnyError> {
+    let test_limits = LimitsConfig::new(
+        args.test_max_cpu,
+        args.test_max_memory,
+        args.test_max_pids,
+        args.test_max_disk,
+    );
+    let grading = test_compiler::TestCompilerState::new(
+        docker.clone(),
+        args.parallelism,
+        LimitsConfig::new(
+            args.build_max_cpu,
+            args.build_max_memory,
+            args.build_max_pids,
+            args.build_max_disk,
+        ),
+        test_limits.clone(),
+        args.allowed_bind_mounts.clone(),
+        current_task,
+    )?;
+    if test_taster {
+        return Ok(Box::new(test_tasting::TestTastingState::new(
+            docker,
+            test_limits,
+            args.allowed_bind_mounts.clone(),
+            grading,
+        )));
+    }
+
+    Ok(Box::new(grading))
+}
+
+fn start_periodic_pings(
+    endpoints: &Endpoints,
+    args: &ExecutorConfig,
+    current_task: CurrentTask,
+    test_taster: Arc<AtomicBool>,
+) {
     let id = args.id.clone();
     let token = args.token.clone();
     let url = endpoints.ping.clone();
+    let logs_url = endpoints.logs.clone();
     thread::spawn(move || {
         let client = Client::new();
         loop {
             thread::sleep(Duration::from_secs(15));
-            let _ = client
+
+            // Only tasks we started before sending the ping can be judged by its response
+            let task_before_ping = current_task
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|it| it.task_id.clone());
+
+            let response = client
                 .post(&url)
                 .basic_auth(&id, Some(&token))
                 .send()
+                .and_then(|it| it.error_for_status())
+                .and_then(|it| it.json::<RunnerPingResponse>())
                 .context(ReqwestSnafu);
+            let response = match response {
+                Err(e) => {
+                    debug!(error = ?Report::from_error(e), "Failed to ping backend");
+                    continue;
+                }
+                Ok(response) => response,
+            };
+            if let Some(role) = response.test_taster {
+                test_taster.store(role, Ordering::Relaxed);
+            }
+            if response.send_logs {
+                let logs = RunnerLogs {
+                    lines: LogBuffer::recent_lines(),
+                };
+                let res = client
+                    .post(&logs_url)
+                    .basic_auth(&id, Some(&token))
+                    .json(&logs)
+                    .send()
+                    .and_then(|it| it.error_for_status());
+                if let Err(e) = res {
+                    warn!(error = ?Report::from_error(e), "Failed to send logs to backend");
+                }
+            }
+
+            let Some(task_before_ping) = task_before_ping else {
+                continue;
+            };
+            if response.leased_task.as_ref() == Some(&task_before_ping) {
+                continue;
+            }
+
+            let mut current_task = current_task.lock().unwrap();
+            if let Some(task) = current_task
+                .as_mut()
+                .filter(|it| it.task_id == task_before_ping)
+            {
+                warn!(task = %task.task_id, "Lease for task was lost, aborting it");
+                task.lost = true;
+                task.aborted.store(true, Ordering::Relaxed);
+            }
         }
     });
 }
 
 fn start_update_listener(
-    args: &CliExecutorArgs,
+    args: &ExecutorConfig,
     endpoints: &Endpoints,
+    task_id: String,
     rx: Receiver<RunnerUpdate>,
 ) {
     let id = args.id.clone();
@@ -155,7 +407,25 @@ fn start_update_listener(
     // This is a daemon thread, so we do not care about the stop flag
     thread::spawn(move || {
         let client = Client::new();
-        while let Ok(event) = rx.recv() {
+        // Updates are moved out of the channel before sending, so we know how many are waiting
+        let mut spool = VecDeque::new();
+        loop {
+            let spooled = spool.len();
+            if spool.is_empty() {
+                let Ok(update) = rx.recv() else {
+                    break;
+                };
+                spool.push_back(update);
+            }
+            spool.extend(rx.try_iter());
+            let added = spool.len() - spooled;
+            let update = spool.pop_front().expect("spool is not empty");
+            metrics::record_spool_change(added, 1);
+
+            let event = TaskRunnerUpdate {
+                task_id: Some(task_id.clone()),
+                update,
+            };
             let res = client
                 .post(&update_endpoint)
                 .json(&event)
@@ -174,10 +444,16 @@ fn start_update_listener(
     });
 }
 
-fn register_termination_handler(stop_requested: &Arc<AtomicBool>) {
+fn register_termination_handler(stop_requested: &Arc<AtomicBool>, current_tasks: &[CurrentTask]) {
     let stop_requested_clone = stop_requested.clone();
+    let current_tasks = current_tasks.to_vec();
     let ctrlc_result = ctrlc::set_handler(move || {
         stop_requested_clone.store(true, Ordering::Relaxed);
+        for current_task in &current_tasks {
+            if let Some(task) = current_task.lock().unwrap().as_ref() {
+                task.aborted.store(true, Ordering::Relaxed);
+            }
+        }
     });
 
     if let Err(e) = ctrlc_result {
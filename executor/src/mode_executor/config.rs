@@ -0,0 +1,285 @@
+use crate::mode_executor::CliExecutorArgs;
+use serde::{Deserialize, Serialize, Serializer};
+use snafu::{IntoError, Location, NoneError, ResultExt, Snafu};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+const DEFAULT_BUILD_MAX_MEMORY: usize = 5 * 1024 * 1024 * 1024;
+const DEFAULT_TEST_MAX_CPU: u32 = 1;
+const DEFAULT_TEST_MAX_MEMORY: usize = 2 * 1024 * 1024 * 1024;
+const DEFAULT_TEST_MAX_PIDS: u32 = 512;
+
+#[derive(Debug, Snafu)]
+pub enum ConfigError {
+    #[snafu(display("Could not read config file `{}` at {location}", path.display()))]
+    ReadConfig {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not parse config file `{}` at {location}", path.display()))]
+    ParseConfig {
+        path: PathBuf,
+        source: toml::de::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not read token file `{}` at {location}", path.display()))]
+    ReadTokenFile {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("No token was configured for registry `{registry}` at {location}"))]
+    MissingRegistryToken {
+        registry: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("No {what} was configured at {location}"))]
+    Missing {
+        what: &'static str,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("Could not create work directory `{}` at {location}", path.display()))]
+    CreateWorkDir {
+        path: PathBuf,
+        source: std::io::Error,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+/// The contents of an `executor.toml`. Every value is optional and can be overwritten by the
+/// command line or environment.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    id: Option<String>,
+    token: Option<String>,
+    token_file: Option<PathBuf>,
+    endpoint: Option<String>,
+    test_taster: Option<bool>,
+    image_cache_dir: Option<PathBuf>,
+    parallelism: Option<usize>,
+    concurrent_tasks: Option<usize>,
+    build_max_cpu: Option<u32>,
+    build_max_memory: Option<usize>,
+    build_max_pids: Option<u32>,
+    build_max_disk: Option<u64>,
+    test_max_cpu: Option<u32>,
+    test_max_memory: Option<usize>,
+    test_max_pids: Option<u32>,
+    test_max_disk: Option<u64>,
+    metrics_address: Option<SocketAddr>,
+    work_dir: Option<PathBuf>,
+    labels: Option<Vec<String>>,
+    allowed_bind_mounts: Option<Vec<PathBuf>>,
+    registries: Option<BTreeMap<String, RegistryEntry>>,
+}
+
+/// The credentials of a registry in the config file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryEntry {
+    username: String,
+    token: Option<String>,
+    token_file: Option<PathBuf>,
+}
+
+/// The credentials to log in to a private registry with before pulling images from it
+#[derive(Debug, Clone, Serialize)]
+pub struct RegistryAuth {
+    pub username: String,
+    #[serde(serialize_with = "redacted")]
+    pub token: String,
+}
+
+/// The effective executor configuration, merged from the command line, the environment and the
+/// config file in that order of precedence
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutorConfig {
+    pub id: String,
+    #[serde(serialize_with = "redacted")]
+    pub token: String,
+    pub endpoint: String,
+    pub test_taster: bool,
+    pub image_cache_dir: Option<PathBuf>,
+    pub parallelism: usize,
+    pub concurrent_tasks: usize,
+    pub build_max_cpu: u32,
+    pub build_max_memory: usize,
+    pub build_max_pids: u32,
+    pub build_max_disk: u64,
+    pub test_max_cpu: u32,
+    pub test_max_memory: usize,
+    pub test_max_pids: u32,
+    pub test_max_disk: u64,
+    pub metrics_address: Option<SocketAddr>,
+    pub work_dir: Option<PathBuf>,
+    pub labels: Vec<String>,
+    pub allowed_bind_mounts: Vec<PathBuf>,
+    /// The credentials of private registries, keyed by their host, e.g. `registry.gitlab.com`
+    pub registries: BTreeMap<String, RegistryAuth>,
+}
+
+impl ExecutorConfig {
+    pub fn resolve(args: CliExecutorArgs) -> Result<Self, ConfigError> {
+        let file = match &args.config {
+            Some(path) => read_config_file(path)?,
+            None => ConfigFile::default(),
+        };
+
+        let token = match (args.token, args.token_file) {
+            (Some(token), _) => Some(token),
+            (None, Some(path)) => Some(read_token_file(&path)?),
+            (None, None) => match (file.token, file.token_file) {
+                (Some(token), _) => Some(token),
+                (None, Some(path)) => Some(read_token_file(&path)?),
+                (None, None) => None,
+            },
+        };
+
+        let registries = file
+            .registries
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(registry, entry)| {
+                let token = match (entry.token, entry.token_file) {
+                    (Some(token), _) => token,
+                    (None, Some(path)) => read_token_file(&path)?,
+                    (None, None) => return MissingRegistryTokenSnafu { registry }.fail(),
+                };
+                let auth = RegistryAuth {
+                    username: entry.username,
+                    token,
+                };
+                Ok((registry, auth))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            id: args.id.or(file.id).ok_or_else(|| missing("runner id"))?,
+            token: token.ok_or_else(|| missing("runner token"))?,
+            endpoint: args
+                .endpoint
+                .or(file.endpoint)
+                .ok_or_else(|| missing("backend endpoint"))?,
+            test_taster: args.test_taster || file.test_taster.unwrap_or(false),
+            image_cache_dir: args.image_cache_dir.or(file.image_cache_dir),
+            parallelism: args.parallelism.or(file.parallelism).unwrap_or(0),
+            concurrent_tasks: args.concurrent_tasks.or(file.concurrent_tasks).unwrap_or(1),
+            build_max_cpu: args.build_max_cpu.or(file.build_max_cpu).unwrap_or(0),
+            build_max_memory: args
+                .build_max_memory
+                .or(file.build_max_memory)
+                .unwrap_or(DEFAULT_BUILD_MAX_MEMORY),
+            build_max_pids: args.build_max_pids.or(file.build_max_pids).unwrap_or(0),
+            build_max_disk: args.build_max_disk.or(file.build_max_disk).unwrap_or(0),
+            test_max_cpu: args
+                .test_max_cpu
+                .or(file.test_max_cpu)
+                .unwrap_or(DEFAULT_TEST_MAX_CPU),
+            test_max_memory: args
+                .test_max_memory
+                .or(file.test_max_memory)
+                .unwrap_or(DEFAULT_TEST_MAX_MEMORY),
+            test_max_pids: args
+                .test_max_pids
+                .or(file.test_max_pids)
+                .unwrap_or(DEFAULT_TEST_MAX_PIDS),
+            test_max_disk: args.test_max_disk.or(file.test_max_disk).unwrap_or(0),
+            metrics_address: args.metrics_address.or(file.metrics_address),
+            work_dir: args.work_dir.or(file.work_dir),
+            labels: if args.labels.is_empty() {
+                file.labels.unwrap_or_default()
+            } else {
+                args.labels
+            },
+            allowed_bind_mounts: if args.allowed_bind_mounts.is_empty() {
+                file.allowed_bind_mounts.unwrap_or_default()
+            } else {
+                args.allowed_bind_mounts
+            },
+            registries,
+        })
+    }
+
+    /// The configuration for one of `slots` concurrently running tasks. The build limits are for
+    /// the whole machine and split evenly between the slots, processors not limited explicitly as
+    /// well. Test limits apply to every single test container and are kept as they are.
+    pub fn for_slot(&self, slot: usize, slots: usize) -> Self {
+        if slots == 1 {
+            return self.clone();
+        }
+        let processors = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let processors_per_slot = (processors / slots).max(1);
+
+        Self {
+            id: format!("{}-{slot}", self.id),
+            parallelism: if self.parallelism == 0 {
+                processors_per_slot
+            } else {
+                (self.parallelism / slots).max(1)
+            },
+            build_max_cpu: if self.build_max_cpu == 0 {
+                processors_per_slot as u32
+            } else {
+                (self.build_max_cpu / slots as u32).max(1)
+            },
+            // Unlimited memory stays unlimited, as we do not know how much the machine has
+            build_max_memory: if self.build_max_memory == 0 {
+                0
+            } else {
+                (self.build_max_memory / slots).max(1)
+            },
+            ..self.clone()
+        }
+    }
+
+    /// Creates the work directory and makes it the location of all temporary build and test
+    /// files. Must be called before any other threads are started.
+    pub fn apply_work_dir(&self) -> Result<(), ConfigError> {
+        let Some(work_dir) = &self.work_dir else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(work_dir).context(CreateWorkDirSnafu { path: work_dir })?;
+
+        // SAFETY: We are still single threaded, so nobody can read the environment concurrently
+        unsafe {
+            std::env::set_var("TMPDIR", work_dir);
+        }
+
+        Ok(())
+    }
+
+    /// The configuration as TOML, with the tokens redacted
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("Unexpected toml serialize error")
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile, ConfigError> {
+    let contents = std::fs::read_to_string(path).context(ReadConfigSnafu { path })?;
+    toml::from_str(&contents).context(ParseConfigSnafu { path })
+}
+
+fn read_token_file(path: &Path) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path)
+        .map(|it| it.trim().to_string())
+        .context(ReadTokenFileSnafu { path })
+}
+
+fn missing(what: &'static str) -> ConfigError {
+    MissingSnafu { what }.into_error(NoneError)
+}
+
+fn redacted<T, S: Serializer>(_: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("<redacted>")
+}
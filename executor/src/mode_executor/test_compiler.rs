@@ -1,15 +1,17 @@
 use crate::containers::LimitsConfig;
 use crate::docker::Docker;
-use crate::mode_executor::{CliExecutorArgs, backoff, start_update_listener};
+use crate::mode_executor::config::ExecutorConfig;
+use crate::mode_executor::{CurrentTask, LeasedTask, backoff, start_update_listener};
 use crate::task_executor::{ExecutingTask, execute_task};
-use crate::{AnyError, Endpoints, NO_TASK_BACKOFF, ReqwestSnafu, TempFileSnafu};
+use crate::{AnyError, Endpoints, NO_TASK_BACKOFF, ReqwestSnafu, TempFileSnafu, metrics};
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use reqwest::blocking::Client;
 use shared::{RunnerInfo, RunnerWorkResponse};
 use snafu::{Report, ResultExt, location};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
 
 pub struct TestCompilerState {
@@ -17,6 +19,8 @@ pub struct TestCompilerState {
     docker: Docker,
     build_limits: LimitsConfig,
     test_limits: LimitsConfig,
+    allowed_bind_mounts: Vec<PathBuf>,
+    current_task: CurrentTask,
 }
 
 impl TestCompilerState {
@@ -25,6 +29,8 @@ impl TestCompilerState {
         max_parallelism: usize,
         build_limits: LimitsConfig,
         test_limits: LimitsConfig,
+        allowed_bind_mounts: Vec<PathBuf>,
+        current_task: CurrentTask,
     ) -> Result<Self, AnyError> {
         let thread_pool = match ThreadPoolBuilder::new()
             .num_threads(max_parallelism)
@@ -44,6 +50,8 @@ impl TestCompilerState {
             docker,
             build_limits,
             test_limits,
+            allowed_bind_mounts,
+            current_task,
         })
     }
 }
@@ -51,7 +59,7 @@ impl TestCompilerState {
 impl super::Iteration for TestCompilerState {
     fn iteration(
         &mut self,
-        args: &CliExecutorArgs,
+        args: &ExecutorConfig,
         endpoints: &Endpoints,
         current_backoff: &mut Duration,
         shutdown_requested: &Arc<AtomicBool>,
@@ -65,12 +73,14 @@ impl super::Iteration for TestCompilerState {
             .send()
             .context(ReqwestSnafu)?;
 
-        let response = match client
+        let poll_start = Instant::now();
+        let response = client
             .post(&endpoints.work)
             .json(&runner_info)
             .basic_auth(&args.id, Some(&args.token))
-            .send()
-        {
+            .send();
+        metrics::record_duration(metrics::POLL_DURATION, poll_start.elapsed());
+        let response = match response {
             Err(e) => {
                 warn!(
                     error = ?Report::from_error(e),
@@ -115,6 +125,19 @@ impl super::Iteration for TestCompilerState {
         let task_id = task.task_id.clone();
 
         info!(id = task_id, "Received task");
+        metrics::record_current_task(&args.id, &task_id, true);
+        let aborted = {
+            let mut current_task = self.current_task.lock().unwrap();
+            // Read under the lock, so we can not miss a shutdown request
+            let aborted = Arc::new(AtomicBool::new(shutdown_requested.load(Ordering::Relaxed)));
+            *current_task = Some(LeasedTask {
+                task_id: task_id.clone(),
+                aborted: aborted.clone(),
+                lost: false,
+            });
+            aborted
+        };
+
         let (tx, rx) = std::sync::mpsc::channel();
         let source_tar = tempfile::NamedTempFile::new().context(TempFileSnafu)?;
         client
@@ -128,19 +151,27 @@ impl super::Iteration for TestCompilerState {
         let task = ExecutingTask {
             inner: task,
             pool: &self.thread_pool,
-            aborted: shutdown_requested.clone(),
+            aborted,
             message_channel: tx,
         };
-        start_update_listener(args, endpoints, rx);
+        start_update_listener(args, endpoints, task_id.clone(), rx);
         let res = execute_task(
             task,
             source_tar.into_temp_path(),
             &self.docker,
             &self.build_limits,
             &self.test_limits,
+            &self.allowed_bind_mounts,
         );
 
         info!(id = task_id, res = ?res.info(), "Task finished");
+        metrics::record_current_task(&args.id, &task_id, false);
+        let lease = self.current_task.lock().unwrap().take();
+        if lease.is_some_and(|it| it.lost) {
+            warn!(id = task_id, "Lease for task was lost, discarding result");
+            return Ok(());
+        }
+
         let res = client
             .post(&endpoints.done)
             .json(&res)
@@ -1,50 +1,55 @@
 use crate::containers::{Built, LimitsConfig, TaskContainer};
 use crate::docker::{Docker, ImageId};
-use crate::mode_executor::CliExecutorArgs;
-use crate::{AnyError, Endpoints, NO_TASK_BACKOFF, ReqwestSnafu, task_executor};
+use crate::mode_executor::Iteration;
+use crate::mode_executor::config::ExecutorConfig;
+use crate::mode_executor::test_compiler::TestCompilerState;
+use crate::{AnyError, Endpoints, ReqwestSnafu, task_executor};
 use reqwest::blocking::Client;
 use shared::{RunnerInfo, RunnerWorkTasteTestDone, RunnerWorkTasteTestResponse};
 use snafu::{Report, ResultExt};
 use std::cell::RefCell;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 use std::time::Duration;
 use tracing::{debug, info, warn};
 
+/// Tastes tests ahead of everything else and only executes full tasks while no tasting is waiting
 pub struct TestTastingState {
     pub container: Rc<RefCell<Option<TaskContainer<Built>>>>,
     docker: Docker,
     test_limits: LimitsConfig,
+    allowed_bind_mounts: Vec<PathBuf>,
+    grading: TestCompilerState,
 }
 
 impl TestTastingState {
-    pub fn new(docker: Docker, test_limits: LimitsConfig) -> Self {
+    pub fn new(
+        docker: Docker,
+        test_limits: LimitsConfig,
+        allowed_bind_mounts: Vec<PathBuf>,
+        grading: TestCompilerState,
+    ) -> Self {
         Self {
             container: Rc::new(RefCell::new(None)),
             docker,
             test_limits,
+            allowed_bind_mounts,
+            grading,
         }
     }
-}
 
-impl super::Iteration for TestTastingState {
-    fn iteration(
+    /// Tastes the next open test. Returns false if none is waiting, so the runner may do other work.
+    fn taste(
         &mut self,
-        args: &CliExecutorArgs,
+        args: &ExecutorConfig,
         endpoints: &Endpoints,
         current_backoff: &mut Duration,
         shutdown_requested: &Arc<AtomicBool>,
         client: &Client,
         runner_info: &RunnerInfo,
-    ) -> Result<(), AnyError> {
-        client
-            .post(&endpoints.register)
-            .basic_auth(&args.id, Some(&args.token))
-            .json(&runner_info)
-            .send()
-            .context(ReqwestSnafu)?;
-
+    ) -> Result<bool, AnyError> {
         let response = match client
             .post(&endpoints.work_taste_test)
             .json(runner_info)
@@ -59,7 +64,7 @@ impl super::Iteration for TestTastingState {
                     "Failed to request work"
                 );
                 super::backoff(current_backoff, shutdown_requested);
-                return Ok(());
+                return Ok(true);
             }
             Ok(response) => response,
         };
@@ -71,7 +76,7 @@ impl super::Iteration for TestTastingState {
                 "Failed to request work"
             );
             super::backoff(current_backoff, shutdown_requested);
-            return Ok(());
+            return Ok(true);
         }
         let task = match response.json::<RunnerWorkTasteTestResponse>() {
             Err(e) => {
@@ -82,15 +87,13 @@ impl super::Iteration for TestTastingState {
                     "Failed to parse task"
                 );
                 super::backoff(current_backoff, shutdown_requested);
-                return Ok(());
+                return Ok(true);
             }
             Ok(task) => task,
         };
         let Some(task) = task.task else {
-            let current_backoff = &mut NO_TASK_BACKOFF.clone();
-            debug!(backoff = ?current_backoff, "No task received");
-            super::backoff(current_backoff, shutdown_requested);
-            return Ok(());
+            debug!("No test to taste received");
+            return Ok(false);
         };
         let task_id = task.id;
 
@@ -103,10 +106,12 @@ impl super::Iteration for TestTastingState {
             task_id.to_string(),
             &ImageId(task.image_id),
             task.test,
+            task.fileset.as_ref(),
             shutdown_requested.clone(),
             self.container.clone(),
             &self.docker,
             &self.test_limits,
+            &self.allowed_bind_mounts,
         );
         let res = RunnerWorkTasteTestDone {
             output: res,
@@ -121,6 +126,41 @@ impl super::Iteration for TestTastingState {
             .send()
             .context(ReqwestSnafu)?;
 
-        Ok(())
+        Ok(true)
+    }
+}
+
+impl Iteration for TestTastingState {
+    fn iteration(
+        &mut self,
+        args: &ExecutorConfig,
+        endpoints: &Endpoints,
+        current_backoff: &mut Duration,
+        shutdown_requested: &Arc<AtomicBool>,
+        client: &Client,
+        runner_info: &RunnerInfo,
+    ) -> Result<(), AnyError> {
+        let tasted = self.taste(
+            args,
+            endpoints,
+            current_backoff,
+            shutdown_requested,
+            client,
+            runner_info,
+        )?;
+        if tasted {
+            return Ok(());
+        }
+
+        // The backend only hands out a full task while no tasting is waiting and another taster
+        // is free, so tastings do not queue behind it
+        self.grading.iteration(
+            args,
+            endpoints,
+            current_backoff,
+            shutdown_requested,
+            client,
+            runner_info,
+        )
     }
 }
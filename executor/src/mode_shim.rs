@@ -1,17 +1,90 @@
 use crate::AnyError;
-use crate::containers::CROW_SIGNAL_SHIM_MAGIC;
+use crate::containers::{CROW_SHIM_TRAILER_MAGIC, PidsUsage, ShimTrailer};
 use clap::Args;
+use shared::ResourceUsage;
 use snafu::{Report, location};
-use std::os::unix::process::ExitStatusExt;
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+
+/// The signals asking the container to stop. We are its init process, and the kernel drops these
+/// for an init without handlers, so they would never reach the program otherwise.
+const FORWARDED_SIGNALS: [libc::c_int; 4] =
+    [libc::SIGTERM, libc::SIGINT, libc::SIGHUP, libc::SIGQUIT];
+
+/// The process group of the program, 0 until it is started
+static PROGRAM_PGID: AtomicI32 = AtomicI32::new(0);
+/// The last signal we received and forwarded, 0 if there was none
+static FORWARDED_SIGNAL: AtomicI32 = AtomicI32::new(0);
 
 #[derive(Args, Debug)]
 pub struct CliShimArgs {
+    /// The file descriptor to write our own diagnostics to, keeping them out of the stderr of the
+    /// program. Defaults to stderr.
+    #[clap(long)]
+    pub log_fd: Option<RawFd>,
+    /// Run the program without address space layout randomization, so its addresses are the same
+    /// in every run
+    #[clap(long)]
+    pub disable_aslr: bool,
     // Pass-through arguments
     pub args: Vec<String>,
 }
 
 pub fn run_shim(args: CliShimArgs) -> Result<(), AnyError> {
-    let args = args.args;
+    if args.disable_aslr {
+        disable_aslr()?;
+    }
+
+    let Some(log_fd) = args.log_fd else {
+        return run_program(&args.args, &mut std::io::stderr());
+    };
+
+    let mut log = open_log(log_fd);
+    if let Err(e) = run_program(&args.args, &mut log) {
+        // Whoever reads the log also wants our errors, stderr belongs to the program
+        let _ = writeln!(log, "{}", Report::from_error(e));
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Sets the personality flag disabling ASLR. The program inherits it from us.
+fn disable_aslr() -> Result<(), AnyError> {
+    // SAFETY: Changing the personality has no memory safety requirements
+    let res = unsafe {
+        let current = libc::personality(0xffff_ffff);
+        if current == -1 {
+            current
+        } else {
+            libc::personality(current as libc::c_ulong | libc::ADDR_NO_RANDOMIZE as libc::c_ulong)
+        }
+    };
+    if res == -1 {
+        let e = std::io::Error::last_os_error();
+        return Err(AnyError::ShimWithSource {
+            msg: format!("Could not disable ASLR: {}", Report::from_error(&e)),
+            source: Box::new(e),
+            location: location!(),
+        });
+    }
+
+    Ok(())
+}
+
+fn open_log(fd: RawFd) -> File {
+    // SAFETY: The executor passes the descriptor to us and nothing else in this process uses it
+    let file = unsafe { File::from_raw_fd(fd) };
+
+    // The clone is close-on-exec, so the program does not inherit the log
+    file.try_clone().unwrap_or(file)
+}
+
+fn run_program(args: &[String], log: &mut dyn Write) -> Result<(), AnyError> {
     if args.is_empty() {
         return Err(AnyError::Shim {
             msg: "No arguments, I don't know what to invoke".to_string(),
@@ -19,12 +92,33 @@ pub fn run_shim(args: CliShimArgs) -> Result<(), AnyError> {
         });
     }
 
-    let result = std::process::Command::new(&args[0])
+    if let Err(e) = install_signal_forwarding() {
+        let _ = writeln!(log, "Could not forward signals: {}", Report::from_error(e));
+    }
+
+    let start = Instant::now();
+    let child = std::process::Command::new(&args[0])
         .args(&args[1..])
         .stdin(std::process::Stdio::inherit())
         .stdout(std::process::Stdio::inherit())
         .stderr(std::process::Stdio::inherit())
-        .status();
+        // Signals are forwarded to the group, so they also reach whatever the program started
+        .process_group(0)
+        .spawn();
+
+    let result = child.and_then(|mut child| {
+        let pgid = child.id() as i32;
+        PROGRAM_PGID.store(pgid, Ordering::SeqCst);
+        // A signal may have arrived before we knew where to forward it
+        let pending = FORWARDED_SIGNAL.load(Ordering::SeqCst);
+        if pending != 0 {
+            // SAFETY: Sending a signal has no memory safety requirements
+            unsafe { libc::kill(-pgid, pending) };
+        }
+
+        child.wait()
+    });
+    let wall_time = start.elapsed();
 
     let result = match result {
         Ok(status) => status,
@@ -41,8 +135,15 @@ pub fn run_shim(args: CliShimArgs) -> Result<(), AnyError> {
         }
     };
 
-    if let Some(signal) = result.signal() {
-        eprintln!("{CROW_SIGNAL_SHIM_MAGIC}{signal}");
+    let trailer = ShimTrailer {
+        signal: result.signal(),
+        forwarded_signal: Some(FORWARDED_SIGNAL.load(Ordering::SeqCst)).filter(|it| *it != 0),
+        resource_usage: children_resource_usage(wall_time),
+        // The cgroup dies with us, so the executor can not read it afterwards
+        pids: PidsUsage::read_own_cgroup(),
+    };
+    if let Ok(trailer) = serde_json::to_string(&trailer) {
+        let _ = writeln!(log, "{CROW_SHIM_TRAILER_MAGIC}{trailer}");
     }
 
     if let Some(code) = result.code() {
@@ -51,3 +152,55 @@ pub fn run_shim(args: CliShimArgs) -> Result<(), AnyError> {
 
     std::process::exit(21);
 }
+
+extern "C" fn forward_signal(signal: libc::c_int) {
+    FORWARDED_SIGNAL.store(signal, Ordering::SeqCst);
+    let pgid = PROGRAM_PGID.load(Ordering::SeqCst);
+    if pgid > 0 {
+        // SAFETY: kill is async-signal-safe
+        unsafe { libc::kill(-pgid, signal) };
+    }
+}
+
+fn install_signal_forwarding() -> std::io::Result<()> {
+    for signal in FORWARDED_SIGNALS {
+        // SAFETY: The handler only touches atomics and calls async-signal-safe functions. The
+        // program gets the default handlers back when it is executed.
+        let res = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = forward_signal as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut())
+        };
+        if res == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// The usage of the program and the descendants it waited for. We only ever wait for the program,
+/// so nothing else is included.
+fn children_resource_usage(wall_time: Duration) -> Option<ResourceUsage> {
+    // SAFETY: getrusage only writes to the struct we pass
+    let usage = unsafe {
+        let mut usage: libc::rusage = std::mem::zeroed();
+        if libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) == -1 {
+            return None;
+        }
+        usage
+    };
+    let time = |it: libc::timeval| {
+        Duration::from_secs(it.tv_sec as u64) + Duration::from_micros(it.tv_usec as u64)
+    };
+
+    Some(ResourceUsage {
+        wall_time,
+        user_time: time(usage.ru_utime),
+        system_time: time(usage.ru_stime),
+        // Linux reports it in KiB
+        max_rss_kib: usage.ru_maxrss as u64,
+    })
+}
@@ -1,21 +1,32 @@
 use crate::containers::{
-    Built, ContainerCreateError, IntegrateSourceError, LimitsConfig, TaskContainer, TestRunError,
-    execution_output_from_wait_error,
+    Built, ContainerCreateError, CrowEnvironment, IntegrateSourceError, LimitsConfig,
+    TaskContainer, TestRunError, execution_output_from_wait_error,
 };
 use crate::docker::{Docker, ImageId};
+use crate::metrics;
+use crate::metrics::MetricLabel;
 use rayon::ThreadPool;
 use shared::{
-    CompilerTask, CompilerTest, ExecutionOutput, FinishedCompilerTask, FinishedExecution,
-    FinishedTaskInfo, FinishedTest, InternalError, RunnerUpdate, TestExecutionOutput,
+    CategoryFileset, CompilerDiagnostic, CompilerTask, CompilerTest, ContainerPermissions,
+    ExecutionOutput, FinishedCompilerTask, FinishedExecution, FinishedTaskInfo, FinishedTest,
+    InternalError, PinnedEnvironment, RunnerUpdate, TestExecutionOutput, TestInvocation,
 };
-use snafu::{Location, Report, ResultExt, Snafu, location};
+use snafu::{Location, Report, ResultExt, Snafu, ensure, location};
 use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, mpsc};
 use std::time::{Duration, Instant, SystemTime};
 use tempfile::TempPath;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// The line prefix compilers put in front of their machine-readable diagnostics
+const DIAGNOSTICS_TRAILER: &str = "crow-diagnostics:";
+/// How many diagnostics of a single test are kept, so chatty compilers do not bloat the results
+const MAX_DIAGNOSTICS: usize = 100;
 
 #[derive(Debug, Snafu)]
 pub enum TaskRunError {
@@ -43,6 +54,36 @@ pub enum TaskRunError {
         #[snafu(implicit)]
         location: Location,
     },
+    #[snafu(display(
+        "The image does not contain `{program}`, the runner prefix of category `{category}` at \
+        {location}"
+    ))]
+    RunnerPrefixMissing {
+        program: String,
+        category: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+    #[snafu(display("This runner does not allow {used_by} to mount `{path}` at {location}"))]
+    MountNotAllowed {
+        path: String,
+        used_by: String,
+        #[snafu(implicit)]
+        location: Location,
+    },
+}
+
+impl MetricLabel for TaskRunError {
+    fn metric_label(&self) -> &'static str {
+        match self {
+            Self::ContainerCreate { .. } => "container_create",
+            Self::IntegrateSource { .. } => "integrate_source",
+            Self::ContainerRun { .. } => "container_run",
+            Self::WaitForBuild { .. } => "wait_for_build",
+            Self::RunnerPrefixMissing { .. } => "runner_prefix_missing",
+            Self::MountNotAllowed { .. } => "mount_not_allowed",
+        }
+    }
 }
 
 pub struct ExecutingTask<'a> {
@@ -58,6 +99,7 @@ pub fn execute_task(
     docker: &Docker,
     build_limits: &LimitsConfig,
     test_limits: &LimitsConfig,
+    allowed_bind_mounts: &[PathBuf],
 ) -> FinishedCompilerTask {
     let task_id = task.inner.task_id.clone();
     let team_id = task.inner.team_id.clone();
@@ -67,7 +109,14 @@ pub fn execute_task(
     let start_monotonic = Instant::now();
     let message_channel = task.message_channel.clone();
 
-    let res = match execute_task_impl(task, source_tar, docker, build_limits, test_limits) {
+    let res = match execute_task_impl(
+        task,
+        source_tar,
+        docker,
+        build_limits,
+        test_limits,
+        allowed_bind_mounts,
+    ) {
         Ok(res) => res,
         Err(e) => task_run_error_to_task(
             start,
@@ -79,6 +128,11 @@ pub fn execute_task(
             e,
         ),
     };
+    metrics::record_duration(metrics::TASK_DURATION, start_monotonic.elapsed());
+    metrics::record_task_executed(match &res {
+        FinishedCompilerTask::BuildFailed { .. } => "build_failed",
+        FinishedCompilerTask::RanTests { .. } => "ran_tests",
+    });
     let _ = message_channel.send(RunnerUpdate::Done);
 
     res
@@ -90,6 +144,7 @@ fn execute_task_impl(
     docker: &Docker,
     build_limits: &LimitsConfig,
     test_limits: &LimitsConfig,
+    allowed_bind_mounts: &[PathBuf],
 ) -> Result<FinishedCompilerTask, TaskRunError> {
     let start = SystemTime::now();
     let start_monotonic = Instant::now();
@@ -98,31 +153,67 @@ fn execute_task_impl(
     let aborted = task.aborted;
     let message_channel = task.message_channel;
     let task = task.inner;
+
+    check_mounts(&task.build_permissions, allowed_bind_mounts, "the build")?;
+    for test in &task.tests {
+        check_mounts(
+            &test.permissions,
+            allowed_bind_mounts,
+            &format!("category `{}`", test.category),
+        )?;
+    }
+
     let container = TaskContainer::<()>::new(
         &ImageId(task.image),
         &task.build_command,
         docker,
         build_limits,
+        &CrowEnvironment::for_build(task.task_id.clone(), task.build_timeout),
+        &task.build_permissions,
     )
     .context(ContainerCreateSnafu)?;
 
+    // Otherwise every single test would fail with a confusing error
+    let mut checked_programs = HashSet::new();
+    for test in &task.tests {
+        let Some(program) = test.runner_prefix.first() else {
+            continue;
+        };
+        if !checked_programs.insert(program) {
+            continue;
+        }
+        ensure!(
+            container.contains_program(program),
+            RunnerPrefixMissingSnafu {
+                program,
+                category: &test.category,
+            }
+        );
+    }
+
     container
         .integrate_source(source_tar)
         .context(IntegrateSourceSnafu)?;
 
     let container = container.run().context(ContainerRunSnafu)?;
     let _ = message_channel.send(RunnerUpdate::StartedBuild);
-    let container = container
-        .wait_for_build(task.build_timeout, aborted.clone())
-        .map_err(|output| TaskRunError::WaitForBuild {
-            output,
-            location: location!(),
-        })?;
+    let build_start = Instant::now();
+    let container =
+        container.wait_for_build(task.build_timeout, aborted.clone(), task.record_output);
+    metrics::record_duration(metrics::BUILD_DURATION, build_start.elapsed());
+    let container = container.map_err(|output| TaskRunError::WaitForBuild {
+        output,
+        location: location!(),
+    })?;
     let build_output = FinishedExecution {
         stdout: container.data.stdout.clone(),
         stderr: container.data.stderr.clone(),
         runtime: container.data.runtime,
         exit_status: container.data.exit_status.code(),
+        infrastructure_log: container.data.infrastructure_log.clone(),
+        recording: container.data.recording.clone(),
+        resource_usage: container.data.resource_usage,
+        full_output_id: None,
     };
     let _ = message_channel.send(RunnerUpdate::FinishedBuild {
         result: build_output.clone(),
@@ -137,6 +228,9 @@ fn execute_task_impl(
                 team_id: task.team_id,
                 revision_id: task.revision_id,
                 commit_message: task.commit_message,
+                environment: Some(PinnedEnvironment::default()),
+                rerun_of: None,
+                commit: None,
             },
             build_output: ExecutionOutput::Failure {
                 execution: build_output,
@@ -145,57 +239,118 @@ fn execute_task_impl(
         });
     }
 
-    let test_results = pool.scope(|s| {
-        let (tx, rx) = mpsc::channel();
-
-        for test in task.tests {
-            let tx = tx.clone();
-            let container = &container;
-            let aborted = aborted.clone();
-            let message_channel = message_channel.clone();
-            s.spawn(move |_| {
-                let _ = message_channel.send(RunnerUpdate::StartedTest {
-                    test_id: test.test_id.clone(),
-                });
-                let res = container.run_test(&test, test.timeout, aborted, test_limits);
-                let res = tx.send((test.clone(), res));
-                if let Err(e) = res {
-                    error!(
-                        test_id = test.test_id.as_str(),
-                        error = ?e,
-                        "Could not send test result"
-                    );
-                }
-            });
+    // Tests only run after all their dependencies finished, so a failing dependency skips them
+    let mut failed = HashSet::new();
+    let mut test_results = Vec::new();
+    for wave in dependency_waves(&task.tests) {
+        let mut runnable = Vec::new();
+        for test in wave {
+            let Some(dependency) = test.depends_on.iter().find(|it| failed.contains(*it)) else {
+                runnable.push(test);
+                continue;
+            };
+            for (profile, test) in test.profile_variants() {
+                let result = FinishedTest {
+                    output: TestExecutionOutput::skipped(dependency),
+                    test_id: test.test_id,
+                    category: Some(test.category),
+                    provisional_for_category: test.provisional_for_category,
+                    profile,
+                    binary_size: None,
+                    compile_duration_ms: None,
+                    invocation: TestInvocation::default(),
+                    diagnostics: Vec::new(),
+                };
+                test_results.push(result.clone());
+                let _ = message_channel.send(RunnerUpdate::FinishedTest { result });
+            }
+            failed.insert(test.test_id.clone());
         }
 
-        // Drop the original tx so that the receiver is collected when all threads are done
-        drop(tx);
-
-        let mut results = Vec::new();
-        while let Ok((test, res)) = rx.recv() {
-            let result = match res {
-                Ok(res) => res,
-                Err(e) => TestExecutionOutput::Error {
-                    output_so_far: test_run_error_to_output(
-                        start_monotonic,
-                        task.task_id.clone(),
-                        test.test_id.clone(),
-                        e,
+        let results = pool.scope(|s| {
+            let (tx, rx) = mpsc::channel();
+
+            let variants = runnable.iter().flat_map(|test| test.profile_variants());
+            for (profile, test) in variants {
+                let tx = tx.clone();
+                let container = &container;
+                let task_id = &task.task_id;
+                let record_output = task.record_output;
+                let fileset = task.filesets.get(&test.category);
+                let aborted = aborted.clone();
+                let message_channel = message_channel.clone();
+                s.spawn(move |_| {
+                    let _ = message_channel.send(RunnerUpdate::StartedTest {
+                        test_id: test.test_id.clone(),
+                    });
+                    let test_start = Instant::now();
+                    let res = container.run_test(
+                        task_id,
+                        &test,
+                        fileset,
+                        test.timeout,
+                        aborted,
+                        test_limits,
+                        record_output,
+                    );
+                    metrics::record_duration(metrics::TEST_DURATION, test_start.elapsed());
+                    metrics::record_test_executed();
+                    let res = tx.send((profile, test.clone(), res));
+                    if let Err(e) = res {
+                        error!(
+                            test_id = test.test_id.as_str(),
+                            error = ?e,
+                            "Could not send test result"
+                        );
+                    }
+                });
+            }
+
+            // Drop the original tx so that the receiver is collected when all threads are done
+            drop(tx);
+
+            let mut results = Vec::new();
+            while let Ok((profile, test, res)) = rx.recv() {
+                let (result, invocation, binary_size) = match res {
+                    Ok(res) => (res.output, res.invocation, res.binary_size),
+                    Err(e) => (
+                        TestExecutionOutput::Error {
+                            output_so_far: test_run_error_to_output(
+                                start_monotonic,
+                                task.task_id.clone(),
+                                test.test_id.clone(),
+                                e,
+                            ),
+                        },
+                        TestInvocation::default(),
+                        None,
                     ),
-                },
-            };
-            let result = FinishedTest {
-                test_id: test.test_id,
-                category: Some(test.category),
-                output: result,
-                provisional_for_category: test.provisional_for_category,
-            };
-            results.push(result.clone());
-            let _ = message_channel.send(RunnerUpdate::FinishedTest { result });
-        }
-        results
-    });
+                };
+                let result = FinishedTest {
+                    test_id: test.test_id,
+                    category: Some(test.category),
+                    compile_duration_ms: result.compile_duration().map(|it| it.as_millis() as u64),
+                    diagnostics: compiler_diagnostics(result.compiler_output()),
+                    output: result,
+                    provisional_for_category: test.provisional_for_category,
+                    profile,
+                    binary_size,
+                    invocation,
+                };
+                results.push(result.clone());
+                let _ = message_channel.send(RunnerUpdate::FinishedTest { result });
+            }
+            results
+        });
+
+        failed.extend(
+            results
+                .iter()
+                .filter(|it| !matches!(it.output, TestExecutionOutput::Success { .. }))
+                .map(|it| it.test_id.clone()),
+        );
+        test_results.extend(results);
+    }
 
     Ok(FinishedCompilerTask::RanTests {
         info: FinishedTaskInfo {
@@ -205,12 +360,77 @@ fn execute_task_impl(
             team_id: task.team_id,
             revision_id: task.revision_id,
             commit_message: task.commit_message,
+            environment: Some(PinnedEnvironment::default()),
+            rerun_of: None,
+            commit: None,
         },
         build_output,
         tests: test_results,
     })
 }
 
+/// Groups the tests into waves, where each test only depends on tests of earlier waves.
+/// Dependencies that are not part of the task are ignored. Tests with cyclic dependencies can
+/// not be ordered and end up together in the last wave.
+fn dependency_waves(tests: &[CompilerTest]) -> Vec<Vec<&CompilerTest>> {
+    let in_task: HashSet<&str> = tests.iter().map(|it| it.test_id.as_str()).collect();
+    let mut scheduled = HashSet::new();
+    let mut remaining: Vec<&CompilerTest> = tests.iter().collect();
+    let mut waves = Vec::new();
+
+    while !remaining.is_empty() {
+        let (ready, blocked): (Vec<_>, Vec<_>) = remaining.into_iter().partition(|test| {
+            test.depends_on
+                .iter()
+                .all(|it| !in_task.contains(it.as_str()) || scheduled.contains(it.as_str()))
+        });
+        if ready.is_empty() {
+            warn!(
+                tests = ?blocked.iter().map(|it| &it.test_id).collect::<Vec<_>>(),
+                "Tests have cyclic dependencies, running them unordered"
+            );
+            waves.push(blocked);
+            break;
+        }
+        scheduled.extend(ready.iter().map(|it| it.test_id.as_str()));
+        waves.push(ready);
+        remaining = blocked;
+    }
+
+    waves
+}
+
+/// Reads the diagnostics from the `crow-diagnostics:` trailer compilers may end their stderr with.
+/// Malformed trailers are ignored, the raw output is still shown.
+fn compiler_diagnostics(output: &ExecutionOutput) -> Vec<CompilerDiagnostic> {
+    let execution = match output {
+        ExecutionOutput::Success(execution)
+        | ExecutionOutput::Failure { execution, .. }
+        | ExecutionOutput::Timeout(execution) => execution,
+        ExecutionOutput::Aborted(_) | ExecutionOutput::Error(_) => return Vec::new(),
+    };
+    let trailer = execution
+        .stderr
+        .lines()
+        .rev()
+        .find(|it| !it.trim().is_empty())
+        .and_then(|it| it.strip_prefix(DIAGNOSTICS_TRAILER));
+    let Some(trailer) = trailer else {
+        return Vec::new();
+    };
+
+    match serde_json::from_str::<Vec<CompilerDiagnostic>>(trailer) {
+        Ok(mut diagnostics) => {
+            diagnostics.truncate(MAX_DIAGNOSTICS);
+            diagnostics
+        }
+        Err(e) => {
+            warn!(error = %e, "Ignoring malformed compiler diagnostics");
+            Vec::new()
+        }
+    }
+}
+
 fn task_run_error_to_task(
     start: SystemTime,
     start_monotonic: Instant,
@@ -227,7 +447,11 @@ fn task_run_error_to_task(
         team_id,
         revision_id,
         commit_message,
+        environment: Some(PinnedEnvironment::default()),
+        rerun_of: None,
+        commit: None,
     };
+    metrics::record_failure("task", &e);
 
     if let TaskRunError::WaitForBuild { output, .. } = e {
         return FinishedCompilerTask::BuildFailed {
@@ -260,12 +484,13 @@ fn test_run_error_to_output(
     e: TestRunError,
 ) -> ExecutionOutput {
     if let TestRunError::Execution { source, .. } = &e {
-        if let Some(res) = execution_output_from_wait_error(source) {
+        if let Some(res) = execution_output_from_wait_error(source, None, None) {
             return res;
         }
     }
 
     // We have *some* internal error
+    metrics::record_failure("test", &e);
     let report = Report::from_error(e);
     error!(
         error = ?report,
@@ -280,14 +505,17 @@ fn test_run_error_to_output(
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run_test(
     task_id: String,
     image_id: &ImageId,
     test: CompilerTest,
+    fileset: Option<&CategoryFileset>,
     shutdown_requested: Arc<AtomicBool>,
     base_container: Rc<RefCell<Option<TaskContainer<Built>>>>,
     docker: &Docker,
     limits: &LimitsConfig,
+    allowed_bind_mounts: &[PathBuf],
 ) -> TestExecutionOutput {
     let test_id = test.test_id.clone();
     let start = Instant::now();
@@ -295,10 +523,12 @@ pub fn run_test(
         task_id.clone(),
         image_id,
         test,
+        fileset,
         shutdown_requested,
         base_container,
         docker,
         limits,
+        allowed_bind_mounts,
     );
 
     match res {
@@ -322,15 +552,24 @@ pub fn run_test(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn run_test_impl(
     task_id: String,
     image_id: &ImageId,
     test: CompilerTest,
+    fileset: Option<&CategoryFileset>,
     shutdown_requested: Arc<AtomicBool>,
     base_container: Rc<RefCell<Option<TaskContainer<Built>>>>,
     docker: &Docker,
     limits: &LimitsConfig,
+    allowed_bind_mounts: &[PathBuf],
 ) -> Result<TestExecutionOutput, TaskRunError> {
+    check_mounts(
+        &test.permissions,
+        allowed_bind_mounts,
+        &format!("category `{}`", test.category),
+    )?;
+
     if base_container.borrow().is_none() {
         info!("Creating reference compiler container");
         // We have nothing really to do here, so we just use `true` as the builder.
@@ -341,13 +580,17 @@ fn run_test_impl(
             &LimitsConfig {
                 cpus: Some(1),
                 memory_bytes: Some(50 * 1024 * 1024), // 50MiB
+                pids: Some(64),
+                disk_bytes: None,
             },
+            &CrowEnvironment::for_build(task_id.clone(), Duration::from_secs(10)),
+            &ContainerPermissions::default(),
         )
         .context(ContainerCreateSnafu)?
         .run()
         .context(ContainerRunSnafu)?;
         let container = container
-            .wait_for_build(Duration::from_secs(10), shutdown_requested.clone())
+            .wait_for_build(Duration::from_secs(10), shutdown_requested.clone(), false)
             .map_err(|output| TaskRunError::WaitForBuild {
                 output,
                 location: location!(),
@@ -359,10 +602,18 @@ fn run_test_impl(
     let base_container = base_container.as_ref().unwrap();
 
     let start = Instant::now();
-    let res = base_container.run_test(&test, test.timeout, shutdown_requested.clone(), limits);
+    let res = base_container.run_test(
+        &task_id,
+        &test,
+        fileset,
+        test.timeout,
+        shutdown_requested.clone(),
+        limits,
+        false,
+    );
 
     let res = match res {
-        Ok(res) => res,
+        Ok(res) => res.output,
         Err(e) => {
             return Ok(TestExecutionOutput::Error {
                 output_so_far: test_run_error_to_output(
@@ -377,3 +628,34 @@ fn run_test_impl(
 
     Ok(res)
 }
+
+/// Refuses invalid paths and bind mounts from outside the directories the operator of this runner
+/// allowed
+fn check_mounts(
+    permissions: &ContainerPermissions,
+    allowed_bind_mounts: &[PathBuf],
+    used_by: &str,
+) -> Result<(), TaskRunError> {
+    if let Some(path) = permissions.find_invalid_path() {
+        return MountNotAllowedSnafu { path, used_by }.fail();
+    }
+
+    for mount in &permissions.bind_mounts {
+        // Resolve links, so they can not point out of an allowed directory
+        let source = fs::canonicalize(&mount.source).ok();
+        let allowed = source.is_some_and(|source| {
+            allowed_bind_mounts.iter().any(|allowed| {
+                fs::canonicalize(allowed).is_ok_and(|allowed| source.starts_with(allowed))
+            })
+        });
+        ensure!(
+            allowed,
+            MountNotAllowedSnafu {
+                path: &mount.source,
+                used_by
+            }
+        );
+    }
+
+    Ok(())
+}
@@ -30,8 +30,13 @@
           naersk' = pkgs.callPackage naersk { };
           inherit (gitignore.lib) gitignoreSource gitignoreFilterWith;
 
+          buildDate = self.lastModifiedDate or "19700101000000";
+
           backend-naersk = naersk'.buildPackage {
             version = (pkgs.lib.importTOML ./Cargo.toml).workspace.package.version;
+            # Reported by the backend's /version endpoint
+            CROW_GIT_COMMIT = self.rev or self.dirtyRev or "unknown";
+            CROW_BUILD_DATE = "${builtins.substring 0 4 buildDate}-${builtins.substring 4 2 buildDate}-${builtins.substring 6 2 buildDate}";
             src = pkgs.lib.cleanSourceWith {
               filter = gitignoreFilterWith {
                 basePath = ./.;
@@ -23,11 +23,10 @@ export async function fetchWithError(
   }
   const response = await fetch(url, init)
   if (!response.ok && !extra?.extraSuccessStatus.includes(response.status)) {
-    // TODO: Prettify WebErrors
     let text = await response.text().catch(() => 'unknown')
     try {
       const json = JSON.parse(text)
-      text = json.error
+      text = json.message ?? json.error
     } catch {}
     throw new FetchError(text, response.status)
   }
@@ -3,39 +3,89 @@ import {
   AdminUserInfoSchema,
   type ApiFinishedCompilerTaskSummary,
   ApiFinishedCompilerTaskSummarySchema,
+  type ConsistencyReport,
+  ConsistencyReportSchema,
+  type Course,
+  CourseSchema,
+  type CourseMilestones,
+  CourseMilestonesSchema,
   type FinalSelectedTask,
   FinalSelectedTaskSchema,
   type FinishedCompilerTask,
   FinishedCompilerTaskSchema,
   type FinishedCompilerTaskSummary,
   FinishedCompilerTaskSummarySchema,
+  type FinishedTest,
+  FinishedTestSchema,
+  type GradingPreview,
+  GradingPreviewSchema,
   type IntegrationInfoResponse,
   IntegrationInfoResponseSchema,
   type ListTestResponse,
   ListTestResponseSchema,
+  type LoginProvider,
+  LoginProviderSchema,
+  type Notification,
+  NotificationSchema,
   type QueueResponse,
   QueueResponseSchema,
+  type QueueSimulation,
+  QueueSimulationSchema,
+  type QueuedTask,
+  QueuedTaskSchema,
   type Repo,
   RepoSchema,
   type RequestRevision,
   RequestRevisionSchema,
   type RerunResponse,
   RerunResponseSchema,
+  type RestoreResponse,
+  RestoreResponseSchema,
+  type RetentionReport,
+  RetentionReportSchema,
+  type RunnerLogs,
+  RunnerLogsSchema,
   type SetTestResponse,
   SetTestResponseSchema,
   type ShowMyselfResponse,
   ShowMyselfResponseSchema,
+  type SnapshotInfo,
+  SnapshotInfoSchema,
   type SnapshotResponse,
   SnapshotResponseSchema,
   type TaskId,
+  type TaskNotificationSettings,
+  type TaskNotificationSettingsResponse,
+  TaskNotificationSettingsResponseSchema,
+  TaskNotificationSettingsSchema,
+  type TeamActivityDay,
+  TeamActivityDaySchema,
   type TeamId,
   TeamIdSchema,
   type TeamInfo,
   TeamInfoSchema,
+  type TeamInvite,
+  TeamInviteSchema,
+  type TeamMilestones,
+  TeamMilestonesSchema,
+  type TeamPerformance,
+  TeamPerformanceSchema,
+  type TeamSshKey,
+  TeamSshKeySchema,
   type TeamStatistics,
   TeamStatisticsSchema,
+  type TestConflictResolution,
   type TestId,
+  type TestImportReport,
+  TestImportReportSchema,
+  type TestLintReport,
+  TestLintReportSchema,
   type TestModifier,
+  type TestStats,
+  TestStatsSchema,
+  type TestSuiteSnapshot,
+  TestSuiteSnapshotSchema,
+  type TestVisibility,
   type TestWithTestTasting,
   TestWithTestTastingSchema,
   type WorkItem,
@@ -59,6 +109,10 @@ interface TestPatch {
   binaryModifiers: TestModifier[]
   category: string
   ignoreTestTasting: boolean
+  practice: boolean
+  timeoutMs: number | null
+  dependsOn: TestId[]
+  license: string | null
 }
 
 async function fetchMyself(): Promise<ShowMyselfResponse> {
@@ -72,6 +126,23 @@ function isLoggedIn(): Ref<boolean> {
   return loggedIn
 }
 
+async function fetchLoginProvider(): Promise<LoginProvider> {
+  const response = await fetchWithAuth('/login/provider')
+  const json = await response.json()
+  return LoginProviderSchema.parse(json.provider)
+}
+
+export function queryLoginProvider() {
+  return useQuery({
+    queryKey: ['login-provider'],
+    queryFn: fetchLoginProvider,
+    staleTime: Infinity,
+    meta: {
+      purpose: 'fetching how to log in',
+    },
+  })
+}
+
 export function queryMyself() {
   return useQuery({
     queryKey: ['userinfo'],
@@ -131,6 +202,51 @@ export function mutateRepo(queryClient: QueryClient) {
   })
 }
 
+async function fetchSshKey(team: TeamId): Promise<TeamSshKey | null> {
+  const response = await fetchWithAuth(`/repo/${encodeURIComponent(team)}/ssh-key`, undefined, {
+    extraSuccessStatus: [404],
+  })
+  if (response.status === 404) {
+    return null
+  }
+  const json = await response.json()
+  return TeamSshKeySchema.parse(json)
+}
+
+export function querySshKey(team: MaybeRefOrGetter<TeamId | undefined>) {
+  const enabled = computed(() => !!toRef(team).value)
+  return useQuery({
+    queryKey: ['ssh-key', team],
+    // we only enable it then
+    // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
+    queryFn: () => fetchSshKey(toValue(team)!),
+    meta: {
+      purpose: 'fetching the ssh key of your repository',
+    },
+    enabled: computed(() => enabled.value && isLoggedIn().value),
+  })
+}
+
+async function fetchGenerateSshKey(team: TeamId): Promise<TeamSshKey> {
+  const response = await fetchWithAuth(`/repo/${encodeURIComponent(team)}/ssh-key`, {
+    method: 'POST',
+  })
+  const json = await response.json()
+  return TeamSshKeySchema.parse(json)
+}
+
+export function mutateGenerateSshKey(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: (team: TeamId) => fetchGenerateSshKey(team),
+    onSuccess: async (_, team, __) => {
+      await queryClient.invalidateQueries({ queryKey: ['ssh-key', team] })
+    },
+    meta: {
+      purpose: 'generating an ssh key',
+    },
+  })
+}
+
 export async function fetchGetRecentTasks(count: number): Promise<FinishedCompilerTaskSummary[]> {
   const response = await fetchWithAuth(`/team/recent-tasks/${count.toString()}`)
   const json = await response.json()
@@ -149,6 +265,41 @@ export function queryRecentTasks(count?: number) {
   })
 }
 
+/**
+ * Fetches at most `limit` bytes of the build output of the task, starting at byte `offset`. Chunks
+ * may split characters, so decode them with a streaming `TextDecoder`.
+ */
+export async function fetchBuildLogChunk(
+  taskId: TaskId,
+  {
+    stream = 'stdout',
+    offset = 0,
+    limit,
+  }: { stream?: 'stdout' | 'stderr'; offset?: number; limit?: number },
+): Promise<{ chunk: Uint8Array; totalLength: number }> {
+  const params = new URLSearchParams({ stream, offset: String(offset) })
+  if (limit !== undefined) {
+    params.set('limit', String(limit))
+  }
+  const response = await fetchWithAuth(
+    `/tasks/${encodeURIComponent(taskId)}/build-log?${params}`,
+  )
+  return {
+    chunk: new Uint8Array(await response.arrayBuffer()),
+    totalLength: Number(response.headers.get('X-Log-Length') ?? 0),
+  }
+}
+
+export async function fetchRawOutput(
+  taskId: TaskId,
+  executionId: string,
+  stream: 'stdout' | 'stderr',
+): Promise<Blob> {
+  const path = `/tasks/${encodeURIComponent(taskId)}/output/${encodeURIComponent(executionId)}`
+  const response = await fetchWithAuth(`${path}/raw?stream=${stream}`)
+  return await response.blob()
+}
+
 export async function fetchTask(taskId: TaskId): Promise<FinishedCompilerTask | null> {
   const response = await fetchWithAuth(`/tasks/${encodeURIComponent(taskId)}`, undefined, {
     extraSuccessStatus: [404],
@@ -176,6 +327,55 @@ export function queryTask(taskId: MaybeRefOrGetter<TaskId | undefined>) {
   })
 }
 
+/** Fetches a test of a finished task with its full output, as tasks only contain its start */
+export async function fetchTaskTest(
+  taskId: TaskId,
+  testId: TestId,
+  profile: string | null,
+): Promise<FinishedTest | null> {
+  const query = profile !== null ? `?${new URLSearchParams({ profile })}` : ''
+  const response = await fetchWithAuth(
+    `/tasks/${encodeURIComponent(taskId)}/tests/${encodeURIComponent(testId)}${query}`,
+    undefined,
+    { extraSuccessStatus: [404] },
+  )
+  if (response.status === 404) {
+    return null
+  }
+  const json = await response.json()
+  return FinishedTestSchema.parse(json)
+}
+
+export function queryTaskTest(
+  taskId: MaybeRefOrGetter<TaskId | undefined>,
+  test: MaybeRefOrGetter<FinishedTest | undefined>,
+  enabled: MaybeRefOrGetter<boolean>,
+) {
+  const loggedIn = isLoggedIn()
+
+  return useQuery({
+    queryKey: [
+      'task-test',
+      taskId,
+      computed(() => toValue(test)?.testId),
+      computed(() => toValue(test)?.profile),
+    ],
+    queryFn: () => {
+      // we only enable it then
+      // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
+      const { testId, profile } = toValue(test)!
+      // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
+      return fetchTaskTest(toValue(taskId)!, testId, profile)
+    },
+    enabled: computed(
+      () => !!toValue(taskId) && !!toValue(test) && toValue(enabled) && loggedIn.value,
+    ),
+    meta: {
+      purpose: 'fetching the full output of a test',
+    },
+  })
+}
+
 export async function fetchTeamInfo(teamId: TeamId): Promise<TeamInfo | null> {
   const response = await fetchWithAuth(`/team/info/${encodeURIComponent(teamId)}`)
   if (response.status === 404) {
@@ -201,6 +401,61 @@ export function queryTeamInfo(teamId: MaybeRefOrGetter<TeamId | undefined>) {
   })
 }
 
+async function fetchTeamPerformance(team: TeamId): Promise<TeamPerformance> {
+  const response = await fetchWithAuth(`/team/performance/${encodeURIComponent(team)}`)
+  return TeamPerformanceSchema.parse(await response.json())
+}
+
+export function queryTeamPerformance(team: MaybeRefOrGetter<TeamId | undefined>) {
+  const enabled = computed(() => !!toRef(team).value)
+  return useQuery({
+    queryKey: ['team-performance', team],
+    // we only enable it then
+    // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
+    queryFn: () => fetchTeamPerformance(toValue(team)!),
+    meta: {
+      purpose: 'fetching binary sizes and compile times',
+    },
+    enabled: computed(() => enabled.value && isLoggedIn().value),
+  })
+}
+
+export async function fetchTeamActivity(): Promise<TeamActivityDay[]> {
+  const response = await fetchWithAuth('/team/activity')
+  return TeamActivityDaySchema.array().parse(await response.json())
+}
+
+export function queryTeamActivity(enabled: MaybeRefOrGetter<boolean>) {
+  const loggedIn = isLoggedIn()
+
+  return useQuery({
+    queryKey: ['team-activity'],
+    queryFn: fetchTeamActivity,
+    enabled: computed(() => toValue(enabled) && loggedIn.value),
+    meta: {
+      purpose: 'fetching team activity',
+    },
+  })
+}
+
+export async function fetchTeamMilestones(): Promise<TeamMilestones> {
+  const response = await fetchWithAuth('/team/milestones')
+  return TeamMilestonesSchema.parse(await response.json())
+}
+
+export function queryTeamMilestones(enabled: MaybeRefOrGetter<boolean>) {
+  const loggedIn = isLoggedIn()
+
+  return useQuery({
+    queryKey: ['team-milestones'],
+    queryFn: fetchTeamMilestones,
+    enabled: computed(() => toValue(enabled) && loggedIn.value),
+    meta: {
+      purpose: 'fetching team milestones',
+    },
+  })
+}
+
 export async function fetchTests(): Promise<ListTestResponse> {
   const response = await fetchWithAuth('/tests')
   const json = await response.json()
@@ -228,6 +483,38 @@ export async function fetchTestDetail(testId: TestId): Promise<TestWithTestTasti
   return TestWithTestTastingSchema.parse(json)
 }
 
+/**
+ * Fetches a zip of all tests as rendered `.crow-test.md` files, optionally limited to a category.
+ */
+export async function fetchTestBundle(category?: string): Promise<Blob> {
+  const query = category ? `?category=${encodeURIComponent(category)}` : ''
+  const response = await fetchWithAuth(`/tests/export${query}`)
+  return await response.blob()
+}
+
+async function fetchTestStats(testId: TestId): Promise<TestStats> {
+  const response = await fetchWithAuth(`/tests/${encodeURIComponent(testId)}/stats`)
+  return TestStatsSchema.parse(await response.json())
+}
+
+export function queryTestStats(
+  testId: MaybeRefOrGetter<TestId | undefined>,
+  enabled: MaybeRefOrGetter<boolean>,
+) {
+  const loggedIn = isLoggedIn()
+
+  return useQuery({
+    queryKey: ['test-stats', testId],
+    // we only enable it then
+    // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
+    queryFn: () => fetchTestStats(toValue(testId)!),
+    enabled: computed(() => !!toValue(testId) && toValue(enabled) && loggedIn.value),
+    meta: {
+      purpose: 'fetching test statistics',
+    },
+  })
+}
+
 export function queryTest(testId: MaybeRefOrGetter<TestId | undefined>, refetchOnMount?: boolean) {
   const enabled = computed(() => !!toRef(testId).value)
   const loggedIn = isLoggedIn()
@@ -254,6 +541,11 @@ export async function fetchSetTest(test: TestPatch): Promise<SetTestResponse> {
       binaryModifiers: test.binaryModifiers,
       category: test.category,
       ignoreTestTasting: test.ignoreTestTasting,
+      practice: test.practice,
+      timeoutMs: test.timeoutMs,
+      dependsOn: test.dependsOn,
+      license: test.license,
+      visibility: test.visibility,
     }),
   })
   const json = await response.json()
@@ -334,7 +626,7 @@ export async function fetchTaskExists(taskId: TaskId): Promise<boolean> {
   return response.status === 200
 }
 
-export async function fetchQueuedTask(taskId: TaskId): Promise<WorkItem | null> {
+export async function fetchQueuedTask(taskId: TaskId): Promise<QueuedTask | null> {
   const response = await fetchWithAuth(`/queue/task/${encodeURIComponent(taskId)}`, undefined, {
     extraSuccessStatus: [404],
   })
@@ -342,7 +634,7 @@ export async function fetchQueuedTask(taskId: TaskId): Promise<WorkItem | null>
     return null
   }
   const json = await response.json()
-  return WorkItemSchema.parse(json)
+  return QueuedTaskSchema.parse(json)
 }
 
 export async function fetchRequestRevision(revision: string): Promise<RequestRevision | null> {
@@ -511,6 +803,46 @@ export async function fetchCreateSnapshot(): Promise<SnapshotResponse> {
   return SnapshotResponseSchema.parse(await res.json())
 }
 
+export function querySnapshots() {
+  const loggedIn = isLoggedIn()
+  const isAdmin = storeToRefs(useUserStore()).isAdmin
+  return useQuery({
+    queryKey: ['snapshots'],
+    queryFn: fetchSnapshots,
+    meta: {
+      purpose: 'fetching snapshots',
+    },
+    enabled: computed(() => loggedIn.value && isAdmin.value),
+  })
+}
+
+export async function fetchSnapshots(): Promise<SnapshotInfo[]> {
+  const res = await fetchWithAuth('/admin/snapshots')
+  return SnapshotInfoSchema.array().parse(await res.json())
+}
+
+export function mutateRestoreSnapshot(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchRestoreSnapshot,
+    onSuccess: async () => {
+      // Everything might have changed
+      await queryClient.invalidateQueries()
+    },
+    meta: {
+      purpose: 'restoring a snapshot',
+    },
+  })
+}
+
+export async function fetchRestoreSnapshot(name: string): Promise<RestoreResponse> {
+  const res = await fetchWithAuth('/admin/restore', {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ name }),
+  })
+  return RestoreResponseSchema.parse(await res.json())
+}
+
 export function mutateRerunForGrading() {
   return useMutation({
     mutationFn: fetchRerunForGrading,
@@ -527,6 +859,98 @@ export async function fetchRerunForGrading(category: string): Promise<RerunRespo
   return RerunResponseSchema.parse(await res.json())
 }
 
+export function mutateRerunTask() {
+  return useMutation({
+    mutationFn: fetchRerunTask,
+    meta: {
+      purpose: 'rerunning the task',
+    },
+  })
+}
+
+export async function fetchRerunTask(taskId: TaskId): Promise<WorkItem> {
+  const res = await fetchWithAuth(`/admin/rerun-task/${encodeURIComponent(taskId)}`, {
+    method: 'POST',
+  })
+  return WorkItemSchema.parse(await res.json())
+}
+
+export function queryRunnerLogs(runnerId: MaybeRefOrGetter<string | undefined>) {
+  const enabled = computed(() => !!toRef(runnerId).value)
+  const isAdmin = storeToRefs(useUserStore()).isAdmin
+  return useQuery({
+    queryKey: ['runner-logs', runnerId],
+    // we only enable it then
+    // eslint-disable-next-line @typescript-eslint/no-non-null-assertion
+    queryFn: () => fetchRunnerLogs(toValue(runnerId)!),
+    // The runner pushes requested logs with its next ping
+    refetchInterval: (query) => (query.state.data?.requested ? 5000 : false),
+    meta: {
+      purpose: 'fetching runner logs',
+    },
+    enabled: computed(() => enabled.value && isLoggedIn().value && isAdmin.value),
+  })
+}
+
+export async function fetchRunnerLogs(runnerId: string): Promise<RunnerLogs> {
+  const res = await fetchWithAuth(`/admin/runner-logs/${encodeURIComponent(runnerId)}`)
+  return RunnerLogsSchema.parse(await res.json())
+}
+
+export function mutateRequestRunnerLogs(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchRequestRunnerLogs,
+    onSuccess: async (_, runnerId) => {
+      await queryClient.invalidateQueries({ queryKey: ['runner-logs', runnerId] })
+    },
+    meta: {
+      purpose: 'requesting runner logs',
+    },
+  })
+}
+
+export async function fetchRequestRunnerLogs(runnerId: string): Promise<void> {
+  await fetchWithAuth(`/admin/runner-logs/${encodeURIComponent(runnerId)}`, {
+    method: 'POST',
+  })
+}
+
+export function queryTestSuiteSnapshots() {
+  const loggedIn = isLoggedIn()
+  return useQuery({
+    queryKey: ['test-suite-snapshots'],
+    queryFn: fetchTestSuiteSnapshots,
+    meta: {
+      purpose: 'fetching frozen test suites',
+    },
+    enabled: loggedIn,
+  })
+}
+
+export async function fetchTestSuiteSnapshots(): Promise<TestSuiteSnapshot[]> {
+  const response = await fetchWithAuth('/test-suite-snapshots')
+  return TestSuiteSnapshotSchema.array().parse(await response.json())
+}
+
+export function mutateFreezeTestSuite(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchFreezeTestSuite,
+    onSuccess: async () => {
+      await queryClient.invalidateQueries({ queryKey: ['test-suite-snapshots'] })
+    },
+    meta: {
+      purpose: 'freezing a test suite',
+    },
+  })
+}
+
+export async function fetchFreezeTestSuite(category: string): Promise<TestSuiteSnapshot> {
+  const res = await fetchWithAuth(`/admin/freeze_tests/${encodeURIComponent(category)}`, {
+    method: 'POST',
+  })
+  return TestSuiteSnapshotSchema.parse(await res.json())
+}
+
 export function mutateRehashTests() {
   return useMutation({
     mutationFn: fetchRehashTests,
@@ -559,3 +983,458 @@ export async function fetchTeamStatistics(): Promise<TeamStatistics[]> {
   const response = await fetchWithAuth('/admin/team_statistics')
   return TeamStatisticsSchema.array().parse(await response.json())
 }
+
+export function queryCourseMilestones() {
+  const loggedIn = isLoggedIn()
+  const isAdmin = storeToRefs(useUserStore()).isAdmin
+  return useQuery({
+    queryKey: ['course-milestones'],
+    queryFn: fetchCourseMilestones,
+    meta: {
+      purpose: 'fetching course milestones',
+    },
+    enabled: computed(() => loggedIn.value && isAdmin.value),
+  })
+}
+
+export async function fetchCourseMilestones(): Promise<CourseMilestones> {
+  const response = await fetchWithAuth('/admin/milestones')
+  return CourseMilestonesSchema.parse(await response.json())
+}
+
+export type QueueSimulationParams = {
+  runners: number
+  submissionsPerHour: number
+  averageTaskDurationMs?: number
+}
+
+export function mutateSimulateQueue() {
+  return useMutation({
+    mutationFn: fetchQueueSimulation,
+    meta: {
+      purpose: 'simulating the queue',
+    },
+  })
+}
+
+export async function fetchQueueSimulation(
+  params: QueueSimulationParams,
+): Promise<QueueSimulation> {
+  const query = new URLSearchParams({
+    runners: params.runners.toString(),
+    submissions_per_hour: params.submissionsPerHour.toString(),
+  })
+  if (params.averageTaskDurationMs !== undefined) {
+    query.set('average_task_duration_ms', params.averageTaskDurationMs.toString())
+  }
+  const response = await fetchWithAuth(`/admin/queue_simulation?${query}`)
+  return QueueSimulationSchema.parse(await response.json())
+}
+
+export type GradingPreviewParams = {
+  formula: string
+  category: string
+}
+
+export function mutateGradingPreview() {
+  return useMutation({
+    mutationFn: fetchGradingPreview,
+    meta: {
+      purpose: 'previewing the grading formula',
+    },
+  })
+}
+
+export async function fetchGradingPreview(params: GradingPreviewParams): Promise<GradingPreview> {
+  const response = await fetchWithAuth('/admin/grading-preview', {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify(params),
+  })
+  return GradingPreviewSchema.parse(await response.json())
+}
+
+export function mutatePruneOldTasks() {
+  return useMutation({
+    mutationFn: fetchPruneOldTasks,
+    meta: {
+      purpose: 'pruning old tasks',
+    },
+  })
+}
+
+export async function fetchPruneOldTasks(dryRun: boolean): Promise<RetentionReport> {
+  const response = await fetchWithAuth(`/admin/retention/prune?dry_run=${dryRun}`, {
+    method: 'POST',
+  })
+  return RetentionReportSchema.parse(await response.json())
+}
+
+export function mutateCheckConsistency() {
+  return useMutation({
+    mutationFn: fetchCheckConsistency,
+    meta: {
+      purpose: 'checking the database consistency',
+    },
+  })
+}
+
+export async function fetchCheckConsistency(fix: boolean): Promise<ConsistencyReport> {
+  const response = await fetchWithAuth(`/admin/consistency?fix=${fix}`)
+  return ConsistencyReportSchema.parse(await response.json())
+}
+
+export function mutateLintTests() {
+  return useMutation({
+    mutationFn: fetchLintTests,
+    meta: {
+      purpose: 'linting the tests',
+    },
+  })
+}
+
+export async function fetchLintTests(): Promise<TestLintReport> {
+  const response = await fetchWithAuth('/admin/lint-tests', { method: 'POST' })
+  return TestLintReportSchema.parse(await response.json())
+}
+
+export function queryCourses() {
+  const loggedIn = isLoggedIn()
+  const isAdmin = storeToRefs(useUserStore()).isAdmin
+  return useQuery({
+    queryKey: ['courses'],
+    queryFn: fetchCourses,
+    meta: {
+      purpose: 'fetching courses',
+    },
+    enabled: computed(() => loggedIn.value && isAdmin.value),
+  })
+}
+
+export async function fetchCourses(): Promise<Course[]> {
+  const response = await fetchWithAuth('/admin/courses')
+  return z.array(CourseSchema).parse(await response.json())
+}
+
+export function mutateCreateCourse(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchCreateCourse,
+    onSuccess: async () => {
+      await queryClient.invalidateQueries({ queryKey: ['courses'] })
+    },
+    meta: {
+      purpose: 'creating the course',
+    },
+  })
+}
+
+export async function fetchCreateCourse({
+  id,
+  displayName,
+}: {
+  id: string
+  displayName: string
+}): Promise<Course> {
+  const response = await fetchWithAuth('/admin/courses', {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ id, displayName }),
+  })
+  return CourseSchema.parse(await response.json())
+}
+
+export function mutateArchiveCourse(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: ([id, archived]: [string, boolean]) => fetchArchiveCourse(id, archived),
+    onSuccess: async () => {
+      await queryClient.invalidateQueries({ queryKey: ['courses'] })
+    },
+    meta: {
+      purpose: 'archiving the course',
+    },
+  })
+}
+
+export async function fetchArchiveCourse(id: string, archived: boolean): Promise<Course> {
+  const response = await fetchWithAuth(`/admin/courses/${encodeURIComponent(id)}`, {
+    method: 'PATCH',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ archived }),
+  })
+  return CourseSchema.parse(await response.json())
+}
+
+export function queryTeamInvites() {
+  const loggedIn = isLoggedIn()
+  const isAdmin = storeToRefs(useUserStore()).isAdmin
+  return useQuery({
+    queryKey: ['team-invites'],
+    queryFn: fetchTeamInvites,
+    meta: {
+      purpose: 'fetching team invites',
+    },
+    enabled: computed(() => loggedIn.value && isAdmin.value),
+  })
+}
+
+export async function fetchTeamInvites(): Promise<TeamInvite[]> {
+  const response = await fetchWithAuth('/admin/invites')
+  return z.array(TeamInviteSchema).parse(await response.json())
+}
+
+export function mutateCreateTeamInvite(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchCreateTeamInvite,
+    onSuccess: async () => {
+      await queryClient.invalidateQueries({ queryKey: ['team-invites'] })
+    },
+    meta: {
+      purpose: 'creating the team invite',
+    },
+  })
+}
+
+export async function fetchCreateTeamInvite({
+  teamId,
+  maxUses,
+  expiresAt,
+}: {
+  teamId: TeamId
+  maxUses: number
+  expiresAt?: Date
+}): Promise<TeamInvite> {
+  const response = await fetchWithAuth('/admin/invites', {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ teamId, maxUses, expiresAt: expiresAt?.toISOString() }),
+  })
+  return TeamInviteSchema.parse(await response.json())
+}
+
+export function mutateDeleteTeamInvite(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchDeleteTeamInvite,
+    onSuccess: async () => {
+      await queryClient.invalidateQueries({ queryKey: ['team-invites'] })
+    },
+    meta: {
+      purpose: 'deleting the team invite',
+    },
+  })
+}
+
+export async function fetchDeleteTeamInvite(code: string): Promise<void> {
+  await fetchWithAuth(`/admin/invites/${encodeURIComponent(code)}`, { method: 'DELETE' })
+}
+
+export function mutateRedeemTeamInvite(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchRedeemTeamInvite,
+    onSuccess: (me) => {
+      queryClient.setQueryData(['userinfo'], me)
+    },
+    meta: {
+      purpose: 'joining the team',
+    },
+  })
+}
+
+export async function fetchRedeemTeamInvite(code: string): Promise<ShowMyselfResponse> {
+  const response = await fetchWithAuth('/users/me/invite', {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ code }),
+  })
+  return ShowMyselfResponseSchema.parse(await response.json())
+}
+
+/**
+ * Fetches the signed archive of all tests another crow instance can import.
+ */
+export async function fetchTestArchive(): Promise<Blob> {
+  const response = await fetchWithAuth('/admin/tests-export')
+  return await response.blob()
+}
+
+/**
+ * Fetches the CSV of the metadata of all tasks queued in the range, for research.
+ */
+export async function fetchTaskExport({
+  from,
+  to,
+  pseudonymize,
+  salt,
+}: {
+  from?: Date
+  to?: Date
+  pseudonymize: boolean
+  salt?: string
+}): Promise<Blob> {
+  const params = new URLSearchParams()
+  if (from) {
+    params.set('from', from.toISOString())
+  }
+  if (to) {
+    params.set('to', to.toISOString())
+  }
+  params.set('pseudonymize', String(pseudonymize))
+  if (pseudonymize && salt) {
+    params.set('salt', salt)
+  }
+  const response = await fetchWithAuth(`/admin/export/tasks.csv?${params}`)
+  return await response.blob()
+}
+
+export function mutateImportTestArchive() {
+  return useMutation({
+    mutationFn: fetchImportTestArchive,
+    meta: {
+      purpose: 'importing the test archive',
+    },
+  })
+}
+
+export async function fetchImportTestArchive({
+  archive,
+  conflict,
+}: {
+  archive: File
+  conflict: TestConflictResolution
+}): Promise<TestImportReport> {
+  const response = await fetchWithAuth(`/admin/tests-import?conflict=${conflict}`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/zip' },
+    body: archive,
+  })
+  return TestImportReportSchema.parse(await response.json())
+}
+
+export function queryNotifications(enabled: MaybeRefOrGetter<boolean>) {
+  const loggedIn = isLoggedIn()
+
+  return useQuery({
+    queryKey: ['notifications'],
+    queryFn: fetchNotifications,
+    enabled: computed(() => toValue(enabled) && loggedIn.value),
+    meta: {
+      purpose: 'fetching notifications',
+    },
+  })
+}
+
+export async function fetchNotifications(): Promise<Notification[]> {
+  const response = await fetchWithAuth('/team/notifications')
+  return NotificationSchema.array().parse(await response.json())
+}
+
+export function mutateMarkNotificationRead(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchMarkNotificationRead,
+    onSuccess: async () => {
+      await queryClient.invalidateQueries({ queryKey: ['notifications'] })
+    },
+    meta: {
+      purpose: 'marking a notification as read',
+    },
+  })
+}
+
+export async function fetchMarkNotificationRead(id: string): Promise<void> {
+  await fetchWithAuth(`/team/notifications/${encodeURIComponent(id)}/read`, {
+    method: 'POST',
+  })
+}
+
+export function queryTaskNotificationSettings(enabled: MaybeRefOrGetter<boolean>) {
+  const loggedIn = isLoggedIn()
+
+  return useQuery({
+    queryKey: ['task-notification-settings'],
+    queryFn: fetchTaskNotificationSettings,
+    enabled: computed(() => toValue(enabled) && loggedIn.value),
+    meta: {
+      purpose: 'fetching your task notification settings',
+    },
+  })
+}
+
+export async function fetchTaskNotificationSettings(): Promise<TaskNotificationSettingsResponse> {
+  const response = await fetchWithAuth('/users/me/task-notifications')
+  return TaskNotificationSettingsResponseSchema.parse(await response.json())
+}
+
+export function mutateTaskNotificationSettings(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchSetTaskNotificationSettings,
+    onSuccess: async () => {
+      await queryClient.invalidateQueries({ queryKey: ['task-notification-settings'] })
+    },
+    meta: {
+      purpose: 'updating your task notification settings',
+    },
+  })
+}
+
+export async function fetchSetTaskNotificationSettings(
+  settings: TaskNotificationSettings,
+): Promise<TaskNotificationSettings> {
+  const response = await fetchWithAuth('/users/me/task-notifications', {
+    method: 'PUT',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify(settings),
+  })
+  return TaskNotificationSettingsSchema.parse(await response.json())
+}
+
+export function queryProvisionalTests() {
+  const loggedIn = isLoggedIn()
+  const isAdmin = storeToRefs(useUserStore()).isAdmin
+  return useQuery({
+    queryKey: ['provisional-tests'],
+    queryFn: fetchProvisionalTests,
+    meta: {
+      purpose: 'fetching provisional tests',
+    },
+    enabled: computed(() => loggedIn.value && isAdmin.value),
+  })
+}
+
+export async function fetchProvisionalTests(): Promise<TestWithTestTasting[]> {
+  const response = await fetchWithAuth('/admin/provisional_tests')
+  return TestWithTestTastingSchema.array().parse(await response.json())
+}
+
+export function mutateReviewProvisionalTest(queryClient: QueryClient) {
+  return useMutation({
+    mutationFn: fetchReviewProvisionalTest,
+    onSuccess: async () => {
+      await queryClient.invalidateQueries({ queryKey: ['provisional-tests'] })
+      await queryClient.invalidateQueries({ queryKey: ['tests'] })
+    },
+    meta: {
+      purpose: 'reviewing a provisional test',
+    },
+  })
+}
+
+/**
+ * Approves the test if no rejection reason is given, otherwise rejects it
+ */
+export async function fetchReviewProvisionalTest({
+  testId,
+  rejectionReason,
+}: {
+  testId: TestId
+  rejectionReason?: string
+}): Promise<void> {
+  const id = encodeURIComponent(testId)
+  if (rejectionReason === undefined) {
+    await fetchWithAuth(`/admin/provisional_tests/${id}/approve`, { method: 'POST' })
+  } else {
+    await fetchWithAuth(`/admin/provisional_tests/${id}/reject`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ reason: rejectionReason }),
+    })
+  }
+}
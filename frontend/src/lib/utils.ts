@@ -69,6 +69,15 @@ export function formatBusyDuration(currentTime: number, insertTime: number) {
   return `Since ${new Date(insertTime).toLocaleString()}`
 }
 
+/**
+ * Renders the command so it can be pasted into a POSIX shell.
+ */
+export function shellCommandLine(command: string[]): string {
+  return command
+    .map((arg) => (/^[\w\-./=:,+@%]+$/.test(arg) ? arg : `'${arg.replaceAll("'", "'\\''")}'`))
+    .join(' ')
+}
+
 export function statusColor(
   status: 'Success' | 'Failure' | 'Error' | 'Timeout' | 'Aborted' | 'Queued' | 'Started',
   prefix: string,
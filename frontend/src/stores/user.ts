@@ -23,7 +23,19 @@ export const useUserStore = defineStore('user', () => {
         credentials: 'include',
       },
     )
-    const json = await res.json()
+    storeLogin(await res.json())
+  }
+
+  async function logInWithPassword(username: string, password: string) {
+    const res = await fetchWithError('/login/password', {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ username, password }),
+    })
+    storeLogin(await res.json())
+  }
+
+  function storeLogin(json: { user: unknown; token: string }) {
     user.value = OwnUserSchema.parse(json.user)
     token.value = json.token
 
@@ -69,7 +81,18 @@ export const useUserStore = defineStore('user', () => {
     }
   }
 
-  return { accountReady, isAdmin, token, team, user, loggedIn, logIn, logOut, validateToken }
+  return {
+    accountReady,
+    isAdmin,
+    token,
+    team,
+    user,
+    loggedIn,
+    logIn,
+    logInWithPassword,
+    logOut,
+    validateToken,
+  }
 })
 
 export function hydrateUserStore() {
@@ -7,10 +7,24 @@ export const TeamIntegrationTokenSchema = z.string()
 export const TestIdSchema = z.string()
 export const UserIdSchema = z.string()
 
+export const OutputRecordingSchema = z.object({
+  events: z.array(
+    z.object({
+      offsetMs: z.number(),
+      stream: z.union([z.literal('stdout'), z.literal('stderr')]),
+      data: z.string(),
+    }),
+  ),
+  truncated: z.boolean(),
+})
+
 export const AbortedExecutionSchema = z.object({
   stdout: z.string(),
   stderr: z.string(),
   runtime: z.number().describe('duration in ms'),
+  infrastructureLog: z.string().nullish(),
+  recording: OutputRecordingSchema.nullish(),
+  fullOutputId: z.string().nullish(),
 })
 
 export const ExecutingTestSchema = z.object({
@@ -32,12 +46,24 @@ export const InternalErrorSchema = z.object({
   runtime: z.number().describe('duration in ms'),
 })
 
+// Out of order due to dependencies
+export const ResourceUsageSchema = z.object({
+  wallTime: z.number().describe('duration in ms'),
+  userTime: z.number().describe('duration in ms'),
+  systemTime: z.number().describe('duration in ms'),
+  maxRssKib: z.number(),
+})
+
 // Out of order due to dependencies
 export const FinishedExecutionSchema = z.object({
   stdout: z.string(),
   stderr: z.string(),
   runtime: z.number().describe('duration in ms'),
   exitStatus: z.number().nullable(),
+  infrastructureLog: z.string().nullish(),
+  recording: OutputRecordingSchema.nullish(),
+  resourceUsage: ResourceUsageSchema.nullish(),
+  fullOutputId: z.string().nullish(),
 })
 
 export const ExecutionOutputSchema = z.discriminatedUnion('type', [
@@ -52,6 +78,12 @@ export const ExecutionOutputSchema = z.discriminatedUnion('type', [
   z.object({ type: z.literal('Timeout') }).merge(FinishedExecutionSchema),
 ])
 
+export const CommitMetadataSchema = z.object({
+  authorName: z.string(),
+  authorEmail: z.string(),
+  committedAt: z.number().transform((ms) => new Date(ms)),
+})
+
 // Out of order due to dependencies
 export const FinishedTaskInfoSchema = z.object({
   taskId: TaskIdSchema,
@@ -60,6 +92,17 @@ export const FinishedTaskInfoSchema = z.object({
   teamId: TeamIdSchema,
   revisionId: z.string(),
   commitMessage: z.string(),
+  environment: z
+    .object({
+      locale: z.string(),
+      timezone: z.string(),
+      umask: z.number(),
+      sourceDateEpoch: z.number(),
+    })
+    .nullable()
+    .optional(),
+  rerunOf: TaskIdSchema.nullable().default(null),
+  commit: CommitMetadataSchema.nullable().default(null),
 })
 
 export const TestExecutionOutputSchema = z.discriminatedUnion('type', [
@@ -70,6 +113,7 @@ export const TestExecutionOutputSchema = z.discriminatedUnion('type', [
   }),
   z.object({ type: z.literal('CompilerFailed'), compilerOutput: ExecutionOutputSchema }),
   z.object({ type: z.literal('Error'), outputSoFar: ExecutionOutputSchema }),
+  z.object({ type: z.literal('Skipped'), outputSoFar: ExecutionOutputSchema }),
   z.object({
     type: z.literal('Success'),
     compilerOutput: ExecutionOutputSchema,
@@ -78,11 +122,27 @@ export const TestExecutionOutputSchema = z.discriminatedUnion('type', [
 ])
 
 // Out of order due to dependencies
+export const TestInvocationSchema = z.object({
+  compiler: z.array(z.string()).nullable(),
+  binary: z.array(z.string()).nullable(),
+})
+
+export const CompilerDiagnosticSchema = z.object({
+  severity: z.enum(['error', 'warning', 'note']),
+  message: z.string(),
+  file: z.string().nullable().default(null),
+  line: z.number().nullable().default(null),
+  column: z.number().nullable().default(null),
+})
+
 export const FinishedTestSchema = z.object({
   testId: z.string(),
   category: z.string().nullable(),
   provisionalForCategory: z.string().nullable(),
   output: TestExecutionOutputSchema,
+  profile: z.string().nullable().default(null),
+  invocation: TestInvocationSchema.default({ compiler: null, binary: null }),
+  diagnostics: z.array(CompilerDiagnosticSchema).default([]),
 })
 
 export const CountWithProvisionalSchema = z.object({
@@ -95,16 +155,27 @@ export const FinishedCompilerTaskStatisticsSchema = z.object({
   abort: CountWithProvisionalSchema,
   error: CountWithProvisionalSchema,
   failure: CountWithProvisionalSchema,
+  skipped: CountWithProvisionalSchema.default({ normal: 0, provisional: 0, total: 0 }),
   success: CountWithProvisionalSchema,
   timeout: CountWithProvisionalSchema,
   total: CountWithProvisionalSchema,
 })
 
+export const FailureClusterSchema = z.object({
+  stage: z.enum(['Compiler', 'Binary', 'Unknown']),
+  status: ExecutionExitStatusSchema,
+  signal: z.number().nullable(),
+  exitCode: z.number().nullable(),
+  firstStderrLine: z.string().nullable(),
+  testIds: z.array(TestIdSchema),
+})
+
 export const FinishedCompilerTaskSchema = z.discriminatedUnion('type', [
   z.object({
     type: z.literal('BuildFailed'),
     info: FinishedTaskInfoSchema,
     buildOutput: ExecutionOutputSchema,
+    buildHints: z.array(z.string()).default([]),
   }),
   z.object({
     type: z.literal('RanTests'),
@@ -113,6 +184,7 @@ export const FinishedCompilerTaskSchema = z.discriminatedUnion('type', [
     tests: z.array(FinishedTestSchema),
     outdated: z.array(TestIdSchema),
     statistics: FinishedCompilerTaskStatisticsSchema,
+    failureClusters: z.array(FailureClusterSchema).default([]),
   }),
 ])
 
@@ -121,6 +193,8 @@ export const FinishedTestSummarySchema = z.object({
   testId: TestIdSchema,
   provisionalForCategory: z.string().nullable(),
   output: ExecutionExitStatusSchema,
+  skipped: z.boolean().default(false),
+  profile: z.string().nullable().default(null),
 })
 
 export const FinishedCompilerTaskSummarySchema = z.discriminatedUnion('type', [
@@ -192,6 +266,11 @@ export const RepoSchema = z.object({
   url: z.string(),
 })
 
+export const TeamSshKeySchema = z.object({
+  team: TeamIdSchema,
+  publicKey: z.string(),
+})
+
 export const RequestRevisionSchema = z.object({
   taskId: TaskIdSchema,
 })
@@ -203,6 +282,20 @@ export const WorkItemSchema = z.object({
   revision: z.string(),
   commitMessage: z.string(),
   insertTime: z.number().transform((ms) => new Date(ms)),
+  identicalTo: TaskIdSchema.nullable().default(null),
+  rerunOf: TaskIdSchema.nullable().default(null),
+  commit: CommitMetadataSchema.nullable().default(null),
+})
+
+export const QueuePositionSchema = z.object({
+  position: z.number(),
+  activeRunners: z.number(),
+  averageTaskDurationMs: z.number().nullable(),
+  etaMs: z.number().nullable(),
+})
+
+export const QueuedTaskSchema = WorkItemSchema.extend({
+  position: QueuePositionSchema,
 })
 
 export const RunnerWorkingOnSchema = z.discriminatedUnion('type', [
@@ -244,9 +337,15 @@ export const RunnerUpdateSchema = z.discriminatedUnion('type', [
   z.object({ type: z.literal('StartedTest'), testId: TestIdSchema }),
   z.object({ type: z.literal('FinishedTest'), result: FinishedTestSummarySchema }),
   z.object({ type: z.literal('Done') }),
+  z.object({
+    type: z.literal('Requeued'),
+    attempt: z.number(),
+    maxAttempts: z.number(),
+  }),
 ])
 
 export const RunnerUpdateMessageSchema = z.object({
+  id: z.number(),
   update: RunnerUpdateSchema,
   time: z.number().transform((ms) => new Date(ms)),
 })
@@ -274,8 +373,18 @@ export const CompilerFailReasonSchema = z.union([
 ])
 
 export const TestModifierSchema = z.discriminatedUnion('type', [
-  z.object({ type: z.literal('ExitCode'), code: z.number() }),
+  z.object({ type: z.literal('ExitCode'), code: z.number().int().min(0).max(255) }),
   z.object({ type: z.literal('ExpectedOutput'), output: z.string() }),
+  z.object({ type: z.literal('ExpectedOutputAnyOf'), outputs: z.array(z.string()) }),
+  z.object({
+    type: z.literal('ExpectedFileOutput'),
+    path: z.string(),
+    contents: z.string(),
+  }),
+  z.object({ type: z.literal('FloatTolerance'), tolerance: z.number() }),
+  z.object({ type: z.literal('IgnoreLineEndings') }),
+  z.object({ type: z.literal('IgnoreTrailingWhitespace') }),
+  z.object({ type: z.literal('IgnoreTrailingNewlines') }),
   z.object({ type: z.literal('ProgramArgument'), arg: z.string() }),
   z.object({ type: z.literal('ProgramArgumentFile'), contents: z.string() }),
   z.object({ type: z.literal('ProgramInput'), input: z.string() }),
@@ -283,6 +392,19 @@ export const TestModifierSchema = z.discriminatedUnion('type', [
   z.object({ type: z.literal('ShouldFail'), reason: CompilerFailReasonSchema }),
   z.object({ type: z.literal('ShouldSucceed') }),
   z.object({ type: z.literal('ShouldTimeout') }),
+  z.object({ type: z.literal('UnorderedLines') }),
+])
+
+export const TestVisibilitySchema = z.discriminatedUnion('type', [
+  z.object({ type: z.literal('Public') }),
+  z.object({ type: z.literal('TeamOnly') }),
+  z.object({ type: z.literal('HiddenUntil'), until: z.number() }),
+])
+
+export const TestAcceptanceSchema = z.discriminatedUnion('type', [
+  z.object({ type: z.literal('Accepted') }),
+  z.object({ type: z.literal('Pending'), message: z.string() }),
+  z.object({ type: z.literal('Rejected'), message: z.string() }),
 ])
 
 export const TestSchema = z.object({
@@ -293,7 +415,12 @@ export const TestSchema = z.object({
   binaryModifiers: z.array(TestModifierSchema),
   adminAuthored: z.boolean(),
   limitedToCategory: z.boolean(),
+  practice: z.boolean().default(false),
   provisionalForCategory: z.string().nullable(),
+  timeoutMs: z.number().nullable(),
+  dependsOn: z.array(TestIdSchema).default([]),
+  license: z.string().nullable().default(null),
+  visibility: TestVisibilitySchema.default({ type: 'Public' }),
   lastUpdated: z.number().transform((ms) => new Date(ms)),
 })
 
@@ -302,15 +429,25 @@ export const TestTastingResultSchema = z.discriminatedUnion('type', [
   z.object({ type: z.literal('Failure'), output: TestExecutionOutputSchema }),
 ])
 
+export const ReferenceOutputSchema = z.object({
+  image: z.string(),
+  compileCommand: z.array(z.string()),
+  computedAt: z.number().transform((ms) => new Date(ms)),
+})
+
 export const TestWithTestTastingSchema = TestSchema.merge(
   z.object({
+    acceptance: TestAcceptanceSchema.default({ type: 'Accepted' }),
     testTastingResult: TestTastingResultSchema.nullable(),
+    referenceOutput: ReferenceOutputSchema.nullish(),
   }),
 )
 
 export const SetTestResponseSchema = z.discriminatedUnion('type', [
   z.object({ type: z.literal('TastingFailed'), output: TestExecutionOutputSchema }),
-  z.object({ type: z.literal('TestAdded') }).merge(TestSchema),
+  z
+    .object({ type: z.literal('TestAdded'), acceptance: TestAcceptanceSchema.optional() })
+    .merge(TestSchema),
 ])
 
 export const TeamInfoSchema = z.object({
@@ -319,6 +456,13 @@ export const TeamInfoSchema = z.object({
   members: z.array(UserSchema),
 })
 
+export const TestStatsSchema = z.object({
+  testId: TestIdSchema,
+  passingTeams: z.number(),
+  failingTeams: z.number(),
+  averageRuntimeMs: z.number().nullable(),
+})
+
 export const TestSummarySchema = z.object({
   id: TestIdSchema,
   creatorId: TeamIdSchema,
@@ -328,6 +472,9 @@ export const TestSummarySchema = z.object({
   testTasteSuccess: z.boolean().nullable(),
   provisionalForCategory: z.string().nullable(),
   limitedToCategory: z.boolean(),
+  practice: z.boolean().default(false),
+  visibility: TestVisibilitySchema.default({ type: 'Public' }),
+  acceptance: TestAcceptanceSchema.default({ type: 'Accepted' }),
   lastUpdated: z.number().transform((ms) => new Date(ms)),
 })
 
@@ -335,14 +482,17 @@ export const TestCategorySchema = z.object({
   startsAt: z.number().transform((ms) => new Date(ms)),
   labsEndAt: z.number().transform((ms) => new Date(ms)),
   testsEndAt: z.number().transform((ms) => new Date(ms)),
+  compileOnly: z.boolean(),
 })
 
 export const ListTestResponseSchema = z.object({
   tests: z.array(TestSummarySchema),
   categories: z.record(z.string(), TestCategorySchema),
+  total: z.number().optional(),
 })
 
 export const UserRoleSchema = z.enum(['Admin', 'Regular'])
+export const LoginProviderSchema = z.enum(['oidc', 'static', 'ldap'])
 export const FullUserForAdminSchema = OwnUserSchema.merge(z.object({ role: UserRoleSchema }))
 
 export const AdminUserInfoSchema = FullUserForAdminSchema.merge(
@@ -357,11 +507,34 @@ export const SnapshotResponseSchema = z.object({
   exported: z.array(TeamIdSchema),
 })
 
+export const SnapshotInfoSchema = z.object({
+  name: z.string(),
+  createdAt: z.number().transform((ms) => new Date(ms)),
+  dbSizeBytes: z.number(),
+})
+
+export const RestoreResponseSchema = z.object({
+  restored: z.string(),
+  backup: z.string(),
+})
+
 export const RerunResponseSchema = z.object({
   errors: z.array(z.string()),
   submitted: z.array(z.tuple([TeamIdSchema, TaskIdSchema])),
 })
 
+export const TestSuiteSnapshotSchema = z.object({
+  category: z.string(),
+  frozenAt: z.number().transform((ms) => new Date(ms)),
+  frozenBy: UserIdSchema.nullable(),
+  tests: z.array(
+    z.object({
+      id: TestIdSchema,
+      hash: z.string(),
+    }),
+  ),
+})
+
 export const TestClassificationSchema = z.object({
   runtimeError: z.number(),
   compileError: z.number(),
@@ -385,14 +558,201 @@ export const TeamStatisticsSchema = z.object({
   ),
 })
 
+export const TeamActivityDaySchema = z.object({
+  day: z.string(),
+  submissions: z.number(),
+  bestPassRate: z.number(),
+  passRateDelta: z.number().nullable(),
+})
+
+export const TaskPerformanceSchema = z.object({
+  taskId: TaskIdSchema,
+  revision: z.string(),
+  queueTime: z.number(),
+  measuredTests: z.number(),
+  averageBinarySize: z.number().nullable(),
+  maxBinarySize: z.number().nullable(),
+  averageCompileDurationMs: z.number().nullable(),
+  totalCompileDurationMs: z.number().nullable(),
+  binarySizeDelta: z.number().nullable(),
+  compileDurationDeltaMs: z.number().nullable(),
+})
+
+export const TeamPerformanceSchema = z.object({
+  teamId: TeamIdSchema,
+  tasks: z.array(TaskPerformanceSchema),
+})
+
+export const TeamMilestonesSchema = z.object({
+  team: TeamIdSchema,
+  tests: z.array(
+    z.object({
+      testId: TestIdSchema,
+      taskId: TaskIdSchema.nullable(),
+      passedAt: z.number().transform((ms) => new Date(ms)),
+    }),
+  ),
+  categories: z.array(
+    z.object({
+      category: z.string(),
+      threshold: z.number(),
+      taskId: TaskIdSchema.nullable(),
+      reachedAt: z.number().transform((ms) => new Date(ms)),
+    }),
+  ),
+})
+
+export const CourseMilestonesSchema = z.object({
+  teamCount: z.number(),
+  milestones: z.array(
+    z.object({
+      category: z.string(),
+      threshold: z.number(),
+      reachedAt: z.array(z.number().transform((ms) => new Date(ms))),
+    }),
+  ),
+})
+
+export const QueueSimulationSchema = z.object({
+  runners: z.number(),
+  currentRunners: z.number(),
+  submissionsPerHour: z.number(),
+  averageTaskDurationMs: z.number(),
+  queuedTasks: z.number(),
+  runningTasks: z.number(),
+  projection: z.array(
+    z.object({
+      hour: z.number(),
+      arrivals: z.number(),
+      queueLength: z.number(),
+      averageWaitMs: z.number(),
+      maxWaitMs: z.number(),
+    }),
+  ),
+})
+
+export const GradingPreviewSchema = z.object({
+  formula: z.string(),
+  teams: z.array(
+    z.object({
+      team: TeamIdSchema,
+      taskId: TaskIdSchema.nullable(),
+      points: z.number().nullable(),
+      currentPoints: z.number().nullable(),
+      error: z.string().nullable(),
+    }),
+  ),
+})
+
+export const TaskNotificationSettingsSchema = z.object({
+  onSuccess: z.boolean(),
+  onFailure: z.boolean(),
+  email: z.string().nullable(),
+  webhookUrl: z.string().nullable(),
+})
+
+export const TaskNotificationSettingsResponseSchema = z.object({
+  settings: TaskNotificationSettingsSchema,
+  available: z.boolean(),
+  emailAvailable: z.boolean(),
+})
+
+export const NotificationSchema = z.object({
+  id: z.string(),
+  team: TeamIdSchema,
+  message: z.string(),
+  createdAt: z.number().transform((ms) => new Date(ms)),
+  readAt: z
+    .number()
+    .nullable()
+    .transform((ms) => (ms === null ? null : new Date(ms))),
+})
+
+export const RetentionReportSchema = z.object({
+  dryRun: z.boolean(),
+  tasks: z.array(
+    z.object({
+      taskId: TaskIdSchema,
+      teamId: TeamIdSchema,
+      endTime: z.number().transform((ms) => new Date(ms)),
+    }),
+  ),
+  executionResults: z.number(),
+})
+
+const ConsistencyIssueSchema = z.object({
+  count: z.number(),
+  ids: z.array(z.string()),
+  repaired: z.number(),
+})
+
+export const ConsistencyReportSchema = z.object({
+  fixed: z.boolean(),
+  orphanedExecutions: ConsistencyIssueSchema,
+  resultsOfDeletedTests: ConsistencyIssueSchema,
+  finalizedMissingTasks: ConsistencyIssueSchema,
+  queuedForUnknownTeams: ConsistencyIssueSchema,
+})
+
+export const CourseSchema = z.object({
+  id: z.string(),
+  displayName: z.string(),
+  archived: z.boolean(),
+  createdAt: z.number().transform((ms) => new Date(ms)),
+})
+
+export const TeamInviteSchema = z.object({
+  code: z.string(),
+  teamId: TeamIdSchema,
+  createdBy: z.string(),
+  createdAt: z.number().transform((ms) => new Date(ms)),
+  expiresAt: z
+    .number()
+    .nullable()
+    .transform((ms) => (ms === null ? null : new Date(ms))),
+  maxUses: z.number(),
+  uses: z.number(),
+})
+
+export const TestLintReportSchema = z.object({
+  duplicates: z.array(z.array(TestIdSchema)),
+  conflicts: z.array(z.array(TestIdSchema)),
+  violations: z.array(z.object({ testId: TestIdSchema, problem: z.string() })),
+})
+
+export const RunnerLogsSchema = z.object({
+  requested: z.boolean(),
+  logs: z
+    .object({
+      receivedAt: z.number().transform((ms) => new Date(ms)),
+      lines: z.array(z.string()),
+    })
+    .nullable(),
+})
+
+export const TestConflictResolutionSchema = z.enum(['skip', 'overwrite', 'rename'])
+
+export const TestImportReportSchema = z.object({
+  imported: z.array(TestIdSchema),
+  overwritten: z.array(TestIdSchema),
+  skipped: z.array(TestIdSchema),
+  renamed: z.array(z.object({ from: TestIdSchema, to: TestIdSchema })),
+  reassigned: z.array(TestIdSchema),
+  unknownCategories: z.array(z.string()),
+})
+
 export type AbortedExecution = z.infer<typeof AbortedExecutionSchema>
+export type CommitMetadata = z.infer<typeof CommitMetadataSchema>
+export type CompilerDiagnostic = z.infer<typeof CompilerDiagnosticSchema>
 export type ExecutingTest = z.infer<typeof ExecutingTestSchema>
 export type ExecutionExitStatus = z.infer<typeof ExecutionExitStatusSchema>
 export type ExecutionOutput = z.infer<typeof ExecutionOutputSchema>
+export type FailureCluster = z.infer<typeof FailureClusterSchema>
 export type FinishedCompilerTask = z.infer<typeof FinishedCompilerTaskSchema>
 export type FinishedCompilerTaskSummary = z.infer<typeof FinishedCompilerTaskSummarySchema>
 export type ApiFinishedCompilerTaskSummary = z.infer<typeof ApiFinishedCompilerTaskSummarySchema>
 export type FinishedExecution = z.infer<typeof FinishedExecutionSchema>
+export type ResourceUsage = z.infer<typeof ResourceUsageSchema>
 export type FinishedTaskInfo = z.infer<typeof FinishedTaskInfoSchema>
 export type FinishedTest = z.infer<typeof FinishedTestSchema>
 export type FinishedTestSummary = z.infer<typeof FinishedTestSummarySchema>
@@ -401,9 +761,11 @@ export type GithubIntegrationInfoResponse = z.infer<typeof GithubIntegrationInfo
 export type IntegrationInfoResponse = z.infer<typeof IntegrationInfoResponseSchema>
 export type InternalError = z.infer<typeof InternalErrorSchema>
 export type ListTestResponse = z.infer<typeof ListTestResponseSchema>
+export type LoginProvider = z.infer<typeof LoginProviderSchema>
 export type TestCategory = z.infer<typeof TestCategorySchema>
 export type QueueResponse = z.infer<typeof QueueResponseSchema>
 export type Repo = z.infer<typeof RepoSchema>
+export type TeamSshKey = z.infer<typeof TeamSshKeySchema>
 export type RequestRevision = z.infer<typeof RequestRevisionSchema>
 export type Runner = z.infer<typeof RunnerSchema>
 export type RunnerUpdate = z.infer<typeof RunnerUpdateSchema>
@@ -422,19 +784,46 @@ export type CompilerFailReason = z.infer<typeof CompilerFailReasonSchema>
 export type TestModifier = z.infer<typeof TestModifierSchema>
 export type TestWithTestTasting = z.infer<typeof TestWithTestTastingSchema>
 export type TestId = z.infer<typeof TestIdSchema>
+export type TestAcceptance = z.infer<typeof TestAcceptanceSchema>
 export type TestSummary = z.infer<typeof TestSummarySchema>
+export type TestVisibility = z.infer<typeof TestVisibilitySchema>
 export type User = z.infer<typeof UserSchema>
+export type OutputRecording = z.infer<typeof OutputRecordingSchema>
 export type OwnUser = z.infer<typeof OwnUserSchema>
 export type UserId = z.infer<typeof UserIdSchema>
 export type RunnerWorkingOn = z.infer<typeof RunnerWorkingOnSchema>
 export type WorkItem = z.infer<typeof WorkItemSchema>
+export type QueuePosition = z.infer<typeof QueuePositionSchema>
+export type QueuedTask = z.infer<typeof QueuedTaskSchema>
 export type UserRole = z.infer<typeof UserRoleSchema>
 export type FullUserForAdmin = z.infer<typeof FullUserForAdminSchema>
 export type AdminUserInfo = z.infer<typeof AdminUserInfoSchema>
 export type SnapshotResponse = z.infer<typeof SnapshotResponseSchema>
+export type SnapshotInfo = z.infer<typeof SnapshotInfoSchema>
+export type RestoreResponse = z.infer<typeof RestoreResponseSchema>
 export type RerunResponse = z.infer<typeof RerunResponseSchema>
+export type TestSuiteSnapshot = z.infer<typeof TestSuiteSnapshotSchema>
 export type TestClassification = z.infer<typeof TestClassificationSchema>
 export type TeamStatistics = z.infer<typeof TeamStatisticsSchema>
+export type TeamActivityDay = z.infer<typeof TeamActivityDaySchema>
+export type TestStats = z.infer<typeof TestStatsSchema>
+export type TaskPerformance = z.infer<typeof TaskPerformanceSchema>
+export type TeamPerformance = z.infer<typeof TeamPerformanceSchema>
+export type TeamMilestones = z.infer<typeof TeamMilestonesSchema>
+export type CourseMilestones = z.infer<typeof CourseMilestonesSchema>
+export type QueueSimulation = z.infer<typeof QueueSimulationSchema>
+export type GradingPreview = z.infer<typeof GradingPreviewSchema>
+export type TaskNotificationSettings = z.infer<typeof TaskNotificationSettingsSchema>
+export type TaskNotificationSettingsResponse = z.infer<typeof TaskNotificationSettingsResponseSchema>
+export type Notification = z.infer<typeof NotificationSchema>
+export type RetentionReport = z.infer<typeof RetentionReportSchema>
+export type ConsistencyReport = z.infer<typeof ConsistencyReportSchema>
+export type Course = z.infer<typeof CourseSchema>
+export type TeamInvite = z.infer<typeof TeamInviteSchema>
+export type RunnerLogs = z.infer<typeof RunnerLogsSchema>
+export type TestLintReport = z.infer<typeof TestLintReportSchema>
+export type TestConflictResolution = z.infer<typeof TestConflictResolutionSchema>
+export type TestImportReport = z.infer<typeof TestImportReportSchema>
 export type AdminFinalizedTask = z.infer<typeof AdminFinalizedTaskSchema>
 export type CountWithProvisional = z.infer<typeof CountWithProvisionalSchema>
 export type FinishedCompilerTaskStatistics = z.infer<typeof FinishedCompilerTaskStatisticsSchema>
@@ -444,7 +833,9 @@ export type ModifierValue<T extends TestModifier> = T extends { type: 'ExitCode'
   ? T['code']
   : T extends { type: 'ExpectedOutput' }
     ? T['output']
-    : T extends { type: 'ProgramArgument' }
+    : T extends { type: 'ExpectedFileOutput' }
+      ? T['contents']
+      : T extends { type: 'ProgramArgument' }
       ? T['arg']
       : T extends { type: 'ProgramArgumentFile' }
         ? T['contents']
@@ -458,7 +849,19 @@ export type ModifierValue<T extends TestModifier> = T extends { type: 'ExitCode'
                 ? undefined
                 : T extends { type: 'ShouldTimeout' }
                   ? undefined
-                  : 'ERROR, not exhaustive!'
+                  : T extends { type: 'FloatTolerance' }
+                    ? T['tolerance']
+                    : T extends { type: 'IgnoreLineEndings' }
+                      ? undefined
+                      : T extends { type: 'IgnoreTrailingWhitespace' }
+                        ? undefined
+                        : T extends { type: 'IgnoreTrailingNewlines' }
+                          ? undefined
+                          : T extends { type: 'ExpectedOutputAnyOf' }
+                            ? T['outputs']
+                            : T extends { type: 'UnorderedLines' }
+                              ? undefined
+                              : 'ERROR, not exhaustive!'
 
 export function toExecutionStatus(output: TestExecutionOutput): ExecutionExitStatus {
   switch (output.type) {
@@ -467,6 +870,7 @@ export function toExecutionStatus(output: TestExecutionOutput): ExecutionExitSta
     case 'BinaryFailed':
       return output.binaryOutput.type
     case 'Error':
+    case 'Skipped':
       return output.outputSoFar.type
     case 'Success':
       return 'Success'
@@ -478,9 +882,15 @@ export function toFinishedTestSummary(finishedTest: FinishedTest): FinishedTestS
     testId: finishedTest.testId,
     provisionalForCategory: finishedTest.provisionalForCategory,
     output: toExecutionStatus(finishedTest.output),
+    skipped: finishedTest.output.type === 'Skipped',
+    profile: finishedTest.profile,
   }
 }
 
+export function testDisplayName(test: { testId: TestId; profile: string | null }): string {
+  return test.profile ? `${test.testId} [${test.profile}]` : test.testId
+}
+
 export function toCompilerOutput(output: TestExecutionOutput): ExecutionOutput | undefined {
   switch (output.type) {
     case 'CompilerFailed':
@@ -488,6 +898,7 @@ export function toCompilerOutput(output: TestExecutionOutput): ExecutionOutput |
     case 'BinaryFailed':
       return output.compilerOutput
     case 'Error':
+    case 'Skipped':
       return undefined
     case 'Success':
       return output.compilerOutput
@@ -501,6 +912,7 @@ export function toBinaryOutput(output: TestExecutionOutput): ExecutionOutput | u
     case 'BinaryFailed':
       return output.binaryOutput
     case 'Error':
+    case 'Skipped':
       return output.outputSoFar
     case 'Success':
       return output.binaryOutput ?? undefined
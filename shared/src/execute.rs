@@ -1,13 +1,15 @@
 use crate::exit::CrowExitStatus;
-use crate::judge::judge_output;
+use crate::judge::{ProducedFile, judge_output};
+use crate::recording::{OutputRecording, OutputStream};
 use crate::{
     CompilerTest, ExecutionOutput, FinishedExecution, InternalError, TestExecutionOutput,
-    TestModifier, TestModifierExt,
+    TestInvocation, TestModifier, TestModifierExt, is_safe_relative_path,
 };
 use is_executable::IsExecutable;
 use snafu::{IntoError, NoneError, Report, ResultExt, Snafu};
+use std::collections::HashMap;
 use std::error::Error;
-use std::io::Read;
+use std::io::{ErrorKind, Read};
 use std::os::fd::AsRawFd;
 use std::path::Path;
 use std::process::{Child, ChildStderr, ChildStdout, ExitStatus};
@@ -74,23 +76,32 @@ pub fn execute_test(
     working_dir: &Path,
     output_binary_host_path: &Path,
     parent_dir_in_container: &Path,
+    program_work_dir: &Path,
     run_cmd: impl FnMut(
         &Path,
         &[String],
         Option<Duration>,
         String,
     ) -> Result<CommandResult, Box<dyn Error + Sync + Send>>,
-) -> TestExecutionOutput {
-    impl_execute_test(
+) -> (TestExecutionOutput, TestInvocation) {
+    let mut invocation = TestInvocation::default();
+    let output = impl_execute_test(
         test,
         working_dir,
         output_binary_host_path,
         parent_dir_in_container,
+        program_work_dir,
+        &mut invocation,
         run_cmd,
     )
-    .unwrap_or_else(From::from)
+    .unwrap_or_else(From::from);
+
+    (output, invocation)
 }
 
+/// The name of the binary the compiler writes, next to the argument files of the test
+pub const OUTPUT_BINARY_NAME: &str = "out.🦆";
+
 const TIMEOUT_MODIFIER_DURATION_SECONDS: u64 = 2;
 
 #[allow(clippy::result_large_err)] // we accept it here, it will hopefully be inlined anyway
@@ -99,6 +110,8 @@ fn impl_execute_test(
     working_dir: &Path,
     output_binary_host_path: &Path,
     parent_dir_in_container: &Path,
+    program_work_dir: &Path,
+    invocation: &mut TestInvocation,
     mut run_cmd: impl FnMut(
         &Path,
         &[String],
@@ -106,7 +119,7 @@ fn impl_execute_test(
         String,
     ) -> Result<CommandResult, Box<dyn Error + Sync + Send>>,
 ) -> Result<TestExecutionOutput, ExecuteInternalError> {
-    let should_run_binary = !test.binary_modifiers.is_empty();
+    let should_run_binary = !test.compile_only && !test.binary_modifiers.is_empty();
     let output_binary_run_path = parent_dir_in_container.join(
         output_binary_host_path
             .file_name()
@@ -126,6 +139,7 @@ fn impl_execute_test(
         })?,
     );
     compiler_commands.push(output_binary_run_path.display().to_string());
+    invocation.compiler = Some(command_line(&test.compile_command[0], &compiler_commands));
 
     let start = Instant::now();
     let compiler_result = run_cmd(
@@ -140,9 +154,12 @@ fn impl_execute_test(
 
     let compiler_output = match compiler_result {
         CommandResult::ProcessedFailed(output) => output,
-        CommandResult::Unprocessed((exit_status, execution)) => {
-            judge_output(&test.compiler_modifiers, exit_status, execution)
-        }
+        CommandResult::Unprocessed((exit_status, execution)) => judge_output(
+            &test.compiler_modifiers,
+            exit_status,
+            execution,
+            &read_produced_files(&test.compiler_modifiers, program_work_dir),
+        ),
     };
 
     if !matches!(compiler_output, ExecutionOutput::Success(_)) {
@@ -176,6 +193,10 @@ fn impl_execute_test(
     } else {
         None
     };
+    invocation.binary = Some(command_line(
+        &output_binary_run_path.display().to_string(),
+        &run_commands,
+    ));
     let binary_result = run_cmd(
         Path::new(&output_binary_run_path),
         &run_commands,
@@ -188,13 +209,19 @@ fn impl_execute_test(
     })?;
 
     let binary_output = match binary_result {
-        CommandResult::ProcessedFailed(ExecutionOutput::Timeout(execution)) => {
-            judge_output(&test.binary_modifiers, CrowExitStatus::Timeout, execution)
-        }
+        CommandResult::ProcessedFailed(ExecutionOutput::Timeout(execution)) => judge_output(
+            &test.binary_modifiers,
+            CrowExitStatus::Timeout,
+            execution,
+            &read_produced_files(&test.binary_modifiers, program_work_dir),
+        ),
         CommandResult::ProcessedFailed(output) => output,
-        CommandResult::Unprocessed((exit_status, execution)) => {
-            judge_output(&test.binary_modifiers, exit_status, execution)
-        }
+        CommandResult::Unprocessed((exit_status, execution)) => judge_output(
+            &test.binary_modifiers,
+            exit_status,
+            execution,
+            &read_produced_files(&test.binary_modifiers, program_work_dir),
+        ),
     };
 
     if !matches!(binary_output, ExecutionOutput::Success(_)) {
@@ -210,6 +237,12 @@ fn impl_execute_test(
     })
 }
 
+fn command_line(program: &str, args: &[String]) -> Vec<String> {
+    std::iter::once(program.to_string())
+        .chain(args.iter().cloned())
+        .collect()
+}
+
 fn verify_compiler_built_executable(
     output_binary_path: &Path,
     compiler_output: ExecutionOutput,
@@ -250,29 +283,112 @@ fn gather_arguments(
     work_dir: &Path,
     parent_dir_in_container: &Path,
 ) -> Result<Vec<String>, Box<dyn Error + Sync + Send>> {
+    let (args, files) = modifier_arguments(modifiers, parent_dir_in_container);
+    for (file_name, contents) in files {
+        std::fs::write(work_dir.join(file_name), contents)?;
+    }
+
+    Ok(args)
+}
+
+/// Returns the arguments the modifiers append to a command and the files they refer to, as
+/// `(file name, contents)`. The files are expected in `parent_dir_in_container`.
+pub fn modifier_arguments(
+    modifiers: &[TestModifier],
+    parent_dir_in_container: &Path,
+) -> (Vec<String>, Vec<(String, String)>) {
     let mut args = Vec::new();
+    let mut files = Vec::new();
 
-    let mut file_counter = 0;
     for modifier in modifiers {
         match modifier {
             TestModifier::ProgramArgument { arg } => args.push(arg.clone()),
             TestModifier::ProgramArgumentFile { contents } => {
-                let file_name = format!("file_{file_counter}");
-                std::fs::write(work_dir.join(&file_name), contents)?;
+                let file_name = format!("file_{}", files.len());
                 args.push(
                     parent_dir_in_container
-                        .join(file_name)
+                        .join(&file_name)
                         .display()
                         .to_string(),
                 );
-
-                file_counter += 1;
+                files.push((file_name, contents.clone()));
             }
             _ => {}
         }
     }
 
-    Ok(args)
+    (args, files)
+}
+
+/// Produced files larger than this are not read, that would not be a reasonable test anyway
+const MAX_PRODUCED_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Reads the files the modifiers expect the program to write to its working directory
+fn read_produced_files(
+    modifiers: &[TestModifier],
+    program_work_dir: &Path,
+) -> HashMap<String, ProducedFile> {
+    modifiers
+        .iter()
+        .filter_map(|it| match it {
+            TestModifier::ExpectedFileOutput { path, .. } => Some(path),
+            _ => None,
+        })
+        .map(|path| (path.clone(), read_produced_file(path, program_work_dir)))
+        .collect()
+}
+
+fn read_produced_file(path: &str, program_work_dir: &Path) -> ProducedFile {
+    if !is_safe_relative_path(path) {
+        return ProducedFile::Unreadable {
+            message: "the path must be relative and stay inside the working directory".to_string(),
+        };
+    }
+
+    // The program controls the directory, so we must not follow its symlinks out of it
+    let mut current = program_work_dir.to_path_buf();
+    let mut metadata = None;
+    for part in path.split('/') {
+        current.push(part);
+        match std::fs::symlink_metadata(&current) {
+            Ok(found) if found.is_symlink() => {
+                return ProducedFile::Unreadable {
+                    message: "it is or lies below a symlink".to_string(),
+                };
+            }
+            Ok(found) => metadata = Some(found),
+            Err(e) if e.kind() == ErrorKind::NotFound => return ProducedFile::Missing,
+            Err(e) => {
+                return ProducedFile::Unreadable {
+                    message: Report::from_error(e).to_string(),
+                };
+            }
+        }
+    }
+
+    let Some(metadata) = metadata else {
+        return ProducedFile::Missing;
+    };
+    if !metadata.is_file() {
+        return ProducedFile::Unreadable {
+            message: "it is not a regular file".to_string(),
+        };
+    }
+    if metadata.len() > MAX_PRODUCED_FILE_SIZE {
+        return ProducedFile::Unreadable {
+            message: format!(
+                "it is larger than the limit of {MAX_PRODUCED_FILE_SIZE} bytes ({} bytes)",
+                metadata.len()
+            ),
+        };
+    }
+
+    match std::fs::read(&current) {
+        Ok(contents) => ProducedFile::Contents(String::from_utf8_lossy(&contents).to_string()),
+        Err(e) => ProducedFile::Unreadable {
+            message: Report::from_error(e).to_string(),
+        },
+    }
 }
 
 #[derive(Debug, Snafu)]
@@ -304,6 +420,7 @@ pub fn run_with_timeout(
     stderr: &mut ChildStderr,
     process: &mut Child,
     timeout: Duration,
+    mut recording: Option<&mut OutputRecording>,
 ) -> Result<(String, String, ExitStatus, Duration), RunWithTimeoutError> {
     #[allow(unsafe_code)]
     unsafe {
@@ -322,10 +439,16 @@ pub fn run_with_timeout(
         let mut tmpbuf = [0_u8; 1024];
         if let Ok(count) = stdout.read(&mut tmpbuf) {
             stdout_buf.extend_from_slice(&tmpbuf[..count]);
+            if let Some(recording) = recording.as_deref_mut() {
+                recording.record(start.elapsed(), OutputStream::Stdout, &tmpbuf[..count]);
+            }
         }
         let mut tmpbuf = [0_u8; 1024];
         if let Ok(count) = stderr.read(&mut tmpbuf) {
             stderr_buf.extend_from_slice(&tmpbuf[..count]);
+            if let Some(recording) = recording.as_deref_mut() {
+                recording.record(start.elapsed(), OutputStream::Stderr, &tmpbuf[..count]);
+            }
         }
     };
 
@@ -0,0 +1,81 @@
+use crate::{FilesetFile, TestModifier};
+use sha2::{Digest, Sha256};
+
+/// The version of the algorithm implemented by [hash_test]. Bump it whenever the computed hash
+/// changes, so outdated clients can tell their hashes apart from the server's.
+pub const TEST_HASH_VERSION: u32 = 1;
+
+/// Everything that goes into the hash of a test
+#[derive(Debug, Clone)]
+pub struct TestHashInput<'a> {
+    pub compiler_modifiers: &'a [TestModifier],
+    pub binary_modifiers: &'a [TestModifier],
+    pub owner: &'a str,
+    pub admin_authored: bool,
+    pub limited_to_category: bool,
+    pub category: &'a str,
+    pub timeout_ms: Option<u64>,
+    pub depends_on: &'a [String],
+    pub practice: bool,
+    pub license: Option<&'a str>,
+}
+
+/// The canonical hash of a test, shared by the backend and the client
+pub fn hash_test(input: &TestHashInput<'_>) -> String {
+    // Line endings are normalized, so the same test written on Windows has the same hash
+    let normalize = |modifiers: &[TestModifier]| {
+        modifiers
+            .iter()
+            .map(TestModifier::with_normalized_line_endings)
+            .collect::<Vec<_>>()
+    };
+    let compiler_modifiers = serde_json::to_string(&normalize(input.compiler_modifiers))
+        .expect("Unexpected json serialize error");
+    let binary_modifiers = serde_json::to_string(&normalize(input.binary_modifiers))
+        .expect("Unexpected json serialize error");
+
+    let mut hash = Sha256::new();
+    hash.update(compiler_modifiers.as_bytes());
+    hash.update(binary_modifiers.as_bytes());
+    hash.update(input.owner.as_bytes());
+    hash.update([input.admin_authored as u8]);
+    hash.update([input.limited_to_category as u8]);
+    hash.update(input.category.as_bytes());
+    // Only hashed if present, so tests without an override keep their hash
+    if let Some(timeout_ms) = input.timeout_ms {
+        hash.update(timeout_ms.to_string().as_bytes());
+    }
+    // Same for dependencies
+    for dependency in input.depends_on {
+        hash.update(dependency.as_bytes());
+        hash.update([0]);
+    }
+    // Same for the practice flag
+    if input.practice {
+        hash.update(b"practice");
+    }
+    // And the license
+    if let Some(license) = input.license {
+        hash.update(b"license");
+        hash.update(license.as_bytes());
+    }
+
+    format!("{:x}", hash.finalize())
+}
+
+/// The hash of a category fileset. Independent of the order of the files.
+pub fn hash_fileset(files: &[FilesetFile]) -> String {
+    let mut files = files.iter().collect::<Vec<_>>();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut hash = Sha256::new();
+    for file in files {
+        // Length prefixes keep the boundaries between paths and contents unambiguous
+        hash.update((file.path.len() as u64).to_le_bytes());
+        hash.update(file.path.as_bytes());
+        hash.update((file.contents.len() as u64).to_le_bytes());
+        hash.update(file.contents.as_bytes());
+    }
+
+    format!("{:x}", hash.finalize())
+}
@@ -4,17 +4,59 @@ use crate::{
     TestModifierExt,
 };
 use similar::{DiffableStr, TextDiff};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 
+/// A file the program should have written, as found after it exited
+#[derive(Debug, Clone)]
+pub enum ProducedFile {
+    Missing,
+    Unreadable { message: String },
+    Contents(String),
+}
+
 struct JudgeProblem {
     message: String,
     modifier_name: String,
 }
 
-impl Display for JudgeProblem {
+/// All problems found while judging, each in its own section. Users should learn about every
+/// violated modifier at once, not fix them one by one.
+struct JudgeProblems(Vec<JudgeProblem>);
+
+impl Display for JudgeProblems {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "==== {} ====", self.modifier_name,)?;
-        write!(f, "{}", self.message.trim_end())
+        let total = self.0.len();
+        if total > 1 {
+            let names = self
+                .0
+                .iter()
+                .map(|it| it.modifier_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(f, "{total} checks failed: {names}")?;
+            writeln!(f)?;
+        }
+
+        for (index, problem) in self.0.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+                writeln!(f)?;
+            }
+            if total > 1 {
+                writeln!(
+                    f,
+                    "==== {} ({}/{total}) ====",
+                    problem.modifier_name,
+                    index + 1
+                )?;
+            } else {
+                writeln!(f, "==== {} ====", problem.modifier_name)?;
+            }
+            write!(f, "{}", problem.message.trim_end())?;
+        }
+
+        Ok(())
     }
 }
 
@@ -22,14 +64,25 @@ pub fn judge_output(
     modifiers: &[TestModifier],
     exit_status: CrowExitStatus,
     execution: FinishedExecution,
+    produced_files: &HashMap<String, ProducedFile>,
 ) -> ExecutionOutput {
     let mut problems = Vec::new();
 
-    let expected_output = (&modifiers).full_output();
-    if let Some(expected_output) = expected_output {
-        if let Some(problem) = judge_program_output(&execution, expected_output) {
-            problems.push(problem);
+    let normalization = OutputNormalization::from_modifiers(modifiers);
+    let output_problem = match (
+        (&modifiers).output_alternatives(),
+        (&modifiers).full_output(),
+    ) {
+        (Some(alternatives), _) => {
+            judge_program_output_any_of(&execution, &alternatives, &normalization)
         }
+        (None, Some(expected_output)) => {
+            judge_program_output(&execution, expected_output, &normalization)
+        }
+        (None, None) => None,
+    };
+    if let Some(problem) = output_problem {
+        problems.push(problem);
     }
 
     for modifier in modifiers {
@@ -41,10 +94,19 @@ pub fn judge_output(
             TestModifier::ShouldSucceed => judge_program_should_succeed(exit_status),
             TestModifier::ShouldFail { reason } => judge_program_should_fail(exit_status, *reason),
             TestModifier::ShouldTimeout => judge_program_should_timeout(exit_status),
+            TestModifier::ExpectedFileOutput { path, contents } => {
+                judge_program_file_output(path, contents, produced_files.get(path), &normalization)
+            }
             TestModifier::ExpectedOutput { .. } => None,
+            TestModifier::ExpectedOutputAnyOf { .. } => None,
+            TestModifier::FloatTolerance { .. } => None,
+            TestModifier::IgnoreLineEndings => None,
+            TestModifier::IgnoreTrailingWhitespace => None,
+            TestModifier::IgnoreTrailingNewlines => None,
             TestModifier::ProgramArgument { .. } => None,
             TestModifier::ProgramArgumentFile { .. } => None,
             TestModifier::ProgramInput { .. } => None,
+            TestModifier::UnorderedLines => None,
         };
         if let Some(problem) = problem {
             problems.push(problem);
@@ -57,19 +119,22 @@ pub fn judge_output(
             stderr: execution.stderr,
             exit_status: execution.exit_status,
             runtime: execution.runtime,
+            infrastructure_log: execution.infrastructure_log,
+            recording: execution.recording,
+            resource_usage: execution.resource_usage,
+            full_output_id: execution.full_output_id,
         });
     }
-    let problems = &problems
-        .into_iter()
-        .map(|it| it.to_string())
-        .collect::<Vec<String>>()
-        .join("\n\n");
 
     let execution = FinishedExecution {
         stdout: execution.stdout,
         stderr: execution.stderr,
         runtime: execution.runtime,
         exit_status: execution.exit_status,
+        infrastructure_log: execution.infrastructure_log,
+        recording: execution.recording,
+        resource_usage: execution.resource_usage,
+        full_output_id: execution.full_output_id,
     };
 
     if matches!(exit_status, CrowExitStatus::Timeout) {
@@ -77,37 +142,257 @@ pub fn judge_output(
     } else {
         ExecutionOutput::Failure {
             execution,
-            accumulated_errors: Some(problems.clone()),
+            accumulated_errors: Some(JudgeProblems(problems).to_string()),
         }
     }
 }
 
+/// How the expected and actual output are normalized before comparing them
+#[derive(Debug, Default)]
+struct OutputNormalization {
+    /// Numbers are compared with this absolute tolerance
+    float_tolerance: Option<f64>,
+    /// `\r\n` is treated like `\n`
+    line_endings: bool,
+    /// Whitespace at the end of each line is ignored
+    trailing_whitespace: bool,
+    /// Any number of empty lines at the end is ignored
+    trailing_newlines: bool,
+    /// Lines are sorted before comparing them
+    unordered_lines: bool,
+}
+
+impl OutputNormalization {
+    fn from_modifiers(modifiers: &[TestModifier]) -> Self {
+        let mut normalization = Self::default();
+        for modifier in modifiers {
+            match modifier {
+                TestModifier::FloatTolerance { tolerance } => {
+                    normalization.float_tolerance = Some(*tolerance)
+                }
+                TestModifier::IgnoreLineEndings => normalization.line_endings = true,
+                TestModifier::IgnoreTrailingWhitespace => normalization.trailing_whitespace = true,
+                TestModifier::IgnoreTrailingNewlines => normalization.trailing_newlines = true,
+                TestModifier::UnorderedLines => normalization.unordered_lines = true,
+                _ => {}
+            }
+        }
+
+        normalization
+    }
+
+    fn normalize(&self, output: &str) -> String {
+        let mut output = output.to_string();
+
+        if self.line_endings {
+            output = output.replace("\r\n", "\n");
+        }
+        if self.trailing_whitespace {
+            output = output
+                .split('\n')
+                .map(|line| line.trim_end())
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        if self.trailing_newlines {
+            output.truncate(output.trim_end_matches('\n').len());
+        }
+        if self.unordered_lines {
+            let mut lines = output.split_terminator('\n').collect::<Vec<_>>();
+            lines.sort_unstable();
+            output = lines.join("\n");
+        }
+
+        // Normalize newlines for diff. This helps users understand it better, many people are not
+        // well versed in that distinction.
+        if !output.ends_with_newline() {
+            output.push('\n');
+        }
+
+        output
+    }
+
+    fn matches(&self, expected: &str, actual: &str) -> bool {
+        let Some(tolerance) = self.float_tolerance else {
+            return expected == actual;
+        };
+
+        let expected_lines = expected.split('\n').collect::<Vec<_>>();
+        let actual_lines = actual.split('\n').collect::<Vec<_>>();
+        if expected_lines.len() != actual_lines.len() {
+            return false;
+        }
+
+        expected_lines
+            .iter()
+            .zip(actual_lines)
+            .all(|(expected, actual)| line_matches_with_tolerance(expected, actual, tolerance))
+    }
+
+    fn describe(&self) -> Vec<String> {
+        let mut result = Vec::new();
+        if let Some(tolerance) = self.float_tolerance {
+            result.push(format!(
+                "numbers are compared with a tolerance of {tolerance}"
+            ));
+        }
+        if self.line_endings {
+            result.push("line endings are ignored".to_string());
+        }
+        if self.trailing_whitespace {
+            result.push("trailing whitespace is ignored".to_string());
+        }
+        if self.trailing_newlines {
+            result.push("trailing newlines are ignored".to_string());
+        }
+        if self.unordered_lines {
+            result.push("lines are sorted".to_string());
+        }
+
+        result
+    }
+}
+
+fn line_matches_with_tolerance(expected: &str, actual: &str, tolerance: f64) -> bool {
+    if expected == actual {
+        return true;
+    }
+
+    let expected_tokens = expected.split_whitespace().collect::<Vec<_>>();
+    let actual_tokens = actual.split_whitespace().collect::<Vec<_>>();
+    if expected_tokens.len() != actual_tokens.len() {
+        return false;
+    }
+
+    expected_tokens
+        .iter()
+        .zip(actual_tokens)
+        .all(|(expected, actual)| {
+            if *expected == actual {
+                return true;
+            }
+            match (expected.parse::<f64>(), actual.parse::<f64>()) {
+                (Ok(expected), Ok(actual)) => (expected - actual).abs() <= tolerance,
+                _ => false,
+            }
+        })
+}
+
 fn judge_program_output(
     execution: &FinishedExecution,
-    mut expected_output: String,
+    expected_output: String,
+    normalization: &OutputNormalization,
 ) -> Option<JudgeProblem> {
-    let mut actual_output = execution.stdout.clone();
+    let message = describe_mismatch(
+        &expected_output,
+        &execution.stdout,
+        normalization,
+        "You can always compute it yourself by copying the stdout.",
+    )?;
+
+    Some(JudgeProblem {
+        message,
+        modifier_name: "ExpectedOutput".to_string(),
+    })
+}
 
-    // Normalize newlines for diff. This helps users understand it better, many people are not
-    // well versed in that distinction.
-    if !expected_output.ends_with_newline() {
-        expected_output.push('\n');
+/// Accepts the output if it matches any of the alternatives, and otherwise shows how it differs
+/// from the most similar one
+fn judge_program_output_any_of(
+    execution: &FinishedExecution,
+    alternatives: &[String],
+    normalization: &OutputNormalization,
+) -> Option<JudgeProblem> {
+    let actual = normalization.normalize(&execution.stdout);
+    let matches =
+        |expected: &String| normalization.matches(&normalization.normalize(expected), &actual);
+    if alternatives.iter().any(matches) {
+        return None;
     }
-    if !actual_output.ends_with_newline() {
-        actual_output.push('\n');
+
+    let similarity = |expected: &String| {
+        TextDiff::from_lines(&normalization.normalize(expected), &actual).ratio()
+    };
+    let closest = alternatives
+        .iter()
+        .map(|it| (it, similarity(it)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(it, _)| it)?;
+
+    let mut message = describe_mismatch(
+        closest,
+        &execution.stdout,
+        normalization,
+        "You can always compute it yourself by copying the stdout.",
+    )?;
+    if alternatives.len() > 1 {
+        message = format!(
+            "The output matched none of the {} accepted outputs, comparing with the closest one.\n\
+            {message}",
+            alternatives.len()
+        );
     }
 
-    if expected_output == *actual_output {
+    Some(JudgeProblem {
+        message,
+        modifier_name: "ExpectedOutputAnyOf".to_string(),
+    })
+}
+
+fn judge_program_file_output(
+    path: &str,
+    expected_contents: &str,
+    produced: Option<&ProducedFile>,
+    normalization: &OutputNormalization,
+) -> Option<JudgeProblem> {
+    let message = match produced {
+        None | Some(ProducedFile::Missing) => format!(
+            "The program did not create the file `{path}`. \
+            The path is relative to the working directory of the program."
+        ),
+        Some(ProducedFile::Unreadable { message }) => {
+            format!("The file `{path}` could not be read: {message}")
+        }
+        Some(ProducedFile::Contents(actual)) => describe_mismatch(
+            expected_contents,
+            actual,
+            normalization,
+            "Run the program locally to inspect the whole file.",
+        )?,
+    };
+
+    Some(JudgeProblem {
+        message,
+        modifier_name: "ExpectedFileOutput".to_string(),
+    })
+}
+
+/// Explains how the actual output differs from the expected one, if it does. The hint tells users
+/// how to get at the whole output.
+fn describe_mismatch(
+    expected: &str,
+    actual: &str,
+    normalization: &OutputNormalization,
+    hint: &str,
+) -> Option<String> {
+    let expected_output = normalization.normalize(expected);
+    let actual_output = normalization.normalize(actual);
+
+    if normalization.matches(&expected_output, &actual_output) {
         return None;
     }
 
     let mut stderr_result = String::new();
 
-    if !stderr_result.is_empty() {
-        stderr_result += "\n\n";
+    let normalizations = normalization.describe();
+    if !normalizations.is_empty() {
+        stderr_result += "When comparing, ";
+        stderr_result += &normalizations.join(", ");
+        stderr_result += ".\n";
     }
     stderr_result += "A diff of your result follows. ";
-    stderr_result += "You can always compute it yourself by copying the stdout.\n";
+    stderr_result += hint;
+    stderr_result += "\n";
 
     let diff = TextDiff::from_lines(&expected_output, &actual_output);
     let mut diff = diff.unified_diff();
@@ -117,10 +402,7 @@ fn judge_program_output(
 
     stderr_result += &diff.to_string();
 
-    Some(JudgeProblem {
-        message: stderr_result,
-        modifier_name: "ExpectedOutput".to_string(),
-    })
+    Some(stderr_result)
 }
 
 fn judge_program_exit_status(
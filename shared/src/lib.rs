@@ -1,7 +1,9 @@
 use crate::exit::HandleExitcode;
+use crate::recording::OutputRecording;
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use std::fmt::Formatter;
 use std::path::Path;
 use std::process::{Command, Output};
@@ -10,7 +12,13 @@ use std::time::{Duration, SystemTime};
 
 pub mod execute;
 pub mod exit;
+pub mod hash;
 pub mod judge;
+pub mod markdown;
+pub mod recording;
+pub mod repro;
+pub mod runc;
+pub mod validation;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -19,12 +27,22 @@ pub struct CompilerTask {
     pub revision_id: String,
     pub commit_message: String,
     pub team_id: String,
+    /// The image to build in. Images pinned with `@sha256:...` are verified to have that digest.
     pub image: String,
     pub build_command: Vec<String>,
     #[serde(serialize_with = "serialize_duration")]
     #[serde(deserialize_with = "deserialize_duration")]
     pub build_timeout: Duration,
     pub tests: Vec<CompilerTest>,
+    /// The filesets of the categories of the tests, keyed by category
+    #[serde(default)]
+    pub filesets: HashMap<String, CategoryFileset>,
+    /// The permissions of the build container, combined from the categories of the tests
+    #[serde(default)]
+    pub build_permissions: ContainerPermissions,
+    /// Record the output of the build and tests as it appears over time, so it can be replayed
+    #[serde(default)]
+    pub record_output: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +58,157 @@ pub struct CompilerTest {
     pub compiler_modifiers: Vec<TestModifier>,
     pub binary_modifiers: Vec<TestModifier>,
     pub provisional_for_category: Option<String>,
+    /// Only run the compiler, even if the test has binary modifiers
+    #[serde(default)]
+    pub compile_only: bool,
+    /// Named sets of extra compiler arguments. The test is run once per profile, or once with the
+    /// plain compile command if this is empty.
+    #[serde(default)]
+    pub profiles: Vec<CommandProfile>,
+    /// Tests that must pass before this one runs. The test is skipped if any of them fails.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// A command the compiled binary is run with, e.g. `qemu-riscv64` to emulate another
+    /// architecture. The binary and its arguments are appended to it. Empty to run it directly.
+    #[serde(default)]
+    pub runner_prefix: Vec<String>,
+    /// The user and extra mounts the test containers of this category run with
+    #[serde(default)]
+    pub permissions: ContainerPermissions,
+    /// How the compiled binary is run, on top of the pinned environment of every command
+    #[serde(default)]
+    pub binary_environment: BinaryEnvironment,
+}
+
+impl CompilerTest {
+    /// Expands the test into one variant per profile, with the profile's arguments appended to
+    /// the compile command. Returns the test itself if it has no profiles.
+    pub fn profile_variants(&self) -> Vec<(Option<String>, Self)> {
+        if self.profiles.is_empty() {
+            return vec![(None, self.clone())];
+        }
+
+        self.profiles
+            .iter()
+            .map(|profile| {
+                let mut test = self.clone();
+                test.compile_command.extend(profile.args.iter().cloned());
+                test.profiles = Vec::new();
+                (Some(profile.name.clone()), test)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandProfile {
+    pub name: String,
+    /// Appended to the compile command when running a test with this profile
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Files a category places into the working directory of its tests before they are compiled,
+/// e.g. a runtime support library or header files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryFileset {
+    /// Incremented by the backend whenever the files change
+    pub version: i64,
+    /// See [hash::hash_fileset]
+    pub hash: String,
+    pub files: Vec<FilesetFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilesetFile {
+    /// The path relative to the working directory, using `/` as separator
+    pub path: String,
+    pub contents: String,
+}
+
+impl FilesetFile {
+    /// Returns whether the path stays inside the directory it is materialized in
+    pub fn has_safe_path(&self) -> bool {
+        is_safe_relative_path(&self.path)
+    }
+}
+
+/// Returns whether the relative path can not escape the directory it is resolved against
+pub fn is_safe_relative_path(path: &str) -> bool {
+    !path.is_empty()
+        && !path.starts_with('/')
+        && path
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..")
+}
+
+/// How the containers of a category are set up. The default runs everything as root with only
+/// the image itself mounted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerPermissions {
+    /// The user to run commands as instead of root
+    #[serde(default)]
+    pub user: Option<ContainerUser>,
+    /// Absolute paths in the container that get an empty directory writable by every user
+    #[serde(default)]
+    pub writable_paths: Vec<String>,
+    /// Host paths mounted into the container. Runners refuse sources they do not allow.
+    #[serde(default)]
+    pub bind_mounts: Vec<BindMount>,
+}
+
+impl ContainerPermissions {
+    /// Adds the paths and mounts of the other permissions. The user is only taken if we do not
+    /// have one yet.
+    pub fn merge(&mut self, other: &Self) {
+        if self.user.is_none() {
+            self.user = other.user;
+        }
+        for path in &other.writable_paths {
+            if !self.writable_paths.contains(path) {
+                self.writable_paths.push(path.clone());
+            }
+        }
+        for mount in &other.bind_mounts {
+            if !self.bind_mounts.contains(mount) {
+                self.bind_mounts.push(mount.clone());
+            }
+        }
+    }
+
+    /// Returns the first path that is not absolute or contains a `..` component
+    pub fn find_invalid_path(&self) -> Option<&str> {
+        let is_valid = |path: &str| path.starts_with('/') && !path.split('/').any(|it| it == "..");
+
+        self.writable_paths
+            .iter()
+            .map(String::as_str)
+            .chain(
+                self.bind_mounts
+                    .iter()
+                    .flat_map(|it| [it.source.as_str(), it.destination.as_str()]),
+            )
+            .find(|path| !is_valid(path))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerUser {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BindMount {
+    /// The absolute path on the runner
+    pub source: String,
+    /// The absolute path in the container
+    pub destination: String,
+    /// Mounts are read-only unless this is set
+    #[serde(default)]
+    pub writable: bool,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -111,15 +280,47 @@ impl CrashSignal {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum TestModifier {
-    ExitCode { code: u8 },
-    ExpectedOutput { output: String },
-    ProgramArgument { arg: String },
-    ProgramArgumentFile { contents: String },
-    ProgramInput { input: String },
-    ShouldCrash { signal: CrashSignal },
-    ShouldFail { reason: CompilerFailReason },
+    ExitCode {
+        code: u8,
+    },
+    ExpectedOutput {
+        output: String,
+    },
+    /// The program prints any one of `outputs`, e.g. when the order of its results is unspecified
+    ExpectedOutputAnyOf {
+        outputs: Vec<String>,
+    },
+    /// The program writes `contents` to the file at `path`, relative to its working directory
+    #[serde(alias = "ExpectedFile")]
+    ExpectedFileOutput {
+        path: String,
+        contents: String,
+    },
+    FloatTolerance {
+        tolerance: f64,
+    },
+    IgnoreLineEndings,
+    IgnoreTrailingWhitespace,
+    IgnoreTrailingNewlines,
+    ProgramArgument {
+        arg: String,
+    },
+    ProgramArgumentFile {
+        contents: String,
+    },
+    ProgramInput {
+        input: String,
+    },
+    ShouldCrash {
+        signal: CrashSignal,
+    },
+    ShouldFail {
+        reason: CompilerFailReason,
+    },
     ShouldSucceed,
     ShouldTimeout,
+    /// Lines of the output are compared regardless of their order
+    UnorderedLines,
 }
 
 impl TestModifier {
@@ -127,6 +328,12 @@ impl TestModifier {
         match self {
             Self::ExitCode { .. } => "ExitCode",
             Self::ExpectedOutput { .. } => "ExpectedOutput",
+            Self::ExpectedOutputAnyOf { .. } => "ExpectedOutputAnyOf",
+            Self::ExpectedFileOutput { .. } => "ExpectedFileOutput",
+            Self::FloatTolerance { .. } => "FloatTolerance",
+            Self::IgnoreLineEndings => "IgnoreLineEndings",
+            Self::IgnoreTrailingWhitespace => "IgnoreTrailingWhitespace",
+            Self::IgnoreTrailingNewlines => "IgnoreTrailingNewlines",
             Self::ProgramArgument { .. } => "ProgramArgument",
             Self::ProgramArgumentFile { .. } => "ProgramArgumentFile",
             Self::ProgramInput { .. } => "ProgramInput",
@@ -134,6 +341,37 @@ impl TestModifier {
             Self::ShouldFail { .. } => "ShouldFail",
             Self::ShouldSucceed => "ShouldSucceed",
             Self::ShouldTimeout => "ShouldTimeout",
+            Self::UnorderedLines => "UnorderedLines",
+        }
+    }
+
+    /// Replaces Windows line endings in all string payloads, so a test has the same contents
+    /// regardless of the platform it was written on.
+    pub fn with_normalized_line_endings(&self) -> Self {
+        match self {
+            Self::ExpectedOutput { output } => Self::ExpectedOutput {
+                output: normalize_line_endings(output),
+            },
+            Self::ExpectedOutputAnyOf { outputs } => Self::ExpectedOutputAnyOf {
+                outputs: outputs
+                    .iter()
+                    .map(|it| normalize_line_endings(it))
+                    .collect(),
+            },
+            Self::ExpectedFileOutput { path, contents } => Self::ExpectedFileOutput {
+                path: path.clone(),
+                contents: normalize_line_endings(contents),
+            },
+            Self::ProgramArgument { arg } => Self::ProgramArgument {
+                arg: normalize_line_endings(arg),
+            },
+            Self::ProgramArgumentFile { contents } => Self::ProgramArgumentFile {
+                contents: normalize_line_endings(contents),
+            },
+            Self::ProgramInput { input } => Self::ProgramInput {
+                input: normalize_line_endings(input),
+            },
+            other => other.clone(),
         }
     }
 }
@@ -141,8 +379,12 @@ impl TestModifier {
 pub trait TestModifierExt {
     fn full_input(&self) -> String;
     fn full_output(&self) -> Option<String>;
+    /// The outputs of the first `ExpectedOutputAnyOf`, if there is one
+    fn output_alternatives(&self) -> Option<Vec<String>>;
     fn all_arguments(&self) -> Vec<String>;
     fn should_timeout(&self) -> bool;
+    /// The names of the modifiers saying how the program ends, a test may only have one
+    fn exit_expectations(&self) -> Vec<&'static str>;
 }
 
 impl<'a, T: Borrow<&'a [TestModifier]>> TestModifierExt for T {
@@ -175,6 +417,13 @@ impl<'a, T: Borrow<&'a [TestModifier]>> TestModifierExt for T {
         }
     }
 
+    fn output_alternatives(&self) -> Option<Vec<String>> {
+        self.borrow().iter().find_map(|it| match it {
+            TestModifier::ExpectedOutputAnyOf { outputs } => Some(outputs.clone()),
+            _ => None,
+        })
+    }
+
     fn all_arguments(&self) -> Vec<String> {
         self.borrow()
             .iter()
@@ -191,6 +440,23 @@ impl<'a, T: Borrow<&'a [TestModifier]>> TestModifierExt for T {
             .iter()
             .any(|it| matches!(it, TestModifier::ShouldTimeout))
     }
+
+    fn exit_expectations(&self) -> Vec<&'static str> {
+        self.borrow()
+            .iter()
+            .filter(|it| {
+                matches!(
+                    it,
+                    TestModifier::ExitCode { .. }
+                        | TestModifier::ShouldCrash { .. }
+                        | TestModifier::ShouldFail { .. }
+                        | TestModifier::ShouldSucceed
+                        | TestModifier::ShouldTimeout
+                )
+            })
+            .map(TestModifier::name)
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -202,6 +468,37 @@ pub struct FinishedExecution {
     #[serde(deserialize_with = "deserialize_duration")]
     pub runtime: Duration,
     pub exit_status: Option<i32>,
+    /// Diagnostics of runc and the shim, kept apart from the judged stderr
+    #[serde(default)]
+    pub infrastructure_log: Option<String>,
+    /// The output as it appeared over time, if the task asked for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording: Option<OutputRecording>,
+    /// Measured by the shim inside the container, unknown for local runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_usage: Option<ResourceUsage>,
+    /// Set by the backend if it stored only the start of the stdout or stderr. The whole output can
+    /// be downloaded with this id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_output_id: Option<String>,
+}
+
+/// The resources the program and the children it waited for used, as reported by `getrusage`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceUsage {
+    /// The time the program ran, without the time spent setting up its container
+    #[serde(serialize_with = "serialize_duration")]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub wall_time: Duration,
+    #[serde(serialize_with = "serialize_duration")]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub user_time: Duration,
+    #[serde(serialize_with = "serialize_duration")]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub system_time: Duration,
+    /// The peak resident set size of the largest process
+    pub max_rss_kib: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -212,6 +509,15 @@ pub struct AbortedExecution {
     #[serde(serialize_with = "serialize_duration")]
     #[serde(deserialize_with = "deserialize_duration")]
     pub runtime: Duration,
+    /// Diagnostics of runc and the shim, kept apart from the stderr
+    #[serde(default)]
+    pub infrastructure_log: Option<String>,
+    /// The output as it appeared over time, if the task asked for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording: Option<OutputRecording>,
+    /// See [FinishedExecution::full_output_id]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_output_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -264,6 +570,9 @@ pub enum TestExecutionOutput {
     CompilerFailed { compiler_output: ExecutionOutput },
     #[serde(rename_all = "camelCase")]
     Error { output_so_far: ExecutionOutput },
+    /// The test did not run, as a test it depends on did not pass. The output explains which.
+    #[serde(rename_all = "camelCase")]
+    Skipped { output_so_far: ExecutionOutput },
     #[serde(rename_all = "camelCase")]
     Success {
         compiler_output: ExecutionOutput,
@@ -281,12 +590,23 @@ impl TestExecutionOutput {
                 compiler_output, ..
             } => compiler_output,
             Self::Error { output_so_far, .. } => output_so_far,
+            Self::Skipped { output_so_far } => output_so_far,
             Self::Success {
                 compiler_output, ..
             } => compiler_output,
         }
     }
 
+    /// The output of a test skipped because its dependency did not pass
+    pub fn skipped(dependency: &str) -> Self {
+        Self::Skipped {
+            output_so_far: ExecutionOutput::Error(InternalError {
+                message: format!("Skipped, as the test `{dependency}` it depends on did not pass"),
+                runtime: Duration::ZERO,
+            }),
+        }
+    }
+
     pub fn binary_output(&self) -> Option<&ExecutionOutput> {
         match self {
             Self::BinaryFailed { binary_output, .. } => Some(binary_output),
@@ -294,6 +614,21 @@ impl TestExecutionOutput {
             _ => None,
         }
     }
+
+    /// How long the compiler took, if it compiled the test program successfully
+    pub fn compile_duration(&self) -> Option<Duration> {
+        match self {
+            Self::BinaryFailed {
+                compiler_output: ExecutionOutput::Success(execution),
+                ..
+            }
+            | Self::Success {
+                compiler_output: ExecutionOutput::Success(execution),
+                ..
+            } => Some(execution.runtime),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Display, Serialize, Deserialize)]
@@ -302,6 +637,7 @@ pub enum TestExecutionOutputType {
     BinaryFailed,
     Success,
     Error,
+    Skipped,
 }
 
 impl TestExecutionOutputType {
@@ -319,6 +655,9 @@ impl TestExecutionOutputType {
             Self::Error => TestExecutionOutput::Error {
                 output_so_far: compiler_output,
             },
+            Self::Skipped => TestExecutionOutput::Skipped {
+                output_so_far: compiler_output,
+            },
             Self::Success => TestExecutionOutput::Success {
                 compiler_output,
                 binary_output,
@@ -333,6 +672,7 @@ impl From<&TestExecutionOutput> for TestExecutionOutputType {
             TestExecutionOutput::BinaryFailed { .. } => Self::BinaryFailed,
             TestExecutionOutput::CompilerFailed { .. } => Self::CompilerFailed,
             TestExecutionOutput::Error { .. } => Self::Error,
+            TestExecutionOutput::Skipped { .. } => Self::Skipped,
             TestExecutionOutput::Success { .. } => Self::Success,
         }
     }
@@ -347,6 +687,7 @@ impl FromStr for TestExecutionOutputType {
             "BinaryFailed" => Ok(Self::BinaryFailed),
             "Success" => Ok(Self::Success),
             "Error" => Ok(Self::Error),
+            "Skipped" => Ok(Self::Skipped),
             _ => Err(format!("Invalid TestExecutionOutputType: `{s}`")),
         }
     }
@@ -359,6 +700,82 @@ pub struct FinishedTest {
     pub category: Option<String>,
     pub output: TestExecutionOutput,
     pub provisional_for_category: Option<String>,
+    /// The command profile the test was run with, if the category defines any
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// The size of the binary the compiler produced in bytes, if it produced one
+    #[serde(default)]
+    pub binary_size: Option<u64>,
+    /// How long compiling the test program took, if the compiler succeeded
+    #[serde(default)]
+    pub compile_duration_ms: Option<u64>,
+    #[serde(default)]
+    pub invocation: TestInvocation,
+    /// What the compiler reported in its `crow-diagnostics:` trailer, if it emitted one
+    #[serde(default)]
+    pub diagnostics: Vec<CompilerDiagnostic>,
+}
+
+/// A machine-readable problem report of the compiler. Compilers opt in by ending their stderr with
+/// a `crow-diagnostics: [...]` line holding a JSON array of them. Lines and columns start at 1.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompilerDiagnostic {
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub line: Option<u32>,
+    #[serde(default)]
+    pub column: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// The commands a test ran, after all modifiers were applied. The first element is the program.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TestInvocation {
+    pub compiler: Option<Vec<String>>,
+    /// Absent if the binary was not run
+    pub binary: Option<Vec<String>>,
+}
+
+/// Renders the command so it can be pasted into a POSIX shell
+pub fn shell_command_line(command: &[String]) -> String {
+    command
+        .iter()
+        .map(|arg| {
+            let is_plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+            if is_plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Who authored a commit and when, as recorded by git
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitMetadata {
+    pub author_name: String,
+    pub author_email: String,
+    #[serde(serialize_with = "serialize_system_time")]
+    #[serde(deserialize_with = "deserialize_system_time")]
+    pub committed_at: SystemTime,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -376,6 +793,67 @@ pub struct FinishedTaskInfo {
     pub team_id: String,
     pub revision_id: String,
     pub commit_message: String,
+
+    /// The pinned environment the task was executed in. Unknown for tasks executed before it was
+    /// recorded.
+    #[serde(default)]
+    pub environment: Option<PinnedEnvironment>,
+    /// The task this one reran against a newer test suite. Filled in by the backend.
+    #[serde(default)]
+    pub rerun_of: Option<String>,
+    /// The author of the tested commit. Filled in by the backend, unknown for tasks queued before
+    /// it was recorded.
+    #[serde(default)]
+    pub commit: Option<CommitMetadata>,
+}
+
+/// Settings every build and test command is executed with, so that programs behave identically
+/// across runners and when reproducing a run locally.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinnedEnvironment {
+    pub locale: String,
+    pub timezone: String,
+    pub umask: u32,
+    pub source_date_epoch: u64,
+}
+
+impl PinnedEnvironment {
+    /// The environment variables implied by these settings. The umask is not an environment
+    /// variable and needs to be applied separately.
+    pub fn variables(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("LANG", self.locale.clone()),
+            ("LC_ALL", self.locale.clone()),
+            ("TZ", self.timezone.clone()),
+            ("SOURCE_DATE_EPOCH", self.source_date_epoch.to_string()),
+        ]
+    }
+}
+
+impl Default for PinnedEnvironment {
+    fn default() -> Self {
+        Self {
+            locale: "C.UTF-8".to_string(),
+            timezone: "UTC".to_string(),
+            umask: 0o022,
+            // 1980-01-01, the earliest timestamp zip files can represent
+            source_date_epoch: 315_532_800,
+        }
+    }
+}
+
+/// Settings only the compiled binary of a test is run with, so that programs depending on their
+/// locale or memory layout behave the same in every run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinaryEnvironment {
+    /// Replaces the pinned environment of the binary, e.g. to test a specific locale
+    #[serde(default)]
+    pub pinned: Option<PinnedEnvironment>,
+    /// Runs the binary without address space layout randomization
+    #[serde(default)]
+    pub disable_aslr: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -413,10 +891,14 @@ pub struct RunnerInfo {
     pub info: String,
     pub current_task: Option<String>,
     pub test_taster: bool,
+    /// Missing for runners predating version reporting
+    #[serde(default)]
+    pub version: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
+#[allow(clippy::large_enum_variant)] // updates are sent one at a time, the size does not matter
 pub enum RunnerUpdate {
     StartedBuild,
     FinishedBuild {
@@ -432,6 +914,17 @@ pub enum RunnerUpdate {
     Done,
 }
 
+/// A [RunnerUpdate] for the task it belongs to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRunnerUpdate {
+    /// Missing for runners predating concurrent tasks
+    #[serde(default)]
+    pub task_id: Option<String>,
+    #[serde(flatten)]
+    pub update: RunnerUpdate,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RunnerWorkResponse {
@@ -439,6 +932,28 @@ pub struct RunnerWorkResponse {
     pub reset: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunnerPingResponse {
+    /// The task the runner currently holds a lease for. If the runner is executing a different
+    /// task, its lease expired (or the backend forgot about it) and the work should be aborted.
+    pub leased_task: Option<String>,
+    /// The role the runner should switch to. Older backends do not send it.
+    #[serde(default)]
+    pub test_taster: Option<bool>,
+    /// Whether an admin asked for the recent logs of the runner, which it should push to the
+    /// backend. Older backends do not send it.
+    #[serde(default)]
+    pub send_logs: bool,
+}
+
+/// The most recent log lines of a runner, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunnerLogs {
+    pub lines: Vec<String>,
+}
+
 #[derive(Debug, Clone, Hash, From, PartialEq, Eq, Display, Serialize, Deserialize)]
 pub struct TestTasteId(String);
 
@@ -448,6 +963,8 @@ pub struct WorkTasteTestTask {
     pub id: TestTasteId,
     pub test: CompilerTest,
     pub image_id: String,
+    #[serde(default)]
+    pub fileset: Option<CategoryFileset>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -517,6 +1034,10 @@ pub fn validate_test_id(input: &str) -> Result<(), &'static str> {
     Ok(())
 }
 
+pub fn normalize_line_endings(string: &str) -> String {
+    string.replace("\r\n", "\n")
+}
+
 pub fn indent(string: &str, count: usize) -> String {
     let indented = string
         .trim()
@@ -0,0 +1,201 @@
+use crate::TestModifier;
+use markdown::mdast::{Code, Heading, Node, Root, Text};
+use std::fmt::{Display, Formatter};
+
+/// The headings of the markdown test format
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Keys {
+    CompilerModifiers,
+    BinaryModifiers,
+    Meta,
+    Hash,
+    Creator,
+    AdminAuthored,
+    LimitedToCategory,
+    Timeout,
+    DependsOn,
+    Practice,
+    License,
+}
+
+impl Display for Keys {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CompilerModifiers => write!(f, "Executing your compiler"),
+            Self::BinaryModifiers => write!(f, "Executing the compiled binary"),
+            Self::Meta => write!(f, "Meta"),
+            Self::Hash => write!(f, "Hash"),
+            Self::Creator => write!(f, "Creator"),
+            Self::AdminAuthored => write!(f, "Admin Authored"),
+            Self::LimitedToCategory => write!(f, "Limited to Category"),
+            Self::Timeout => write!(f, "Timeout"),
+            Self::DependsOn => write!(f, "Depends On"),
+            Self::Practice => write!(f, "Practice"),
+            Self::License => write!(f, "License"),
+        }
+    }
+}
+
+/// Everything the markdown representation of a test contains
+#[derive(Debug, Clone)]
+pub struct TestMarkdownInput<'a> {
+    pub compiler_modifiers: &'a [TestModifier],
+    pub binary_modifiers: &'a [TestModifier],
+    pub limited_to_category: bool,
+    pub admin_authored: bool,
+    pub timeout_ms: Option<u64>,
+    pub depends_on: &'a [String],
+    /// Only run locally, never on the server
+    pub practice: bool,
+    /// The license and attribution of the test, e.g. an SPDX identifier
+    pub license: Option<&'a str>,
+    pub creator: &'a str,
+    pub hash: &'a str,
+}
+
+/// The canonical markdown representation of a test, as written by `crow sync-tests` and the test
+/// mirror of the backend
+pub fn test_to_markdown(test: &TestMarkdownInput<'_>) -> String {
+    let mut root = Root {
+        children: vec![],
+        position: None,
+    };
+
+    root.children.extend(modifiers_to_markdown(
+        Keys::CompilerModifiers.to_string(),
+        test.compiler_modifiers,
+    ));
+    root.children.extend(modifiers_to_markdown(
+        Keys::BinaryModifiers.to_string(),
+        test.binary_modifiers,
+    ));
+
+    root.children
+        .extend(write_heading_value(&Keys::Meta.to_string(), 1, None));
+
+    root.children.extend(write_heading_value(
+        &Keys::LimitedToCategory.to_string(),
+        2,
+        Some(test.limited_to_category.to_string()),
+    ));
+
+    // Only admins can set timeouts, but they are part of the hash, so the file must carry them for
+    // team-owned tests as well
+    if let Some(timeout_ms) = test.timeout_ms {
+        root.children.extend(write_heading_value(
+            &Keys::Timeout.to_string(),
+            2,
+            Some(timeout_ms.to_string()),
+        ));
+    }
+
+    if !test.depends_on.is_empty() {
+        root.children.extend(write_heading_value(
+            &Keys::DependsOn.to_string(),
+            2,
+            Some(test.depends_on.join(", ")),
+        ));
+    }
+
+    if test.practice {
+        root.children.extend(write_heading_value(
+            &Keys::Practice.to_string(),
+            2,
+            Some(test.practice.to_string()),
+        ));
+    }
+
+    if let Some(license) = test.license {
+        root.children.extend(write_heading_value(
+            &Keys::License.to_string(),
+            2,
+            Some(license.to_string()),
+        ));
+    }
+
+    root.children.extend(write_heading_value(
+        &Keys::Creator.to_string(),
+        2,
+        Some(test.creator.to_string()),
+    ));
+
+    root.children.extend(write_heading_value(
+        &Keys::AdminAuthored.to_string(),
+        2,
+        Some(test.admin_authored.to_string()),
+    ));
+
+    root.children.extend(write_heading_value(
+        &Keys::Hash.to_string(),
+        2,
+        Some(test.hash.to_string()),
+    ));
+
+    mdast_util_to_markdown::to_markdown(&Node::Root(root)).expect("Could convert to markdown")
+}
+
+fn modifiers_to_markdown(heading: String, modifiers: &[TestModifier]) -> Vec<Node> {
+    let mut result = write_heading_value(&heading, 1, None);
+
+    result.extend(modifiers.iter().flat_map(modifier_to_markdown));
+
+    result
+}
+
+fn modifier_to_markdown(modifier: &TestModifier) -> Vec<Node> {
+    let modifier = modifier.with_normalized_line_endings();
+    // Every alternative gets its own code block below the heading
+    if let TestModifier::ExpectedOutputAnyOf { outputs } = &modifier {
+        let mut result = write_heading_value(modifier.name(), 2, None);
+        result.extend(outputs.iter().map(|it| code_block(it.clone())));
+        return result;
+    }
+    write_heading_value(modifier.name(), 2, modifier_arg_to_string(&modifier))
+}
+
+fn modifier_arg_to_string(modifier: &TestModifier) -> Option<String> {
+    match modifier {
+        TestModifier::ExitCode { code } => Some(code.to_string()),
+        TestModifier::ExpectedOutput { output } => Some(output.to_string()),
+        TestModifier::ExpectedOutputAnyOf { .. } => None,
+        TestModifier::ExpectedFileOutput { path, contents } => Some(format!("{path}\n{contents}")),
+        TestModifier::FloatTolerance { tolerance } => Some(tolerance.to_string()),
+        TestModifier::IgnoreLineEndings => None,
+        TestModifier::IgnoreTrailingWhitespace => None,
+        TestModifier::IgnoreTrailingNewlines => None,
+        TestModifier::ProgramArgument { arg } => Some(arg.to_string()),
+        TestModifier::ProgramArgumentFile { contents } => Some(contents.to_string()),
+        TestModifier::ProgramInput { input } => Some(input.to_string()),
+        TestModifier::ShouldCrash { signal } => Some(signal.to_string()),
+        TestModifier::ShouldFail { reason } => Some(reason.to_string()),
+        TestModifier::ShouldSucceed => None,
+        TestModifier::ShouldTimeout => None,
+        TestModifier::UnorderedLines => None,
+    }
+}
+
+fn write_heading_value(heading: &str, depth: u8, value: Option<String>) -> Vec<Node> {
+    let mut res = vec![Node::Heading(Heading {
+        depth,
+        children: vec![Node::Text(Text {
+            value: heading.to_string(),
+            position: None,
+        })],
+        position: None,
+    })];
+
+    if let Some(value) = value {
+        res.push(code_block(value));
+    }
+
+    res
+}
+
+fn code_block(value: String) -> Node {
+    Node::Code(Code {
+        value,
+        lang: None,
+        meta: None,
+        position: None,
+    })
+}
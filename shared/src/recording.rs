@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The most output a recording keeps, in bytes
+pub const MAX_RECORDING_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// A piece of output the process wrote
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputEvent {
+    /// Milliseconds since the process was started
+    pub offset_ms: u64,
+    pub stream: OutputStream,
+    pub data: String,
+}
+
+/// The output of a process as it appeared over time, in the spirit of the event log of asciinema.
+/// Stops recording once [MAX_RECORDING_BYTES] were recorded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputRecording {
+    pub events: Vec<OutputEvent>,
+    /// Whether later output was dropped as the recording was full
+    pub truncated: bool,
+    #[serde(skip)]
+    recorded_bytes: usize,
+    /// Characters split between two reads, per stream
+    #[serde(skip)]
+    partial: [Vec<u8>; 2],
+}
+
+impl OutputRecording {
+    /// Records output read from the process `offset` after it was started
+    pub fn record(&mut self, offset: Duration, stream: OutputStream, bytes: &[u8]) {
+        if bytes.is_empty() || self.truncated {
+            return;
+        }
+
+        let partial = &mut self.partial[stream as usize];
+        partial.extend_from_slice(bytes);
+        // Keep an incomplete character at the end until the rest of it is read
+        let complete = match std::str::from_utf8(partial) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => partial.len(),
+        };
+        if complete == 0 {
+            return;
+        }
+        let data = partial.drain(..complete).collect::<Vec<_>>();
+
+        if self.recorded_bytes + data.len() > MAX_RECORDING_BYTES {
+            self.truncated = true;
+            return;
+        }
+        self.recorded_bytes += data.len();
+        self.events.push(OutputEvent {
+            offset_ms: offset.as_millis() as u64,
+            stream,
+            data: String::from_utf8_lossy(&data).to_string(),
+        });
+    }
+}
@@ -0,0 +1,41 @@
+use crate::{
+    CompilerTest, PinnedEnvironment, TestInvocation, deserialize_duration, serialize_duration,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The [ReproManifest] in a bundle
+pub const MANIFEST_FILE: &str = "repro.json";
+/// The files of the category, relative to the working directory of the test
+pub const FILESET_DIR: &str = "fileset";
+/// The argument files and stdin of the compiler
+pub const COMPILER_FILES_DIR: &str = "files/compiler";
+/// The argument files and stdin of the compiled binary
+pub const BINARY_FILES_DIR: &str = "files/binary";
+/// The file in the directories above that is passed to the program as stdin
+pub const STDIN_FILE: &str = "stdin";
+/// The templates of the runc configs the executor runs containers with
+pub const RUNC_DIR: &str = "runc";
+
+/// Everything needed to rerun one test of a task outside of crow, as written to the
+/// `repro.json` of a reproducibility bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReproManifest {
+    pub task_id: String,
+    pub team_id: String,
+    pub revision_id: String,
+    /// The image builds and tests run in
+    pub image: String,
+    pub build_command: Vec<String>,
+    #[serde(serialize_with = "serialize_duration")]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub build_timeout: Duration,
+    /// The test as the executor receives it, with the arguments of [Self::profile] already
+    /// appended to its compile command
+    pub test: CompilerTest,
+    pub profile: Option<String>,
+    /// The commands the test ran in the task, as recorded by the executor
+    pub invocation: TestInvocation,
+    pub environment: PinnedEnvironment,
+}
@@ -0,0 +1,7 @@
+/// The runc config of containers that write directly into their rootfs, like builds. Placeholders
+/// in braces are filled in by the executor.
+pub const READ_WRITE_TEMPLATE: &str = include_str!("../resources/runc-read-write.json");
+
+/// The runc config of containers that write into an overlay over the rootfs of the build, like
+/// tests. Placeholders in braces are filled in by the executor.
+pub const OVERLAY_TEMPLATE: &str = include_str!("../resources/runc-overlay.json");
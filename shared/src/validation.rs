@@ -0,0 +1,151 @@
+use crate::{TestModifier, TestModifierExt, is_safe_relative_path, validate_test_id};
+use std::fmt::{Display, Formatter};
+
+/// The longest license the server accepts, in bytes
+pub const MAX_LICENSE_LENGTH: usize = 200;
+
+/// Everything about a test the server checks before storing it
+#[derive(Debug, Clone)]
+pub struct TestValidationInput<'a> {
+    pub id: &'a str,
+    pub compiler_modifiers: &'a [TestModifier],
+    pub binary_modifiers: &'a [TestModifier],
+    pub depends_on: &'a [String],
+    pub license: Option<&'a str>,
+    /// Whether the category of the test only runs the compiler
+    pub compile_only: bool,
+}
+
+/// A reason the server rejects a test, phrased to follow "Bad request"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestProblem {
+    InvalidId {
+        reason: &'static str,
+    },
+    BinaryModifiersInCompileOnlyCategory,
+    ConflictingExitExpectations {
+        target: &'static str,
+        expectations: Vec<&'static str>,
+    },
+    UnsafeFileOutput {
+        path: String,
+    },
+    EmptyOutputAlternatives,
+    ExpectedOutputWithAlternatives {
+        target: &'static str,
+    },
+    DependsOnItself,
+    InvalidLicense,
+}
+
+impl Display for TestProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidId { reason } => write!(f, "test id: {reason}"),
+            Self::BinaryModifiersInCompileOnlyCategory => {
+                write!(f, "binary modifiers in a compile-only category")
+            }
+            Self::ConflictingExitExpectations {
+                target,
+                expectations,
+            } => write!(
+                f,
+                "conflicting {target} exit expectations `{}`",
+                expectations.join("`, `")
+            ),
+            Self::UnsafeFileOutput { path } => {
+                write!(
+                    f,
+                    "expected output file `{path}` outside of the working directory"
+                )
+            }
+            Self::EmptyOutputAlternatives => write!(f, "`ExpectedOutputAnyOf` without any outputs"),
+            Self::ExpectedOutputWithAlternatives { target } => write!(
+                f,
+                "{target} modifiers mixing `ExpectedOutputAnyOf` with other expected outputs"
+            ),
+            Self::DependsOnItself => write!(f, "test depending on itself"),
+            Self::InvalidLicense => write!(
+                f,
+                "license, it must be a single non-blank line of at most {MAX_LICENSE_LENGTH} bytes"
+            ),
+        }
+    }
+}
+
+/// Runs the checks the server applies to uploaded tests that do not need its database. Returns
+/// every problem instead of stopping at the first one.
+pub fn validate_test(test: &TestValidationInput<'_>) -> Vec<TestProblem> {
+    let mut problems = Vec::new();
+
+    if let Err(reason) = validate_test_id(test.id) {
+        problems.push(TestProblem::InvalidId { reason });
+    }
+
+    if test.compile_only && !test.binary_modifiers.is_empty() {
+        problems.push(TestProblem::BinaryModifiersInCompileOnlyCategory);
+    }
+
+    for (target, modifiers) in [
+        ("compiler", test.compiler_modifiers),
+        ("binary", test.binary_modifiers),
+    ] {
+        let expectations = modifiers.exit_expectations();
+        if expectations.len() > 1 {
+            problems.push(TestProblem::ConflictingExitExpectations {
+                target,
+                expectations,
+            });
+        }
+
+        // The alternatives replace the expected output, so they can not be combined with it
+        let output_modifiers = modifiers
+            .iter()
+            .filter(|it| {
+                matches!(
+                    it,
+                    TestModifier::ExpectedOutput { .. } | TestModifier::ExpectedOutputAnyOf { .. }
+                )
+            })
+            .count();
+        if modifiers.output_alternatives().is_some() && output_modifiers > 1 {
+            problems.push(TestProblem::ExpectedOutputWithAlternatives { target });
+        }
+    }
+
+    let empty_alternatives = test
+        .compiler_modifiers
+        .iter()
+        .chain(test.binary_modifiers)
+        .any(
+            |it| matches!(it, TestModifier::ExpectedOutputAnyOf { outputs } if outputs.is_empty()),
+        );
+    if empty_alternatives {
+        problems.push(TestProblem::EmptyOutputAlternatives);
+    }
+
+    problems.extend(
+        test.compiler_modifiers
+            .iter()
+            .chain(test.binary_modifiers)
+            .filter_map(|it| match it {
+                TestModifier::ExpectedFileOutput { path, .. } if !is_safe_relative_path(path) => {
+                    Some(TestProblem::UnsafeFileOutput { path: path.clone() })
+                }
+                _ => None,
+            }),
+    );
+
+    if test.depends_on.iter().any(|it| it == test.id) {
+        problems.push(TestProblem::DependsOnItself);
+    }
+
+    let invalid_license = |license: &&str| {
+        license.trim().is_empty() || license.len() > MAX_LICENSE_LENGTH || license.contains('\n')
+    };
+    if test.license.as_ref().is_some_and(invalid_license) {
+        problems.push(TestProblem::InvalidLicense);
+    }
+
+    problems
+}